@@ -1,7 +1,6 @@
-use std::error::Error;
 use std::thread;
 use std::time::Duration;
-use rainbow_hat_rs::buzzer::Buzzer;
+use rainbow_hat_rs::prelude::*;
 
 const NOTES: [u32; 36] = [
     71, 71, 71, 71, 71, 71, 71, 64, 67, 71,
@@ -18,7 +17,7 @@ const TIMES: [u32; 36] = [
 ];
 
 /// Play a melody with the buzzer.
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     let mut buzzer = Buzzer::new()?;
 
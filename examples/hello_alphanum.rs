@@ -1,9 +1,8 @@
-use std::error::Error;
 use std::thread;
 use std::time::Duration;
-use rainbow_hat_rs::alphanum4::Alphanum4;
+use rainbow_hat_rs::prelude::*;
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let sleep_time = 500;
     let msg = "HELLO WORLD  ";
@@ -12,12 +11,12 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let mut start_index = 0;
     let mut msg2 = msg.to_string();
-    msg2.push_str(&msg);
+    msg2.push_str(msg);
 
     loop {
 
         let substring = &msg2[start_index..=(start_index + 3)];
-        alphanum.print_str(substring, false);
+        alphanum.print_str(substring, Alignment::Left)?;
         alphanum.show()?;
         thread::sleep(Duration::from_millis(sleep_time));
 
@@ -0,0 +1,64 @@
+use std::time::Duration;
+use rainbow_hat_rs::prelude::*;
+use rainbow_hat_rs::testing::Scenario;
+
+/// Tiny application under test: a frost alarm showing "ICE" and beeping once when the temperature drops below 3 °C,
+/// acknowledged with the button A.
+struct FrostAlarm {
+
+    /// whether the temperature is below 3 °C
+    freezing: bool,
+
+    /// whether the alarm is raised and not acknowledged
+    raised: bool,
+}
+
+impl FrostAlarm {
+
+    /// Read the sensor and the buttons once.
+    fn tick(&mut self, hat: &mut RainbowHat) -> Result<(), Error> {
+        let temperature = hat.bmp280.read_measurement()?.temperature_c;
+
+        if !self.freezing && temperature < 3.0 {
+            self.raised = true;
+            hat.alphanum.print_str("ICE", Alignment::Left)?;
+            hat.alphanum.show()?;
+            hat.buzzer.note(880.0, 0.5)?;
+            hat.lights.red.on();
+        }
+        self.freezing = temperature < 3.0;
+
+        if self.raised && hat.buttons.a.poll_event() == Some(TouchEvent::Pressed) {
+            self.raised = false;
+            hat.lights.red.off();
+        }
+
+        Ok(())
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+
+    // Mild, then freezing for a while before the alarm is acknowledged
+    let scenario = Scenario::builder()
+        .sensor(Duration::ZERO, 12.0, 1013.0)
+        .sensor(Duration::from_secs(60), 1.5, 1013.0)
+        .advance(Duration::from_secs(60))
+        .tap(Duration::from_secs(30), ButtonId::A, Duration::from_millis(150))
+        .build();
+    let mut hat = scenario.board()?;
+
+    let mut alarm = FrostAlarm { freezing: false, raised: false };
+    scenario.run(&mut hat, |hat| alarm.tick(hat))?;
+
+    // The display is written before the beep, the red light turned on then off
+    scenario.assert_order(&["display:write", "buzzer:note", "lights:set", "lights:set"]);
+    assert!(!alarm.raised && !hat.lights.red.state);
+
+    println!("Frost alarm tested over {:?} of simulated time:", scenario.elapsed());
+    for entry in hat.recording() {
+        println!("  {}", rainbow_hat_rs::testing::label(&entry));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,23 @@
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use rainbow_hat_rs::prelude::*;
+use rainbow_hat_rs::simon::{SimonGame, DEFAULT_ROUNDS};
+
+/// Play Simon: repeat the sequence of colors with the buttons under the lights.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+
+    let mut hat = RainbowHat::new()?;
+
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as u64;
+    let mut game = SimonGame::new(seed, DEFAULT_ROUNDS)?;
+    game.start(&mut hat)?;
+
+    while game.result().is_none() {
+        game.poll(&mut hat)?;
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    println!("{:?} with a score of {}", game.result().unwrap(), game.score());
+
+    Ok(())
+}
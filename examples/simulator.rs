@@ -0,0 +1,26 @@
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use rainbow_hat_rs::prelude::*;
+use rainbow_hat_rs::sim_ui::LiveView;
+use rainbow_hat_rs::simon::{SimonGame, DEFAULT_ROUNDS};
+
+/// Play Simon on a simulated board drawn in the terminal: type a, b or c then Enter to press the buttons.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+
+    let mut hat = RainbowHat::with_simulation()?;
+    let live_view = LiveView::attach(&mut hat)?;
+
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as u64;
+    let mut game = SimonGame::new(seed, DEFAULT_ROUNDS)?;
+    game.start(&mut hat)?;
+
+    while game.result().is_none() {
+        game.poll(&mut hat)?;
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    live_view.stop();
+    println!("{:?} with a score of {}", game.result().unwrap(), game.score());
+
+    Ok(())
+}
@@ -0,0 +1,15 @@
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+use rainbow_hat_rs::thermometer::Thermometer;
+
+/// Displays the temperature, with the rainbow lights going from blue at 15°C to red at 30°C.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+
+    let mut thermometer = Thermometer::new()?;
+    thermometer.enable_strip(15.0, 30.0)?;
+
+    let cancel = AtomicBool::new(false);
+    thermometer.run(Duration::from_secs(1), &cancel)?;
+
+    Ok(())
+}
@@ -1,31 +1,37 @@
-use std::error::Error;
-use std::thread;
-use std::time::Duration;
-use rainbow_hat_rs::lights::Lights;
-use rainbow_hat_rs::touch::Buttons;
+use std::sync::atomic::AtomicBool;
+use rainbow_hat_rs::prelude::*;
 
-fn main() -> Result<(), Box<dyn Error>> {
-    
-    let mut lights = Lights::new()?;
-    let mut buttons = Buttons::new()?;
+fn main() -> Result<(), Box<dyn std::error::Error>> {
 
-    // Turn on the light when a touch is pressed.
-    loop {
-        
-        thread::sleep(Duration::from_millis(50));
+    let mut event_loop = EventLoop::new(RainbowHat::new()?);
 
-        if buttons.a.is_pressed() {
+    // Turn on the light of a touch while it is pressed.
+    event_loop
+        .on_press(ButtonId::A, |hat| {
             println!("Button A touched!");
-            lights.rgb(true, false, false);
-        } else if buttons.b.is_pressed() {
+            hat.lights.rgb(true, false, false);
+            Ok(())
+        })
+        .on_press(ButtonId::B, |hat| {
             println!("Button B touched!");
-            lights.rgb(false, true, false);
-        } else if buttons.c.is_pressed(){
+            hat.lights.rgb(false, true, false);
+            Ok(())
+        })
+        .on_press(ButtonId::C, |hat| {
             println!("Button C touched!");
-            lights.rgb(false, false, true);
-        } else {
+            hat.lights.rgb(false, false, true);
+            Ok(())
+        });
+
+    for id in [ButtonId::A, ButtonId::B, ButtonId::C] {
+        event_loop.on_release(id, |hat| {
             println!("Button release!");
-            lights.rgb(false, false, false);
-        }
-     }
+            hat.lights.rgb(false, false, false);
+            Ok(())
+        });
+    }
+
+    event_loop.run(&AtomicBool::new(false))?;
+
+    Ok(())
 }
@@ -1,7 +1,22 @@
 
 use std::collections::HashMap;
 use std::fmt;
-use crate::ht16k33::HT16K33;
+use std::sync::{Arc, Mutex, MutexGuard};
+use crate::error::invalid_input;
+#[cfg(feature = "hardware")]
+use crate::hal::Backend;
+use crate::ht16k33::{BufferedDisplay, HT16K33, HT16K33Handle, BUFFER_SIZE};
+use crate::recorder::Recorder;
+use crate::rng::Rng;
+
+/// Number of digits of the display.
+pub const NUM_DIGITS: usize = 4;
+
+/// Characters drawn by the random glyph animations.
+pub const RANDOM_GLYPHS: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// Number of frames of random glyphs before each digit of `settle` locks on its target.
+pub const SETTLE_FRAMES: usize = 4;
 
 /// Digit value to bitmask mapping.
 const DIGIT_VALUES: [(char, u16); 95] = 
@@ -103,15 +118,43 @@ const DIGIT_VALUES: [(char, u16); 95] =
         ('~', 0b0000010100100000)
     ];
 
+/// Alignment of a text shorter than the display, padded with spaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Alignment {
+
+    /// Text on the left digits, padded on the right.
+    #[default]
+    Left,
+
+    /// Text on the right digits, padded on the left.
+    Right,
+
+    /// Text in the middle, padded on both sides. An odd padding puts the extra space on the right.
+    Center,
+}
+
 /// 4-digit alphanumeric 7-segment display driver.
+/// Drives an owned HT16K33 by default, or a shared `HT16K33Handle`.
+/// Send, and Sync only on top of a shared `HT16K33Handle`: use `into_shared` to print from several threads.
 #[derive(Debug)]
-pub struct Alphanum4 {
+pub struct Alphanum4<D: BufferedDisplay = HT16K33> {
     
     /// Driver for HT16K33
-    pub ht16k33 : HT16K33,
+    pub ht16k33 : D,
 
     /// Map of bitmask for each character to print on the display
     digit_value: HashMap<char, u16>,
+
+    /// Buffer of the last write to the display, None before the first one
+    last_shown: Option<[u8; BUFFER_SIZE]>,
+
+    /// Whether each digit blinks in software
+    blink_digits: [bool; NUM_DIGITS],
+
+    /// Bytes of the blinking digits blanked during the off phase of the blink
+    hidden_digits: [Option<[u8; 2]>; NUM_DIGITS],
 }
 
 impl Alphanum4 {
@@ -119,12 +162,229 @@ impl Alphanum4 {
     /// Creates the alphanumeric 7-segment display driver screen.
     pub fn new() -> Result<Alphanum4, Error> {
         let ht16k33 = HT16K33::new()?;
+
+        Ok(Alphanum4::with_display(ht16k33))
+    }
+
+    /// Creates the alphanumeric display driver for a display at another address, or on another bus of the Raspberry Pi.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - I2C bus number, None for the default bus.
+    /// * `address` - Address of the display: 0x70 to 0x77.
+    pub fn new_i2c(bus: Option<u8>, address: u16) -> Result<Alphanum4, Error> {
+        let ht16k33 = HT16K33::new_i2c(bus, address)?;
+
+        Ok(Alphanum4::with_display(ht16k33))
+    }
+
+    /// Creates the alphanumeric 7-segment display driver in simulation mode, recording the writes
+    /// instead of accessing the hardware.
+    pub fn with_simulation() -> Result<Alphanum4, Error> {
+        let ht16k33 = HT16K33::with_simulation()?;
+
+        Ok(Alphanum4::with_display(ht16k33))
+    }
+
+    /// Enables simulation mode on the HT16K33: the writes are recorded instead of sent to the hardware.
+    pub fn enable_simulation(&mut self) {
+        self.ht16k33.enable_simulation();
+    }
+
+    /// Get whether simulation mode is enabled on the HT16K33.
+    pub fn is_simulation(&self) -> bool {
+        self.ht16k33.is_simulation()
+    }
+
+    /// Switch the HT16K33 to simulation mode on setup when the hardware is absent, instead of returning an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to fall back to simulation mode.
+    pub fn set_auto_simulate(&mut self, enabled: bool) {
+        self.ht16k33.set_auto_simulate(enabled);
+    }
+
+    /// Set the backend opening the I2C bus of the HT16K33 on setup.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Backend to use.
+    #[cfg(feature = "hardware")]
+    pub fn set_backend<B: Backend + 'static>(&mut self, backend: B) {
+        self.ht16k33.set_backend(backend);
+    }
+
+    /// Set a hook receiving a message before each write to the HT16K33.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Closure receiving each message.
+    pub fn set_trace<F: FnMut(&str) + Send + 'static>(&mut self, hook: F) {
+        self.ht16k33.set_trace(hook);
+    }
+
+    /// Remove the trace hook of the HT16K33.
+    pub fn clear_trace(&mut self) {
+        self.ht16k33.clear_trace();
+    }
+
+    /// Set the recorder of the writes to the HT16K33 in simulation mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `recorder` - Recorder to use.
+    pub fn set_recorder<R: Recorder + 'static>(&mut self, recorder: R) {
+        self.ht16k33.set_recorder(recorder);
+    }
+
+    /// Get whether every segment of the display buffer is off.
+    pub fn is_blank(&self) -> bool {
+        self.ht16k33.is_blank()
+    }
+
+    /// Read back the text of the display buffer, None when a digit is not the glyph of a character.
+    /// The decimal points are ignored.
+    pub fn read_str(&self) -> Option<String> {
+        self.decode_str(&self.ht16k33.buffer)
+    }
+
+    /// Display buffer on display from an async task.
+    /// The buffer is written before yielding to the runtime, so dropping the future never leaves it half written.
+    #[cfg(feature = "async")]
+    pub async fn show_async(&mut self) -> Result <(), Error> {
+        self.show()?;
+        tokio::task::yield_now().await;
+
+        Ok(())
+    }
+
+    /// Move the display behind a mutex so it can be shared between threads.
+    pub fn into_shared(self) -> Alphanum4Handle {
+        Alphanum4Handle {
+            inner: Arc::new(Mutex::new(self)),
+        }
+    }
+}
+
+impl Alphanum4<HT16K33Handle> {
+
+    /// Creates the alphanumeric 7-segment display driver on top of a shared HT16K33.
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - Shared HT16K33 driver.
+    pub fn with_handle(handle: HT16K33Handle) -> Alphanum4<HT16K33Handle> {
+        Alphanum4::with_display(handle)
+    }
+
+    /// Enables simulation mode on the shared HT16K33.
+    pub fn enable_simulation(&mut self) {
+        self.ht16k33.enable_simulation();
+    }
+
+    /// Get whether simulation mode is enabled on the shared HT16K33.
+    pub fn is_simulation(&self) -> bool {
+        self.ht16k33.is_simulation()
+    }
+
+    /// Set a hook receiving a message before each write to the shared HT16K33.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Closure receiving each message.
+    pub fn set_trace<F: FnMut(&str) + Send + 'static>(&mut self, hook: F) {
+        self.ht16k33.set_trace(hook);
+    }
+
+    /// Remove the trace hook of the shared HT16K33.
+    pub fn clear_trace(&mut self) {
+        self.ht16k33.clear_trace();
+    }
+
+    /// Get whether every segment of the shared display buffer is off.
+    pub fn is_blank(&self) -> bool {
+        self.ht16k33.is_blank()
+    }
+
+    /// Read back the text of the shared display buffer, None when a digit is not the glyph of a character.
+    /// The decimal points are ignored.
+    pub fn read_str(&self) -> Option<String> {
+        self.ht16k33.with_buffer(|buffer| self.decode_str(buffer))
+    }
+
+    /// Display buffer on display from an async task, writing it on a blocking thread of the runtime.
+    #[cfg(feature = "async")]
+    pub async fn show_async(&mut self) -> Result <(), Error> {
+        let buffer = self.ht16k33.with_buffer(|buffer| *buffer);
+        self.ht16k33.write_display_async().await?;
+        self.last_shown = Some(buffer);
+
+        Ok(())
+    }
+}
+
+impl<D: BufferedDisplay> Alphanum4<D> {
+
+    /// Creates the alphanumeric 7-segment display driver on top of an HT16K33 display.
+    ///
+    /// # Arguments
+    ///
+    /// * `ht16k33` - HT16K33 driver or shared handle.
+    fn with_display(ht16k33: D) -> Alphanum4<D> {
         let digit_value = DIGIT_VALUES.iter().cloned().collect();
 
-        Ok(Self {
+        Self {
             ht16k33,
             digit_value,
-        })
+            last_shown: None,
+            blink_digits: [false; NUM_DIGITS],
+            hidden_digits: [None; NUM_DIGITS],
+        }
+    }
+
+    /// Set the bitmask printed for a character, adding the character or replacing its default glyph.
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - Character.
+    /// * `bitmask` - bitmask value printed for the character.
+    pub fn set_glyph(&mut self, c: char, bitmask: u16) {
+        self.digit_value.insert(c, bitmask);
+    }
+
+    /// Load a set of glyphs at once, for example a custom alphabet or symbols.
+    /// The glyphs replace the ones of the same characters and add the others.
+    ///
+    /// # Arguments
+    ///
+    /// * `glyphs` - Characters and their bitmask.
+    pub fn load_glyphs(&mut self, glyphs: &[(char, u16)]) {
+        self.digit_value.extend(glyphs.iter().cloned());
+    }
+
+    /// Restore the default glyphs, removing the custom ones.
+    pub fn reset_glyphs(&mut self) {
+        self.digit_value = DIGIT_VALUES.iter().cloned().collect();
+    }
+
+    /// Get the number of digits of the display.
+    pub fn digit_count(&self) -> usize {
+        NUM_DIGITS
+    }
+
+    /// Clear every digit and decimal point of the display buffer.
+    pub fn clear(&mut self) {
+        self.ht16k33.with_buffer(|buffer| *buffer = [0; BUFFER_SIZE]);
+    }
+
+    /// Get the bitmask printed for a character.
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - Character.
+    pub fn glyph(&self, c: char) -> Option<u16> {
+        self.digit_value.get(&c).cloned()
     }
 
     /// Set digit at position to raw bitmask value.
@@ -133,14 +393,12 @@ impl Alphanum4 {
     ///
     /// * `pos` - Position should be a value 0 to 3 with 0 being the left most digit on the display.
     /// * `bitmask` - bitmask value to set.
-    pub fn set_digit_raw(&mut self, pos:usize, bitmask: u16) {
-        
-        // Ignore out of bounds digits.
-        if pos <= 3 {
-            let digit = self.u16_to_u8(bitmask);
-            self.ht16k33.buffer[pos * 2] = digit.0;
-            self.ht16k33.buffer[pos * 2 + 1] = digit.1;
-        }
+    pub fn set_digit_raw(&mut self, pos:usize, bitmask: u16) -> Result <(), Error> {
+
+        Self::check_position(pos)?;
+        self.ht16k33.with_buffer(|buffer| Self::write_digit(buffer, pos, bitmask));
+
+        Ok(())
     }
 
     /// Turn decimal point on or off at provided position.
@@ -149,18 +407,98 @@ impl Alphanum4 {
     ///
     /// * `pos` - Position should be a value 0 to 3 with 0 being the left most digit on the display.
     /// * `decimal` - Decimal should be True to turn on the decimal point and False to turn it off.
-    pub fn set_decimal(&mut self, pos : usize, decimal: bool) {
+    pub fn set_decimal(&mut self, pos : usize, decimal: bool) -> Result <(), Error> {
+
+        Self::check_position(pos)?;
+        self.ht16k33.with_buffer(|buffer| Self::write_decimal(buffer, pos, decimal));
+
+        Ok(())
+    }
 
-        // Ignore out of bounds digits.
-        if pos <= 3 {
-            if decimal {
-                self.ht16k33.buffer[pos * 2 + 1] |= 1 << 6;
+    /// Turn all the decimal points on or off.
+    ///
+    /// # Arguments
+    ///
+    /// * `decimal` - Decimal should be True to turn on the decimal points and False to turn them off.
+    pub fn set_decimal_all(&mut self, decimal: bool) {
+
+        self.ht16k33.with_buffer(|buffer| {
+            for pos in 0..4 {
+                Self::write_decimal(buffer, pos, decimal);
+            }
+        });
+    }
+
+    /// Light a single decimal point, moving from left to right as the frame increases.
+    /// Useful as a loading indicator: call it with an increasing frame number then `show()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Animation frame. The lit position is the frame modulo 4.
+    pub fn cycle_decimal(&mut self, frame: usize) {
+
+        self.ht16k33.with_buffer(|buffer| {
+            for pos in 0..4 {
+                Self::write_decimal(buffer, pos, pos == frame % 4);
+            }
+        });
+    }
+
+    /// Blink a single digit, for example the one being edited when setting a clock.
+    /// The HT16K33 only blinks the whole display, so the digit is blinked in software by `blink_tick()`.
+    /// Stopping the blink shows the digit again.
+    ///
+    /// # Arguments
+    ///
+    /// * `pos` - Position should be a value 0 to 3 with 0 being the left most digit on the display.
+    /// * `on` - true to blink the digit, false to keep it steady.
+    pub fn set_blink_digit(&mut self, pos: usize, on: bool) -> Result <(), Error> {
+
+        Self::check_position(pos)?;
+        self.blink_digits[pos] = on;
+        if !on {
+            self.reveal_digit(pos);
+        }
+
+        Ok(())
+    }
+
+    /// Toggle the blinking digits between their content and blank, leaving the other digits steady.
+    /// Call it at the blink rate then `show()`. A blinking digit written while blank keeps its new content.
+    pub fn blink_tick(&mut self) {
+        for pos in 0..NUM_DIGITS {
+            if !self.blink_digits[pos] {
+                continue;
+            }
+
+            if self.hidden_digits[pos].is_some() {
+                self.reveal_digit(pos);
             } else {
-                self.ht16k33.buffer[pos * 2 + 1] &= !(1 << 6);
+                let digit = self.ht16k33.with_buffer(|buffer| {
+                    let digit = [buffer[pos * 2], buffer[pos * 2 + 1]];
+                    Self::write_digit(buffer, pos, 0);
+                    digit
+                });
+                self.hidden_digits[pos] = Some(digit);
             }
         }
     }
 
+    /// Restores a digit blanked by the blink, unless it was written since.
+    ///
+    /// # Arguments
+    ///
+    /// * `pos` - Position, already validated: 0 to 3 with 0 being the left most digit on the display.
+    fn reveal_digit(&mut self, pos: usize) {
+        if let Some(digit) = self.hidden_digits[pos].take() {
+            self.ht16k33.with_buffer(|buffer| {
+                if buffer[pos * 2..pos * 2 + 2] == [0, 0] {
+                    buffer[pos * 2..pos * 2 + 2].copy_from_slice(&digit);
+                }
+            });
+        }
+    }
+
     /// Set digit at position to provided value.
     ///
     /// # Arguments
@@ -168,31 +506,130 @@ impl Alphanum4 {
     /// * `pos` - Position should be a value of 0 to 3 with 0 being the left most digit on the display.
     /// * `digit` - Digit should be any ASCII value 32-127 (printable ASCII).
     /// * `decimal` - Decimal should be True to turn on the decimal point and False to turn it off.
-    pub fn set_digit(&mut self, pos : usize, digit: char, decimal: bool) {
-        self.set_digit_raw(pos, *self.digit_value.get(&digit).unwrap());
-        self.set_decimal(pos, decimal);
+    pub fn set_digit(&mut self, pos : usize, digit: char, decimal: bool) -> Result <(), Error> {
+
+        Self::check_position(pos)?;
+        let bitmask = self.bitmask(digit)?;
+
+        self.ht16k33.with_buffer(|buffer| {
+            Self::write_digit(buffer, pos, bitmask);
+            Self::write_decimal(buffer, pos, decimal);
+        });
+
+        Ok(())
     }
 
-    /// Print a 4 character long string of values to the display.
+    /// Print a string of up to 4 characters to the display, padded with spaces according to the alignment.
+    /// Every digit is written and its decimal point cleared, in a single batch. Only the first 4 characters
+    /// of a longer string are printed.
     ///
     /// # Arguments
     ///
     /// * `value` - String where characters in the string should be any ASCII value 32 to 127 (printable ASCII).
-    /// * `justify_right` - Align to the right.
-    pub fn print_str(&mut self, value : &str, justify_right: bool) {
+    /// * `alignment` - Alignment of a string shorter than the display.
+    pub fn print_str(&mut self, value : &str, alignment: Alignment) -> Result <(), Error> {
+
+        let bitmasks = value.chars().take(NUM_DIGITS).map(|c| self.bitmask(c)).collect::<Result<Vec<u16>, Error>>()?;
 
-        let char_vec: Vec<char> = value.chars().collect();
-        let mut pos = 0;
+        // Calculate starting position of digits based on alignment.
+        let padding = NUM_DIGITS - bitmasks.len();
+        let start = match alignment {
+            Alignment::Left => 0,
+            Alignment::Right => padding,
+            Alignment::Center => padding / 2,
+        };
 
-        // Calculcate starting position of digits based on justification.
-        if justify_right {
-            pos = 4 - value.len();
+        let mut update = self.ht16k33.begin_update();
+        for pos in 0..NUM_DIGITS {
+            let bitmask = pos.checked_sub(start).and_then(|i| bitmasks.get(i)).cloned().unwrap_or(0);
+            Self::write_digit(&mut update, pos, bitmask);
+            Self::write_decimal(&mut update, pos, false);
         }
+        update.commit();
+
+        Ok(())
+    }
+
+    /// Print a 4 character window of a string scrolling from right to left, followed by a 4 space gap
+    /// before it starts again. A string fitting on the display is printed right aligned without scrolling.
+    /// Call it with an increasing step then `show()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - String where characters in the string should be any ASCII value 32 to 127 (printable ASCII).
+    /// * `step` - Scroll step: the window starts at the character of the step modulo the length of the loop.
+    pub fn scroll_str(&mut self, value: &str, step: usize) -> Result <(), Error> {
+        let chars: Vec<char> = value.chars().collect();
+
+        if chars.len() <= 4 {
+            return self.print_str(value, Alignment::Right);
+        }
+
+        // Check the whole string, not only the window shown at this step
+        if let Some(c) = chars.iter().find(|c| !self.digit_value.contains_key(c)) {
+            return Err(Self::no_glyph(*c));
+        }
+
+        let cycle = chars.len() + 4;
+        let window: String = (0..4).map(|i| {
+            chars.get((step + i) % cycle).cloned().unwrap_or(' ')
+        }).collect();
+        self.print_str(&window, Alignment::Left)
+    }
+
+    /// Print a number, scrolling it when wider than the display, as with `scroll_str`.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Number, negative numbers being printed with their sign.
+    /// * `step` - Scroll step.
+    pub fn scroll_number(&mut self, value: i64, step: usize) {
+        let result = self.scroll_str(&value.to_string(), step);
+
+        // The digits and the minus sign always have a glyph
+        debug_assert!(result.is_ok());
+    }
 
-        for c in char_vec {
-            self.set_digit(pos, c, false);
-            pos += 1;
+    /// Fill the four digits with random glyphs, for a matrix rain effect. The decimal points are cleared.
+    /// Call it at the animation rate then `show()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `rng` - Source of the random glyphs, seeded to replay the same sequence.
+    pub fn random_glyph_tick(&mut self, rng: &mut dyn Rng) {
+        let bitmasks: Vec<u16> = (0..NUM_DIGITS).map(|_| self.random_bitmask(rng)).collect();
+
+        let mut update = self.ht16k33.begin_update();
+        for (pos, bitmask) in bitmasks.into_iter().enumerate() {
+            Self::write_digit(&mut update, pos, bitmask);
+            Self::write_decimal(&mut update, pos, false);
         }
+        update.commit();
+    }
+
+    /// Animate random glyphs converging to a string: the digits lock on their target from left to right,
+    /// each after `SETTLE_FRAMES` more frames, the others showing random glyphs.
+    /// Call it with an increasing frame number then `show()` until it returns true, the display then
+    /// holding the string as printed by `print_str`.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - String where characters in the string should be any ASCII value 32 to 127 (printable ASCII).
+    /// * `alignment` - Alignment of a string shorter than the display.
+    /// * `frame` - Animation frame, starting at 0.
+    /// * `rng` - Source of the random glyphs.
+    pub fn settle(&mut self, target: &str, alignment: Alignment, frame: usize, rng: &mut dyn Rng) -> Result <bool, Error> {
+        self.print_str(target, alignment)?;
+
+        let locked = (frame / SETTLE_FRAMES).min(NUM_DIGITS);
+        let bitmasks: Vec<u16> = (locked..NUM_DIGITS).map(|_| self.random_bitmask(rng)).collect();
+        self.ht16k33.with_buffer(|buffer| {
+            for (pos, bitmask) in (locked..NUM_DIGITS).zip(bitmasks) {
+                Self::write_digit(buffer, pos, bitmask);
+            }
+        });
+
+        Ok(locked == NUM_DIGITS)
     }
 
     // TODO:
@@ -202,17 +639,130 @@ impl Alphanum4 {
 
     /// Display buffer on display.
     pub fn show(&mut self) -> Result <(), Error>{
+        let buffer = self.ht16k33.with_buffer(|buffer| *buffer);
         self.ht16k33.write_display()?;
+        self.last_shown = Some(buffer);
+
+        Ok(())
+    }
+
+    /// Display buffer on display only if it differs from the last one written by this display,
+    /// returning whether it was written. Suited to clocks and counters printing mostly the same
+    /// text many times per second, saving the I2C traffic of the identical frames.
+    pub fn show_if_changed(&mut self) -> Result <bool, Error> {
+        let buffer = self.ht16k33.with_buffer(|buffer| *buffer);
+        if self.last_shown == Some(buffer) {
+            return Ok(false);
+        }
+
+        self.show()?;
+
+        Ok(true)
+    }
+
+    /// Forget the last buffer written, so the next `show_if_changed()` writes the buffer.
+    /// Needed when the display was written or reset other than through this display.
+    pub fn invalidate(&mut self) {
+        self.last_shown = None;
+    }
+
+    /// Validates a digit position.
+    ///
+    /// # Arguments
+    ///
+    /// * `pos` - Position should be a value 0 to 3 with 0 being the left most digit on the display.
+    fn check_position(pos: usize) -> Result <(), Error> {
+
+        if pos >= NUM_DIGITS {
+            return Err(Error::InvalidArgument(invalid_input("digit position", pos, "0 to 3")));
+        }
 
         Ok(())
     }
 
+    /// Get the bitmask printed for a character, an error if it has no glyph.
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - Character.
+    fn bitmask(&self, c: char) -> Result <u16, Error> {
+        self.glyph(c).ok_or_else(|| Self::no_glyph(c))
+    }
+
+    /// Get the bitmask of a random character of `RANDOM_GLYPHS`.
+    ///
+    /// # Arguments
+    ///
+    /// * `rng` - Source of the random glyphs.
+    fn random_bitmask(&self, rng: &mut dyn Rng) -> u16 {
+        let index = (rng.next_u64() % RANDOM_GLYPHS.len() as u64) as usize;
+        let c = RANDOM_GLYPHS.as_bytes()[index] as char;
+
+        // Loaded glyphs only add or replace characters, so the letters and digits always have one
+        self.glyph(c).unwrap_or(0)
+    }
+
+    /// Error of a character without glyph.
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - Character.
+    fn no_glyph(c: char) -> Error {
+        Error::InvalidArgument(invalid_input("character", format!("{:?}", c), "printable ASCII or a loaded glyph"))
+    }
+
+    /// Writes a digit bitmask in a buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - Buffer to write to.
+    /// * `pos` - Position, already validated: 0 to 3 with 0 being the left most digit on the display.
+    /// * `bitmask` - bitmask value to set.
+    fn write_digit(buffer: &mut [u8; BUFFER_SIZE], pos: usize, bitmask: u16) {
+        debug_assert!(pos < NUM_DIGITS);
+
+        let digit = Self::u16_to_u8(bitmask);
+        buffer[pos * 2] = digit.0;
+        buffer[pos * 2 + 1] = digit.1;
+    }
+
+    /// Writes a decimal point in a buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - Buffer to write to.
+    /// * `pos` - Position, already validated: 0 to 3 with 0 being the left most digit on the display.
+    /// * `decimal` - Decimal should be True to turn on the decimal point and False to turn it off.
+    fn write_decimal(buffer: &mut [u8; BUFFER_SIZE], pos: usize, decimal: bool) {
+        debug_assert!(pos < NUM_DIGITS);
+
+        if decimal {
+            buffer[pos * 2 + 1] |= 1 << 6;
+        } else {
+            buffer[pos * 2 + 1] &= !(1 << 6);
+        }
+    }
+
+    /// Decodes the text of a buffer, None when a digit is not the glyph of a character.
+    /// Characters sharing a glyph are decoded as a letter or digit first, such as '1' rather than '!'.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - Buffer to decode.
+    fn decode_str(&self, buffer: &[u8; BUFFER_SIZE]) -> Option<String> {
+        (0..NUM_DIGITS).map(|pos| {
+            let bitmask = (buffer[pos * 2] as u16 | (buffer[pos * 2 + 1] as u16) << 8) & !(1 << 14);
+            self.digit_value.iter().filter(|(_c, value)| **value == bitmask).map(|(c, _value)| *c)
+                .min_by_key(|c| (!c.is_ascii_alphanumeric(), *c))
+        }).collect()
+    }
+
     /// Splits a u16 in a tuple of u8.
     ///
     /// # Arguments
     ///
     /// * `num` - u16 number.
-    fn u16_to_u8(&mut self, num : u16) -> (u8, u8) {
+    fn u16_to_u8(num : u16) -> (u8, u8) {
         (
             (num & 0xFF) as u8,
             ((num >> 8) & 0xFF) as u8
@@ -220,20 +770,117 @@ impl Alphanum4 {
     }
 }
 
+/// Handle to an alphanumeric display shared between threads, Send and Sync.
+/// Clones refer to the same display. Each operation holds an internal lock, and `show_str`
+/// prints and shows at once so the string of another thread is never shown instead.
+#[derive(Debug, Clone)]
+pub struct Alphanum4Handle {
+
+    /// Shared display.
+    inner: Arc<Mutex<Alphanum4>>,
+}
+
+impl Alphanum4Handle {
+
+    /// Locks the display. A poisoned lock is recovered as the display state stays consistent between calls.
+    fn lock(&self) -> MutexGuard<'_, Alphanum4> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Enables simulation mode on the HT16K33: the writes are recorded instead of sent to the hardware.
+    pub fn enable_simulation(&self) {
+        self.lock().enable_simulation();
+    }
+
+    /// Get whether simulation mode is enabled on the HT16K33.
+    pub fn is_simulation(&self) -> bool {
+        self.lock().is_simulation()
+    }
+
+    /// Set a hook receiving a message before each write to the HT16K33 of the shared display.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Closure receiving each message.
+    pub fn set_trace<F: FnMut(&str) + Send + 'static>(&self, hook: F) {
+        self.lock().set_trace(hook);
+    }
+
+    /// Remove the trace hook of the shared display.
+    pub fn clear_trace(&self) {
+        self.lock().clear_trace();
+    }
+
+    /// Run a closure with exclusive access to the display.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - Closure receiving the display.
+    pub fn with_driver<R, F: FnOnce(&mut Alphanum4) -> R>(&self, f: F) -> R {
+        f(&mut self.lock())
+    }
+
+    /// Print a string of up to 4 characters to the display, as with `Alphanum4::print_str`.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - String where characters in the string should be any ASCII value 32 to 127 (printable ASCII).
+    /// * `alignment` - Alignment of a string shorter than the display.
+    pub fn print_str(&self, value : &str, alignment: Alignment) -> Result <(), Error> {
+        self.lock().print_str(value, alignment)
+    }
+
+    /// Print a 4 character window of a string scrolling from right to left, as with `Alphanum4::scroll_str`.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - String where characters in the string should be any ASCII value 32 to 127 (printable ASCII).
+    /// * `step` - Scroll step.
+    pub fn scroll_str(&self, value: &str, step: usize) -> Result <(), Error> {
+        self.lock().scroll_str(value, step)
+    }
+
+    /// Display buffer on display.
+    pub fn show(&self) -> Result <(), Error> {
+        self.lock().show()
+    }
+
+    /// Display buffer on display only if it differs from the last one written, as with `Alphanum4::show_if_changed`.
+    pub fn show_if_changed(&self) -> Result <bool, Error> {
+        self.lock().show_if_changed()
+    }
+
+    /// Print a string of up to 4 characters and display it, without another thread writing in between.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - String where characters in the string should be any ASCII value 32 to 127 (printable ASCII).
+    /// * `alignment` - Alignment of a string shorter than the display.
+    pub fn show_str(&self, value : &str, alignment: Alignment) -> Result <(), Error> {
+        let mut alphanum = self.lock();
+        alphanum.print_str(value, alignment)?;
+        alphanum.show()
+    }
+}
+
 /// Errors that can occur.
 #[derive(Debug)]
 pub enum Error {
 
     /// HT16K33 error.
     HT16K33(crate::ht16k33::Error),
+
+    /// Invalid argument.
+    InvalidArgument(String),
 }
 
 impl std::error::Error for Error {}
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &*self {
+        match self {
             Error::HT16K33(err) => write!(f, "HT16K33 error: {}", &err),
+            Error::InvalidArgument(msg) => write!(f, "Invalid argument: {}", &msg),
         }
     }
 }
@@ -244,3 +891,384 @@ impl From<crate::ht16k33::Error> for Error {
         Error::HT16K33(err)
     }
 }
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SimpleRng;
+
+    /// Tests turning all the decimal points on and off.
+    #[test]
+    fn test_alphanum4_set_decimal_all() -> Result<(), Error> {
+        let mut alphanum = Alphanum4::new()?;
+        alphanum.print_str("ABCD", Alignment::Left)?;
+        let digits = alphanum.ht16k33.buffer;
+
+        alphanum.set_decimal_all(true);
+        for pos in 0..4 {
+            assert!(alphanum.ht16k33.buffer[pos * 2] == digits[pos * 2]);
+            assert!(alphanum.ht16k33.buffer[pos * 2 + 1] == digits[pos * 2 + 1] | 0b01000000);
+        }
+
+        alphanum.set_decimal_all(false);
+        assert!(alphanum.ht16k33.buffer == digits);
+
+        Ok(())
+    }
+
+    /// Tests moving a single decimal point across the positions.
+    #[test]
+    fn test_alphanum4_cycle_decimal() -> Result<(), Error> {
+        let mut alphanum = Alphanum4::new()?;
+
+        for frame in 0..8 {
+            alphanum.cycle_decimal(frame);
+            for pos in 0..4 {
+                let lit = alphanum.ht16k33.buffer[pos * 2 + 1] & 0b01000000 != 0;
+                assert!(lit == (pos == frame % 4));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Tests blinking a single digit while the others stay steady.
+    #[test]
+    fn test_alphanum4_blink_digit() -> Result<(), Error> {
+        let mut alphanum = Alphanum4::new()?;
+        alphanum.print_str("1234", Alignment::Left)?;
+        alphanum.set_decimal(2, true)?;
+        let content = alphanum.ht16k33.buffer;
+
+        assert!(matches!(alphanum.set_blink_digit(4, true), Err(Error::InvalidArgument(_))));
+        alphanum.set_blink_digit(2, true)?;
+
+        for tick in 0..4 {
+            alphanum.blink_tick();
+            let buffer = alphanum.ht16k33.buffer;
+            let expected = if tick & 1 == 0 { [0, 0] } else { [content[4], content[5]] };
+            assert!(buffer[4..6] == expected);
+            assert!(buffer[0..4] == content[0..4] && buffer[6..] == content[6..]);
+        }
+
+        // A digit written while blank keeps its new content
+        alphanum.blink_tick();
+        alphanum.set_digit(2, '9', false)?;
+        alphanum.blink_tick();
+        assert!(alphanum.read_str() == Some("1294".to_string()));
+
+        // Stopping the blink shows the digit
+        alphanum.print_str("1234", Alignment::Left)?;
+        alphanum.blink_tick();
+        alphanum.set_blink_digit(2, false)?;
+        assert!(alphanum.read_str() == Some("1234".to_string()));
+        alphanum.blink_tick();
+        assert!(alphanum.read_str() == Some("1234".to_string()));
+
+        Ok(())
+    }
+
+    /// Tests loading a custom glyph set and restoring the defaults.
+    #[test]
+    fn test_alphanum4_load_glyphs() -> Result<(), Error> {
+        let mut alphanum = Alphanum4::new()?;
+
+        let default_a = alphanum.glyph('A').unwrap();
+
+        alphanum.load_glyphs(&[('A', 0b0000000000000001), ('▮', 0b0011111111111111)]);
+        assert!(alphanum.glyph('A') == Some(0b0000000000000001));
+
+        alphanum.set_digit(0, '▮', false)?;
+        assert!(alphanum.ht16k33.buffer[0..2] == [0b11111111, 0b00111111]);
+
+        alphanum.reset_glyphs();
+        assert!(alphanum.glyph('A') == Some(default_a));
+        assert!(alphanum.glyph('▮').is_none());
+
+        Ok(())
+    }
+
+    /// Tests creating the display in simulation mode.
+    #[test]
+    fn test_alphanum4_with_simulation() -> Result<(), Error> {
+        let mut alphanum = Alphanum4::with_simulation()?;
+
+        alphanum.print_str("SIM ", Alignment::Left)?;
+        alphanum.show()?;
+        assert!(!alphanum.ht16k33.transcript().is_empty());
+
+        Ok(())
+    }
+
+    /// Tests enabling simulation mode on displays created for the hardware, owned or shared.
+    #[test]
+    fn test_alphanum4_enable_simulation() -> Result<(), Error> {
+        let mut alphanum = Alphanum4::new()?;
+        assert!(alphanum.is_simulation() != cfg!(feature = "hardware"));
+        alphanum.enable_simulation();
+        assert!(alphanum.is_simulation());
+        alphanum.show()?;
+
+        let mut shared = Alphanum4::with_handle(HT16K33::new()?.into_shared());
+        assert!(shared.is_simulation() != cfg!(feature = "hardware"));
+        shared.enable_simulation();
+        assert!(shared.is_simulation());
+
+        Ok(())
+    }
+
+    /// Tests scrolling a number wider than the display through every window.
+    #[test]
+    fn test_alphanum4_scroll_number() -> Result<(), Error> {
+        let mut alphanum = Alphanum4::with_simulation()?;
+        let mut expected = Alphanum4::with_simulation()?;
+
+        let windows = ["1234", "2345", "3456", "456 ", "56  ", "6   ", "    ", "   1", "  12", " 123", "1234"];
+        for (step, window) in windows.iter().enumerate() {
+            alphanum.scroll_number(123_456, step);
+            expected.print_str(window, Alignment::Left)?;
+            assert!(alphanum.ht16k33.buffer == expected.ht16k33.buffer);
+        }
+
+        // The sign is scrolled with the digits
+        alphanum.scroll_number(-12_345, 0);
+        expected.print_str("-123", Alignment::Left)?;
+        assert!(alphanum.ht16k33.buffer == expected.ht16k33.buffer);
+
+        // A number fitting on the display does not scroll
+        alphanum.scroll_number(-42, 3);
+        expected.print_str(" -42", Alignment::Left)?;
+        assert!(alphanum.ht16k33.buffer == expected.ht16k33.buffer);
+
+        Ok(())
+    }
+
+    /// Pins the auto traits: the display can be moved to another thread, and shared between threads
+    /// through its handle or on top of a shared HT16K33.
+    const _: () = {
+        const fn assert_send<T: Send>() {}
+        const fn assert_sync<T: Sync>() {}
+        assert_send::<Alphanum4>();
+        assert_send::<Alphanum4<HT16K33Handle>>();
+        assert_sync::<Alphanum4<HT16K33Handle>>();
+        assert_send::<Alphanum4Handle>();
+        assert_sync::<Alphanum4Handle>();
+        assert_send::<Error>();
+        assert_sync::<Error>();
+    };
+
+    /// Tests sharing the display between two threads.
+    #[test]
+    fn test_alphanum4_shared_handle() -> Result<(), Error> {
+        let handle = Alphanum4::with_simulation()?.into_shared();
+
+        let threads: Vec<_> = ["ABCD", "1234"].iter().map(|&value| {
+            let handle = handle.clone();
+            std::thread::spawn(move || {
+                for _i in 0..10 {
+                    handle.show_str(value, Alignment::Left).unwrap();
+                }
+            })
+        }).collect();
+
+        for writer in threads {
+            writer.join().unwrap();
+        }
+
+        // Every flush wrote one of the strings, never a mix of both
+        let mut abcd = Alphanum4::with_simulation()?;
+        abcd.print_str("ABCD", Alignment::Left)?;
+        let mut digits = Alphanum4::with_simulation()?;
+        digits.print_str("1234", Alignment::Left)?;
+
+        handle.with_driver(|alphanum| {
+            let transcript = alphanum.ht16k33.transcript();
+            let writes: Vec<&(u8, Vec<u8>)> = transcript.iter().filter(|(command, _)| *command == 0x00).collect();
+            assert!(writes.len() == 20);
+            for (_command, data) in writes {
+                assert!(data == &abcd.ht16k33.buffer.to_vec() || data == &digits.ht16k33.buffer.to_vec());
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Tests the padded digit positions of each alignment.
+    #[test]
+    fn test_alphanum4_print_str_alignment() -> Result<(), Error> {
+        let mut alphanum = Alphanum4::with_simulation()?;
+        let a = alphanum.glyph('A').unwrap();
+        let b = alphanum.glyph('B').unwrap();
+
+        let digits = |alphanum: &Alphanum4| -> Vec<u16> {
+            alphanum.ht16k33.buffer[..8].chunks(2).map(|digit| digit[0] as u16 | (digit[1] as u16) << 8).collect()
+        };
+
+        // The previous digits and decimal points are overwritten by the padding
+        alphanum.print_str("WXYZ", Alignment::Left)?;
+        alphanum.set_decimal_all(true);
+
+        alphanum.print_str("AB", Alignment::Left)?;
+        assert!(digits(&alphanum) == [a, b, 0, 0]);
+
+        alphanum.print_str("AB", Alignment::Right)?;
+        assert!(digits(&alphanum) == [0, 0, a, b]);
+
+        alphanum.print_str("AB", Alignment::Center)?;
+        assert!(digits(&alphanum) == [0, a, b, 0]);
+
+        // An odd padding puts the extra space on the right
+        alphanum.print_str("A", Alignment::Center)?;
+        assert!(digits(&alphanum) == [0, a, 0, 0]);
+
+        // Only the first 4 characters of a longer string are printed, whatever the alignment
+        alphanum.print_str("ABABAB", Alignment::Right)?;
+        assert!(digits(&alphanum) == [a, b, a, b]);
+
+        assert!(Alignment::default() == Alignment::Left);
+
+        Ok(())
+    }
+
+    /// Tests out of range positions and characters without glyph are rejected without changing the buffer.
+    #[test]
+    fn test_alphanum4_invalid_input() -> Result<(), Error> {
+        let mut alphanum = Alphanum4::with_simulation()?;
+        alphanum.print_str("ABCD", Alignment::Left)?;
+        let buffer = alphanum.ht16k33.buffer;
+
+        assert!(matches!(alphanum.set_digit_raw(4, 0x3FFF), Err(Error::InvalidArgument(_))));
+        assert!(matches!(alphanum.set_decimal(4, true), Err(Error::InvalidArgument(_))));
+        assert!(matches!(alphanum.set_digit(4, 'A', true), Err(Error::InvalidArgument(_))));
+        assert!(matches!(alphanum.set_digit(0, '\u{e9}', true), Err(Error::InvalidArgument(_))));
+        assert!(matches!(alphanum.print_str("A\u{e9}", Alignment::Left), Err(Error::InvalidArgument(_))));
+
+        // The whole scrolled string is checked, not only the visible window
+        assert!(matches!(alphanum.scroll_str("HELLO \u{e9}", 0), Err(Error::InvalidArgument(_))));
+
+        let err = alphanum.set_digit(0, '\u{e9}', false).unwrap_err();
+        assert!(err.to_string() == "Invalid argument: invalid character '\u{e9}', expected printable ASCII or a loaded glyph");
+
+        assert!(alphanum.ht16k33.buffer == buffer);
+
+        // A loaded glyph can be printed
+        alphanum.set_glyph('\u{e9}', 0x0079);
+        alphanum.set_digit(3, '\u{e9}', false)?;
+        assert!(alphanum.glyph('\u{e9}') == Some(0x0079));
+
+        Ok(())
+    }
+
+    /// Tests identical frames are written only once.
+    #[test]
+    fn test_alphanum4_show_if_changed() -> Result<(), Error> {
+        let mut alphanum = Alphanum4::with_simulation()?;
+        let writes = |alphanum: &Alphanum4| alphanum.ht16k33.commands().iter()
+            .filter(|command| matches!(command, crate::ht16k33::Command::WriteRam { .. })).count();
+
+        alphanum.print_str("1234", Alignment::Left)?;
+        assert!(alphanum.show_if_changed()?);
+        alphanum.print_str("1234", Alignment::Left)?;
+        assert!(!alphanum.show_if_changed()?);
+        assert!(writes(&alphanum) == 1);
+
+        // A change is written, even of a decimal point only
+        alphanum.set_decimal(1, true)?;
+        assert!(alphanum.show_if_changed()?);
+        assert!(writes(&alphanum) == 2);
+
+        // A plain show counts as the last write
+        alphanum.print_str("5678", Alignment::Left)?;
+        alphanum.show()?;
+        assert!(!alphanum.show_if_changed()?);
+        assert!(writes(&alphanum) == 3);
+
+        // Invalidating writes the same frame again
+        alphanum.invalidate();
+        assert!(alphanum.show_if_changed()?);
+        assert!(writes(&alphanum) == 4);
+
+        Ok(())
+    }
+
+    /// Tests reading back the text of the buffer.
+    #[test]
+    fn test_alphanum4_read_str() -> Result<(), Error> {
+        let mut alphanum = Alphanum4::with_simulation()?;
+        assert!(alphanum.read_str().as_deref() == Some("    "));
+
+        alphanum.print_str("1IF", Alignment::Right)?;
+        alphanum.set_decimal(1, true)?;
+        assert!(alphanum.read_str().as_deref() == Some(" 1IF"));
+
+        alphanum.set_digit_raw(0, 0b0011_1111_1111_1111)?;
+        assert!(alphanum.read_str().is_none());
+
+        let mut shared = Alphanum4::with_handle(HT16K33::with_simulation()?.into_shared());
+        shared.print_str("HI", Alignment::Left)?;
+        assert!(shared.read_str().as_deref() == Some("HI  "));
+
+        Ok(())
+    }
+
+    /// Tests the random glyphs replayed from a fixed seed and settling on a target string.
+    #[test]
+    fn test_alphanum4_random_glyphs() -> Result<(), Error> {
+        let mut alphanum = Alphanum4::with_simulation()?;
+        let mut rng = SimpleRng::new(1);
+
+        alphanum.set_decimal(0, true)?;
+        alphanum.random_glyph_tick(&mut rng);
+        assert!(alphanum.read_str().as_deref() == Some("X5XH"));
+        assert!(alphanum.ht16k33.buffer[1] & 0b01000000 == 0);
+        alphanum.random_glyph_tick(&mut rng);
+        assert!(alphanum.read_str().as_deref() == Some("519L"));
+
+        let mut rng = SimpleRng::new(1);
+        assert!(!alphanum.settle("HI", Alignment::Right, 0, &mut rng)?);
+        assert!(alphanum.read_str().as_deref() == Some("X5XH"));
+
+        let mut frame = 0;
+        while !alphanum.settle("HI", Alignment::Right, frame, &mut rng)? {
+            let text = alphanum.read_str().unwrap();
+            let locked = frame / SETTLE_FRAMES;
+            assert!(text[..locked] == "  HI"[..locked]);
+            frame += 1;
+        }
+        assert!(frame == NUM_DIGITS * SETTLE_FRAMES);
+        assert!(alphanum.read_str().as_deref() == Some("  HI"));
+
+        assert!(alphanum.settle("HI~\u{e9}", Alignment::Left, 0, &mut rng).is_err());
+
+        Ok(())
+    }
+
+    /// Tests the number of digits and the blank display after clearing it.
+    #[test]
+    fn test_alphanum4_digit_count_is_blank() -> Result<(), Error> {
+        let mut alphanum = Alphanum4::with_simulation()?;
+        assert!(alphanum.digit_count() == 4);
+        assert!(alphanum.is_blank());
+
+        alphanum.print_str("AB", Alignment::Left)?;
+        assert!(!alphanum.is_blank());
+        alphanum.clear();
+        assert!(alphanum.is_blank());
+
+        alphanum.set_decimal(3, true)?;
+        assert!(!alphanum.is_blank());
+        alphanum.ht16k33.clear();
+        assert!(alphanum.is_blank());
+
+        // On top of a shared driver
+        let mut shared = Alphanum4::with_handle(HT16K33::with_simulation()?.into_shared());
+        assert!(shared.digit_count() == 4);
+        shared.print_str("1", Alignment::Left)?;
+        assert!(!shared.is_blank());
+        shared.clear();
+        assert!(shared.is_blank());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,523 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use crate::Error;
+use crate::alphanum4::Alphanum4;
+use crate::apa102::APA102;
+use crate::clock::{Clock, SystemClock};
+use crate::error::invalid_input;
+use crate::lights::Lights;
+use crate::rainbow::Rainbow;
+
+/// Number of frames per second of a new animator.
+pub const DEFAULT_FRAME_RATE: u32 = 50;
+
+/// Outputs of the board animated together.
+#[derive(Debug)]
+pub struct Outputs {
+
+    /// Multicolour LEDs.
+    pub apa102: APA102,
+
+    /// Blue, green and red LEDs.
+    pub lights: Lights,
+
+    /// Alphanumeric display.
+    pub alphanum: Alphanum4,
+}
+
+impl Outputs {
+
+    /// Creates the drivers of the outputs. The hardware is initialized when first used.
+    pub fn new() -> Result<Outputs, Error> {
+        Ok(Self {
+            apa102: APA102::new()?,
+            lights: Lights::new()?,
+            alphanum: Alphanum4::new()?,
+        })
+    }
+
+    /// Creates the drivers of the outputs in simulation mode.
+    pub fn with_simulation() -> Result<Outputs, Error> {
+        Ok(Self {
+            apa102: APA102::with_simulation()?,
+            lights: Lights::with_simulation()?,
+            alphanum: Alphanum4::with_simulation()?,
+        })
+    }
+}
+
+/// Progress of an animation after a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+
+    /// The animation continues on the next frame.
+    Running,
+
+    /// The animation is over and is removed from the animator.
+    Finished,
+}
+
+/// Animation driven by an `Animator`, rendering a frame in the buffers of the outputs.
+/// Closures receiving the outputs and the elapsed time are animations.
+pub trait Animation: Send {
+
+    /// Render the frame of an instant, computed from the time elapsed rather than from the number of frames,
+    /// so the animation looks the same at any frame rate. The animator shows the outputs after every animation ran.
+    ///
+    /// # Arguments
+    ///
+    /// * `outputs` - Outputs of the board.
+    /// * `elapsed` - Time elapsed since the first frame of the animation.
+    fn tick(&mut self, outputs: &mut Outputs, elapsed: Duration) -> Result<Progress, Error>;
+}
+
+impl<F> Animation for F
+where
+    F: FnMut(&mut Outputs, Duration) -> Result<Progress, Error> + Send,
+{
+    fn tick(&mut self, outputs: &mut Outputs, elapsed: Duration) -> Result<Progress, Error> {
+        self(outputs, elapsed)
+    }
+}
+
+/// Animation added to an animator.
+struct Scheduled {
+
+    /// Identifier returned by `Animator::add`.
+    id: u64,
+
+    /// Instant of the first frame, None until then.
+    start: Option<Instant>,
+
+    /// Animation to run.
+    animation: Box<dyn Animation>,
+}
+
+/// Drives several animations of the multicolour LEDs, the lights and the display from a single thread:
+/// at each frame, every animation renders in the buffers, in the order they were added, then each output is shown once.
+/// Send but not Sync, as the animations are only Send.
+pub struct Animator {
+
+    /// Outputs given to the animations.
+    outputs: Outputs,
+
+    /// Animations running.
+    animations: Vec<Scheduled>,
+
+    /// Identifier of the next animation added.
+    next_id: u64,
+
+    /// Interval between two frames.
+    frame_interval: Duration,
+
+    /// Clock scheduling the frames and timing the animations.
+    clock: Box<dyn Clock>,
+}
+
+impl Animator {
+
+    /// Creates an animator without animations, running `DEFAULT_FRAME_RATE` frames per second.
+    ///
+    /// # Arguments
+    ///
+    /// * `outputs` - Outputs given to the animations, real or in simulation mode.
+    pub fn new(outputs: Outputs) -> Animator {
+        Self {
+            outputs,
+            animations: Vec::new(),
+            next_id: 0,
+            frame_interval: Duration::from_secs(1) / DEFAULT_FRAME_RATE,
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Creates an animator of outputs in simulation mode.
+    pub fn with_simulation() -> Result<Animator, Error> {
+        Ok(Animator::new(Outputs::with_simulation()?))
+    }
+
+    /// Get the outputs.
+    pub fn outputs(&self) -> &Outputs {
+        &self.outputs
+    }
+
+    /// Get the outputs, for example to set a background before the animations.
+    pub fn outputs_mut(&mut self) -> &mut Outputs {
+        &mut self.outputs
+    }
+
+    /// Stop the animations and get the outputs back.
+    pub fn into_outputs(self) -> Outputs {
+        self.outputs
+    }
+
+    /// Set the clock scheduling the frames and timing the animations.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Clock to use.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Box::new(clock);
+    }
+
+    /// Set the number of frames per second.
+    ///
+    /// # Arguments
+    ///
+    /// * `frames_per_second` - Frame rate, greater than 0.
+    pub fn set_frame_rate(&mut self, frames_per_second: u32) -> Result<(), Error> {
+        if frames_per_second == 0 {
+            return Err(Error::InvalidInput(invalid_input("frame rate", frames_per_second, "greater than 0")));
+        }
+
+        self.frame_interval = Duration::from_secs(1) / frames_per_second;
+        Ok(())
+    }
+
+    /// Get the interval between two frames.
+    pub fn frame_interval(&self) -> Duration {
+        self.frame_interval
+    }
+
+    /// Add an animation, starting on the next frame and rendered after the animations already added.
+    /// Returns its identifier.
+    ///
+    /// # Arguments
+    ///
+    /// * `animation` - Animation to run.
+    pub fn add<A: Animation + 'static>(&mut self, animation: A) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.animations.push(Scheduled {
+            id,
+            start: None,
+            animation: Box::new(animation),
+        });
+        id
+    }
+
+    /// Remove an animation, leaving the outputs as they are.
+    /// Returns true if the animation was running.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Identifier returned by `add`.
+    pub fn remove(&mut self, id: u64) -> bool {
+        let count = self.animations.len();
+        self.animations.retain(|scheduled| scheduled.id != id);
+        self.animations.len() != count
+    }
+
+    /// Get the number of animations running.
+    pub fn len(&self) -> usize {
+        self.animations.len()
+    }
+
+    /// Get whether no animation is running.
+    pub fn is_empty(&self) -> bool {
+        self.animations.is_empty()
+    }
+
+    /// Run one frame: every animation renders in the buffers, the finished ones being removed,
+    /// then the multicolour LEDs are shown, and the display if it changed.
+    /// Returns the error of the first failing animation, which is removed, the next animations of the frame not being run.
+    pub fn step(&mut self) -> Result<(), Error> {
+        let now = self.clock.now();
+
+        let mut i = 0;
+        while i < self.animations.len() {
+            let scheduled = &mut self.animations[i];
+            let elapsed = now - *scheduled.start.get_or_insert(now);
+
+            match scheduled.animation.tick(&mut self.outputs, elapsed) {
+                Ok(Progress::Running) => i += 1,
+                Ok(Progress::Finished) => {
+                    self.animations.remove(i);
+                }
+                Err(err) => {
+                    self.animations.remove(i);
+                    return Err(err);
+                }
+            }
+        }
+
+        self.outputs.apa102.show()?;
+        self.outputs.alphanum.show_if_changed()?;
+
+        Ok(())
+    }
+
+    /// Run the frames at the frame rate until cancelled, until every animation finished, or until one fails.
+    /// The frames are scheduled from the first one, so the time taken by the animations does not make the animator drift.
+    ///
+    /// # Arguments
+    ///
+    /// * `cancel` - Set to true to stop, checked before each frame.
+    pub fn run(&mut self, cancel: &AtomicBool) -> Result<(), Error> {
+        let mut next = self.clock.now();
+
+        while !cancel.load(Ordering::SeqCst) && !self.animations.is_empty() {
+            self.step()?;
+
+            // A late frame delays the next ones instead of running several frames in a row
+            next += self.frame_interval;
+            let now = self.clock.now();
+            if next > now {
+                self.clock.sleep(next - now);
+            } else {
+                next = now;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Animator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Animator")
+            .field("animations", &self.animations.iter().map(|scheduled| scheduled.id).collect::<Vec<_>>())
+            .field("frame_interval", &self.frame_interval)
+            .finish()
+    }
+}
+
+/// Rainbow cycling on the multicolour LEDs, never finishing.
+#[derive(Debug)]
+pub struct RainbowCycle {
+
+    /// Rainbow giving the hues.
+    rainbow: Rainbow,
+
+    /// Brightness of the pixels, between 0.0 and 1.0.
+    brightness: f32,
+}
+
+impl RainbowCycle {
+
+    /// Creates a rainbow cycle.
+    ///
+    /// # Arguments
+    ///
+    /// * `rainbow` - Rainbow giving the speed and spread of the hues.
+    /// * `brightness` - Brightness of the pixels, between 0.0 and 1.0.
+    pub fn new(rainbow: Rainbow, brightness: f32) -> RainbowCycle {
+        Self {
+            rainbow,
+            brightness,
+        }
+    }
+}
+
+impl Animation for RainbowCycle {
+    fn tick(&mut self, outputs: &mut Outputs, elapsed: Duration) -> Result<Progress, Error> {
+        self.rainbow.render_at(&mut outputs.apa102, self.brightness, elapsed)?;
+        Ok(Progress::Running)
+    }
+}
+
+/// Text scrolling on the display, as with `Alphanum4::scroll_str`, once or in a loop.
+/// A text fitting on the display is printed right aligned for one step.
+#[derive(Debug, Clone)]
+pub struct ScrollText {
+
+    /// Text scrolled.
+    text: String,
+
+    /// Duration of each scroll step.
+    step_interval: Duration,
+
+    /// Whether the text scrolls again after the end.
+    looping: bool,
+}
+
+impl ScrollText {
+
+    /// Creates a text scrolling once.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - Text of printable ASCII characters or loaded glyphs.
+    /// * `step_interval` - Duration of each scroll step.
+    pub fn new(text: &str, step_interval: Duration) -> ScrollText {
+        Self {
+            text: text.to_string(),
+            step_interval,
+            looping: false,
+        }
+    }
+
+    /// Scroll the text again after the end, never finishing.
+    ///
+    /// # Arguments
+    ///
+    /// * `looping` - true to loop.
+    pub fn looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+}
+
+impl Animation for ScrollText {
+    fn tick(&mut self, outputs: &mut Outputs, elapsed: Duration) -> Result<Progress, Error> {
+        let length = self.text.chars().count();
+        let digits = outputs.alphanum.digit_count();
+        let steps = if length <= digits { 1 } else { length + digits };
+
+        let step = if self.step_interval.is_zero() { 0 } else { (elapsed.as_nanos() / self.step_interval.as_nanos()) as usize };
+        if !self.looping && step >= steps {
+            outputs.alphanum.clear();
+            return Ok(Progress::Finished);
+        }
+
+        outputs.alphanum.scroll_str(&self.text, step % steps)?;
+        Ok(Progress::Running)
+    }
+}
+
+/// Lights blinking together, on then off for the same interval.
+#[derive(Debug, Clone)]
+pub struct LightBlink {
+
+    /// Whether each light blinks: red, green and blue.
+    lights: (bool, bool, bool),
+
+    /// Duration of the on and of the off part of a blink.
+    interval: Duration,
+
+    /// Number of blinks before finishing, None to blink forever.
+    count: Option<u32>,
+}
+
+impl LightBlink {
+
+    /// Creates lights blinking forever.
+    ///
+    /// # Arguments
+    ///
+    /// * `r` - Whether the red light blinks.
+    /// * `g` - Whether the green light blinks.
+    /// * `b` - Whether the blue light blinks.
+    /// * `interval` - Duration of the on and of the off part of a blink.
+    pub fn new(r: bool, g: bool, b: bool, interval: Duration) -> LightBlink {
+        Self {
+            lights: (r, g, b),
+            interval,
+            count: None,
+        }
+    }
+
+    /// Finish after a number of blinks, with the lights off.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - Number of blinks.
+    pub fn times(mut self, count: u32) -> Self {
+        self.count = Some(count);
+        self
+    }
+
+    /// Switch the blinking lights.
+    ///
+    /// # Arguments
+    ///
+    /// * `lights` - Lights of the board.
+    /// * `state` - true for on, false for off.
+    fn write(&self, lights: &mut Lights, state: bool) {
+        let (r, g, b) = self.lights;
+        for (light, blinks) in [(&mut lights.red, r), (&mut lights.green, g), (&mut lights.blue, b)] {
+            if blinks {
+                light.write(state);
+            }
+        }
+    }
+}
+
+impl Animation for LightBlink {
+    fn tick(&mut self, outputs: &mut Outputs, elapsed: Duration) -> Result<Progress, Error> {
+        let half_blinks = if self.interval.is_zero() { 0 } else { elapsed.as_nanos() / self.interval.as_nanos() };
+
+        if self.count.is_some_and(|count| half_blinks >= count as u128 * 2) {
+            self.write(&mut outputs.lights, false);
+            return Ok(Progress::Finished);
+        }
+
+        self.write(&mut outputs.lights, half_blinks & 1 == 0);
+        Ok(Progress::Running)
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    /// Tests the animations of every output are driven together, frame by frame.
+    #[test]
+    fn test_animator_combined_frames() -> Result<(), Error> {
+        let clock = ManualClock::new();
+        let mut animator = Animator::with_simulation()?;
+        animator.set_clock(clock.clone());
+        animator.set_frame_rate(4)?;
+
+        animator.add(RainbowCycle::new(Rainbow::new(), 1.0));
+        animator.add(ScrollText::new("HELLO", Duration::from_millis(500)));
+        animator.add(LightBlink::new(true, false, true, Duration::from_millis(250)).times(2));
+        assert!(animator.len() == 3);
+
+        // Text shown and lights state at each frame, every 250 ms
+        let frames = [
+            ("HELL", true), ("HELL", false), ("ELLO", true), ("ELLO", false),
+            ("LLO ", false), ("LLO ", false), ("LO  ", false), ("LO  ", false),
+        ];
+        let mut hues = Vec::new();
+        for &(text, on) in frames.iter() {
+            animator.step()?;
+            clock.advance(animator.frame_interval());
+
+            let outputs = animator.outputs();
+            assert!(outputs.alphanum.read_str().as_deref() == Some(text), "{:?}", outputs.alphanum.read_str());
+            assert!(outputs.lights.red.state == on && outputs.lights.blue.state == on && !outputs.lights.green.state);
+            hues.push(outputs.apa102.last_frame().unwrap()[crate::apa102::NUM_PIXELS - 1]);
+        }
+
+        // The blink finished after its two blinks, the rainbow keeps moving
+        assert!(animator.len() == 2);
+        assert!(hues.windows(2).all(|pair| pair[0] != pair[1]));
+
+        Ok(())
+    }
+
+    /// Tests running the animator until the animations finish, the time of each frame being given to closures.
+    #[test]
+    fn test_animator_run() -> Result<(), Error> {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let mut animator = Animator::with_simulation()?;
+        animator.set_clock(clock.clone());
+        animator.set_frame_rate(10)?;
+
+        animator.add(|outputs: &mut Outputs, elapsed: Duration| {
+            outputs.apa102.set_all(0, 255, 0, 1.0)?;
+            Ok(if elapsed >= Duration::from_millis(500) { Progress::Finished } else { Progress::Running })
+        });
+        let removed = animator.add(ScrollText::new("SCROLLING", Duration::from_millis(100)).looping(true));
+        assert!(animator.remove(removed));
+        assert!(!animator.remove(removed));
+
+        animator.run(&AtomicBool::new(false))?;
+        assert!(animator.is_empty());
+        assert!(clock.now() - start == Duration::from_millis(600));
+        assert!(animator.outputs().apa102.last_frame().unwrap().iter().all(|pixel| pixel[1..4] == [0, 255, 0]));
+
+        // A failing animation is removed and stops the animator
+        animator.add(ScrollText::new("É", Duration::from_millis(100)));
+        assert!(matches!(animator.run(&AtomicBool::new(false)), Err(Error::InvalidInput(_))));
+        assert!(animator.is_empty());
+        assert!(matches!(animator.set_frame_rate(0), Err(Error::InvalidInput(_))));
+
+        Ok(())
+    }
+}
@@ -1,8 +1,27 @@
 use std::fmt;
+use std::f32::consts::PI;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
 use std::thread;
-use std::time::Duration;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 use core::fmt::Debug;
-use rppal::gpio::{Gpio, OutputPin, Level};
+#[cfg(feature = "hardware")]
+use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
+use crate::clock::{Clock, Phase, SystemClock};
+use crate::colors;
+use crate::error::invalid_input;
+use crate::hal::DigitalOutput;
+#[cfg(feature = "hardware")]
+use crate::hal::{self, Backend, Rppal};
+#[cfg(feature = "hardware")]
+use crate::pins;
+use crate::pins::{PinClaim, PinInUse, PinRegistry};
+use crate::rainbow_hat::Component;
+use crate::rainbow::hsv_to_rgb;
+use crate::recorder::{MemoryRecorder, Payload, Recorder};
+use crate::rng::{Rng, SimpleRng};
+use crate::trace::Tracer;
+use crate::units::Brightness;
 
 /// GPIO BCM pin number for DAT.
 pub const GPIO_DAT: u8 = 10;
@@ -19,27 +38,139 @@ pub const NUM_PIXELS: usize = 7;
 /// Brightness.
 pub const BRIGHTNESS: u8 = 7;
 
-/// Sleep time between pin commands.
+/// Sleep time between pin commands, in milliseconds. Too coarse to slow the clock down: see `set_min_clock_period`.
 pub const SLEEP_TIME : u64 = 0;
 
+/// Gamma of the perceived brightness, corrected by `present()`.
+pub const GAMMA: f32 = 2.8;
+
+/// Current drawn by a single color channel at full value and full brightness, in milliamps.
+pub const MILLIAMPS_PER_CHANNEL: f32 = 20.0;
+
+/// Slowest SPI clock speed the Raspberry Pi generates, in hertz: its 250 MHz core clock divided by 65536.
+pub const SPI_MIN_CLOCK_SPEED: u32 = 3_815;
+
+/// Fastest SPI clock speed the Raspberry Pi generates, in hertz: its 250 MHz core clock divided by 2.
+pub const SPI_MAX_CLOCK_SPEED: u32 = 125_000_000;
+
+/// Get the BCM pins of a SPI bus written by the LEDs: MOSI, SCLK and the first slave select.
+///
+/// # Arguments
+///
+/// * `bus` - SPI bus.
+#[cfg(feature = "hardware")]
+fn spi_pins(bus: Bus) -> [u8; 3] {
+    match bus {
+        Bus::Spi0 => [10, 11, 8],
+        Bus::Spi1 | Bus::Spi6 => [20, 21, 18],
+        Bus::Spi2 => [41, 42, 43],
+        Bus::Spi3 => [2, 3, 0],
+        Bus::Spi4 => [6, 7, 4],
+        Bus::Spi5 => [14, 15, 12],
+    }
+}
+
+/// Seed of the random sparks of the fire effect, until `set_fire_seed` is called.
+pub const FIRE_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Number of pixels from the base of the fire, pixel 0, where the sparks are ignited.
+pub const FIRE_SPARK_PIXELS: usize = 2;
+
+/// Percentage from which the threshold palette of the graph turns amber.
+pub const GRAPH_AMBER_THRESHOLD: f32 = 50.0;
+
+/// Percentage from which the threshold palette of the graph turns red.
+pub const GRAPH_RED_THRESHOLD: f32 = 80.0;
+
 /// Rainbow HAT APA102 Driver.
+/// Send, so it can be moved to the thread animating the LEDs, but not Sync as its pins and trace hook
+/// are only Send: use `into_shared` to drive it from several threads.
 #[derive(Debug)]
 pub struct APA102 {
 
     /// Output pin to write to GPIO. Optional as not used in simulated mode.
-    pin_dat: Option<Box<OutputPin>>,
+    pin_dat: Option<Box<dyn DigitalOutput>>,
 
     /// Output pin to write to GPIO. Optional as not used in simulated mode.
-    pin_clk: Option<Box<OutputPin>>,
+    pin_clk: Option<Box<dyn DigitalOutput>>,
 
     /// Output pin to write to GPIO. Optional as not used in simulated mode.
-    pin_cs: Option<Box<OutputPin>>,
+    pin_cs: Option<Box<dyn DigitalOutput>>,
+
+    /// SPI bus and clock speed in hertz, when written with hardware SPI instead of the GPIO pins
+    #[cfg(feature = "hardware")]
+    spi_config: Option<(Bus, u32)>,
+
+    /// SPI. Optional as only used with hardware SPI, not in simulated mode.
+    #[cfg(feature = "hardware")]
+    spi: Option<Spi>,
 
     /// pixels to be printed
     pub pixels: [[u8;4] ; NUM_PIXELS],
 
-    /// brightness between 0 and 15
-    brightness: u8,
+    /// global brightness between 0.0 and 1.0, scaling the brightness of every pixel when shown
+    global_brightness: f32,
+
+    /// clock used by the time based effects and the minimum clock period
+    clock: Box<dyn Clock>,
+
+    /// minimum period of the clock output when written with the GPIO pins, zero for as fast as possible
+    min_clock_period: Duration,
+
+    /// instant at which the breathing effect started
+    breathe_start: Option<Instant>,
+
+    /// instant at which the strobe effect started
+    strobe_start: Option<Instant>,
+
+    /// heat of each pixel of the fire effect
+    fire_heat: [u8; NUM_PIXELS],
+
+    /// source of the random numbers of the fire effect
+    random: Box<dyn Rng>,
+
+    /// whether the scaled brightness alternates between its floor and ceiling over the frames
+    dithering: bool,
+
+    /// brightness error carried to the next frame of each pixel when dithering
+    dither_error: [f32; NUM_PIXELS],
+
+    /// maximum current the pixels can draw, in milliamps
+    power_limit: Option<u32>,
+
+    /// colors of the paletted mode
+    palette: Vec<(u8, u8, u8)>,
+
+    /// index in the palette of each pixel, if in paletted mode
+    palette_indices: Option<[usize; NUM_PIXELS]>,
+
+    /// last frame written, as sent on the wire for each pixel: brightness, blue, green, red
+    last_frame: Option<[[u8; 4]; NUM_PIXELS]>,
+
+    /// duration without a frame written after which the watchdog blanks the strip, if enabled
+    watchdog_timeout: Option<Duration>,
+
+    /// instant of the last frame written, or of the start of the watchdog
+    last_show: Option<Instant>,
+
+    /// hook receiving a message before each hardware operation
+    tracer: Tracer,
+
+    /// recorder of the frames shown in simulation mode
+    recorder: Box<dyn Recorder>,
+
+    /// backend opening the peripherals on setup
+    #[cfg(feature = "hardware")]
+    backend: Box<dyn Backend>,
+
+    /// claims of the pins on setup, released with the driver
+    claims: Vec<PinClaim>,
+
+    /// registry of the pins claimed in simulation mode, shared by the drivers of a board
+    pin_registry: PinRegistry,
+
+    /// whether to switch to simulation mode on setup when the hardware is absent
+    auto_simulate: bool,
 
     /// In simulation mode, no interaction with the hardware is done to simplify testability.
     simulation: bool, 
@@ -52,42 +183,244 @@ impl APA102 {
     
     /// Creates a APA102.
     pub fn new() -> Result<APA102, Error>  {     
+        Ok(APA102::create())
+    }
 
-        Ok(Self {
+    /// Creates the driver with every pixel off and the default settings, not set up.
+    fn create() -> APA102 {
+        Self {
             pin_dat: None,
             pin_clk: None,
             pin_cs: None,
+            #[cfg(feature = "hardware")]
+            spi_config: None,
+            #[cfg(feature = "hardware")]
+            spi: None,
             pixels:[[0; 4]; NUM_PIXELS],
-            brightness: BRIGHTNESS,
-            simulation: false,
+            global_brightness: 1.0,
+            clock: Box::new(SystemClock),
+            min_clock_period: Duration::ZERO,
+            breathe_start: None,
+            strobe_start: None,
+            fire_heat: [0; NUM_PIXELS],
+            random: Box::new(SimpleRng::new(FIRE_SEED)),
+            dithering: false,
+            dither_error: [0.0; NUM_PIXELS],
+            power_limit: None,
+            palette: Vec::new(),
+            palette_indices: None,
+            last_frame: None,
+            watchdog_timeout: None,
+            last_show: None,
+            tracer: Tracer::new(),
+            recorder: Box::new(MemoryRecorder::new()),
+            #[cfg(feature = "hardware")]
+            backend: Box::new(Rppal),
+            claims: Vec::new(),
+            pin_registry: PinRegistry::new(),
+            auto_simulate: false,
+            simulation: !cfg!(feature = "hardware"),
             is_setup: false,
-        })
+        }
+    }
+
+    /// Creates a APA102 written by toggling the given outputs instead of the Raspberry Pi GPIO.
+    /// # Arguments
+    ///
+    /// * `dat` - Data output.
+    /// * `clk` - Clock output.
+    /// * `cs` - Chip select output.
+    pub fn with_pins<D, C, S>(dat: D, clk: C, cs: S) -> APA102
+    where
+        D: DigitalOutput + 'static,
+        C: DigitalOutput + 'static,
+        S: DigitalOutput + 'static,
+    {
+        let mut apa102 = APA102::create();
+        apa102.pin_dat = Some(Box::new(dat));
+        apa102.pin_clk = Some(Box::new(clk));
+        apa102.pin_cs = Some(Box::new(cs));
+        apa102.simulation = false;
+        apa102
+    }
+
+    /// Creates a APA102 written with hardware SPI, for example on SPI1 when SPI0 is used by another device.
+    /// The LEDs tolerate a wide range of clock speeds: raise it for high frame rates, lower it for long wires.
+    /// # Arguments
+    ///
+    /// * `bus` - SPI bus, the LEDs being selected by its first slave select pin.
+    /// * `clock_speed` - Clock speed in hertz, from `SPI_MIN_CLOCK_SPEED` to `SPI_MAX_CLOCK_SPEED`.
+    #[cfg(feature = "hardware")]
+    pub fn with_spi_bus(bus: Bus, clock_speed: u32) -> Result<APA102, Error> {
+        if !(SPI_MIN_CLOCK_SPEED..=SPI_MAX_CLOCK_SPEED).contains(&clock_speed) {
+            return Err(Error::InvalidArgument(invalid_input(
+                "SPI clock speed", format!("{} Hz", clock_speed), &format!("{} to {} Hz", SPI_MIN_CLOCK_SPEED, SPI_MAX_CLOCK_SPEED))));
+        }
+
+        let mut apa102 = APA102::new()?;
+        apa102.spi_config = Some((bus, clock_speed));
+        Ok(apa102)
+    }
+
+    /// Get the SPI bus the LEDs are written with, None when written with the GPIO pins.
+    #[cfg(feature = "hardware")]
+    pub fn spi_bus(&self) -> Option<Bus> {
+        self.spi_config.map(|(bus, _)| bus)
+    }
+
+    /// Get the SPI clock speed in hertz, None when written with the GPIO pins.
+    #[cfg(feature = "hardware")]
+    pub fn spi_clock_speed(&self) -> Option<u32> {
+        self.spi_config.map(|(_, clock_speed)| clock_speed)
+    }
+
+    /// Creates a APA102 in simulation mode, never accessing the hardware.
+    pub fn with_simulation() -> Result<APA102, Error> {
+        let mut apa102 = APA102::new()?;
+        apa102.simulation = true;
+        Ok(apa102)
+    }
+
+    /// Enables simulation mode: no interaction with the hardware is done.
+    /// Enable it before the first operation, which initializes the hardware.
+    pub fn enable_simulation(&mut self) {
+        self.simulation = true;
+    }
+
+    /// Get whether simulation mode is enabled.
+    pub fn is_simulation(&self) -> bool {
+        self.simulation
+    }
+
+    /// Switch to simulation mode on setup when the hardware is absent, for example when running off the Raspberry Pi,
+    /// instead of returning an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to fall back to simulation mode.
+    pub fn set_auto_simulate(&mut self, enabled: bool) {
+        self.auto_simulate = enabled;
+    }
+
+    /// Get whether the driver switches to simulation mode on setup when the hardware is absent.
+    pub fn is_auto_simulate(&self) -> bool {
+        self.auto_simulate
+    }
+
+    /// Set the backend opening the GPIO on setup.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Backend to use.
+    #[cfg(feature = "hardware")]
+    pub fn set_backend<B: Backend + 'static>(&mut self, backend: B) {
+        self.backend = Box::new(backend);
+    }
+
+    /// Set the registry the data, clock and select pins are claimed in when set up in simulation mode, such as the
+    /// one of the board. The driver has its own registry by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - Registry to use.
+    pub fn set_pin_registry(&mut self, registry: PinRegistry) {
+        self.pin_registry = registry;
+    }
+
+    /// Set a hook receiving a message before each hardware operation, also in simulation mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Closure receiving each message.
+    pub fn set_trace<F: FnMut(&str) + Send + 'static>(&mut self, hook: F) {
+        self.tracer.set(hook);
+    }
+
+    /// Remove the trace hook.
+    pub fn clear_trace(&mut self) {
+        self.tracer.clear();
     }
 
     /// Initialize driver.
     pub fn setup(&mut self) -> Result <(), Error> {
         if !self.is_setup {
 
-            // Ignore Gpio initialization if in simulation mode
-            if !self.simulation {
-                let gpio_dat = Gpio::new()?;
-                let output_dat = gpio_dat.get(GPIO_DAT)?.into_output(); 
-                self.pin_dat = Some(Box::new(output_dat));
+            // Claim the pins unless given the outputs: in the registry of the process when opened on the hardware,
+            // of the board in simulation mode
+            let mut claims = Vec::new();
 
-                let gpio_clk = Gpio::new()?;
-                let output_clk = gpio_clk.get(GPIO_CLK)?.into_output(); 
-                self.pin_clk = Some(Box::new(output_clk));
+            // Ignore Gpio initialization if in simulation mode
+            #[cfg(feature = "hardware")]
+            if let (false, Some((bus, clock_speed))) = (self.simulation, self.spi_config) {
+                claims = pins::claim_all(&spi_pins(bus), "multicolour LEDs")?;
+                match Spi::new(bus, SlaveSelect::Ss0, clock_speed, Mode::Mode0) {
+                    Ok(spi) => self.spi = Some(spi),
+                    Err(err) if self.auto_simulate && hal::is_spi_absent(&err) => self.fall_back_to_simulation(&err),
+                    Err(err) => return Err(err.into()),
+                }
+            } else if !self.simulation && self.pin_dat.is_none() {
+                match self.backend.gpio() {
+                    Ok(gpio) => {
+                        claims = pins::claim_all(&[GPIO_DAT, GPIO_CLK, GPIO_CS], "multicolour LEDs")?;
+                        let output_dat = gpio.get(GPIO_DAT).map_err(|source| Error::Gpio { pin: GPIO_DAT, source })?.into_output();
+                        self.pin_dat = Some(Box::new(output_dat));
+
+                        let output_clk = gpio.get(GPIO_CLK).map_err(|source| Error::Gpio { pin: GPIO_CLK, source })?.into_output();
+                        self.pin_clk = Some(Box::new(output_clk));
+
+                        let output_cs = gpio.get(GPIO_CS).map_err(|source| Error::Gpio { pin: GPIO_CS, source })?.into_output();
+                        self.pin_cs = Some(Box::new(output_cs));
+                    }
+                    Err(err) if self.auto_simulate && hal::is_gpio_absent(&err) => self.fall_back_to_simulation(&err),
+                    Err(source) => return Err(Error::Gpio { pin: GPIO_DAT, source }),
+                }
+            }
 
-                let gpio_cs = Gpio::new()?;
-                let output_cs = gpio_cs.get(GPIO_CS)?.into_output(); 
-                self.pin_cs = Some(Box::new(output_cs));
+            if self.simulation {
+                claims = self.pin_registry.claim_all(&self.pins_to_claim(), "multicolour LEDs")?;
             }
 
+            self.claims = claims;
             self.is_setup = true;
         }
         Ok(())
     }
 
+    /// Get the BCM pins written by the driver: those of the SPI bus or the GPIO pins, none when given the outputs.
+    fn pins_to_claim(&self) -> Vec<u8> {
+        #[cfg(feature = "hardware")]
+        if let Some((bus, _)) = self.spi_config {
+            return spi_pins(bus).to_vec();
+        }
+
+        match self.pin_dat {
+            None => vec![GPIO_DAT, GPIO_CLK, GPIO_CS],
+            Some(_) => Vec::new(),
+        }
+    }
+
+    /// Release the pins or the SPI bus: their handles are dropped, so rppal returns them to their prior state now
+    /// rather than when the driver is dropped. The driver is set up again on its next use, the pixels being kept.
+    /// Pins given with `with_pins` are dropped too.
+    pub fn release(&mut self) {
+        self.pin_dat = None;
+        self.pin_clk = None;
+        self.pin_cs = None;
+        #[cfg(feature = "hardware")]
+        {
+            self.spi = None;
+        }
+        self.claims.clear();
+        self.is_setup = false;
+    }
+
+    /// Switches to simulation mode as the hardware is absent.
+    #[cfg(feature = "hardware")]
+    fn fall_back_to_simulation(&mut self, err: &dyn fmt::Display) {
+        self.tracer.trace(|| format!("APA102: hardware unavailable ({}), simulating", err));
+        self.simulation = true;
+    }
+
     /// Exit.
     pub fn exit(&mut self) -> Result <(), Error> {
         self.clear();
@@ -100,22 +433,295 @@ impl APA102 {
     /// # Arguments
     ///
     /// * `brightness` - Brightness: 0.0 to 1.0.
-    pub fn set_brightness(&mut self, brightness : f32) {
+    pub fn set_brightness(&mut self, brightness : f32) -> Result <(), Error> {
+        APA102::check_brightness(brightness)?;
+        self.write_brightness(brightness);
+
+        Ok(())
+    }
+
+    /// Set the brightness of all pixels.
+    /// # Arguments
+    ///
+    /// * `brightness` - Brightness: 0.0 to 1.0.
+    #[deprecated(note = "set_brightness returns the error")]
+    pub fn try_set_brightness(&mut self, brightness : f32) -> Result <(), Error> {
+        self.set_brightness(brightness)
+    }
 
-        assert!(brightness >= 0.0);
-        assert!(brightness <= 1.0);
+    /// Set the brightness of all pixels, clamping it to 0.0 to 1.0. NaN is treated as 0.0.
+    /// Suited to computed brightness values.
+    /// # Arguments
+    ///
+    /// * `brightness` - Brightness, any value.
+    pub fn set_brightness_clamped(&mut self, brightness : f32) {
+        self.write_brightness(APA102::clamp_brightness(brightness));
+    }
+
+    /// Writes the brightness of all pixels in the buffer.
+    /// # Arguments
+    ///
+    /// * `brightness` - Brightness, already validated: 0.0 to 1.0.
+    fn write_brightness(&mut self, brightness : f32) {
+        debug_assert!((0.0..=1.0).contains(&brightness));
 
         for i in 0..self.pixels.len() {
-            self.pixels[i][3] = (31.0 * brightness.round()) as u8;
+            self.pixels[i][3] = (31.0 * brightness).round() as u8;
+        }
+    }
+
+    /// Set the global brightness, scaling the brightness of every pixel when shown
+    /// without changing the pixel buffer.
+    /// # Arguments
+    ///
+    /// * `brightness` - Brightness: 0.0 to 1.0.
+    pub fn set_global_brightness(&mut self, brightness : f32) -> Result <(), Error> {
+        APA102::check_brightness(brightness)?;
+        self.global_brightness = brightness;
+
+        Ok(())
+    }
+
+    /// Set the global brightness from a brightness shared with the other outputs of the board,
+    /// already valid so the call cannot fail.
+    /// # Arguments
+    ///
+    /// * `brightness` - Brightness, for example `Brightness::percent(30)`.
+    pub fn dim_to<B: Into<Brightness>>(&mut self, brightness: B) {
+        self.global_brightness = brightness.into().value();
+    }
+
+    /// Get the global brightness, between 0.0 and 1.0.
+    pub fn global_brightness(&self) -> f32 {
+        self.global_brightness
+    }
+
+    /// Set the recorder of the frames shown in simulation mode, for example one shared with the other drivers
+    /// to check the order of the operations across peripherals.
+    ///
+    /// # Arguments
+    ///
+    /// * `recorder` - Recorder to use.
+    pub fn set_recorder<R: Recorder + 'static>(&mut self, recorder: R) {
+        self.recorder = Box::new(recorder);
+    }
+
+    /// Set the clock used by the time based effects and to time the minimum clock period.
+    /// # Arguments
+    ///
+    /// * `clock` - Clock to use.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Box::new(clock);
+    }
+
+    /// Modulate the global brightness with a sine wave so the whole strip "breathes" while keeping its colors.
+    /// To be called on each frame before `show()`. The phase starts on the first call.
+    /// # Arguments
+    ///
+    /// * `period` - Duration of a full breath.
+    pub fn breathe_tick(&mut self, period: Duration) {
+        let now = self.clock.now();
+        let start = *self.breathe_start.get_or_insert(now);
+        self.breathe_with(&Phase::new(start, period));
+    }
+
+    /// Modulate the global brightness with a sine wave, like `breathe_tick`, at the current phase of a period
+    /// started by the caller, for example to breathe in sync with another effect.
+    /// # Arguments
+    ///
+    /// * `phase` - Phase of the breath, read from the clock of the driver.
+    pub fn breathe_with(&mut self, phase: &Phase) {
+        let phase = phase.now(self.clock.as_ref());
+
+        self.global_brightness = (0.5 + 0.5 * (2.0 * PI * phase).sin()).clamp(0.0, 1.0);
+    }
+
+    /// Toggle the whole strip between a color and black, for alerts.
+    /// To be called on each frame before `show()`. The period starts on the first call with the strip on.
+    /// The brightness of the pixels is kept.
+    /// # Arguments
+    ///
+    /// * `color` - Color shown during the on portion of the period: red, green, blue.
+    /// * `on_ratio` - Portion of the period the strip is on, clamped between 0.0 and 1.0.
+    /// * `period` - Duration of a full on/off cycle.
+    pub fn strobe_tick(&mut self, color: (u8, u8, u8), on_ratio: f32, period: Duration) {
+        let now = self.clock.now();
+        let start = *self.strobe_start.get_or_insert(now);
+        self.strobe_with(&Phase::new(start, period), color, on_ratio);
+    }
+
+    /// Toggle the whole strip between a color and black, like `strobe_tick`, at the current phase of a period
+    /// started by the caller. The brightness of the pixels is kept.
+    /// # Arguments
+    ///
+    /// * `phase` - Phase of the on/off cycle, read from the clock of the driver.
+    /// * `color` - Color shown during the on portion of the period: red, green, blue.
+    /// * `on_ratio` - Portion of the period the strip is on, clamped between 0.0 and 1.0.
+    pub fn strobe_with(&mut self, phase: &Phase, color: (u8, u8, u8), on_ratio: f32) {
+        let phase = phase.now(self.clock.as_ref());
+
+        let (r, g, b) = if phase < on_ratio.clamp(0.0, 1.0) { color } else { (0, 0, 0) };
+
+        for pixel in self.pixels.iter_mut() {
+            pixel[0] = r;
+            pixel[1] = g;
+            pixel[2] = b;
+        }
+    }
+
+    /// Seed the random sparks of the fire effect and cool the fire down, so the same seed always gives the same flames.
+    /// # Arguments
+    ///
+    /// * `seed` - Seed of the sparks.
+    pub fn set_fire_seed(&mut self, seed: u64) {
+        self.set_rng(SimpleRng::new(seed));
+    }
+
+    /// Set the source of the random numbers of the random effects and cool the fire down.
+    /// # Arguments
+    ///
+    /// * `rng` - Source to use.
+    pub fn set_rng<R: Rng + 'static>(&mut self, rng: R) {
+        self.random = Box::new(rng);
+        self.fire_heat = [0; NUM_PIXELS];
+    }
+
+    /// Get the heat of each pixel of the fire effect, from 0 for cold to 255 for the hottest.
+    pub fn fire_heat(&self) -> &[u8; NUM_PIXELS] {
+        &self.fire_heat
+    }
+
+    /// Animate a flickering flame rising from pixel 0, with the heat diffusion of the Fire2012 effect of FastLED.
+    /// Each pixel cools down, the heat drifts away from the base, new sparks are randomly ignited at the base,
+    /// then the heat is mapped to a color from black through red and yellow to white.
+    /// To be called on each frame before `show()`. The brightness of the pixels is kept.
+    /// # Arguments
+    ///
+    /// * `cooling` - How much the pixels cool down on each frame: around 55, higher for shorter flames.
+    /// * `sparking` - Chance out of 255 that a spark is ignited on each frame: around 120, higher for a roaring fire.
+    pub fn fire_tick(&mut self, cooling: u8, sparking: u8) {
+
+        // Cool down every pixel a little
+        let max_cooling = (cooling as usize * 10 / NUM_PIXELS + 2).min(255) as u8;
+        for i in 0..NUM_PIXELS {
+            let amount = self.random_below(max_cooling);
+            self.fire_heat[i] = self.fire_heat[i].saturating_sub(amount);
+        }
+
+        // Heat drifts away from the base and diffuses a little
+        for i in (2..NUM_PIXELS).rev() {
+            self.fire_heat[i] = ((self.fire_heat[i - 1] as u16 + 2 * self.fire_heat[i - 2] as u16) / 3) as u8;
+        }
+
+        // Randomly ignite new sparks near the base
+        if self.random.next_u8() < sparking {
+            let i = self.random_below(FIRE_SPARK_PIXELS as u8) as usize;
+            let spark = 160 + self.random_below(95);
+            self.fire_heat[i] = self.fire_heat[i].saturating_add(spark);
+        }
+
+        for i in 0..NUM_PIXELS {
+            let (r, g, b) = APA102::heat_color(self.fire_heat[i]);
+            self.pixels[i][0] = r;
+            self.pixels[i][1] = g;
+            self.pixels[i][2] = b;
+        }
+    }
+
+    /// Get the color of a heat, from black through red and yellow to white, as HeatColor of FastLED.
+    /// # Arguments
+    ///
+    /// * `heat` - Heat from 0 to 255.
+    pub fn heat_color(heat: u8) -> (u8, u8, u8) {
+
+        // Scale to 0 to 191, never rounding a non zero heat down to 0
+        let t192 = ((heat as u16 * 191) >> 8) as u8 + if heat > 0 { 1 } else { 0 };
+
+        // Ramp up the current third of the heat scale
+        let ramp = (t192 & 0x3F) << 2;
+
+        if t192 & 0x80 != 0 {
+            (255, 255, ramp)
+        } else if t192 & 0x40 != 0 {
+            (255, ramp, 0)
+        } else {
+            (ramp, 0, 0)
         }
     }
 
+    /// Get the next random number of the random effects below a limit, 0 if the limit is 0.
+    /// # Arguments
+    ///
+    /// * `limit` - Exclusive upper bound.
+    fn random_below(&mut self, limit: u8) -> u8 {
+        ((self.random.next_u8() as u16 * limit as u16) >> 8) as u8
+    }
+
     /// Clear the pixel buffer.
     pub fn clear(&mut self) {
         for i in 0..self.pixels.len() {
-            self.pixels[i][0] = 0 as u8; // R
-            self.pixels[i][1] = 0 as u8; // G
-            self.pixels[i][2] = 0 as u8; // B
+            self.pixels[i][0] = 0; // R
+            self.pixels[i][1] = 0; // G
+            self.pixels[i][2] = 0; // B
+        }
+    }
+
+    /// Get the number of pixels.
+    pub fn len(&self) -> usize {
+        self.pixels.len()
+    }
+
+    /// Get whether there are no pixels, never the case: see `is_blank` for a buffer with every pixel off.
+    pub fn is_empty(&self) -> bool {
+        self.pixels.is_empty()
+    }
+
+    /// Get whether every pixel of the buffer is black, whatever its brightness.
+    pub fn is_blank(&self) -> bool {
+        self.pixels.iter().all(|pixel| pixel[0..3] == [0, 0, 0])
+    }
+
+    /// Set the minimum period of the clock output when written with the GPIO pins, for marginal wiring
+    /// where a fast Pi toggles it faster than the LEDs sample it reliably. Each of the high and low levels
+    /// is held for at least half the period with a busy-wait, as `thread::sleep` is too coarse.
+    /// # Arguments
+    ///
+    /// * `period` - Minimum period, zero for as fast as possible.
+    pub fn set_min_clock_period(&mut self, period: Duration) {
+        self.min_clock_period = period;
+    }
+
+    /// Get the minimum period of the clock output when written with the GPIO pins.
+    pub fn min_clock_period(&self) -> Duration {
+        self.min_clock_period
+    }
+
+    /// Pulse the clock output once, holding each level for at least half the minimum period.
+    /// # Arguments
+    ///
+    /// * `output_clk` - Clock output.
+    /// * `clock` - Clock timing the levels.
+    /// * `min_period` - Minimum period of the pulse.
+    fn pulse(output_clk: &mut dyn DigitalOutput, clock: &dyn Clock, min_period: Duration) {
+        output_clk.set_level(true);
+        APA102::busy_wait(clock, min_period / 2);
+        output_clk.set_level(false);
+        APA102::busy_wait(clock, min_period / 2);
+    }
+
+    /// Spin until a duration elapsed, with a sub-microsecond resolution.
+    /// # Arguments
+    ///
+    /// * `clock` - Clock timing the wait.
+    /// * `duration` - Duration to wait, returning at once if zero.
+    fn busy_wait(clock: &dyn Clock, duration: Duration) {
+        if duration.is_zero() {
+            return;
+        }
+
+        let start = clock.now();
+        while clock.now().duration_since(start) < duration {
+            std::hint::spin_loop();
         }
     }
 
@@ -131,15 +737,8 @@ impl APA102 {
 
             // Scan from most significative to least
             for i in 0..8 {
-                if APA102::get_bit_at(byte, 7 - i) {
-                    output_dat.write(Level::High);
-                } else {
-                    output_dat.write(Level::Low);
-                }
-                output_clk.write(Level::High);
-                thread::sleep(Duration::from_millis(SLEEP_TIME));
-                output_clk.write(Level::Low);
-                thread::sleep(Duration::from_millis(SLEEP_TIME));
+                output_dat.set_level(APA102::get_bit_at(byte, 7 - i));
+                APA102::pulse(output_clk, self.clock.as_ref(), self.min_clock_period);
             }
         }
     }
@@ -151,13 +750,10 @@ impl APA102 {
             let output_dat = self.pin_dat.as_deref_mut().unwrap();
             let output_clk = self.pin_clk.as_deref_mut().unwrap();
 
-            output_dat.write(Level::Low);
+            output_dat.set_level(false);
 
             for _x in 0..36 {
-                output_clk.write(Level::High);
-                thread::sleep(Duration::from_millis(SLEEP_TIME));
-                output_clk.write(Level::Low);
-                thread::sleep(Duration::from_millis(SLEEP_TIME));
+                APA102::pulse(output_clk, self.clock.as_ref(), self.min_clock_period);
             }
         }
     }
@@ -169,76 +765,649 @@ impl APA102 {
             let output_dat = self.pin_dat.as_deref_mut().unwrap();
             let output_clk = self.pin_clk.as_deref_mut().unwrap();
 
-            output_dat.write(Level::Low);
+            output_dat.set_level(false);
 
             for _x in 0..32 {
-                output_clk.write(Level::High);
-                thread::sleep(Duration::from_millis(SLEEP_TIME));
-                output_clk.write(Level::Low);
-                thread::sleep(Duration::from_millis(SLEEP_TIME));
+                APA102::pulse(output_clk, self.clock.as_ref(), self.min_clock_period);
             }
         }
     }
 
-    /// Output the buffer.
+    /// Output the buffer, resolving the palette indices first if in paletted mode.
     pub fn show(&mut self) -> Result <(), Error>{
+        self.apply_palette();
+        let frame = self.frame(self.global_brightness, false);
+        self.write_frame(frame)
+    }
+
+    /// Enable the watchdog: `watchdog_tick` blanks the strip when no frame was written by `show` or `present`
+    /// for the timeout, so a hung render loop doesn't leave the LEDs stuck on. The timeout starts now.
+    /// # Arguments
+    ///
+    /// * `timeout` - Duration without a frame written after which the strip is blanked.
+    pub fn set_watchdog(&mut self, timeout: Duration) {
+        self.watchdog_timeout = Some(timeout);
+        self.last_show = Some(self.clock.now());
+    }
+
+    /// Disable the watchdog.
+    pub fn clear_watchdog(&mut self) {
+        self.watchdog_timeout = None;
+    }
+
+    /// Blank the strip if the watchdog is enabled and no frame was written for its timeout,
+    /// returning whether the strip was blanked. To be called regularly, for example from the thread
+    /// started by `APA102Handle::spawn_watchdog`, or cooperatively from a loop not rendering the frames.
+    pub fn watchdog_tick(&mut self) -> Result <bool, Error> {
+        let timeout = match self.watchdog_timeout {
+            Some(timeout) => timeout,
+            None => return Ok(false),
+        };
+
+        let now = self.clock.now();
+        if self.last_show.is_some_and(|last_show| now - last_show < timeout) {
+            return Ok(false);
+        }
+
+        self.tracer.trace(|| format!("APA102 watchdog: no frame for {:?}, blanking", timeout));
+        self.clear();
+        self.show()?;
+
+        Ok(true)
+    }
+
+    /// Output the buffer from an async task.
+    /// Writing a frame of 7 pixels takes less time than handing it to a blocking thread, so the frame
+    /// is written before yielding to the runtime: dropping the future never leaves a partial frame.
+    #[cfg(feature = "async")]
+    pub async fn show_async(&mut self) -> Result <(), Error>{
+        self.show()?;
+        tokio::task::yield_now().await;
+
+        Ok(())
+    }
+
+    /// Output the buffer with a temporary global brightness and optional gamma correction,
+    /// without changing the buffer nor the global brightness.
+    /// Suited to set the colors once then vary the brightness on each frame.
+    /// # Arguments
+    ///
+    /// * `global_brightness` - Global brightness for this frame: 0.0 to 1.0.
+    /// * `gamma` - true to correct the colors for the perceived brightness.
+    pub fn present(&mut self, global_brightness: f32, gamma: bool) -> Result <(), Error>{
+        APA102::check_brightness(global_brightness)?;
+
+        let frame = self.frame(global_brightness, gamma);
+        self.write_frame(frame)
+    }
+
+    /// Writes a frame.
+    /// # Arguments
+    ///
+    /// * `frame` - Frame as sent on the wire.
+    fn write_frame(&mut self, frame: [[u8; 4]; NUM_PIXELS]) -> Result <(), Error>{
 
         // Initialize if not done yet
         if !self.is_setup {
-            let _result = self.setup();
+            self.setup()?;
+        }
+
+        self.tracer.trace(|| format!("APA102 show: {} pixels", frame.len()));
+        self.last_show = Some(self.clock.now());
+
+        #[cfg(feature = "hardware")]
+        if let (false, Some(_)) = (self.simulation, self.spi_config) {
+            let spi = self.spi.as_mut().unwrap();
+            spi.write(&APA102::spi_frame(&frame))?;
+
+            self.last_frame = Some(frame);
+            return Ok(());
         }
 
         if !self.simulation {
             let output_cs = self.pin_cs.as_deref_mut().unwrap();
-            output_cs.write(Level::Low);
+            output_cs.set_level(false);
 
             self.sof();
 
-            for i in 0..self.pixels.len() {
-                self.write_byte(0b11100000 | self.pixels[i][3]); // brightness
-                self.write_byte(self.pixels[i][2]); // b
-                self.write_byte(self.pixels[i][1]); // g
-                self.write_byte(self.pixels[i][0]); // r
+            for pixel in frame.iter() {
+                self.write_byte(0b11100000 | pixel[0]); // brightness
+                self.write_byte(pixel[1]); // b
+                self.write_byte(pixel[2]); // g
+                self.write_byte(pixel[3]); // r
             }
 
             self.eof();
 
             let output_cs = self.pin_cs.as_deref_mut().unwrap();
-            output_cs.write(Level::High);
+            output_cs.set_level(true);
+        } else {
+            self.recorder.record(Component::Pixels, "show", Payload::Frame(frame.to_vec()));
         }
 
+        self.last_frame = Some(frame);
+
         Ok(())
     }
 
-    /// Set the RGB value and optionally brightness of all pixels.
+    /// Encodes a frame for hardware SPI: the start frame, each pixel then the end frame.
     /// # Arguments
     ///
-    /// * `r` - Amount of red: 0 to 255
-    /// * `g` - Amount of green: 0 to 255
-    /// * `b` - Amount of blue: 0 to 255
-    /// * `brightness` - Brightness: 0.0 to 1.0
-    pub fn set_all(&mut self, r : u8, g: u8, b: u8, brightness: f32) {
-        for i in 0..self.pixels.len() {
-            self.set_pixel(i, r, g, b, brightness);
+    /// * `frame` - Frame as sent on the wire.
+    #[cfg(feature = "hardware")]
+    fn spi_frame(frame: &[[u8; 4]; NUM_PIXELS]) -> Vec<u8> {
+
+        // 32 bits of start frame, 36 bits of end frame as when written with the GPIO pins
+        let mut bytes = vec![0u8; 4];
+        for pixel in frame.iter() {
+            bytes.push(0b1110_0000 | pixel[0]);
+            bytes.extend_from_slice(&pixel[1..4]);
         }
+        bytes.extend_from_slice(&[0u8; 5]);
+
+        bytes
     }
 
-    /// Set the RGB value, and optionally brightness, of a single pixel.
+    /// Get the last frame written by `show()`, as sent on the wire for each pixel: brightness, blue, green, red.
+    pub fn last_frame(&self) -> Option<&[[u8; 4]; NUM_PIXELS]> {
+        self.last_frame.as_ref()
+    }
+
+    /// Enable temporal dithering: when the global brightness scales a pixel to a fractional level,
+    /// successive `show()` calls alternate between the levels around it so their average matches it.
+    /// Smooths slow fades at low brightness.
     /// # Arguments
     ///
-    /// * `x` - The horizontal position of the pixel: 0 to 7
-    /// * `r` - Amount of red: 0 to 255
-    /// * `g` - Amount of green: 0 to 255
-    /// * `b` - Amount of blue: 0 to 255
-    /// * `brightness` - Brightness: 0.0 to 1.0
-    pub fn set_pixel(&mut self, x: usize, r : u8, g: u8, b: u8, brightness: f32) {
-        assert!(brightness >= 0.0);
-        assert!(brightness <= 1.0);
-        
-        self.pixels[x][0] = r as u8; // R
-        self.pixels[x][1] = g as u8; // G
-        self.pixels[x][2] = b as u8; // B
-        self.pixels[x][3] = (31.0 * brightness.round()) as u8; // Brightness
+    /// * `dithering` - true to enable dithering.
+    pub fn set_dithering(&mut self, dithering: bool) {
+        self.dithering = dithering;
+        self.dither_error = [0.0; NUM_PIXELS];
+    }
+
+    /// Computes the frame to write, applying the global brightness, the gamma correction and the power limit.
+    /// Advances the dithering.
+    /// # Arguments
+    ///
+    /// * `global_brightness` - Global brightness: 0.0 to 1.0.
+    /// * `gamma` - true to correct the colors.
+    fn frame(&mut self, global_brightness: f32, gamma: bool) -> [[u8; 4]; NUM_PIXELS] {
+        let mut frame = [[0; 4]; NUM_PIXELS];
+
+        for (i, pixel) in self.pixels.iter().enumerate() {
+            let ideal = pixel[3] as f32 * global_brightness;
+
+            let brightness = if self.dithering {
+
+                // Carry the part lost by the quantization to the next frame
+                let brightness = (ideal + self.dither_error[i]).floor().clamp(0.0, 31.0);
+                self.dither_error[i] += ideal - brightness;
+                brightness as u8
+            } else {
+                ideal.round() as u8
+            };
+
+            frame[i] = if gamma {
+                [brightness, APA102::gamma_correct(pixel[2]), APA102::gamma_correct(pixel[1]), APA102::gamma_correct(pixel[0])]
+            } else {
+                [brightness, pixel[2], pixel[1], pixel[0]]
+            };
+        }
+
+        if let Some(limit) = self.power_limit {
+            let current = APA102::frame_current(&frame);
+
+            if current > limit as f32 {
+                let scale = limit as f32 / current;
+                for pixel in frame.iter_mut() {
+                    pixel[0] = (pixel[0] as f32 * scale).floor() as u8;
+                }
+            }
+        }
+
+        frame
+    }
+
+    /// Corrects a color channel for the perceived brightness.
+    /// # Arguments
+    ///
+    /// * `value` - Amount of the color: 0 to 255.
+    pub fn gamma_correct(value: u8) -> u8 {
+        ((value as f32 / 255.0).powf(GAMMA) * 255.0).round() as u8
+    }
+
+    /// Estimates the current drawn by a frame, in milliamps.
+    /// # Arguments
+    ///
+    /// * `frame` - Frame as sent on the wire.
+    fn frame_current(frame: &[[u8; 4]; NUM_PIXELS]) -> f32 {
+        frame.iter().map(|pixel| {
+            let channels = pixel[1] as f32 + pixel[2] as f32 + pixel[3] as f32;
+            channels / 255.0 * pixel[0] as f32 / 31.0 * MILLIAMPS_PER_CHANNEL
+        }).sum()
+    }
+
+    /// Limit the current drawn by the pixels. When a frame would draw more, `show()` scales down
+    /// the brightness of every pixel proportionally. The buffer is not changed.
+    /// # Arguments
+    ///
+    /// * `max_milliamps` - Maximum current in milliamps.
+    pub fn set_power_limit(&mut self, max_milliamps: u32) {
+        self.power_limit = Some(max_milliamps);
+    }
+
+    /// Remove the power limit.
+    pub fn remove_power_limit(&mut self) {
+        self.power_limit = None;
+    }
+
+    /// Set the RGB value and optionally brightness of all pixels.
+    /// # Arguments
+    ///
+    /// * `r` - Amount of red: 0 to 255
+    /// * `g` - Amount of green: 0 to 255
+    /// * `b` - Amount of blue: 0 to 255
+    /// * `brightness` - Brightness: 0.0 to 1.0
+    pub fn set_all(&mut self, r : u8, g: u8, b: u8, brightness: f32) -> Result <(), Error> {
+        APA102::check_brightness(brightness)?;
+        for i in 0..self.pixels.len() {
+            self.write_pixel(i, r, g, b, brightness);
+        }
+
+        Ok(())
+    }
+
+    /// Set the RGB value, and optionally brightness, of a single pixel.
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal position of the pixel: 0 to 7
+    /// * `r` - Amount of red: 0 to 255
+    /// * `g` - Amount of green: 0 to 255
+    /// * `b` - Amount of blue: 0 to 255
+    /// * `brightness` - Brightness: 0.0 to 1.0
+    pub fn set_pixel(&mut self, x: usize, r : u8, g: u8, b: u8, brightness: f32) -> Result <(), Error> {
+        self.check_index(x)?;
+        APA102::check_brightness(brightness)?;
+        self.write_pixel(x, r, g, b, brightness);
+
+        Ok(())
+    }
+
+    /// Writes the RGB value and brightness of a single pixel in the buffer.
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal position of the pixel, already validated: 0 to 6
+    /// * `r` - Amount of red: 0 to 255
+    /// * `g` - Amount of green: 0 to 255
+    /// * `b` - Amount of blue: 0 to 255
+    /// * `brightness` - Brightness, already validated: 0.0 to 1.0
+    fn write_pixel(&mut self, x: usize, r : u8, g: u8, b: u8, brightness: f32) {
+        debug_assert!(x < self.pixels.len());
+        debug_assert!((0.0..=1.0).contains(&brightness));
+
+        self.pixels[x][0] = r; // R
+        self.pixels[x][1] = g; // G
+        self.pixels[x][2] = b; // B
+        self.pixels[x][3] = (31.0 * brightness).round() as u8; // Brightness
+    }
+
+    /// Set the RGB value and brightness of a single pixel at a signed index wrapped into range,
+    /// so effects can compute positions such as `head - 1` without modulo: -1 is the last pixel and `len()` the first one.
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal position of the pixel, any value.
+    /// * `r` - Amount of red: 0 to 255
+    /// * `g` - Amount of green: 0 to 255
+    /// * `b` - Amount of blue: 0 to 255
+    /// * `brightness` - Brightness: 0.0 to 1.0
+    pub fn set_pixel_wrapping(&mut self, x: isize, r : u8, g: u8, b: u8, brightness: f32) -> Result <(), Error> {
+        self.set_pixel(self.wrap_index(x), r, g, b, brightness)
+    }
+
+    /// Wraps a signed pixel index into range: -1 gives the last pixel and `len()` the first one.
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal position of the pixel, any value.
+    pub fn wrap_index(&self, x: isize) -> usize {
+        x.rem_euclid(self.pixels.len() as isize) as usize
+    }
+
+    /// Clamps a signed pixel index into range: negative values give the first pixel and values past the end the last one.
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal position of the pixel, any value.
+    pub fn clamp_index(&self, x: isize) -> usize {
+        x.clamp(0, self.pixels.len() as isize - 1) as usize
+    }
+
+    /// Set the color and brightness of a single pixel, given as a tuple, an array, a 0xRRGGBB number or a `Color`.
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal position of the pixel: 0 to 6
+    /// * `color` - Color of the pixel.
+    /// * `brightness` - Brightness: 0.0 to 1.0
+    pub fn set_pixel_color<C: Into<Color>>(&mut self, x: usize, color: C, brightness: f32) -> Result <(), Error> {
+        let color = color.into();
+        self.set_pixel(x, color.r, color.g, color.b, brightness)
+    }
+
+    /// Set the color and brightness of all pixels, given as a tuple, an array, a 0xRRGGBB number or a `Color`.
+    /// # Arguments
+    ///
+    /// * `color` - Color of the pixels.
+    /// * `brightness` - Brightness: 0.0 to 1.0
+    pub fn set_all_color<C: Into<Color>>(&mut self, color: C, brightness: f32) -> Result <(), Error> {
+        let color = color.into();
+        self.set_all(color.r, color.g, color.b, brightness)
+    }
+
+    /// Set the colors of the paletted mode. Rotating the palette with `rotate_palette` animates the pixels
+    /// without touching their indices.
+    /// # Arguments
+    ///
+    /// * `palette` - Colors: red, green, blue.
+    pub fn set_palette(&mut self, palette: Vec<(u8, u8, u8)>) {
+        self.palette = palette;
+    }
+
+    /// Get the colors of the paletted mode.
+    pub fn palette(&self) -> &[(u8, u8, u8)] {
+        &self.palette
+    }
+
+    /// Rotate the palette to the left, each index then resolving to the color that followed it.
+    /// # Arguments
+    ///
+    /// * `steps` - Number of colors to rotate by.
+    pub fn rotate_palette(&mut self, steps: usize) {
+        if !self.palette.is_empty() {
+            let len = self.palette.len();
+            self.palette.rotate_left(steps % len);
+        }
+    }
+
+    /// Set the palette index of a single pixel, switching to paletted mode: `show()` then resolves the index of
+    /// every pixel to its color, keeping the brightness of the pixels. An index outside the palette resolves to
+    /// the first color.
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal position of the pixel: 0 to 6
+    /// * `palette_index` - Index in the palette.
+    pub fn set_pixel_index(&mut self, x: usize, palette_index: usize) -> Result <(), Error> {
+        self.check_index(x)?;
+        self.palette_indices.get_or_insert([0; NUM_PIXELS])[x] = palette_index;
+
+        Ok(())
+    }
+
+    /// Get the palette index of a single pixel, None if not in paletted mode.
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal position of the pixel: 0 to 6
+    pub fn pixel_index(&self, x: usize) -> Option<usize> {
+        self.palette_indices.map(|indices| indices[x])
+    }
+
+    /// Resolve the palette index of every pixel to its color in the buffer, keeping their brightness.
+    /// Done by `show()`, nothing is changed when not in paletted mode. Pixels are turned off when the palette is empty.
+    pub fn apply_palette(&mut self) {
+        let Some(indices) = self.palette_indices else {
+            return;
+        };
+        for (pixel, index) in self.pixels.iter_mut().zip(indices) {
+            let (r, g, b) = self.palette.get(index).or(self.palette.first()).copied().unwrap_or((0, 0, 0));
+            pixel[0] = r;
+            pixel[1] = g;
+            pixel[2] = b;
+        }
+    }
+
+    /// Leave the paletted mode: `show()` outputs the buffer as set, the palette being kept.
+    pub fn clear_palette_indices(&mut self) {
+        self.palette_indices = None;
+    }
+
+    /// Get the RGB value and the raw brightness of a single pixel, from 0 to 31, as stored in the buffer
+    /// and sent on the wire, mirroring `set_pixel`.
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal position of the pixel: 0 to 6
+    pub fn get_pixel(&self, x: usize) -> Result <(u8, u8, u8, u8), Error> {
+        self.check_index(x)?;
+
+        let pixel = self.pixels[x];
+        Ok((pixel[0], pixel[1], pixel[2], pixel[3]))
+    }
+
+    /// Get the RGB value of a single pixel with its brightness scaled from 0.0 to 1.0, as given to `set_pixel`.
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal position of the pixel: 0 to 6
+    pub fn get_pixel_normalized(&self, x: usize) -> Result <(u8, u8, u8, f32), Error> {
+        let (r, g, b, brightness) = self.get_pixel(x)?;
+        Ok((r, g, b, brightness as f32 / 31.0))
+    }
+
+    /// Set alternating colors: even pixels to the first color and odd pixels to the second one.
+    /// Increasing the phase on each frame swaps the colors, animating the pattern.
+    /// # Arguments
+    ///
+    /// * `color_a` - Color of the even pixels at an even phase: red, green, blue.
+    /// * `color_b` - Color of the odd pixels at an even phase: red, green, blue.
+    /// * `brightness` - Brightness: 0.0 to 1.0
+    /// * `phase` - Animation phase: colors are swapped when odd.
+    pub fn fill_alternating(&mut self, color_a: (u8, u8, u8), color_b: (u8, u8, u8), brightness: f32, phase: usize) -> Result <(), Error> {
+        APA102::check_brightness(brightness)?;
+        for x in 0..self.pixels.len() {
+            let (r, g, b) = if (x + phase) % 2 == 1 { color_b } else { color_a };
+            self.write_pixel(x, r, g, b, brightness);
+        }
+
+        Ok(())
+    }
+
+    /// Get the index of the center pixel: the middle one of the 7 pixels.
+    pub fn center(&self) -> usize {
+        self.pixels.len() / 2
+    }
+
+    /// Mirror the first half of the pixels onto the second half, so the strip is symmetric around the center pixel,
+    /// which is kept. The colors and brightness are copied: pixel 0 onto the last pixel, 1 onto the one before, and so on.
+    pub fn mirror_halves(&mut self) {
+        let len = self.pixels.len();
+        for x in 0..len / 2 {
+            self.pixels[len - 1 - x] = self.pixels[x];
+        }
+    }
+
+    /// Fill the pixels from the center outward, for example for a pulse or an equalizer level.
+    /// The center pixel is the first ring, then each ring adds the pixel on both sides: the 7 pixels make 4 rings.
+    /// The pixels outside the lit rings are turned off.
+    /// # Arguments
+    ///
+    /// * `color` - Color of the lit pixels.
+    /// * `brightness` - Brightness: 0.0 to 1.0
+    /// * `progress` - Portion of the rings lit, rounded to the nearest ring: 0.0 for none to 1.0 for every pixel.
+    pub fn center_out_fill<C: Into<Color>>(&mut self, color: C, brightness: f32, progress: f32) -> Result <(), Error> {
+        APA102::check_brightness(brightness)?;
+        if !(0.0..=1.0).contains(&progress) {
+            return Err(Error::InvalidArgument(invalid_input("progress", progress, "0.0 to 1.0")));
+        }
+
+        let color = color.into();
+        let center = self.center();
+        let rings = self.pixels.len().div_ceil(2);
+        let lit = (progress * rings as f32).round() as usize;
+
+        for x in 0..self.pixels.len() {
+            if x.abs_diff(center) < lit {
+                self.write_pixel(x, color.r, color.g, color.b, brightness);
+            } else {
+                self.write_pixel(x, 0, 0, 0, brightness);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Show a percentage as a bar graph, for example a CPU load or a volume: the pixels from the first one
+    /// are lit in proportion, rounded to the nearest pixel, and the others are turned off.
+    /// # Arguments
+    ///
+    /// * `percentage` - Value of the graph, clamped to 0.0 to 100.0.
+    /// * `palette` - Colors of the lit pixels.
+    /// * `brightness` - Brightness: 0.0 to 1.0
+    pub fn graph(&mut self, percentage: f32, palette: GraphPalette, brightness: f32) -> Result <(), Error> {
+        APA102::check_brightness(brightness)?;
+        if percentage.is_nan() {
+            return Err(Error::InvalidArgument(invalid_input("percentage", percentage, "a number")));
+        }
+
+        let len = self.pixels.len();
+        let percentage = percentage.clamp(0.0, 100.0);
+        let lit = (percentage / 100.0 * len as f32).round() as usize;
+
+        for x in 0..len {
+            let color = if x < lit { palette.color(percentage, x, len) } else { Color::default() };
+            self.write_pixel(x, color.r, color.g, color.b, brightness);
+        }
+
+        Ok(())
+    }
+
+    /// Set the RGB value and brightness of all pixels.
+    /// # Arguments
+    ///
+    /// * `r` - Amount of red: 0 to 255
+    /// * `g` - Amount of green: 0 to 255
+    /// * `b` - Amount of blue: 0 to 255
+    /// * `brightness` - Brightness: 0.0 to 1.0
+    #[deprecated(note = "set_all returns the error")]
+    pub fn try_set_all(&mut self, r : u8, g: u8, b: u8, brightness: f32) -> Result <(), Error> {
+        self.set_all(r, g, b, brightness)
+    }
+
+    /// Set the RGB value and brightness of a single pixel.
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal position of the pixel: 0 to 6
+    /// * `r` - Amount of red: 0 to 255
+    /// * `g` - Amount of green: 0 to 255
+    /// * `b` - Amount of blue: 0 to 255
+    /// * `brightness` - Brightness: 0.0 to 1.0
+    #[deprecated(note = "set_pixel returns the error")]
+    pub fn try_set_pixel(&mut self, x: usize, r : u8, g: u8, b: u8, brightness: f32) -> Result <(), Error> {
+        self.set_pixel(x, r, g, b, brightness)
+    }
+
+    /// Set the RGB value and brightness of a single pixel, clamping the brightness to 0.0 to 1.0.
+    /// NaN is treated as 0.0.
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal position of the pixel: 0 to 6
+    /// * `r` - Amount of red: 0 to 255
+    /// * `g` - Amount of green: 0 to 255
+    /// * `b` - Amount of blue: 0 to 255
+    /// * `brightness` - Brightness, any value.
+    pub fn set_pixel_clamped(&mut self, x: usize, r : u8, g: u8, b: u8, brightness: f32) -> Result <(), Error> {
+        self.set_pixel(x, r, g, b, APA102::clamp_brightness(brightness))
+    }
+
+    /// Clamps a brightness value to 0.0 to 1.0, NaN giving 0.0.
+    /// # Arguments
+    ///
+    /// * `brightness` - Brightness, any value.
+    pub fn clamp_brightness(brightness: f32) -> f32 {
+        if brightness.is_nan() {
+            0.0
+        } else {
+            brightness.clamp(0.0, 1.0)
+        }
+    }
+
+    /// Validates a brightness value.
+    /// # Arguments
+    ///
+    /// * `brightness` - Brightness: 0.0 to 1.0
+    fn check_brightness(brightness: f32) -> Result <(), Error> {
+        if !(0.0..=1.0).contains(&brightness) {
+            return Err(Error::InvalidArgument(invalid_input("brightness", brightness, "0.0 to 1.0")));
+        }
+
+        Ok(())
+    }
+
+    /// Validates a pixel position.
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal position of the pixel: 0 to 6
+    fn check_index(&self, x: usize) -> Result <(), Error> {
+        if x >= self.pixels.len() {
+            return Err(Error::InvalidArgument(invalid_input("pixel", x, &format!("0 to {}", self.pixels.len() - 1))));
+        }
+
+        Ok(())
+    }
+
+    /// Copy a source strip onto the pixels at a fractional offset.
+    /// Each pixel is interpolated between the two nearest source pixels, so scrolling
+    /// the offset by small steps moves the image smoothly. Pixels outside the source fade to black.
+    /// # Arguments
+    ///
+    /// * `source` - Source colors as (r, g, b) tuples.
+    /// * `offset` - Position in the source of the first pixel, can be fractional or negative.
+    /// * `brightness` - Brightness: 0.0 to 1.0
+    pub fn blit_subpixel(&mut self, source: &[(u8, u8, u8)], offset: f32, brightness: f32) -> Result <(), Error> {
+        APA102::check_brightness(brightness)?;
+
+        let base = offset.floor();
+        let frac = offset - base;
+
+        for x in 0..self.pixels.len() {
+            let index = base as isize + x as isize;
+            let left = APA102::source_color(source, index);
+            let right = APA102::source_color(source, index + 1);
+
+            let r = APA102::lerp(left.0, right.0, frac);
+            let g = APA102::lerp(left.1, right.1, frac);
+            let b = APA102::lerp(left.2, right.2, frac);
+            self.write_pixel(x, r, g, b, brightness);
+        }
+
+        Ok(())
+    }
+
+    /// Copy a source strip onto the pixels at a fractional offset.
+    /// # Arguments
+    ///
+    /// * `source` - Source colors as (r, g, b) tuples.
+    /// * `offset` - Position in the source of the first pixel, can be fractional or negative.
+    /// * `brightness` - Brightness: 0.0 to 1.0
+    #[deprecated(note = "blit_subpixel returns the error")]
+    pub fn try_blit_subpixel(&mut self, source: &[(u8, u8, u8)], offset: f32, brightness: f32) -> Result <(), Error> {
+        self.blit_subpixel(source, offset, brightness)
+    }
+
+    /// Get a source color, black if out of bounds.
+    /// # Arguments
+    ///
+    /// * `source` - Source colors.
+    /// * `index` - Index in the source.
+    fn source_color(source: &[(u8, u8, u8)], index: isize) -> (u8, u8, u8) {
+        if index < 0 || index as usize >= source.len() {
+            (0, 0, 0)
+        } else {
+            source[index as usize]
+        }
+    }
+
+    /// Linear interpolation between two channel values.
+    /// # Arguments
+    ///
+    /// * `from` - Value at ratio 0.0.
+    /// * `to` - Value at ratio 1.0.
+    /// * `ratio` - Ratio between 0.0 and 1.0.
+    fn lerp(from: u8, to: u8, ratio: f32) -> u8 {
+        (from as f32 + (to as f32 - from as f32) * ratio).round() as u8
     }
 
     /// gets the bit at position `n`. Bits are numbered from 0 (least significant) to 31 (most significant).
@@ -247,151 +1416,907 @@ impl APA102 {
     /// * `byte` - The byte to get the bit from.
     /// * `n` - Bit position.
     fn get_bit_at(byte: u8, n: u8) -> bool {
-        assert!(n < 8);
+        debug_assert!(n < 8);
 
         byte & (1 << n) != 0
     }
+
+    /// Move the driver behind a mutex so it can be shared between threads.
+    pub fn into_shared(self) -> APA102Handle {
+        APA102Handle {
+            inner: Arc::new(Mutex::new(self)),
+        }
+    }
+}
+
+/// Colors of the bar graph drawn by `APA102::graph`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphPalette {
+
+    /// Every lit pixel in the same color.
+    Solid(Color),
+
+    /// Every lit pixel in green, amber from `GRAPH_AMBER_THRESHOLD` or red from `GRAPH_RED_THRESHOLD`,
+    /// so the color tells the level at a glance.
+    Thresholds,
+
+    /// Each pixel in its own hue, from red on the first pixel along the color wheel.
+    Rainbow,
+}
+
+impl GraphPalette {
+
+    /// Get the color of a lit pixel of the graph.
+    /// # Arguments
+    ///
+    /// * `percentage` - Value of the graph: 0.0 to 100.0
+    /// * `x` - Position of the pixel.
+    /// * `len` - Number of pixels of the graph.
+    pub fn color(self, percentage: f32, x: usize, len: usize) -> Color {
+        match self {
+            GraphPalette::Solid(color) => color,
+            GraphPalette::Thresholds => GraphPalette::threshold_color(percentage),
+            GraphPalette::Rainbow => hsv_to_rgb(360.0 * x as f32 / len.max(1) as f32, 1.0, 1.0).into(),
+        }
+    }
+
+    /// Get the color of the threshold palette for a percentage, clamped to 0.0 to 100.0:
+    /// green, amber from `GRAPH_AMBER_THRESHOLD` or red from `GRAPH_RED_THRESHOLD`.
+    /// # Arguments
+    ///
+    /// * `percentage` - Value of the graph.
+    pub fn threshold_color(percentage: f32) -> Color {
+        let percentage = percentage.clamp(0.0, 100.0);
+        if percentage >= GRAPH_RED_THRESHOLD {
+            colors::RED.into()
+        } else if percentage >= GRAPH_AMBER_THRESHOLD {
+            colors::AMBER.into()
+        } else {
+            colors::GREEN.into()
+        }
+    }
+}
+
+/// Color of a pixel.
+/// Converts from a `(r, g, b)` tuple, a `[r, g, b]` array or a 0xRRGGBB number,
+/// and parses from a name such as "orange" or from a "#RRGGBB" hex string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Color {
+
+    /// Amount of red: 0 to 255.
+    pub r: u8,
+
+    /// Amount of green: 0 to 255.
+    pub g: u8,
+
+    /// Amount of blue: 0 to 255.
+    pub b: u8,
+}
+
+/// Colors by name. The primary and secondary colors are at full intensity, unlike their CSS equivalents.
+const NAMED_COLORS: [(&str, Color); 11] = [
+    ("black", Color::new(0, 0, 0)),
+    ("white", Color::new(255, 255, 255)),
+    ("red", Color::new(255, 0, 0)),
+    ("green", Color::new(0, 255, 0)),
+    ("blue", Color::new(0, 0, 255)),
+    ("yellow", Color::new(255, 255, 0)),
+    ("cyan", Color::new(0, 255, 255)),
+    ("magenta", Color::new(255, 0, 255)),
+    ("orange", Color::new(255, 165, 0)),
+    ("purple", Color::new(128, 0, 128)),
+    ("pink", Color::new(255, 192, 203)),
+];
+
+impl Color {
+
+    /// Creates a color.
+    /// # Arguments
+    ///
+    /// * `r` - Amount of red: 0 to 255
+    /// * `g` - Amount of green: 0 to 255
+    /// * `b` - Amount of blue: 0 to 255
+    pub const fn new(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b }
+    }
+
+    /// Get a color by name, ignoring the case: black, white, red, green, blue, yellow, cyan, magenta, orange, purple or pink.
+    /// # Arguments
+    ///
+    /// * `name` - Name of the color.
+    pub fn named(name: &str) -> Option<Color> {
+        NAMED_COLORS.iter().find(|(known, _)| known.eq_ignore_ascii_case(name)).map(|&(_, color)| color)
+    }
+}
+
+impl From<(u8, u8, u8)> for Color {
+    fn from((r, g, b): (u8, u8, u8)) -> Color {
+        Color::new(r, g, b)
+    }
+}
+
+impl From<[u8; 3]> for Color {
+    fn from([r, g, b]: [u8; 3]) -> Color {
+        Color::new(r, g, b)
+    }
+}
+
+/// Converts a 0xRRGGBB number, ignoring the highest byte
+impl From<u32> for Color {
+    fn from(rgb: u32) -> Color {
+        Color::new((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+    }
+}
+
+impl From<Color> for (u8, u8, u8) {
+    fn from(color: Color) -> (u8, u8, u8) {
+        (color.r, color.g, color.b)
+    }
+}
+
+/// Parses a color name or a "#RRGGBB" hex string
+impl std::str::FromStr for Color {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Color, Error> {
+        let invalid = || Error::InvalidArgument(invalid_input("color", format!("{:?}", value), "a name or #RRGGBB"));
+
+        match value.strip_prefix('#') {
+            Some(hex) if hex.len() == 6 && hex.bytes().all(|c| c.is_ascii_hexdigit()) => u32::from_str_radix(hex, 16).map(Color::from).map_err(|_| invalid()),
+            Some(_) => Err(invalid()),
+            None => Color::named(value).ok_or_else(invalid),
+        }
+    }
+}
+
+/// Handle to an APA102 driver shared between threads, Send and Sync.
+/// Clones refer to the same driver. Each operation holds an internal lock, so a frame is never
+/// shown while another thread is halfway through updating the pixels in `with_driver`.
+#[derive(Debug, Clone)]
+pub struct APA102Handle {
+
+    /// Shared driver.
+    inner: Arc<Mutex<APA102>>,
+}
+
+impl APA102Handle {
+
+    /// Locks the driver. A poisoned lock is recovered as the driver state stays consistent between calls.
+    fn lock(&self) -> MutexGuard<'_, APA102> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Enables simulation mode: no interaction with the hardware is done.
+    pub fn enable_simulation(&self) {
+        self.lock().enable_simulation();
+    }
+
+    /// Get whether simulation mode is enabled.
+    pub fn is_simulation(&self) -> bool {
+        self.lock().is_simulation()
+    }
+
+    /// Set a hook receiving a message before each hardware operation of the shared driver.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Closure receiving each message.
+    pub fn set_trace<F: FnMut(&str) + Send + 'static>(&self, hook: F) {
+        self.lock().set_trace(hook);
+    }
+
+    /// Remove the trace hook of the shared driver.
+    pub fn clear_trace(&self) {
+        self.lock().clear_trace();
+    }
+
+    /// Run a closure with exclusive access to the driver.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - Closure receiving the driver.
+    pub fn with_driver<R, F: FnOnce(&mut APA102) -> R>(&self, f: F) -> R {
+        f(&mut self.lock())
+    }
+
+    /// Set the brightness of all the pixels, between 0.0 and 1.0.
+    ///
+    /// # Arguments
+    ///
+    /// * `brightness` - Brightness between 0.0 and 1.0.
+    pub fn set_brightness(&self, brightness : f32) -> Result <(), Error> {
+        self.lock().set_brightness(brightness)
+    }
+
+    /// Set the color of all the pixels.
+    ///
+    /// # Arguments
+    ///
+    /// * `r` - Amount of red: 0 to 255.
+    /// * `g` - Amount of green: 0 to 255.
+    /// * `b` - Amount of blue: 0 to 255.
+    /// * `brightness` - Brightness: 0.0 to 1.0.
+    pub fn set_all(&self, r : u8, g: u8, b: u8, brightness: f32) -> Result <(), Error> {
+        self.lock().set_all(r, g, b, brightness)
+    }
+
+    /// Set the color of a single pixel.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal position of the pixel: 0 to 6.
+    /// * `r` - Amount of red: 0 to 255.
+    /// * `g` - Amount of green: 0 to 255.
+    /// * `b` - Amount of blue: 0 to 255.
+    /// * `brightness` - Brightness: 0.0 to 1.0.
+    pub fn set_pixel(&self, x: usize, r : u8, g: u8, b: u8, brightness: f32) -> Result <(), Error> {
+        self.lock().set_pixel(x, r, g, b, brightness)
+    }
+
+    /// Get the color and raw brightness of a single pixel: 0 to 31.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal position of the pixel: 0 to 6.
+    pub fn get_pixel(&self, x: usize) -> Result <(u8, u8, u8, u8), Error> {
+        self.lock().get_pixel(x)
+    }
+
+    /// Get the color of a single pixel with its brightness from 0.0 to 1.0.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal position of the pixel: 0 to 6.
+    pub fn get_pixel_normalized(&self, x: usize) -> Result <(u8, u8, u8, f32), Error> {
+        self.lock().get_pixel_normalized(x)
+    }
+
+    /// Clear the pixel buffer.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Output the buffer.
+    pub fn show(&self) -> Result <(), Error> {
+        self.lock().show()
+    }
+
+    /// Enable the watchdog of the shared driver, see `APA102::set_watchdog`.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Duration without a frame written after which the strip is blanked.
+    pub fn set_watchdog(&self, timeout: Duration) {
+        self.lock().set_watchdog(timeout);
+    }
+
+    /// Blank the shared strip if no frame was written for the timeout of the watchdog, returning whether it was blanked.
+    pub fn watchdog_tick(&self) -> Result <bool, Error> {
+        self.lock().watchdog_tick()
+    }
+
+    /// Start a thread checking the watchdog at an interval, independent of the render loop so it still blanks
+    /// the strip when that loop hangs. The thread ends when every handle to the strip is dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Interval between two checks, shorter than the timeout of the watchdog.
+    pub fn spawn_watchdog(&self, interval: Duration) -> JoinHandle<()> {
+        let strip: Weak<Mutex<APA102>> = Arc::downgrade(&self.inner);
+
+        thread::spawn(move || {
+            while let Some(inner) = strip.upgrade() {
+                let _result = APA102Handle { inner }.watchdog_tick();
+                thread::sleep(interval);
+            }
+        })
+    }
 }
 
 /// Errors that can occur.
 #[derive(Debug)]
 pub enum Error {
 
-    /// Gpio error.
-    Gpio(rppal::gpio::Error),
+    /// Gpio error configuring one of the pins of the LEDs.
+    #[cfg(feature = "hardware")]
+    Gpio {
+
+        /// GPIO pin number using the BCM pin numbering.
+        pin: u8,
+
+        /// Error of the GPIO.
+        source: rppal::gpio::Error,
+    },
+
+    /// SPI error.
+    #[cfg(feature = "hardware")]
+    Spi(rppal::spi::Error),
+
+    /// One of the pins of the LEDs is already used by another driver of the process.
+    PinAlreadyInUse {
+
+        /// GPIO pin number using the BCM pin numbering.
+        pin: u8,
+
+        /// Driver using the pin, such as "light".
+        owner: &'static str,
+    },
+
+    /// Invalid argument.
+    InvalidArgument(String),
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "hardware")]
+            Error::Gpio { source, .. } => Some(source),
+            #[cfg(feature = "hardware")]
+            Error::Spi(err) => Some(err),
+            Error::PinAlreadyInUse { .. } => None,
+            Error::InvalidArgument(_) => None,
+        }
+    }
+}
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &*self {
-            Error::Gpio(err) => write!(f, "Gpio error: {}", &err),
+        match self {
+            #[cfg(feature = "hardware")]
+            Error::Gpio { pin, source } => write!(f, "failed to configure BCM {} for APA102: {}", pin, &source),
+            #[cfg(feature = "hardware")]
+            Error::Spi(err) => write!(f, "SPI error: {}", &err),
+            Error::PinAlreadyInUse { pin, owner } => write!(f, "BCM {} is already used by the {}", pin, owner),
+            Error::InvalidArgument(msg) => write!(f, "Invalid argument: {}", &msg),
         }
     }
 }
 
-/// Converts Gpio error
-impl From<rppal::gpio::Error> for Error {
-    fn from(err: rppal::gpio::Error) -> Error {
-        Error::Gpio(err)
+/// Converts SPI error
+#[cfg(feature = "hardware")]
+impl From<rppal::spi::Error> for Error {
+    fn from(err: rppal::spi::Error) -> Error {
+        Error::Spi(err)
+    }
+}
+
+/// Converts a conflict of pins
+impl From<PinInUse> for Error {
+    fn from(err: PinInUse) -> Error {
+        Error::PinAlreadyInUse { pin: err.pin, owner: err.owner }
+    }
+}
+
+
+/// Unit tests
+#[cfg(test)]
+#[allow(clippy::bool_comparison, clippy::unnecessary_cast, clippy::zero_prefixed_literal)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    /// Tests the setup of the light.
+    #[test]
+    fn test_apa102_setup() -> Result<(), Error> {
+        
+        let mut apa102 = APA102::new()?;
+        apa102.simulation = true;
+        
+        // Not setup
+        assert!(apa102.is_setup == false);
+
+        // Force setup
+        let _result = apa102.setup();
+
+        assert!(apa102.is_setup == true);
+
+        Ok(())
+    }
+
+    /// Tests the setup of the light.
+    #[test]
+    fn test_apa102_set_brightness() -> Result<(), Error> {
+        
+        let mut apa102 = APA102::new()?;
+        apa102.simulation = true;
+        let _result = apa102.setup();
+
+        apa102.set_brightness(0.0)?;
+        for i in 0..apa102.pixels.len() {
+            assert!(apa102.pixels[i][3] == 0);
+        }
+
+        apa102.set_brightness(1.0)?;
+        for i in 0..apa102.pixels.len() {
+            assert!(apa102.pixels[i][3] == 31);
+        }
+
+        Ok(())
+    }
+
+    /// Tests the intermediate brightness levels are rounded to the nearest of the 32 levels of the LEDs.
+    #[test]
+    fn test_apa102_intermediate_brightness() -> Result<(), Error> {
+        let mut apa102 = APA102::with_simulation()?;
+
+        for &(brightness, raw) in [(0.25, 8), (0.5, 16), (0.75, 23)].iter() {
+            apa102.set_brightness(brightness)?;
+            assert!(apa102.pixels.iter().all(|pixel| pixel[3] == raw), "brightness {}", brightness);
+
+            apa102.set_pixel(1, 255, 0, 0, brightness)?;
+            assert!(apa102.pixels[1][3] == raw, "pixel brightness {}", brightness);
+        }
+
+        Ok(())
+    }
+
+    /// Test clearing the buffer.
+    #[test]
+    fn test_apa102_clear() -> Result<(), Error> {
+        
+        let mut apa102 = APA102::new()?;
+        apa102.simulation = true;
+        let _result = apa102.setup();
+
+        let brightness : u8 = 31;
+
+        for i in 0..apa102.pixels.len() {
+            apa102.pixels[i][0] = 250 as u8; // R
+            apa102.pixels[i][1] = 250 as u8; // G
+            apa102.pixels[i][2] = 250 as u8; // B
+            apa102.pixels[i][3] = brightness;
+        }
+
+        apa102.clear();
+
+        // The RGB pixels are clear but the brightness is unchanged
+        for i in 0..apa102.pixels.len() {
+            assert!(apa102.pixels[i][0] == 0);
+            assert!(apa102.pixels[i][1] == 0);
+            assert!(apa102.pixels[i][2] == 0);
+            assert!(apa102.pixels[i][3] == 31);
+        }
+
+        Ok(())
+    }
+
+    /// Tests to set pixel colors.
+    #[test]
+    fn test_apa102_set_pixel() -> Result<(), Error> {
+        
+        let mut apa102 = APA102::new()?;
+        apa102.simulation = true;
+        let _result = apa102.setup();
+
+        apa102.set_pixel(0, 123, 234, 012, 1.0)?;
+        apa102.set_pixel(6, 12, 58, 123, 0.0)?;
+
+        assert!(apa102.pixels[0][0] == 123);
+        assert!(apa102.pixels[0][1] == 234);
+        assert!(apa102.pixels[0][2] == 12);
+        assert!(apa102.pixels[0][3] == 31);
+
+        assert!(apa102.pixels[6][0] == 12);
+        assert!(apa102.pixels[6][1] == 58);
+        assert!(apa102.pixels[6][2] == 123);
+        assert!(apa102.pixels[6][3] == 0);
+        Ok(())
+    }
+
+    /// Tests reading back the pixels with their raw brightness, for example to toggle one.
+    #[test]
+    fn test_apa102_get_pixel() -> Result<(), Error> {
+        let mut apa102 = APA102::with_simulation()?;
+
+        apa102.set_pixel(2, 10, 20, 30, 1.0)?;
+        assert!(apa102.get_pixel(2)? == (10, 20, 30, 31));
+        assert!(apa102.get_pixel_normalized(2)? == (10, 20, 30, 1.0));
+        assert!(apa102.get_pixel(6)? == (0, 0, 0, 0));
+
+        // Toggle the pixel off and on through the public API
+        let toggle = |apa102: &mut APA102, x: usize| -> Result<(), Error> {
+            let (r, g, b, _brightness) = apa102.get_pixel(x)?;
+            let (r, g, b) = if (r, g, b) == (0, 0, 0) { (255, 255, 255) } else { (0, 0, 0) };
+            apa102.set_pixel(x, r, g, b, 1.0)
+        };
+        toggle(&mut apa102, 2)?;
+        assert!(apa102.get_pixel(2)? == (0, 0, 0, 31));
+        toggle(&mut apa102, 2)?;
+        assert!(apa102.get_pixel(2)? == (255, 255, 255, 31));
+
+        // Out of range, with the error of `set_pixel`
+        let message = "Invalid argument: invalid pixel 7, expected 0 to 6";
+        assert!(apa102.get_pixel(NUM_PIXELS).unwrap_err().to_string() == message);
+        assert!(apa102.get_pixel_normalized(NUM_PIXELS).unwrap_err().to_string() == message);
+        assert!(apa102.set_pixel(NUM_PIXELS, 0, 0, 0, 1.0).unwrap_err().to_string() == message);
+
+        let shared = apa102.into_shared();
+        assert!(shared.get_pixel(2)? == (255, 255, 255, 31));
+        assert!(matches!(shared.get_pixel(NUM_PIXELS), Err(Error::InvalidArgument(_))));
+
+        Ok(())
+    }
+
+    /// Tests the signed pixel indices wrapped or clamped into range.
+    #[test]
+    fn test_apa102_wrap_clamp_index() -> Result<(), Error> {
+        let mut apa102 = APA102::with_simulation()?;
+        let len = apa102.len() as isize;
+
+        assert!(apa102.wrap_index(-1) == NUM_PIXELS - 1);
+        assert!(apa102.wrap_index(len) == 0);
+        assert!(apa102.wrap_index(-len - 2) == NUM_PIXELS - 2);
+        assert!(apa102.wrap_index(3) == 3);
+
+        assert!(apa102.clamp_index(-1) == 0);
+        assert!(apa102.clamp_index(len) == NUM_PIXELS - 1);
+        assert!(apa102.clamp_index(isize::MIN) == 0 && apa102.clamp_index(isize::MAX) == NUM_PIXELS - 1);
+
+        apa102.set_pixel_wrapping(-1, 255, 0, 0, 1.0)?;
+        apa102.set_pixel_wrapping(len, 0, 0, 255, 1.0)?;
+        assert!(apa102.pixels[NUM_PIXELS - 1] == [255, 0, 0, 31]);
+        assert!(apa102.pixels[0] == [0, 0, 255, 31]);
+
+        Ok(())
+    }
+
+    /// Tests to set all 
+    #[test]
+    fn test_apa102_set_all() -> Result<(), Error> {
+        
+        let mut apa102 = APA102::new()?;
+        apa102.simulation = true;
+        let _result = apa102.setup();
+
+        apa102.set_all(123, 234, 012, 1.0)?;
+
+        for i in 0..apa102.pixels.len() {
+            assert!(apa102.pixels[i][0] == 123);
+            assert!(apa102.pixels[i][1] == 234);
+            assert!(apa102.pixels[i][2] == 12);
+            assert!(apa102.pixels[i][3] == 31);
+        }
+
+        Ok(())
+    }
+
+    /// Tests mirroring the first half of the pixels around the center pixel.
+    #[test]
+    fn test_apa102_mirror_halves() -> Result<(), Error> {
+        let mut apa102 = APA102::with_simulation()?;
+        assert!(apa102.center() == 3);
+
+        for x in 0..apa102.len() {
+            apa102.set_pixel(x, x as u8 * 10, 0, 0, 1.0)?;
+        }
+        apa102.mirror_halves();
+
+        let reds: Vec<u8> = apa102.pixels.iter().map(|pixel| pixel[0]).collect();
+        assert!(reds == [0, 10, 20, 30, 20, 10, 0]);
+
+        Ok(())
+    }
+
+    /// Tests the threshold colors of the graph at the boundaries, out of range values being clamped.
+    #[test]
+    fn test_graph_palette_thresholds() {
+        let green: Color = crate::colors::GREEN.into();
+        let amber: Color = crate::colors::AMBER.into();
+        let red: Color = crate::colors::RED.into();
+
+        assert!(GraphPalette::threshold_color(-1.0) == green);
+        assert!(GraphPalette::threshold_color(0.0) == green);
+        assert!(GraphPalette::threshold_color(49.9) == green);
+        assert!(GraphPalette::threshold_color(50.0) == amber);
+        assert!(GraphPalette::threshold_color(79.9) == amber);
+        assert!(GraphPalette::threshold_color(80.0) == red);
+        assert!(GraphPalette::threshold_color(100.0) == red);
+        assert!(GraphPalette::threshold_color(101.0) == red);
+    }
+
+    /// Tests the pixels lit by the bar graph and their colors.
+    #[test]
+    fn test_apa102_graph() -> Result<(), Error> {
+        let mut apa102 = APA102::with_simulation()?;
+        let lit = |apa102: &APA102| (0..NUM_PIXELS).filter(|&x| matches!(apa102.get_pixel(x), Ok((r, g, _, _)) if r > 0 || g > 0)).count();
+
+        apa102.graph(0.0, GraphPalette::Thresholds, 1.0)?;
+        assert!(lit(&apa102) == 0);
+        apa102.graph(49.9, GraphPalette::Thresholds, 1.0)?;
+        assert!(lit(&apa102) == 3);
+        assert!(apa102.get_pixel(0)? == (0, 255, 0, 31));
+        apa102.graph(101.0, GraphPalette::Thresholds, 1.0)?;
+        assert!(lit(&apa102) == NUM_PIXELS);
+        assert!(apa102.get_pixel(6)? == (255, 0, 0, 31));
+
+        apa102.graph(50.0, GraphPalette::Solid(Color::new(0, 0, 255)), 1.0)?;
+        assert!(apa102.get_pixel(3)? == (0, 0, 255, 31) && apa102.get_pixel(4)? == (0, 0, 0, 31));
+        apa102.graph(100.0, GraphPalette::Rainbow, 1.0)?;
+        assert!(apa102.get_pixel(0)? == (255, 0, 0, 31));
+        assert!(apa102.get_pixel(1)? != apa102.get_pixel(2)?);
+
+        assert!(matches!(apa102.graph(f32::NAN, GraphPalette::Rainbow, 1.0), Err(Error::InvalidArgument(_))));
+
+        Ok(())
     }
-}
 
+    /// Tests filling the rings around the center pixel according to the progress.
+    #[test]
+    fn test_apa102_center_out_fill() -> Result<(), Error> {
+        let mut apa102 = APA102::with_simulation()?;
+
+        // Progress and lit pixels
+        let cases: [(f32, [bool; NUM_PIXELS]); 5] = [
+            (0.0, [false, false, false, false, false, false, false]),
+            (0.25, [false, false, false, true, false, false, false]),
+            (0.5, [false, false, true, true, true, false, false]),
+            (0.7, [false, true, true, true, true, true, false]),
+            (1.0, [true, true, true, true, true, true, true]),
+        ];
+        for (progress, lit) in cases.iter() {
+            apa102.center_out_fill((0, 255, 0), 1.0, *progress)?;
+            for (x, &on) in lit.iter().enumerate() {
+                let expected = if on { [0, 255, 0, 31] } else { [0, 0, 0, 31] };
+                assert!(apa102.pixels[x] == expected, "progress {} pixel {}", progress, x);
+            }
+        }
 
-/// Unit tests
-#[cfg(test)]
-mod tests {
-    use super::*;
+        assert!(matches!(apa102.center_out_fill((0, 255, 0), 1.0, 1.5), Err(Error::InvalidArgument(_))));
+        assert!(matches!(apa102.center_out_fill((0, 255, 0), 2.0, 0.5), Err(Error::InvalidArgument(_))));
 
-    /// Tests the setup of the light.
+        Ok(())
+    }
+
+    /// Tests the alternating pattern and swapping it with the phase.
     #[test]
-    fn test_apa102_setup() -> Result<(), Error> {
-        
+    fn test_apa102_fill_alternating() -> Result<(), Error> {
+
         let mut apa102 = APA102::new()?;
         apa102.simulation = true;
-        
-        // Not setup
-        assert!(apa102.is_setup == false);
 
-        // Force setup
-        let _result = apa102.setup();
+        apa102.fill_alternating((255, 0, 0), (0, 0, 255), 1.0, 0)?;
+        for i in 0..apa102.pixels.len() {
+            let expected = if i % 2 == 1 { [0, 0, 255, 31] } else { [255, 0, 0, 31] };
+            assert!(apa102.pixels[i] == expected);
+        }
 
-        assert!(apa102.is_setup == true);
+        apa102.fill_alternating((255, 0, 0), (0, 0, 255), 1.0, 1)?;
+        for i in 0..apa102.pixels.len() {
+            let expected = if i % 2 == 1 { [255, 0, 0, 31] } else { [0, 0, 255, 31] };
+            assert!(apa102.pixels[i] == expected);
+        }
 
         Ok(())
     }
 
-    /// Tests the setup of the light.
+    /// Tests sub-pixel blitting halfway between source pixels.
     #[test]
-    fn test_apa102_set_brightness() -> Result<(), Error> {
-        
+    fn test_apa102_blit_subpixel() -> Result<(), Error> {
+
         let mut apa102 = APA102::new()?;
         apa102.simulation = true;
         let _result = apa102.setup();
 
-        apa102.set_brightness(0.0);
-        for i in 0..apa102.pixels.len() {
-            assert!(apa102.pixels[i][3] == 0);
-        }
+        let source = [(0, 0, 0), (100, 200, 50), (200, 0, 50), (0, 100, 250),
+            (10, 20, 30), (30, 20, 10), (50, 50, 50), (150, 250, 0)];
+
+        apa102.blit_subpixel(&source, 0.5, 1.0)?;
 
-        apa102.set_brightness(1.0);
         for i in 0..apa102.pixels.len() {
+            let (r0, g0, b0) = source[i];
+            let (r1, g1, b1) = source[i + 1];
+            assert!(apa102.pixels[i][0] == ((r0 as f32 + r1 as f32) / 2.0).round() as u8);
+            assert!(apa102.pixels[i][1] == ((g0 as f32 + g1 as f32) / 2.0).round() as u8);
+            assert!(apa102.pixels[i][2] == ((b0 as f32 + b1 as f32) / 2.0).round() as u8);
             assert!(apa102.pixels[i][3] == 31);
         }
 
+        // Past the end of the source the last pixel fades to black.
+        apa102.blit_subpixel(&source, 1.5, 1.0)?;
+        assert!(apa102.pixels[6] == [75, 125, 0, 31]);
+
         Ok(())
     }
 
-    /// Test clearing the buffer.
+    /// Tests invalid input is rejected with an error, without panicking nor changing anything.
     #[test]
-    fn test_apa102_clear() -> Result<(), Error> {
-        
+    #[allow(deprecated)]
+    fn test_apa102_invalid_input() -> Result<(), Error> {
+
         let mut apa102 = APA102::new()?;
         apa102.simulation = true;
         let _result = apa102.setup();
 
-        let brightness : u8 = 31;
-
+        assert!(matches!(apa102.set_brightness(-0.1), Err(Error::InvalidArgument(_))));
+        assert!(matches!(apa102.set_brightness(1.1), Err(Error::InvalidArgument(_))));
+        assert!(matches!(apa102.set_pixel(0, 1, 2, 3, 1.5), Err(Error::InvalidArgument(_))));
+        assert!(matches!(apa102.set_pixel(7, 1, 2, 3, 1.0), Err(Error::InvalidArgument(_))));
+        assert!(matches!(apa102.set_pixel_clamped(7, 1, 2, 3, 1.0), Err(Error::InvalidArgument(_))));
+        assert!(matches!(apa102.set_all(1, 2, 3, -1.0), Err(Error::InvalidArgument(_))));
+        assert!(matches!(apa102.fill_alternating((1, 2, 3), (4, 5, 6), 1.5, 0), Err(Error::InvalidArgument(_))));
+        assert!(matches!(apa102.blit_subpixel(&[(1, 2, 3)], 0.0, 2.0), Err(Error::InvalidArgument(_))));
+        assert!(matches!(apa102.set_global_brightness(1.5), Err(Error::InvalidArgument(_))));
+        assert!(matches!(apa102.present(-0.5, false), Err(Error::InvalidArgument(_))));
+
+        // The messages name the parameter, its value and the expected values
+        assert!(apa102.set_pixel(7, 1, 2, 3, 1.0).unwrap_err().to_string() == "Invalid argument: invalid pixel 7, expected 0 to 6");
+        assert!(apa102.set_all(1, 2, 3, 1.5).unwrap_err().to_string() == "Invalid argument: invalid brightness 1.5, expected 0.0 to 1.0");
+
+        // The deprecated fallible variants behave the same
+        assert!(matches!(apa102.try_set_brightness(1.1), Err(Error::InvalidArgument(_))));
+        assert!(matches!(apa102.try_set_pixel(7, 1, 2, 3, 1.0), Err(Error::InvalidArgument(_))));
+        assert!(matches!(apa102.try_set_all(1, 2, 3, -1.0), Err(Error::InvalidArgument(_))));
+        assert!(matches!(apa102.try_blit_subpixel(&[(1, 2, 3)], 0.0, 2.0), Err(Error::InvalidArgument(_))));
+
+        // Nothing was changed by the rejected calls
         for i in 0..apa102.pixels.len() {
-            apa102.pixels[i][0] = 250 as u8; // R
-            apa102.pixels[i][1] = 250 as u8; // G
-            apa102.pixels[i][2] = 250 as u8; // B
-            apa102.pixels[i][3] = brightness;
+            assert!(apa102.pixels[i] == [0, 0, 0, 0]);
         }
+        assert!(apa102.global_brightness() == 1.0);
+        assert!(apa102.last_frame().is_none());
 
-        apa102.clear();
+        // Valid input is applied
+        apa102.set_pixel(6, 1, 2, 3, 1.0)?;
+        assert!(apa102.pixels[6] == [1, 2, 3, 31]);
 
-        // The RGB pixels are clear but the brightness is unchanged
+        Ok(())
+    }
+
+    /// Tests the breathing effect follows a sine over the period.
+    #[test]
+    fn test_apa102_breathe_tick() -> Result<(), Error> {
+
+        let mut apa102 = APA102::new()?;
+        apa102.simulation = true;
+        let clock = ManualClock::new();
+        apa102.set_clock(clock.clone());
+        apa102.set_all(10, 20, 30, 1.0)?;
+
+        let period = Duration::from_millis(1000);
+        let expected = [0.5, 1.0, 0.5, 0.0, 0.5];
+
+        for expected_brightness in expected.iter() {
+            apa102.breathe_tick(period);
+            assert!((apa102.global_brightness() - expected_brightness).abs() < 0.001);
+            clock.advance(period / 4);
+        }
+
+        // The colors are kept
         for i in 0..apa102.pixels.len() {
-            assert!(apa102.pixels[i][0] == 0);
-            assert!(apa102.pixels[i][1] == 0);
-            assert!(apa102.pixels[i][2] == 0);
-            assert!(apa102.pixels[i][3] == 31);
+            assert!(apa102.pixels[i] == [10, 20, 30, 31]);
         }
 
         Ok(())
     }
 
-    /// Tests to set pixel colors.
+    /// Tests two effects following the same phase, whatever the number of frames rendered.
     #[test]
-    fn test_apa102_set_pixel() -> Result<(), Error> {
-        
+    fn test_apa102_effects_with_phase() -> Result<(), Error> {
+
+        let mut apa102 = APA102::with_simulation()?;
+        let clock = ManualClock::new();
+        apa102.set_clock(clock.clone());
+        let phase = Phase::starting_now(&clock, Duration::from_millis(1000));
+
+        clock.advance(Duration::from_millis(2250));
+        apa102.breathe_with(&phase);
+        apa102.strobe_with(&phase, (0, 0, 255), 0.5);
+        assert!((apa102.global_brightness() - 1.0).abs() < 0.001);
+        assert!(apa102.pixels[0][0..3] == [0, 0, 255]);
+
+        clock.advance(Duration::from_millis(500));
+        apa102.breathe_with(&phase);
+        apa102.strobe_with(&phase, (0, 0, 255), 0.5);
+        assert!(apa102.global_brightness().abs() < 0.001);
+        assert!(apa102.pixels[0][0..3] == [0, 0, 0]);
+
+        Ok(())
+    }
+
+    /// Tests the strobe is on during the on portion of the period and off during the rest.
+    #[test]
+    fn test_apa102_strobe_tick() -> Result<(), Error> {
+
         let mut apa102 = APA102::new()?;
         apa102.simulation = true;
-        let _result = apa102.setup();
+        let clock = ManualClock::new();
+        apa102.set_clock(clock.clone());
+        apa102.set_all(0, 0, 0, 1.0)?;
 
-        apa102.set_pixel(0, 123, 234, 012, 1.0);
-        apa102.set_pixel(6, 12, 58, 123, 0.0);
+        let period = Duration::from_millis(1000);
 
-        assert!(apa102.pixels[0][0] == 123);
-        assert!(apa102.pixels[0][1] == 234);
-        assert!(apa102.pixels[0][2] == 12);
-        assert!(apa102.pixels[0][3] == 31);
+        // On for the first 250ms of each second: time advanced before each frame and expected state
+        let frames = [(0, true), (200, true), (50, false), (650, false), (100, true), (300, false)];
+
+        for &(advance, on) in frames.iter() {
+            clock.advance(Duration::from_millis(advance));
+            apa102.strobe_tick((255, 0, 0), 0.25, period);
+
+            let expected = if on { [255, 0, 0, 31] } else { [0, 0, 0, 31] };
+            for pixel in apa102.pixels.iter() {
+                assert!(*pixel == expected);
+            }
+        }
+
+        // The ratio is clamped
+        apa102.strobe_tick((0, 255, 0), 2.0, period);
+        assert!(apa102.pixels[0] == [0, 255, 0, 31]);
+        apa102.strobe_tick((0, 255, 0), -1.0, period);
+        assert!(apa102.pixels[0] == [0, 0, 0, 31]);
 
-        assert!(apa102.pixels[6][0] == 12);
-        assert!(apa102.pixels[6][1] == 58);
-        assert!(apa102.pixels[6][2] == 123);
-        assert!(apa102.pixels[6][3] == 0);
         Ok(())
     }
 
-    /// Tests to set all 
+    /// Tests the dithered brightness alternates around the fractional level.
     #[test]
-    fn test_apa102_set_all() -> Result<(), Error> {
-        
+    fn test_apa102_dithering() -> Result<(), Error> {
+
         let mut apa102 = APA102::new()?;
         apa102.simulation = true;
-        let _result = apa102.setup();
+        apa102.set_all(255, 255, 255, 1.0)?;
+        for pixel in apa102.pixels.iter_mut() {
+            pixel[3] = 5;
+        }
 
-        apa102.set_all(123, 234, 012, 1.0);
+        // 5 * 0.5 = 2.5
+        apa102.set_global_brightness(0.5)?;
 
-        for i in 0..apa102.pixels.len() {
-            assert!(apa102.pixels[i][0] == 123);
-            assert!(apa102.pixels[i][1] == 234);
-            assert!(apa102.pixels[i][2] == 12);
-            assert!(apa102.pixels[i][3] == 31);
+        // Without dithering, always rounded
+        for _i in 0..4 {
+            apa102.show()?;
+            assert!(apa102.last_frame().unwrap()[0][0] == 3);
+        }
+
+        apa102.set_dithering(true);
+        for &expected in [2, 3, 2, 3, 2, 3].iter() {
+            apa102.show()?;
+            assert!(apa102.last_frame().unwrap().iter().all(|pixel| pixel[0] == expected));
+        }
+
+        // 31 * 0.1 = 3.1: mostly 3, sometimes 4, averaging 3.1
+        apa102.set_all(255, 255, 255, 1.0)?;
+        apa102.set_global_brightness(0.1)?;
+        apa102.set_dithering(true);
+
+        let mut sum = 0;
+        for _i in 0..10 {
+            apa102.show()?;
+            let brightness = apa102.last_frame().unwrap()[0][0];
+            assert!(brightness == 3 || brightness == 4);
+            sum += brightness as u32;
         }
+        assert!(sum == 31);
+
+        Ok(())
+    }
+
+    /// Tests the brightness is scaled down to respect the power limit.
+    #[test]
+    fn test_apa102_power_limit() -> Result<(), Error> {
+
+        let mut apa102 = APA102::new()?;
+        apa102.simulation = true;
+
+        // Full white draws 7 * 3 * 20 = 420 mA
+        apa102.set_all(255, 255, 255, 1.0)?;
+        apa102.show()?;
+        assert!(apa102.last_frame().unwrap().iter().all(|pixel| *pixel == [31, 255, 255, 255]));
+
+        // Limited to half
+        apa102.set_power_limit(210);
+        apa102.show()?;
+        let frame = *apa102.last_frame().unwrap();
+        assert!(frame.iter().all(|pixel| *pixel == [15, 255, 255, 255]));
+        assert!(APA102::frame_current(&frame) <= 210.0);
+
+        // The buffer is unchanged
+        assert!(apa102.pixels.iter().all(|pixel| *pixel == [255, 255, 255, 31]));
+
+        // Under the limit nothing is scaled
+        apa102.set_all(255, 0, 0, 1.0)?;
+        apa102.show()?;
+        assert!(apa102.last_frame().unwrap().iter().all(|pixel| *pixel == [31, 0, 0, 255]));
 
         Ok(())
     }
@@ -413,4 +2338,531 @@ mod tests {
 
         Ok(())
     }
+
+    /// Tests creating the driver in simulation mode.
+    #[test]
+    fn test_apa102_with_simulation() -> Result<(), Error> {
+        let mut apa102 = APA102::with_simulation()?;
+        assert!(apa102.simulation);
+
+        apa102.show()?;
+        assert!(apa102.last_frame().is_some());
+
+        Ok(())
+    }
+
+    /// Tests presenting with a temporary global brightness and gamma, leaving the buffer unchanged.
+    #[test]
+    fn test_apa102_present() -> Result<(), Error> {
+        let mut apa102 = APA102::with_simulation()?;
+        apa102.set_all(255, 128, 0, 1.0)?;
+
+        apa102.present(0.5, false)?;
+        assert!(apa102.last_frame().unwrap().iter().all(|pixel| *pixel == [16, 0, 128, 255]));
+
+        apa102.present(0.25, true)?;
+        assert!(apa102.last_frame().unwrap().iter().all(|pixel| *pixel == [8, 0, 37, 255]));
+
+        assert!(apa102.get_pixel(0)? == (255, 128, 0, 31));
+        assert!(apa102.global_brightness() == 1.0);
+
+        apa102.show()?;
+        assert!(apa102.last_frame().unwrap()[0] == [31, 0, 128, 255]);
+
+        Ok(())
+    }
+
+    /// Tests NaN and out of range brightness are rejected or clamped without panicking.
+    #[test]
+    fn test_apa102_brightness_nan() -> Result<(), Error> {
+        let mut apa102 = APA102::with_simulation()?;
+
+        for &brightness in [f32::NAN, -0.5, 1.5, f32::INFINITY, f32::NEG_INFINITY].iter() {
+            assert!(matches!(apa102.set_brightness(brightness), Err(Error::InvalidArgument(_))));
+            assert!(matches!(apa102.set_pixel(0, 255, 0, 0, brightness), Err(Error::InvalidArgument(_))));
+            assert!(matches!(apa102.set_all(255, 0, 0, brightness), Err(Error::InvalidArgument(_))));
+        }
+
+        assert!(APA102::clamp_brightness(f32::NAN) == 0.0);
+        assert!(APA102::clamp_brightness(-0.5) == 0.0);
+        assert!(APA102::clamp_brightness(1.5) == 1.0);
+        assert!(APA102::clamp_brightness(f32::INFINITY) == 1.0);
+        assert!(APA102::clamp_brightness(0.4) == 0.4);
+
+        apa102.set_pixel_clamped(0, 255, 0, 0, 2.0)?;
+        assert!(apa102.pixels[0] == [255, 0, 0, 31]);
+        apa102.set_pixel_clamped(0, 255, 0, 0, f32::NAN)?;
+        assert!(apa102.pixels[0] == [255, 0, 0, 0]);
+
+        apa102.set_brightness_clamped(7.0);
+        assert!(apa102.pixels.iter().all(|pixel| pixel[3] == 31));
+        apa102.set_brightness_clamped(f32::NAN);
+        assert!(apa102.pixels.iter().all(|pixel| pixel[3] == 0));
+
+        Ok(())
+    }
+
+    /// Tests enabling simulation mode on a driver created for the hardware.
+    #[test]
+    fn test_apa102_enable_simulation() -> Result<(), Error> {
+        let mut apa102 = APA102::new()?;
+        assert!(apa102.is_simulation() != cfg!(feature = "hardware"));
+
+        apa102.enable_simulation();
+        assert!(apa102.is_simulation());
+        apa102.show()?;
+
+        Ok(())
+    }
+
+    /// Tests the trace hook receives a message before each frame is written, in simulation mode.
+    #[test]
+    fn test_apa102_trace() -> Result<(), Error> {
+        let messages = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut apa102 = APA102::with_simulation()?;
+
+        let received = messages.clone();
+        apa102.set_trace(move |message| received.lock().unwrap().push(message.to_string()));
+        apa102.show()?;
+        apa102.show()?;
+        assert!(*messages.lock().unwrap() == ["APA102 show: 7 pixels", "APA102 show: 7 pixels"]);
+
+        apa102.clear_trace();
+        apa102.show()?;
+        assert!(messages.lock().unwrap().len() == 2);
+
+        Ok(())
+    }
+
+    /// Tests the SPI bus and clock speed are validated, stored and used to encode the frames.
+    #[test]
+    #[cfg(feature = "hardware")]
+    fn test_apa102_with_spi_bus() -> Result<(), Error> {
+        let mut apa102 = APA102::with_spi_bus(Bus::Spi1, 8_000_000)?;
+        assert!(apa102.spi_bus() == Some(Bus::Spi1));
+        assert!(apa102.spi_clock_speed() == Some(8_000_000));
+        assert!(APA102::new()?.spi_bus().is_none());
+
+        assert!(matches!(APA102::with_spi_bus(Bus::Spi0, SPI_MIN_CLOCK_SPEED - 1), Err(Error::InvalidArgument(_))));
+        assert!(matches!(APA102::with_spi_bus(Bus::Spi0, SPI_MAX_CLOCK_SPEED + 1), Err(Error::InvalidArgument(_))));
+        assert!(APA102::with_spi_bus(Bus::Spi0, SPI_MAX_CLOCK_SPEED).is_ok());
+
+        // In simulation mode the bus is not opened
+        apa102.enable_simulation();
+        apa102.set_pixel(0, 255, 128, 1, 1.0)?;
+        apa102.show()?;
+        assert!(apa102.spi.is_none());
+
+        let bytes = APA102::spi_frame(apa102.last_frame().unwrap());
+        assert!(bytes.len() == 4 + NUM_PIXELS * 4 + 5);
+        assert!(bytes[0..4] == [0, 0, 0, 0]);
+        assert!(bytes[4..8] == [0b1111_1111, 1, 128, 255]);
+        assert!(bytes[8..12] == [0b1110_0000, 0, 0, 0]);
+        assert!(bytes[bytes.len() - 5..] == [0; 5]);
+
+        Ok(())
+    }
+
+    /// Tests the pins used by two drivers of the same registry are rejected in simulation mode, also when showing the
+    /// pixels, until the first driver is dropped.
+    #[test]
+    fn test_apa102_pin_in_use() -> Result<(), Error> {
+        let registry = PinRegistry::new();
+        let mut first = APA102::with_simulation()?;
+        first.set_pin_registry(registry.clone());
+        first.setup()?;
+
+        let mut second = APA102::with_simulation()?;
+        second.set_pin_registry(registry);
+        assert!(matches!(second.setup(), Err(Error::PinAlreadyInUse { pin: GPIO_DAT, owner: "multicolour LEDs" })));
+        assert!(matches!(second.show(), Err(Error::PinAlreadyInUse { pin: GPIO_DAT, .. })));
+        assert!(second.last_frame().is_none());
+
+        // A driver of its own registry is free
+        APA102::with_simulation()?.show()?;
+
+        drop(first);
+        second.show()?;
+
+        Ok(())
+    }
+
+    /// Tests the pins of the SPI bus are claimed, the first bus sharing them with the GPIO pins.
+    #[test]
+    #[cfg(feature = "hardware")]
+    fn test_apa102_spi_pin_in_use() -> Result<(), Error> {
+        let registry = PinRegistry::new();
+        let mut gpio = APA102::with_simulation()?;
+        gpio.set_pin_registry(registry.clone());
+        gpio.setup()?;
+
+        let mut spi0 = APA102::with_spi_bus(Bus::Spi0, 8_000_000)?;
+        spi0.enable_simulation();
+        spi0.set_pin_registry(registry.clone());
+        assert!(matches!(spi0.setup(), Err(Error::PinAlreadyInUse { pin: GPIO_DAT, .. })));
+
+        // Another bus is free until set up twice
+        let mut spi1 = APA102::with_spi_bus(Bus::Spi1, 8_000_000)?;
+        spi1.enable_simulation();
+        spi1.set_pin_registry(registry.clone());
+        spi1.setup()?;
+        assert!(registry.owner(20) == Some("multicolour LEDs"));
+        let mut spi6 = APA102::with_spi_bus(Bus::Spi6, 8_000_000)?;
+        spi6.enable_simulation();
+        spi6.set_pin_registry(registry);
+        assert!(spi6.setup().is_err());
+
+        drop(gpio);
+        spi0.setup()?;
+
+        Ok(())
+    }
+
+    /// Output recording the levels written, shared by the outputs of a test.
+    #[derive(Debug)]
+    struct RecordingOutput {
+        name: char,
+        levels: std::sync::Arc<std::sync::Mutex<Vec<(char, bool)>>>,
+    }
+
+    impl DigitalOutput for RecordingOutput {
+        fn set_level(&mut self, high: bool) {
+            self.levels.lock().unwrap().push((self.name, high));
+        }
+    }
+
+    /// Clock moving forward by a step each time it is read, so a busy-wait ends without real time passing.
+    #[derive(Debug)]
+    struct SteppingClock {
+        clock: ManualClock,
+        step: Duration,
+    }
+
+    impl Clock for SteppingClock {
+        fn now(&self) -> Instant {
+            self.clock.advance(self.step);
+            self.clock.now()
+        }
+    }
+
+    /// Output recording the instants at which the clock output changes.
+    #[derive(Debug)]
+    struct TimedOutput {
+        clock: ManualClock,
+        edges: std::sync::Arc<std::sync::Mutex<Vec<Instant>>>,
+    }
+
+    impl DigitalOutput for TimedOutput {
+        fn set_level(&mut self, _high: bool) {
+            self.edges.lock().unwrap().push(self.clock.now());
+        }
+    }
+
+    /// Tests each level of the clock output is held for at least half the minimum clock period.
+    #[test]
+    fn test_apa102_min_clock_period() -> Result<(), Error> {
+        let clock = ManualClock::new();
+        let edges = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let levels = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let clk = TimedOutput { clock: clock.clone(), edges: edges.clone() };
+        let mut apa102 = APA102::with_pins(RecordingOutput { name: 'd', levels: levels.clone() }, clk, RecordingOutput { name: 's', levels });
+        apa102.set_clock(SteppingClock { clock: clock.clone(), step: Duration::from_nanos(30) });
+        assert!(apa102.min_clock_period() == Duration::ZERO);
+
+        // As fast as possible, the clock is not even read
+        apa102.show()?;
+        assert!(edges.lock().unwrap().windows(2).all(|pair| pair[0] == pair[1]));
+
+        edges.lock().unwrap().clear();
+        apa102.set_min_clock_period(Duration::from_nanos(500));
+        apa102.show()?;
+
+        let edges = edges.lock().unwrap();
+        assert!(edges.len() == 2 * (32 + NUM_PIXELS * 32 + 36));
+        assert!(edges.windows(2).all(|pair| pair[1] - pair[0] >= Duration::from_nanos(250)));
+        assert!(edges.windows(2).all(|pair| pair[1] - pair[0] < Duration::from_nanos(250 + 2 * 30)));
+
+        Ok(())
+    }
+
+    /// Tests the bytes clocked out on custom outputs: sampled on each rising edge of the clock while selected.
+    #[test]
+    fn test_apa102_with_pins() -> Result<(), Error> {
+        let levels = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let output = |name| RecordingOutput { name, levels: levels.clone() };
+        let mut apa102 = APA102::with_pins(output('d'), output('c'), output('s'));
+        assert!(!apa102.is_simulation());
+
+        apa102.set_pixel(0, 255, 128, 1, 1.0)?;
+        apa102.show()?;
+
+        let levels = levels.lock().unwrap();
+        assert!(levels.first() == Some(&('s', false)));
+        assert!(levels.last() == Some(&('s', true)));
+
+        let mut data = false;
+        let mut bits = Vec::new();
+        for &(name, high) in levels.iter() {
+            match name {
+                'd' => data = high,
+                'c' if high => bits.push(data),
+                _ => (),
+            }
+        }
+
+        // Start frame, pixels, then end frame
+        assert!(bits.len() == 32 + NUM_PIXELS * 32 + 36);
+        let bytes: Vec<u8> = bits[32..32 + NUM_PIXELS * 32].chunks(8)
+            .map(|byte| byte.iter().fold(0, |value, &bit| value << 1 | bit as u8))
+            .collect();
+        assert!(bits[..32].iter().all(|&bit| !bit));
+        assert!(bytes[0..4] == [0b1111_1111, 1, 128, 255]);
+        assert!(bytes[4..8] == [0b1110_0000, 0, 0, 0]);
+
+        Ok(())
+    }
+
+    /// Pins the auto traits: the driver can be moved to another thread, its handle shared between threads.
+    const _: () = {
+        const fn assert_send<T: Send>() {}
+        const fn assert_sync<T: Sync>() {}
+        assert_send::<APA102>();
+        assert_send::<APA102Handle>();
+        assert_sync::<APA102Handle>();
+        assert_send::<Error>();
+        assert_sync::<Error>();
+    };
+
+    /// Tests the fire effect with a fixed seed: the heat rises from the sparks at the base and maps to flame colors.
+    #[test]
+    fn test_apa102_fire_tick() -> Result<(), Error> {
+        let mut apa102 = APA102::with_simulation()?;
+        apa102.set_all(0, 0, 0, 0.5)?;
+        let brightness = apa102.pixels[0][3];
+        apa102.set_fire_seed(42);
+
+        // A spark is ignited on the first frame
+        apa102.fire_tick(55, 200);
+        assert!(*apa102.fire_heat() == [0, 192, 0, 0, 0, 0, 0]);
+        assert!(apa102.pixels[1] == [255, 255, 64, brightness]);
+
+        // It cools down and drifts away from the base while a new spark is ignited
+        apa102.fire_tick(55, 200);
+        assert!(*apa102.fire_heat() == [201, 128, 42, 85, 0, 0, 0]);
+        let colors = (0..NUM_PIXELS).map(|x| {
+            let (r, g, b, _brightness) = apa102.get_pixel(x)?;
+            Ok((r, g, b))
+        }).collect::<Result<Vec<(u8, u8, u8)>, Error>>()?;
+        assert!(colors == [(255, 255, 88), (255, 128, 0), (128, 0, 0), (255, 0, 0), (0, 0, 0), (0, 0, 0), (0, 0, 0)]);
+
+        // The same seed gives the same flames
+        let mut other = APA102::with_simulation()?;
+        other.set_fire_seed(42);
+        other.fire_tick(55, 200);
+        other.fire_tick(55, 200);
+        assert!(other.fire_heat() == apa102.fire_heat());
+
+        // Without sparks, the fire dies out
+        for _i in 0..20 {
+            apa102.fire_tick(255, 0);
+        }
+        assert!(*apa102.fire_heat() == [0; NUM_PIXELS]);
+        assert!(apa102.pixels[0] == [0, 0, 0, brightness]);
+
+        Ok(())
+    }
+
+    /// Tests the heat scale goes from black through red and yellow to white.
+    #[test]
+    fn test_apa102_heat_color() {
+        assert!(APA102::heat_color(0) == (0, 0, 0));
+        assert!(APA102::heat_color(60) == (180, 0, 0));
+        assert!(APA102::heat_color(128) == (255, 128, 0));
+        assert!(APA102::heat_color(255) == (255, 255, 252));
+    }
+
+    /// Tests sharing the driver between two threads.
+    #[test]
+    fn test_apa102_shared_handle() -> Result<(), Error> {
+        let handle = APA102::with_simulation()?.into_shared();
+
+        // Two threads each setting their own pixels and showing the frame
+        let threads: Vec<_> = [(0, 255), (1, 0)].iter().map(|&(first, red)| {
+            let handle = handle.clone();
+            thread::spawn(move || {
+                for x in (first..NUM_PIXELS).step_by(2) {
+                    handle.set_pixel(x, red, 0, 255 - red, 1.0).unwrap();
+                    handle.show().unwrap();
+                }
+            })
+        }).collect();
+
+        for writer in threads {
+            writer.join().unwrap();
+        }
+
+        // The last frame shown has every pixel
+        let frame = handle.with_driver(|driver| *driver.last_frame().unwrap());
+        for (x, pixel) in frame.iter().enumerate() {
+            let (r, _g, b, _brightness) = handle.get_pixel(x)?;
+            assert!((r, b) == if x & 1 == 0 { (255, 0) } else { (0, 255) });
+            assert!((pixel[3], pixel[1]) == (r, b));
+        }
+
+        Ok(())
+    }
+
+    /// Tests the watchdog blanks the strip when no frame is shown within the timeout.
+    #[test]
+    fn test_apa102_watchdog() -> Result<(), Error> {
+        let mut apa102 = APA102::with_simulation()?;
+        let clock = ManualClock::new();
+        apa102.set_clock(clock.clone());
+        apa102.set_all(255, 0, 0, 1.0)?;
+        apa102.show()?;
+
+        // Disabled by default
+        clock.advance(Duration::from_secs(3600));
+        assert!(!apa102.watchdog_tick()?);
+
+        apa102.set_watchdog(Duration::from_secs(1));
+        clock.advance(Duration::from_millis(900));
+        assert!(!apa102.watchdog_tick()?);
+
+        // Each frame shown restarts the timeout
+        apa102.show()?;
+        clock.advance(Duration::from_millis(900));
+        assert!(!apa102.watchdog_tick()?);
+        assert!(!apa102.is_blank());
+
+        clock.advance(Duration::from_millis(100));
+        assert!(apa102.watchdog_tick()?);
+        assert!(apa102.is_blank());
+        assert!(apa102.last_frame().unwrap().iter().all(|pixel| pixel[1..4] == [0, 0, 0]));
+
+        apa102.clear_watchdog();
+        clock.advance(Duration::from_secs(10));
+        assert!(!apa102.watchdog_tick()?);
+
+        Ok(())
+    }
+
+    /// Tests the watchdog thread blanks a shared strip no longer shown, and ends with the strip.
+    #[test]
+    fn test_apa102_spawn_watchdog() -> Result<(), Error> {
+        let mut apa102 = APA102::with_simulation()?;
+        let clock = ManualClock::new();
+        apa102.set_clock(clock.clone());
+        let handle = apa102.into_shared();
+        handle.set_all(255, 0, 0, 1.0)?;
+        handle.set_watchdog(Duration::from_secs(1));
+
+        let watchdog = handle.spawn_watchdog(Duration::from_millis(1));
+        clock.advance(Duration::from_secs(2));
+
+        let start = Instant::now();
+        while !handle.with_driver(|driver| driver.is_blank()) {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        drop(handle);
+        watchdog.join().unwrap();
+
+        Ok(())
+    }
+
+    /// Tests the number of pixels and the blank buffer after clearing it.
+    #[test]
+    fn test_apa102_len_is_blank() -> Result<(), Error> {
+        let mut apa102 = APA102::with_simulation()?;
+        assert!(apa102.len() == NUM_PIXELS);
+        assert!(!apa102.is_empty());
+        assert!(apa102.is_blank());
+
+        apa102.set_pixel(6, 0, 0, 1, 0.5)?;
+        assert!(!apa102.is_blank());
+        apa102.clear();
+        assert!(apa102.is_blank());
+
+        // The brightness alone does not light a pixel
+        apa102.set_all(0, 0, 0, 1.0)?;
+        assert!(apa102.is_blank());
+
+        Ok(())
+    }
+
+    /// Tests the paletted mode, rotating the palette changing the colors shown but not the indices.
+    #[test]
+    fn test_apa102_palette() -> Result<(), Error> {
+        let mut apa102 = APA102::with_simulation()?;
+        apa102.set_brightness(1.0)?;
+        apa102.set_palette(vec![(255, 0, 0), (0, 255, 0), (0, 0, 255)]);
+        assert!(apa102.pixel_index(0).is_none());
+
+        for x in 0..NUM_PIXELS {
+            apa102.set_pixel_index(x, x % 3)?;
+        }
+        // Out of range indices resolve to the first color
+        apa102.set_pixel_index(6, 9)?;
+        assert!(apa102.set_pixel_index(7, 0).is_err());
+
+        apa102.show()?;
+        let colors: Vec<[u8; 4]> = apa102.last_frame().unwrap().to_vec();
+        assert!(colors[0] == [31, 0, 0, 255]);
+        assert!(colors[1] == [31, 0, 255, 0]);
+        assert!(colors[2] == [31, 255, 0, 0]);
+        assert!(colors[6] == [31, 0, 0, 255]);
+
+        apa102.rotate_palette(1);
+        apa102.show()?;
+        let rotated = apa102.last_frame().unwrap();
+        assert!(rotated[0] == colors[1]);
+        assert!(rotated[1] == colors[2]);
+        assert!(rotated[2] == colors[0]);
+        assert!(rotated[6] == colors[1]);
+        assert!((0..NUM_PIXELS).map(|x| apa102.pixel_index(x).unwrap()).eq([0, 1, 2, 0, 1, 2, 9]));
+
+        // Leaving the paletted mode keeps the last colors resolved
+        apa102.clear_palette_indices();
+        apa102.set_pixel(0, 1, 2, 3, 1.0)?;
+        apa102.show()?;
+        assert!(apa102.get_pixel(0)? == (1, 2, 3, 31));
+        assert!(apa102.get_pixel(1)? == (0, 0, 255, 31));
+
+        Ok(())
+    }
+
+    /// Tests setting a pixel from the different color inputs.
+    #[test]
+    fn test_apa102_set_pixel_color() -> Result<(), Error> {
+        let mut apa102 = APA102::with_simulation()?;
+
+        apa102.set_pixel_color(0, (255, 165, 0), 1.0)?;
+        apa102.set_pixel_color(1, [255, 165, 0], 1.0)?;
+        apa102.set_pixel_color(2, 0xFFA500, 1.0)?;
+        apa102.set_pixel_color(3, Color::named("Orange").unwrap(), 1.0)?;
+        apa102.set_pixel_color(4, "#ffa500".parse::<Color>()?, 1.0)?;
+        apa102.set_pixel(5, 255, 165, 0, 1.0)?;
+
+        for x in 1..6 {
+            assert!(apa102.pixels[x] == apa102.pixels[0]);
+        }
+        assert!(apa102.get_pixel(0)? == (255, 165, 0, 31));
+
+        apa102.set_all_color(Color::named("blue").unwrap(), 1.0)?;
+        assert!(apa102.pixels.iter().all(|pixel| *pixel == [0, 0, 255, 31]));
+
+        // Unknown names and malformed hex strings are rejected
+        assert!(Color::named("octarine").is_none());
+        for invalid in ["octarine", "#ffa50", "#ffa5000", "#gggggg", "#+ffa50", "ffa500"].iter() {
+            assert!(matches!(invalid.parse::<Color>(), Err(Error::InvalidArgument(_))));
+        }
+
+        // A color converts back to a tuple
+        let rgb: (u8, u8, u8) = Color::from(0x102030).into();
+        assert!(rgb == (0x10, 0x20, 0x30));
+
+        Ok(())
+    }
 }
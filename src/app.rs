@@ -0,0 +1,397 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use crate::Error;
+use crate::clock::{Clock, SystemClock};
+use crate::error::invalid_input;
+use crate::rainbow_hat::RainbowHat;
+use crate::touch::{ButtonId, EdgeDetector, TouchEvent};
+
+/// Number of frames per second of a new event loop.
+pub const DEFAULT_FRAME_RATE: u32 = 50;
+
+/// Handler run by the event loop, receiving the board. Its error stops the loop.
+pub type Handler = Box<dyn FnMut(&mut RainbowHat) -> Result<(), Error> + Send>;
+
+/// Handler run at a regular interval.
+struct Timer {
+
+    /// Interval between two runs.
+    interval: Duration,
+
+    /// Instant of the next run, None until the first frame.
+    next: Option<Instant>,
+
+    /// Handler to run.
+    handler: Handler,
+}
+
+/// Event loop owning a board: at each frame, it reads the buttons and runs the handlers of their presses and releases,
+/// the timers that are due and the frame handler, then shows the outputs changed by the handlers.
+/// Send but not Sync, as the handlers are only Send.
+pub struct EventLoop {
+
+    /// Board given to the handlers.
+    hat: RainbowHat,
+
+    /// Handlers run when a button is pressed.
+    press_handlers: Vec<(ButtonId, Handler)>,
+
+    /// Handlers run when a button is released.
+    release_handlers: Vec<(ButtonId, Handler)>,
+
+    /// Handlers run at a regular interval.
+    timers: Vec<Timer>,
+
+    /// Handler run at each frame, if any.
+    frame_handler: Option<Handler>,
+
+    /// Detects the presses and releases of each button: A, B and C.
+    edges: [EdgeDetector; 3],
+
+    /// Interval between two frames.
+    frame_interval: Duration,
+
+    /// Clock scheduling the frames and the timers.
+    clock: Box<dyn Clock>,
+}
+
+impl EventLoop {
+
+    /// Creates an event loop without handlers, running `DEFAULT_FRAME_RATE` frames per second.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board given to the handlers, real or in simulation mode.
+    pub fn new(hat: RainbowHat) -> EventLoop {
+        Self {
+            hat,
+            press_handlers: Vec::new(),
+            release_handlers: Vec::new(),
+            timers: Vec::new(),
+            frame_handler: None,
+            edges: [EdgeDetector::new(); 3],
+            frame_interval: Duration::from_secs(1) / DEFAULT_FRAME_RATE,
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Creates an event loop on a board in simulation mode, to test an application without the hardware.
+    pub fn with_simulation() -> Result<EventLoop, Error> {
+        Ok(EventLoop::new(RainbowHat::with_simulation()?))
+    }
+
+    /// Get the board.
+    pub fn hat(&self) -> &RainbowHat {
+        &self.hat
+    }
+
+    /// Get the board, for example to set up the outputs before running the loop.
+    pub fn hat_mut(&mut self) -> &mut RainbowHat {
+        &mut self.hat
+    }
+
+    /// Stop the event loop and get the board back.
+    pub fn into_hat(self) -> RainbowHat {
+        self.hat
+    }
+
+    /// Set the clock scheduling the frames and the timers.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Clock to use.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Box::new(clock);
+    }
+
+    /// Set the number of frames per second, that is how often the buttons are read and the outputs shown.
+    ///
+    /// # Arguments
+    ///
+    /// * `frames_per_second` - Frame rate, greater than 0.
+    pub fn set_frame_rate(&mut self, frames_per_second: u32) -> Result<(), Error> {
+        if frames_per_second == 0 {
+            return Err(Error::InvalidInput(invalid_input("frame rate", frames_per_second, "greater than 0")));
+        }
+
+        self.frame_interval = Duration::from_secs(1) / frames_per_second;
+        Ok(())
+    }
+
+    /// Get the interval between two frames.
+    pub fn frame_interval(&self) -> Duration {
+        self.frame_interval
+    }
+
+    /// Run a handler when a button is pressed, after the handlers already registered for it.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Identifier of the button.
+    /// * `handler` - Closure receiving the board.
+    pub fn on_press<F>(&mut self, id: ButtonId, handler: F) -> &mut Self
+    where
+        F: FnMut(&mut RainbowHat) -> Result<(), Error> + Send + 'static,
+    {
+        self.press_handlers.push((id, Box::new(handler)));
+        self
+    }
+
+    /// Run a handler when a button is released, after the handlers already registered for it.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Identifier of the button.
+    /// * `handler` - Closure receiving the board.
+    pub fn on_release<F>(&mut self, id: ButtonId, handler: F) -> &mut Self
+    where
+        F: FnMut(&mut RainbowHat) -> Result<(), Error> + Send + 'static,
+    {
+        self.release_handlers.push((id, Box::new(handler)));
+        self
+    }
+
+    /// Run a handler at a regular interval, the first time one interval after the first frame.
+    /// It runs at most once per frame: an interval shorter than a frame runs it at each frame,
+    /// and a late run is not repeated to catch up.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Interval between two runs.
+    /// * `handler` - Closure receiving the board.
+    pub fn every<F>(&mut self, interval: Duration, handler: F) -> &mut Self
+    where
+        F: FnMut(&mut RainbowHat) -> Result<(), Error> + Send + 'static,
+    {
+        self.timers.push(Timer {
+            interval,
+            next: None,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Run a handler at each frame, after the button and timer handlers, replacing any previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - Closure receiving the board, for example rendering an animation.
+    pub fn on_frame<F>(&mut self, handler: F) -> &mut Self
+    where
+        F: FnMut(&mut RainbowHat) -> Result<(), Error> + Send + 'static,
+    {
+        self.frame_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// Run one frame: read the buttons and run the handlers of their presses and releases, the timers that are due
+    /// and the frame handler. Then the multicolour LEDs are shown if a handler ran, and the display if it changed.
+    /// Returns the error of the first failing handler, the next handlers of the frame not being run.
+    pub fn step(&mut self) -> Result<(), Error> {
+        let now = self.clock.now();
+        let mut ran = false;
+
+        for (i, &id) in [ButtonId::A, ButtonId::B, ButtonId::C].iter().enumerate() {
+            let pressed = self.hat.buttons.get_mut(id).is_pressed();
+
+            let handlers = match self.edges[i].update(pressed) {
+                Some(TouchEvent::Pressed) => &mut self.press_handlers,
+                Some(TouchEvent::Released) => &mut self.release_handlers,
+                None => continue,
+            };
+            for (_id, handler) in handlers.iter_mut().filter(|(handler_id, _handler)| *handler_id == id) {
+                handler(&mut self.hat)?;
+                ran = true;
+            }
+        }
+
+        for timer in self.timers.iter_mut() {
+            let next = *timer.next.get_or_insert(now + timer.interval);
+            if now >= next {
+                (timer.handler)(&mut self.hat)?;
+                ran = true;
+
+                // Scheduled from the previous run so the timer does not drift, unless it is late
+                let next = next + timer.interval;
+                timer.next = Some(if next > now { next } else { now + timer.interval });
+            }
+        }
+
+        if let Some(handler) = self.frame_handler.as_mut() {
+            handler(&mut self.hat)?;
+            ran = true;
+        }
+
+        if ran {
+            self.hat.apa102.show()?;
+        }
+        self.hat.alphanum.show_if_changed()?;
+
+        Ok(())
+    }
+
+    /// Run the frames at the frame rate until cancelled, or until a handler fails.
+    /// The frames are scheduled from the first one, so the time taken by the handlers does not make the loop drift.
+    ///
+    /// # Arguments
+    ///
+    /// * `cancel` - Set to true to stop, checked before each frame.
+    pub fn run(&mut self, cancel: &AtomicBool) -> Result<(), Error> {
+        let mut next = self.clock.now();
+
+        while !cancel.load(Ordering::SeqCst) {
+            self.step()?;
+
+            // A late frame delays the next ones instead of running several frames in a row
+            next += self.frame_interval;
+            let now = self.clock.now();
+            if next > now {
+                self.clock.sleep(next - now);
+            } else {
+                next = now;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for EventLoop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventLoop")
+            .field("press_handlers", &self.press_handlers.iter().map(|(id, _handler)| id).collect::<Vec<_>>())
+            .field("release_handlers", &self.release_handlers.iter().map(|(id, _handler)| id).collect::<Vec<_>>())
+            .field("timers", &self.timers.iter().map(|timer| timer.interval).collect::<Vec<_>>())
+            .field("frame_handler", &self.frame_handler.is_some())
+            .field("frame_interval", &self.frame_interval)
+            .finish()
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use crate::alphanum4::Alignment;
+    use crate::clock::ManualClock;
+
+    /// Tests scripted presses and releases run the handlers of their button once.
+    #[test]
+    fn test_event_loop_buttons() -> Result<(), Error> {
+        let mut event_loop = EventLoop::with_simulation()?;
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = events.clone();
+        event_loop.on_press(ButtonId::A, move |hat| {
+            recorded.lock().unwrap().push("A pressed");
+            hat.lights.rgb(true, false, false);
+            Ok(())
+        });
+        let recorded = events.clone();
+        event_loop.on_release(ButtonId::A, move |hat| {
+            recorded.lock().unwrap().push("A released");
+            hat.lights.all(false);
+            Ok(())
+        });
+        let recorded = events.clone();
+        event_loop.on_press(ButtonId::C, move |hat| {
+            recorded.lock().unwrap().push("C pressed");
+            hat.alphanum.print_str("C", Alignment::Left)?;
+            Ok(())
+        });
+
+        event_loop.step()?;
+        assert!(events.lock().unwrap().is_empty());
+
+        // Held over several frames, pressed once
+        event_loop.hat_mut().buttons.a.set_simulated_state(true);
+        event_loop.step()?;
+        event_loop.step()?;
+        assert!(event_loop.hat().lights.red.state);
+
+        event_loop.hat_mut().buttons.a.set_simulated_state(false);
+        event_loop.hat_mut().buttons.b.set_simulated_state(true);
+        event_loop.hat_mut().buttons.c.set_simulated_state(true);
+        event_loop.step()?;
+        assert!(!event_loop.hat().lights.red.state);
+        assert!(*events.lock().unwrap() == ["A pressed", "A released", "C pressed"]);
+
+        // The display changed by the handler is shown
+        let hat = event_loop.into_hat();
+        assert!(hat.alphanum.ht16k33.commands().iter().any(|command| matches!(command, crate::ht16k33::Command::WriteRam { .. })));
+
+        Ok(())
+    }
+
+    /// Tests running the loop at the frame rate with a timer and a frame handler, until cancelled by a handler.
+    #[test]
+    fn test_event_loop_run() -> Result<(), Error> {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let mut event_loop = EventLoop::with_simulation()?;
+        event_loop.set_clock(clock.clone());
+        event_loop.set_frame_rate(10)?;
+        assert!(event_loop.frame_interval() == Duration::from_millis(100));
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let ticks = Arc::new(Mutex::new(Vec::new()));
+        let frames = Arc::new(Mutex::new(0));
+
+        let (recorded, timer_clock) = (ticks.clone(), clock.clone());
+        event_loop.every(Duration::from_millis(250), move |_hat| {
+            recorded.lock().unwrap().push(timer_clock.now() - start);
+            Ok(())
+        });
+        let (counted, stop) = (frames.clone(), cancel.clone());
+        event_loop.on_frame(move |hat| {
+            let mut frames = counted.lock().unwrap();
+            *frames += 1;
+            hat.apa102.set_all(0, 0, 255, 1.0)?;
+
+            // Press A on the third frame, and stop after the tenth
+            hat.buttons.a.set_simulated_state(*frames == 3);
+            stop.store(*frames == 10, Ordering::SeqCst);
+            Ok(())
+        });
+        let presses = Arc::new(Mutex::new(0));
+        let counted = presses.clone();
+        event_loop.on_press(ButtonId::A, move |_hat| {
+            *counted.lock().unwrap() += 1;
+            Ok(())
+        });
+
+        event_loop.run(&cancel)?;
+
+        assert!(*frames.lock().unwrap() == 10);
+        assert!(*presses.lock().unwrap() == 1);
+        assert!(clock.now() - start == Duration::from_millis(1000));
+
+        // The timer runs on the first frame after each interval
+        let ticks: Vec<u64> = ticks.lock().unwrap().iter().map(|tick| tick.as_millis() as u64).collect();
+        assert!(ticks == [300, 500, 800]);
+        assert!(event_loop.hat().apa102.last_frame().unwrap().iter().all(|pixel| pixel[1..4] == [255, 0, 0]));
+
+        assert!(matches!(event_loop.set_frame_rate(0), Err(Error::InvalidInput(_))));
+
+        Ok(())
+    }
+
+    /// Tests the error of a handler stops the loop.
+    #[test]
+    fn test_event_loop_handler_error() -> Result<(), Error> {
+        let mut event_loop = EventLoop::with_simulation()?;
+        event_loop.set_clock(ManualClock::new());
+        event_loop.on_frame(|hat| {
+            hat.alphanum.print_str("Été", Alignment::Left)?;
+            Ok(())
+        });
+
+        let cancel = AtomicBool::new(false);
+        assert!(matches!(event_loop.run(&cancel), Err(Error::InvalidInput(_))));
+
+        Ok(())
+    }
+}
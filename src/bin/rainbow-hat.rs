@@ -0,0 +1,217 @@
+use std::env;
+use std::fmt;
+use std::process::ExitCode;
+use std::thread;
+use std::time::Duration;
+use rainbow_hat_rs::Error;
+use rainbow_hat_rs::alphanum4::Alignment;
+use rainbow_hat_rs::buzzer;
+use rainbow_hat_rs::rainbow_hat::RainbowHat;
+
+/// Usage printed with `--help` and after an invalid command.
+const USAGE: &str = "\
+Usage: rainbow-hat [--simulate] <command>
+
+Commands:
+  lights rgb <on|off> <on|off> <on|off>     Turn the red, green and blue lights on or off
+  lights all <on|off>                       Turn every light on or off
+  pixel <0-6> <r> <g> <b> [--brightness <0.0-1.0>]
+                                            Set the color of a multicolour LED
+  display print <text>                      Print up to 4 characters on the display
+  display scroll <text>                     Scroll a text on the display
+  buzzer note <name> <seconds>              Play a note, such as A4 or C#5
+  buzzer rtttl <song>                       Play a song in the RTTTL format
+  sensor read                               Read the temperature and the pressure
+  clear                                     Turn every peripheral off
+
+Options:
+  --simulate                                Run without accessing the hardware
+  -h, --help                                Print this help";
+
+/// Delay between two frames of a scrolling text.
+const SCROLL_DELAY: Duration = Duration::from_millis(250);
+
+/// Error ending the command.
+#[derive(Debug)]
+enum CliError {
+
+    /// Invalid command line, reported with the usage.
+    Usage(String),
+
+    /// Error of the board.
+    Board(Error),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(msg) => write!(f, "{}", &msg),
+            CliError::Board(err) => write!(f, "{}", &err),
+        }
+    }
+}
+
+/// Converts the errors of the board and of each peripheral
+impl<E: Into<Error>> From<E> for CliError {
+    fn from(err: E) -> CliError {
+        CliError::Board(err.into())
+    }
+}
+
+/// Command line tool driving the Rainbow HAT.
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let simulate = args.iter().any(|arg| arg == "--simulate");
+    let args: Vec<&str> = args.iter().map(String::as_str).filter(|&arg| arg != "--simulate").collect();
+
+    match run(&args, simulate) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(CliError::Usage(msg)) => {
+            eprintln!("error: {}\n\n{}", msg, USAGE);
+            ExitCode::from(2)
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Run a command.
+///
+/// # Arguments
+///
+/// * `args` - Command and its arguments.
+/// * `simulate` - Whether to run without accessing the hardware.
+fn run(args: &[&str], simulate: bool) -> Result<(), CliError> {
+    let mut hat = if simulate { RainbowHat::with_simulation()? } else { RainbowHat::new()? };
+
+    match args {
+        ["lights", "rgb", r, g, b] => {
+            let (r, g, b) = (parse_state(r)?, parse_state(g)?, parse_state(b)?);
+            hat.lights.rgb(r, g, b);
+            println!("lights: red {}, green {}, blue {}", state_name(r), state_name(g), state_name(b));
+        }
+        ["lights", "all", state] => {
+            let state = parse_state(state)?;
+            hat.lights.all(state);
+            println!("lights: all {}", state_name(state));
+        }
+        ["pixel", x, r, g, b, options @ ..] => {
+            let brightness = match options {
+                [] => 1.0,
+                ["--brightness", brightness] => parse(brightness, "brightness")?,
+                _ => return Err(CliError::Usage(format!("unexpected arguments: {}", options.join(" ")))),
+            };
+            let x: usize = parse(x, "pixel")?;
+            let (r, g, b) = (parse(r, "red")?, parse(g, "green")?, parse(b, "blue")?);
+
+            hat.apa102.set_pixel(x, r, g, b, brightness)?;
+            hat.apa102.show()?;
+            println!("pixel {}: {} {} {} at brightness {}", x, r, g, b, brightness);
+        }
+        ["display", "print", text] => {
+            let text = parse_text(text)?;
+            if text.chars().count() > hat.alphanum.digit_count() {
+                return Err(CliError::Usage(format!("{:?} is longer than the {} digits of the display, use display scroll", text, hat.alphanum.digit_count())));
+            }
+
+            hat.alphanum.print_str(text, Alignment::Left)?;
+            hat.alphanum.show()?;
+            println!("display: {}", text);
+        }
+        ["display", "scroll", text] => {
+            let text = parse_text(text)?;
+            let length = text.chars().count();
+            let steps = if length <= hat.alphanum.digit_count() { 1 } else { length + hat.alphanum.digit_count() };
+
+            for step in 0..steps {
+                hat.alphanum.scroll_str(text, step)?;
+                hat.alphanum.show()?;
+
+                if !simulate {
+                    thread::sleep(SCROLL_DELAY);
+                }
+            }
+            println!("display: scrolled {} in {} steps", text, steps);
+        }
+        ["buzzer", "note", name, seconds] => {
+            let note_number = buzzer::parse_note_name(name)?;
+            let seconds: f64 = parse(seconds, "duration")?;
+            if seconds.is_nan() || seconds < 0.0 {
+                return Err(CliError::Usage(format!("invalid duration {:?}", seconds)));
+            }
+
+            hat.buzzer.midi_note(note_number, seconds)?;
+            println!("buzzer: {} (MIDI {}) for {} s", name, note_number, seconds);
+        }
+        ["buzzer", "rtttl", song] => {
+            let notes = buzzer::parse_rtttl(song)?;
+            hat.buzzer.play_rtttl(song)?;
+            println!("buzzer: played {} notes", notes.len());
+        }
+        ["sensor", "read"] => {
+            let measurement = hat.bmp280.measure_once()?;
+            println!("temperature: {}", measurement.temperature());
+            println!("pressure: {}", measurement.pressure());
+        }
+        ["clear"] => {
+            hat.stop_all()?;
+            println!("cleared");
+        }
+        [] => return Err(CliError::Usage("missing command".to_string())),
+        _ => return Err(CliError::Usage(format!("unknown command: {}", args.join(" ")))),
+    }
+
+    Ok(())
+}
+
+/// Parse a number.
+///
+/// # Arguments
+///
+/// * `value` - Argument to parse.
+/// * `name` - Name of the argument, for the error message.
+fn parse<T: std::str::FromStr>(value: &str, name: &str) -> Result<T, CliError> {
+    value.parse().map_err(|_| CliError::Usage(format!("invalid {} {:?}", name, value)))
+}
+
+/// Parse the state of a light.
+///
+/// # Arguments
+///
+/// * `value` - on or off.
+fn parse_state(value: &str) -> Result<bool, CliError> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(CliError::Usage(format!("invalid light state {:?}, expected on or off", value))),
+    }
+}
+
+/// Get the name of the state of a light.
+///
+/// # Arguments
+///
+/// * `state` - true for on, false for off.
+fn state_name(state: bool) -> &'static str {
+    if state { "on" } else { "off" }
+}
+
+/// Check a text only has characters the display can print.
+///
+/// # Arguments
+///
+/// * `text` - Text to print.
+fn parse_text(text: &str) -> Result<&str, CliError> {
+    match text.chars().find(|c| !(' '..='~').contains(c)) {
+        Some(c) => Err(CliError::Usage(format!("the display cannot print {:?}", c))),
+        None => Ok(text),
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::rainbow_hat::RainbowHat;
+use crate::touch::{ButtonId, Gesture, GestureDetector};
+
+/// Action run when a gesture is recognized.
+pub type Action = Box<dyn FnMut(&mut RainbowHat) + Send>;
+
+/// Registry of actions bound to the gestures made on the buttons, run by `RainbowHat::run_bindings`.
+/// Send but not Sync, as the actions are only Send.
+pub struct Bindings {
+
+    /// Actions by button and gesture.
+    actions: HashMap<(ButtonId, Gesture), Action>,
+
+    /// Gesture detector of each button: A, B and C.
+    detectors: [GestureDetector; 3],
+}
+
+impl Bindings {
+
+    /// Creates a registry without bindings, recognizing the gestures with the default timings.
+    pub fn new() -> Bindings {
+        Bindings::with_detector(GestureDetector::new())
+    }
+
+    /// Creates a registry without bindings, recognizing the gestures with the given detector.
+    ///
+    /// # Arguments
+    ///
+    /// * `detector` - Detector used for every button, for example with custom timings.
+    pub fn with_detector(detector: GestureDetector) -> Bindings {
+        Self {
+            actions: HashMap::new(),
+            detectors: [detector; 3],
+        }
+    }
+
+    /// Bind an action to a gesture on a button, replacing any previous action.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Identifier of the button.
+    /// * `gesture` - Gesture triggering the action.
+    /// * `action` - Closure receiving the board.
+    pub fn bind<F>(&mut self, id: ButtonId, gesture: Gesture, action: F) -> &mut Self
+    where
+        F: FnMut(&mut RainbowHat) + Send + 'static,
+    {
+        self.actions.insert((id, gesture), Box::new(action));
+        self
+    }
+
+    /// Remove the action bound to a gesture on a button.
+    /// Returns true if an action was bound.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Identifier of the button.
+    /// * `gesture` - Gesture triggering the action.
+    pub fn unbind(&mut self, id: ButtonId, gesture: Gesture) -> bool {
+        self.actions.remove(&(id, gesture)).is_some()
+    }
+
+    /// Get the number of bindings.
+    pub fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    /// Get whether there are no bindings.
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Run the action bound to a gesture on a button.
+    /// Returns true if an action was bound.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board given to the action.
+    /// * `id` - Identifier of the button.
+    /// * `gesture` - Gesture made.
+    pub fn run(&mut self, hat: &mut RainbowHat, id: ButtonId, gesture: Gesture) -> bool {
+        match self.actions.get_mut(&(id, gesture)) {
+            Some(action) => {
+                action(hat);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Get the gesture detector of a button.
+    pub(crate) fn detector_mut(&mut self, id: ButtonId) -> &mut GestureDetector {
+        match id {
+            ButtonId::A => &mut self.detectors[0],
+            ButtonId::B => &mut self.detectors[1],
+            ButtonId::C => &mut self.detectors[2],
+        }
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Bindings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bindings")
+            .field("actions", &self.actions.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use crate::clock::ManualClock;
+    use crate::Error;
+
+    /// Tests running the action bound to a tap on button A.
+    #[test]
+    fn test_bindings_tap() -> Result<(), Error> {
+        let mut hat = RainbowHat::new()?;
+        hat.enable_simulation();
+        let clock = ManualClock::new();
+        hat.set_clock(clock.clone());
+
+        let taps = Arc::new(AtomicUsize::new(0));
+        let counter = taps.clone();
+
+        let mut bindings = Bindings::new();
+        bindings
+            .bind(ButtonId::A, Gesture::Tap, move |hat| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                hat.lights.red.on();
+            })
+            .bind(ButtonId::B, Gesture::LongPress, |hat| hat.lights.blue.on());
+
+        // Tap on button A
+        hat.buttons.a.set_simulated_state(true);
+        assert!(hat.poll_bindings(&mut bindings) == 0);
+        clock.advance(Duration::from_millis(100));
+        hat.buttons.a.set_simulated_state(false);
+        assert!(hat.poll_bindings(&mut bindings) == 1);
+
+        assert!(taps.load(Ordering::SeqCst) == 1);
+        assert!(hat.lights.red.state);
+
+        // Unbound gesture
+        hat.buttons.c.set_simulated_state(true);
+        clock.advance(Duration::from_millis(100));
+        hat.buttons.c.set_simulated_state(false);
+        assert!(hat.poll_bindings(&mut bindings) == 0);
+        assert!(!hat.lights.blue.state);
+
+        Ok(())
+    }
+
+    /// Tests the loop runs the bound actions until the stop condition is met.
+    #[test]
+    fn test_run_bindings_stop() -> Result<(), Error> {
+        let mut hat = RainbowHat::new()?;
+        hat.enable_simulation();
+
+        let mut bindings = Bindings::new();
+        bindings.bind(ButtonId::A, Gesture::Tap, |hat| hat.lights.green.on());
+
+        // Scripted tap on the first readings, stopping once the action ran
+        let mut iteration = 0;
+        hat.run_bindings(&mut bindings, Duration::from_millis(1), |hat| {
+            iteration += 1;
+            hat.buttons.a.set_simulated_state(iteration == 1);
+            hat.lights.green.state || iteration > 100
+        });
+
+        assert!(hat.lights.green.state);
+
+        Ok(())
+    }
+}
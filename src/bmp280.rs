@@ -0,0 +1,1912 @@
+use std::collections::VecDeque;
+use std::error;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+#[cfg(feature = "hardware")]
+use rppal::i2c::I2c;
+#[cfg(feature = "hardware")]
+use rppal::spi::{Bus, SlaveSelect, Spi};
+#[cfg(feature = "hardware")]
+use crate::hal::{self, Backend, Rppal};
+use crate::rainbow_hat::Component;
+use crate::recorder::{MemoryRecorder, Payload, Recorder};
+use crate::units::{Pressure, Temperature};
+use crate::trace::Tracer;
+
+pub const DEFAULT_ADDRESS: u16 = 0x77;
+pub const SECONDARY_ADDRESS: u16 = 0x76;
+pub const BMP280_CHIP_ID: u8 = 0x58;
+pub const BMP280_REG_CALIBRATION: u8 = 0x88;
+pub const BMP280_REG_CHIP_ID: u8 = 0xD0;
+pub const BMP280_REG_RESET: u8 = 0xE0;
+pub const BMP280_REG_STATUS: u8 = 0xF3;
+pub const BMP280_REG_CTRL_MEAS: u8 = 0xF4;
+pub const BMP280_REG_CONFIG: u8 = 0xF5;
+pub const BMP280_REG_DATA: u8 = 0xF7;
+
+pub const BMP280_STATUS_MEASURING: u8 = 0x08;
+pub const BMP280_STATUS_IM_UPDATE: u8 = 0x01;
+
+/// Value written to the reset register to reset the sensor.
+pub const BMP280_RESET_VALUE: u8 = 0xB6;
+
+/// Maximum duration of the copy of the calibration after a reset, with a margin over the start-up time of the datasheet.
+pub const RESET_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// Interval between two readings of the status register while waiting for a conversion.
+pub const STATUS_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Clock speed of the SPI bus, in hertz.
+pub const SPI_CLOCK_SPEED: u32 = 1_000_000;
+
+/// Bit of the register address set to read in SPI mode. It is cleared to write.
+pub const SPI_READ_BIT: u8 = 0x80;
+
+/// Number of bytes of the calibration registers.
+pub const CALIBRATION_SIZE: usize = 24;
+
+/// Power mode of the sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Mode {
+
+    /// No measurement, lowest consumption.
+    Sleep,
+
+    /// Single measurement, then back to sleep.
+    Forced,
+
+    /// Continuous measurements separated by the standby time.
+    Normal,
+}
+
+impl Mode {
+
+    /// Get the mode bits of the ctrl_meas register.
+    pub fn bits(self) -> u8 {
+        match self {
+            Mode::Sleep => 0b00,
+            Mode::Forced => 0b01,
+            Mode::Normal => 0b11,
+        }
+    }
+}
+
+/// Oversampling of a measurement: number of samples averaged, reducing the noise
+/// at the cost of a longer conversion time and higher consumption.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Oversampling {
+    X1,
+    X2,
+    X4,
+    X8,
+    X16,
+}
+
+impl Oversampling {
+
+    /// Get the osrs_t or osrs_p bits of the ctrl_meas register.
+    pub fn bits(self) -> u8 {
+        match self {
+            Oversampling::X1 => 0b001,
+            Oversampling::X2 => 0b010,
+            Oversampling::X4 => 0b011,
+            Oversampling::X8 => 0b100,
+            Oversampling::X16 => 0b101,
+        }
+    }
+
+    /// Get the number of samples averaged.
+    pub fn samples(self) -> u32 {
+        match self {
+            Oversampling::X1 => 1,
+            Oversampling::X2 => 2,
+            Oversampling::X4 => 4,
+            Oversampling::X8 => 8,
+            Oversampling::X16 => 16,
+        }
+    }
+}
+
+/// Coefficient of the IIR filter smoothing the measurements, for example against door slams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IirFilter {
+    Off,
+    X2,
+    X4,
+    X8,
+    X16,
+}
+
+impl IirFilter {
+
+    /// Get the filter bits of the config register.
+    pub fn bits(self) -> u8 {
+        match self {
+            IirFilter::Off => 0b000,
+            IirFilter::X2 => 0b001,
+            IirFilter::X4 => 0b010,
+            IirFilter::X8 => 0b011,
+            IirFilter::X16 => 0b100,
+        }
+    }
+}
+
+/// Inactive time between two measurements in normal mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StandbyTime {
+    Ms0_5,
+    Ms62_5,
+    Ms125,
+    Ms250,
+    Ms500,
+    Ms1000,
+    Ms2000,
+    Ms4000,
+}
+
+impl StandbyTime {
+
+    /// Get the t_sb bits of the config register.
+    pub fn bits(self) -> u8 {
+        match self {
+            StandbyTime::Ms0_5 => 0b000,
+            StandbyTime::Ms62_5 => 0b001,
+            StandbyTime::Ms125 => 0b010,
+            StandbyTime::Ms250 => 0b011,
+            StandbyTime::Ms500 => 0b100,
+            StandbyTime::Ms1000 => 0b101,
+            StandbyTime::Ms2000 => 0b110,
+            StandbyTime::Ms4000 => 0b111,
+        }
+    }
+}
+
+/// Measurement configuration of the sensor, written to the ctrl_meas and config registers.
+/// The pressure can be skipped, but the temperature is always measured as the pressure compensation needs it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Configuration {
+
+    /// oversampling of the temperature
+    pub temperature: Oversampling,
+
+    /// oversampling of the pressure, None to skip the pressure measurement
+    pub pressure: Option<Oversampling>,
+
+    /// coefficient of the IIR filter
+    pub filter: IirFilter,
+
+    /// standby time between measurements in normal mode
+    pub standby_time: StandbyTime,
+
+    /// power mode
+    pub mode: Mode,
+}
+
+impl Configuration {
+
+    /// Encodes the ctrl_meas register.
+    pub fn ctrl_meas(&self) -> u8 {
+        let pressure = self.pressure.map_or(0, Oversampling::bits);
+        (self.temperature.bits() << 5) | (pressure << 2) | self.mode.bits()
+    }
+
+    /// Get the maximum duration of a measurement with the oversampling, from the datasheet.
+    pub fn max_measurement_time(&self) -> Duration {
+        let pressure = self.pressure.map_or(0.0, |p| 2.3 * p.samples() as f32 + 0.575);
+        let millis = 1.25 + 2.3 * self.temperature.samples() as f32 + pressure;
+        Duration::from_micros((millis * 1000.0).ceil() as u64)
+    }
+
+    /// Encodes the config register.
+    pub fn config(&self) -> u8 {
+        (self.standby_time.bits() << 5) | (self.filter.bits() << 2)
+    }
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        Self {
+            temperature: Oversampling::X1,
+            pressure: Some(Oversampling::X1),
+            filter: IirFilter::Off,
+            standby_time: StandbyTime::Ms1000,
+            mode: Mode::Normal,
+        }
+    }
+}
+
+/// Factory calibration of the sensor, used to compensate the raw measurements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Calibration {
+    pub dig_t1: u16,
+    pub dig_t2: i16,
+    pub dig_t3: i16,
+    pub dig_p1: u16,
+    pub dig_p2: i16,
+    pub dig_p3: i16,
+    pub dig_p4: i16,
+    pub dig_p5: i16,
+    pub dig_p6: i16,
+    pub dig_p7: i16,
+    pub dig_p8: i16,
+    pub dig_p9: i16,
+}
+
+impl Calibration {
+
+    /// Decodes the calibration registers, read from 0x88 to 0x9F. Values are little endian.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Content of the calibration registers.
+    pub fn from_bytes(data: &[u8; CALIBRATION_SIZE]) -> Calibration {
+        let unsigned = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+        let signed = |i: usize| i16::from_le_bytes([data[i], data[i + 1]]);
+
+        Self {
+            dig_t1: unsigned(0),
+            dig_t2: signed(2),
+            dig_t3: signed(4),
+            dig_p1: unsigned(6),
+            dig_p2: signed(8),
+            dig_p3: signed(10),
+            dig_p4: signed(12),
+            dig_p5: signed(14),
+            dig_p6: signed(16),
+            dig_p7: signed(18),
+            dig_p8: signed(20),
+            dig_p9: signed(22),
+        }
+    }
+
+    /// Computes the fine temperature used by the pressure compensation, as described in the datasheet.
+    ///
+    /// # Arguments
+    ///
+    /// * `adc_t` - Raw temperature measurement.
+    fn t_fine(&self, adc_t: i32) -> f64 {
+        let adc_t = adc_t as f64;
+        let t1 = self.dig_t1 as f64;
+
+        let var1 = (adc_t / 16384.0 - t1 / 1024.0) * self.dig_t2 as f64;
+        let var2 = (adc_t / 131072.0 - t1 / 8192.0) * (adc_t / 131072.0 - t1 / 8192.0) * self.dig_t3 as f64;
+
+        var1 + var2
+    }
+
+    /// Computes the temperature in degrees Celsius from the raw measurement.
+    ///
+    /// # Arguments
+    ///
+    /// * `adc_t` - Raw temperature measurement.
+    pub fn compensate_temperature(&self, adc_t: i32) -> f64 {
+        self.t_fine(adc_t) / 5120.0
+    }
+
+    /// Computes the pressure in Pascal from the raw measurements, as described in the datasheet.
+    ///
+    /// # Arguments
+    ///
+    /// * `adc_t` - Raw temperature measurement.
+    /// * `adc_p` - Raw pressure measurement.
+    pub fn compensate_pressure(&self, adc_t: i32, adc_p: i32) -> f64 {
+        let t_fine = self.t_fine(adc_t);
+
+        let mut var1 = t_fine / 2.0 - 64000.0;
+        let mut var2 = var1 * var1 * self.dig_p6 as f64 / 32768.0;
+        var2 += var1 * self.dig_p5 as f64 * 2.0;
+        var2 = var2 / 4.0 + self.dig_p4 as f64 * 65536.0;
+        var1 = (self.dig_p3 as f64 * var1 * var1 / 524288.0 + self.dig_p2 as f64 * var1) / 524288.0;
+        var1 = (1.0 + var1 / 32768.0) * self.dig_p1 as f64;
+
+        // Avoid a division by zero
+        if var1 == 0.0 {
+            return 0.0;
+        }
+
+        let mut p = 1048576.0 - adc_p as f64;
+        p = (p - var2 / 4096.0) * 6250.0 / var1;
+        var1 = self.dig_p9 as f64 * p * p / 2147483648.0;
+        var2 = p * self.dig_p8 as f64 / 32768.0;
+
+        p + (var1 + var2 + self.dig_p7 as f64) / 16.0
+    }
+}
+
+/// Compensated measurement of the sensor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+
+    /// Temperature, in degrees Celsius.
+    pub temperature_c: f32,
+
+    /// Pressure, in hectopascal.
+    pub pressure_hpa: f32,
+
+    /// Instant at which the measurement was read.
+    pub timestamp: Instant,
+}
+
+impl Measurement {
+
+    /// Get the temperature.
+    pub fn temperature(&self) -> Temperature {
+        Temperature::from_celsius(self.temperature_c)
+    }
+
+    /// Get the pressure.
+    pub fn pressure(&self) -> Pressure {
+        Pressure::from_hpa(self.pressure_hpa)
+    }
+}
+
+/// Content of the status register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Status {
+
+    /// A conversion is running.
+    pub measuring: bool,
+
+    /// The calibration is being copied to the image registers, after a reset or power on.
+    pub im_update: bool,
+}
+
+impl Status {
+
+    /// Decodes the status register.
+    pub fn from_bits(bits: u8) -> Status {
+        Status {
+            measuring: bits & BMP280_STATUS_MEASURING != 0,
+            im_update: bits & BMP280_STATUS_IM_UPDATE != 0,
+        }
+    }
+}
+
+/// Settings of the driver that can be saved and restored: the measurement configuration and the temperature offset.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Settings {
+
+    /// measurement configuration
+    pub configuration: Configuration,
+
+    /// offset added to the temperatures, in degrees Celsius
+    pub temperature_offset: f32,
+}
+
+/// Access to the registers of the sensor over a bus.
+pub trait Transport: Send + fmt::Debug {
+
+    /// Read consecutive registers.
+    ///
+    /// # Arguments
+    ///
+    /// * `register` - Address of the first register.
+    /// * `buffer` - Receives the values.
+    fn read(&mut self, register: u8, buffer: &mut [u8]) -> Result <(), Error>;
+
+    /// Write a register.
+    ///
+    /// # Arguments
+    ///
+    /// * `register` - Address of the register.
+    /// * `value` - Value to write.
+    fn write(&mut self, register: u8, value: u8) -> Result <(), Error>;
+}
+
+/// Bus used by the I2C transport, implemented by the I2C of rppal.
+pub trait I2cBus: Send + fmt::Debug {
+
+    /// Write bytes to the slave.
+    fn write(&mut self, buffer: &[u8]) -> Result <(), Error>;
+
+    /// Write bytes to the slave then read its answer.
+    fn write_read(&mut self, write_buffer: &[u8], read_buffer: &mut [u8]) -> Result <(), Error>;
+}
+
+#[cfg(feature = "hardware")]
+impl I2cBus for I2c {
+    fn write(&mut self, buffer: &[u8]) -> Result <(), Error> {
+        I2c::write(self, buffer).map(drop).map_err(|source| Error::I2c { bus: Some(self.bus()), address: None, source })
+    }
+
+    fn write_read(&mut self, write_buffer: &[u8], read_buffer: &mut [u8]) -> Result <(), Error> {
+        I2c::write_read(self, write_buffer, read_buffer).map_err(|source| Error::I2c { bus: Some(self.bus()), address: None, source })
+    }
+}
+
+/// Bus used by the SPI transport, implemented by the SPI of rppal.
+pub trait SpiBus: Send + fmt::Debug {
+
+    /// Write bytes while reading the same number of bytes.
+    fn transfer(&mut self, read_buffer: &mut [u8], write_buffer: &[u8]) -> Result <(), Error>;
+}
+
+#[cfg(feature = "hardware")]
+impl SpiBus for Spi {
+    fn transfer(&mut self, read_buffer: &mut [u8], write_buffer: &[u8]) -> Result <(), Error> {
+        Spi::transfer(self, read_buffer, write_buffer)?;
+        Ok(())
+    }
+}
+
+/// I2C transport: the address of the register is written, then the values are read or written after it.
+/// Defaults to the bus of the Raspberry Pi with the `hardware` feature.
+#[cfg(feature = "hardware")]
+#[derive(Debug)]
+pub struct I2cTransport<B: I2cBus = I2c> {
+
+    /// bus of the sensor
+    bus: B,
+
+    /// address of the sensor named by the errors, if known
+    address: Option<u16>,
+}
+
+/// I2C transport: the address of the register is written, then the values are read or written after it.
+#[cfg(not(feature = "hardware"))]
+#[derive(Debug)]
+pub struct I2cTransport<B: I2cBus> {
+
+    /// bus of the sensor
+    bus: B,
+
+    /// address of the sensor named by the errors, if known
+    address: Option<u16>,
+}
+
+#[cfg(feature = "hardware")]
+impl I2cTransport {
+
+    /// Opens the I2C bus of the Raspberry Pi.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the sensor: 0x76 or 0x77.
+    pub fn open(address: u16) -> Result <I2cTransport, Error> {
+        I2cTransport::open_bus(None, address)
+    }
+
+    /// Opens an I2C bus of the Raspberry Pi.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - I2C bus number, None for the default bus.
+    /// * `address` - Address of the sensor: 0x76 or 0x77.
+    pub fn open_bus(bus: Option<u8>, address: u16) -> Result <I2cTransport, Error> {
+        I2cTransport::open_backend(&Rppal, bus, address)
+    }
+
+    /// Opens an I2C bus with the backend.
+    fn open_backend(backend: &dyn Backend, bus: Option<u8>, address: u16) -> Result <I2cTransport, Error> {
+        let context = |source| Error::I2c { bus, address: Some(address), source };
+        let mut i2c = backend.i2c(bus).map_err(context)?;
+
+        // Set the I2C slave address to the device we're communicating with.
+        i2c.set_slave_address(address).map_err(context)?;
+
+        Ok(I2cTransport { bus: i2c, address: Some(address) })
+    }
+}
+
+impl<B: I2cBus> I2cTransport<B> {
+
+    /// Creates a transport over a bus.
+    pub fn new(bus: B) -> I2cTransport<B> {
+        Self { bus, address: None }
+    }
+}
+
+impl<B: I2cBus> Transport for I2cTransport<B> {
+    fn read(&mut self, register: u8, buffer: &mut [u8]) -> Result <(), Error> {
+        let address = self.address;
+        self.bus.write_read(&[register], buffer).map_err(|err| err.at_address(address))
+    }
+
+    fn write(&mut self, register: u8, value: u8) -> Result <(), Error> {
+        let address = self.address;
+        self.bus.write(&[register, value]).map_err(|err| err.at_address(address))
+    }
+}
+
+/// SPI transport: the most significant bit of the address of the register is replaced by the read bit.
+/// Defaults to the bus of the Raspberry Pi with the `hardware` feature.
+#[cfg(feature = "hardware")]
+#[derive(Debug)]
+pub struct SpiTransport<B: SpiBus = Spi> {
+
+    /// bus of the sensor
+    bus: B,
+}
+
+/// SPI transport: the most significant bit of the address of the register is replaced by the read bit.
+#[cfg(not(feature = "hardware"))]
+#[derive(Debug)]
+pub struct SpiTransport<B: SpiBus> {
+
+    /// bus of the sensor
+    bus: B,
+}
+
+#[cfg(feature = "hardware")]
+impl SpiTransport {
+
+    /// Opens an SPI bus of the Raspberry Pi, in mode 0.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - SPI bus.
+    /// * `slave_select` - Slave select pin the sensor is wired to.
+    pub fn open(bus: Bus, slave_select: SlaveSelect) -> Result <SpiTransport, Error> {
+        let spi = Spi::new(bus, slave_select, SPI_CLOCK_SPEED, rppal::spi::Mode::Mode0)?;
+        Ok(SpiTransport::new(spi))
+    }
+}
+
+impl<B: SpiBus> SpiTransport<B> {
+
+    /// Creates a transport over a bus.
+    pub fn new(bus: B) -> SpiTransport<B> {
+        Self { bus }
+    }
+}
+
+impl<B: SpiBus> Transport for SpiTransport<B> {
+    fn read(&mut self, register: u8, buffer: &mut [u8]) -> Result <(), Error> {
+
+        // The values are clocked out after the address
+        let mut write_buffer = vec![0u8; buffer.len() + 1];
+        write_buffer[0] = register | SPI_READ_BIT;
+        let mut read_buffer = vec![0u8; buffer.len() + 1];
+
+        self.bus.transfer(&mut read_buffer, &write_buffer)?;
+        buffer.copy_from_slice(&read_buffer[1..]);
+
+        Ok(())
+    }
+
+    fn write(&mut self, register: u8, value: u8) -> Result <(), Error> {
+        let mut read_buffer = [0u8; 2];
+        self.bus.transfer(&mut read_buffer, &[register & !SPI_READ_BIT, value])
+    }
+}
+
+/// Bus the sensor is wired to, opened on setup.
+#[derive(Debug, Clone, Copy)]
+enum Interface {
+
+    /// I2C bus, None for the default one, with the address of the sensor.
+    I2c(Option<u8>, u16),
+
+    /// SPI bus, with the slave select pin of the sensor.
+    #[cfg(feature = "hardware")]
+    Spi(Bus, SlaveSelect),
+}
+
+impl Interface {
+
+    /// Opens the bus of the Raspberry Pi.
+    #[cfg(feature = "hardware")]
+    fn open(self, backend: &dyn Backend) -> Result <Box<dyn Transport>, Error> {
+        Ok(match self {
+            Interface::I2c(bus, address) => Box::new(I2cTransport::open_backend(backend, bus, address)?),
+            Interface::Spi(bus, slave_select) => Box::new(SpiTransport::open(bus, slave_select)?),
+        })
+    }
+
+    /// The buses of the Raspberry Pi need the `hardware` feature.
+    #[cfg(not(feature = "hardware"))]
+    fn open(self) -> Result <Box<dyn Transport>, Error> {
+        match self {
+            Interface::I2c(_bus, address) => Err(Error::Unsupported(format!("I2C bus at 0x{:02X} without the hardware feature", address))),
+        }
+    }
+}
+
+/// Driver for the Bosch BMP280 temperature and pressure sensor.
+/// Send but not Sync, as its transport and trace hook are only Send: sample it from another thread with `start_sampling`.
+#[derive(Debug)]
+pub struct BMP280 {
+
+    /// bus opened on setup, None for a transport given to `with_transport`
+    interface: Option<Interface>,
+
+    /// Register access. Optional as not used in simulated mode.
+    transport: Option<Box<dyn Transport>>,
+
+    /// factory calibration, read on setup
+    calibration: Calibration,
+
+    /// measurement configuration, applied on setup
+    configuration: Configuration,
+
+    /// offset added to the returned temperatures, in degrees Celsius
+    temperature_offset: f32,
+
+    /// calibration read on setup in simulation mode
+    simulated_calibration: Calibration,
+
+    /// raw temperature and pressure measurements returned in simulation mode
+    simulated_adc: (i32, i32),
+
+    /// readings returned in simulation mode before falling back to `simulated_adc`
+    simulated_readings: VecDeque<Result<(i32, i32), Error>>,
+
+    /// number of status readings reporting a conversion in progress after a forced measurement in simulation mode
+    simulated_conversion_polls: u32,
+
+    /// number of status readings left before the simulated conversion is done
+    conversion_polls_left: u32,
+
+    /// recorder of the registers and values written in simulation mode
+    recorder: Box<dyn Recorder>,
+
+    /// hook receiving a message before each hardware operation
+    tracer: Tracer,
+
+    /// backend opening the peripherals on setup
+    #[cfg(feature = "hardware")]
+    backend: Box<dyn Backend>,
+
+    /// whether to switch to simulation mode on setup when the hardware is absent
+    auto_simulate: bool,
+
+    /// In simulation mode, no interaction with the hardware is done to simplify testability.
+    simulation: bool,
+
+    /// is the setup completed
+    is_setup: bool,
+}
+
+impl BMP280 {
+
+    /// Create a BMP280 driver for device.
+    /// Uses the I2C address of the Rainbow HAT sensor (0x77).
+    pub fn new() -> Result<BMP280, Error> {
+        BMP280::new_i2c(DEFAULT_ADDRESS)
+    }
+
+    /// Create a BMP280 driver for a sensor on the I2C bus.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the sensor: 0x76 or 0x77.
+    pub fn new_i2c(address: u16) -> Result<BMP280, Error> {
+        BMP280::new_i2c_bus(None, address)
+    }
+
+    /// Create a BMP280 driver for a sensor on an I2C bus of the Raspberry Pi.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - I2C bus number, None for the default bus.
+    /// * `address` - Address of the sensor: 0x76 or 0x77.
+    pub fn new_i2c_bus(bus: Option<u8>, address: u16) -> Result<BMP280, Error> {
+        Ok(BMP280::with_interface(Some(Interface::I2c(bus, address)), None))
+    }
+
+    /// Create a BMP280 driver for a sensor on an SPI bus, such as an external breakout.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - SPI bus.
+    /// * `slave_select` - Slave select pin the sensor is wired to.
+    #[cfg(feature = "hardware")]
+    pub fn new_spi(bus: Bus, slave_select: SlaveSelect) -> Result<BMP280, Error> {
+        Ok(BMP280::with_interface(Some(Interface::Spi(bus, slave_select)), None))
+    }
+
+    /// Create a BMP280 driver accessing the registers through a transport.
+    ///
+    /// # Arguments
+    ///
+    /// * `transport` - Register access, for example an `I2cTransport` over another bus.
+    pub fn with_transport<T: Transport + 'static>(transport: T) -> BMP280 {
+        let mut bmp280 = BMP280::with_interface(None, Some(Box::new(transport)));
+        bmp280.simulation = false;
+        bmp280
+    }
+
+    /// Creates the driver.
+    fn with_interface(interface: Option<Interface>, transport: Option<Box<dyn Transport>>) -> BMP280 {
+
+        Self {
+            interface,
+            transport,
+            calibration: Calibration::default(),
+            configuration: Configuration::default(),
+            temperature_offset: 0.0,
+            simulated_calibration: Calibration::default(),
+            simulated_adc: (0, 0),
+            simulated_readings: VecDeque::new(),
+            simulated_conversion_polls: 0,
+            conversion_polls_left: 0,
+            recorder: Box::new(MemoryRecorder::new()),
+            tracer: Tracer::new(),
+            #[cfg(feature = "hardware")]
+            backend: Box::new(Rppal),
+            auto_simulate: false,
+            simulation: !cfg!(feature = "hardware"),
+            is_setup: false,
+        }
+    }
+
+    /// Create a BMP280 driver in simulation mode, never accessing the hardware.
+    /// Use `simulate` to choose the raw measurements.
+    pub fn with_simulation() -> Result<BMP280, Error> {
+        let mut bmp280 = BMP280::new()?;
+        bmp280.simulation = true;
+        Ok(bmp280)
+    }
+
+    /// Enables simulation mode: no interaction with the hardware is done.
+    /// Enable it before the first operation, which initializes the hardware.
+    pub fn enable_simulation(&mut self) {
+        self.simulation = true;
+    }
+
+    /// Get whether simulation mode is enabled.
+    pub fn is_simulation(&self) -> bool {
+        self.simulation
+    }
+
+    /// Get the I2C address of the sensor, None when it is on an SPI bus or accessed through a transport.
+    pub fn address(&self) -> Option<u16> {
+        match self.interface {
+            Some(Interface::I2c(_bus, address)) if self.transport.is_none() => Some(address),
+            _ => None,
+        }
+    }
+
+    /// Switch to simulation mode on setup when the hardware is absent, for example when running off the Raspberry Pi,
+    /// instead of returning an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to fall back to simulation mode.
+    pub fn set_auto_simulate(&mut self, enabled: bool) {
+        self.auto_simulate = enabled;
+    }
+
+    /// Get whether the driver switches to simulation mode on setup when the hardware is absent.
+    pub fn is_auto_simulate(&self) -> bool {
+        self.auto_simulate
+    }
+
+    /// Set the backend opening the I2C bus on setup.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Backend to use.
+    #[cfg(feature = "hardware")]
+    pub fn set_backend<B: Backend + 'static>(&mut self, backend: B) {
+        self.backend = Box::new(backend);
+    }
+
+    /// Set a hook receiving a message before each hardware operation, also in simulation mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Closure receiving each message.
+    pub fn set_trace<F: FnMut(&str) + Send + 'static>(&mut self, hook: F) {
+        self.tracer.set(hook);
+    }
+
+    /// Remove the trace hook.
+    pub fn clear_trace(&mut self) {
+        self.tracer.clear();
+    }
+
+    /// Switch to simulation mode, compensating the given raw measurements instead of reading the sensor.
+    ///
+    /// # Arguments
+    ///
+    /// * `calibration` - Calibration of the simulated sensor.
+    /// * `adc_t` - Raw temperature measurement.
+    /// * `adc_p` - Raw pressure measurement.
+    pub fn simulate(&mut self, calibration: Calibration, adc_t: i32, adc_p: i32) {
+        self.simulation = true;
+        self.calibration = calibration;
+        self.simulated_calibration = calibration;
+        self.simulated_adc = (adc_t, adc_p);
+    }
+
+    /// Opens the bus of the sensor, None when switching to simulation mode as the hardware is absent.
+    #[cfg(feature = "hardware")]
+    fn open(&mut self, interface: Interface) -> Result <Option<Box<dyn Transport>>, Error> {
+        match interface.open(self.backend.as_ref()) {
+            Ok(transport) => Ok(Some(transport)),
+            Err(err) if self.auto_simulate && err.is_hardware_absent() => {
+                self.tracer.trace(|| format!("BMP280: hardware unavailable ({}), simulating", err));
+                self.simulation = true;
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Opens the bus of the sensor.
+    #[cfg(not(feature = "hardware"))]
+    fn open(&mut self, interface: Interface) -> Result <Option<Box<dyn Transport>>, Error> {
+        interface.open().map(Some)
+    }
+
+    /// Initialize driver: verifies the chip ID, reads the calibration and starts the measurements.
+    /// Operations reading the sensor call it lazily.
+    pub fn setup(&mut self) -> Result <(), Error> {
+
+        if !self.is_setup {
+
+            // A transport given to the driver is kept, the bus is opened otherwise
+            if let (false, None, Some(interface)) = (self.simulation, &self.transport, self.interface) {
+                self.transport = self.open(interface)?;
+            }
+
+            if !self.simulation {
+                let transport = self.transport.as_mut().unwrap();
+
+                let mut chip_id = [0u8; 1];
+                transport.read(BMP280_REG_CHIP_ID, &mut chip_id)?;
+                if chip_id[0] != BMP280_CHIP_ID {
+                    return Err(Error::ChipId(chip_id[0]));
+                }
+
+                let mut calibration = [0u8; CALIBRATION_SIZE];
+                transport.read(BMP280_REG_CALIBRATION, &mut calibration)?;
+                self.calibration = Calibration::from_bytes(&calibration);
+            } else {
+                self.calibration = self.simulated_calibration;
+            }
+
+            // The config register is written first: writes to it may be ignored in normal mode
+            self.write_register(BMP280_REG_CONFIG, self.configuration.config())?;
+            self.write_register(BMP280_REG_CTRL_MEAS, self.configuration.ctrl_meas())?;
+
+            self.is_setup = true;
+        }
+
+        Ok(())
+    }
+
+    /// Initialize the driver again, for example after a power loss of the sensor.
+    /// The measurement configuration is applied again.
+    pub fn reinitialize(&mut self) -> Result <(), Error> {
+        self.is_setup = false;
+
+        // A transport given to the driver can't be opened again
+        if self.interface.is_some() {
+            self.transport = None;
+        }
+        self.setup()
+    }
+
+    /// Release the I2C or SPI bus: its handle is dropped now rather than when the driver is dropped.
+    /// The driver is set up again on its next reading. A transport given with `with_transport` is kept,
+    /// as it can't be opened again.
+    pub fn release(&mut self) {
+        if self.interface.is_some() {
+            self.transport = None;
+        }
+        self.is_setup = false;
+    }
+
+    /// Writes a register, or records the write in simulation mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `register` - Address of the register.
+    /// * `value` - Value to write.
+    fn write_register(&mut self, register: u8, value: u8) -> Result <(), Error> {
+
+        self.tracer.trace(|| format!("BMP280 write 0x{:02X}: 0x{:02X}", register, value));
+
+        if !self.simulation {
+            self.transport.as_mut().unwrap().write(register, value)?;
+        } else {
+            self.recorder.record(Component::Sensor, "write", Payload::Write { register, data: vec![value] });
+        }
+
+        Ok(())
+    }
+
+    /// Writes a register of the configuration, or applies the whole configuration if the driver is not setup yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `register` - Address of the register.
+    /// * `value` - Value to write.
+    fn apply_register(&mut self, register: u8, value: u8) -> Result <(), Error> {
+        if self.is_setup {
+            self.write_register(register, value)
+        } else {
+            self.setup()
+        }
+    }
+
+    /// Set the oversampling of the measurements.
+    ///
+    /// # Arguments
+    ///
+    /// * `temperature` - Oversampling of the temperature.
+    /// * `pressure` - Oversampling of the pressure, None to skip the pressure measurement.
+    pub fn set_oversampling(&mut self, temperature: Oversampling, pressure: Option<Oversampling>) -> Result <(), Error> {
+        self.configuration.temperature = temperature;
+        self.configuration.pressure = pressure;
+        self.apply_register(BMP280_REG_CTRL_MEAS, self.configuration.ctrl_meas())
+    }
+
+    /// Set the coefficient of the IIR filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Filter coefficient.
+    pub fn set_filter(&mut self, filter: IirFilter) -> Result <(), Error> {
+        self.configuration.filter = filter;
+        self.apply_register(BMP280_REG_CONFIG, self.configuration.config())
+    }
+
+    /// Set the standby time between measurements in normal mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `standby_time` - Standby time.
+    pub fn set_standby_time(&mut self, standby_time: StandbyTime) -> Result <(), Error> {
+        self.configuration.standby_time = standby_time;
+        self.apply_register(BMP280_REG_CONFIG, self.configuration.config())
+    }
+
+    /// Set the power mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - Power mode.
+    pub fn set_mode(&mut self, mode: Mode) -> Result <(), Error> {
+        self.configuration.mode = mode;
+        self.apply_register(BMP280_REG_CTRL_MEAS, self.configuration.ctrl_meas())?;
+
+        if self.simulation && mode == Mode::Forced {
+            self.conversion_polls_left = self.simulated_conversion_polls;
+        }
+
+        Ok(())
+    }
+
+    /// Trigger a single measurement and read it once the conversion is done.
+    /// The sensor goes back to sleep mode afterwards, so it only consumes power when a reading is asked.
+    pub fn measure_once(&mut self) -> Result <Measurement, Error> {
+        self.set_mode(Mode::Forced)?;
+
+        // The sensor returns to sleep by itself after the conversion
+        self.configuration.mode = Mode::Sleep;
+
+        // Leave a margin over the maximum conversion time of the datasheet
+        let timeout = self.configuration.max_measurement_time() * 2;
+        let start = Instant::now();
+
+        while self.read_status()?.measuring {
+            if start.elapsed() > timeout {
+                return Err(Error::Timeout(timeout));
+            }
+            thread::sleep(STATUS_POLL_INTERVAL);
+        }
+
+        self.read_measurement()
+    }
+
+    /// Trigger a single measurement from an async task, polling the status with the timer of the runtime.
+    /// Dropping the future is safe: the sensor completes the conversion and returns to sleep by itself.
+    #[cfg(feature = "async")]
+    pub async fn measure_once_async(&mut self) -> Result <Measurement, Error> {
+        self.set_mode(Mode::Forced)?;
+
+        // The sensor returns to sleep by itself after the conversion
+        self.configuration.mode = Mode::Sleep;
+
+        // Leave a margin over the maximum conversion time of the datasheet
+        let timeout = self.configuration.max_measurement_time() * 2;
+        let start = Instant::now();
+
+        while self.read_status()?.measuring {
+            if start.elapsed() > timeout {
+                return Err(Error::Timeout(timeout));
+            }
+            tokio::time::sleep(STATUS_POLL_INTERVAL).await;
+        }
+
+        self.read_measurement()
+    }
+
+    /// Read the temperature and the pressure of the last conversion together.
+    pub fn read_measurement(&mut self) -> Result <Measurement, Error> {
+        let (adc_t, adc_p) = self.read_raw()?;
+
+        Ok(Measurement {
+            temperature_c: self.compensate_temperature(adc_t),
+            pressure_hpa: (self.calibration.compensate_pressure(adc_t, adc_p) / 100.0) as f32,
+            timestamp: Instant::now(),
+        })
+    }
+
+    /// Move the sensor to a thread reading a measurement at each interval and sending it down a channel,
+    /// so a UI thread is never blocked on I2C. The first measurement is read immediately.
+    /// Failed readings are sent as errors and the sampling goes on.
+    /// The sensor is given back when the sampling is stopped.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Interval between two measurements.
+    pub fn start_sampling(mut self, interval: Duration) -> Result <SamplerHandle, Error> {
+        self.setup()?;
+
+        let (sender, receiver) = mpsc::channel();
+        let (control, control_receiver) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            let mut interval = interval;
+            let mut deadline = Instant::now();
+
+            loop {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+
+                match control_receiver.recv_timeout(timeout) {
+                    Err(RecvTimeoutError::Timeout) => {
+
+                        // The receiver was dropped
+                        if sender.send(self.read_measurement()).is_err() {
+                            break;
+                        }
+
+                        // Next deadline from the schedule, not from the end of the reading, to avoid drifting.
+                        // Deadlines missed while reading are skipped.
+                        deadline += interval;
+                        let now = Instant::now();
+                        if deadline < now {
+                            deadline = now + interval;
+                        }
+                    }
+                    Ok(SamplerControl::SetInterval(new_interval)) => {
+                        deadline = deadline - interval + new_interval;
+                        interval = new_interval;
+                    }
+                    Ok(SamplerControl::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+
+            self
+        });
+
+        Ok(SamplerHandle {
+            receiver,
+            control,
+            thread: Some(thread),
+        })
+    }
+
+    /// Read a register, for debugging.
+    /// In simulation mode, the status reports a forced conversion in progress for the configured number of readings,
+    /// the chip ID is the one of the BMP280 and the other registers hold the last value written.
+    ///
+    /// # Arguments
+    ///
+    /// * `register` - Address of the register.
+    pub fn read_register(&mut self, register: u8) -> Result <u8, Error> {
+
+        self.setup()?;
+
+        if self.simulation {
+            let value = match register {
+                BMP280_REG_STATUS if self.conversion_polls_left > 0 => {
+                    self.conversion_polls_left -= 1;
+                    BMP280_STATUS_MEASURING
+                }
+                BMP280_REG_STATUS => 0,
+                BMP280_REG_CHIP_ID => BMP280_CHIP_ID,
+                _ => self.transcript().iter().rev().find(|&&(r, _)| r == register).map_or(0, |&(_, v)| v),
+            };
+            return Ok(value);
+        }
+
+        let mut value = [0u8; 1];
+        self.transport.as_mut().unwrap().read(register, &mut value)?;
+
+        Ok(value[0])
+    }
+
+    /// Read the chip ID, 0x58 for a BMP280.
+    pub fn read_chip_id(&mut self) -> Result <u8, Error> {
+        self.read_register(BMP280_REG_CHIP_ID)
+    }
+
+    /// Read the status register.
+    pub fn read_status(&mut self) -> Result <Status, Error> {
+        Ok(Status::from_bits(self.read_register(BMP280_REG_STATUS)?))
+    }
+
+    /// Reset the sensor as on power on, then initialize the driver again: the calibration is read again
+    /// and the measurement configuration is applied again.
+    pub fn soft_reset(&mut self) -> Result <(), Error> {
+        self.setup()?;
+        self.write_register(BMP280_REG_RESET, BMP280_RESET_VALUE)?;
+
+        // The calibration is copied to the image registers after the reset
+        let start = Instant::now();
+        while self.read_status()?.im_update {
+            if start.elapsed() > RESET_TIMEOUT {
+                return Err(Error::Timeout(RESET_TIMEOUT));
+            }
+            thread::sleep(STATUS_POLL_INTERVAL);
+        }
+
+        self.reinitialize()
+    }
+
+    /// Get the measurement configuration.
+    pub fn configuration(&self) -> Configuration {
+        self.configuration
+    }
+
+    /// Set an offset added to every returned temperature, for example to correct the heat of the Raspberry Pi
+    /// making the sensor read high.
+    /// The pressure compensation still uses the uncorrected temperature: the calibration of the datasheet
+    /// relates to the temperature of the sensor die itself, which is the one actually measured.
+    ///
+    /// # Arguments
+    ///
+    /// * `delta_c` - Offset in degrees Celsius, negative if the sensor reads high.
+    pub fn set_temperature_offset(&mut self, delta_c: f32) {
+        self.temperature_offset = delta_c;
+    }
+
+    /// Get the offset added to the temperatures, in degrees Celsius.
+    pub fn temperature_offset(&self) -> f32 {
+        self.temperature_offset
+    }
+
+    /// Get the settings, to save them.
+    pub fn settings(&self) -> Settings {
+        Settings {
+            configuration: self.configuration,
+            temperature_offset: self.temperature_offset,
+        }
+    }
+
+    /// Restore saved settings, writing the configuration to the sensor.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Settings to restore.
+    pub fn apply_settings(&mut self, settings: Settings) -> Result <(), Error> {
+        self.configuration = settings.configuration;
+        self.temperature_offset = settings.temperature_offset;
+
+        if self.is_setup {
+            self.write_register(BMP280_REG_CONFIG, self.configuration.config())?;
+            self.write_register(BMP280_REG_CTRL_MEAS, self.configuration.ctrl_meas())
+        } else {
+            self.setup()
+        }
+    }
+
+    /// Set the recorder of the writes in simulation mode, for example one shared with the other drivers
+    /// to check the order of the operations across peripherals. The writes already recorded are not moved,
+    /// so the simulated registers read back as 0 until written again.
+    ///
+    /// # Arguments
+    ///
+    /// * `recorder` - Recorder to use.
+    pub fn set_recorder<R: Recorder + 'static>(&mut self, recorder: R) {
+        self.recorder = Box::new(recorder);
+    }
+
+    /// Get the registers and values written in simulation mode, from the sensor writes of the recorder.
+    pub fn transcript(&self) -> Vec<(u8, u8)> {
+        let mut transcript = Vec::new();
+        self.recorder.visit(&mut |entry| {
+            if let (Component::Sensor, Payload::Write { register, data }) = (entry.component, &entry.payload) {
+                if let Some(&value) = data.first() {
+                    transcript.push((*register, value));
+                }
+            }
+        });
+        transcript
+    }
+
+    /// Forget the writes recorded in simulation mode.
+    pub fn clear_transcript(&mut self) {
+        self.recorder.retain(&mut |entry| entry.component != Component::Sensor);
+    }
+
+    /// Get the factory calibration.
+    pub fn calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    /// Reads the raw temperature and pressure measurements.
+    fn read_raw(&mut self) -> Result <(i32, i32), Error> {
+
+        self.setup()?;
+
+        if self.simulation {
+            return self.simulated_readings.pop_front().unwrap_or(Ok(self.simulated_adc));
+        }
+
+        let mut data = [0u8; 6];
+        self.transport.as_mut().unwrap().read(BMP280_REG_DATA, &mut data)?;
+
+        // 20 bits values: msb, lsb, xlsb (4 high bits)
+        let adc_p = ((data[0] as i32) << 12) | ((data[1] as i32) << 4) | ((data[2] as i32) >> 4);
+        let adc_t = ((data[3] as i32) << 12) | ((data[4] as i32) << 4) | ((data[5] as i32) >> 4);
+
+        Ok((adc_t, adc_p))
+    }
+
+    /// Read the temperature, in degrees Celsius.
+    pub fn read_temperature(&mut self) -> Result <f32, Error> {
+        let (adc_t, _) = self.read_raw()?;
+        Ok(self.compensate_temperature(adc_t))
+    }
+
+    /// Compensates a raw temperature measurement and adds the offset.
+    fn compensate_temperature(&self, adc_t: i32) -> f32 {
+        self.calibration.compensate_temperature(adc_t) as f32 + self.temperature_offset
+    }
+
+    /// Read the pressure, in hectopascal.
+    pub fn read_pressure(&mut self) -> Result <f32, Error> {
+        let (adc_t, adc_p) = self.read_raw()?;
+        Ok((self.calibration.compensate_pressure(adc_t, adc_p) / 100.0) as f32)
+    }
+
+    /// Read the temperature as a value convertible to other units.
+    pub fn temperature(&mut self) -> Result <Temperature, Error> {
+        Ok(Temperature::from_celsius(self.read_temperature()?))
+    }
+
+    /// Read the pressure as a value convertible to other units.
+    pub fn pressure(&mut self) -> Result <Pressure, Error> {
+        Ok(Pressure::from_hpa(self.read_pressure()?))
+    }
+}
+
+/// Message sent to the sampling thread.
+#[derive(Debug)]
+enum SamplerControl {
+
+    /// Change the interval between two measurements.
+    SetInterval(Duration),
+
+    /// Stop the sampling.
+    Stop,
+}
+
+/// Handle of the thread sampling the sensor, receiving its measurements.
+/// Dropping the handle stops the sampling. Send but not Sync, as the receiver of the measurements is only Send.
+#[derive(Debug)]
+pub struct SamplerHandle {
+
+    /// Measurements, or errors of the failed readings.
+    receiver: Receiver<Result<Measurement, Error>>,
+
+    /// Sends the control messages to the thread.
+    control: Sender<SamplerControl>,
+
+    /// Thread sampling the sensor, giving it back when done.
+    thread: Option<JoinHandle<BMP280>>,
+}
+
+impl SamplerHandle {
+
+    /// Get the receiver of the measurements.
+    pub fn receiver(&self) -> &Receiver<Result<Measurement, Error>> {
+        &self.receiver
+    }
+
+    /// Change the interval between two measurements, from the last measurement.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Interval between two measurements.
+    pub fn set_interval(&self, interval: Duration) {
+        let _result = self.control.send(SamplerControl::SetInterval(interval));
+    }
+
+    /// Stop the sampling and get the sensor back.
+    pub fn stop(mut self) -> BMP280 {
+        let _result = self.control.send(SamplerControl::Stop);
+        self.thread.take().unwrap().join().unwrap()
+    }
+}
+
+impl Drop for SamplerHandle {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _result = self.control.send(SamplerControl::Stop);
+            let _result = thread.join();
+        }
+    }
+}
+
+/// Errors that can occur.
+#[derive(Debug)]
+pub enum Error {
+
+    /// I2C error accessing the sensor.
+    #[cfg(feature = "hardware")]
+    I2c {
+
+        /// I2C bus number, None for the default bus.
+        bus: Option<u8>,
+
+        /// Address of the sensor, None when unknown.
+        address: Option<u16>,
+
+        /// Error of the bus.
+        source: rppal::i2c::Error,
+    },
+
+    /// SPI error.
+    #[cfg(feature = "hardware")]
+    Spi(rppal::spi::Error),
+
+    /// I/O error of a custom bus.
+    Io(std::io::Error),
+
+    /// The operation is not supported in this build.
+    Unsupported(String),
+
+    /// The device answering at the address is not a BMP280: unexpected chip ID.
+    ChipId(u8),
+
+    /// The conversion was not done after the given duration.
+    Timeout(Duration),
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "hardware")]
+            Error::I2c { source, .. } => Some(source),
+            #[cfg(feature = "hardware")]
+            Error::Spi(err) => Some(err),
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+
+    /// Get whether opening the bus failed because the program is not running on a Raspberry Pi
+    /// or is not allowed to access the bus.
+    #[cfg(feature = "hardware")]
+    fn is_hardware_absent(&self) -> bool {
+        match self {
+            Error::I2c { source, .. } => hal::is_i2c_absent(source),
+            Error::Spi(err) => hal::is_spi_absent(err),
+            _ => false,
+        }
+    }
+
+    /// Name the address of the sensor in an I2C error of a bus not knowing it.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the sensor, if known.
+    #[cfg_attr(not(feature = "hardware"), allow(unused_variables))]
+    fn at_address(self, address: Option<u16>) -> Error {
+        match self {
+            #[cfg(feature = "hardware")]
+            Error::I2c { bus, address: None, source } => Error::I2c { bus, address, source },
+            err => err,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "hardware")]
+            Error::I2c { bus, address, source } => {
+                write!(f, "failed to access {} for BMP280: {}", crate::error::i2c_location(*bus, *address), &source)
+            }
+            #[cfg(feature = "hardware")]
+            Error::Spi(err) => write!(f, "SPI error: {}", &err),
+            Error::Io(err) => write!(f, "I/O error: {}", &err),
+            Error::Unsupported(msg) => write!(f, "Unsupported: {}", &msg),
+            Error::ChipId(id) => write!(f, "Unexpected chip ID 0x{:02X}, expected 0x{:02X}", id, BMP280_CHIP_ID),
+            Error::Timeout(duration) => write!(f, "Conversion not done after {:?}", duration),
+        }
+    }
+}
+
+/// Converts SPI error
+#[cfg(feature = "hardware")]
+impl From<rppal::spi::Error> for Error {
+    fn from(err: rppal::spi::Error) -> Error {
+        Error::Spi(err)
+    }
+}
+
+/// Converts I/O error
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Calibration of the worked example of the datasheet.
+    const DATASHEET_CALIBRATION: Calibration = Calibration {
+        dig_t1: 27504,
+        dig_t2: 26435,
+        dig_t3: -1000,
+        dig_p1: 36477,
+        dig_p2: -10685,
+        dig_p3: 3024,
+        dig_p4: 2855,
+        dig_p5: 140,
+        dig_p6: -7,
+        dig_p7: 15500,
+        dig_p8: -14600,
+        dig_p9: 6000,
+    };
+
+    /// Raw temperature of the worked example of the datasheet.
+    const DATASHEET_ADC_T: i32 = 519888;
+
+    /// Raw pressure of the worked example of the datasheet.
+    const DATASHEET_ADC_P: i32 = 415148;
+
+    /// Tests the compensation against the worked example of the datasheet.
+    #[test]
+    fn test_bmp280_compensation() {
+        let calibration = DATASHEET_CALIBRATION;
+
+        assert!((calibration.t_fine(DATASHEET_ADC_T) - 128422.2869).abs() < 0.001);
+        assert!((calibration.compensate_temperature(DATASHEET_ADC_T) - 25.0825).abs() < 0.001);
+        assert!((calibration.compensate_pressure(DATASHEET_ADC_T, DATASHEET_ADC_P) - 100653.27).abs() < 0.01);
+    }
+
+    /// Tests reading the simulated sensor.
+    #[test]
+    fn test_bmp280_read() -> Result<(), Error> {
+        let mut bmp280 = BMP280::new()?;
+        bmp280.simulate(DATASHEET_CALIBRATION, DATASHEET_ADC_T, DATASHEET_ADC_P);
+
+        assert!((bmp280.read_temperature()? - 25.08).abs() < 0.01);
+        assert!((bmp280.read_pressure()? - 1006.53).abs() < 0.01);
+
+        assert!(bmp280.temperature()?.to_string() == "25.1°C");
+        assert!((bmp280.temperature()?.fahrenheit() - 77.15).abs() < 0.01);
+        assert!(bmp280.pressure()?.to_string() == "1006.5 hPa");
+
+        Ok(())
+    }
+
+    /// Tests encoding the registers against the tables of the datasheet.
+    #[test]
+    fn test_bmp280_register_encoding() {
+        let oversampling = [
+            (Oversampling::X1, 0b001), (Oversampling::X2, 0b010), (Oversampling::X4, 0b011),
+            (Oversampling::X8, 0b100), (Oversampling::X16, 0b101),
+        ];
+        for &(value, bits) in oversampling.iter() {
+            assert!(value.bits() == bits);
+        }
+
+        let filters = [
+            (IirFilter::Off, 0b000), (IirFilter::X2, 0b001), (IirFilter::X4, 0b010),
+            (IirFilter::X8, 0b011), (IirFilter::X16, 0b100),
+        ];
+        for &(value, bits) in filters.iter() {
+            assert!(value.bits() == bits);
+        }
+
+        let standby_times = [
+            (StandbyTime::Ms0_5, 0b000), (StandbyTime::Ms62_5, 0b001), (StandbyTime::Ms125, 0b010),
+            (StandbyTime::Ms250, 0b011), (StandbyTime::Ms500, 0b100), (StandbyTime::Ms1000, 0b101),
+            (StandbyTime::Ms2000, 0b110), (StandbyTime::Ms4000, 0b111),
+        ];
+        for &(value, bits) in standby_times.iter() {
+            assert!(value.bits() == bits);
+        }
+
+        // Default: oversampling x1, normal mode, 1000ms standby, filter off
+        let configuration = Configuration::default();
+        assert!(configuration.ctrl_meas() == 0x27);
+        assert!(configuration.config() == 0xA0);
+
+        // Datasheet indoor navigation profile: ultra high resolution, filter x16, 0.5ms standby
+        let configuration = Configuration {
+            temperature: Oversampling::X2,
+            pressure: Some(Oversampling::X16),
+            filter: IirFilter::X16,
+            standby_time: StandbyTime::Ms0_5,
+            mode: Mode::Normal,
+        };
+        assert!(configuration.ctrl_meas() == 0b0101_0111);
+        assert!(configuration.config() == 0b0001_0000);
+
+        // Pressure skipped
+        let configuration = Configuration { pressure: None, ..Configuration::default() };
+        assert!(configuration.ctrl_meas() == 0b0010_0011);
+    }
+
+    /// Tests the mode bits and the maximum measurement time of the datasheet.
+    #[test]
+    fn test_bmp280_mode() {
+        assert!(Mode::Sleep.bits() == 0b00);
+        assert!(Mode::Forced.bits() == 0b01);
+        assert!(Mode::Normal.bits() == 0b11);
+
+        let configuration = Configuration { mode: Mode::Forced, ..Configuration::default() };
+        assert!(configuration.ctrl_meas() == 0b0010_0101);
+        assert!(configuration.max_measurement_time() == Duration::from_micros(6425));
+
+        let configuration = Configuration {
+            temperature: Oversampling::X2,
+            pressure: Some(Oversampling::X16),
+            ..Configuration::default()
+        };
+        assert!(configuration.max_measurement_time() == Duration::from_micros(43225));
+    }
+
+    /// Tests a forced measurement waits for the conversion then reads the values.
+    #[test]
+    fn test_bmp280_measure_once() -> Result<(), Error> {
+        let mut bmp280 = BMP280::new()?;
+        bmp280.simulate(DATASHEET_CALIBRATION, DATASHEET_ADC_T, DATASHEET_ADC_P);
+        bmp280.simulated_conversion_polls = 3;
+
+        // Longest conversion, leaving a comfortable timeout to the polling on a loaded machine
+        bmp280.set_oversampling(Oversampling::X16, Some(Oversampling::X16))?;
+        bmp280.clear_transcript();
+
+        let before = Instant::now();
+        let measurement = bmp280.measure_once()?;
+
+        // Forced mode triggered, the status polled until the conversion is done
+        assert!(bmp280.transcript() == [(BMP280_REG_CTRL_MEAS, 0b1011_0101)]);
+        assert!(bmp280.conversion_polls_left == 0);
+        assert!((measurement.temperature_c - 25.08).abs() < 0.01);
+        assert!((measurement.pressure_hpa - 1006.53).abs() < 0.01);
+        assert!(measurement.timestamp >= before);
+        assert!(bmp280.configuration().mode == Mode::Sleep);
+
+        bmp280.clear_transcript();
+        bmp280.set_mode(Mode::Sleep)?;
+        assert!(bmp280.transcript() == [(BMP280_REG_CTRL_MEAS, 0b1011_0100)]);
+
+        Ok(())
+    }
+
+    /// Tests a conversion never done is reported as a timeout.
+    #[test]
+    fn test_bmp280_measure_once_timeout() -> Result<(), Error> {
+        let mut bmp280 = BMP280::new()?;
+        bmp280.simulate(DATASHEET_CALIBRATION, DATASHEET_ADC_T, DATASHEET_ADC_P);
+        bmp280.simulated_conversion_polls = u32::MAX;
+
+        assert!(matches!(bmp280.measure_once(), Err(Error::Timeout(_))));
+
+        Ok(())
+    }
+
+    /// Tests receiving the measurements of the sampling thread, including a failed reading.
+    #[test]
+    fn test_bmp280_start_sampling() -> Result<(), Error> {
+        let mut bmp280 = BMP280::new()?;
+        bmp280.simulate(DATASHEET_CALIBRATION, DATASHEET_ADC_T, DATASHEET_ADC_P);
+
+        bmp280.simulated_readings.push_back(Ok((DATASHEET_ADC_T, DATASHEET_ADC_P)));
+        bmp280.simulated_readings.push_back(Err(Error::from(std::io::Error::from_raw_os_error(121))));
+        bmp280.simulated_readings.push_back(Ok((DATASHEET_ADC_T + 1000, DATASHEET_ADC_P)));
+
+        let sampler = bmp280.start_sampling(Duration::from_millis(1))?;
+        let timeout = Duration::from_secs(5);
+
+        let first = sampler.receiver().recv_timeout(timeout).unwrap()?;
+        assert!((first.temperature_c - 25.08).abs() < 0.01);
+
+        // The failed reading is reported and the sampling goes on
+        assert!(matches!(sampler.receiver().recv_timeout(timeout).unwrap(), Err(Error::Io(_))));
+
+        let second = sampler.receiver().recv_timeout(timeout).unwrap()?;
+        assert!(second.temperature_c > first.temperature_c);
+        assert!(second.timestamp > first.timestamp);
+
+        sampler.set_interval(Duration::from_millis(2));
+        let bmp280 = sampler.stop();
+        assert!(bmp280.simulated_readings.is_empty());
+
+        Ok(())
+    }
+
+    /// Tests the configuration is written and applied again on reinitialization.
+    #[test]
+    fn test_bmp280_configuration() -> Result<(), Error> {
+        let mut bmp280 = BMP280::new()?;
+        bmp280.simulate(DATASHEET_CALIBRATION, DATASHEET_ADC_T, DATASHEET_ADC_P);
+
+        // The first setter applies the whole configuration
+        bmp280.set_filter(IirFilter::X4)?;
+        assert!(bmp280.transcript() == [(BMP280_REG_CONFIG, 0b1010_1000), (BMP280_REG_CTRL_MEAS, 0x27)]);
+        bmp280.clear_transcript();
+
+        bmp280.set_oversampling(Oversampling::X2, Some(Oversampling::X8))?;
+        bmp280.set_standby_time(StandbyTime::Ms125)?;
+        assert!(bmp280.transcript() == [(BMP280_REG_CTRL_MEAS, 0b0101_0011), (BMP280_REG_CONFIG, 0b0100_1000)]);
+        bmp280.clear_transcript();
+
+        bmp280.reinitialize()?;
+        assert!(bmp280.transcript() == [(BMP280_REG_CONFIG, 0b0100_1000), (BMP280_REG_CTRL_MEAS, 0b0101_0011)]);
+
+        Ok(())
+    }
+
+    /// Tests decoding the little endian calibration registers.
+    #[test]
+    fn test_bmp280_calibration_from_bytes() {
+        let mut data = [0u8; CALIBRATION_SIZE];
+        data[0..2].copy_from_slice(&27504u16.to_le_bytes());
+        data[4..6].copy_from_slice(&(-1000i16).to_le_bytes());
+        data[16..18].copy_from_slice(&(-7i16).to_le_bytes());
+        data[22..24].copy_from_slice(&6000i16.to_le_bytes());
+
+        let calibration = Calibration::from_bytes(&data);
+        assert!(calibration.dig_t1 == 27504);
+        assert!(calibration.dig_t2 == 0);
+        assert!(calibration.dig_t3 == -1000);
+        assert!(calibration.dig_p6 == -7);
+        assert!(calibration.dig_p9 == 6000);
+    }
+
+    /// Tests creating the driver in simulation mode.
+    #[test]
+    fn test_bmp280_with_simulation() -> Result<(), Error> {
+        let mut bmp280 = BMP280::with_simulation()?;
+        assert!(bmp280.simulation);
+
+        bmp280.setup()?;
+        assert!(bmp280.transcript().len() == 2);
+
+        Ok(())
+    }
+
+    /// Tests the temperature offset is applied to every reading, but not to the pressure compensation.
+    #[test]
+    fn test_bmp280_temperature_offset() -> Result<(), Error> {
+        let mut bmp280 = BMP280::with_simulation()?;
+        bmp280.simulate(DATASHEET_CALIBRATION, DATASHEET_ADC_T, DATASHEET_ADC_P);
+        bmp280.set_temperature_offset(-3.0);
+        assert!(bmp280.temperature_offset() == -3.0);
+
+        let temperature = bmp280.read_temperature()?;
+        assert!((temperature - 22.08).abs() < 0.01);
+
+        let measurement = bmp280.measure_once()?;
+        assert!(measurement.temperature_c == temperature);
+        assert!((measurement.pressure_hpa - 1006.53).abs() < 0.01);
+        assert!((bmp280.read_pressure()? - 1006.53).abs() < 0.01);
+
+        Ok(())
+    }
+
+    /// Tests saving and restoring the settings.
+    #[test]
+    fn test_bmp280_settings() -> Result<(), Error> {
+        let mut bmp280 = BMP280::with_simulation()?;
+        bmp280.set_temperature_offset(-2.5);
+        bmp280.set_oversampling(Oversampling::X2, Some(Oversampling::X8))?;
+        let settings = bmp280.settings();
+
+        let mut restored = BMP280::with_simulation()?;
+        restored.setup()?;
+        restored.clear_transcript();
+        restored.apply_settings(settings)?;
+
+        assert!(restored.temperature_offset() == -2.5);
+        assert!(restored.configuration() == bmp280.configuration());
+        assert!(restored.transcript() == [(BMP280_REG_CONFIG, 0xA0), (BMP280_REG_CTRL_MEAS, 0b0101_0011)]);
+
+        Ok(())
+    }
+
+    /// Tests reading the registers of the simulated sensor.
+    #[test]
+    fn test_bmp280_read_register() -> Result<(), Error> {
+        let mut bmp280 = BMP280::with_simulation()?;
+        bmp280.simulated_conversion_polls = 1;
+
+        assert!(bmp280.read_chip_id()? == BMP280_CHIP_ID);
+        assert!(bmp280.read_register(BMP280_REG_CONFIG)? == 0xA0);
+        assert!(bmp280.read_register(BMP280_REG_CTRL_MEAS)? == 0x27);
+
+        bmp280.set_mode(Mode::Forced)?;
+        assert!(bmp280.read_status()? == Status { measuring: true, im_update: false });
+        assert!(bmp280.read_status()? == Status::default());
+
+        assert!(Status::from_bits(0x09) == Status { measuring: true, im_update: true });
+
+        Ok(())
+    }
+
+    /// Tests a soft reset reads the calibration and applies the configuration again.
+    #[test]
+    fn test_bmp280_soft_reset() -> Result<(), Error> {
+        let mut bmp280 = BMP280::new()?;
+        bmp280.simulate(DATASHEET_CALIBRATION, DATASHEET_ADC_T, DATASHEET_ADC_P);
+        bmp280.setup()?;
+        bmp280.clear_transcript();
+
+        // Calibration lost, as if the sensor was replaced
+        bmp280.calibration = Calibration::default();
+        bmp280.soft_reset()?;
+
+        assert!(bmp280.calibration() == DATASHEET_CALIBRATION);
+        assert!(bmp280.transcript() == [
+            (BMP280_REG_RESET, BMP280_RESET_VALUE),
+            (BMP280_REG_CONFIG, 0xA0),
+            (BMP280_REG_CTRL_MEAS, 0x27),
+        ]);
+        assert!((bmp280.read_temperature()? - 25.08).abs() < 0.01);
+
+        Ok(())
+    }
+
+    /// Registers of a fake sensor, shared with the test, reachable as an I2C or SPI bus.
+    #[derive(Debug, Clone)]
+    struct FakeBus {
+
+        /// Values of the registers.
+        registers: Arc<Mutex<[u8; 256]>>,
+
+        /// Bytes written on the bus.
+        writes: Arc<Mutex<Vec<Vec<u8>>>>,
+    }
+
+    impl FakeBus {
+
+        /// Creates a sensor with the datasheet calibration and measurements.
+        fn new() -> FakeBus {
+            let mut registers = [0u8; 256];
+            registers[BMP280_REG_CHIP_ID as usize] = BMP280_CHIP_ID;
+
+            let c = DATASHEET_CALIBRATION;
+            let words = [
+                c.dig_t1, c.dig_t2 as u16, c.dig_t3 as u16,
+                c.dig_p1, c.dig_p2 as u16, c.dig_p3 as u16, c.dig_p4 as u16, c.dig_p5 as u16,
+                c.dig_p6 as u16, c.dig_p7 as u16, c.dig_p8 as u16, c.dig_p9 as u16,
+            ];
+            for (i, word) in words.iter().enumerate() {
+                let register = BMP280_REG_CALIBRATION as usize + i * 2;
+                registers[register..register + 2].copy_from_slice(&word.to_le_bytes());
+            }
+
+            let data = BMP280_REG_DATA as usize;
+            for (i, &adc) in [DATASHEET_ADC_P, DATASHEET_ADC_T].iter().enumerate() {
+                registers[data + i * 3] = (adc >> 12) as u8;
+                registers[data + i * 3 + 1] = (adc >> 4) as u8;
+                registers[data + i * 3 + 2] = ((adc & 0x0F) << 4) as u8;
+            }
+
+            FakeBus {
+                registers: Arc::new(Mutex::new(registers)),
+                writes: Arc::new(Mutex::new(Vec::new())),
+            }
+        }
+    }
+
+    impl I2cBus for FakeBus {
+        fn write(&mut self, buffer: &[u8]) -> Result<(), Error> {
+            self.writes.lock().unwrap().push(buffer.to_vec());
+            self.registers.lock().unwrap()[buffer[0] as usize] = buffer[1];
+            Ok(())
+        }
+
+        fn write_read(&mut self, write_buffer: &[u8], read_buffer: &mut [u8]) -> Result<(), Error> {
+            let register = write_buffer[0] as usize;
+            read_buffer.copy_from_slice(&self.registers.lock().unwrap()[register..register + read_buffer.len()]);
+            Ok(())
+        }
+    }
+
+    impl SpiBus for FakeBus {
+        fn transfer(&mut self, read_buffer: &mut [u8], write_buffer: &[u8]) -> Result<(), Error> {
+            self.writes.lock().unwrap().push(write_buffer.to_vec());
+
+            // The registers are all above 0x80: the sensor restores the bit used to select reading
+            let register = (write_buffer[0] | SPI_READ_BIT) as usize;
+            let mut registers = self.registers.lock().unwrap();
+
+            if write_buffer[0] & SPI_READ_BIT != 0 {
+                let len = read_buffer.len() - 1;
+                read_buffer[1..].copy_from_slice(&registers[register..register + len]);
+            } else {
+                registers[register] = write_buffer[1];
+            }
+            Ok(())
+        }
+    }
+
+    /// Tests reading the sensor through the I2C transport.
+    #[test]
+    fn test_bmp280_i2c_transport() -> Result<(), Error> {
+        let bus = FakeBus::new();
+        let mut bmp280 = BMP280::with_transport(I2cTransport::new(bus.clone()));
+
+        assert!((bmp280.read_temperature()? - 25.08).abs() < 0.01);
+        assert!((bmp280.read_pressure()? - 1006.53).abs() < 0.01);
+        assert!(bmp280.calibration() == DATASHEET_CALIBRATION);
+
+        assert!(*bus.writes.lock().unwrap() == [vec![BMP280_REG_CONFIG, 0xA0], vec![BMP280_REG_CTRL_MEAS, 0x27]]);
+
+        // The transport is kept when initializing again
+        bmp280.reinitialize()?;
+        assert!(bus.writes.lock().unwrap().len() == 4);
+
+        Ok(())
+    }
+
+    /// Tests reading the sensor through the SPI transport, the read bit set in the address.
+    #[test]
+    fn test_bmp280_spi_transport() -> Result<(), Error> {
+        let bus = FakeBus::new();
+        let mut bmp280 = BMP280::with_transport(SpiTransport::new(bus.clone()));
+
+        assert!((bmp280.read_temperature()? - 25.08).abs() < 0.01);
+        assert!((bmp280.read_pressure()? - 1006.53).abs() < 0.01);
+        assert!(bmp280.calibration() == DATASHEET_CALIBRATION);
+        assert!(bmp280.read_chip_id()? == BMP280_CHIP_ID);
+
+        let writes = bus.writes.lock().unwrap();
+        assert!(writes[0] == [0xD0, 0]);
+        assert!(writes[1][0] == 0x88 && writes[1].len() == CALIBRATION_SIZE + 1);
+        assert!(writes[2] == [0x75, 0xA0]);
+        assert!(writes[3] == [0x74, 0x27]);
+        assert!(writes[4] == [0xF7, 0, 0, 0, 0, 0, 0]);
+
+        let registers = bus.registers.lock().unwrap();
+        assert!(registers[BMP280_REG_CONFIG as usize] == 0xA0);
+        assert!(registers[BMP280_REG_CTRL_MEAS as usize] == 0x27);
+
+        Ok(())
+    }
+
+    /// Tests enabling simulation mode on a driver created for the hardware.
+    #[test]
+    fn test_bmp280_enable_simulation() -> Result<(), Error> {
+        let mut bmp280 = BMP280::new()?;
+        assert!(bmp280.is_simulation() != cfg!(feature = "hardware"));
+
+        bmp280.enable_simulation();
+        assert!(bmp280.is_simulation());
+        bmp280.setup()?;
+
+        Ok(())
+    }
+
+    /// Pins the auto traits: the driver and its sampler can be moved to another thread, the measurements shared between threads.
+    const _: () = {
+        const fn assert_send<T: Send>() {}
+        const fn assert_sync<T: Sync>() {}
+        assert_send::<BMP280>();
+        assert_send::<SamplerHandle>();
+        assert_send::<Measurement>();
+        assert_send::<Error>();
+        assert_sync::<Measurement>();
+        assert_sync::<Error>();
+    };
+}
@@ -1,18 +1,91 @@
 use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
+use std::thread::JoinHandle;
 use std::time::Duration;
 use core::fmt::Debug;
-use rppal::gpio::{Gpio, OutputPin};
+use crate::clock::{Clock, SystemClock};
+use crate::error::invalid_input;
+use crate::hal::ToneOutput;
+#[cfg(feature = "hardware")]
+use crate::hal::{self, Backend, Rppal};
+#[cfg(feature = "hardware")]
+use crate::pins;
+use crate::pins::{PinClaim, PinInUse, PinRegistry};
+use crate::rainbow_hat::Component;
+use crate::recorder::{MemoryRecorder, Payload, Recorder};
+use crate::trace::Tracer;
 
 /// GPIO BCM pin number for buzzer.
 pub const GPIO_BUZZER: u8 = 13;
 
+/// MIDI note of the metronome clicks, C6.
+pub const METRONOME_NOTE: u32 = 84;
+
+/// MIDI note of the accented metronome clicks on the first beat of each bar, C7.
+pub const METRONOME_ACCENT_NOTE: u32 = 96;
+
+/// Number of beats in a bar of the metronome.
+pub const METRONOME_BEATS_PER_BAR: u32 = 4;
+
+/// Duration of a metronome click in seconds, shortened at fast tempos to half a beat.
+pub const METRONOME_CLICK_DURATION: f64 = 0.05;
+
+/// Number of steps of each rise and fall of the siren.
+pub const SIREN_STEPS: u32 = 16;
+
+/// Tone played by `Buzzer::alert` to get the attention of the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlertTone {
+
+    /// No sound.
+    Silent,
+
+    /// Single short beep.
+    Beep,
+
+    /// Two short beeps.
+    DoubleBeep,
+
+    /// Three rising notes, for a pleasant notification.
+    Chime,
+
+    /// Warble between two frequencies, for an urgent alert.
+    Urgent,
+}
+
 /// Buzzer on the board.
+/// Send but not Sync, as its pin and trace hook are only Send: use `into_shared` to beep from several threads.
 #[derive(Debug)]
 pub struct Buzzer {
 
+    /// GPIO pin number using the BCM pin numbering.
+    bcm_pin: u8,
+
     /// Output pin to write to GPIO. Optional as not used in simulated mode.
-    pin: Option<Box<OutputPin>>,
+    pin: Option<Box<dyn ToneOutput>>,
+
+    /// hook receiving a message before each hardware operation
+    tracer: Tracer,
+
+    /// recorder of the tones played in simulation mode
+    recorder: Box<dyn Recorder>,
+
+    /// clock timing the notes and the metronome
+    clock: Box<dyn Clock>,
+
+    /// backend opening the peripherals on setup
+    #[cfg(feature = "hardware")]
+    backend: Box<dyn Backend>,
+
+    /// claim of the pin on setup, released with the driver
+    claim: Option<PinClaim>,
+
+    /// registry of the pins claimed in simulation mode, shared by the drivers of a board
+    pin_registry: PinRegistry,
+
+    /// whether to switch to simulation mode on setup when the hardware is absent
+    auto_simulate: bool,
 
     /// In simulation mode, no interaction with the hardware is done to simplify testability.
     simulation: bool, 
@@ -25,54 +98,432 @@ impl Buzzer {
     
     /// Creates a Buzzer.
     pub fn new() -> Result<Buzzer, Error>  {     
+        Buzzer::with_bcm_pin(GPIO_BUZZER)
+    }
 
-        Ok(Self {
+    /// Creates a Buzzer wired to another GPIO pin, for example on a board with the buzzer rewired.
+    /// # Arguments
+    ///
+    /// * `bcm_pin` - GPIO pin number using the BCM pin numbering.
+    pub fn with_bcm_pin(bcm_pin: u8) -> Result<Buzzer, Error>  {
+        Ok(Buzzer::create(bcm_pin))
+    }
+
+    /// Creates a buzzer with every setting at its default, not set up.
+    /// # Arguments
+    ///
+    /// * `bcm_pin` - GPIO pin number using the BCM pin numbering.
+    fn create(bcm_pin: u8) -> Buzzer {
+        Self {
+            bcm_pin,
             pin: None,
-            simulation: false,
+            tracer: Tracer::new(),
+            recorder: Box::new(MemoryRecorder::new()),
+            clock: Box::new(SystemClock),
+            #[cfg(feature = "hardware")]
+            backend: Box::new(Rppal),
+            claim: None,
+            pin_registry: PinRegistry::new(),
+            auto_simulate: false,
+            simulation: !cfg!(feature = "hardware"),
             is_setup: false,
-        })
+        }
+    }
+
+    /// Creates a Buzzer in simulation mode, never accessing the hardware.
+    pub fn with_simulation() -> Result<Buzzer, Error> {
+        let mut buzzer = Buzzer::new()?;
+        buzzer.simulation = true;
+        Ok(buzzer)
+    }
+
+    /// Creates a buzzer playing the tones on the given output instead of the Raspberry Pi GPIO.
+    /// # Arguments
+    ///
+    /// * `pin` - Output playing the tones.
+    pub fn with_pin<P: ToneOutput + 'static>(pin: P) -> Buzzer {
+        let mut buzzer = Buzzer::create(GPIO_BUZZER);
+        buzzer.pin = Some(Box::new(pin));
+        buzzer.simulation = false;
+        buzzer
+    }
+
+    /// Enables simulation mode: no interaction with the hardware is done.
+    /// Enable it before the first operation, which initializes the hardware.
+    pub fn enable_simulation(&mut self) {
+        self.simulation = true;
+    }
+
+    /// Get the GPIO pin number of the buzzer, using the BCM pin numbering.
+    pub fn bcm_pin(&self) -> u8 {
+        self.bcm_pin
+    }
+
+    /// Get whether simulation mode is enabled.
+    pub fn is_simulation(&self) -> bool {
+        self.simulation
+    }
+
+    /// Switch to simulation mode on setup when the hardware is absent, for example when running off the Raspberry Pi,
+    /// instead of returning an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to fall back to simulation mode.
+    pub fn set_auto_simulate(&mut self, enabled: bool) {
+        self.auto_simulate = enabled;
+    }
+
+    /// Get whether the driver switches to simulation mode on setup when the hardware is absent.
+    pub fn is_auto_simulate(&self) -> bool {
+        self.auto_simulate
+    }
+
+    /// Set the backend opening the GPIO on setup.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Backend to use.
+    #[cfg(feature = "hardware")]
+    pub fn set_backend<B: Backend + 'static>(&mut self, backend: B) {
+        self.backend = Box::new(backend);
+    }
+
+    /// Set the registry the pin is claimed in when set up in simulation mode, for example the one shared by the
+    /// drivers of a board to find the drivers using the same pin. Each buzzer has a registry of its own by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - Registry to use.
+    pub fn set_pin_registry(&mut self, registry: PinRegistry) {
+        self.pin_registry = registry;
+    }
+
+    /// Set the clock timing the notes and the metronome.
+    /// # Arguments
+    ///
+    /// * `clock` - Clock to use.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Box::new(clock);
+    }
+
+    /// Set a hook receiving a message before each hardware operation, also in simulation mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Closure receiving each message.
+    pub fn set_trace<F: FnMut(&str) + Send + 'static>(&mut self, hook: F) {
+        self.tracer.set(hook);
+    }
+
+    /// Remove the trace hook.
+    pub fn clear_trace(&mut self) {
+        self.tracer.clear();
+    }
+
+    /// Set the recorder of the tones played in simulation mode, for example one shared with the other drivers
+    /// to check the order of the operations across peripherals.
+    ///
+    /// # Arguments
+    ///
+    /// * `recorder` - Recorder to use.
+    pub fn set_recorder<R: Recorder + 'static>(&mut self, recorder: R) {
+        self.recorder = Box::new(recorder);
     }
 
     /// Setup piezo buzzer.
     pub fn setup(&mut self) -> Result <(), Error> {
         if !self.is_setup {
 
-            // Ignore Gpio initialization if in sumulation mode
-            if !self.simulation {
-                let gpio = Gpio::new()?;
-                let output = gpio.get(GPIO_BUZZER)?.into_output(); 
-                self.pin = Some(Box::new(output));
+            // Claim the pin unless given the output: in the registry of the process when opened on the hardware,
+            // of the board in simulation mode
+            let mut claim = None;
+
+            // Ignore Gpio initialization if in sumulation mode or given an output
+            #[cfg(feature = "hardware")]
+            if !self.simulation && self.pin.is_none() {
+                match self.backend.gpio() {
+                    Ok(gpio) => {
+                        claim = Some(pins::claim(self.bcm_pin, "buzzer")?);
+                        let output = gpio.get(self.bcm_pin).map_err(|source| Error::Gpio { pin: self.bcm_pin, source })?.into_output();
+                        self.pin = Some(Box::new(output));
+                    }
+                    Err(err) if self.auto_simulate && hal::is_gpio_absent(&err) => {
+                        self.tracer.trace(|| format!("Buzzer: hardware unavailable ({}), simulating", err));
+                        self.simulation = true;
+                    }
+                    Err(source) => return Err(Error::Gpio { pin: self.bcm_pin, source }),
+                }
+            }
+
+            if self.simulation && self.pin.is_none() {
+                claim = Some(self.pin_registry.claim(self.bcm_pin, "buzzer")?);
             }
 
+            self.claim = claim;
             self.is_setup = true;
         }
         Ok(())
     }
 
+    /// Release the pin: its handle is dropped, stopping any tone, so rppal returns it to its prior state now rather
+    /// than when the driver is dropped. The driver is set up again on its next use.
+    pub fn release(&mut self) {
+        self.pin = None;
+        self.claim = None;
+        self.is_setup = false;
+    }
+
     /// Play a single note.
     ///
     /// # Arguments
     ///
-    /// * `frequency` - Musical frequency in hertz.
-    /// * `duration` - Duration in seconds.
+    /// * `frequency` - Musical frequency in hertz, greater than 0.
+    /// * `duration` - Duration in seconds, 0 or more.
     pub fn note(&mut self, frequency : f64, duration: f64) -> Result<(), Error>{
-        
-        assert!(frequency > 0.0);
+
+        Buzzer::check_note(frequency, duration)?;
 
         if !self.is_setup {
-            let _result = self.setup();
+            self.setup()?;
         }
 
+        self.tracer.trace(|| format!("Buzzer note: {:.1} Hz for {} s", frequency, duration));
+
         // Only perform actual pin write if not in simulation mode
         if !self.simulation {
 
             let pin = self.pin.as_deref_mut().unwrap();
 
-            pin.set_pwm_frequency(frequency, 0.90)?;
+            pin.start_tone(frequency)?;
+
+            self.clock.sleep(Duration::from_millis((duration * 1000.0) as u64));
+
+            pin.stop_tone()?;
+        } else {
+            self.recorder.record(Component::Buzzer, "note", Payload::Tone { frequency, duration: Duration::from_secs_f64(duration) });
+        }
+
+        Ok(())
+    }
+
+    /// Play a single note from an async task, waiting with the timer of the runtime.
+    /// Dropping the future before the end of the note silences the buzzer.
+    ///
+    /// # Arguments
+    ///
+    /// * `frequency` - Musical frequency in hertz, greater than 0.
+    /// * `duration` - Duration in seconds, 0 or more.
+    #[cfg(feature = "async")]
+    pub async fn note_async(&mut self, frequency : f64, duration: f64) -> Result<(), Error>{
+
+        Buzzer::check_note(frequency, duration)?;
+
+        if !self.is_setup {
+            self.setup()?;
+        }
+
+        self.tracer.trace(|| format!("Buzzer note: {:.1} Hz for {} s", frequency, duration));
+
+        // Only perform actual pin write if not in simulation mode
+        if !self.simulation {
+
+            let pin = self.pin.as_deref_mut().unwrap();
+
+            pin.start_tone(frequency)?;
+            let tone = ToneGuard { pin: Some(pin) };
+
+            tokio::time::sleep(Duration::from_millis((duration * 1000.0) as u64)).await;
+
+            tone.stop()?;
+        } else {
+            self.recorder.record(Component::Buzzer, "note", Payload::Tone { frequency, duration: Duration::from_secs_f64(duration) });
+        }
+
+        Ok(())
+    }
+
+    /// Alternate between two frequencies, a warble more distinctive than a single note for urgent alerts.
+    /// The tone switches without silence between the frequencies, the last one being shortened to end
+    /// after the total duration.
+    ///
+    /// # Arguments
+    ///
+    /// * `freq_a` - First frequency in hertz, greater than 0.
+    /// * `freq_b` - Second frequency in hertz, greater than 0.
+    /// * `switch_interval` - Duration of each frequency in seconds, greater than 0.
+    /// * `total_duration` - Duration of the warble in seconds, 0 or more.
+    pub fn warble(&mut self, freq_a: f64, freq_b: f64, switch_interval: f64, total_duration: f64) -> Result<(), Error> {
+
+        Buzzer::check_note(freq_a, total_duration)?;
+        Buzzer::check_note(freq_b, total_duration)?;
+        if switch_interval.is_nan() || switch_interval <= 0.0 {
+            return Err(Error::InvalidArgument(invalid_input("switch interval", switch_interval, "greater than 0 s")));
+        }
+
+        if !self.is_setup {
+            self.setup()?;
+        }
+
+        let interval = Duration::from_secs_f64(switch_interval);
+        let total = Duration::from_secs_f64(total_duration);
+        let mut elapsed = Duration::ZERO;
+        let mut index = 0;
 
-            thread::sleep(Duration::from_millis((duration * 1000.0) as u64));
+        while elapsed < total {
+            let segment = interval.min(total - elapsed);
+            let frequency = if index & 1 == 0 { freq_a } else { freq_b };
 
-            pin.clear_pwm()?;
+            self.tracer.trace(|| format!("Buzzer warble: {:.1} Hz for {} s", frequency, segment.as_secs_f64()));
+
+            // Only perform actual pin write if not in simulation mode
+            if !self.simulation {
+
+                let pin = self.pin.as_deref_mut().unwrap();
+
+                if let Err(err) = pin.start_tone(frequency) {
+                    let _result = pin.stop_tone();
+                    return Err(err);
+                }
+
+                self.clock.sleep(segment);
+            } else {
+                self.recorder.record(Component::Buzzer, "warble", Payload::Tone { frequency, duration: segment });
+            }
+
+            elapsed += segment;
+            index += 1;
+        }
+
+        if !self.simulation && index > 0 {
+            self.pin.as_deref_mut().unwrap().stop_tone()?;
+        }
+
+        Ok(())
+    }
+
+    /// Ramp the frequency from a start to an end frequency in discrete steps of the same duration,
+    /// without silence between them: the first step plays the start frequency and the last one the end frequency.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_hz` - Frequency of the first step in hertz, greater than 0.
+    /// * `end_hz` - Frequency of the last step in hertz, greater than 0.
+    /// * `duration` - Duration of the sweep in seconds, 0 or more.
+    /// * `steps` - Number of steps, at least 1.
+    pub fn sweep(&mut self, start_hz: f64, end_hz: f64, duration: f64, steps: u32) -> Result<(), Error> {
+
+        Buzzer::check_note(start_hz, duration)?;
+        Buzzer::check_note(end_hz, duration)?;
+        if steps == 0 {
+            return Err(Error::InvalidArgument(invalid_input("steps", steps, "at least 1")));
+        }
+
+        let tones = Buzzer::ramp(start_hz, end_hz, duration, steps);
+        self.glide(&tones, "sweep")
+    }
+
+    /// Sweep up from a low to a high frequency then back down, repeatedly, as a classic siren.
+    /// Each half of a cycle is a sweep of `SIREN_STEPS` steps.
+    ///
+    /// # Arguments
+    ///
+    /// * `low_hz` - Lowest frequency in hertz, greater than 0.
+    /// * `high_hz` - Highest frequency in hertz, greater than 0.
+    /// * `period` - Duration of each rise and fall in seconds, 0 or more.
+    /// * `cycles` - Number of rises and falls.
+    pub fn siren(&mut self, low_hz: f64, high_hz: f64, period: f64, cycles: u32) -> Result<(), Error> {
+
+        Buzzer::check_note(low_hz, period)?;
+        Buzzer::check_note(high_hz, period)?;
+
+        let mut tones = Vec::new();
+        for _ in 0..cycles {
+            tones.extend(Buzzer::ramp(low_hz, high_hz, period / 2.0, SIREN_STEPS));
+            tones.extend(Buzzer::ramp(high_hz, low_hz, period / 2.0, SIREN_STEPS));
+        }
+        self.glide(&tones, "siren")
+    }
+
+    /// Get the frequency and duration of each step of a linear sweep.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_hz` - Frequency of the first step in hertz.
+    /// * `end_hz` - Frequency of the last step in hertz.
+    /// * `duration` - Duration of the sweep in seconds.
+    /// * `steps` - Number of steps, at least 1.
+    fn ramp(start_hz: f64, end_hz: f64, duration: f64, steps: u32) -> Vec<(f64, Duration)> {
+        let step = Duration::from_secs_f64(duration / steps as f64);
+
+        (0..steps).map(|i| {
+            let progress = if steps > 1 { i as f64 / (steps - 1) as f64 } else { 0.0 };
+            (start_hz + (end_hz - start_hz) * progress, step)
+        }).collect()
+    }
+
+    /// Play tones one after the other without silence between them, then stop the tone.
+    ///
+    /// # Arguments
+    ///
+    /// * `tones` - Frequency in hertz and duration of each tone, already validated.
+    /// * `operation` - Name of the effect in the traces and the recorder.
+    fn glide(&mut self, tones: &[(f64, Duration)], operation: &'static str) -> Result<(), Error> {
+
+        if !self.is_setup {
+            self.setup()?;
+        }
+
+        for &(frequency, duration) in tones {
+            self.tracer.trace(|| format!("Buzzer {}: {:.1} Hz for {} s", operation, frequency, duration.as_secs_f64()));
+
+            // Only perform actual pin write if not in simulation mode
+            if !self.simulation {
+
+                let pin = self.pin.as_deref_mut().unwrap();
+
+                if let Err(err) = pin.start_tone(frequency) {
+                    let _result = pin.stop_tone();
+                    return Err(err);
+                }
+
+                self.clock.sleep(duration);
+            } else {
+                self.recorder.record(Component::Buzzer, operation, Payload::Tone { frequency, duration });
+            }
+        }
+
+        if !self.simulation && !tones.is_empty() {
+            self.pin.as_deref_mut().unwrap().stop_tone()?;
+        }
+
+        Ok(())
+    }
+
+    /// Play a single note.
+    ///
+    /// # Arguments
+    ///
+    /// * `frequency` - Musical frequency in hertz, greater than 0.
+    /// * `duration` - Duration in seconds, 0 or more.
+    #[deprecated(note = "note returns the error")]
+    pub fn try_note(&mut self, frequency : f64, duration: f64) -> Result<(), Error>{
+        self.note(frequency, duration)
+    }
+
+    /// Validates the frequency and the duration of a note.
+    ///
+    /// # Arguments
+    ///
+    /// * `frequency` - Musical frequency in hertz, greater than 0.
+    /// * `duration` - Duration in seconds, 0 or more.
+    fn check_note(frequency : f64, duration: f64) -> Result<(), Error>{
+
+        if frequency.is_nan() || frequency <= 0.0 {
+            return Err(Error::InvalidArgument(invalid_input("frequency", frequency, "greater than 0 Hz")));
+        }
+        if duration.is_nan() || duration < 0.0 {
+            return Err(Error::InvalidArgument(invalid_input("duration", duration, "0 s or more")));
         }
 
         Ok(())
@@ -83,11 +534,13 @@ impl Buzzer {
     ///
     /// # Arguments
     ///
-    /// * `note_number` - MIDI note number of note.
-    /// * `duration` - Duration in seconds.
+    /// * `note_number` - MIDI note number of note, greater than 0.
+    /// * `duration` - Duration in seconds, 0 or more.
     pub fn midi_note(&mut self, note_number : u32, duration: f64) -> Result <(), Error>{
-        
-        assert!(note_number > 0);
+
+        if note_number == 0 {
+            return Err(Error::InvalidArgument(invalid_input("MIDI note", note_number, "greater than 0")));
+        }
 
         let freq = Buzzer::midi_note_to_frequency(note_number);
         self.note(freq, duration)?;
@@ -95,13 +548,24 @@ impl Buzzer {
         Ok(())
     }
 
+    /// Play a single note by MIDI note number.
+    ///
+    /// # Arguments
+    ///
+    /// * `note_number` - MIDI note number of note, greater than 0.
+    /// * `duration` - Duration in seconds, 0 or more.
+    #[deprecated(note = "midi_note returns the error")]
+    pub fn try_midi_note(&mut self, note_number : u32, duration: f64) -> Result <(), Error>{
+        self.midi_note(note_number, duration)
+    }
+
     /// Get the frequency in Hz from the midi note.
     ///
     /// # Arguments
     ///
     /// * `note_number` - Midi note number.
     fn midi_note_to_frequency(note_number : u32) -> f64 {
-        assert!(note_number > 0);
+        debug_assert!(note_number > 0);
 
         let base: f64 = 2.0;
         base.powf((note_number as f64 - 69.0) / 12.0) * 440.0
@@ -112,49 +576,444 @@ impl Buzzer {
     pub fn stop(&mut self) -> Result <(), Error>{
 
         if !self.is_setup {
-            let _result = self.setup();
+            self.setup()?;
         }
 
+        self.tracer.trace(|| "Buzzer stop".to_string());
+
         // Only perform actual pin write if not in simulation mode
         if !self.simulation {
 
             let pin = self.pin.as_deref_mut().unwrap();
-            pin.clear_pwm()?;
+            pin.stop_tone()?;
+        } else {
+            self.recorder.record(Component::Buzzer, "stop", Payload::None);
         }
 
         Ok(())
     }
+
+    /// Play a song in the RTTTL (Ring Tone Text Transfer Language) format of the Nokia ring tones,
+    /// for example "scale:d=4,o=5,b=120:c,d,e,f,g,a,b,c6".
+    ///
+    /// # Arguments
+    ///
+    /// * `song` - Song in the RTTTL format.
+    pub fn play_rtttl(&mut self, song: &str) -> Result <(), Error> {
+
+        for (note, duration) in parse_rtttl(song)? {
+            match note {
+                Some(note_number) => self.midi_note(note_number, duration)?,
+
+                // Rests are only waited for if not in simulation mode
+                None => if !self.simulation {
+                    thread::sleep(Duration::from_millis((duration * 1000.0) as u64));
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Play an alert tone. In simulation mode, the silence between two beeps is not waited for.
+    ///
+    /// # Arguments
+    ///
+    /// * `tone` - Tone to play.
+    pub fn alert(&mut self, tone: AlertTone) -> Result<(), Error> {
+        match tone {
+            AlertTone::Silent => {}
+            AlertTone::Beep => self.midi_note(81, 0.15)?,
+            AlertTone::DoubleBeep => {
+                self.midi_note(81, 0.1)?;
+                if !self.simulation {
+                    self.clock.sleep(Duration::from_millis(100));
+                }
+                self.midi_note(81, 0.1)?;
+            }
+            AlertTone::Chime => {
+                for &note_number in [76, 79, 84].iter() {
+                    self.midi_note(note_number, 0.12)?;
+                }
+            }
+            AlertTone::Urgent => self.warble(880.0, 660.0, 0.1, 0.8)?,
+        }
+
+        Ok(())
+    }
+
+    /// Click at a tempo for a number of beats, for music practice or timing.
+    /// Each click is scheduled from the first one rather than from the previous one, so the time taken
+    /// to play the clicks doesn't make the tempo drift. In simulation mode, the beats are not waited for.
+    ///
+    /// # Arguments
+    ///
+    /// * `bpm` - Tempo in beats per minute, greater than 0.
+    /// * `beats` - Number of beats.
+    /// * `accent_first` - Whether to click the first beat of each bar of `METRONOME_BEATS_PER_BAR` beats with a higher pitch.
+    pub fn metronome(&mut self, bpm: u32, beats: u32, accent_first: bool) -> Result<(), Error> {
+
+        if bpm == 0 {
+            return Err(Error::InvalidArgument(invalid_input("tempo", format!("{} bpm", bpm), "greater than 0 bpm")));
+        }
+
+        let interval = Duration::from_secs(60) / bpm;
+        let click = METRONOME_CLICK_DURATION.min(interval.as_secs_f64() / 2.0);
+        let start = self.clock.now();
+
+        for beat in 0..beats {
+            let next = start + interval * beat;
+            let now = self.clock.now();
+            if !self.simulation && next > now {
+                self.clock.sleep(next - now);
+            }
+
+            let note_number = if accent_first && beat.is_multiple_of(METRONOME_BEATS_PER_BAR) { METRONOME_ACCENT_NOTE } else { METRONOME_NOTE };
+            self.midi_note(note_number, click)?;
+        }
+
+        Ok(())
+    }
+
+    /// Move the buzzer behind a mutex so it can be shared between threads.
+    pub fn into_shared(self) -> SharedBuzzer {
+        SharedBuzzer {
+            inner: Arc::new(Mutex::new(self)),
+        }
+    }
+}
+
+/// Handle to a buzzer shared between threads, Send and Sync.
+/// Clones refer to the same buzzer. The notes are played on a background thread, so the callers
+/// never wait for them; notes requested at the same time are played one after the other.
+#[derive(Debug, Clone)]
+pub struct SharedBuzzer {
+
+    /// Shared buzzer.
+    inner: Arc<Mutex<Buzzer>>,
+}
+
+impl SharedBuzzer {
+
+    /// Locks the buzzer. A poisoned lock is recovered as the buzzer state stays consistent between calls.
+    fn lock(inner: &Mutex<Buzzer>) -> MutexGuard<'_, Buzzer> {
+        inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Enables simulation mode: no interaction with the hardware is done.
+    /// Waits for the notes being played.
+    pub fn enable_simulation(&self) {
+        SharedBuzzer::lock(&self.inner).enable_simulation();
+    }
+
+    /// Get whether simulation mode is enabled.
+    /// Waits for the notes being played.
+    pub fn is_simulation(&self) -> bool {
+        SharedBuzzer::lock(&self.inner).is_simulation()
+    }
+
+    /// Set a hook receiving a message before each hardware operation of the shared buzzer.
+    /// Waits for the notes being played.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Closure receiving each message.
+    pub fn set_trace<F: FnMut(&str) + Send + 'static>(&self, hook: F) {
+        SharedBuzzer::lock(&self.inner).set_trace(hook);
+    }
+
+    /// Remove the trace hook of the shared buzzer.
+    pub fn clear_trace(&self) {
+        SharedBuzzer::lock(&self.inner).clear_trace();
+    }
+
+    /// Play a single note in the background.
+    /// An invalid frequency is reported by `Playback::wait`.
+    ///
+    /// # Arguments
+    ///
+    /// * `frequency` - Musical frequency in hertz, greater than 0.
+    /// * `duration` - Duration in seconds.
+    pub fn beep(&self, frequency: f64, duration: f64) -> Playback {
+        self.spawn(move |buzzer| buzzer.note(frequency, duration))
+    }
+
+    /// Play a sequence of MIDI notes in the background, without notes of other callers in between.
+    /// An invalid note is reported by `Playback::wait` and ends the sequence.
+    ///
+    /// # Arguments
+    ///
+    /// * `notes` - MIDI note numbers, greater than 0, with their duration in seconds.
+    pub fn play(&self, notes: &[(u32, f64)]) -> Playback {
+        let notes = notes.to_vec();
+
+        self.spawn(move |buzzer| {
+            for (note_number, duration) in notes {
+                buzzer.midi_note(note_number, duration)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Run the given operation on a background thread holding the lock.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - Operation using the buzzer.
+    fn spawn<F: FnOnce(&mut Buzzer) -> Result<(), Error> + Send + 'static>(&self, operation: F) -> Playback {
+        let inner = self.inner.clone();
+
+        Playback {
+            handle: thread::spawn(move || operation(&mut SharedBuzzer::lock(&inner))),
+        }
+    }
+}
+
+/// Notes being played in the background by a `SharedBuzzer`.
+/// Dropping it does not stop the notes.
+#[derive(Debug)]
+pub struct Playback {
+
+    /// Thread playing the notes.
+    handle: JoinHandle<Result<(), Error>>,
+}
+
+impl Playback {
+
+    /// Get whether all the notes were played.
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Wait until all the notes are played.
+    pub fn wait(self) -> Result<(), Error> {
+        match self.handle.join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+/// Get the MIDI note number of a note name, such as "A4" (69), "C#5" or "Bb3".
+/// The octave goes from -1 to 9, C4 being the middle C.
+///
+/// # Arguments
+///
+/// * `name` - Letter from A to G, optional # or b accidental, then octave.
+pub fn parse_note_name(name: &str) -> Result<u32, Error> {
+    let invalid = || Error::InvalidArgument(invalid_input("note name", format!("{:?}", name), "a letter, an optional # or b, and an octave"));
+
+    let mut chars = name.chars();
+    let mut semitone = match chars.next().map(|c| c.to_ascii_uppercase()) {
+        Some('C') => 0,
+        Some('D') => 2,
+        Some('E') => 4,
+        Some('F') => 5,
+        Some('G') => 7,
+        Some('A') => 9,
+        Some('B') => 11,
+        _ => return Err(invalid()),
+    };
+
+    let rest = chars.as_str();
+    let octave = if let Some(octave) = rest.strip_prefix('#') {
+        semitone += 1;
+        octave
+    } else if let Some(octave) = rest.strip_prefix('b') {
+        semitone -= 1;
+        octave
+    } else {
+        rest
+    };
+
+    let octave: i32 = octave.parse().map_err(|_| invalid())?;
+    let note_number = (octave + 1) * 12 + semitone;
+
+    if !(-1..=9).contains(&octave) || note_number < 1 {
+        return Err(invalid());
+    }
+
+    Ok(note_number as u32)
+}
+
+/// Parse a song in the RTTTL (Ring Tone Text Transfer Language) format: a name, the defaults
+/// (d: duration, o: octave, b: beats per minute), then the notes, each with an optional duration,
+/// a letter or p for a rest, an optional sharp, an optional dot lengthening it by half and an optional octave.
+/// Returns the MIDI note number of each note, None for a rest, with its duration in seconds.
+///
+/// # Arguments
+///
+/// * `song` - Song in the RTTTL format, for example "scale:d=4,o=5,b=120:c,d,e,8f#,g.,p,c6".
+pub fn parse_rtttl(song: &str) -> Result<Vec<(Option<u32>, f64)>, Error> {
+    let invalid = |reason: String| Error::InvalidArgument(format!("RTTTL song: {}", reason));
+
+    let sections: Vec<&str> = song.trim().splitn(3, ':').collect();
+    if sections.len() != 3 {
+        return Err(invalid("expected a name, the defaults and the notes separated by ':'".to_string()));
+    }
+
+    // Defaults of the specification
+    let (mut default_duration, mut default_octave, mut bpm) = (4, 6, 63);
+
+    for setting in sections[1].split(',').map(str::trim).filter(|setting| !setting.is_empty()) {
+        let (key, value) = setting.split_once('=').ok_or_else(|| invalid(format!("invalid default {:?}", setting)))?;
+        let value: u32 = value.trim().parse().map_err(|_| invalid(format!("invalid default {:?}", setting)))?;
+
+        match key.trim() {
+            "d" => default_duration = value,
+            "o" => default_octave = value,
+            "b" => bpm = value,
+            _ => return Err(invalid(format!("unknown default {:?}", setting))),
+        }
+    }
+
+    if default_duration == 0 || bpm == 0 {
+        return Err(invalid("the default duration and the beats per minute must be greater than 0".to_string()));
+    }
+
+    // A whole note lasts 4 beats
+    let whole = 4.0 * 60.0 / bpm as f64;
+
+    sections[2].split(',').map(str::trim).filter(|note| !note.is_empty()).map(|note| {
+        let error = || invalid(format!("invalid note {:?}", note));
+        let lower = note.to_ascii_lowercase();
+        let (duration, rest) = lower.split_at(lower.find(|c: char| !c.is_ascii_digit()).ok_or_else(error)?);
+
+        let duration = if duration.is_empty() { default_duration } else { duration.parse().map_err(|_| error())? };
+        if duration == 0 {
+            return Err(error());
+        }
+
+        let mut chars = rest.chars().peekable();
+        let semitone = match chars.next() {
+            Some('p') => None,
+            Some('c') => Some(0),
+            Some('d') => Some(2),
+            Some('e') => Some(4),
+            Some('f') => Some(5),
+            Some('g') => Some(7),
+            Some('a') => Some(9),
+            Some('b') | Some('h') => Some(11),
+            _ => return Err(error()),
+        };
+
+        let sharp = chars.next_if_eq(&'#').is_some();
+        let mut dotted = chars.next_if_eq(&'.').is_some();
+        let octave = match chars.next_if(char::is_ascii_digit) {
+            Some(digit) => digit.to_digit(10).unwrap(),
+            None => default_octave,
+        };
+        dotted |= chars.next_if_eq(&'.').is_some();
+
+        if chars.next().is_some() {
+            return Err(error());
+        }
+
+        let mut seconds = whole / duration as f64;
+        if dotted {
+            seconds *= 1.5;
+        }
+
+        let note_number = semitone.map(|semitone| (octave + 1) * 12 + semitone + if sharp { 1 } else { 0 });
+
+        Ok((note_number, seconds))
+    }).collect()
+}
+
+/// Tone being played by `note_async`, stopped when dropped so a cancelled note does not keep sounding.
+#[cfg(feature = "async")]
+struct ToneGuard<'a> {
+
+    /// Output playing the tone, None once stopped.
+    pin: Option<&'a mut dyn ToneOutput>,
+}
+
+#[cfg(feature = "async")]
+impl ToneGuard<'_> {
+
+    /// Stop the tone at the end of the note.
+    fn stop(mut self) -> Result<(), Error> {
+        match self.pin.take() {
+            Some(pin) => pin.stop_tone(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for ToneGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(pin) = self.pin.take() {
+            let _result = pin.stop_tone();
+        }
+    }
 }
 
 /// Errors that can occur.
 #[derive(Debug)]
 pub enum Error {
 
-    /// Gpio error.
-    Gpio(rppal::gpio::Error),
+    /// Gpio error configuring the pin of the buzzer.
+    #[cfg(feature = "hardware")]
+    Gpio {
+
+        /// GPIO pin number using the BCM pin numbering.
+        pin: u8,
+
+        /// Error of the GPIO.
+        source: rppal::gpio::Error,
+    },
+
+    /// The pin of the buzzer is already used by another driver of the process.
+    PinAlreadyInUse {
+
+        /// GPIO pin number using the BCM pin numbering.
+        pin: u8,
+
+        /// Driver using the pin, such as "light".
+        owner: &'static str,
+    },
+
+    /// Invalid argument.
+    InvalidArgument(String),
 }
 
-impl std::error::Error for Error {}
+/// Converts a conflict of pins
+impl From<PinInUse> for Error {
+    fn from(err: PinInUse) -> Error {
+        Error::PinAlreadyInUse { pin: err.pin, owner: err.owner }
+    }
+}
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &*self {
-            Error::Gpio(err) => write!(f, "Gpio error: {}", &err),
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "hardware")]
+            Error::Gpio { source, .. } => Some(source),
+            Error::PinAlreadyInUse { .. } => None,
+            Error::InvalidArgument(_) => None,
         }
     }
 }
 
-/// Converts Gpio error
-impl From<rppal::gpio::Error> for Error {
-    fn from(err: rppal::gpio::Error) -> Error {
-        Error::Gpio(err)
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "hardware")]
+            Error::Gpio { pin, source } => write!(f, "failed to configure BCM {} for buzzer: {}", pin, &source),
+            Error::PinAlreadyInUse { pin, owner } => write!(f, "BCM {} is already used by the {}", pin, owner),
+            Error::InvalidArgument(msg) => write!(f, "Invalid argument: {}", &msg),
+        }
     }
 }
 
 /// Unit tests
 #[cfg(test)]
+#[allow(clippy::bool_comparison, clippy::needless_range_loop)]
 mod tests {
     use super::*;
+    use crate::recorder::Entry;
+    use crate::testing::{assert_order, Scenario};
 
     /// Tests the setup of the buzzer.
     #[test]
@@ -196,50 +1055,463 @@ mod tests {
     /// Tests note.
     #[test]
     fn test_buzzer_note() -> Result<(), Error> {
+        let recorder = MemoryRecorder::new();
         let mut buzzer = Buzzer::new()?;
         // enable simulation
         buzzer.simulation = true;
+        buzzer.set_recorder(recorder.clone());
 
         buzzer.note(493.0, 0.5)?;
+        buzzer.stop()?;
 
         assert!(buzzer.is_setup == true);
+        assert_order(&recorder, &["buzzer:note", "buzzer:stop"]);
+        assert!(recorder.entries()[0].payload == Payload::Tone { frequency: 493.0, duration: Duration::from_millis(500) });
 
         Ok(())
     }
 
     /// Tests invalid note.
     #[test]
-    #[should_panic]
-    fn test_buzzer_note_invalid() {
-        let mut buzzer = Buzzer::new().unwrap();
+    fn test_buzzer_note_invalid() -> Result<(), Error> {
+        let mut buzzer = Buzzer::new()?;
         // enable simulation
         buzzer.simulation = true;
 
-        let _result = buzzer.note(-1.0, 0.5);
+        let err = buzzer.note(-1.0, 0.5).unwrap_err();
+        assert!(err.to_string() == "Invalid argument: invalid frequency -1, expected greater than 0 Hz");
+
+        assert!(matches!(buzzer.note(0.0, 0.5), Err(Error::InvalidArgument(_))));
+        assert!(matches!(buzzer.note(f64::NAN, 0.5), Err(Error::InvalidArgument(_))));
+        assert!(matches!(buzzer.note(440.0, -0.5), Err(Error::InvalidArgument(_))));
+        assert!(matches!(buzzer.note(440.0, f64::NAN), Err(Error::InvalidArgument(_))));
+
+        // The buzzer is not setup for a rejected note
+        assert!(!buzzer.is_setup);
+
+        Ok(())
     }
 
     /// Tests midi note.
     #[test]
     fn test_buzzer_midi_note() -> Result<(), Error> {
+        let recorder = MemoryRecorder::new();
         let mut buzzer = Buzzer::new()?;
         // enable simulation
         buzzer.simulation = true;
+        buzzer.set_recorder(recorder.clone());
 
-        buzzer.midi_note(71, 0.5)?;
+        buzzer.midi_note(69, 0.5)?;
+        buzzer.midi_note(81, 0.0)?;
 
         assert!(buzzer.is_setup == true);
+        assert_order(&recorder, &["buzzer:note", "buzzer:note"]);
+        let frequencies: Vec<f64> = recorder.entries().iter().filter_map(|entry| match entry.payload {
+            Payload::Tone { frequency, .. } => Some(frequency),
+            _ => None,
+        }).collect();
+        assert!(frequencies == [440.0, 880.0]);
 
         Ok(())
     }
 
     /// Tests invalid midi note.
     #[test]
-    #[should_panic]
-    fn test_buzzer_midi_note_invalid() {
-        let mut buzzer = Buzzer::new().unwrap();
+    fn test_buzzer_midi_note_invalid() -> Result<(), Error> {
+        let mut buzzer = Buzzer::new()?;
+        // enable simulation
+        buzzer.simulation = true;
+
+        assert!(matches!(buzzer.midi_note(0, 0.5), Err(Error::InvalidArgument(_))));
+        assert!(matches!(buzzer.midi_note(69, -0.5), Err(Error::InvalidArgument(_))));
+
+        Ok(())
+    }
+
+    /// Tests the deprecated fallible variants still reject invalid input.
+    #[test]
+    #[allow(deprecated)]
+    fn test_buzzer_try_variants() -> Result<(), Error> {
+        let mut buzzer = Buzzer::new()?;
         // enable simulation
         buzzer.simulation = true;
 
-        let _result = buzzer.midi_note(0, 0.5);
+        assert!(matches!(buzzer.try_note(-1.0, 0.5), Err(Error::InvalidArgument(_))));
+        assert!(matches!(buzzer.try_note(0.0, 0.5), Err(Error::InvalidArgument(_))));
+        assert!(matches!(buzzer.try_note(f64::NAN, 0.5), Err(Error::InvalidArgument(_))));
+        assert!(matches!(buzzer.try_midi_note(0, 0.5), Err(Error::InvalidArgument(_))));
+
+        buzzer.try_note(493.0, 0.0)?;
+        buzzer.try_midi_note(71, 0.0)?;
+
+        Ok(())
+    }
+
+    /// Tests creating the driver in simulation mode, the notes being recorded when played instead of waited for.
+    #[test]
+    fn test_buzzer_with_simulation() -> Result<(), crate::Error> {
+        let mut buzzer = Buzzer::with_simulation()?;
+        assert!(buzzer.simulation);
+
+        buzzer.note(440.0, 0.0)?;
+
+        // A note played on each touch of a button of a simulated board
+        let scenario = Scenario::builder()
+            .tap(Duration::from_millis(250), crate::touch::ButtonId::A, Duration::from_millis(100))
+            .tap(Duration::from_secs(1), crate::touch::ButtonId::A, Duration::from_millis(100))
+            .build();
+        let mut hat = scenario.board()?;
+
+        scenario.run(&mut hat, |hat| {
+            if hat.buttons.a.poll_event() == Some(crate::touch::TouchEvent::Pressed) {
+                hat.buzzer.note(440.0, 10.0)?;
+            }
+            Ok(())
+        })?;
+
+        scenario.assert_order(&["buzzer:note", "buzzer:note"]);
+        let instants: Vec<std::time::Instant> = hat.recording().iter().map(|entry| entry.timestamp).collect();
+        assert!(instants.len() == 2 && instants[1] - instants[0] == Duration::from_millis(1100));
+
+        Ok(())
+    }
+
+    /// Tests enabling simulation mode on a driver created for the hardware.
+    #[test]
+    fn test_buzzer_enable_simulation() -> Result<(), Error> {
+        let mut buzzer = Buzzer::new()?;
+        assert!(buzzer.is_simulation() != cfg!(feature = "hardware"));
+
+        buzzer.enable_simulation();
+        assert!(buzzer.is_simulation());
+        buzzer.note(440.0, 0.0)?;
+
+        Ok(())
+    }
+
+    /// Output recording the tones played, None when stopped.
+    #[derive(Debug)]
+    struct RecordingTone {
+        tones: std::sync::Arc<std::sync::Mutex<Vec<Option<f64>>>>,
+    }
+
+    impl ToneOutput for RecordingTone {
+        fn start_tone(&mut self, frequency: f64) -> Result<(), Error> {
+            self.tones.lock().unwrap().push(Some(frequency));
+            Ok(())
+        }
+
+        fn stop_tone(&mut self) -> Result<(), Error> {
+            self.tones.lock().unwrap().push(None);
+            Ok(())
+        }
+    }
+
+    /// Tests a buzzer playing on a custom output.
+    #[test]
+    fn test_buzzer_with_pin() -> Result<(), Error> {
+        let tones = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut buzzer = Buzzer::with_pin(RecordingTone { tones: tones.clone() });
+        assert!(!buzzer.is_simulation());
+
+        buzzer.note(440.0, 0.0)?;
+        buzzer.stop()?;
+        assert!(*tones.lock().unwrap() == [Some(440.0), None, None]);
+
+        Ok(())
+    }
+
+    /// Tests a pin used by two buzzers of the same registry is rejected in simulation mode, also when playing a note,
+    /// until the first buzzer is released or dropped.
+    #[test]
+    fn test_buzzer_pin_in_use() -> Result<(), Error> {
+        let registry = PinRegistry::new();
+        let mut first = Buzzer::with_simulation()?;
+        first.set_pin_registry(registry.clone());
+        first.setup()?;
+
+        let mut second = Buzzer::with_simulation()?;
+        second.set_pin_registry(registry);
+        assert!(matches!(second.setup(), Err(Error::PinAlreadyInUse { pin: GPIO_BUZZER, owner: "buzzer" })));
+        assert!(matches!(second.note(440.0, 0.0), Err(Error::PinAlreadyInUse { pin: GPIO_BUZZER, .. })));
+
+        // A buzzer of its own registry is free
+        Buzzer::with_simulation()?.setup()?;
+
+        // Released, then dropped
+        first.release();
+        second.note(440.0, 0.0)?;
+        second.release();
+        first.setup()?;
+        drop(first);
+        second.setup()?;
+
+        Ok(())
+    }
+
+    /// Output recording the instant and frequency of each tone, taking some time to stop it.
+    #[derive(Debug)]
+    struct TimedTone {
+        clock: crate::clock::ManualClock,
+        starts: std::sync::Arc<std::sync::Mutex<Vec<(std::time::Instant, f64)>>>,
+    }
+
+    impl ToneOutput for TimedTone {
+        fn start_tone(&mut self, frequency: f64) -> Result<(), Error> {
+            self.starts.lock().unwrap().push((self.clock.now(), frequency));
+            Ok(())
+        }
+
+        fn stop_tone(&mut self) -> Result<(), Error> {
+            self.clock.advance(Duration::from_millis(3));
+            Ok(())
+        }
+    }
+
+    /// Tests the metronome clicks at the tempo without drifting, accenting the first beat of each bar.
+    #[test]
+    fn test_buzzer_metronome() -> Result<(), Error> {
+        let clock = crate::clock::ManualClock::new();
+        let starts = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut buzzer = Buzzer::with_pin(TimedTone { clock: clock.clone(), starts: starts.clone() });
+        buzzer.set_clock(clock.clone());
+
+        buzzer.metronome(120, 9, true)?;
+
+        let starts = starts.lock().unwrap();
+        assert!(starts.len() == 9);
+        for (beat, (instant, _frequency)) in starts.iter().enumerate() {
+            assert!(*instant - starts[0].0 == Duration::from_millis(500) * beat as u32);
+        }
+
+        let accent = Buzzer::midi_note_to_frequency(METRONOME_ACCENT_NOTE);
+        let click = Buzzer::midi_note_to_frequency(METRONOME_NOTE);
+        assert!(accent > click);
+        let frequencies: Vec<f64> = starts.iter().map(|(_instant, frequency)| *frequency).collect();
+        assert!(frequencies == [accent, click, click, click, accent, click, click, click, accent]);
+
+        Ok(())
+    }
+
+    /// Tests a warble alternates between the frequencies, shortening the last one.
+    #[test]
+    fn test_buzzer_warble() -> Result<(), Error> {
+        let clock = crate::clock::ManualClock::new();
+        let starts = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut buzzer = Buzzer::with_pin(TimedTone { clock: clock.clone(), starts: starts.clone() });
+        buzzer.set_clock(clock.clone());
+        let start = clock.now();
+
+        buzzer.warble(880.0, 660.0, 0.1, 0.35)?;
+
+        let starts = starts.lock().unwrap();
+        let frequencies: Vec<f64> = starts.iter().map(|(_instant, frequency)| *frequency).collect();
+        assert!(frequencies == [880.0, 660.0, 880.0, 660.0]);
+        for (index, (instant, _frequency)) in starts.iter().enumerate() {
+            assert!(*instant - start == Duration::from_millis(100) * index as u32);
+        }
+
+        // Ends after the last 50 ms, then stops the tone taking 3 ms
+        assert!(clock.now() - start == Duration::from_millis(353));
+
+        assert!(matches!(buzzer.warble(880.0, 660.0, 0.0, 1.0), Err(Error::InvalidArgument(_))));
+        assert!(matches!(buzzer.warble(880.0, -1.0, 0.1, 1.0), Err(Error::InvalidArgument(_))));
+
+        // In simulation mode, the warble is traced without waiting
+        let messages = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = messages.clone();
+        let mut buzzer = Buzzer::with_simulation()?;
+        buzzer.set_trace(move |message| recorded.lock().unwrap().push(message.to_string()));
+        buzzer.warble(880.0, 660.0, 0.25, 0.5)?;
+        assert!(*messages.lock().unwrap() == ["Buzzer warble: 880.0 Hz for 0.25 s", "Buzzer warble: 660.0 Hz for 0.25 s"]);
+
+        Ok(())
+    }
+
+    /// Tests the sweeps ramp the frequency monotonically from the start to the end frequency.
+    #[test]
+    fn test_buzzer_sweep() -> Result<(), Error> {
+        let recorder = MemoryRecorder::new();
+        let mut buzzer = Buzzer::with_simulation()?;
+        buzzer.set_recorder(recorder.clone());
+
+        buzzer.sweep(440.0, 880.0, 1.0, 5)?;
+        buzzer.sweep(880.0, 440.0, 0.5, 3)?;
+        let tones: Vec<(f64, Duration)> = recorder.entries().iter().map(|entry| match entry.payload {
+            Payload::Tone { frequency, duration } if entry.operation == "sweep" => (frequency, duration),
+            ref other => panic!("unexpected payload: {:?}", other),
+        }).collect();
+        assert!(tones[..5].iter().map(|tone| tone.0).eq([440.0, 550.0, 660.0, 770.0, 880.0]));
+        assert!(tones[..5].iter().all(|tone| tone.1 == Duration::from_millis(200)));
+        assert!(tones[5..].iter().map(|tone| tone.0).eq([880.0, 660.0, 440.0]));
+
+        // A single step plays the start frequency
+        recorder.retain(&mut |_entry| false);
+        buzzer.sweep(300.0, 600.0, 0.1, 1)?;
+        assert!(matches!(recorder.entries()[..], [Entry { payload: Payload::Tone { frequency, .. }, .. }] if frequency == 300.0));
+
+        assert!(matches!(buzzer.sweep(440.0, 880.0, 1.0, 0), Err(Error::InvalidArgument(msg)) if msg == "invalid steps 0, expected at least 1"));
+        assert!(matches!(buzzer.sweep(0.0, 880.0, 1.0, 4), Err(Error::InvalidArgument(_))));
+
+        // The siren rises then falls on each cycle, without stopping the tone between the steps
+        let clock = crate::clock::ManualClock::new();
+        let starts = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut buzzer = Buzzer::with_pin(TimedTone { clock: clock.clone(), starts: starts.clone() });
+        buzzer.set_clock(clock.clone());
+        let start = clock.now();
+
+        buzzer.siren(600.0, 1200.0, 0.32, 2)?;
+
+        let frequencies: Vec<f64> = starts.lock().unwrap().iter().map(|(_instant, frequency)| *frequency).collect();
+        let half = SIREN_STEPS as usize;
+        assert!(frequencies.len() == 4 * half);
+        assert!(frequencies[..half].windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(frequencies[half..2 * half].windows(2).all(|pair| pair[0] > pair[1]));
+        assert!(frequencies[0] == 600.0 && frequencies[half - 1] == 1200.0 && frequencies[2 * half] == 600.0);
+        assert!(clock.now() - start == Duration::from_millis(643));
+
+        Ok(())
+    }
+
+    /// Tests the metronome without accent and its invalid tempo.
+    #[test]
+    fn test_buzzer_metronome_no_accent() -> Result<(), Error> {
+        let tones = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut buzzer = Buzzer::with_pin(RecordingTone { tones: tones.clone() });
+        buzzer.set_clock(crate::clock::ManualClock::new());
+
+        buzzer.metronome(600, 3, false)?;
+        let click = Some(Buzzer::midi_note_to_frequency(METRONOME_NOTE));
+        assert!(*tones.lock().unwrap() == [click, None, click, None, click, None]);
+
+        assert!(matches!(buzzer.metronome(0, 3, true), Err(Error::InvalidArgument(_))));
+
+        Ok(())
+    }
+
+    /// Tests a note played from an async task is silenced when the future is dropped.
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_buzzer_note_async_cancelled() -> Result<(), Error> {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        let tones = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut buzzer = Buzzer::with_pin(RecordingTone { tones: tones.clone() });
+
+        runtime.block_on(buzzer.note_async(440.0, 0.01))?;
+        assert!(*tones.lock().unwrap() == [Some(440.0), None]);
+
+        let timeout = std::time::Duration::from_millis(10);
+        assert!(runtime.block_on(async { tokio::time::timeout(timeout, buzzer.note_async(880.0, 60.0)).await }).is_err());
+        assert!(*tones.lock().unwrap() == [Some(440.0), None, Some(880.0), None]);
+
+        Ok(())
+    }
+
+    /// Pins the auto traits: the buzzer can be moved to another thread, its handle shared between threads.
+    const _: () = {
+        const fn assert_send<T: Send>() {}
+        const fn assert_sync<T: Sync>() {}
+        assert_send::<Buzzer>();
+        assert_send::<SharedBuzzer>();
+        assert_sync::<SharedBuzzer>();
+        assert_send::<Playback>();
+        assert_sync::<Playback>();
+        assert_send::<Error>();
+        assert_sync::<Error>();
+    };
+
+    /// Tests beeping concurrently from clones of a shared buzzer.
+    #[test]
+    fn test_shared_buzzer_beep() -> Result<(), Error> {
+        let tones = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let buzzer = Buzzer::with_pin(RecordingTone { tones: tones.clone() }).into_shared();
+
+        let threads: Vec<_> = [440.0, 880.0].iter().map(|&frequency| {
+            let buzzer = buzzer.clone();
+            thread::spawn(move || buzzer.beep(frequency, 0.01).wait())
+        }).collect();
+
+        for handle in threads {
+            handle.join().unwrap()?;
+        }
+
+        // Both notes were played, one after the other.
+        let tones = tones.lock().unwrap().clone();
+        assert!(tones.len() == 4);
+        assert!(tones[1].is_none() && tones[3].is_none());
+        assert!(tones.contains(&Some(440.0)) && tones.contains(&Some(880.0)));
+
+        Ok(())
+    }
+
+    /// Tests playing a sequence on a shared buzzer.
+    #[test]
+    fn test_shared_buzzer_play() -> Result<(), Error> {
+        let tones = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let buzzer = Buzzer::with_pin(RecordingTone { tones: tones.clone() }).into_shared();
+
+        buzzer.play(&[(69, 0.0), (81, 0.0)]).wait()?;
+        assert!(*tones.lock().unwrap() == [Some(440.0), None, Some(880.0), None]);
+
+        assert!(matches!(buzzer.play(&[(0, 0.0), (69, 0.0)]).wait(), Err(Error::InvalidArgument(_))));
+        assert!(matches!(buzzer.beep(-1.0, 0.0).wait(), Err(Error::InvalidArgument(_))));
+        assert!(tones.lock().unwrap().len() == 4);
+
+        Ok(())
+    }
+
+    /// Tests the MIDI note numbers of note names.
+    #[test]
+    fn test_buzzer_parse_note_name() {
+        assert!(parse_note_name("A4").unwrap() == 69);
+        assert!(parse_note_name("a4").unwrap() == 69);
+        assert!(parse_note_name("C4").unwrap() == 60);
+        assert!(parse_note_name("C#5").unwrap() == 73);
+        assert!(parse_note_name("Bb3").unwrap() == 58);
+        assert!(parse_note_name("C-1").is_err());
+        assert!(parse_note_name("C#-1").unwrap() == 1);
+
+        for invalid in ["", "A", "H4", "A#", "A10", "4A"].iter() {
+            assert!(matches!(parse_note_name(invalid), Err(Error::InvalidArgument(_))));
+        }
+    }
+
+    /// Tests parsing songs in the RTTTL format.
+    #[test]
+    fn test_buzzer_parse_rtttl() -> Result<(), Error> {
+
+        // 120 beats per minute: a quarter note lasts 0.5s
+        let notes = parse_rtttl("test:d=4,o=5,b=120:c,8d#,e.,p,2a4,16b6.")?;
+        assert!(notes == [
+            (Some(72), 0.5),
+            (Some(75), 0.25),
+            (Some(76), 0.75),
+            (None, 0.5),
+            (Some(69), 1.0),
+            (Some(95), 0.1875),
+        ]);
+
+        // Default values of the specification: quarter notes, octave 6 and 63 beats per minute
+        let notes = parse_rtttl("defaults::a")?;
+        assert!(notes.len() == 1 && notes[0].0 == Some(93));
+        assert!((notes[0].1 - 60.0 / 63.0).abs() < 1e-9);
+
+        for invalid in ["no notes", "x:d=0:a", "x:q=4:a", "x:d=4:0a", "x:d=4:x", "x:d=4:a#x"].iter() {
+            assert!(matches!(parse_rtttl(invalid), Err(Error::InvalidArgument(_))));
+        }
+
+        Ok(())
+    }
+
+    /// Tests playing a song in the RTTTL format.
+    #[test]
+    fn test_buzzer_play_rtttl() -> Result<(), Error> {
+        let tones = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut buzzer = Buzzer::with_pin(RecordingTone { tones: tones.clone() });
+
+        buzzer.play_rtttl("beep:d=64,o=4,b=240:a,p,a5")?;
+        assert!(*tones.lock().unwrap() == [Some(440.0), None, Some(880.0), None]);
+
+        Ok(())
     }
 }
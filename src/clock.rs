@@ -0,0 +1,254 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Source of monotonic time used by the time based effects.
+/// Injecting a clock allows the effects to be tested without waiting.
+pub trait Clock: Debug + Send {
+
+    /// Get the current instant.
+    fn now(&self) -> Instant;
+
+    /// Get the wall clock time, which unlike the instants can be adjusted, for example by NTP.
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    /// Wait for a duration, blocking the thread by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - Duration to wait.
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// Clock using the system monotonic time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock that only moves forward when advanced manually.
+/// Clones share the same time, so a test can keep a clone to drive a clock given to a driver.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+
+    /// Instant at which the clock was created.
+    start: Instant,
+
+    /// Time elapsed since the start.
+    elapsed: Arc<Mutex<Duration>>,
+
+    /// Wall clock time at the start, moved by the adjustments.
+    wall_start: Arc<Mutex<SystemTime>>,
+}
+
+impl ManualClock {
+
+    /// Creates a manual clock.
+    pub fn new() -> ManualClock {
+        Self {
+            start: Instant::now(),
+            elapsed: Arc::new(Mutex::new(Duration::from_secs(0))),
+            wall_start: Arc::new(Mutex::new(SystemTime::now())),
+        }
+    }
+
+    /// Move the clock forward.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - Duration to advance the clock by.
+    pub fn advance(&self, duration: Duration) {
+        let mut elapsed = self.elapsed.lock().unwrap();
+        *elapsed += duration;
+    }
+
+    /// Adjust the wall clock time, as NTP would, without moving the instants.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - New wall clock time.
+    pub fn set_system_time(&self, time: SystemTime) {
+        let elapsed = *self.elapsed.lock().unwrap();
+        *self.wall_start.lock().unwrap() = time - elapsed;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        *self.wall_start.lock().unwrap() + *self.elapsed.lock().unwrap()
+    }
+
+    /// Advances the clock instead of blocking.
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+/// Position within a repeating period, computed from the time elapsed since a start instant,
+/// so an animation looks the same whatever the frame rate, unlike a fixed change per frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Phase {
+
+    /// Instant at which the phase is 0.0.
+    start: Instant,
+
+    /// Duration of a full period.
+    period: Duration,
+}
+
+impl Phase {
+
+    /// Creates a phase starting at an instant.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - Instant at which the phase is 0.0.
+    /// * `period` - Duration of a full period, the phase always being 0.0 if zero.
+    pub fn new(start: Instant, period: Duration) -> Phase {
+        Self {
+            start,
+            period,
+        }
+    }
+
+    /// Creates a phase starting at the current instant of a clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Clock giving the start.
+    /// * `period` - Duration of a full period, the phase always being 0.0 if zero.
+    pub fn starting_now(clock: &dyn Clock, period: Duration) -> Phase {
+        Phase::new(clock.now(), period)
+    }
+
+    /// Get the duration of a full period.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Get the phase at an instant, from 0.0 at the start of each period up to but excluding 1.0.
+    /// Instants before the start have the phase 0.0.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Instant.
+    pub fn at(&self, now: Instant) -> f32 {
+        if self.period.is_zero() {
+            return 0.0;
+        }
+
+        let elapsed = now.saturating_duration_since(self.start).as_nanos();
+        let phase = (elapsed % self.period.as_nanos()) as f64 / self.period.as_nanos() as f64;
+
+        // Rounding to f32 must not reach the next period
+        (phase as f32).min(1.0 - f32::EPSILON / 2.0)
+    }
+
+    /// Get the phase at the current instant of a clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Clock giving the current instant.
+    pub fn now(&self, clock: &dyn Clock) -> f32 {
+        self.at(clock.now())
+    }
+
+    /// Get the number of full periods elapsed at an instant, 0 for a zero period.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Instant.
+    pub fn cycles(&self, now: Instant) -> u64 {
+        if self.period.is_zero() {
+            return 0;
+        }
+
+        (now.saturating_duration_since(self.start).as_nanos() / self.period.as_nanos()) as u64
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests advancing a manual clock shared between clones.
+    #[test]
+    fn test_manual_clock_advance() {
+        let clock = ManualClock::new();
+        let other = clock.clone();
+        let start = clock.now();
+
+        other.advance(Duration::from_millis(250));
+        assert!(clock.now() - start == Duration::from_millis(250));
+
+        clock.advance(Duration::from_secs(1));
+        assert!(other.now() - start == Duration::from_millis(1250));
+
+        // Sleeping advances the clock without blocking
+        clock.sleep(Duration::from_secs(3600));
+        assert!(other.now() - start == Duration::from_millis(3601250));
+    }
+
+    /// Tests the wall clock time follows the advances and can be adjusted without moving the instants.
+    #[test]
+    fn test_manual_clock_system_time() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let noon = SystemTime::UNIX_EPOCH + Duration::from_secs(12 * 3600);
+
+        clock.advance(Duration::from_secs(10));
+        clock.clone().set_system_time(noon);
+        assert!(clock.system_time() == noon && clock.now() - start == Duration::from_secs(10));
+
+        clock.advance(Duration::from_secs(60));
+        assert!(clock.system_time() == noon + Duration::from_secs(60));
+
+        // Set back an hour
+        clock.set_system_time(noon - Duration::from_secs(3600));
+        assert!(clock.system_time() == noon - Duration::from_secs(3600));
+        assert!(clock.now() - start == Duration::from_secs(70));
+    }
+
+    /// Tests the phase wraps at each period whatever the frame rate.
+    #[test]
+    fn test_phase_wraps() {
+        let clock = ManualClock::new();
+        let phase = Phase::starting_now(&clock, Duration::from_millis(400));
+        assert!(phase.period() == Duration::from_millis(400));
+        assert!(phase.now(&clock) == 0.0);
+
+        // Over three periods and a half, 100 ms at a time
+        let expected = [0.25, 0.5, 0.75, 0.0, 0.25, 0.5, 0.75, 0.0, 0.25, 0.5, 0.75, 0.0, 0.25, 0.5];
+        for (i, &value) in expected.iter().enumerate() {
+            clock.advance(Duration::from_millis(100));
+            assert!((phase.now(&clock) - value).abs() < 1e-6, "{} ms", (i + 1) * 100);
+            assert!(phase.cycles(clock.now()) == (i as u64 + 1) / 4);
+        }
+
+        // Just before the end of a period, before the start, and with a zero period
+        let start = clock.now();
+        assert!(phase.at(start + Duration::from_millis(199) + Duration::from_nanos(999_999)) < 1.0);
+        assert!(Phase::new(start + Duration::from_secs(1), Duration::from_secs(1)).at(start) == 0.0);
+        assert!(Phase::new(start, Duration::ZERO).at(start + Duration::from_secs(1)) == 0.0);
+    }
+}
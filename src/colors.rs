@@ -0,0 +1,121 @@
+use crate::apa102::Color;
+
+/// Black: every LED off.
+pub const BLACK: (u8, u8, u8) = (0, 0, 0);
+
+/// White.
+pub const WHITE: (u8, u8, u8) = (255, 255, 255);
+
+/// Warm white, the yellowish white of an incandescent bulb.
+pub const WARM_WHITE: (u8, u8, u8) = (255, 172, 68);
+
+/// Cool white, the bluish white of daylight.
+pub const COOL_WHITE: (u8, u8, u8) = (200, 220, 255);
+
+/// Red.
+pub const RED: (u8, u8, u8) = (255, 0, 0);
+
+/// Green.
+pub const GREEN: (u8, u8, u8) = (0, 255, 0);
+
+/// Blue.
+pub const BLUE: (u8, u8, u8) = (0, 0, 255);
+
+/// Yellow.
+pub const YELLOW: (u8, u8, u8) = (255, 255, 0);
+
+/// Cyan.
+pub const CYAN: (u8, u8, u8) = (0, 255, 255);
+
+/// Magenta.
+pub const MAGENTA: (u8, u8, u8) = (255, 0, 255);
+
+/// Orange.
+pub const ORANGE: (u8, u8, u8) = (255, 165, 0);
+
+/// Amber, between orange and yellow.
+pub const AMBER: (u8, u8, u8) = (255, 191, 0);
+
+/// Purple.
+pub const PURPLE: (u8, u8, u8) = (128, 0, 128);
+
+/// Pink.
+pub const PINK: (u8, u8, u8) = (255, 192, 203);
+
+/// Color chosen among the well known ones, for the high level calls such as `RainbowHat::notify`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NamedColor {
+    Red,
+    Green,
+    Blue,
+    Yellow,
+    Cyan,
+    Magenta,
+    Orange,
+    Amber,
+    Purple,
+    Pink,
+    White,
+}
+
+impl NamedColor {
+
+    /// Get the red, green and blue components of the color.
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        match self {
+            NamedColor::Red => RED,
+            NamedColor::Green => GREEN,
+            NamedColor::Blue => BLUE,
+            NamedColor::Yellow => YELLOW,
+            NamedColor::Cyan => CYAN,
+            NamedColor::Magenta => MAGENTA,
+            NamedColor::Orange => ORANGE,
+            NamedColor::Amber => AMBER,
+            NamedColor::Purple => PURPLE,
+            NamedColor::Pink => PINK,
+            NamedColor::White => WHITE,
+        }
+    }
+
+    /// Get which of the red, green and blue lights show the color best: the ones of the components at half intensity or more.
+    pub fn lights(&self) -> (bool, bool, bool) {
+        let (r, g, b) = self.rgb();
+        (r >= 128, g >= 128, b >= 128)
+    }
+}
+
+impl From<NamedColor> for Color {
+    fn from(color: NamedColor) -> Color {
+        color.rgb().into()
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apa102::APA102;
+    use crate::Error;
+
+    /// Tests the constants have the expected values and match the color names.
+    #[test]
+    fn test_colors() -> Result<(), Error> {
+        assert!(ORANGE == (255, 165, 0));
+        assert!(WARM_WHITE == (255, 172, 68));
+
+        for (name, color) in [("red", RED), ("orange", ORANGE), ("purple", PURPLE), ("pink", PINK)] {
+            assert!(Color::named(name) == Some(color.into()));
+        }
+
+        let mut apa102 = APA102::with_simulation()?;
+        apa102.set_all_color(ORANGE, 1.0)?;
+        assert!(apa102.get_pixel(3)? == (255, 165, 0, 31));
+
+        apa102.set_all_color(NamedColor::Purple, 1.0)?;
+        assert!(apa102.get_pixel(0)? == (128, 0, 128, 31));
+        assert!(NamedColor::Purple.lights() == (true, false, true));
+        assert!(NamedColor::Amber.lights() == (true, true, false));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::Error;
+use crate::alphanum4::Alignment;
+use crate::apa102::Color;
+use crate::rainbow_hat::RainbowHat;
+use crate::touch::ButtonId;
+
+/// Parse a JSON payload, the errors being invalid input.
+///
+/// # Arguments
+///
+/// * `payload` - JSON payload.
+pub(crate) fn parse<'a, T: Deserialize<'a>>(payload: &'a str) -> Result<T, Error> {
+    serde_json::from_str(payload).map_err(|err| Error::InvalidInput(format!("invalid JSON payload: {}", err)))
+}
+
+/// Payload setting the lights.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightsPayload {
+    r: Option<bool>,
+    g: Option<bool>,
+    b: Option<bool>,
+}
+
+/// Payload printing a text on the display.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DisplayPayload {
+    text: String,
+    #[serde(default)]
+    align: Alignment,
+}
+
+/// Get the state of the lights: `{"r": true, "g": false, "b": true}`.
+///
+/// # Arguments
+///
+/// * `board` - Board controlled.
+pub(crate) fn lights_state(board: &RainbowHat) -> Value {
+    json!({ "r": board.lights.red.state, "g": board.lights.green.state, "b": board.lights.blue.state })
+}
+
+/// Turn the given lights on or off, the lights not given being kept, returning the state of the lights.
+///
+/// # Arguments
+///
+/// * `board` - Board controlled.
+/// * `payload` - JSON payload: `{"r": true, "g": false, "b": true}`, each light being optional.
+pub(crate) fn set_lights(board: &mut RainbowHat, payload: &str) -> Result<Value, Error> {
+    let lights: LightsPayload = parse(payload)?;
+    let leds = board.lights()?;
+
+    let r = lights.r.unwrap_or(leds.red.state);
+    let g = lights.g.unwrap_or(leds.green.state);
+    let b = lights.b.unwrap_or(leds.blue.state);
+    leds.rgb(r, g, b);
+
+    Ok(lights_state(board))
+}
+
+/// Parse a color: `[r, g, b]` array, 0xRRGGBB number, name or "#RRGGBB" string.
+///
+/// # Arguments
+///
+/// * `value` - JSON color.
+fn parse_color(value: &Value) -> Result<Color, Error> {
+    let invalid = || Error::InvalidInput(format!("invalid color {}, expected [r, g, b], 0xRRGGBB, a name or #RRGGBB", value));
+
+    match value {
+        Value::String(name) => name.parse().map_err(|_| invalid()),
+        Value::Number(number) => match number.as_u64() {
+            Some(rgb) if rgb <= 0xFF_FFFF => Ok(Color::from(rgb as u32)),
+            _ => Err(invalid()),
+        },
+        Value::Array(channels) if channels.len() == 3 => {
+            let mut rgb = [0; 3];
+            for (channel, value) in rgb.iter_mut().zip(channels) {
+                *channel = value.as_u64().filter(|&value| value <= 255).ok_or_else(invalid)? as u8;
+            }
+            Ok(Color::from(rgb))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Show the given colors on the multicolour LEDs, the pixels not given being turned off,
+/// returning the colors of the pixels and the brightness. Nothing is changed when the payload is invalid.
+///
+/// # Arguments
+///
+/// * `board` - Board controlled.
+/// * `payload` - JSON payload: array of colors, or `{"pixels": [...], "brightness": 0.5}`.
+pub(crate) fn set_pixels(board: &mut RainbowHat, payload: &str) -> Result<Value, Error> {
+    let value: Value = parse(payload)?;
+
+    let (colors, brightness) = match &value {
+        Value::Array(colors) => (colors, 1.0),
+        Value::Object(fields) => {
+            let colors = fields.get("pixels").and_then(Value::as_array)
+                .ok_or_else(|| Error::InvalidInput("expected the array of colors in \"pixels\"".to_string()))?;
+            let brightness = match fields.get("brightness") {
+                None => 1.0,
+                Some(brightness) => brightness.as_f64().ok_or_else(|| Error::InvalidInput("the brightness must be a number".to_string()))? as f32,
+            };
+            (colors, brightness)
+        }
+        _ => return Err(Error::InvalidInput("expected an array of colors".to_string())),
+    };
+
+    let apa102 = board.pixels()?;
+    if colors.len() > apa102.len() {
+        return Err(Error::InvalidInput(format!("{} colors for {} pixels", colors.len(), apa102.len())));
+    }
+
+    let colors = colors.iter().map(parse_color).collect::<Result<Vec<Color>, Error>>()?;
+
+    // Validate the brightness before changing the pixels
+    apa102.set_all(0, 0, 0, brightness)?;
+    for (x, color) in colors.iter().enumerate() {
+        apa102.set_pixel_color(x, *color, brightness)?;
+    }
+    apa102.show()?;
+
+    let pixels = (0..apa102.len()).map(|x| {
+        let (r, g, b, _brightness) = apa102.get_pixel(x)?;
+        Ok(format!("#{:02x}{:02x}{:02x}", r, g, b))
+    }).collect::<Result<Vec<String>, Error>>()?;
+
+    Ok(json!({ "pixels": pixels, "brightness": brightness }))
+}
+
+/// Print a text of up to 4 printable ASCII characters on the display, clearing the previous one.
+///
+/// # Arguments
+///
+/// * `board` - Board controlled.
+/// * `payload` - JSON payload: `{"text": "HI", "align": "right"}`, the alignment being left, right or center, left by default.
+pub(crate) fn set_display(board: &mut RainbowHat, payload: &str) -> Result<Value, Error> {
+    let display: DisplayPayload = parse(payload)?;
+    let alphanum = board.display()?;
+
+    if display.text.chars().count() > alphanum.digit_count() {
+        return Err(Error::InvalidInput(format!("{:?} is longer than the {} digits of the display", display.text, alphanum.digit_count())));
+    }
+    if let Some(c) = display.text.chars().find(|c| !(' '..='~').contains(c)) {
+        return Err(Error::InvalidInput(format!("the display cannot print {:?}", c)));
+    }
+
+    alphanum.print_str(&display.text, display.align)?;
+    alphanum.show()?;
+
+    Ok(json!({ "text": display.text }))
+}
+
+/// Get the state of the buttons: `{"a": false, "b": true, "c": false}`.
+///
+/// # Arguments
+///
+/// * `board` - Board controlled.
+pub(crate) fn buttons_state(board: &mut RainbowHat) -> Value {
+    json!({
+        "a": board.buttons.get_mut(ButtonId::A).is_pressed(),
+        "b": board.buttons.get_mut(ButtonId::B).is_pressed(),
+        "c": board.buttons.get_mut(ButtonId::C).is_pressed(),
+    })
+}
+
+/// Measure the temperature and the pressure: `{"temperature_c": 21.5, "pressure_hpa": 1013.2}`.
+///
+/// # Arguments
+///
+/// * `board` - Board controlled.
+pub(crate) fn sensor_state(board: &mut RainbowHat) -> Result<Value, Error> {
+    let measurement = board.sensor()?.measure_once()?;
+
+    Ok(json!({ "temperature_c": measurement.temperature_c, "pressure_hpa": measurement.pressure_hpa }))
+}
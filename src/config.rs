@@ -0,0 +1,402 @@
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use toml::{Table, Value};
+use crate::Error;
+use crate::bmp280;
+use crate::error::invalid_input;
+use crate::ht16k33;
+use crate::lights::{GPIO_LIGHT_BLUE, GPIO_LIGHT_GREEN, GPIO_LIGHT_RED};
+use crate::rainbow_hat::{Component, RainbowHat, RainbowHatBuilder};
+use crate::touch::{GPIO_TOUCH_A, GPIO_TOUCH_B, GPIO_TOUCH_C};
+
+/// Highest GPIO pin number of the Raspberry Pi header, using the BCM pin numbering.
+pub const MAX_BCM_PIN: u8 = 27;
+
+/// Keys of each section of the configuration, the root one being unnamed.
+const SCHEMA: [(&str, &[&str]); 7] = [
+    ("", &["simulation", "auto_simulate", "check_hat", "strict", "i2c_bus"]),
+    ("pixels", &["enabled", "brightness", "spi_bus", "spi_clock_speed"]),
+    ("lights", &["enabled", "red", "green", "blue"]),
+    ("buttons", &["enabled", "a", "b", "c"]),
+    ("display", &["enabled", "address"]),
+    ("buzzer", &["enabled", "pin"]),
+    ("sensor", &["enabled", "address"]),
+];
+
+/// Configuration of a board read from TOML, for boards wired differently from the Rainbow HAT.
+/// Every key is optional, the defaults being those of the Rainbow HAT:
+///
+/// ```toml
+/// simulation = false   # run every peripheral in simulation mode
+/// auto_simulate = false # switch to simulation mode when the hardware is absent
+/// check_hat = false    # check the Rainbow HAT is attached by reading its EEPROM
+/// strict = true        # unknown keys are errors, or only warnings when false
+/// i2c_bus = 1          # I2C bus of the display and the sensor
+///
+/// [pixels]
+/// enabled = true
+/// brightness = 0.3     # global brightness, 0.0 to 1.0
+/// spi_bus = 0          # hardware SPI bus instead of the GPIO pins
+/// spi_clock_speed = 8000000
+///
+/// [lights]
+/// red = 6
+/// green = 19
+/// blue = 26
+///
+/// [buttons]
+/// a = 21
+/// b = 20
+/// c = 16
+///
+/// [display]
+/// address = 0x70
+///
+/// [buzzer]
+/// pin = 13
+///
+/// [sensor]
+/// address = 0x77
+/// ```
+#[derive(Debug, Clone)]
+pub struct Config {
+
+    /// Builder of the board configured.
+    builder: RainbowHatBuilder,
+
+    /// Unknown keys ignored in non strict mode.
+    warnings: Vec<String>,
+}
+
+impl Config {
+
+    /// Parse a configuration. The errors name the offending key and value.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - Configuration in the TOML format.
+    pub fn parse(text: &str) -> Result<Config, Error> {
+        let root: Table = text.parse().map_err(|err| Error::InvalidInput(format!("invalid configuration: {}", err)))?;
+
+        let strict = boolean(&root, "", "strict")?.unwrap_or(true);
+        let mut warnings = Vec::new();
+        for key in unknown_keys(&root)? {
+            if strict {
+                return Err(Error::InvalidInput(format!("unknown configuration key {}", key)));
+            }
+            warnings.push(format!("unknown configuration key {} ignored", key));
+        }
+
+        let mut builder = RainbowHat::builder();
+        let empty = Table::new();
+        let section = |name: &str| root.get(name).and_then(Value::as_table).unwrap_or(&empty);
+
+        if let Some(simulation) = boolean(&root, "", "simulation")? {
+            builder = builder.simulation(simulation);
+        }
+        if let Some(auto_simulate) = boolean(&root, "", "auto_simulate")? {
+            builder = builder.auto_simulate(auto_simulate);
+        }
+        if let Some(check_hat) = boolean(&root, "", "check_hat")? {
+            builder = builder.check_hat(check_hat);
+        }
+        if let Some(bus) = integer(&root, "", "i2c_bus", 0..=u8::MAX as i64, "an I2C bus number")? {
+            builder = builder.i2c_bus(bus as u8);
+        }
+
+        for (name, component) in [("pixels", Component::Pixels), ("lights", Component::Lights), ("buttons", Component::Buttons),
+                                  ("display", Component::Display), ("buzzer", Component::Buzzer), ("sensor", Component::Sensor)] {
+            if let Some(enabled) = boolean(section(name), name, "enabled")? {
+                builder = builder.with_component(component, enabled);
+            }
+        }
+
+        let pixels = section("pixels");
+        if let Some(brightness) = number(pixels, "pixels", "brightness", 0.0..=1.0, "0.0 to 1.0")? {
+            builder = builder.apa102_brightness(brightness as f32);
+        }
+        builder = spi(builder, pixels)?;
+
+        let lights = section("lights");
+        builder = builder.light_pins(
+            pin(lights, "lights", "red")?.unwrap_or(GPIO_LIGHT_RED),
+            pin(lights, "lights", "green")?.unwrap_or(GPIO_LIGHT_GREEN),
+            pin(lights, "lights", "blue")?.unwrap_or(GPIO_LIGHT_BLUE));
+
+        let buttons = section("buttons");
+        builder = builder.button_pins(
+            pin(buttons, "buttons", "a")?.unwrap_or(GPIO_TOUCH_A),
+            pin(buttons, "buttons", "b")?.unwrap_or(GPIO_TOUCH_B),
+            pin(buttons, "buttons", "c")?.unwrap_or(GPIO_TOUCH_C));
+
+        if let Some(pin) = pin(section("buzzer"), "buzzer", "pin")? {
+            builder = builder.buzzer_pin(pin);
+        }
+
+        let addresses = format!("0x{:02X} to 0x{:02X}", ht16k33::FIRST_ADDRESS, ht16k33::LAST_ADDRESS);
+        let range = ht16k33::FIRST_ADDRESS as i64..=ht16k33::LAST_ADDRESS as i64;
+        if let Some(address) = integer(section("display"), "display", "address", range, &addresses)? {
+            builder = builder.display_address(address as u16);
+        }
+
+        let range = bmp280::SECONDARY_ADDRESS as i64..=bmp280::DEFAULT_ADDRESS as i64;
+        if let Some(address) = integer(section("sensor"), "sensor", "address", range, "0x76 or 0x77")? {
+            builder = builder.sensor_address(address as u16);
+        }
+
+        Ok(Self {
+            builder,
+            warnings,
+        })
+    }
+
+    /// Read and parse a configuration file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the TOML file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, Error> {
+        Config::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Get the warnings about the unknown keys ignored in non strict mode.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Get a builder of the board configured, to adjust it before building.
+    pub fn builder(&self) -> RainbowHatBuilder {
+        self.builder.clone()
+    }
+
+    /// Creates the board configured.
+    pub fn build(self) -> Result<RainbowHat, Error> {
+        self.builder.build()
+    }
+}
+
+/// Get the full name of a key, prefixed with its section.
+///
+/// # Arguments
+///
+/// * `section` - Name of the section, empty for the root.
+/// * `key` - Key in the section.
+fn full_key(section: &str, key: &str) -> String {
+    if section.is_empty() { key.to_string() } else { format!("{}.{}", section, key) }
+}
+
+/// Get the keys of the configuration that are not in the schema, checking the sections are tables.
+///
+/// # Arguments
+///
+/// * `root` - Root table of the configuration.
+fn unknown_keys(root: &Table) -> Result<Vec<String>, Error> {
+    let mut unknown = Vec::new();
+
+    for (key, value) in root {
+        match SCHEMA.iter().find(|(section, _keys)| section == key) {
+            Some((section, keys)) => {
+                let table = value.as_table().ok_or_else(|| Error::InvalidInput(invalid_input(section, describe(value), "a table")))?;
+                unknown.extend(table.keys().filter(|key| !keys.contains(&key.as_str())).map(|key| full_key(section, key)));
+            }
+            None if !SCHEMA[0].1.contains(&key.as_str()) => unknown.push(key.clone()),
+            None => {}
+        }
+    }
+
+    Ok(unknown)
+}
+
+/// Describe a value in an error message, strings being quoted and tables or arrays named by their type.
+///
+/// # Arguments
+///
+/// * `value` - Value of the configuration.
+fn describe(value: &Value) -> String {
+    match value {
+        Value::String(text) => format!("{:?}", text),
+        Value::Integer(number) => number.to_string(),
+        Value::Float(number) => number.to_string(),
+        Value::Boolean(flag) => flag.to_string(),
+        other => other.type_str().to_string(),
+    }
+}
+
+/// Get a boolean value.
+///
+/// # Arguments
+///
+/// * `table` - Table of the section.
+/// * `section` - Name of the section, for the error message.
+/// * `key` - Key of the value.
+fn boolean(table: &Table, section: &str, key: &str) -> Result<Option<bool>, Error> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Boolean(value)) => Ok(Some(*value)),
+        Some(value) => Err(Error::InvalidInput(invalid_input(&full_key(section, key), describe(value), "true or false"))),
+    }
+}
+
+/// Get an integer value in a range.
+///
+/// # Arguments
+///
+/// * `table` - Table of the section.
+/// * `section` - Name of the section, for the error message.
+/// * `key` - Key of the value.
+/// * `range` - Valid values.
+/// * `expected` - Description of the valid values, for the error message.
+fn integer(table: &Table, section: &str, key: &str, range: RangeInclusive<i64>, expected: &str) -> Result<Option<i64>, Error> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Integer(value)) if range.contains(value) => Ok(Some(*value)),
+        Some(value) => Err(Error::InvalidInput(invalid_input(&full_key(section, key), describe(value), expected))),
+    }
+}
+
+/// Get a number in a range, integer or float.
+///
+/// # Arguments
+///
+/// * `table` - Table of the section.
+/// * `section` - Name of the section, for the error message.
+/// * `key` - Key of the value.
+/// * `range` - Valid values.
+/// * `expected` - Description of the valid values, for the error message.
+fn number(table: &Table, section: &str, key: &str, range: RangeInclusive<f64>, expected: &str) -> Result<Option<f64>, Error> {
+    let number = match table.get(key) {
+        None => return Ok(None),
+        Some(Value::Integer(value)) => *value as f64,
+        Some(Value::Float(value)) => *value,
+        Some(value) => return Err(Error::InvalidInput(invalid_input(&full_key(section, key), describe(value), expected))),
+    };
+
+    if !range.contains(&number) {
+        return Err(Error::InvalidInput(invalid_input(&full_key(section, key), number, expected)));
+    }
+
+    Ok(Some(number))
+}
+
+/// Get a GPIO pin number.
+///
+/// # Arguments
+///
+/// * `table` - Table of the section.
+/// * `section` - Name of the section, for the error message.
+/// * `key` - Key of the pin.
+fn pin(table: &Table, section: &str, key: &str) -> Result<Option<u8>, Error> {
+    let expected = format!("a BCM pin 0 to {}", MAX_BCM_PIN);
+    Ok(integer(table, section, key, 0..=MAX_BCM_PIN as i64, &expected)?.map(|pin| pin as u8))
+}
+
+/// Write the multicolour LEDs with hardware SPI when `pixels.spi_bus` is set.
+///
+/// # Arguments
+///
+/// * `builder` - Builder of the board.
+/// * `pixels` - Table of the pixels section.
+#[cfg(feature = "hardware")]
+fn spi(builder: RainbowHatBuilder, pixels: &Table) -> Result<RainbowHatBuilder, Error> {
+    use rppal::spi::Bus;
+    use crate::apa102::{SPI_MAX_CLOCK_SPEED, SPI_MIN_CLOCK_SPEED};
+
+    let speeds = format!("{} to {} Hz", SPI_MIN_CLOCK_SPEED, SPI_MAX_CLOCK_SPEED);
+    let clock_speed = integer(pixels, "pixels", "spi_clock_speed", SPI_MIN_CLOCK_SPEED as i64..=SPI_MAX_CLOCK_SPEED as i64, &speeds)?;
+
+    let bus = match integer(pixels, "pixels", "spi_bus", 0..=6, "0 to 6")? {
+        None if clock_speed.is_some() => return Err(Error::InvalidInput("pixels.spi_clock_speed needs pixels.spi_bus".to_string())),
+        None => return Ok(builder),
+        Some(0) => Bus::Spi0,
+        Some(1) => Bus::Spi1,
+        Some(2) => Bus::Spi2,
+        Some(3) => Bus::Spi3,
+        Some(4) => Bus::Spi4,
+        Some(5) => Bus::Spi5,
+        Some(_) => Bus::Spi6,
+    };
+
+    Ok(builder.apa102_spi(bus, clock_speed.unwrap_or(SPI_MAX_CLOCK_SPEED as i64 / 16) as u32))
+}
+
+/// Hardware SPI needs the `hardware` feature.
+///
+/// # Arguments
+///
+/// * `builder` - Builder of the board.
+/// * `pixels` - Table of the pixels section.
+#[cfg(not(feature = "hardware"))]
+fn spi(builder: RainbowHatBuilder, pixels: &Table) -> Result<RainbowHatBuilder, Error> {
+    match ["spi_bus", "spi_clock_speed"].iter().find(|key| pixels.contains_key(**key)) {
+        Some(key) => Err(Error::Unsupported(format!("pixels.{} without the hardware feature", key))),
+        None => Ok(builder),
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests a board with the buzzer rewired, from a fixture file.
+    #[test]
+    fn test_config_buzzer_pin() -> Result<(), Error> {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/buzzer_rewired.toml");
+        let hat = RainbowHat::from_config(path)?;
+
+        assert!(hat.is_simulation());
+        assert!(hat.buzzer.bcm_pin() == 12);
+        assert!(hat.lights.red.bcm_pin == GPIO_LIGHT_RED);
+        assert!((hat.apa102.global_brightness() - 0.3).abs() < 1e-6);
+
+        Ok(())
+    }
+
+    /// Tests a board with the display at another address and without sensor.
+    #[test]
+    fn test_config_display_address() -> Result<(), Error> {
+        let config = Config::parse(include_str!("../tests/fixtures/display_0x71.toml"))?;
+        assert!(config.warnings().is_empty());
+
+        let hat = config.build()?;
+        assert!(hat.alphanum.ht16k33.address() == 0x71);
+        assert!(hat.alphanum.ht16k33.bus() == Some(3));
+        assert!(!hat.is_enabled(Component::Sensor));
+
+        Ok(())
+    }
+
+    /// Tests the errors name the offending key and value.
+    #[test]
+    fn test_config_errors() {
+        let message = |text: &str| match Config::parse(text) {
+            Err(Error::InvalidInput(msg)) => msg,
+            other => panic!("{:?}", other.map(|config| config.warnings().to_vec())),
+        };
+
+        assert!(message(include_str!("../tests/fixtures/malformed.toml")).starts_with("invalid configuration: "));
+        assert!(message("[display]\naddress = 0x80") == "invalid display.address 128, expected 0x70 to 0x77");
+        assert!(message("[buzzer]\npin = \"GPIO13\"") == "invalid buzzer.pin \"GPIO13\", expected a BCM pin 0 to 27");
+        assert!(message("[pixels]\nbrightness = 1.5") == "invalid pixels.brightness 1.5, expected 0.0 to 1.0");
+        assert!(message("simulation = 1") == "invalid simulation 1, expected true or false");
+        assert!(message("lights = 3") == "invalid lights 3, expected a table");
+        assert!(message("[display]\nadress = 0x71") == "unknown configuration key display.adress");
+    }
+
+    /// Tests unknown keys are only warnings in non strict mode.
+    #[test]
+    fn test_config_not_strict() -> Result<(), Error> {
+        let config = Config::parse("strict = false\nsimulation = true\ncolour = \"red\"\n[sensor]\nadress = 0x76")?;
+
+        assert!(config.warnings() == ["unknown configuration key colour ignored", "unknown configuration key sensor.adress ignored"]);
+        assert!(config.builder().build()?.is_simulation());
+
+        // The board created from the configuration keeps the warnings
+        let hat = RainbowHat::from_config_str("strict = false\nsimulation = true\ncolour = \"red\"")?;
+        assert!(hat.config_warnings() == ["unknown configuration key colour ignored"]);
+        assert!(RainbowHat::from_config_str("simulation = true")?.config_warnings().is_empty());
+
+        Ok(())
+    }
+}
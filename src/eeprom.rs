@@ -0,0 +1,148 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::Error;
+
+/// Directory where the Raspberry Pi firmware exposes the content of the HAT EEPROM read on boot.
+pub const HAT_DIR: &str = "/proc/device-tree/hat";
+
+/// Product name of the Rainbow HAT, compared ignoring the case.
+pub const RAINBOW_HAT_PRODUCT: &str = "Rainbow HAT";
+
+/// HAT identified by its EEPROM.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct HatInfo {
+
+    /// Vendor, such as "Pimoroni Ltd.", empty if not given.
+    pub vendor: String,
+
+    /// Product name, empty if not given.
+    pub product: String,
+
+    /// Product ID as written by the firmware, such as "0x0007", empty if not given.
+    pub product_id: String,
+}
+
+impl HatInfo {
+
+    /// Creates the information from the content of the device tree files, ignoring the trailing NUL and whitespace.
+    ///
+    /// # Arguments
+    ///
+    /// * `vendor` - Content of the vendor file.
+    /// * `product` - Content of the product file.
+    /// * `product_id` - Content of the product_id file.
+    pub fn parse(vendor: &[u8], product: &[u8], product_id: &[u8]) -> HatInfo {
+        let text = |content: &[u8]| String::from_utf8_lossy(content).trim_end_matches(|c: char| c == '\0' || c.is_whitespace()).to_string();
+
+        Self {
+            vendor: text(vendor),
+            product: text(product),
+            product_id: text(product_id),
+        }
+    }
+
+    /// Get whether the HAT is a Rainbow HAT.
+    pub fn is_rainbow_hat(&self) -> bool {
+        self.product.eq_ignore_ascii_case(RAINBOW_HAT_PRODUCT)
+    }
+}
+
+impl fmt::Display for HatInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} by {}", &self.product, &self.vendor)
+    }
+}
+
+/// Identify the HAT attached to the Raspberry Pi from its EEPROM.
+/// Returns `Error::HatNotDetected` when no HAT with an EEPROM was found, for example off the Raspberry Pi.
+pub fn detect() -> Result<HatInfo, Error> {
+    detect_in(Path::new(HAT_DIR))
+}
+
+/// Identify the HAT from the files of a device tree directory, for example a copy of `/proc/device-tree/hat`.
+/// A missing file is read as empty, a missing directory as no HAT.
+///
+/// # Arguments
+///
+/// * `dir` - Directory holding the vendor, product and product_id files.
+pub fn detect_in(dir: &Path) -> Result<HatInfo, Error> {
+    if !dir.is_dir() {
+        return Err(Error::HatNotDetected(format!("no HAT EEPROM in {}", dir.display())));
+    }
+
+    let read = |name: &str| match fs::read(dir.join(name)) {
+        Ok(content) => Ok(content),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(Error::Io(err)),
+    };
+
+    Ok(HatInfo::parse(&read("vendor")?, &read("product")?, &read("product_id")?))
+}
+
+/// Identify the HAT and check it is a Rainbow HAT.
+///
+/// # Arguments
+///
+/// * `dir` - Directory holding the vendor, product and product_id files.
+pub(crate) fn check_rainbow_hat(dir: &Path) -> Result<HatInfo, Error> {
+    let info = detect_in(dir)?;
+    if !info.is_rainbow_hat() {
+        return Err(Error::HatNotDetected(format!("found {}", info)));
+    }
+
+    Ok(info)
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Creates a device tree directory with the given files, NUL terminated as written by the firmware.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the directory, unique to the test.
+    /// * `files` - Names and contents of the files.
+    fn fixture(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rainbow-hat-rs-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, content) in files {
+            fs::write(dir.join(file), format!("{}\0", content)).unwrap();
+        }
+        dir
+    }
+
+    /// Tests detecting a Rainbow HAT, another HAT and no HAT.
+    #[test]
+    fn test_detect_in() -> Result<(), Error> {
+        let dir = fixture("present", &[("vendor", "Pimoroni Ltd."), ("product", "Rainbow HAT"), ("product_id", "0x0007")]);
+        let info = detect_in(&dir)?;
+        assert!(info == HatInfo { vendor: "Pimoroni Ltd.".to_string(), product: "Rainbow HAT".to_string(), product_id: "0x0007".to_string() });
+        assert!(info.is_rainbow_hat());
+        assert!(check_rainbow_hat(&dir).is_ok());
+
+        let dir = fixture("other", &[("vendor", "Raspberry Pi"), ("product", "Sense HAT")]);
+        let info = detect_in(&dir)?;
+        assert!(!info.is_rainbow_hat() && info.product_id.is_empty());
+        assert!(matches!(check_rainbow_hat(&dir), Err(Error::HatNotDetected(msg)) if msg == "found Sense HAT by Raspberry Pi"));
+
+        let dir = fixture("absent", &[]).join("hat");
+        assert!(matches!(detect_in(&dir), Err(Error::HatNotDetected(msg)) if msg.starts_with("no HAT EEPROM in")));
+
+        Ok(())
+    }
+
+    /// Tests parsing the content of the files.
+    #[test]
+    fn test_hat_info_parse() {
+        let info = HatInfo::parse(b"Pimoroni Ltd.\0", b"rainbow hat\n", b"");
+        assert!(info.vendor == "Pimoroni Ltd." && info.product_id.is_empty());
+        assert!(info.is_rainbow_hat());
+        assert!(!HatInfo::parse(b"", b"Rainbow HAT 2\0", b"").is_rainbow_hat());
+    }
+}
@@ -0,0 +1,383 @@
+use std::error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Errors of every peripheral, returned by the high level APIs combining them.
+/// Each module error converts into it, carrying its source.
+#[derive(Debug)]
+pub enum Error {
+
+    /// Gpio error configuring a pin.
+    #[cfg(feature = "hardware")]
+    Gpio {
+
+        /// GPIO pin number using the BCM pin numbering.
+        pin: u8,
+
+        /// Driver using the pin, such as "buzzer".
+        component: &'static str,
+
+        /// Error of the GPIO.
+        source: rppal::gpio::Error,
+    },
+
+    /// I2C error accessing a device.
+    #[cfg(feature = "hardware")]
+    I2c {
+
+        /// I2C bus number, None for the default bus.
+        bus: Option<u8>,
+
+        /// Address of the device, None when the bus itself failed or was given to the driver.
+        address: Option<u16>,
+
+        /// Driver of the device, such as "BMP280".
+        component: &'static str,
+
+        /// Error of the bus.
+        source: rppal::i2c::Error,
+    },
+
+    /// SPI error.
+    #[cfg(feature = "hardware")]
+    Spi(rppal::spi::Error),
+
+    /// I/O error of a custom bus.
+    Io(std::io::Error),
+
+    /// Invalid input.
+    InvalidInput(String),
+
+    /// The pin is already used by another driver of the process.
+    PinAlreadyInUse {
+
+        /// GPIO pin number using the BCM pin numbering.
+        pin: u8,
+
+        /// Driver using the pin, such as "buzzer".
+        owner: &'static str,
+    },
+
+    /// The peripheral is not setup as its setup failed with the given error.
+    NotSetup(Arc<dyn error::Error + Send + Sync>),
+
+    /// The operation is not supported.
+    Unsupported(String),
+
+    /// The device answering at the address is not the expected one: unexpected chip ID.
+    ChipId(u8),
+
+    /// The device did not answer after the given duration.
+    Timeout(Duration),
+
+    /// Several operations failed.
+    Multiple(Vec<Error>),
+
+    /// No Rainbow HAT was detected, for the given reason.
+    HatNotDetected(String),
+
+    /// The peripheral is disabled on this board.
+    ComponentDisabled(crate::rainbow_hat::Component),
+
+    /// The setup of a peripheral failed.
+    ComponentSetup {
+
+        /// Peripheral whose setup failed.
+        component: crate::rainbow_hat::Component,
+
+        /// Pins or address of the peripheral, such as "pin 6".
+        location: String,
+
+        /// Error of the setup.
+        source: Box<Error>,
+    },
+}
+
+/// Message of an invalid input, the same for every peripheral: "invalid brightness 1.5, expected 0.0 to 1.0".
+///
+/// # Arguments
+///
+/// * `parameter` - Name of the invalid parameter.
+/// * `value` - Value given.
+/// * `expected` - Values accepted.
+pub(crate) fn invalid_input<V: fmt::Display>(parameter: &str, value: V, expected: &str) -> String {
+    format!("invalid {} {}, expected {}", parameter, value, expected)
+}
+
+/// Location of an I2C device in the error messages: "I2C address 0x70 on bus 1".
+///
+/// # Arguments
+///
+/// * `bus` - I2C bus number, None for the default bus.
+/// * `address` - Address of the device, None for the bus itself.
+#[cfg(feature = "hardware")]
+pub(crate) fn i2c_location(bus: Option<u8>, address: Option<u16>) -> String {
+    let bus = match bus {
+        Some(bus) => format!("bus {}", bus),
+        None => "the default bus".to_string(),
+    };
+    match address {
+        Some(address) => format!("I2C address 0x{:02X} on {}", address, bus),
+        None => format!("I2C {}", bus),
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "hardware")]
+            Error::Gpio { source, .. } => Some(source),
+            #[cfg(feature = "hardware")]
+            Error::I2c { source, .. } => Some(source),
+            #[cfg(feature = "hardware")]
+            Error::Spi(err) => Some(err),
+            Error::Io(err) => Some(err),
+            Error::NotSetup(err) => Some(err.as_ref()),
+            Error::ComponentSetup { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "hardware")]
+            Error::Gpio { pin, component, source } => {
+                write!(f, "failed to configure BCM {} for {}: {}", pin, component, &source)
+            }
+            #[cfg(feature = "hardware")]
+            Error::I2c { bus, address, component, source } => {
+                write!(f, "failed to access {} for {}: {}", i2c_location(*bus, *address), component, &source)
+            }
+            #[cfg(feature = "hardware")]
+            Error::Spi(err) => write!(f, "SPI error: {}", &err),
+            Error::Io(err) => write!(f, "I/O error: {}", &err),
+            Error::InvalidInput(msg) => write!(f, "Invalid input: {}", &msg),
+            Error::PinAlreadyInUse { pin, owner } => write!(f, "BCM {} is already used by the {}", pin, owner),
+            Error::NotSetup(err) => write!(f, "Setup failed: {}", &err),
+            Error::Unsupported(msg) => write!(f, "Unsupported: {}", &msg),
+            Error::ChipId(id) => write!(f, "Unexpected chip ID 0x{:02X}", id),
+            Error::Timeout(duration) => write!(f, "No answer after {:?}", duration),
+            Error::HatNotDetected(reason) => write!(f, "Rainbow HAT not detected: {}", &reason),
+            Error::ComponentDisabled(component) => write!(f, "Component disabled: {}", component),
+            Error::ComponentSetup { component, location, source } => {
+                write!(f, "Setup of the {} on {} failed: {}", component, location, &source)
+            }
+            Error::Multiple(errors) => {
+                write!(f, "{} errors:", errors.len())?;
+                for err in errors {
+                    write!(f, " {};", &err)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Converts SPI error
+#[cfg(feature = "hardware")]
+impl From<rppal::spi::Error> for Error {
+    fn from(err: rppal::spi::Error) -> Error {
+        Error::Spi(err)
+    }
+}
+
+/// Converts I/O error
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+/// Converts a conflict of pins
+impl From<crate::pins::PinInUse> for Error {
+    fn from(err: crate::pins::PinInUse) -> Error {
+        Error::PinAlreadyInUse { pin: err.pin, owner: err.owner }
+    }
+}
+
+/// Converts APA102 error
+impl From<crate::apa102::Error> for Error {
+    fn from(err: crate::apa102::Error) -> Error {
+        match err {
+            crate::apa102::Error::PinAlreadyInUse { pin, owner } => Error::PinAlreadyInUse { pin, owner },
+            #[cfg(feature = "hardware")]
+            crate::apa102::Error::Gpio { pin, source } => Error::Gpio { pin, component: "APA102", source },
+            #[cfg(feature = "hardware")]
+            crate::apa102::Error::Spi(err) => Error::Spi(err),
+            crate::apa102::Error::InvalidArgument(msg) => Error::InvalidInput(msg),
+        }
+    }
+}
+
+/// Converts Lights error
+impl From<crate::lights::Error> for Error {
+    fn from(err: crate::lights::Error) -> Error {
+        match err {
+            crate::lights::Error::PinAlreadyInUse { pin, owner } => Error::PinAlreadyInUse { pin, owner },
+            #[cfg(feature = "hardware")]
+            crate::lights::Error::Gpio { pin, source } => Error::Gpio { pin, component: "light", source },
+        }
+    }
+}
+
+/// Converts Touch error
+impl From<crate::touch::Error> for Error {
+    fn from(err: crate::touch::Error) -> Error {
+        match err {
+            crate::touch::Error::PinAlreadyInUse { pin, owner } => Error::PinAlreadyInUse { pin, owner },
+            #[cfg(feature = "hardware")]
+            crate::touch::Error::Gpio { pin, source } => Error::Gpio { pin, component: "button", source },
+        }
+    }
+}
+
+/// Converts Buzzer error
+impl From<crate::buzzer::Error> for Error {
+    fn from(err: crate::buzzer::Error) -> Error {
+        match err {
+            crate::buzzer::Error::PinAlreadyInUse { pin, owner } => Error::PinAlreadyInUse { pin, owner },
+            #[cfg(feature = "hardware")]
+            crate::buzzer::Error::Gpio { pin, source } => Error::Gpio { pin, component: "buzzer", source },
+            crate::buzzer::Error::InvalidArgument(msg) => Error::InvalidInput(msg),
+        }
+    }
+}
+
+/// Converts HT16K33 error
+impl From<crate::ht16k33::Error> for Error {
+    fn from(err: crate::ht16k33::Error) -> Error {
+        match err {
+            #[cfg(feature = "hardware")]
+            crate::ht16k33::Error::I2c { bus, address, source } => Error::I2c { bus, address, component: "HT16K33", source },
+            crate::ht16k33::Error::Io(err) => Error::Io(err),
+            crate::ht16k33::Error::InvalidArgument(msg) => Error::InvalidInput(msg),
+            crate::ht16k33::Error::Setup(err) => Error::NotSetup(err),
+        }
+    }
+}
+
+/// Converts Alphanum4 error
+impl From<crate::alphanum4::Error> for Error {
+    fn from(err: crate::alphanum4::Error) -> Error {
+        match err {
+            crate::alphanum4::Error::HT16K33(err) => Error::from(err),
+            crate::alphanum4::Error::InvalidArgument(msg) => Error::InvalidInput(msg),
+        }
+    }
+}
+
+/// Converts BMP280 error
+impl From<crate::bmp280::Error> for Error {
+    fn from(err: crate::bmp280::Error) -> Error {
+        match err {
+            #[cfg(feature = "hardware")]
+            crate::bmp280::Error::I2c { bus, address, source } => Error::I2c { bus, address, component: "BMP280", source },
+            #[cfg(feature = "hardware")]
+            crate::bmp280::Error::Spi(err) => Error::Spi(err),
+            crate::bmp280::Error::Io(err) => Error::Io(err),
+            crate::bmp280::Error::Unsupported(msg) => Error::Unsupported(msg),
+            crate::bmp280::Error::ChipId(id) => Error::ChipId(id),
+            crate::bmp280::Error::Timeout(duration) => Error::Timeout(duration),
+        }
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+    use crate::alphanum4::{Alignment, Alphanum4};
+    use crate::apa102::APA102;
+    use crate::bmp280::BMP280;
+    use crate::buzzer::Buzzer;
+
+    /// Uses `?` on the errors of several modules.
+    fn play(frequency: f64, brightness: f32) -> Result<(), Error> {
+        let mut apa102 = APA102::with_simulation()?;
+        let mut buzzer = Buzzer::with_simulation()?;
+        let mut alphanum = Alphanum4::with_simulation()?;
+        let mut bmp280 = BMP280::with_simulation()?;
+
+        bmp280.read_temperature()?;
+        alphanum.print_str("PLAY", Alignment::Left)?;
+        alphanum.show()?;
+        apa102.set_all(255, 0, 0, brightness)?;
+        buzzer.note(frequency, 0.0)?;
+
+        Ok(())
+    }
+
+    /// Tests propagating the errors of several modules into the crate error.
+    #[test]
+    fn test_error_propagation() {
+        assert!(play(440.0, 1.0).is_ok());
+        assert!(matches!(play(440.0, 2.0), Err(Error::InvalidInput(msg)) if msg.contains("brightness")));
+        assert!(matches!(play(-1.0, 1.0), Err(Error::InvalidInput(msg)) if msg.contains("frequency")));
+
+        // Every peripheral describes the invalid input the same way
+        let err = Error::from(Alphanum4::with_simulation().unwrap().set_digit(4, 'A', false).unwrap_err());
+        assert!(err.to_string() == "Invalid input: invalid digit position 4, expected 0 to 3");
+    }
+
+    /// Tests the conversions carry the source of the module errors.
+    #[test]
+    fn test_error_conversion() {
+        let io = || std::io::Error::from_raw_os_error(121);
+
+        let err = Error::from(crate::bmp280::Error::Io(io()));
+        assert!(matches!(err, Error::Io(_)));
+        assert!(err.source().is_some());
+
+        let setup = Arc::new(crate::ht16k33::Error::Io(io()));
+        let err = Error::from(crate::alphanum4::Error::HT16K33(crate::ht16k33::Error::Setup(setup)));
+        assert!(matches!(err, Error::NotSetup(_)));
+        assert!(err.source().unwrap().to_string() == crate::ht16k33::Error::Io(io()).to_string());
+
+        assert!(matches!(Error::from(crate::bmp280::Error::ChipId(0x60)), Error::ChipId(0x60)));
+
+        let err = Error::from(crate::pins::PinInUse { pin: 6, owner: "light" });
+        assert!(matches!(err, Error::PinAlreadyInUse { pin: 6, owner: "light" }));
+        assert!(err.to_string() == "BCM 6 is already used by the light");
+    }
+
+    /// Tests the hardware errors name the pin or address and the driver, keeping the rppal error as source.
+    #[test]
+    #[cfg(feature = "hardware")]
+    fn test_error_context() {
+        let permission = || rppal::gpio::Error::PermissionDenied("/dev/gpiomem".to_string());
+
+        let err = Error::from(crate::buzzer::Error::Gpio { pin: 13, source: permission() });
+        assert!(err.to_string() == "failed to configure BCM 13 for buzzer: Permission denied: /dev/gpiomem");
+        assert!(matches!(err.source().unwrap().downcast_ref(), Some(rppal::gpio::Error::PermissionDenied(_))));
+
+        let err = Error::from(crate::touch::Error::Gpio { pin: 21, source: rppal::gpio::Error::PinNotAvailable(21) });
+        assert!(err.to_string() == "failed to configure BCM 21 for button: Pin 21 is not available");
+
+        let err = Error::from(crate::apa102::Error::Gpio { pin: 10, source: permission() });
+        assert!(err.to_string() == "failed to configure BCM 10 for APA102: Permission denied: /dev/gpiomem");
+
+        let conflict = crate::pins::PinInUse { pin: 13, owner: "light" };
+        let err = Error::from(crate::buzzer::Error::from(conflict));
+        assert!(matches!(err, Error::PinAlreadyInUse { pin: 13, owner: "light" }) && err.source().is_none());
+
+        let err = Error::from(crate::ht16k33::Error::I2c { bus: None, address: Some(0x70), source: rppal::i2c::Error::UnknownModel });
+        assert!(err.to_string() == "failed to access I2C address 0x70 on the default bus for HT16K33: Unknown Raspberry Pi model");
+        assert!(matches!(err.source().unwrap().downcast_ref(), Some(rppal::i2c::Error::UnknownModel)));
+
+        let err = Error::from(crate::bmp280::Error::I2c { bus: Some(1), address: None, source: rppal::i2c::Error::FeatureNotSupported });
+        assert!(err.to_string() == "failed to access I2C bus 1 for BMP280: I2C/SMBus feature not supported");
+        assert!(matches!(err, Error::I2c { bus: Some(1), address: None, component: "BMP280", .. }));
+    }
+
+    /// Pins the auto traits: the errors can be sent and shared between threads.
+    const _: () = {
+        const fn assert_send<T: Send>() {}
+        const fn assert_sync<T: Sync>() {}
+        assert_send::<Error>();
+        assert_sync::<Error>();
+    };
+}
@@ -0,0 +1,557 @@
+use std::time::{Duration, Instant};
+use crate::Error;
+use crate::error::invalid_input;
+use crate::alphanum4::Alignment;
+use crate::clock::{Clock, SystemClock};
+use crate::rainbow_hat::RainbowHat;
+use crate::rng::{Rng, SimpleRng};
+use crate::touch::{ButtonId, TouchEvent};
+
+/// Interval between two readings of the buttons while playing.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// MIDI note played with the cue.
+pub const CUE_NOTE: u32 = 81;
+
+/// Duration of the note played with the cue, in seconds.
+pub const CUE_DURATION: f64 = 0.1;
+
+/// MIDI note played on a false start, a wrong button or a missed cue.
+pub const FAIL_NOTE: u32 = 40;
+
+/// Settings of a reaction game.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReactionConfig {
+
+    /// Number of rounds, at least 1.
+    pub rounds: usize,
+
+    /// Shortest random wait before the cue.
+    pub min_delay: Duration,
+
+    /// Longest random wait before the cue, not shorter than `min_delay`.
+    pub max_delay: Duration,
+
+    /// Time given to press the button after the cue before the round is missed, more than 0.
+    pub timeout: Duration,
+
+    /// Seed of the waits and of the buttons cued. The same seed always gives the same rounds.
+    pub seed: u64,
+}
+
+impl Default for ReactionConfig {
+    fn default() -> Self {
+        Self {
+            rounds: 5,
+            min_delay: Duration::from_millis(1000),
+            max_delay: Duration::from_millis(4000),
+            timeout: Duration::from_secs(2),
+            seed: 1,
+        }
+    }
+}
+
+impl ReactionConfig {
+
+    /// Check the settings are consistent.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.rounds == 0 {
+            return Err(Error::InvalidInput(invalid_input("rounds", self.rounds, "at least 1")));
+        }
+
+        if self.min_delay > self.max_delay {
+            return Err(Error::InvalidInput(invalid_input("max_delay", format!("{:?}", self.max_delay), &format!("at least the min_delay of {:?}", self.min_delay))));
+        }
+
+        if self.timeout == Duration::from_secs(0) {
+            return Err(Error::InvalidInput(invalid_input("timeout", format!("{:?}", self.timeout), "more than 0")));
+        }
+
+        Ok(())
+    }
+}
+
+/// How a round ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundOutcome {
+
+    /// The cued button was pressed after the given reaction time.
+    Hit(Duration),
+
+    /// A button was pressed before the cue.
+    FalseStart,
+
+    /// Another button than the cued one was pressed.
+    WrongButton(ButtonId),
+
+    /// No button was pressed before the timeout.
+    Missed,
+}
+
+/// Result of a round.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoundResult {
+
+    /// Button cued.
+    pub target: ButtonId,
+
+    /// Random wait before the cue.
+    pub delay: Duration,
+
+    /// How the round ended.
+    pub outcome: RoundOutcome,
+}
+
+/// Results of a game.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GameResults {
+
+    /// Result of each completed round.
+    pub rounds: Vec<RoundResult>,
+}
+
+impl GameResults {
+
+    /// Get the reaction times of the rounds hit.
+    pub fn times(&self) -> Vec<Duration> {
+        self.rounds.iter().filter_map(|round| match round.outcome {
+            RoundOutcome::Hit(time) => Some(time),
+            _ => None,
+        }).collect()
+    }
+
+    /// Get the best reaction time, None when no round was hit.
+    pub fn best(&self) -> Option<Duration> {
+        self.times().into_iter().min()
+    }
+
+    /// Get the average reaction time of the rounds hit, None when no round was hit.
+    pub fn average(&self) -> Option<Duration> {
+        let times = self.times();
+        if times.is_empty() {
+            return None;
+        }
+
+        Some(times.iter().sum::<Duration>() / times.len() as u32)
+    }
+
+    /// Get the number of false starts.
+    pub fn false_starts(&self) -> usize {
+        self.rounds.iter().filter(|round| round.outcome == RoundOutcome::FalseStart).count()
+    }
+}
+
+/// Step of a round.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+
+    /// Waiting for the start of the game.
+    Idle,
+
+    /// Waiting for the cue, due at the instant.
+    Waiting(Instant),
+
+    /// Cue given at the instant, waiting for a press.
+    Cued(Instant),
+
+    /// Every round was played.
+    Finished,
+}
+
+/// Reaction game: after a random wait, the light of a button is turned on with a beep, and the button must be
+/// pressed as fast as possible. The reaction time is shown on the display in milliseconds, and the best time
+/// at the end of the game. A press before the cue is a false start, ending the round.
+/// Button A is cued by the red light, B by the green one and C by the blue one.
+///
+/// The game is a state machine advanced by `update` with the presses, reading the time from its clock,
+/// so it can be driven by a manual clock and injected presses.
+#[derive(Debug)]
+pub struct ReactionGame {
+
+    /// Settings of the game.
+    config: ReactionConfig,
+
+    /// Generator of the waits and of the buttons cued.
+    rng: SimpleRng,
+
+    /// Source of the time.
+    clock: Box<dyn Clock>,
+
+    /// Step of the current round.
+    state: State,
+
+    /// Button cued in the current round.
+    target: ButtonId,
+
+    /// Random wait of the current round.
+    delay: Duration,
+
+    /// Results of the completed rounds.
+    results: GameResults,
+}
+
+impl ReactionGame {
+
+    /// Creates a game.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Settings of the game.
+    pub fn new(config: ReactionConfig) -> Result<ReactionGame, Error> {
+        config.validate()?;
+
+        Ok(Self {
+            config,
+            rng: SimpleRng::new(config.seed),
+            clock: Box::new(SystemClock),
+            state: State::Idle,
+            target: ButtonId::A,
+            delay: Duration::from_secs(0),
+            results: GameResults::default(),
+        })
+    }
+
+    /// Play a whole game on a board, reading its buttons until every round is played.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board to play on.
+    /// * `config` - Settings of the game.
+    pub fn play(hat: &mut RainbowHat, config: ReactionConfig) -> Result<GameResults, Error> {
+        let mut game = ReactionGame::new(config)?;
+        game.run(hat)?;
+
+        Ok(game.results().clone())
+    }
+
+    /// Set the clock giving the time, for example a `ManualClock` in tests.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Clock to use.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Box::new(clock);
+    }
+
+    /// Get the results of the rounds completed so far.
+    pub fn results(&self) -> &GameResults {
+        &self.results
+    }
+
+    /// Get whether every round was played.
+    pub fn is_finished(&self) -> bool {
+        self.state == State::Finished
+    }
+
+    /// Get the button cued in the current round, None while waiting for the cue.
+    pub fn cued(&self) -> Option<ButtonId> {
+        match self.state {
+            State::Cued(_) => Some(self.target),
+            _ => None,
+        }
+    }
+
+    /// Start the game from the first round.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board to play on.
+    pub fn start(&mut self, hat: &mut RainbowHat) -> Result<(), Error> {
+        self.results = GameResults::default();
+        self.rng = SimpleRng::new(self.config.seed);
+
+        hat.alphanum.print_str("----", Alignment::Left)?;
+        hat.alphanum.show()?;
+        self.next_round(hat);
+
+        Ok(())
+    }
+
+    /// Start the game and read the buttons of the board until every round is played.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board to play on.
+    pub fn run(&mut self, hat: &mut RainbowHat) -> Result<(), Error> {
+        self.start(hat)?;
+
+        while !self.is_finished() {
+            self.poll(hat)?;
+            self.clock.sleep(POLL_INTERVAL);
+        }
+
+        Ok(())
+    }
+
+    /// Read the buttons once and advance the game.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board to play on.
+    pub fn poll(&mut self, hat: &mut RainbowHat) -> Result<Option<RoundResult>, Error> {
+        let mut pressed = None;
+        for &id in [ButtonId::A, ButtonId::B, ButtonId::C].iter() {
+            if let Some(TouchEvent::Pressed) = hat.buttons.get_mut(id).poll_event() {
+                pressed = pressed.or(Some(id));
+            }
+        }
+
+        self.update(hat, pressed)
+    }
+
+    /// Advance the game at the current time of the clock: give the cue when due, end the round on a press or
+    /// on the timeout. Returns the result of the round when it ends.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board to play on.
+    /// * `pressed` - Button pressed since the previous update, if any.
+    pub fn update(&mut self, hat: &mut RainbowHat, pressed: Option<ButtonId>) -> Result<Option<RoundResult>, Error> {
+        let now = self.clock.now();
+
+        let outcome = match (self.state, pressed) {
+            (State::Idle, _) | (State::Finished, _) => return Ok(None),
+            (State::Waiting(_), Some(_)) => RoundOutcome::FalseStart,
+            (State::Waiting(cue_at), None) => {
+                if now >= cue_at {
+                    self.cue(hat)?;
+                }
+                return Ok(None);
+            }
+            (State::Cued(since), Some(id)) if id == self.target => RoundOutcome::Hit(now - since),
+            (State::Cued(_), Some(id)) => RoundOutcome::WrongButton(id),
+            (State::Cued(since), None) => {
+                if now - since <= self.config.timeout {
+                    return Ok(None);
+                }
+                RoundOutcome::Missed
+            }
+        };
+
+        Ok(Some(self.end_round(hat, outcome)?))
+    }
+
+    /// Draw the button and the wait of the next round, or finish the game after the last round.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board to play on.
+    fn next_round(&mut self, hat: &mut RainbowHat) {
+        if self.results.rounds.len() == self.config.rounds {
+            self.state = State::Finished;
+            return;
+        }
+
+        self.target = match self.rng.next_u64() % 3 {
+            0 => ButtonId::A,
+            1 => ButtonId::B,
+            _ => ButtonId::C,
+        };
+        self.delay = self.config.min_delay + (self.config.max_delay - self.config.min_delay).mul_f32(self.rng.next_f32());
+
+        hat.lights.all(false);
+        self.state = State::Waiting(self.clock.now() + self.delay);
+    }
+
+    /// Light the target and beep, the reaction time being measured from the end of the beep call.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board to play on.
+    fn cue(&mut self, hat: &mut RainbowHat) -> Result<(), Error> {
+        match self.target {
+            ButtonId::A => hat.lights.red.on(),
+            ButtonId::B => hat.lights.green.on(),
+            ButtonId::C => hat.lights.blue.on(),
+        }
+        hat.buzzer.midi_note(CUE_NOTE, CUE_DURATION)?;
+
+        self.state = State::Cued(self.clock.now());
+
+        Ok(())
+    }
+
+    /// Record the result of the round, show it and start the next round.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board to play on.
+    /// * `outcome` - How the round ended.
+    fn end_round(&mut self, hat: &mut RainbowHat, outcome: RoundOutcome) -> Result<RoundResult, Error> {
+        let result = RoundResult {
+            target: self.target,
+            delay: self.delay,
+            outcome,
+        };
+        self.results.rounds.push(result);
+
+        let text = match outcome {
+            RoundOutcome::Hit(time) => milliseconds(time),
+            RoundOutcome::FalseStart => "SOON".to_string(),
+            RoundOutcome::WrongButton(_) => "OOPS".to_string(),
+            RoundOutcome::Missed => "MISS".to_string(),
+        };
+        hat.alphanum.print_str(&text, Alignment::Left)?;
+        hat.alphanum.show()?;
+
+        if !matches!(outcome, RoundOutcome::Hit(_)) {
+            hat.buzzer.midi_note(FAIL_NOTE, CUE_DURATION * 2.0)?;
+        }
+
+        self.next_round(hat);
+
+        if self.is_finished() {
+            let best = self.results.best().map(milliseconds).unwrap_or_else(|| "----".to_string());
+            hat.alphanum.print_str(&best, Alignment::Left)?;
+            hat.alphanum.show()?;
+        }
+
+        Ok(result)
+    }
+}
+
+/// Format a reaction time in milliseconds on the 4 characters of the display, capped at 9999.
+///
+/// # Arguments
+///
+/// * `time` - Reaction time.
+fn milliseconds(time: Duration) -> String {
+    format!("{:>4}", time.as_millis().min(9999))
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphanum4::Alphanum4;
+    use crate::clock::ManualClock;
+
+    /// Creates a board in simulation mode and a game driven by a manual clock.
+    fn setup(rounds: usize) -> Result<(RainbowHat, ReactionGame, ManualClock), Error> {
+        let hat = RainbowHat::with_simulation()?;
+        let clock = ManualClock::new();
+
+        let mut game = ReactionGame::new(ReactionConfig {
+            rounds,
+            ..ReactionConfig::default()
+        })?;
+        game.set_clock(clock.clone());
+
+        Ok((hat, game, clock))
+    }
+
+    /// Get whether the display shows a text.
+    fn shows(hat: &RainbowHat, text: &str) -> Result<bool, Error> {
+        let mut expected = Alphanum4::new()?;
+        expected.print_str(text, Alignment::Left)?;
+        Ok(hat.alphanum.ht16k33.buffer == expected.ht16k33.buffer)
+    }
+
+    /// Tests the validation of the settings.
+    #[test]
+    fn test_reaction_config() -> Result<(), Error> {
+        assert!(ReactionConfig::default().validate().is_ok());
+
+        for config in [
+            ReactionConfig { rounds: 0, ..ReactionConfig::default() },
+            ReactionConfig { min_delay: Duration::from_secs(5), ..ReactionConfig::default() },
+            ReactionConfig { timeout: Duration::from_secs(0), ..ReactionConfig::default() },
+        ].iter() {
+            assert!(matches!(ReactionGame::new(*config), Err(Error::InvalidInput(_))));
+        }
+
+        Ok(())
+    }
+
+    /// Tests a game with a hit, a false start, a wrong button and a missed cue, driven by injected presses.
+    #[test]
+    fn test_reaction_rounds() -> Result<(), Error> {
+        let (mut hat, mut game, clock) = setup(4)?;
+        game.start(&mut hat)?;
+        assert!(shows(&hat, "----")?);
+
+        // Hit: the cue is given once the wait is over
+        let delay = game.delay;
+        assert!(delay >= Duration::from_millis(1000) && delay <= Duration::from_millis(4000));
+        clock.advance(delay - Duration::from_millis(1));
+        assert!(game.update(&mut hat, None)?.is_none() && game.cued().is_none());
+        clock.advance(Duration::from_millis(1));
+        assert!(game.update(&mut hat, None)?.is_none());
+
+        let target = game.cued().unwrap();
+        assert!(match target {
+            ButtonId::A => hat.lights.red.state,
+            ButtonId::B => hat.lights.green.state,
+            ButtonId::C => hat.lights.blue.state,
+        });
+
+        clock.advance(Duration::from_millis(250));
+        let round = game.update(&mut hat, Some(target))?.unwrap();
+        assert!(round.outcome == RoundOutcome::Hit(Duration::from_millis(250)));
+        assert!(shows(&hat, " 250")?);
+        assert!(!hat.lights.red.state && !hat.lights.green.state && !hat.lights.blue.state);
+
+        // False start: press before the cue
+        clock.advance(Duration::from_millis(500));
+        let round = game.update(&mut hat, Some(ButtonId::A))?.unwrap();
+        assert!(round.outcome == RoundOutcome::FalseStart);
+        assert!(shows(&hat, "SOON")?);
+
+        // Wrong button
+        clock.advance(game.delay);
+        game.update(&mut hat, None)?;
+        let wrong = match game.cued().unwrap() {
+            ButtonId::A => ButtonId::B,
+            _ => ButtonId::A,
+        };
+        assert!(game.update(&mut hat, Some(wrong))?.unwrap().outcome == RoundOutcome::WrongButton(wrong));
+        assert!(shows(&hat, "OOPS")?);
+
+        // Missed: the round ends after the timeout
+        clock.advance(game.delay);
+        game.update(&mut hat, None)?;
+        clock.advance(Duration::from_secs(2));
+        assert!(game.update(&mut hat, None)?.is_none());
+        clock.advance(Duration::from_millis(1));
+        assert!(game.update(&mut hat, None)?.unwrap().outcome == RoundOutcome::Missed);
+
+        // The best time is shown at the end
+        assert!(game.is_finished());
+        assert!(game.update(&mut hat, Some(ButtonId::A))?.is_none());
+        assert!(shows(&hat, " 250")?);
+
+        let results = game.results();
+        assert!(results.rounds.len() == 4);
+        assert!(results.best() == Some(Duration::from_millis(250)));
+        assert!(results.average() == Some(Duration::from_millis(250)));
+        assert!(results.false_starts() == 1);
+
+        Ok(())
+    }
+
+    /// Tests a whole game read from the simulated buttons, the same seed giving the same rounds.
+    #[test]
+    fn test_reaction_run() -> Result<(), Error> {
+        let (mut hat, mut game, _clock) = setup(3)?;
+
+        // Nobody plays: every cue is missed
+        game.run(&mut hat)?;
+        let results = game.results().clone();
+        assert!(results.rounds.iter().all(|round| round.outcome == RoundOutcome::Missed));
+        assert!(results.best().is_none());
+        assert!(shows(&hat, "----")?);
+
+        // Holding a button from the start is a single false start, the following rounds are missed
+        let (_hat, mut replayed, _clock) = setup(3)?;
+        hat.buttons.get_mut(ButtonId::B).set_simulated_state(true);
+        replayed.run(&mut hat)?;
+
+        let outcomes: Vec<_> = replayed.results().rounds.iter().map(|round| round.outcome).collect();
+        assert!(outcomes == [RoundOutcome::FalseStart, RoundOutcome::Missed, RoundOutcome::Missed]);
+
+        let targets = |results: &GameResults| results.rounds.iter().map(|round| (round.target, round.delay)).collect::<Vec<_>>();
+        assert!(targets(replayed.results()) == targets(&results));
+
+        Ok(())
+    }
+}
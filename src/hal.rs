@@ -0,0 +1,205 @@
+use core::fmt::Debug;
+#[cfg(feature = "hardware")]
+use std::io;
+#[cfg(feature = "hardware")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "hardware")]
+use rppal::gpio::{self, Gpio, InputPin, Level, OutputPin};
+#[cfg(feature = "hardware")]
+use rppal::i2c::{self, I2c};
+#[cfg(feature = "hardware")]
+use rppal::spi;
+use crate::buzzer;
+
+pub use crate::ht16k33::I2cBus;
+
+/// Digital output driving a light or a clock, data or chip select line.
+/// Implemented for the rppal output pin, other implementations allow to use other boards or to record the writes.
+pub trait DigitalOutput: Debug + Send {
+
+    /// Set the level of the output.
+    ///
+    /// # Arguments
+    ///
+    /// * `high` - true for high, false for low.
+    fn set_level(&mut self, high: bool);
+}
+
+/// Digital input reading a button.
+/// Implemented for the rppal input pin, other implementations allow to use other boards or to inject presses.
+pub trait DigitalInput: Debug + Send {
+
+    /// Get whether the input is high.
+    fn is_high(&self) -> bool;
+}
+
+/// Output playing a tone with PWM, driving the buzzer.
+/// Implemented for the rppal output pin, other implementations allow to use other boards or to record the tones.
+pub trait ToneOutput: Debug + Send {
+
+    /// Start playing a tone.
+    ///
+    /// # Arguments
+    ///
+    /// * `frequency` - Frequency in hertz.
+    fn start_tone(&mut self, frequency: f64) -> Result<(), buzzer::Error>;
+
+    /// Stop playing the tone.
+    fn stop_tone(&mut self) -> Result<(), buzzer::Error>;
+}
+
+/// Source of the Raspberry Pi peripherals opened by the drivers on setup.
+/// Implemented by `Rppal`, other implementations allow to test how the drivers behave when the hardware is absent.
+#[cfg(feature = "hardware")]
+pub trait Backend: Debug + Send + Sync {
+
+    /// Open the GPIO peripheral.
+    fn gpio(&self) -> Result<Gpio, gpio::Error>;
+
+    /// Open an I2C bus.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - I2C bus number, None for the default bus.
+    fn i2c(&self, bus: Option<u8>) -> Result<I2c, i2c::Error>;
+}
+
+/// Backend opening the peripherals of the Raspberry Pi with rppal, used by default.
+#[cfg(feature = "hardware")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Rppal;
+
+#[cfg(feature = "hardware")]
+impl Backend for Rppal {
+    fn gpio(&self) -> Result<Gpio, gpio::Error> {
+        Gpio::new()
+    }
+
+    fn i2c(&self, bus: Option<u8>) -> Result<I2c, i2c::Error> {
+        match bus {
+            Some(bus) => I2c::with_bus(bus),
+            None => I2c::new(),
+        }
+    }
+}
+
+/// Backend opening the GPIO once and handing clones of it to every driver, instead of opening it for each pin.
+/// A failure to open it is kept and returned to every driver. The I2C buses are opened by the inner backend.
+/// Clones share the GPIO.
+#[cfg(feature = "hardware")]
+#[derive(Debug, Clone)]
+pub struct SharedGpio {
+
+    /// backend opening the peripherals
+    inner: Arc<dyn Backend>,
+
+    /// result of opening the GPIO, None before the first driver needs it
+    gpio: Arc<Mutex<Option<Result<Gpio, gpio::Error>>>>,
+}
+
+#[cfg(feature = "hardware")]
+impl SharedGpio {
+
+    /// Creates a backend sharing the GPIO opened by another backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - Backend opening the peripherals, such as `Rppal`.
+    pub fn new<B: Backend + 'static>(inner: B) -> SharedGpio {
+        Self {
+            inner: Arc::new(inner),
+            gpio: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl Backend for SharedGpio {
+    fn gpio(&self) -> Result<Gpio, gpio::Error> {
+        let mut gpio = self.gpio.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match gpio.get_or_insert_with(|| self.inner.gpio()) {
+            Ok(gpio) => Ok(gpio.clone()),
+            Err(err) => Err(copy_gpio_error(err)),
+        }
+    }
+
+    fn i2c(&self, bus: Option<u8>) -> Result<I2c, i2c::Error> {
+        self.inner.i2c(bus)
+    }
+}
+
+/// Copies a GPIO error, which is not Clone, to return it to every driver.
+#[cfg(feature = "hardware")]
+fn copy_gpio_error(err: &gpio::Error) -> gpio::Error {
+    match err {
+        gpio::Error::UnknownModel => gpio::Error::UnknownModel,
+        gpio::Error::PinNotAvailable(pin) => gpio::Error::PinNotAvailable(*pin),
+        gpio::Error::PermissionDenied(path) => gpio::Error::PermissionDenied(path.clone()),
+        gpio::Error::Io(err) => gpio::Error::Io(io::Error::new(err.kind(), err.to_string())),
+        gpio::Error::ThreadPanic => gpio::Error::ThreadPanic,
+    }
+}
+
+/// Get whether opening the GPIO failed because the program is not running on a Raspberry Pi
+/// or is not allowed to access the peripheral.
+#[cfg(feature = "hardware")]
+pub(crate) fn is_gpio_absent(err: &gpio::Error) -> bool {
+    match err {
+        gpio::Error::UnknownModel | gpio::Error::PermissionDenied(_) => true,
+        gpio::Error::Io(err) => is_io_absent(err),
+        _ => false,
+    }
+}
+
+/// Get whether opening an I2C bus failed because the program is not running on a Raspberry Pi
+/// or is not allowed to access the bus.
+#[cfg(feature = "hardware")]
+pub(crate) fn is_i2c_absent(err: &i2c::Error) -> bool {
+    match err {
+        i2c::Error::UnknownModel => true,
+        i2c::Error::Io(err) => is_io_absent(err),
+        _ => false,
+    }
+}
+
+/// Get whether opening an SPI bus failed because the device is missing or not accessible.
+#[cfg(feature = "hardware")]
+pub(crate) fn is_spi_absent(err: &spi::Error) -> bool {
+    match err {
+        spi::Error::Io(err) => is_io_absent(err),
+        _ => false,
+    }
+}
+
+/// Get whether the device file is missing or not accessible.
+#[cfg(feature = "hardware")]
+fn is_io_absent(err: &io::Error) -> bool {
+    matches!(err.kind(), io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied)
+}
+
+#[cfg(feature = "hardware")]
+impl DigitalOutput for OutputPin {
+    fn set_level(&mut self, high: bool) {
+        self.write(if high { Level::High } else { Level::Low });
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl DigitalInput for InputPin {
+    fn is_high(&self) -> bool {
+        InputPin::is_high(self)
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl ToneOutput for OutputPin {
+    fn start_tone(&mut self, frequency: f64) -> Result<(), buzzer::Error> {
+        let pin = self.pin();
+        self.set_pwm_frequency(frequency, 0.90).map_err(|source| buzzer::Error::Gpio { pin, source })
+    }
+
+    fn stop_tone(&mut self) -> Result<(), buzzer::Error> {
+        let pin = self.pin();
+        self.clear_pwm().map_err(|source| buzzer::Error::Gpio { pin, source })
+    }
+}
@@ -1,30 +1,235 @@
 use std::error;
 use std::fmt;
+use std::fmt::Debug;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+#[cfg(feature = "hardware")]
 use rppal::i2c::I2c;
+use crate::error::invalid_input;
+#[cfg(feature = "hardware")]
+use crate::hal::{self, Backend, Rppal};
+use crate::rainbow_hat::Component;
+use crate::recorder::{MemoryRecorder, Payload, Recorder};
+use crate::retry::RetryPolicy;
+use crate::trace::Tracer;
+use crate::units::Brightness;
 
 pub const DEFAULT_ADDRESS: u16 = 0x70;
+pub const FIRST_ADDRESS: u16 = 0x70;
+pub const LAST_ADDRESS: u16 = 0x77;
 pub const HT16K33_BLINK_CMD: u8 = 0x80;
 pub const HT16K33_BLINK_DISPLAYON: u8 = 0x01;
 pub const HT16K33_BLINK_OFF: u8 = 0x00;
 pub const HT16K33_BLINK_2HZ: u8 = 0x02;
 pub const HT16K33_BLINK_1HZ: u8 = 0x04;
 pub const HT16K33_BLINK_HALFHZ: u8 = 0x06;
+pub const HT16K33_BLINK_MASK: u8 = 0x06;
 pub const HT16K33_SYSTEM_SETUP: u8 = 0x20;
 pub const HT16K33_OSCILLATOR: u8 = 0x01;
 pub const HT16K33_CMD_BRIGHTNESS: u8 = 0xE0;
 
+/// Size of the display RAM: 2 bytes for each of the 8 rows of 16 LEDs.
+pub const BUFFER_SIZE: usize = 16;
+
+/// Writes recorded in simulation mode: command byte and data of each write.
+pub type Transcript = Vec<(u8, Vec<u8>)>;
+
+/// Commands decoded from the writes recorded in simulation mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commands {
+
+    /// writes decoded
+    transcript: Transcript,
+}
+
+impl Commands {
+
+    /// Iterate over the commands, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = Command<'_>> {
+        self.transcript.iter().filter_map(|(command, data)| Command::decode(*command, data))
+    }
+
+    /// Get the last command, if any.
+    pub fn last(&self) -> Option<Command<'_>> {
+        self.iter().last()
+    }
+
+    /// Get the number of commands.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Get whether no command was written.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'a> PartialEq<Vec<Command<'a>>> for Commands {
+    fn eq(&self, other: &Vec<Command<'a>>) -> bool {
+        self.iter().eq(other.iter().copied())
+    }
+}
+
+/// Blink rate of the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlinkRate {
+
+    /// No blinking.
+    Off,
+
+    /// Blink every 2 seconds.
+    HalfHz,
+
+    /// Blink every second.
+    OneHz,
+
+    /// Blink twice per second.
+    TwoHz,
+}
+
+impl BlinkRate {
+
+    /// Get the blink bits of the display setup command.
+    pub fn bits(self) -> u8 {
+        match self {
+            BlinkRate::Off => HT16K33_BLINK_OFF,
+            BlinkRate::HalfHz => HT16K33_BLINK_HALFHZ,
+            BlinkRate::OneHz => HT16K33_BLINK_1HZ,
+            BlinkRate::TwoHz => HT16K33_BLINK_2HZ,
+        }
+    }
+}
+
+/// Wiring of an LED matrix to the HT16K33, used to translate (x, y) coordinates to the display RAM.
+/// The RAM holds one row of 16 LEDs every 2 bytes, the first byte holding the columns 0 to 7.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatrixLayout {
+
+    /// 16x8 matrix wired as the RAM: x is the column from 0 to 15 and y the row from 0 to 7.
+    #[default]
+    Rows16x8,
+
+    /// Adafruit 8x8 backpack: rows use the first byte of each RAM row
+    /// and the columns are rotated by one bit, the column 0 being wired to the bit 7.
+    Backpack8x8,
+}
+
+impl MatrixLayout {
+
+    /// Get the number of columns.
+    pub fn width(self) -> usize {
+        match self {
+            MatrixLayout::Rows16x8 => 16,
+            MatrixLayout::Backpack8x8 => 8,
+        }
+    }
+
+    /// Get the number of rows.
+    pub fn height(self) -> usize {
+        8
+    }
+
+    /// Get the buffer byte and bit of a pixel, or None if out of the matrix.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - Column of the pixel.
+    /// * `y` - Row of the pixel.
+    pub fn position(self, x: usize, y: usize) -> Option<(usize, u8)> {
+        if x >= self.width() || y >= self.height() {
+            return None;
+        }
+
+        match self {
+            MatrixLayout::Rows16x8 => Some((y * 2 + x / 8, (x % 8) as u8)),
+            MatrixLayout::Backpack8x8 => Some((y * 2, ((x + 7) % 8) as u8)),
+        }
+    }
+}
+
+/// Command of the HT16K33, as described in the datasheet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command<'a> {
+
+    /// Turn the system oscillator on (normal operation) or off (standby).
+    SystemSetup {
+        oscillator: bool,
+    },
+
+    /// Turn the display on or off and select the blink frequency bits.
+    DisplaySetup {
+        on: bool,
+        blink: u8,
+    },
+
+    /// Set the dimming level, from 0 to 15.
+    Brightness(u8),
+
+    /// Write data to the display RAM starting at an address.
+    WriteRam {
+        offset: u8,
+        data: &'a [u8],
+    },
+}
+
+impl<'a> Command<'a> {
+
+    /// Encodes the command as the command byte followed by its data.
+    /// Out of range values are masked so they can't change the kind of command.
+    pub fn encode(&self) -> (u8, &'a [u8]) {
+        match *self {
+            Command::SystemSetup { oscillator } => {
+                let osc = if oscillator { HT16K33_OSCILLATOR } else { 0 };
+                (HT16K33_SYSTEM_SETUP | osc, &[])
+            }
+            Command::DisplaySetup { on, blink } => {
+                let on = if on { HT16K33_BLINK_DISPLAYON } else { 0 };
+                (HT16K33_BLINK_CMD | (blink & HT16K33_BLINK_MASK) | on, &[])
+            }
+            Command::Brightness(level) => (HT16K33_CMD_BRIGHTNESS | (level & 0x0F), &[]),
+            Command::WriteRam { offset, data } => (offset & 0x0F, data),
+        }
+    }
+
+    /// Decodes a command byte followed by its data.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - Command byte.
+    /// * `data` - Data following the command byte.
+    pub fn decode(command: u8, data: &'a [u8]) -> Option<Command<'a>> {
+        match command & 0xF0 {
+            0x00 => Some(Command::WriteRam { offset: command, data }),
+            HT16K33_SYSTEM_SETUP => Some(Command::SystemSetup { oscillator: command & HT16K33_OSCILLATOR != 0 }),
+            HT16K33_BLINK_CMD => Some(Command::DisplaySetup {
+                on: command & HT16K33_BLINK_DISPLAYON != 0,
+                blink: command & HT16K33_BLINK_MASK,
+            }),
+            HT16K33_CMD_BRIGHTNESS => Some(Command::Brightness(command & 0x0F)),
+            _ => None,
+        }
+    }
+}
+
 /// Driver for interfacing with a Holtek HT16K33 16x8 LED driver.
+/// Send but not Sync, as its bus and trace hook are only Send: use `into_shared` to share it between threads.
 #[derive(Debug)]
 pub struct HT16K33 {
 
     /// Address of i2c
     i2c_address: u16,
 
+    /// I2C bus number, None for the default bus of the Raspberry Pi
+    i2c_bus: Option<u8>,
+
     /// I2C. Optional as not used in simulated mode.
-    i2c: Option<Box<I2c>>,
+    i2c: Option<Box<dyn I2cBus>>,
 
     /// buffer with data to be printed
-    pub buffer: [u8; 8],
+    pub buffer: [u8; BUFFER_SIZE],
 
     /// frequency for blink: one of HT16K33_BLINK_OFF, HT16K33_BLINK_2HZ, HT16K33_BLINK_1HZ, HT16K33_BLINK_HALFHZ
     blink_frequency: u8,
@@ -32,11 +237,45 @@ pub struct HT16K33 {
     /// brightness between 0 and 15
     brightness: u8,
 
+    /// is the display turned on
+    display_on: bool,
+
+    /// hook receiving a message before each hardware operation
+    tracer: Tracer,
+
+    /// backend opening the peripherals on setup
+    #[cfg(feature = "hardware")]
+    backend: Box<dyn Backend>,
+
+    /// whether to switch to simulation mode on setup when the hardware is absent
+    auto_simulate: bool,
+
     /// In simulation mode, no interaction with the hardware is done to simplify testability.
     simulation: bool, 
 
+    /// recorder of the commands and data written to the I2C bus in simulation mode
+    recorder: Box<dyn Recorder>,
+
     /// is the setup completed
-    is_setup: bool
+    is_setup: bool,
+
+    /// error of a failed setup, returned by the following operations
+    setup_error: Option<Arc<Error>>,
+
+    /// number of consecutive failed writes after which the device is reinitialized automatically
+    auto_reinitialize: Option<u32>,
+
+    /// number of consecutive failed writes
+    consecutive_errors: u32,
+
+    /// wiring of the LED matrix used by the (x, y) helpers
+    layout: MatrixLayout,
+
+    /// LED lit by the next step of the test pattern
+    test_pattern_led: usize,
+
+    /// number of successful writes of the display buffer
+    write_count: u64,
 }
 
 impl HT16K33 {
@@ -44,77 +283,365 @@ impl HT16K33 {
     /// Create an HT16K33 driver for device.
     /// Uses the specified I2C address (defaults to 0x70) and I2C device.
     pub fn new() -> Result<HT16K33, Error> {
+        Ok(HT16K33::create())
+    }
 
-        Ok(Self {
+    /// Creates the driver with a blank buffer at the default address, not set up.
+    fn create() -> HT16K33 {
+        Self {
             i2c_address: DEFAULT_ADDRESS,
+            i2c_bus: None,
             i2c: None,
-            buffer:[0; 8],
+            buffer:[0; BUFFER_SIZE],
             blink_frequency: HT16K33_BLINK_OFF,
-            brightness: 15 as u8,
-            simulation: false,
+            brightness: 15,
+            display_on: true,
+            tracer: Tracer::new(),
+            #[cfg(feature = "hardware")]
+            backend: Box::new(Rppal),
+            auto_simulate: false,
+            simulation: !cfg!(feature = "hardware"),
+            recorder: Box::new(MemoryRecorder::new()),
             is_setup: false,
-         })
+            setup_error: None,
+            auto_reinitialize: None,
+            consecutive_errors: 0,
+            layout: MatrixLayout::default(),
+            test_pattern_led: 0,
+            write_count: 0,
+        }
+    }
+
+    /// Create an HT16K33 driver in simulation mode, recording the writes instead of accessing the hardware.
+    pub fn with_simulation() -> Result<HT16K33, Error> {
+        let mut ht16k33 = HT16K33::new()?;
+        ht16k33.simulation = true;
+        Ok(ht16k33)
+    }
+
+    /// Enables simulation mode: no interaction with the hardware is done.
+    /// Enable it before the first operation, which initializes the hardware.
+    pub fn enable_simulation(&mut self) {
+        self.simulation = true;
+    }
+
+    /// Get whether simulation mode is enabled.
+    pub fn is_simulation(&self) -> bool {
+        self.simulation
+    }
+
+    /// Switch to simulation mode on setup when the hardware is absent, for example when running off the Raspberry Pi,
+    /// instead of returning an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to fall back to simulation mode.
+    pub fn set_auto_simulate(&mut self, enabled: bool) {
+        self.auto_simulate = enabled;
+    }
+
+    /// Get whether the driver switches to simulation mode on setup when the hardware is absent.
+    pub fn is_auto_simulate(&self) -> bool {
+        self.auto_simulate
+    }
+
+    /// Set the backend opening the I2C bus on setup.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Backend to use.
+    #[cfg(feature = "hardware")]
+    pub fn set_backend<B: Backend + 'static>(&mut self, backend: B) {
+        self.backend = Box::new(backend);
+    }
+
+    /// Set a hook receiving a message before each hardware operation, also in simulation mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Closure receiving each message.
+    pub fn set_trace<F: FnMut(&str) + Send + 'static>(&mut self, hook: F) {
+        self.tracer.set(hook);
+    }
+
+    /// Remove the trace hook.
+    pub fn clear_trace(&mut self) {
+        self.tracer.clear();
+    }
+
+    /// Create an HT16K33 driver for a device at another address, or on another bus of the Raspberry Pi.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - I2C bus number, None for the default bus.
+    /// * `address` - Address of the device: 0x70 to 0x77.
+    pub fn new_i2c(bus: Option<u8>, address: u16) -> Result<HT16K33, Error> {
+
+        if !(FIRST_ADDRESS..=LAST_ADDRESS).contains(&address) {
+            return Err(Error::InvalidArgument(invalid_input("address", format!("0x{:02X}", address), &format!("0x{:02X} to 0x{:02X}", FIRST_ADDRESS, LAST_ADDRESS))));
+        }
+
+        let mut ht16k33 = HT16K33::new()?;
+        ht16k33.i2c_address = address;
+        ht16k33.i2c_bus = bus;
+        Ok(ht16k33)
+    }
+
+    /// Get the I2C address of the device.
+    pub fn address(&self) -> u16 {
+        self.i2c_address
+    }
+
+    /// Get the I2C bus number, None for the default bus of the Raspberry Pi.
+    pub fn bus(&self) -> Option<u8> {
+        self.i2c_bus
+    }
+
+    /// Create an HT16K33 driver writing to the given bus instead of opening the Raspberry Pi I2C bus.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - Bus connected to the device.
+    pub fn with_bus<B: I2cBus + 'static>(bus: B) -> HT16K33 {
+        let mut ht16k33 = HT16K33::create();
+        ht16k33.i2c = Some(Box::new(bus));
+        ht16k33.simulation = false;
+        ht16k33
+    }
+
+    /// Sends a command to the device.
+    /// Initializes the driver first if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - Command to send.
+    fn send(&mut self, command: Command) -> Result <(), Error> {
+
+        self.setup()?;
+
+        let err = match self.bus_write(command) {
+            Ok(()) => {
+                self.consecutive_errors = 0;
+                return Ok(());
+            }
+            Err(err) => err,
+        };
+
+        self.consecutive_errors += 1;
+
+        match self.auto_reinitialize {
+            Some(max_errors) if self.consecutive_errors >= max_errors => {
+
+                // The device may have lost its configuration: restore it and retry once
+                self.consecutive_errors = 0;
+                self.restore()?;
+                self.bus_write(command)
+            }
+            _ => Err(err),
+        }
     }
 
-    /// Encapsulates block write to I2C bus.
+    /// Writes a command to the bus, or records the write in simulation mode.
     ///
     /// # Arguments
     ///
     /// * `command` - Command to write.
-    /// * `buffer` - Buffer to write.
-    fn i2c_block_write(&mut self, command: u8, buffer: &[u8]) -> Result <(), Error> {
+    fn bus_write(&mut self, command: Command) -> Result <(), Error> {
+
+        self.tracer.trace(|| match command {
+            Command::WriteRam { data, .. } => format!("HT16K33 write_display: {} bytes", data.len()),
+            command => format!("HT16K33 {:?}", command),
+        });
+
+        let (command, buffer) = command.encode();
 
         if !self.simulation {
 
             let i2c = self.i2c.as_deref_mut().unwrap();
             i2c.block_write(command, buffer)?;
+        } else {
+            self.recorder.record(Component::Display, "write", Payload::Write { register: command, data: buffer.to_vec() });
         }
 
         Ok(())
     }
 
-    /// Initialize driver with LEDs enabled and all turned off.
-    fn setup(&mut self) -> Result <(), Error> {
+    /// Initialize driver with LEDs enabled, applying the blink frequency and brightness.
+    /// Operations writing to the display call it lazily. If the setup fails, the error is remembered
+    /// and returned by every following operation until `try_setup()` succeeds.
+    pub fn setup(&mut self) -> Result <(), Error> {
+
+        if let Some(err) = &self.setup_error {
+            return Err(Error::Setup(err.clone()));
+        }
 
         if !self.is_setup {
-            if !self.simulation {
+            if let Err(err) = self.configure() {
+                let err = Arc::new(err);
+                self.setup_error = Some(err.clone());
+                return Err(Error::Setup(err));
+            }
 
-                let mut i2c = I2c::new()?;
+            self.is_setup = true;
+        }
 
-                // Set the I2C slave address to the device we're communicating with.
-                i2c.set_slave_address(self.i2c_address)?;
+        Ok(())
+    }
 
-                i2c.block_write(
-                    (HT16K33_SYSTEM_SETUP | HT16K33_OSCILLATOR) as u8, &[]
-                )?;
+    /// Release the I2C bus: its handle is dropped now rather than when the driver is dropped, the display keeping
+    /// its content. Any failed setup is forgotten and the driver is set up again on its next write, from the cached
+    /// state. A bus given with `with_bus` is dropped too.
+    pub fn release(&mut self) {
+        self.i2c = None;
+        self.setup_error = None;
+        self.is_setup = false;
+    }
 
-                self.i2c = Some(Box::new(i2c));
-            }
+    /// Forget any failed setup and initialize the driver again.
+    pub fn try_setup(&mut self) -> Result <(), Error> {
+        self.setup_error = None;
+        self.setup()
+    }
+
+    /// Forget any failed setup and initialize the driver again, retrying with the policy while the setup fails
+    /// with an error which plausibly resolves by itself, such as a busy I2C bus on boot.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - Number of attempts and backoff.
+    pub fn try_setup_with_retry(&mut self, policy: RetryPolicy) -> Result <(), Error> {
+        policy.run(|_attempt| self.try_setup())
+    }
 
-            self.set_blink(self.blink_frequency)?;
+    /// Reinitialize the device from the cached state, for example after a brown out or a hot plug:
+    /// runs the system setup again, applies the blink frequency and brightness, and writes the buffer.
+    pub fn reinitialize(&mut self) -> Result <(), Error> {
+        self.setup_error = None;
+        self.is_setup = false;
 
-            self.set_brightness(self.brightness)?;
+        self.restore()?;
+        self.is_setup = true;
 
-            self.is_setup = true;
+        Ok(())
+    }
+
+    /// Reinitialize the device automatically when writes fail repeatedly.
+    /// After the given number of consecutive failed writes, the device is reinitialized
+    /// and the failing write is retried once before returning an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_errors` - Number of consecutive failed writes triggering a reinitialization, None to disable.
+    pub fn set_auto_reinitialize(&mut self, max_errors: Option<u32>) {
+        self.auto_reinitialize = max_errors;
+        self.consecutive_errors = 0;
+    }
+
+    /// Sends the configuration and the buffer to the device.
+    fn restore(&mut self) -> Result <(), Error> {
+        self.configure()?;
+
+        let buffer = self.buffer;
+        self.bus_write(Command::WriteRam { offset: 0, data: &buffer })
+    }
+
+    /// Get whether the setup is completed.
+    pub fn is_setup(&self) -> bool {
+        self.is_setup
+    }
+
+    /// Opens the I2C bus if needed and sends the configuration to the device.
+    fn configure(&mut self) -> Result <(), Error> {
+
+        #[cfg(feature = "hardware")]
+        if !self.simulation && self.i2c.is_none() {
+
+            let (bus, address) = (self.i2c_bus, self.i2c_address);
+            match self.backend.i2c(bus) {
+                Ok(mut i2c) => {
+                    // Set the I2C slave address to the device we're communicating with.
+                    i2c.set_slave_address(address).map_err(|source| Error::I2c { bus, address: Some(address), source })?;
+
+                    self.i2c = Some(Box::new(AddressedI2c { i2c, address }));
+                }
+                Err(err) if self.auto_simulate && hal::is_i2c_absent(&err) => {
+                    self.tracer.trace(|| format!("HT16K33: hardware unavailable ({}), simulating", err));
+                    self.simulation = true;
+                }
+                Err(source) => return Err(Error::I2c { bus, address: Some(address), source }),
+            }
         }
 
+        self.bus_write(Command::SystemSetup { oscillator: true })?;
+        self.bus_write(self.display_setup_command())?;
+        self.bus_write(Command::Brightness(self.brightness))?;
+
         Ok(())
     }
 
-    /// Blink display at specified frequency
+    /// Blink display at specified rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate` - Blink rate.
+    pub fn set_blink(&mut self, rate: BlinkRate) -> Result <(), Error> {
+        self.set_blink_raw(rate.bits())
+    }
+
+    /// Blink display at specified frequency, given as the raw blink bits of the display setup command.
     ///
     /// # Arguments
     ///
     /// * `frequency` - frequency must be a value allowed by the HT16K33, specifically one of: HT16K33_BLINK_OFF, HT16K33_BLINK_2HZ, HT16K33_BLINK_1HZ, or HT16K33_BLINK_HALFHZ.
-    pub fn set_blink(&mut self, frequency: u8) -> Result <(), Error> {
+    pub fn set_blink_raw(&mut self, frequency: u8) -> Result <(), Error> {
+
+        if frequency & !HT16K33_BLINK_MASK != 0 {
+            return Err(Error::InvalidArgument(invalid_input("blink frequency", format!("0x{:02X}", frequency),
+                "HT16K33_BLINK_OFF, HT16K33_BLINK_2HZ, HT16K33_BLINK_1HZ or HT16K33_BLINK_HALFHZ")));
+        }
+
         self.blink_frequency = frequency;
-        self.i2c_block_write(
-            (HT16K33_BLINK_CMD | HT16K33_BLINK_DISPLAYON | frequency) as u8, &[]
-        )?;
+        self.write_display_setup()?;
+
+        Ok(())
+    }
+
+    /// Get the blink frequency, as the raw blink bits of the display setup command.
+    pub fn blink_frequency(&self) -> u8 {
+        self.blink_frequency
+    }
+
+    /// Turn the display on or off.
+    /// The buffer, brightness and blink frequency are kept while the display is off.
+    ///
+    /// # Arguments
+    ///
+    /// * `on` - true to turn the display on, false to turn it off.
+    pub fn set_display(&mut self, on: bool) -> Result <(), Error> {
+        self.display_on = on;
+        self.write_display_setup()?;
 
         Ok(())
     }
 
+    /// Get whether the display is turned on.
+    pub fn is_display_on(&self) -> bool {
+        self.display_on
+    }
+
+    /// Writes the display setup command with the current display state and blink frequency.
+    fn write_display_setup(&mut self) -> Result <(), Error> {
+        self.send(self.display_setup_command())
+    }
+
+    /// Get the display setup command for the current display state and blink frequency.
+    fn display_setup_command(&self) -> Command<'static> {
+        Command::DisplaySetup {
+            on: self.display_on,
+            blink: self.blink_frequency,
+        }
+    }
+
     // Set brightness of entire display to specified value.
     // Supports 16 levels, from 0 to 15.
     ///
@@ -122,72 +649,698 @@ impl HT16K33 {
     ///
     /// * `brightness` - level of brightness, from 0 to 15.
     pub fn set_brightness(&mut self, brightness: u8) -> Result <(), Error> {
-        
-        assert!(brightness <= 15);
+
+        HT16K33::check_brightness(brightness)?;
 
         self.brightness = brightness;
 
-        self.i2c_block_write(
-            (HT16K33_CMD_BRIGHTNESS | brightness) as u8, &[]
-        )?;
+        self.send(Command::Brightness(brightness))?;
 
         Ok(())
     }
 
-    /// Write display buffer to display hardware.
-    pub fn write_display(&mut self) -> Result <(), Error> {
+    /// Set brightness of entire display from a brightness shared with the other outputs of the board,
+    /// quantized to the nearest of the 16 levels. The lowest level still lights the display.
+    ///
+    /// # Arguments
+    ///
+    /// * `brightness` - Brightness, for example `Brightness::percent(30)`.
+    pub fn dim_to<B: Into<Brightness>>(&mut self, brightness: B) -> Result <(), Error> {
+        self.set_brightness(brightness.into().to_4bit())
+    }
 
-        if !self.is_setup {
-            let _result = self.setup();
-        }
-        
-        let buffer = self.buffer;
+    /// Set brightness of entire display.
+    ///
+    /// # Arguments
+    ///
+    /// * `brightness` - level of brightness, from 0 to 15.
+    #[deprecated(note = "set_brightness returns the error")]
+    pub fn try_set_brightness(&mut self, brightness: u8) -> Result <(), Error> {
+        self.set_brightness(brightness)
+    }
+
+    /// Validates a level of brightness.
+    ///
+    /// # Arguments
+    ///
+    /// * `brightness` - level of brightness, from 0 to 15.
+    fn check_brightness(brightness: u8) -> Result <(), Error> {
 
-        self.i2c_block_write(
-            0x00 as u8, &buffer
-        )?;
+        if brightness > 15 {
+            return Err(Error::InvalidArgument(invalid_input("brightness", brightness, "0 to 15")));
+        }
 
         Ok(())
     }
 
-    /// Clear contents of display buffer.
-    pub fn clear(&mut self) {
+    /// Get the brightness of the display, from 0 to 15.
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
 
-        for i in 0..self.buffer.len() {
-            self.buffer[i] = 0;
+    /// Fade the brightness of the display to a level, stepping through every intermediate level
+    /// with evenly spaced delays. Blocks for the duration of the fade.
+    /// If the display is off the fade is skipped: the target brightness is applied at once
+    /// and is visible when the display is turned back on.
+    ///
+    /// # Arguments
+    ///
+    /// * `to` - Target level of brightness, from 0 to 15.
+    /// * `duration` - Total duration of the fade.
+    pub fn fade_brightness(&mut self, to: u8, duration: Duration) -> Result <(), Error> {
+
+        HT16K33::check_brightness(to)?;
+
+        if !self.display_on {
+            return self.set_brightness(to);
         }
-    }
 
-    // TODO: set_led
-}
+        for (level, delay) in HT16K33::fade_steps(self.brightness, to, duration) {
+            if !self.simulation {
+                thread::sleep(delay);
+            }
+            self.set_brightness(level)?;
+        }
 
-/// Errors that can occur.
-#[derive(Debug)]
-pub enum Error {
+        Ok(())
+    }
 
-    /// I2C error.
-    I2c(rppal::i2c::Error),
-}
+    /// Computes the schedule of a brightness fade: each intermediate level and the delay to wait before applying it.
+    /// Levels are clamped to 15.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Current level of brightness.
+    /// * `to` - Target level of brightness.
+    /// * `duration` - Total duration of the fade.
+    pub fn fade_steps(from: u8, to: u8, duration: Duration) -> Vec<(u8, Duration)> {
+        let from = from.min(15);
+        let to = to.min(15);
 
-impl error::Error for Error {}
+        let levels: Vec<u8> = if to >= from {
+            (from + 1..=to).collect()
+        } else {
+            (to..from).rev().collect()
+        };
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &*self {
-            Error::I2c(err) => write!(f, "I2C error: {}", &err),
+        if levels.is_empty() {
+            return Vec::new();
         }
+
+        let delay = duration / levels.len() as u32;
+        levels.into_iter().map(|level| (level, delay)).collect()
     }
-}
 
-/// Converts I2C error
-impl From<rppal::i2c::Error> for Error {
-    fn from(err: rppal::i2c::Error) -> Error {
-        Error::I2c(err)
+    /// Set the recorder of the writes in simulation mode, for example one shared with the other drivers
+    /// to check the order of the operations across peripherals. The writes already recorded are not moved.
+    ///
+    /// # Arguments
+    ///
+    /// * `recorder` - Recorder to use.
+    pub fn set_recorder<R: Recorder + 'static>(&mut self, recorder: R) {
+        self.recorder = Box::new(recorder);
     }
-}
 
-/// Unit tests
+    /// Get the commands and data written to the I2C bus in simulation mode, from the display writes of the recorder.
+    pub fn transcript(&self) -> Transcript {
+        let mut transcript = Transcript::new();
+        self.recorder.visit(&mut |entry| {
+            if let (Component::Display, Payload::Write { register, data }) = (entry.component, &entry.payload) {
+                transcript.push((*register, data.clone()));
+            }
+        });
+        transcript
+    }
+
+    /// Get the commands written to the I2C bus in simulation mode, decoded.
+    pub fn commands(&self) -> Commands {
+        Commands { transcript: self.transcript() }
+    }
+
+    /// Clear the commands and data recorded in simulation mode.
+    pub fn clear_transcript(&mut self) {
+        self.recorder.retain(&mut |entry| entry.component != Component::Display);
+    }
+
+    /// Write display buffer to display hardware.
+    pub fn write_display(&mut self) -> Result <(), Error> {
+
+        let buffer = self.buffer;
+
+        self.send(Command::WriteRam { offset: 0, data: &buffer })?;
+        self.write_count += 1;
+
+        Ok(())
+    }
+
+    /// Get the number of successful writes of the display buffer since the driver was created.
+    pub fn write_count(&self) -> u64 {
+        self.write_count
+    }
+
+    /// Clear contents of display buffer.
+    pub fn clear(&mut self) {
+
+        for i in 0..self.buffer.len() {
+            self.buffer[i] = 0;
+        }
+    }
+
+    /// Get whether every LED of the display buffer is off.
+    pub fn is_blank(&self) -> bool {
+        self.buffer.iter().all(|&value| value == 0)
+    }
+
+    /// Set a single LED on or off in the display buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `led` - Index of the LED: 8 LEDs per byte of the buffer, starting at bit 0 of the first byte.
+    /// * `on` - true to turn the LED on, false to turn it off.
+    pub fn set_led(&mut self, led: usize, on: bool) -> Result <(), Error> {
+
+        if led >= self.buffer.len() * 8 {
+            return Err(Error::InvalidArgument(invalid_input("LED", led, &format!("0 to {}", self.buffer.len() * 8 - 1))));
+        }
+
+        let pos = led / 8;
+        let offset = led % 8;
+
+        if on {
+            self.buffer[pos] |= 1 << offset;
+        } else {
+            self.buffer[pos] &= !(1 << offset);
+        }
+
+        Ok(())
+    }
+
+    /// Light the LEDs one at a time to find miswired positions: each call clears the buffer, turns on the next LED
+    /// and writes the display, wrapping after the last of the 128 LEDs. Returns the index of the LED lit, as for `set_led`.
+    pub fn test_pattern_tick(&mut self) -> Result <usize, Error> {
+
+        let led = self.test_pattern_led;
+
+        self.clear();
+        self.set_led(led, true)?;
+        self.write_display()?;
+
+        self.test_pattern_led = (led + 1) % (self.buffer.len() * 8);
+
+        Ok(led)
+    }
+
+    /// Restart the test pattern from the first LED.
+    pub fn reset_test_pattern(&mut self) {
+        self.test_pattern_led = 0;
+    }
+
+    /// Set a single LED on or off in the display buffer, by its position in the matrix of the chip:
+    /// the row is the common (COM) pin and the column the row (ROW) pin the LED is wired to.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - Common pin: 0 to 7.
+    /// * `col` - Row pin: 0 to 15.
+    /// * `on` - true to turn the LED on, false to turn it off.
+    pub fn set_led_rc(&mut self, row: u8, col: u8, on: bool) -> Result <(), Error> {
+
+        if row > 7 || col > 15 {
+            return Err(Error::InvalidArgument(invalid_input("LED", format!("at row {} and column {}", row, col), "rows 0 to 7 and columns 0 to 15")));
+        }
+
+        // Two bytes per common pin, the first one for the row pins 0 to 7
+        self.set_led(row as usize * 16 + col as usize, on)
+    }
+
+    /// Set the wiring of the LED matrix used by `set_pixel_xy` and `clear_column`.
+    ///
+    /// # Arguments
+    ///
+    /// * `layout` - Layout of the matrix.
+    pub fn set_layout(&mut self, layout: MatrixLayout) {
+        self.layout = layout;
+    }
+
+    /// Get the wiring of the LED matrix.
+    pub fn layout(&self) -> MatrixLayout {
+        self.layout
+    }
+
+    /// Set a single LED of the matrix on or off in the display buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - Column of the LED, starting at 0 on the left.
+    /// * `y` - Row of the LED, starting at 0 on the top.
+    /// * `on` - true to turn the LED on, false to turn it off.
+    pub fn set_pixel_xy(&mut self, x: usize, y: usize, on: bool) -> Result <(), Error> {
+
+        let (pos, offset) = self.layout.position(x, y).ok_or_else(|| Error::InvalidArgument(invalid_input(
+            "pixel", format!("({}, {})", x, y), &format!("a pixel of the {}x{} matrix", self.layout.width(), self.layout.height()))))?;
+
+        if on {
+            self.buffer[pos] |= 1 << offset;
+        } else {
+            self.buffer[pos] &= !(1 << offset);
+        }
+
+        Ok(())
+    }
+
+    /// Turn off all the LEDs of a column of the matrix in the display buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - Column, starting at 0 on the left.
+    pub fn clear_column(&mut self, x: usize) -> Result <(), Error> {
+
+        for y in 0..self.layout.height() {
+            self.set_pixel_xy(x, y, false)?;
+        }
+
+        Ok(())
+    }
+
+    /// Start a batch of buffer mutations. The mutations made through the guard are staged and applied
+    /// to the buffer at once when the guard is committed or dropped, or discarded if it is aborted.
+    pub fn begin_update(&mut self) -> UpdateGuard<'_, HT16K33> {
+        UpdateGuard::new(self)
+    }
+
+    /// Move the driver behind a mutex so it can be shared between threads.
+    pub fn into_shared(self) -> HT16K33Handle {
+        HT16K33Handle {
+            inner: Arc::new(Mutex::new(self)),
+        }
+    }
+}
+
+/// Bus used to write to the HT16K33.
+/// Implemented for the rppal I2C bus, other implementations allow to use other buses or to inject failures.
+pub trait I2cBus: Debug + Send {
+
+    /// Write a command followed by a buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - Command to write.
+    /// * `buffer` - Buffer to write.
+    fn block_write(&mut self, command: u8, buffer: &[u8]) -> Result <(), Error>;
+}
+
+#[cfg(feature = "hardware")]
+impl I2cBus for I2c {
+    fn block_write(&mut self, command: u8, buffer: &[u8]) -> Result <(), Error> {
+        I2c::block_write(self, command, buffer).map_err(|source| Error::I2c { bus: Some(self.bus()), address: None, source })
+    }
+}
+
+/// I2C bus opened by the driver, whose errors name the address of the device.
+#[cfg(feature = "hardware")]
+#[derive(Debug)]
+struct AddressedI2c {
+
+    /// bus with the slave address set
+    i2c: I2c,
+
+    /// address of the device
+    address: u16,
+}
+
+#[cfg(feature = "hardware")]
+impl I2cBus for AddressedI2c {
+    fn block_write(&mut self, command: u8, buffer: &[u8]) -> Result <(), Error> {
+        let (bus, address) = (self.i2c.bus(), self.address);
+        self.i2c.block_write(command, buffer).map_err(|source| Error::I2c { bus: Some(bus), address: Some(address), source })
+    }
+}
+
+/// Display driven through an HT16K33 buffer.
+/// Implemented by the driver and by its shared handle, so higher level displays can use either.
+pub trait BufferedDisplay {
+
+    /// Run a closure with mutable access to the display buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - Closure receiving the buffer.
+    fn with_buffer<R, F: FnOnce(&mut [u8; BUFFER_SIZE]) -> R>(&mut self, f: F) -> R;
+
+    /// Write display buffer to display hardware.
+    fn write_display(&mut self) -> Result <(), Error>;
+
+    /// Start a batch of buffer mutations, applied at once when the guard is committed or dropped.
+    fn begin_update(&mut self) -> UpdateGuard<'_, Self> where Self: Sized {
+        UpdateGuard::new(self)
+    }
+}
+
+/// Batch of buffer mutations staged on a copy of the buffer.
+/// Dereferences to the staged buffer. The bits changed in the staged buffer are applied to the display buffer
+/// in a single step when the guard is committed or dropped, so a flush never sees half of the batch.
+/// Only the changed bits are written back: the writes made meanwhile through other handles of a shared driver
+/// to the other bits are kept.
+#[derive(Debug)]
+pub struct UpdateGuard<'a, D: BufferedDisplay> {
+
+    /// Display to update.
+    display: &'a mut D,
+
+    /// Staged buffer.
+    staged: [u8; BUFFER_SIZE],
+
+    /// Display buffer when the batch started, telling the bits changed through the guard.
+    original: [u8; BUFFER_SIZE],
+
+    /// Is the batch committed or aborted.
+    done: bool,
+}
+
+impl<'a, D: BufferedDisplay> UpdateGuard<'a, D> {
+
+    /// Creates a guard staging a copy of the display buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - Display to update.
+    pub fn new(display: &'a mut D) -> UpdateGuard<'a, D> {
+        let staged = display.with_buffer(|buffer| *buffer);
+
+        Self {
+            display,
+            staged,
+            original: staged,
+            done: false,
+        }
+    }
+
+    /// Apply the staged mutations to the display buffer.
+    pub fn commit(mut self) {
+        self.apply();
+    }
+
+    /// Discard the staged mutations.
+    pub fn abort(mut self) {
+        self.done = true;
+    }
+
+    /// Copies the bits changed in the staged buffer to the display buffer, once.
+    fn apply(&mut self) {
+        if !self.done {
+            let (staged, original) = (self.staged, self.original);
+            self.display.with_buffer(|buffer| {
+                for i in 0..BUFFER_SIZE {
+                    let changed = staged[i] ^ original[i];
+                    buffer[i] = (buffer[i] & !changed) | (staged[i] & changed);
+                }
+            });
+            self.done = true;
+        }
+    }
+}
+
+impl<'a, D: BufferedDisplay> Deref for UpdateGuard<'a, D> {
+    type Target = [u8; BUFFER_SIZE];
+
+    fn deref(&self) -> &[u8; BUFFER_SIZE] {
+        &self.staged
+    }
+}
+
+impl<'a, D: BufferedDisplay> DerefMut for UpdateGuard<'a, D> {
+    fn deref_mut(&mut self) -> &mut [u8; BUFFER_SIZE] {
+        &mut self.staged
+    }
+}
+
+impl<'a, D: BufferedDisplay> Drop for UpdateGuard<'a, D> {
+    fn drop(&mut self) {
+        self.apply();
+    }
+}
+
+impl BufferedDisplay for HT16K33 {
+    fn with_buffer<R, F: FnOnce(&mut [u8; BUFFER_SIZE]) -> R>(&mut self, f: F) -> R {
+        f(&mut self.buffer)
+    }
+
+    fn write_display(&mut self) -> Result <(), Error> {
+        HT16K33::write_display(self)
+    }
+}
+
+/// Handle to an HT16K33 driver shared between threads, Send and Sync.
+/// Clones refer to the same driver. Each operation holds an internal lock, so a buffer update
+/// made in `with_buffer` is never flushed half done by another thread.
+#[derive(Debug, Clone)]
+pub struct HT16K33Handle {
+
+    /// Shared driver.
+    inner: Arc<Mutex<HT16K33>>,
+}
+
+impl HT16K33Handle {
+
+    /// Locks the driver. A poisoned lock is recovered as the driver state stays consistent between calls.
+    fn lock(&self) -> MutexGuard<'_, HT16K33> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Enables simulation mode: the writes are recorded instead of sent to the hardware.
+    pub fn enable_simulation(&self) {
+        self.lock().enable_simulation();
+    }
+
+    /// Get whether simulation mode is enabled.
+    pub fn is_simulation(&self) -> bool {
+        self.lock().is_simulation()
+    }
+
+    /// Set a hook receiving a message before each write to the shared driver.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Closure receiving each message.
+    pub fn set_trace<F: FnMut(&str) + Send + 'static>(&self, hook: F) {
+        self.lock().set_trace(hook);
+    }
+
+    /// Remove the trace hook of the shared driver.
+    pub fn clear_trace(&self) {
+        self.lock().clear_trace();
+    }
+
+    /// Run a closure with exclusive access to the driver.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - Closure receiving the driver.
+    pub fn with_driver<R, F: FnOnce(&mut HT16K33) -> R>(&self, f: F) -> R {
+        f(&mut self.lock())
+    }
+
+    /// Run a closure with mutable access to the display buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - Closure receiving the buffer.
+    pub fn with_buffer<R, F: FnOnce(&mut [u8; BUFFER_SIZE]) -> R>(&self, f: F) -> R {
+        f(&mut self.lock().buffer)
+    }
+
+    /// Write display buffer to display hardware.
+    pub fn write_display(&self) -> Result <(), Error> {
+        self.lock().write_display()
+    }
+
+    /// Write display buffer to display hardware from an async task, on a blocking thread of the runtime.
+    /// Dropping the future does not cancel the write, which completes on its thread.
+    #[cfg(feature = "async")]
+    pub async fn write_display_async(&self) -> Result <(), Error> {
+        let handle = self.clone();
+
+        match tokio::task::spawn_blocking(move || handle.write_display()).await {
+            Ok(result) => result,
+            Err(err) => std::panic::resume_unwind(err.into_panic()),
+        }
+    }
+
+    /// Set brightness of entire display, from 0 to 15.
+    ///
+    /// # Arguments
+    ///
+    /// * `brightness` - level of brightness, from 0 to 15.
+    pub fn set_brightness(&self, brightness: u8) -> Result <(), Error> {
+        self.lock().set_brightness(brightness)
+    }
+
+    /// Blink display at specified rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate` - Blink rate.
+    pub fn set_blink(&self, rate: BlinkRate) -> Result <(), Error> {
+        self.lock().set_blink(rate)
+    }
+
+    /// Set a single LED on or off in the display buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `led` - Index of the LED.
+    /// * `on` - true to turn the LED on, false to turn it off.
+    pub fn set_led(&self, led: usize, on: bool) -> Result <(), Error> {
+        self.lock().set_led(led, on)
+    }
+
+    /// Set a single LED on or off in the display buffer, by its position in the matrix of the chip.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - Common pin: 0 to 7.
+    /// * `col` - Row pin: 0 to 15.
+    /// * `on` - true to turn the LED on, false to turn it off.
+    pub fn set_led_rc(&self, row: u8, col: u8, on: bool) -> Result <(), Error> {
+        self.lock().set_led_rc(row, col, on)
+    }
+
+    /// Get whether every LED of the display buffer is off.
+    pub fn is_blank(&self) -> bool {
+        self.lock().is_blank()
+    }
+}
+
+impl BufferedDisplay for HT16K33Handle {
+    fn with_buffer<R, F: FnOnce(&mut [u8; BUFFER_SIZE]) -> R>(&mut self, f: F) -> R {
+        HT16K33Handle::with_buffer(self, f)
+    }
+
+    fn write_display(&mut self) -> Result <(), Error> {
+        HT16K33Handle::write_display(self)
+    }
+}
+
+/// Scan an I2C bus for HT16K33 devices.
+/// Probes every address an HT16K33 can be strapped to (0x70 to 0x77) with an SMBus quick write,
+/// which does not change the state of the device, and returns the addresses that acknowledged.
+/// A missing device is not an error: an error is only returned when the bus itself is unavailable.
+///
+/// # Arguments
+///
+/// * `bus` - I2C bus number, 1 on the Raspberry Pi.
+#[cfg(feature = "hardware")]
+pub fn scan(bus: u8) -> Result<Vec<u16>, Error> {
+    let mut i2c = I2c::with_bus(bus).map_err(|source| Error::I2c { bus: Some(bus), address: None, source })?;
+
+    scan_with(|address| {
+        let context = |source| Error::I2c { bus: Some(bus), address: Some(address), source };
+        i2c.set_slave_address(address).map_err(context)?;
+
+        match i2c.smbus_quick_command(false) {
+            Ok(()) => Ok(true),
+            Err(rppal::i2c::Error::Io(err)) if is_no_ack(&err) => Ok(false),
+            Err(err) => Err(context(err)),
+        }
+    })
+}
+
+/// Scan for HT16K33 devices using a custom probe.
+/// Allows the discovery logic to be used without hardware, for example with simulated probe results.
+///
+/// # Arguments
+///
+/// * `probe` - Returns true if a device answers at the address, or an error if the bus failed.
+pub fn scan_with<F>(mut probe: F) -> Result<Vec<u16>, Error>
+where
+    F: FnMut(u16) -> Result<bool, Error>,
+{
+    let mut found = Vec::new();
+
+    for address in FIRST_ADDRESS..=LAST_ADDRESS {
+        if probe(address)? {
+            found.push(address);
+        }
+    }
+
+    Ok(found)
+}
+
+/// Returns true if the I/O error means that no device acknowledged the address.
+///
+/// # Arguments
+///
+/// * `err` - I/O error returned by the I2C driver.
+#[cfg(feature = "hardware")]
+fn is_no_ack(err: &std::io::Error) -> bool {
+    // ENXIO, EIO and EREMOTEIO are reported by the kernel drivers when the address is not acknowledged.
+    matches!(err.raw_os_error(), Some(6) | Some(5) | Some(121))
+}
+
+/// Errors that can occur.
+#[derive(Debug)]
+pub enum Error {
+
+    /// I2C error accessing the device.
+    #[cfg(feature = "hardware")]
+    I2c {
+
+        /// I2C bus number, None for the default bus.
+        bus: Option<u8>,
+
+        /// Address of the device, None when the bus itself failed or was given to the driver.
+        address: Option<u16>,
+
+        /// Error of the bus.
+        source: rppal::i2c::Error,
+    },
+
+    /// I/O error of a custom bus.
+    Io(std::io::Error),
+
+    /// Invalid argument.
+    InvalidArgument(String),
+
+    /// The setup failed with the given error.
+    Setup(Arc<Error>),
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "hardware")]
+            Error::I2c { source, .. } => Some(source),
+            Error::Io(err) => Some(err),
+            Error::Setup(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "hardware")]
+            Error::I2c { bus, address, source } => {
+                write!(f, "failed to access {} for HT16K33: {}", crate::error::i2c_location(*bus, *address), &source)
+            }
+            Error::Io(err) => write!(f, "I/O error: {}", &err),
+            Error::InvalidArgument(msg) => write!(f, "Invalid argument: {}", &msg),
+            Error::Setup(err) => write!(f, "Setup failed: {}", &err),
+        }
+    }
+}
+
+/// Converts I/O error
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+/// Unit tests
 #[cfg(test)]
+#[allow(clippy::bool_comparison)]
 mod tests {
     use super::*;
 
@@ -224,9 +1377,58 @@ mod tests {
         assert!(ht16k33.blink_frequency == HT16K33_BLINK_OFF);
 
         // Change value
-        let _result = ht16k33.set_blink(HT16K33_BLINK_2HZ);
+        let _result = ht16k33.set_blink(BlinkRate::TwoHz);
         assert!(ht16k33.blink_frequency == HT16K33_BLINK_2HZ);
 
+        // Raw value
+        let _result = ht16k33.set_blink_raw(HT16K33_BLINK_HALFHZ);
+        assert!(ht16k33.blink_frequency == HT16K33_BLINK_HALFHZ);
+
+        Ok(())
+    }
+
+    /// Tests raw blink frequencies with bits outside the blink bits are rejected, without any write.
+    #[test]
+    fn test_ht16k33_set_blink_raw_invalid() -> Result<(), Error> {
+        let mut ht16k33 = HT16K33::with_simulation()?;
+        ht16k33.set_blink_raw(HT16K33_BLINK_1HZ)?;
+        ht16k33.clear_transcript();
+
+        for &frequency in [0x01, 0x08, 0x87, 0xFF].iter() {
+            assert!(matches!(ht16k33.set_blink_raw(frequency), Err(Error::InvalidArgument(_))));
+        }
+        assert!(ht16k33.blink_frequency() == HT16K33_BLINK_1HZ);
+        assert!(ht16k33.transcript().is_empty());
+
+        let err = crate::Error::from(ht16k33.set_blink_raw(0x01).unwrap_err());
+        assert!(matches!(err, crate::Error::InvalidInput(_)));
+        assert!(err.to_string().contains("invalid blink frequency 0x01, expected HT16K33_BLINK_OFF"));
+
+        Ok(())
+    }
+
+    /// Tests each blink rate is written with the right command byte.
+    #[test]
+    fn test_ht16k33_blink_rate_command() -> Result<(), Error> {
+        let mut ht16k33 = HT16K33::new()?;
+        // enable simulation
+        ht16k33.simulation = true;
+        ht16k33.setup()?;
+        ht16k33.clear_transcript();
+
+        let expected = [
+            (BlinkRate::Off, 0x81),
+            (BlinkRate::TwoHz, 0x83),
+            (BlinkRate::OneHz, 0x85),
+            (BlinkRate::HalfHz, 0x87),
+        ];
+
+        for &(rate, command) in expected.iter() {
+            ht16k33.clear_transcript();
+            ht16k33.set_blink(rate)?;
+            assert!(ht16k33.transcript() == [(command, vec![])]);
+        }
+
         Ok(())
     }
 
@@ -247,4 +1449,647 @@ mod tests {
         Ok(())
     }
 
+    /// Tests an invalid brightness is rejected without changing the brightness.
+    #[test]
+    #[allow(deprecated)]
+    fn test_ht16k33_set_brightness_invalid() -> Result<(), Error> {
+        let mut ht16k33 = HT16K33::new()?;
+        // enable simulation
+        ht16k33.simulation = true;
+
+        let err = ht16k33.set_brightness(16).unwrap_err();
+        assert!(err.to_string() == "Invalid argument: invalid brightness 16, expected 0 to 15");
+        assert!(ht16k33.brightness == 15);
+
+        // The deprecated fallible variant behaves the same
+        assert!(matches!(ht16k33.try_set_brightness(16), Err(Error::InvalidArgument(_))));
+        assert!(ht16k33.brightness == 15);
+
+        ht16k33.try_set_brightness(3)?;
+        assert!(ht16k33.brightness == 3);
+
+        Ok(())
+    }
+
+    /// Tests the computation of the fade schedule.
+    #[test]
+    fn test_ht16k33_fade_steps() {
+
+        // Fading up steps through each level
+        let steps = HT16K33::fade_steps(2, 6, Duration::from_millis(400));
+        assert!(steps == vec![
+            (3, Duration::from_millis(100)),
+            (4, Duration::from_millis(100)),
+            (5, Duration::from_millis(100)),
+            (6, Duration::from_millis(100)),
+        ]);
+
+        // Fading down
+        let steps = HT16K33::fade_steps(15, 13, Duration::from_millis(100));
+        assert!(steps == vec![(14, Duration::from_millis(50)), (13, Duration::from_millis(50))]);
+
+        // Same level, nothing to do
+        assert!(HT16K33::fade_steps(7, 7, Duration::from_millis(100)).is_empty());
+
+        // Levels are clamped
+        let steps = HT16K33::fade_steps(14, 200, Duration::from_millis(10));
+        assert!(steps == vec![(15, Duration::from_millis(10))]);
+    }
+
+    /// Tests fading the brightness.
+    #[test]
+    fn test_ht16k33_fade_brightness() -> Result<(), Error> {
+        let mut ht16k33 = HT16K33::new()?;
+        // enable simulation
+        ht16k33.simulation = true;
+        ht16k33.setup()?;
+        ht16k33.clear_transcript();
+
+        assert!(ht16k33.brightness() == 15);
+
+        ht16k33.fade_brightness(12, Duration::from_millis(3))?;
+        assert!(ht16k33.brightness() == 12);
+        assert!(ht16k33.transcript() == [
+            (HT16K33_CMD_BRIGHTNESS | 14, vec![]),
+            (HT16K33_CMD_BRIGHTNESS | 13, vec![]),
+            (HT16K33_CMD_BRIGHTNESS | 12, vec![]),
+        ]);
+
+        // Invalid target
+        assert!(matches!(ht16k33.fade_brightness(16, Duration::from_millis(3)), Err(Error::InvalidArgument(_))));
+
+        // Fading while the display is off applies the level without stepping
+        ht16k33.set_display(false)?;
+        ht16k33.clear_transcript();
+        ht16k33.fade_brightness(4, Duration::from_millis(3))?;
+        assert!(ht16k33.brightness() == 4);
+        assert!(ht16k33.transcript() == [(HT16K33_CMD_BRIGHTNESS | 4, vec![])]);
+
+        Ok(())
+    }
+
+    /// Tests turning the display on and off.
+    #[test]
+    fn test_ht16k33_set_display() -> Result<(), Error> {
+        let mut ht16k33 = HT16K33::new()?;
+        // enable simulation
+        ht16k33.simulation = true;
+        ht16k33.setup()?;
+        ht16k33.clear_transcript();
+
+        assert!(ht16k33.is_display_on());
+
+        ht16k33.set_blink(BlinkRate::OneHz)?;
+        ht16k33.set_display(false)?;
+        assert!(!ht16k33.is_display_on());
+        ht16k33.set_display(true)?;
+
+        assert!(ht16k33.transcript() == [
+            (HT16K33_BLINK_CMD | HT16K33_BLINK_DISPLAYON | HT16K33_BLINK_1HZ, vec![]),
+            (HT16K33_BLINK_CMD | HT16K33_BLINK_1HZ, vec![]),
+            (HT16K33_BLINK_CMD | HT16K33_BLINK_DISPLAYON | HT16K33_BLINK_1HZ, vec![]),
+        ]);
+
+        Ok(())
+    }
+
+    /// Tests the encoding of the commands against the datasheet.
+    #[test]
+    fn test_ht16k33_command_encode() {
+        let data = [1, 2, 3];
+
+        let expected: [(Command, u8, &[u8]); 9] = [
+            (Command::SystemSetup { oscillator: true }, 0x21, &[]),
+            (Command::SystemSetup { oscillator: false }, 0x20, &[]),
+            (Command::DisplaySetup { on: true, blink: HT16K33_BLINK_OFF }, 0x81, &[]),
+            (Command::DisplaySetup { on: false, blink: HT16K33_BLINK_OFF }, 0x80, &[]),
+            (Command::DisplaySetup { on: true, blink: HT16K33_BLINK_HALFHZ }, 0x87, &[]),
+            (Command::Brightness(0), 0xE0, &[]),
+            (Command::Brightness(15), 0xEF, &[]),
+            (Command::WriteRam { offset: 0, data: &data }, 0x00, &data),
+            (Command::WriteRam { offset: 4, data: &data }, 0x04, &data),
+        ];
+
+        for (command, byte, bytes) in expected.iter() {
+            assert!(command.encode() == (*byte, *bytes));
+            assert!(Command::decode(*byte, bytes) == Some(*command));
+        }
+
+        // Out of range values don't change the kind of command
+        assert!(Command::Brightness(0xFF).encode().0 == 0xEF);
+        assert!(Command::DisplaySetup { on: false, blink: 0xFF }.encode().0 == 0x86);
+        assert!(Command::decode(0x40, &[]).is_none());
+    }
+
+    /// Tests the decoded commands of the transcript.
+    #[test]
+    fn test_ht16k33_commands() -> Result<(), Error> {
+        let mut ht16k33 = HT16K33::new()?;
+        // enable simulation
+        ht16k33.simulation = true;
+
+        ht16k33.write_display()?;
+
+        assert!(ht16k33.commands() == vec![
+            Command::SystemSetup { oscillator: true },
+            Command::DisplaySetup { on: true, blink: HT16K33_BLINK_OFF },
+            Command::Brightness(15),
+            Command::WriteRam { offset: 0, data: &[0; BUFFER_SIZE] },
+        ]);
+
+        Ok(())
+    }
+
+    /// Bus failing every write while `fail` is set.
+    #[derive(Debug)]
+    struct FailingBus {
+        fail: Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    impl I2cBus for FailingBus {
+        fn block_write(&mut self, _command: u8, _buffer: &[u8]) -> Result <(), Error> {
+            if self.fail.load(std::sync::atomic::Ordering::SeqCst) {
+                return Err(Error::from(std::io::Error::from_raw_os_error(121)));
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Tests a failed setup is remembered and propagated.
+    #[test]
+    fn test_ht16k33_setup_failure() -> Result<(), Error> {
+        let fail = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let mut ht16k33 = HT16K33::with_bus(FailingBus { fail: fail.clone() });
+
+        assert!(!ht16k33.is_setup());
+
+        // The setup fails and the original error is kept
+        let first = match ht16k33.setup() {
+            Err(Error::Setup(err)) => err,
+            _ => panic!("setup should fail"),
+        };
+        assert!(matches!(*first, Error::Io(_)));
+        assert!(!ht16k33.is_setup());
+
+        // Following operations return the same error instead of touching the bus
+        fail.store(false, std::sync::atomic::Ordering::SeqCst);
+        match ht16k33.write_display() {
+            Err(Error::Setup(err)) => assert!(Arc::ptr_eq(&err, &first)),
+            _ => panic!("write_display should return the setup error"),
+        }
+        assert!(matches!(ht16k33.set_brightness(3), Err(Error::Setup(_))));
+        assert!(!ht16k33.is_setup());
+
+        // Retrying the setup clears the error
+        ht16k33.try_setup()?;
+        assert!(ht16k33.is_setup());
+        ht16k33.write_display()?;
+
+        Ok(())
+    }
+
+    /// Backend failing to open the peripherals like off the Raspberry Pi.
+    #[cfg(feature = "hardware")]
+    #[derive(Debug)]
+    struct AbsentBackend;
+
+    #[cfg(feature = "hardware")]
+    impl Backend for AbsentBackend {
+        fn gpio(&self) -> Result<rppal::gpio::Gpio, rppal::gpio::Error> {
+            Err(rppal::gpio::Error::UnknownModel)
+        }
+
+        fn i2c(&self, _bus: Option<u8>) -> Result<rppal::i2c::I2c, rppal::i2c::Error> {
+            Err(rppal::i2c::Error::UnknownModel)
+        }
+    }
+
+    /// Tests the driver switches to simulation mode when the I2C bus is absent, recording the writes.
+    #[test]
+    #[cfg(feature = "hardware")]
+    fn test_ht16k33_auto_simulate() -> Result<(), Error> {
+        let mut ht16k33 = HT16K33::new()?;
+        ht16k33.set_backend(AbsentBackend);
+        assert!(matches!(ht16k33.setup(), Err(Error::Setup(_))));
+
+        ht16k33.set_auto_simulate(true);
+        ht16k33.try_setup()?;
+        assert!(ht16k33.is_simulation());
+        assert!(ht16k33.is_setup());
+
+        ht16k33.buffer[0] = 0x3F;
+        ht16k33.write_display()?;
+        let mut expected = [0u8; BUFFER_SIZE].to_vec();
+        expected[0] = 0x3F;
+        assert!(ht16k33.transcript().pop() == Some((0x00, expected)));
+
+        Ok(())
+    }
+
+
+    #[test]
+    fn test_ht16k33_begin_update() -> Result<(), Error> {
+        let mut ht16k33 = HT16K33::new()?;
+        ht16k33.buffer = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+
+        // Abort leaves the buffer untouched
+        let mut update = ht16k33.begin_update();
+        update[0] = 0xFF;
+        update[7] = 0xFF;
+        update.abort();
+        assert!(ht16k33.buffer == [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
+
+        // Commit applies everything
+        let mut update = ht16k33.begin_update();
+        update[0] = 0xFF;
+        update[7] = 0xFF;
+        update.commit();
+        assert!(ht16k33.buffer == [0xFF, 2, 3, 4, 5, 6, 7, 0xFF, 9, 10, 11, 12, 13, 14, 15, 16]);
+
+        // Dropping the guard commits
+        {
+            let mut update = ht16k33.begin_update();
+            update[1] = 0;
+        }
+        assert!(ht16k33.buffer == [0xFF, 0, 3, 4, 5, 6, 7, 0xFF, 9, 10, 11, 12, 13, 14, 15, 16]);
+
+        // On a shared handle, the other handles see the whole batch at once
+        let handle = ht16k33.into_shared();
+        let mut writer = handle.clone();
+        let mut update = writer.begin_update();
+        update[2] = 0xAA;
+        update[3] = 0xBB;
+        assert!(handle.with_buffer(|buffer| buffer[2..4] == [3, 4]));
+        update.commit();
+        assert!(handle.with_buffer(|buffer| buffer[2..4] == [0xAA, 0xBB]));
+
+        // The writes made through another handle during the batch are kept
+        let mut update = writer.begin_update();
+        update[0] = 0x0F;
+        update[4] = 0x55;
+        handle.with_buffer(|buffer| {
+            buffer[5] = 0x66;
+            buffer[6] = 0x77;
+        });
+        update.commit();
+        assert!(handle.with_buffer(|buffer| *buffer == [0x0F, 0, 0xAA, 0xBB, 0x55, 0x66, 0x77, 0xFF, 9, 10, 11, 12, 13, 14, 15, 16]));
+
+        Ok(())
+    }
+
+    /// Bus recording the writes and failing a number of them.
+    #[derive(Debug)]
+    struct FlakyBus {
+        writes: Arc<Mutex<Transcript>>,
+        failures: Arc<std::sync::atomic::AtomicU32>,
+    }
+
+    impl I2cBus for FlakyBus {
+        fn block_write(&mut self, command: u8, buffer: &[u8]) -> Result <(), Error> {
+            let failures = self.failures.load(std::sync::atomic::Ordering::SeqCst);
+            if failures > 0 {
+                self.failures.store(failures - 1, std::sync::atomic::Ordering::SeqCst);
+                return Err(Error::from(std::io::Error::from_raw_os_error(121)));
+            }
+
+            self.writes.lock().unwrap().push((command, buffer.to_vec()));
+            Ok(())
+        }
+    }
+
+    /// Tests reinitializing the device from the cached state.
+    #[test]
+    fn test_ht16k33_reinitialize() -> Result<(), Error> {
+        let mut ht16k33 = HT16K33::new()?;
+        // enable simulation
+        ht16k33.simulation = true;
+
+        ht16k33.set_blink(BlinkRate::OneHz)?;
+        ht16k33.set_brightness(7)?;
+        ht16k33.buffer = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+        ht16k33.clear_transcript();
+
+        ht16k33.reinitialize()?;
+        assert!(ht16k33.is_setup());
+        assert!(ht16k33.commands() == vec![
+            Command::SystemSetup { oscillator: true },
+            Command::DisplaySetup { on: true, blink: HT16K33_BLINK_1HZ },
+            Command::Brightness(7),
+            Command::WriteRam { offset: 0, data: &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16] },
+        ]);
+
+        Ok(())
+    }
+
+    /// Tests the automatic reinitialization after consecutive errors.
+    #[test]
+    fn test_ht16k33_auto_reinitialize() -> Result<(), Error> {
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        let failures = Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let mut ht16k33 = HT16K33::with_bus(FlakyBus { writes: writes.clone(), failures: failures.clone() });
+
+        ht16k33.setup()?;
+        ht16k33.set_brightness(3)?;
+        ht16k33.set_auto_reinitialize(Some(2));
+        writes.lock().unwrap().clear();
+
+        // The first failure is reported
+        failures.store(1, std::sync::atomic::Ordering::SeqCst);
+        assert!(ht16k33.write_display().is_err());
+        assert!(writes.lock().unwrap().is_empty());
+
+        // The second consecutive failure reinitializes and retries
+        failures.store(1, std::sync::atomic::Ordering::SeqCst);
+        ht16k33.buffer[0] = 0xFF;
+        ht16k33.write_display()?;
+
+        let buffer = vec![0xFF, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert!(*writes.lock().unwrap() == vec![
+            (0x21, vec![]),
+            (0x81, vec![]),
+            (0xE3, vec![]),
+            (0x00, buffer.clone()),
+            (0x00, buffer),
+        ]);
+
+        // Without automatic mode, errors are returned
+        ht16k33.set_auto_reinitialize(None);
+        failures.store(5, std::sync::atomic::Ordering::SeqCst);
+        for _i in 0..5 {
+            assert!(ht16k33.write_display().is_err());
+        }
+
+        Ok(())
+    }
+
+    /// Tests setting single LEDs.
+    #[test]
+    fn test_ht16k33_set_led() -> Result<(), Error> {
+        let mut ht16k33 = HT16K33::new()?;
+
+        ht16k33.set_led(0, true)?;
+        ht16k33.set_led(13, true)?;
+        ht16k33.set_led(127, true)?;
+        assert!(ht16k33.buffer == [0b00000001, 0b00100000, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0b10000000]);
+
+        ht16k33.set_led(13, false)?;
+        assert!(ht16k33.buffer[1] == 0);
+
+        assert!(matches!(ht16k33.set_led(128, true), Err(Error::InvalidArgument(_))));
+
+        Ok(())
+    }
+
+    /// Tests the test pattern writes a single LED advancing at each step, wrapping after the last one.
+    #[test]
+    fn test_ht16k33_test_pattern_tick() -> Result<(), Error> {
+        let mut ht16k33 = HT16K33::with_simulation()?;
+        ht16k33.set_led(5, true)?;
+
+        // Walk the 16 bytes of the display RAM, 8 bits each
+        for row in 0..16 {
+            for bit in 0..8 {
+                let led = ht16k33.test_pattern_tick()?;
+                assert!(led == row * 8 + bit);
+
+                // The last write has a single bit set, the one of the LED
+                let written = match ht16k33.commands().last() {
+                    Some(Command::WriteRam { offset: 0, data }) => data.to_vec(),
+                    _ => panic!("no display write"),
+                };
+                assert!(written.len() == 16);
+                assert!(written.iter().map(|byte| byte.count_ones()).sum::<u32>() == 1);
+                assert!(written[row] == 1 << bit);
+
+                // Half way, the pattern reaches the second half of the display RAM
+                if led == 64 {
+                    assert!(written[8] == 0b00000001);
+                }
+            }
+        }
+
+        // Wrapping after the 128 LEDs
+        assert!(ht16k33.test_pattern_tick()? == 0);
+        assert!(ht16k33.test_pattern_tick()? == 1);
+
+        ht16k33.reset_test_pattern();
+        assert!(ht16k33.test_pattern_tick()? == 0);
+
+        Ok(())
+    }
+
+    /// Tests mapping rows and columns of the matrix to the buffer.
+    #[test]
+    fn test_ht16k33_set_led_rc() -> Result<(), Error> {
+        let mut ht16k33 = HT16K33::new()?;
+
+        // (row, col, byte, bit)
+        let cases = [(0, 0, 0, 0), (0, 7, 0, 7), (0, 8, 1, 0), (3, 2, 6, 2), (5, 12, 11, 4), (7, 15, 15, 7)];
+        for &(row, col, byte, bit) in cases.iter() {
+            ht16k33.set_led_rc(row, col, true)?;
+            assert!(ht16k33.buffer[byte] & (1 << bit) != 0, "row {} col {}", row, col);
+            assert!(ht16k33.buffer.iter().map(|b| b.count_ones()).sum::<u32>() == 1);
+
+            ht16k33.set_led_rc(row, col, false)?;
+            assert!(ht16k33.buffer.iter().all(|&b| b == 0));
+        }
+
+        assert!(matches!(ht16k33.set_led_rc(8, 0, true), Err(Error::InvalidArgument(_))));
+        assert!(matches!(ht16k33.set_led_rc(0, 16, true), Err(Error::InvalidArgument(_))));
+
+        Ok(())
+    }
+
+    /// Tests mapping (x, y) coordinates to the buffer with the 16x8 layout.
+    #[test]
+    fn test_ht16k33_set_pixel_xy_rows() -> Result<(), Error> {
+        let mut ht16k33 = HT16K33::new()?;
+        assert!(ht16k33.layout() == MatrixLayout::Rows16x8);
+
+        ht16k33.set_pixel_xy(0, 0, true)?;
+        ht16k33.set_pixel_xy(9, 0, true)?;
+        ht16k33.set_pixel_xy(15, 7, true)?;
+        assert!(ht16k33.buffer[0] == 0b00000001);
+        assert!(ht16k33.buffer[1] == 0b00000010);
+        assert!(ht16k33.buffer[15] == 0b10000000);
+
+        ht16k33.clear_column(9)?;
+        assert!(ht16k33.buffer[1] == 0);
+        assert!(ht16k33.buffer[0] == 0b00000001);
+
+        assert!(matches!(ht16k33.set_pixel_xy(16, 0, true), Err(Error::InvalidArgument(_))));
+        assert!(matches!(ht16k33.set_pixel_xy(0, 8, true), Err(Error::InvalidArgument(_))));
+
+        Ok(())
+    }
+
+    /// Tests mapping (x, y) coordinates to the buffer with the rotated bits of the 8x8 backpack.
+    #[test]
+    fn test_ht16k33_set_pixel_xy_backpack() -> Result<(), Error> {
+        let mut ht16k33 = HT16K33::new()?;
+        ht16k33.set_layout(MatrixLayout::Backpack8x8);
+
+        ht16k33.set_pixel_xy(0, 0, true)?;
+        ht16k33.set_pixel_xy(0, 1, true)?;
+        ht16k33.set_pixel_xy(1, 3, true)?;
+        ht16k33.set_pixel_xy(7, 7, true)?;
+        ht16k33.set_pixel_xy(0, 7, true)?;
+        assert!(ht16k33.buffer[0] == 0b10000000);
+        assert!(ht16k33.buffer[2] == 0b10000000);
+        assert!(ht16k33.buffer[6] == 0b00000001);
+        assert!(ht16k33.buffer[14] == 0b11000000);
+        assert!(ht16k33.buffer.iter().enumerate().all(|(i, &b)| i % 2 == 0 || b == 0));
+
+        ht16k33.clear_column(0)?;
+        assert!(ht16k33.buffer[0] == 0);
+        assert!(ht16k33.buffer[14] == 0b01000000);
+
+        assert!(matches!(ht16k33.set_pixel_xy(8, 0, true), Err(Error::InvalidArgument(_))));
+
+        Ok(())
+    }
+
+    /// Tests sharing the driver between two threads.
+    #[test]
+    fn test_ht16k33_shared_handle() -> Result<(), Error> {
+        let mut ht16k33 = HT16K33::new()?;
+        // enable simulation
+        ht16k33.simulation = true;
+        let handle = ht16k33.into_shared();
+
+        // A display updating the digits on top of the shared handle
+        let mut alphanum = crate::alphanum4::Alphanum4::with_handle(handle.clone());
+        let writer = thread::spawn(move || {
+            for _i in 0..10 {
+                alphanum.print_str("ABCD", crate::alphanum4::Alignment::Left).unwrap();
+                alphanum.show().unwrap();
+            }
+        });
+
+        // Another thread changing the brightness
+        let brightness_handle = handle.clone();
+        let dimmer = thread::spawn(move || {
+            for level in 0..10 {
+                brightness_handle.set_brightness(level).unwrap();
+            }
+        });
+
+        writer.join().unwrap();
+        dimmer.join().unwrap();
+
+        handle.with_driver(|driver| {
+            assert!(driver.brightness() == 9);
+
+            // Every flush wrote a complete buffer
+            let transcript = driver.transcript();
+            let writes: Vec<&(u8, Vec<u8>)> = transcript.iter().filter(|(command, _)| *command == 0x00).collect();
+            assert!(writes.len() == 10);
+            for (_command, data) in writes {
+                assert!(data == &driver.buffer.to_vec());
+            }
+        });
+
+        // Compound buffer updates
+        handle.with_buffer(|buffer| {
+            buffer[0] = 0xFF;
+            buffer[1] = 0x0F;
+        });
+        handle.set_led(8, false)?;
+        assert!(handle.with_buffer(|buffer| buffer[0..2] == [0xFF, 0x0E]));
+
+        Ok(())
+    }
+
+    /// Tests scanning for devices with simulated probe results.
+    #[test]
+    fn test_ht16k33_scan_with() -> Result<(), Error> {
+        let mut probed = Vec::new();
+
+        let found = scan_with(|address| {
+            probed.push(address);
+            Ok(address == 0x70 || address == 0x73)
+        })?;
+
+        // All the addresses are probed, only the ones that answered are returned
+        assert!(probed == vec![0x70, 0x71, 0x72, 0x73, 0x74, 0x75, 0x76, 0x77]);
+        assert!(found == vec![0x70, 0x73]);
+
+        // Bus errors are reported instead of being treated as missing devices
+        let result = scan_with(|_address| {
+            Err(Error::from(std::io::Error::from_raw_os_error(13)))
+        });
+        assert!(result.is_err());
+
+        // Missing devices are distinguished from bus errors
+        #[cfg(feature = "hardware")]
+        {
+            let no_ack = std::io::Error::from_raw_os_error(121);
+            let denied = std::io::Error::from_raw_os_error(13);
+            assert!(is_no_ack(&no_ack));
+            assert!(!is_no_ack(&denied));
+        }
+
+        Ok(())
+    }
+
+    /// Tests creating the driver in simulation mode.
+    #[test]
+    fn test_ht16k33_with_simulation() -> Result<(), Error> {
+        let mut ht16k33 = HT16K33::with_simulation()?;
+        assert!(ht16k33.simulation);
+
+        ht16k33.write_display()?;
+        assert!(!ht16k33.transcript().is_empty());
+
+        Ok(())
+    }
+
+    /// Tests enabling simulation mode on a driver created for the hardware, and on a shared one.
+    #[test]
+    fn test_ht16k33_enable_simulation() -> Result<(), Error> {
+        let mut ht16k33 = HT16K33::new()?;
+        assert!(ht16k33.is_simulation() != cfg!(feature = "hardware"));
+
+        ht16k33.enable_simulation();
+        assert!(ht16k33.is_simulation());
+        ht16k33.write_display()?;
+
+        let handle = HT16K33::new()?.into_shared();
+        assert!(handle.is_simulation() != cfg!(feature = "hardware"));
+        handle.enable_simulation();
+        assert!(handle.is_simulation());
+
+        Ok(())
+    }
+
+    /// Tests the trace hook receives a message before each write, in simulation mode.
+    #[test]
+    fn test_ht16k33_trace() -> Result<(), Error> {
+        let messages = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut ht16k33 = HT16K33::with_simulation()?;
+        ht16k33.setup()?;
+
+        let received = messages.clone();
+        ht16k33.set_trace(move |message| received.lock().unwrap().push(message.to_string()));
+        ht16k33.write_display()?;
+        assert!(*messages.lock().unwrap() == ["HT16K33 write_display: 16 bytes"]);
+
+        ht16k33.clear_trace();
+        ht16k33.write_display()?;
+        assert!(messages.lock().unwrap().len() == 1);
+
+        Ok(())
+    }
+
+    /// Pins the auto traits: the driver can be moved to another thread, its handle shared between threads.
+    const _: () = {
+        const fn assert_send<T: Send>() {}
+        const fn assert_sync<T: Sync>() {}
+        assert_send::<HT16K33>();
+        assert_send::<HT16K33Handle>();
+        assert_send::<Error>();
+        assert_sync::<HT16K33Handle>();
+        assert_sync::<Error>();
+    };
 }
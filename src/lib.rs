@@ -3,4 +3,58 @@ pub mod touch;
 pub mod ht16k33;
 pub mod alphanum4;
 pub mod apa102;
-pub mod buzzer;
\ No newline at end of file
+pub mod colors;
+pub mod buzzer;
+pub mod clock;
+pub mod retry;
+pub mod rng;
+#[cfg(any(feature = "server", feature = "mqtt"))]
+mod commands;
+pub mod screens;
+pub mod rainbow;
+pub mod bmp280;
+pub mod rainbow_hat;
+pub mod app;
+pub mod animator;
+pub mod recorder;
+pub mod replay;
+pub mod testing;
+pub mod sim_input;
+pub mod bindings;
+pub mod units;
+pub mod thermometer;
+pub mod simon;
+pub mod games;
+pub mod menu;
+pub mod error;
+pub mod trace;
+pub mod hal;
+pub mod pins;
+pub mod eeprom;
+pub mod prelude;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "signals")]
+pub mod signals;
+#[cfg(feature = "config")]
+pub mod config;
+#[cfg(feature = "sim-ui")]
+pub mod sim_ui;
+#[cfg(feature = "schedule")]
+pub mod scheduler;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
+pub use error::Error;
+pub use apa102::Error as APA102Error;
+pub use alphanum4::Error as Alphanum4Error;
+pub use bmp280::Error as BMP280Error;
+pub use buzzer::Error as BuzzerError;
+pub use ht16k33::Error as HT16K33Error;
+pub use lights::Error as LightsError;
+pub use touch::Error as TouchError;
+pub use eeprom::detect;
+#[cfg(feature = "signals")]
+pub use signals::install_cleanup_handler;
@@ -1,6 +1,15 @@
 use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
 use core::fmt::Debug;
-use rppal::gpio::{Gpio, OutputPin, Level};
+use crate::hal::DigitalOutput;
+#[cfg(feature = "hardware")]
+use crate::hal::{self, Backend, Rppal};
+#[cfg(feature = "hardware")]
+use crate::pins;
+use crate::pins::{PinClaim, PinInUse, PinRegistry};
+use crate::rainbow_hat::Component;
+use crate::recorder::{MemoryRecorder, Payload, Recorder};
+use crate::trace::{SharedHook, Tracer};
 
 /// GPIO BCM pin number for the red light.
 pub const GPIO_LIGHT_RED: u8 = 6;
@@ -12,6 +21,7 @@ pub const GPIO_LIGHT_GREEN: u8 = 19;
 pub const GPIO_LIGHT_BLUE: u8 = 26;
 
 /// Light on the board.
+/// Send but not Sync, as its pin and trace hook are only Send.
 #[derive(Debug)]
 pub struct Light {
 
@@ -19,11 +29,30 @@ pub struct Light {
     pub bcm_pin: u8,
 
     /// Output pin to write to GPIO. Optional as not used in simulated mode.
-    pin: Option<Box<OutputPin>>,
+    pin: Option<Box<dyn DigitalOutput>>,
 
     /// State of the light: true for on, false for Off
     pub state: bool,
 
+    /// hook receiving a message before each hardware operation
+    tracer: Tracer,
+
+    /// recorder of the levels written in simulation mode
+    recorder: Box<dyn Recorder>,
+
+    /// backend opening the peripherals on setup
+    #[cfg(feature = "hardware")]
+    backend: Box<dyn Backend>,
+
+    /// claim of the pin on setup, released with the driver
+    claim: Option<PinClaim>,
+
+    /// registry of the pins claimed in simulation mode, shared by the drivers of a board
+    pin_registry: PinRegistry,
+
+    /// whether to switch to simulation mode on setup when the hardware is absent
+    auto_simulate: bool,
+
     /// In simulation mode, no interaction with the hardware is done to simplify testability.
     simulation: bool, 
 
@@ -38,32 +67,156 @@ impl Light {
     ///
     /// * `bcm_pin` - GPIO pin number using the BCM pin numbering.
     pub fn new(bcm_pin: u8) -> Result<Light, Error>  {     
+        Ok(Light::create(bcm_pin))
+    }
 
-        Ok(Self {
+    /// Creates a light with every setting at its default, off and not set up.
+    /// # Arguments
+    ///
+    /// * `bcm_pin` - GPIO pin number using the BCM pin numbering.
+    fn create(bcm_pin: u8) -> Light {
+        Self {
             bcm_pin,
             pin: None,
             state: false,
-            simulation: false,
+            tracer: Tracer::new(),
+            recorder: Box::new(MemoryRecorder::new()),
+            #[cfg(feature = "hardware")]
+            backend: Box::new(Rppal),
+            claim: None,
+            pin_registry: PinRegistry::new(),
+            auto_simulate: false,
+            simulation: !cfg!(feature = "hardware"),
             is_setup: false,
-        })
+        }
+    }
+
+    /// Creates a light driven by the given output instead of the Raspberry Pi GPIO.
+    /// # Arguments
+    ///
+    /// * `bcm_pin` - GPIO pin number using the BCM pin numbering, identifying the light.
+    /// * `pin` - Output driving the light.
+    pub fn with_pin<P: DigitalOutput + 'static>(bcm_pin: u8, pin: P) -> Light {
+        let mut light = Light::create(bcm_pin);
+        light.pin = Some(Box::new(pin));
+        light.simulation = false;
+        light
+    }
+
+    /// Enables simulation mode: no interaction with the hardware is done.
+    /// Enable it before the first operation, which initializes the hardware.
+    pub fn enable_simulation(&mut self) {
+        self.simulation = true;
+    }
+
+    /// Get whether simulation mode is enabled.
+    pub fn is_simulation(&self) -> bool {
+        self.simulation
+    }
+
+    /// Switch to simulation mode on setup when the hardware is absent, for example when running off the Raspberry Pi,
+    /// instead of returning an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to fall back to simulation mode.
+    pub fn set_auto_simulate(&mut self, enabled: bool) {
+        self.auto_simulate = enabled;
+    }
+
+    /// Get whether the driver switches to simulation mode on setup when the hardware is absent.
+    pub fn is_auto_simulate(&self) -> bool {
+        self.auto_simulate
+    }
+
+    /// Set the backend opening the GPIO on setup.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Backend to use.
+    #[cfg(feature = "hardware")]
+    pub fn set_backend<B: Backend + 'static>(&mut self, backend: B) {
+        self.backend = Box::new(backend);
+    }
+
+    /// Set the registry the pin is claimed in when set up in simulation mode, such as the registry of the board.
+    /// Each light has its own by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - Registry to use.
+    pub fn set_pin_registry(&mut self, registry: PinRegistry) {
+        self.pin_registry = registry;
+    }
+
+    /// Set a hook receiving a message before each hardware operation, also in simulation mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Closure receiving each message.
+    pub fn set_trace<F: FnMut(&str) + Send + 'static>(&mut self, hook: F) {
+        self.tracer.set(hook);
+    }
+
+    /// Remove the trace hook.
+    pub fn clear_trace(&mut self) {
+        self.tracer.clear();
+    }
+
+    /// Set the recorder of the levels written in simulation mode, for example one shared with the other drivers
+    /// to check the order of the operations across peripherals.
+    ///
+    /// # Arguments
+    ///
+    /// * `recorder` - Recorder to use.
+    pub fn set_recorder<R: Recorder + 'static>(&mut self, recorder: R) {
+        self.recorder = Box::new(recorder);
     }
 
     /// Initialize driver.
     pub fn setup(&mut self) -> Result <(), Error> {
         if !self.is_setup {
 
-            // Ignore Gpio initialization if in sumulation mode
-            if !self.simulation {
-                let gpio = Gpio::new()?;
-                let output = gpio.get(self.bcm_pin)?.into_output(); 
-                self.pin = Some(Box::new(output));
+            // Claim the pin unless given the output: in the registry of the process when opened on the hardware,
+            // of the board in simulation mode
+            let mut claim = None;
+
+            // Ignore Gpio initialization if in sumulation mode or given an output
+            #[cfg(feature = "hardware")]
+            if !self.simulation && self.pin.is_none() {
+                match self.backend.gpio() {
+                    Ok(gpio) => {
+                        claim = Some(pins::claim(self.bcm_pin, "light")?);
+                        let output = gpio.get(self.bcm_pin).map_err(|source| Error::Gpio { pin: self.bcm_pin, source })?.into_output();
+                        self.pin = Some(Box::new(output));
+                    }
+                    Err(err) if self.auto_simulate && hal::is_gpio_absent(&err) => {
+                        let pin = self.bcm_pin;
+                        self.tracer.trace(|| format!("Light {}: hardware unavailable ({}), simulating", pin, err));
+                        self.simulation = true;
+                    }
+                    Err(source) => return Err(Error::Gpio { pin: self.bcm_pin, source }),
+                }
+            }
+
+            if self.simulation && self.pin.is_none() {
+                claim = Some(self.pin_registry.claim(self.bcm_pin, "light")?);
             }
 
+            self.claim = claim;
             self.is_setup = true;
         }
         Ok(())
     }
 
+    /// Release the pin: its handle is dropped, so rppal returns it to its prior state now rather than when the
+    /// driver is dropped. The driver is set up again on its next use. An output given with `with_pin` is dropped too.
+    pub fn release(&mut self) {
+        self.pin = None;
+        self.claim = None;
+        self.is_setup = false;
+    }
+
     /// Sets the light state to on.
     pub fn on(&mut self) {
         self.write(true);
@@ -86,26 +239,29 @@ impl Light {
     pub fn write(&mut self, state: bool) {
         self.state = state;
 
-        if !self.is_setup {
-            let _result = self.setup();
+        // A light failing to set up, for example on a pin used by another driver, is not written
+        if !self.is_setup && self.setup().is_err() {
+            return;
         }
 
+        let pin = self.bcm_pin;
+        self.tracer.trace(|| format!("Light {}: {}", pin, if state { "on" } else { "off" }));
+
         // Only perform actual pin write if not in simulation mode
         if !self.simulation {
 
             let pin = self.pin.as_deref_mut().unwrap();
+            pin.set_level(state);
 
-            if state {
-                pin.write(Level::High);
-            } else {
-                pin.write(Level::Low);
-            }
-
+        } else {
+            self.recorder.record(Component::Lights, "set", Payload::Level { pin, high: state });
         }
     }
 }
 
 /// Set of lights on the board.
+/// Send but not Sync like each light: use `into_shared` to switch them from several threads.
+#[derive(Debug)]
 pub struct Lights {
 
     /// Red light.
@@ -151,11 +307,170 @@ impl Lights {
         self.blue.write(b);
     }
 
+    /// Release the pins of every light, as with `Light::release`.
+    pub fn release(&mut self) {
+        self.red.release();
+        self.green.release();
+        self.blue.release();
+    }
+
+    /// Creates the set of lights in simulation mode, never accessing the hardware.
+    pub fn with_simulation() -> Result<Lights, Error> {
+        let mut lights = Lights::new()?;
+        lights.enable_simulation();
+        Ok(lights)
+    }
+
     /// Enbles simulation mode.
     pub fn enable_simulation(&mut self) {
-        self.red.simulation = true;
-        self.green.simulation = true;
-        self.blue.simulation = true;
+        self.red.enable_simulation();
+        self.green.enable_simulation();
+        self.blue.enable_simulation();
+    }
+
+    /// Get whether simulation mode is enabled on every light.
+    pub fn is_simulation(&self) -> bool {
+        self.red.is_simulation() && self.green.is_simulation() && self.blue.is_simulation()
+    }
+
+    /// Switch every light to simulation mode on setup when the hardware is absent, instead of returning an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to fall back to simulation mode.
+    pub fn set_auto_simulate(&mut self, enabled: bool) {
+        self.red.set_auto_simulate(enabled);
+        self.green.set_auto_simulate(enabled);
+        self.blue.set_auto_simulate(enabled);
+    }
+
+    /// Set the backend opening the GPIO of every light on setup.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Backend to use, cloned for each light, such as a `SharedGpio` opening the GPIO once.
+    #[cfg(feature = "hardware")]
+    pub fn set_backend<B: Backend + Clone + 'static>(&mut self, backend: B) {
+        self.red.set_backend(backend.clone());
+        self.green.set_backend(backend.clone());
+        self.blue.set_backend(backend);
+    }
+
+    /// Set the registry the pins are claimed in when set up in simulation mode, shared by every light.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - Registry to use.
+    pub fn set_pin_registry(&mut self, registry: PinRegistry) {
+        self.red.set_pin_registry(registry.clone());
+        self.green.set_pin_registry(registry.clone());
+        self.blue.set_pin_registry(registry);
+    }
+
+    /// Set a hook receiving a message before each write to any light.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Closure receiving each message.
+    pub fn set_trace<F: FnMut(&str) + Send + 'static>(&mut self, hook: F) {
+        let shared = SharedHook::new(hook);
+        self.red.set_trace(shared.forward());
+        self.green.set_trace(shared.forward());
+        self.blue.set_trace(shared.forward());
+    }
+
+    /// Set the recorder of the levels written to every light in simulation mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `recorder` - Recorder to use, cloned for each light.
+    pub fn set_recorder<R: Recorder + Clone + 'static>(&mut self, recorder: R) {
+        self.red.set_recorder(recorder.clone());
+        self.green.set_recorder(recorder.clone());
+        self.blue.set_recorder(recorder);
+    }
+
+    /// Remove the trace hook of every light.
+    pub fn clear_trace(&mut self) {
+        self.red.clear_trace();
+        self.green.clear_trace();
+        self.blue.clear_trace();
+    }
+
+    /// Move the lights behind a mutex so they can be shared between threads.
+    pub fn into_shared(self) -> LightsHandle {
+        LightsHandle {
+            inner: Arc::new(Mutex::new(self)),
+        }
+    }
+}
+
+/// Handle to the set of lights shared between threads, Send and Sync.
+/// Clones refer to the same lights. Each operation holds an internal lock, so the three lights
+/// set by `rgb` are never mixed with the states written by another thread.
+#[derive(Debug, Clone)]
+pub struct LightsHandle {
+
+    /// Shared lights.
+    inner: Arc<Mutex<Lights>>,
+}
+
+impl LightsHandle {
+
+    /// Locks the lights. A poisoned lock is recovered as the lights state stays consistent between calls.
+    fn lock(&self) -> MutexGuard<'_, Lights> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Enables simulation mode on every light.
+    pub fn enable_simulation(&self) {
+        self.lock().enable_simulation();
+    }
+
+    /// Get whether simulation mode is enabled on every light.
+    pub fn is_simulation(&self) -> bool {
+        self.lock().is_simulation()
+    }
+
+    /// Set a hook receiving a message before each write to any of the shared lights.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Closure receiving each message.
+    pub fn set_trace<F: FnMut(&str) + Send + 'static>(&self, hook: F) {
+        self.lock().set_trace(hook);
+    }
+
+    /// Remove the trace hook of every shared light.
+    pub fn clear_trace(&self) {
+        self.lock().clear_trace();
+    }
+
+    /// Run a closure with exclusive access to the lights.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - Closure receiving the lights.
+    pub fn with_driver<R, F: FnOnce(&mut Lights) -> R>(&self, f: F) -> R {
+        f(&mut self.lock())
+    }
+
+    /// Set the state for all the lights.
+    /// # Arguments
+    ///
+    /// * `state` - State of the lights: true for on, false for Off.
+    pub fn all(&self, state: bool) {
+        self.lock().all(state);
+    }
+
+    /// Set the state for each light.
+    /// # Arguments
+    ///
+    /// * `r` - State of the red light: true for on, false for Off.
+    /// * `g` - State of the green light: true for on, false for Off.
+    /// * `b` - State of the blue light: true for on, false for Off.
+    pub fn rgb(&self, r: bool, g: bool, b: bool) {
+        self.lock().rgb(r, g, b);
     }
 }
 
@@ -163,30 +478,61 @@ impl Lights {
 #[derive(Debug)]
 pub enum Error {
 
-    /// Gpio error.
-    Gpio(rppal::gpio::Error),
+    /// Gpio error configuring the pin of the light.
+    #[cfg(feature = "hardware")]
+    Gpio {
+
+        /// GPIO pin number using the BCM pin numbering.
+        pin: u8,
+
+        /// Error of the GPIO.
+        source: rppal::gpio::Error,
+    },
+
+    /// The pin of the light is already used by another driver of the process.
+    PinAlreadyInUse {
+
+        /// GPIO pin number using the BCM pin numbering.
+        pin: u8,
+
+        /// Driver using the pin, such as "buzzer".
+        owner: &'static str,
+    },
 }
 
-impl std::error::Error for Error {}
+/// Converts a conflict of pins
+impl From<PinInUse> for Error {
+    fn from(err: PinInUse) -> Error {
+        Error::PinAlreadyInUse { pin: err.pin, owner: err.owner }
+    }
+}
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &*self {
-            Error::Gpio(err) => write!(f, "Gpio error: {}", &err),
+impl std::error::Error for Error {
+    #[cfg_attr(not(feature = "hardware"), allow(unused_variables))]
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            #[cfg(feature = "hardware")]
+            Error::Gpio { ref source, .. } => Some(source),
+            Error::PinAlreadyInUse { .. } => None,
         }
     }
 }
 
-/// Converts Gpio error
-impl From<rppal::gpio::Error> for Error {
-    fn from(err: rppal::gpio::Error) -> Error {
-        Error::Gpio(err)
+impl std::fmt::Display for Error {
+    #[cfg_attr(not(feature = "hardware"), allow(unused_variables))]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "hardware")]
+            Error::Gpio { pin, ref source } => write!(f, "failed to configure BCM {} for light: {}", pin, &source),
+            Error::PinAlreadyInUse { pin, owner } => write!(f, "BCM {} is already used by the {}", pin, owner),
+        }
     }
 }
 
 
 /// Unit tests
 #[cfg(test)]
+#[allow(clippy::bool_comparison)]
 mod tests {
     use super::*;
 
@@ -315,9 +661,9 @@ mod tests {
         let mut lights = Lights::new()?;
 
         // Simulation off by default
-        assert!(!lights.red.simulation);
-        assert!(!lights.green.simulation);
-        assert!(!lights.blue.simulation);
+        assert!(lights.red.simulation != cfg!(feature = "hardware"));
+        assert!(lights.green.simulation != cfg!(feature = "hardware"));
+        assert!(lights.blue.simulation != cfg!(feature = "hardware"));
 
         // Turn on simulation
         lights.enable_simulation();
@@ -328,4 +674,205 @@ mod tests {
 
         Ok(())
     }
+
+    /// Tests creating the lights in simulation mode.
+    #[test]
+    fn test_lights_with_simulation() -> Result<(), Error> {
+        let lights = Lights::with_simulation()?;
+
+        assert!(lights.red.simulation);
+        assert!(lights.green.simulation);
+        assert!(lights.blue.simulation);
+
+        Ok(())
+    }
+
+    /// Tests getting whether simulation mode is enabled on a light and on the set of lights.
+    #[test]
+    fn test_lights_is_simulation() -> Result<(), Error> {
+        let mut light = Light::new(GPIO_LIGHT_RED)?;
+        assert!(light.is_simulation() != cfg!(feature = "hardware"));
+        light.enable_simulation();
+        assert!(light.is_simulation());
+        light.on();
+
+        let mut lights = Lights::new()?;
+        assert!(lights.is_simulation() != cfg!(feature = "hardware"));
+        lights.enable_simulation();
+        assert!(lights.is_simulation());
+
+        Ok(())
+    }
+
+    /// Output recording the levels written.
+    #[derive(Debug)]
+    struct RecordingOutput {
+        levels: std::sync::Arc<std::sync::Mutex<Vec<bool>>>,
+    }
+
+    impl DigitalOutput for RecordingOutput {
+        fn set_level(&mut self, high: bool) {
+            self.levels.lock().unwrap().push(high);
+        }
+    }
+
+    /// Tests a light driven by a custom output.
+    #[test]
+    fn test_light_with_pin() -> Result<(), Error> {
+        let levels = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut light = Light::with_pin(GPIO_LIGHT_RED, RecordingOutput { levels: levels.clone() });
+        assert!(!light.is_simulation());
+
+        light.on();
+        light.toggle();
+        light.write(true);
+        assert!(*levels.lock().unwrap() == [true, false, true]);
+
+        Ok(())
+    }
+
+    /// Tests releasing the pin of a light.
+    #[test]
+    fn test_light_release() -> Result<(), Error> {
+        let levels = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut light = Light::with_pin(GPIO_LIGHT_RED, RecordingOutput { levels: levels.clone() });
+        light.on();
+        assert!(light.is_setup && light.pin.is_some());
+
+        light.release();
+        assert!(!light.is_setup && light.pin.is_none());
+
+        // Set up again on the next use
+        let mut lights = Lights::with_simulation()?;
+        lights.rgb(true, false, false);
+        lights.release();
+        assert!(!lights.red.is_setup && !lights.blue.is_setup);
+        lights.red.off();
+        assert!(lights.red.is_setup && !lights.red.state);
+
+        Ok(())
+    }
+
+    /// Tests a pin used by two lights of the same registry is rejected in simulation mode, until the first light is
+    /// dropped, while the lights of other registries are free to use it.
+    #[test]
+    fn test_light_pin_in_use() -> Result<(), Error> {
+        let registry = PinRegistry::new();
+        let mut first = Light::new(GPIO_LIGHT_RED)?;
+        first.enable_simulation();
+        first.set_pin_registry(registry.clone());
+        first.setup()?;
+
+        let mut second = Light::new(GPIO_LIGHT_RED)?;
+        second.enable_simulation();
+        second.set_pin_registry(registry.clone());
+        assert!(matches!(second.setup(), Err(Error::PinAlreadyInUse { pin: GPIO_LIGHT_RED, owner: "light" })));
+
+        // Not written until set up
+        second.on();
+        assert!(!second.is_setup && second.recorder.entries().is_empty());
+
+        // Another registry, or a given output, is not concerned
+        let mut other = Light::new(GPIO_LIGHT_RED)?;
+        other.enable_simulation();
+        other.setup()?;
+        Light::with_pin(GPIO_LIGHT_RED, RecordingOutput { levels: Default::default() }).setup()?;
+
+        drop(first);
+        second.on();
+        assert!(second.is_setup && second.recorder.entries().len() == 1);
+
+        Ok(())
+    }
+
+    /// Backend failing to open the peripherals like off the Raspberry Pi.
+    #[cfg(feature = "hardware")]
+    #[derive(Debug)]
+    struct AbsentBackend;
+
+    #[cfg(feature = "hardware")]
+    impl Backend for AbsentBackend {
+        fn gpio(&self) -> Result<rppal::gpio::Gpio, rppal::gpio::Error> {
+            Err(rppal::gpio::Error::UnknownModel)
+        }
+
+        fn i2c(&self, _bus: Option<u8>) -> Result<rppal::i2c::I2c, rppal::i2c::Error> {
+            Err(rppal::i2c::Error::UnknownModel)
+        }
+    }
+
+    /// Tests the light switches to simulation mode when the hardware is absent, only when enabled.
+    #[test]
+    #[cfg(feature = "hardware")]
+    fn test_light_auto_simulate() -> Result<(), Error> {
+        let mut light = Light::new(GPIO_LIGHT_RED)?;
+        light.set_backend(AbsentBackend);
+        assert!(!light.is_auto_simulate());
+
+        // Disabled by default: the error is returned
+        let err = light.setup().unwrap_err();
+        assert!(matches!(err, Error::Gpio { pin: 6, source: rppal::gpio::Error::UnknownModel }));
+        assert!(err.to_string() == "failed to configure BCM 6 for light: Unknown Raspberry Pi model");
+        assert!(!light.is_simulation());
+
+        let messages = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let received = messages.clone();
+        light.set_trace(move |message| received.lock().unwrap().push(message.to_string()));
+        light.set_auto_simulate(true);
+        light.setup()?;
+        assert!(light.is_simulation());
+
+        light.on();
+        assert!(light.state);
+        assert!(*messages.lock().unwrap() == [
+            "Light 6: hardware unavailable (Unknown Raspberry Pi model), simulating",
+            "Light 6: on",
+        ]);
+
+        Ok(())
+    }
+
+    /// Pins the auto traits: the lights can be moved to another thread, their handle shared between threads.
+    const _: () = {
+        const fn assert_send<T: Send>() {}
+        const fn assert_sync<T: Sync>() {}
+        assert_send::<Light>();
+        assert_send::<Lights>();
+        assert_send::<LightsHandle>();
+        assert_sync::<LightsHandle>();
+        assert_send::<Error>();
+        assert_sync::<Error>();
+    };
+
+    /// Tests sharing the lights between two threads.
+    #[test]
+    fn test_lights_shared_handle() -> Result<(), Error> {
+        let handle = Lights::with_simulation()?.into_shared();
+        let messages = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let received = messages.clone();
+        handle.set_trace(move |message| received.lock().unwrap().push(message.to_string()));
+
+        // One thread blinking every light while another one alternates red and blue
+        let blinker = handle.clone();
+        let blinking = std::thread::spawn(move || {
+            for i in 0..10 {
+                blinker.all(i & 1 == 0);
+            }
+        });
+        let alternator = handle.clone();
+        let alternating = std::thread::spawn(move || {
+            for i in 0..10 {
+                alternator.rgb(i & 1 == 0, false, i & 1 != 0);
+            }
+        });
+
+        blinking.join().unwrap();
+        alternating.join().unwrap();
+
+        // Every write of both threads reached the lights
+        assert!(messages.lock().unwrap().len() == 60);
+        handle.with_driver(|lights| assert!(lights.is_simulation()));
+
+        Ok(())
+    }
 }
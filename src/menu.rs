@@ -0,0 +1,536 @@
+use std::fmt;
+use crate::Error;
+use crate::alphanum4::Alignment;
+use crate::bindings::Action;
+use crate::error::invalid_input;
+use crate::rainbow_hat::RainbowHat;
+use crate::touch::{ButtonId, TouchEvent};
+
+/// Label of the entry added at the end of each submenu to go back to its parent.
+pub const BACK_LABEL: &str = "BACK";
+
+/// Number of characters of the display, longer labels scroll.
+pub const LABEL_WIDTH: usize = 4;
+
+/// Change caused by a button press on a menu.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuEvent {
+
+    /// The item with the label was selected, its action being run on the board.
+    Activated(String),
+
+    /// The submenu with the label was entered.
+    Entered(String),
+
+    /// The current submenu was left for its parent.
+    Back,
+
+    /// The value of the item with the label was changed.
+    ValueChanged(String, i32),
+}
+
+/// What selecting an item does.
+enum ItemKind {
+
+    /// Run an action.
+    Action(Action),
+
+    /// Enter a submenu.
+    Submenu(Vec<MenuItem>),
+
+    /// Edit a number with buttons A and C.
+    Value {
+
+        /// Current value.
+        value: i32,
+
+        /// Lowest value.
+        min: i32,
+
+        /// Highest value.
+        max: i32,
+
+        /// Change of a press.
+        step: i32,
+    },
+}
+
+/// Entry of a menu, shown by its label: up to 4 characters fit on the display, longer labels scroll.
+pub struct MenuItem {
+
+    /// Label shown on the display.
+    label: String,
+
+    /// What selecting the item does.
+    kind: ItemKind,
+}
+
+impl MenuItem {
+
+    /// Creates an item running an action when selected.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - Label shown on the display.
+    /// * `action` - Closure receiving the board.
+    pub fn action<F>(label: &str, action: F) -> MenuItem
+    where
+        F: FnMut(&mut RainbowHat) + Send + 'static,
+    {
+        Self { label: label.to_string(), kind: ItemKind::Action(Box::new(action)) }
+    }
+
+    /// Creates an item entering a submenu when selected. A `BACK` entry is added at its end.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - Label shown on the display.
+    /// * `items` - Items of the submenu.
+    pub fn submenu(label: &str, items: Vec<MenuItem>) -> MenuItem {
+        Self { label: label.to_string(), kind: ItemKind::Submenu(items) }
+    }
+
+    /// Creates an item editing a number: selecting it shows the value, A decreases and C increases it
+    /// by the step within the bounds, B ends the edition.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - Label shown on the display.
+    /// * `value` - Initial value, between the bounds.
+    /// * `min` - Lowest value.
+    /// * `max` - Highest value, at least the lowest one.
+    /// * `step` - Change of a press, greater than 0.
+    pub fn value(label: &str, value: i32, min: i32, max: i32, step: i32) -> Result<MenuItem, Error> {
+        if step <= 0 {
+            return Err(Error::InvalidInput(invalid_input("step", step, "greater than 0")));
+        }
+        if !(min..=max).contains(&value) {
+            return Err(Error::InvalidInput(invalid_input("value", value, &format!("{} to {}", min, max))));
+        }
+
+        Ok(Self { label: label.to_string(), kind: ItemKind::Value { value, min, max, step } })
+    }
+
+    /// Get the label of the item.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+}
+
+impl fmt::Debug for MenuItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut item = f.debug_struct("MenuItem");
+        item.field("label", &self.label);
+        match &self.kind {
+            ItemKind::Action(_) => item.field("action", &"..."),
+            ItemKind::Submenu(items) => item.field("submenu", items),
+            ItemKind::Value { value, .. } => item.field("value", value),
+        };
+        item.finish()
+    }
+}
+
+/// Menu navigated with the buttons and shown on the display: A shows the previous item and C the next one,
+/// wrapping around, B selects the current item.
+/// The navigation only depends on the presses given to `press`; `poll` binds it to the buttons and the display.
+/// Send but not Sync, as the actions are only Send.
+#[derive(Debug)]
+pub struct Menu {
+
+    /// Items of the top level.
+    items: Vec<MenuItem>,
+
+    /// Index of each entered submenu, from the top level.
+    path: Vec<usize>,
+
+    /// Index of the current item in the current level, its length being the `BACK` entry.
+    cursor: usize,
+
+    /// Whether the value of the current item is edited.
+    editing: bool,
+
+    /// Scroll step of a long label.
+    scroll: usize,
+}
+
+impl Menu {
+
+    /// Creates a menu showing its first item.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - Items of the top level, at least 1.
+    pub fn new(items: Vec<MenuItem>) -> Result<Menu, Error> {
+        if items.is_empty() {
+            return Err(Error::InvalidInput(invalid_input("number of items", 0, "at least 1")));
+        }
+
+        Ok(Self {
+            items,
+            path: Vec::new(),
+            cursor: 0,
+            editing: false,
+            scroll: 0,
+        })
+    }
+
+    /// Get the number of submenus entered.
+    pub fn depth(&self) -> usize {
+        self.path.len()
+    }
+
+    /// Get whether the value of the current item is edited.
+    pub fn is_editing(&self) -> bool {
+        self.editing
+    }
+
+    /// Get the label of the current item, `BACK` for the entry leaving a submenu.
+    pub fn current_label(&self) -> &str {
+        match self.level().get(self.cursor) {
+            Some(item) => &item.label,
+            None => BACK_LABEL,
+        }
+    }
+
+    /// Get the text to show: the label of the current item, or its value while edited.
+    pub fn text(&self) -> String {
+        match self.level().get(self.cursor) {
+            Some(MenuItem { kind: ItemKind::Value { value, .. }, .. }) if self.editing => value.to_string(),
+            _ => self.current_label().to_string(),
+        }
+    }
+
+    /// Get the value of the first item with the label, searching the submenus, None if no value item has it.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - Label of the item.
+    pub fn value(&self, label: &str) -> Option<i32> {
+        fn find(items: &[MenuItem], label: &str) -> Option<i32> {
+            items.iter().find_map(|item| match &item.kind {
+                ItemKind::Value { value, .. } if item.label == label => Some(*value),
+                ItemKind::Submenu(items) => find(items, label),
+                _ => None,
+            })
+        }
+
+        find(&self.items, label)
+    }
+
+    /// Handle a press of a button, without running the actions: returns the change it caused, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Button pressed.
+    pub fn press(&mut self, id: ButtonId) -> Option<MenuEvent> {
+        if self.editing {
+            return match id {
+                ButtonId::A => self.change_value(-1),
+                ButtonId::C => self.change_value(1),
+                ButtonId::B => {
+                    self.editing = false;
+                    None
+                }
+            };
+        }
+
+        let len = self.level_len();
+        match id {
+            ButtonId::A => {
+                self.move_to((self.cursor + len - 1) % len);
+                None
+            }
+            ButtonId::C => {
+                self.move_to((self.cursor + 1) % len);
+                None
+            }
+            ButtonId::B => self.select(),
+        }
+    }
+
+    /// Attach the menu to the board, showing the current item.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board whose display shows the menu.
+    pub fn attach(&mut self, hat: &mut RainbowHat) -> Result<(), Error> {
+        self.render(hat)
+    }
+
+    /// Handle a press of a button on the board: the selected action is run and the display updated.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board whose display shows the menu.
+    /// * `id` - Button pressed.
+    pub fn handle(&mut self, hat: &mut RainbowHat, id: ButtonId) -> Result<Option<MenuEvent>, Error> {
+        let event = self.press(id);
+
+        if let Some(MenuEvent::Activated(_)) = event {
+            let cursor = self.cursor;
+            if let Some(MenuItem { kind: ItemKind::Action(action), .. }) = self.level_mut().get_mut(cursor) {
+                action(hat);
+            }
+        }
+
+        self.render(hat)?;
+        Ok(event)
+    }
+
+    /// Read the buttons once and handle a new press, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board whose display shows the menu.
+    pub fn poll(&mut self, hat: &mut RainbowHat) -> Result<Option<MenuEvent>, Error> {
+        for &id in [ButtonId::A, ButtonId::B, ButtonId::C].iter() {
+            if let Some(TouchEvent::Pressed) = hat.buttons.get_mut(id).poll_event() {
+                return self.handle(hat, id);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Scroll a label longer than the display by one character, to be called periodically, for example every 300 ms.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board whose display shows the menu.
+    pub fn scroll_tick(&mut self, hat: &mut RainbowHat) -> Result<(), Error> {
+        self.scroll += 1;
+        self.render(hat)
+    }
+
+    /// Show the current item on the display: a value right aligned, a label left aligned or scrolling.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board whose display shows the menu.
+    pub fn render(&self, hat: &mut RainbowHat) -> Result<(), Error> {
+        let text = self.text();
+
+        if self.editing {
+            hat.alphanum.print_str(&text, Alignment::Right)?;
+        } else if text.chars().count() <= LABEL_WIDTH {
+            hat.alphanum.print_str(&text, Alignment::Left)?;
+        } else {
+            hat.alphanum.scroll_str(&text, self.scroll)?;
+        }
+        hat.alphanum.show()?;
+
+        Ok(())
+    }
+
+    /// Get the items of the current level.
+    fn level(&self) -> &[MenuItem] {
+        let mut items = &self.items[..];
+        for &index in &self.path {
+            items = match &items[index].kind {
+                ItemKind::Submenu(submenu) => submenu,
+                _ => unreachable!("only submenus are entered"),
+            };
+        }
+        items
+    }
+
+    /// Get the items of the current level to run an action.
+    fn level_mut(&mut self) -> &mut [MenuItem] {
+        let mut items = &mut self.items[..];
+        for &index in &self.path {
+            items = match &mut items[index].kind {
+                ItemKind::Submenu(submenu) => submenu,
+                _ => unreachable!("only submenus are entered"),
+            };
+        }
+        items
+    }
+
+    /// Get the number of entries of the current level, with the `BACK` entry of a submenu.
+    fn level_len(&self) -> usize {
+        self.level().len() + if self.path.is_empty() { 0 } else { 1 }
+    }
+
+    /// Move to an entry of the current level, restarting the scrolling.
+    ///
+    /// # Arguments
+    ///
+    /// * `cursor` - Index of the entry.
+    fn move_to(&mut self, cursor: usize) {
+        self.cursor = cursor;
+        self.scroll = 0;
+    }
+
+    /// Select the current entry.
+    fn select(&mut self) -> Option<MenuEvent> {
+        let cursor = self.cursor;
+
+        match self.level().get(cursor) {
+            None => {
+                let parent = self.path.pop()?;
+                self.move_to(parent);
+                Some(MenuEvent::Back)
+            }
+            Some(item) => match &item.kind {
+                ItemKind::Action(_) => Some(MenuEvent::Activated(item.label.clone())),
+                ItemKind::Submenu(_) => {
+                    let label = item.label.clone();
+                    self.path.push(cursor);
+                    self.move_to(0);
+                    Some(MenuEvent::Entered(label))
+                }
+                ItemKind::Value { .. } => {
+                    self.editing = true;
+                    None
+                }
+            },
+        }
+    }
+
+    /// Change the value of the current item by steps, within its bounds.
+    ///
+    /// # Arguments
+    ///
+    /// * `steps` - Number of steps, negative to decrease.
+    fn change_value(&mut self, steps: i32) -> Option<MenuEvent> {
+        let cursor = self.cursor;
+        let item = self.level_mut().get_mut(cursor)?;
+
+        if let ItemKind::Value { value, min, max, step } = &mut item.kind {
+            let changed = value.saturating_add(steps.saturating_mul(*step)).clamp(*min, *max);
+            if changed != *value {
+                *value = changed;
+                return Some(MenuEvent::ValueChanged(item.label.clone(), changed));
+            }
+        }
+
+        None
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Creates a menu with an action, a value and a submenu holding another action and a value.
+    fn sample() -> Result<Menu, Error> {
+        Menu::new(vec![
+            MenuItem::action("PLAY", |_hat| {}),
+            MenuItem::value("VOL", 5, 0, 10, 2)?,
+            MenuItem::submenu("SETTINGS", vec![
+                MenuItem::action("RST", |_hat| {}),
+                MenuItem::value("BRI", 31, 0, 31, 8)?,
+            ]),
+        ])
+    }
+
+    /// Tests A and C move through the items, wrapping around.
+    #[test]
+    fn test_menu_navigation() -> Result<(), Error> {
+        let mut menu = sample()?;
+        assert!(menu.text() == "PLAY");
+
+        assert!(menu.press(ButtonId::C).is_none());
+        assert!(menu.text() == "VOL");
+        menu.press(ButtonId::C);
+        assert!(menu.text() == "SETTINGS");
+        menu.press(ButtonId::C);
+        assert!(menu.text() == "PLAY");
+        menu.press(ButtonId::A);
+        assert!(menu.text() == "SETTINGS");
+
+        assert!(menu.press(ButtonId::B) == Some(MenuEvent::Entered("SETTINGS".to_string())));
+        assert!(menu.depth() == 1 && menu.text() == "RST");
+        menu.press(ButtonId::A);
+        assert!(menu.current_label() == BACK_LABEL);
+        assert!(menu.press(ButtonId::B) == Some(MenuEvent::Back));
+        assert!(menu.depth() == 0 && menu.text() == "SETTINGS");
+
+        menu.press(ButtonId::C);
+        assert!(menu.press(ButtonId::B) == Some(MenuEvent::Activated("PLAY".to_string())));
+        assert!(menu.text() == "PLAY");
+
+        Ok(())
+    }
+
+    /// Tests editing values within their bounds.
+    #[test]
+    fn test_menu_edit_value() -> Result<(), Error> {
+        let mut menu = sample()?;
+        menu.press(ButtonId::C);
+
+        assert!(menu.press(ButtonId::B).is_none());
+        assert!(menu.is_editing() && menu.text() == "5");
+        assert!(menu.press(ButtonId::C) == Some(MenuEvent::ValueChanged("VOL".to_string(), 7)));
+        menu.press(ButtonId::C);
+        assert!(menu.press(ButtonId::C) == Some(MenuEvent::ValueChanged("VOL".to_string(), 10)));
+        assert!(menu.press(ButtonId::C).is_none());
+        assert!(menu.text() == "10");
+
+        menu.press(ButtonId::B);
+        assert!(!menu.is_editing() && menu.text() == "VOL");
+        assert!(menu.value("VOL") == Some(10));
+
+        // Values of the submenus are edited the same way
+        menu.press(ButtonId::C);
+        menu.press(ButtonId::B);
+        menu.press(ButtonId::C);
+        menu.press(ButtonId::B);
+        for expected in [23, 15, 7, 0] {
+            assert!(menu.press(ButtonId::A) == Some(MenuEvent::ValueChanged("BRI".to_string(), expected)));
+        }
+        assert!(menu.press(ButtonId::A).is_none());
+        menu.press(ButtonId::B);
+        assert!(menu.value("BRI") == Some(0));
+        assert!(menu.value("PLAY").is_none() && menu.value("NONE").is_none());
+
+        Ok(())
+    }
+
+    /// Tests the invalid menus and values are rejected.
+    #[test]
+    fn test_menu_invalid() {
+        assert!(matches!(Menu::new(Vec::new()), Err(Error::InvalidInput(_))));
+        assert!(matches!(MenuItem::value("VOL", 11, 0, 10, 1), Err(Error::InvalidInput(msg)) if msg == "invalid value 11, expected 0 to 10"));
+        assert!(matches!(MenuItem::value("VOL", 5, 0, 10, 0), Err(Error::InvalidInput(_))));
+    }
+
+    /// Tests the menu on the board runs the actions and shows the items, scrolling the long labels.
+    #[test]
+    fn test_menu_on_board() -> Result<(), Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counted = runs.clone();
+        let mut menu = Menu::new(vec![
+            MenuItem::action("RED", move |hat| {
+                hat.lights.red.on();
+                counted.fetch_add(1, Ordering::SeqCst);
+            }),
+            MenuItem::value("LEVEL", 3, 0, 9, 1)?,
+        ])?;
+
+        menu.attach(&mut hat)?;
+        assert!(hat.alphanum.read_str().unwrap() == "RED ");
+
+        assert!(menu.handle(&mut hat, ButtonId::B)? == Some(MenuEvent::Activated("RED".to_string())));
+        assert!(runs.load(Ordering::SeqCst) == 1 && hat.lights.red.state);
+
+        menu.handle(&mut hat, ButtonId::C)?;
+        assert!(hat.alphanum.read_str().unwrap() == "LEVE");
+        menu.scroll_tick(&mut hat)?;
+        assert!(hat.alphanum.read_str().unwrap() == "EVEL");
+
+        menu.handle(&mut hat, ButtonId::B)?;
+        menu.handle(&mut hat, ButtonId::C)?;
+        assert!(hat.alphanum.read_str().unwrap() == "   4");
+
+        // Nothing happens without a press
+        assert!(menu.poll(&mut hat)?.is_none());
+        assert!(runs.load(Ordering::SeqCst) == 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,406 @@
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response};
+use crate::Error;
+use crate::rainbow_hat::{RainbowHat, SharedRainbowHat};
+use crate::touch::{ButtonId, EdgeDetector, TouchEvent};
+
+/// Content type of the Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Buttons of the board with the value of their `button` label.
+const BUTTONS: [(ButtonId, &str); 3] = [(ButtonId::A, "a"), (ButtonId::B, "b"), (ButtonId::C, "c")];
+
+/// Values of the metrics of the board at one gathering.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Metrics {
+
+    /// Temperature, in degrees Celsius. None when the sensor could not be read.
+    pub temperature_c: Option<f32>,
+
+    /// Pressure, in hectopascal. None when the sensor could not be read.
+    pub pressure_hpa: Option<f32>,
+
+    /// Number of presses of the buttons A, B and C.
+    pub button_presses: [u64; 3],
+
+    /// Number of writes of the display buffer.
+    pub display_writes: u64,
+}
+
+impl Metrics {
+
+    /// Render the metrics in the Prometheus text exposition format.
+    /// The gauges of the sensor are left out when it could not be read.
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+
+        if let Some(temperature) = self.temperature_c {
+            family(&mut text, "rainbowhat_temperature_celsius", "gauge", "Temperature measured by the BMP280 sensor, in degrees Celsius.");
+            sample(&mut text, "rainbowhat_temperature_celsius", "", float(temperature));
+        }
+
+        if let Some(pressure) = self.pressure_hpa {
+            family(&mut text, "rainbowhat_pressure_hpa", "gauge", "Pressure measured by the BMP280 sensor, in hectopascal.");
+            sample(&mut text, "rainbowhat_pressure_hpa", "", float(pressure));
+        }
+
+        family(&mut text, "rainbowhat_button_presses_total", "counter", "Number of presses of the touch buttons.");
+        for (&(_id, label), presses) in BUTTONS.iter().zip(self.button_presses.iter()) {
+            sample(&mut text, "rainbowhat_button_presses_total", &format!("{{button=\"{}\"}}", label), presses.to_string());
+        }
+
+        family(&mut text, "rainbowhat_display_writes_total", "counter", "Number of writes of the alphanumeric display.");
+        sample(&mut text, "rainbowhat_display_writes_total", "", self.display_writes.to_string());
+
+        text
+    }
+}
+
+/// Append the HELP and TYPE lines of a metric family.
+///
+/// # Arguments
+///
+/// * `text` - Text rendered.
+/// * `name` - Name of the metric.
+/// * `kind` - Type of the metric: gauge or counter.
+/// * `help` - Description of the metric.
+fn family(text: &mut String, name: &str, kind: &str, help: &str) {
+    let _result = writeln!(text, "# HELP {} {}", name, help);
+    let _result = writeln!(text, "# TYPE {} {}", name, kind);
+}
+
+/// Append a sample line.
+///
+/// # Arguments
+///
+/// * `text` - Text rendered.
+/// * `name` - Name of the metric.
+/// * `labels` - Labels with their braces, empty for none.
+/// * `value` - Value rendered.
+fn sample(text: &mut String, name: &str, labels: &str, value: String) {
+    let _result = writeln!(text, "{}{} {}", name, labels, value);
+}
+
+/// Render a float value, with the spelling of the exposition format for the special values.
+///
+/// # Arguments
+///
+/// * `value` - Value to render.
+fn float(value: f32) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Gathers the metrics of a board, counting the presses of the buttons between successive gatherings.
+/// A press shorter than the interval between two gatherings is not counted.
+#[derive(Debug, Default)]
+pub struct Collector {
+
+    /// Edge detector of each button.
+    edges: [EdgeDetector; 3],
+
+    /// Number of presses of each button.
+    presses: [u64; 3],
+}
+
+impl Collector {
+
+    /// Creates a collector with every counter at zero.
+    pub fn new() -> Collector {
+        Self::default()
+    }
+
+    /// Read the sensor, the buttons and the display of a board.
+    ///
+    /// # Arguments
+    ///
+    /// * `board` - Board read.
+    pub fn gather(&mut self, board: &mut RainbowHat) -> Metrics {
+        for (i, &(id, _label)) in BUTTONS.iter().enumerate() {
+            let pressed = board.buttons.get_mut(id).is_pressed();
+            if self.edges[i].update(pressed) == Some(TouchEvent::Pressed) {
+                self.presses[i] += 1;
+            }
+        }
+
+        let measurement = board.bmp280.read_measurement().ok();
+
+        Metrics {
+            temperature_c: measurement.map(|measurement| measurement.temperature_c),
+            pressure_hpa: measurement.map(|measurement| measurement.pressure_hpa),
+            button_presses: self.presses,
+            display_writes: board.alphanum.ht16k33.write_count(),
+        }
+    }
+}
+
+/// HTTP exporter answering `GET /metrics` with the metrics of a board.
+/// The metrics are gathered from a background thread at each interval, so a scrape never waits for the board.
+pub struct Exporter {
+
+    /// HTTP listener.
+    http: tiny_http::Server,
+
+    /// Metrics of the last gathering.
+    metrics: Arc<Mutex<Metrics>>,
+
+    /// Sender stopping the gathering thread when dropped.
+    stop: Option<Sender<()>>,
+
+    /// Gathering thread.
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Exporter {
+
+    /// Creates an exporter listening on an address and starts gathering the metrics. The first gathering is done
+    /// before returning, so the first scrape is answered with values.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Address to listen on, port 0 choosing a free port.
+    /// * `board` - Board read.
+    /// * `interval` - Interval between two gatherings.
+    pub fn bind(addr: SocketAddr, board: SharedRainbowHat, interval: Duration) -> Result<Exporter, Error> {
+        let http = tiny_http::Server::http(addr).map_err(|err| Error::Io(std::io::Error::other(err)))?;
+
+        let mut collector = Collector::new();
+        let metrics = Arc::new(Mutex::new(board.with_board(|board| collector.gather(board))));
+        let (stop, stopped) = mpsc::channel();
+
+        let gathered = metrics.clone();
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let metrics = board.with_board(|board| collector.gather(board));
+                *gathered.lock().unwrap_or_else(|err| err.into_inner()) = metrics;
+            }
+        });
+
+        Ok(Self {
+            http,
+            metrics,
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+
+    /// Get the address the exporter listens on.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Get the metrics of the last gathering.
+    pub fn metrics(&self) -> Metrics {
+        *self.metrics.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Wait for the next request and answer it.
+    pub fn handle_next(&mut self) -> Result<(), Error> {
+        let request = self.http.recv()?;
+        self.respond(request)
+    }
+
+    /// Answer requests until the listener fails.
+    pub fn run(mut self) -> Result<(), Error> {
+        loop {
+            self.handle_next()?;
+        }
+    }
+
+    /// Answer a request.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - Request received.
+    fn respond(&mut self, request: Request) -> Result<(), Error> {
+        let path = request.url().split('?').next().unwrap_or("");
+
+        let (status, body) = match (request.method(), path) {
+            (Method::Get, "/metrics") => (200, self.metrics().render()),
+            (_, "/metrics") => (405, "method not allowed\n".to_string()),
+            _ => (404, "not found\n".to_string()),
+        };
+
+        let response = Response::from_string(body)
+            .with_status_code(status)
+            .with_header(Header::from_bytes(&b"Content-Type"[..], CONTENT_TYPE.as_bytes()).unwrap());
+        request.respond(response)?;
+
+        Ok(())
+    }
+}
+
+impl Drop for Exporter {
+
+    /// Stops the gathering thread.
+    fn drop(&mut self) {
+        self.stop.take();
+
+        if let Some(thread) = self.thread.take() {
+            let _result = thread.join();
+        }
+    }
+}
+
+/// Serve the metrics of a board on an address, until the listener fails.
+///
+/// # Arguments
+///
+/// * `addr` - Address to listen on.
+/// * `board` - Board read, shared with the application.
+/// * `interval` - Interval between two gatherings.
+pub fn serve(addr: SocketAddr, board: SharedRainbowHat, interval: Duration) -> Result<(), Error> {
+    Exporter::bind(addr, board, interval)?.run()
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphanum4::Alignment;
+    use crate::bmp280::Calibration;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    /// Calibration of the worked example of the datasheet.
+    const DATASHEET_CALIBRATION: Calibration = Calibration {
+        dig_t1: 27504,
+        dig_t2: 26435,
+        dig_t3: -1000,
+        dig_p1: 36477,
+        dig_p2: -10685,
+        dig_p3: 3024,
+        dig_p4: 2855,
+        dig_p5: 140,
+        dig_p6: -7,
+        dig_p7: 15500,
+        dig_p8: -14600,
+        dig_p9: 6000,
+    };
+
+    /// Tests the rendering against the exposition format.
+    #[test]
+    fn test_metrics_render() {
+        let metrics = Metrics {
+            temperature_c: Some(21.5),
+            pressure_hpa: Some(1013.25),
+            button_presses: [3, 0, 12],
+            display_writes: 42,
+        };
+
+        assert!(metrics.render() == "\
+# HELP rainbowhat_temperature_celsius Temperature measured by the BMP280 sensor, in degrees Celsius.
+# TYPE rainbowhat_temperature_celsius gauge
+rainbowhat_temperature_celsius 21.5
+# HELP rainbowhat_pressure_hpa Pressure measured by the BMP280 sensor, in hectopascal.
+# TYPE rainbowhat_pressure_hpa gauge
+rainbowhat_pressure_hpa 1013.25
+# HELP rainbowhat_button_presses_total Number of presses of the touch buttons.
+# TYPE rainbowhat_button_presses_total counter
+rainbowhat_button_presses_total{button=\"a\"} 3
+rainbowhat_button_presses_total{button=\"b\"} 0
+rainbowhat_button_presses_total{button=\"c\"} 12
+# HELP rainbowhat_display_writes_total Number of writes of the alphanumeric display.
+# TYPE rainbowhat_display_writes_total counter
+rainbowhat_display_writes_total 42
+");
+
+        // The gauges are left out without a reading and the special values are spelled as in the format
+        let metrics = Metrics {
+            temperature_c: Some(f32::NEG_INFINITY),
+            ..Metrics::default()
+        };
+        assert!(metrics.render() == "\
+# HELP rainbowhat_temperature_celsius Temperature measured by the BMP280 sensor, in degrees Celsius.
+# TYPE rainbowhat_temperature_celsius gauge
+rainbowhat_temperature_celsius -Inf
+# HELP rainbowhat_button_presses_total Number of presses of the touch buttons.
+# TYPE rainbowhat_button_presses_total counter
+rainbowhat_button_presses_total{button=\"a\"} 0
+rainbowhat_button_presses_total{button=\"b\"} 0
+rainbowhat_button_presses_total{button=\"c\"} 0
+# HELP rainbowhat_display_writes_total Number of writes of the alphanumeric display.
+# TYPE rainbowhat_display_writes_total counter
+rainbowhat_display_writes_total 0
+");
+        assert!(float(f32::NAN) == "NaN" && float(f32::INFINITY) == "+Inf");
+    }
+
+    /// Tests the gathering from a simulated board with injected sensor values.
+    #[test]
+    fn test_metrics_collector() -> Result<(), Error> {
+        let mut board = RainbowHat::with_simulation()?;
+        board.bmp280.simulate(DATASHEET_CALIBRATION, 519888, 415148);
+        let mut collector = Collector::new();
+
+        for &pressed in &[true, false, true, true, false] {
+            board.buttons.get_mut(ButtonId::A).set_simulated_state(pressed);
+            board.buttons.get_mut(ButtonId::C).set_simulated_state(true);
+            collector.gather(&mut board);
+        }
+
+        board.alphanum.print_str("HI", Alignment::Left)?;
+        board.alphanum.show()?;
+        board.alphanum.show()?;
+
+        let metrics = collector.gather(&mut board);
+        assert!(metrics.button_presses == [2, 0, 1]);
+        assert!(metrics.display_writes == 2);
+        assert!((metrics.temperature_c.unwrap() - 25.08).abs() < 0.01);
+        assert!((metrics.pressure_hpa.unwrap() - 1006.5327).abs() < 0.01);
+
+        Ok(())
+    }
+
+    /// Tests the exporter through HTTP with an in-process client.
+    #[test]
+    fn test_metrics_exporter() -> Result<(), Error> {
+        let mut board = RainbowHat::with_simulation()?;
+        board.bmp280.simulate(DATASHEET_CALIBRATION, 519888, 415148);
+        let board = board.into_shared();
+
+        let mut exporter = Exporter::bind("127.0.0.1:0".parse().unwrap(), board.clone(), Duration::from_secs(3600))?;
+        let addr = exporter.local_addr().unwrap();
+
+        let requests = thread::spawn(move || {
+            for _i in 0..3 {
+                exporter.handle_next().unwrap();
+            }
+            exporter
+        });
+
+        let get = |method: &str, path: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: 0\r\n\r\n", method, path).unwrap();
+
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = get("GET", "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains(CONTENT_TYPE));
+        assert!(response.contains("\r\n\r\n# HELP rainbowhat_temperature_celsius "));
+        assert!(response.contains("\nrainbowhat_temperature_celsius 25.08"));
+        assert!(response.contains("\nrainbowhat_display_writes_total 0\n"));
+
+        assert!(get("POST", "/metrics").starts_with("HTTP/1.1 405"));
+        assert!(get("GET", "/").starts_with("HTTP/1.1 404"));
+
+        let exporter = requests.join().unwrap();
+        assert!(exporter.metrics().button_presses == [0, 0, 0]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,385 @@
+use std::thread;
+use std::time::{Duration, Instant};
+use rumqttc::{Client, Event, MqttOptions, Packet, QoS, RecvTimeoutError};
+use serde_json::json;
+use crate::Error;
+use crate::clock::{Clock, SystemClock};
+use crate::commands;
+use crate::rainbow_hat::{Component, RainbowHat};
+
+/// Default interval between two publications of the temperature and the pressure.
+pub const SENSOR_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Delay before reconnecting after the first loss of the broker, doubled on each new failure.
+pub const RECONNECT_MIN_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest delay before reconnecting to the broker.
+pub const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Interval at which the buttons are checked while waiting for the messages of the broker.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Topics of the board. The payloads are JSON:
+///
+/// * `lights_set` - `{"r": true, "g": false, "b": true}`, each light being optional.
+/// * `display_set` - `{"text": "HI", "align": "right"}`, the alignment being left, right or center.
+/// * `pixels_set` - array of colors, or `{"pixels": [...], "brightness": 0.5}`. Each color is
+///   a `[r, g, b]` array, a 0xRRGGBB number, a name or a "#RRGGBB" string.
+/// * `buttons` - retained state of the buttons, published on each press and release: `{"a": false, "b": true, "c": false}`.
+/// * `sensor` - retained temperature and pressure, published periodically: `{"temperature_c": 21.5, "pressure_hpa": 1013.2}`.
+/// * `error` - error of an invalid command, not retained: `{"topic": "...", "error": "..."}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Topics {
+    pub lights_set: String,
+    pub display_set: String,
+    pub pixels_set: String,
+    pub buttons: String,
+    pub sensor: String,
+    pub error: String,
+}
+
+impl Topics {
+
+    /// Creates the topics under a prefix, for example `rainbowhat/lights/set` for the prefix `rainbowhat`.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - Prefix of the topics, without a trailing slash.
+    pub fn with_prefix(prefix: &str) -> Topics {
+        Topics {
+            lights_set: format!("{}/lights/set", prefix),
+            display_set: format!("{}/display/set", prefix),
+            pixels_set: format!("{}/pixels/set", prefix),
+            buttons: format!("{}/buttons", prefix),
+            sensor: format!("{}/sensor", prefix),
+            error: format!("{}/error", prefix),
+        }
+    }
+
+    /// Get the command topics to subscribe to.
+    pub fn commands(&self) -> [&str; 3] {
+        [&self.lights_set, &self.display_set, &self.pixels_set]
+    }
+}
+
+impl Default for Topics {
+    fn default() -> Self {
+        Topics::with_prefix("rainbowhat")
+    }
+}
+
+/// Message to publish on a state topic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+
+    /// Topic of the message.
+    pub topic: String,
+
+    /// JSON payload.
+    pub payload: String,
+
+    /// Whether the broker keeps the message for the next subscribers.
+    pub retain: bool,
+}
+
+/// Link between the board and the topics, independent of the network so it can be driven with synthetic messages:
+/// `apply` executes the commands received, `poll` returns the state messages to publish.
+/// Send but not Sync, as the board and the clock are only Send.
+pub struct Bridge {
+
+    /// Board controlled.
+    board: RainbowHat,
+
+    /// Topics of the board.
+    topics: Topics,
+
+    /// Interval between two publications of the temperature and the pressure.
+    sensor_interval: Duration,
+
+    /// clock timing the publications of the temperature and the pressure
+    clock: Box<dyn Clock>,
+
+    /// state of the buttons last published
+    buttons: Option<serde_json::Value>,
+
+    /// instant of the last publication of the temperature and the pressure
+    last_sensor: Option<Instant>,
+}
+
+impl Bridge {
+
+    /// Creates a bridge publishing the temperature and the pressure every `SENSOR_INTERVAL`.
+    ///
+    /// # Arguments
+    ///
+    /// * `board` - Board controlled, for example in simulation mode.
+    /// * `topics` - Topics of the board.
+    pub fn new(board: RainbowHat, topics: Topics) -> Bridge {
+        Self {
+            board,
+            topics,
+            sensor_interval: SENSOR_INTERVAL,
+            clock: Box::new(SystemClock),
+            buttons: None,
+            last_sensor: None,
+        }
+    }
+
+    /// Set the interval between two publications of the temperature and the pressure.
+    /// # Arguments
+    ///
+    /// * `interval` - Interval between two publications.
+    pub fn set_sensor_interval(&mut self, interval: Duration) {
+        self.sensor_interval = interval;
+    }
+
+    /// Set the clock timing the publications of the temperature and the pressure.
+    /// # Arguments
+    ///
+    /// * `clock` - Clock to use.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Box::new(clock);
+    }
+
+    /// Get the topics of the board.
+    pub fn topics(&self) -> &Topics {
+        &self.topics
+    }
+
+    /// Get the board controlled.
+    pub fn board(&mut self) -> &mut RainbowHat {
+        &mut self.board
+    }
+
+    /// Execute a command received on a topic. Invalid payloads and unknown topics are invalid input,
+    /// leaving the board unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - Topic of the message.
+    /// * `payload` - JSON payload.
+    pub fn apply(&mut self, topic: &str, payload: &[u8]) -> Result<(), Error> {
+        let payload = std::str::from_utf8(payload).map_err(|err| Error::InvalidInput(format!("invalid payload: {}", err)))?;
+
+        if topic == self.topics.lights_set {
+            commands::set_lights(&mut self.board, payload)?;
+        } else if topic == self.topics.display_set {
+            commands::set_display(&mut self.board, payload)?;
+        } else if topic == self.topics.pixels_set {
+            commands::set_pixels(&mut self.board, payload)?;
+        } else {
+            return Err(Error::InvalidInput(format!("no command on topic {}", topic)));
+        }
+
+        Ok(())
+    }
+
+    /// Get the message reporting the error of a command, to publish on the error topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - Topic of the command.
+    /// * `err` - Error of the command.
+    pub fn error_message(&self, topic: &str, err: &Error) -> Message {
+        Message {
+            topic: self.topics.error.clone(),
+            payload: json!({ "topic": topic, "error": err.to_string() }).to_string(),
+            retain: false,
+        }
+    }
+
+    /// Get the state messages to publish: the buttons when they changed, and the temperature and the pressure
+    /// when the interval elapsed. Both are published on the first call. A failed measure is reported on the error topic,
+    /// and the peripherals disabled with the builder are not published.
+    pub fn poll(&mut self) -> Vec<Message> {
+        let mut messages = Vec::new();
+
+        if self.board.is_enabled(Component::Buttons) {
+            let buttons = commands::buttons_state(&mut self.board);
+            if self.buttons.as_ref() != Some(&buttons) {
+                messages.push(Message {
+                    topic: self.topics.buttons.clone(),
+                    payload: buttons.to_string(),
+                    retain: true,
+                });
+                self.buttons = Some(buttons);
+            }
+        }
+
+        // A disabled sensor is not measured, instead of publishing an error at each interval
+        let now = self.clock.now();
+        if self.board.is_enabled(Component::Sensor) && self.last_sensor.is_none_or(|last| now - last >= self.sensor_interval) {
+            messages.push(match commands::sensor_state(&mut self.board) {
+                Ok(state) => Message {
+                    topic: self.topics.sensor.clone(),
+                    payload: state.to_string(),
+                    retain: true,
+                },
+                Err(err) => self.error_message(&self.topics.sensor, &err),
+            });
+            self.last_sensor = Some(now);
+        }
+
+        messages
+    }
+}
+
+/// Get the delay before the next reconnection, doubling the previous one up to `RECONNECT_MAX_DELAY`.
+///
+/// # Arguments
+///
+/// * `delay` - Previous delay, None after a successful connection.
+pub fn reconnect_delay(delay: Option<Duration>) -> Duration {
+    match delay {
+        None => RECONNECT_MIN_DELAY,
+        Some(delay) => (delay * 2).min(RECONNECT_MAX_DELAY),
+    }
+}
+
+/// Connect the bridge to a broker and run it until its event loop stops: subscribe to the command topics,
+/// apply the commands and publish the states. The broker is reconnected with an exponential backoff when lost,
+/// and the subscriptions are renewed on each connection.
+///
+/// # Arguments
+///
+/// * `bridge` - Bridge between the board and the topics.
+/// * `options` - Options of the broker connection: address, client identifier, credentials.
+pub fn run(mut bridge: Bridge, options: MqttOptions) -> Result<(), Error> {
+    let (client, mut connection) = Client::new(options, 16);
+    let mut delay = None;
+
+    loop {
+        match connection.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(Event::Incoming(Packet::ConnAck(_)))) => {
+                delay = None;
+                for topic in bridge.topics().commands().iter() {
+                    let _result = client.try_subscribe(*topic, QoS::AtLeastOnce);
+                }
+
+                // Publish the whole state again on the new connection
+                bridge.buttons = None;
+                bridge.last_sensor = None;
+            }
+            Ok(Ok(Event::Incoming(Packet::Publish(publish)))) => {
+                if let Err(err) = bridge.apply(&publish.topic, &publish.payload) {
+                    let message = bridge.error_message(&publish.topic, &err);
+                    let _result = client.try_publish(message.topic, QoS::AtLeastOnce, message.retain, message.payload);
+                }
+            }
+            Ok(Ok(_event)) => {}
+            Ok(Err(_err)) => {
+                let wait = reconnect_delay(delay);
+                thread::sleep(wait);
+                delay = Some(wait);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+
+        // The messages are queued while the broker is lost, the oldest being dropped when the queue is full
+        for message in bridge.poll() {
+            let _result = client.try_publish(message.topic, QoS::AtLeastOnce, message.retain, message.payload);
+        }
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::touch::ButtonId;
+
+    /// Tests the topics under a prefix.
+    #[test]
+    fn test_mqtt_topics() {
+        let topics = Topics::default();
+        assert!(topics.commands() == ["rainbowhat/lights/set", "rainbowhat/display/set", "rainbowhat/pixels/set"]);
+        assert!(topics.buttons == "rainbowhat/buttons" && topics.sensor == "rainbowhat/sensor");
+
+        assert!(Topics::with_prefix("home/hat").lights_set == "home/hat/lights/set");
+    }
+
+    /// Tests applying synthetic commands to a simulated board.
+    #[test]
+    fn test_mqtt_apply() -> Result<(), Error> {
+        let mut bridge = Bridge::new(RainbowHat::with_simulation()?, Topics::default());
+
+        bridge.apply("rainbowhat/lights/set", br#"{"r": true, "b": true}"#)?;
+        let lights = &bridge.board().lights;
+        assert!(lights.red.state && !lights.green.state && lights.blue.state);
+
+        bridge.apply("rainbowhat/pixels/set", br#"{"pixels": ["red", [0, 0, 255]], "brightness": 0.0}"#)?;
+        assert!(bridge.board().apa102.get_pixel(1)? == (0, 0, 255, 0));
+
+        bridge.apply("rainbowhat/display/set", br#"{"text": "HI"}"#)?;
+        assert!(!bridge.board().alphanum.is_blank());
+
+        // Invalid commands leave the board unchanged
+        let invalid: [(&str, &[u8]); 5] = [
+            ("rainbowhat/lights/set", b"on"),
+            ("rainbowhat/lights/set", br#"{"r": "yes"}"#),
+            ("rainbowhat/pixels/set", br#"["octarine"]"#),
+            ("rainbowhat/display/set", &[0xFF, 0xFE]),
+            ("rainbowhat/buzzer/set", b"{}"),
+        ];
+        for (topic, payload) in invalid.iter() {
+            assert!(matches!(bridge.apply(topic, payload), Err(Error::InvalidInput(_))), "{}", topic);
+        }
+        assert!(bridge.board().lights.red.state);
+        assert!(bridge.board().apa102.get_pixel(0)? == (255, 0, 0, 0));
+
+        let message = bridge.error_message("rainbowhat/lights/set", &Error::InvalidInput("bad".to_string()));
+        assert!(message.topic == "rainbowhat/error" && !message.retain);
+        assert!(message.payload.contains("bad"));
+
+        Ok(())
+    }
+
+    /// Tests the button events and the periodic readings of the sensor are published on the retained state topics.
+    #[test]
+    fn test_mqtt_poll() -> Result<(), Error> {
+        let clock = ManualClock::new();
+        let mut bridge = Bridge::new(RainbowHat::with_simulation()?, Topics::default());
+        bridge.set_clock(clock.clone());
+        bridge.set_sensor_interval(Duration::from_secs(10));
+
+        // The whole state is published first
+        let messages = bridge.poll();
+        assert!(messages.iter().map(|message| message.topic.as_str()).eq(["rainbowhat/buttons", "rainbowhat/sensor"]));
+        assert!(messages.iter().all(|message| message.retain));
+        assert!(messages[0].payload == r#"{"a":false,"b":false,"c":false}"#);
+        assert!(messages[1].payload.contains("temperature_c"));
+        assert!(bridge.poll().is_empty());
+
+        // Press and release
+        bridge.board().buttons.get_mut(ButtonId::A).set_simulated_state(true);
+        let messages = bridge.poll();
+        assert!(messages.len() == 1 && messages[0].payload == r#"{"a":true,"b":false,"c":false}"#);
+        assert!(bridge.poll().is_empty());
+        bridge.board().buttons.get_mut(ButtonId::A).set_simulated_state(false);
+        assert!(bridge.poll()[0].payload == r#"{"a":false,"b":false,"c":false}"#);
+
+        clock.advance(Duration::from_secs(9));
+        assert!(bridge.poll().is_empty());
+        clock.advance(Duration::from_secs(1));
+        let messages = bridge.poll();
+        assert!(messages.len() == 1 && messages[0].topic == "rainbowhat/sensor");
+
+        Ok(())
+    }
+
+    /// Tests the reconnection delay doubles up to its maximum.
+    #[test]
+    fn test_mqtt_reconnect_delay() {
+        let mut delay = None;
+        let mut delays = Vec::new();
+        for _i in 0..8 {
+            let next = reconnect_delay(delay);
+            delays.push(next.as_secs());
+            delay = Some(next);
+        }
+        assert!(delays == [1, 2, 4, 8, 16, 32, 60, 60]);
+        assert!(reconnect_delay(None) == RECONNECT_MIN_DELAY);
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+/// Registry of the pins opened on the hardware by the drivers of the process.
+static PROCESS_REGISTRY: OnceLock<PinRegistry> = OnceLock::new();
+
+/// Whether the claims are checked.
+static CHECK_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// A BCM pin is already used by another driver of the process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PinInUse {
+
+    /// BCM pin number.
+    pub pin: u8,
+
+    /// Driver owning the pin, such as "buzzer".
+    pub owner: &'static str,
+}
+
+impl error::Error for PinInUse {}
+
+impl fmt::Display for PinInUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BCM {} is already used by the {}", self.pin, self.owner)
+    }
+}
+
+/// BCM pins claimed by drivers, with the driver owning each one. The clones share the same registry.
+/// The pins opened on the hardware are claimed in the registry of the process, while the drivers in simulation
+/// mode claim theirs in a registry of their board, so that simulated boards never conflict with each other.
+#[derive(Debug, Clone, Default)]
+pub struct PinRegistry {
+
+    /// Driver owning each claimed pin.
+    owners: Arc<Mutex<HashMap<u8, &'static str>>>,
+}
+
+impl PinRegistry {
+
+    /// Creates an empty registry, for example shared by the drivers of a board in simulation mode.
+    pub fn new() -> PinRegistry {
+        PinRegistry::default()
+    }
+
+    /// Get the registry of the process, holding the pins opened on the hardware.
+    pub fn process() -> PinRegistry {
+        PROCESS_REGISTRY.get_or_init(PinRegistry::new).clone()
+    }
+
+    /// Claim a BCM pin for a driver, failing if another driver holds it in this registry.
+    ///
+    /// # Arguments
+    ///
+    /// * `pin` - BCM pin number.
+    /// * `owner` - Driver claiming the pin, such as "buzzer".
+    pub fn claim(&self, pin: u8, owner: &'static str) -> Result<PinClaim, PinInUse> {
+        if !is_check_enabled() {
+            return Ok(PinClaim { pin: None, registry: self.clone() });
+        }
+
+        let mut owners = self.lock();
+        if let Some(&owner) = owners.get(&pin) {
+            return Err(PinInUse { pin, owner });
+        }
+        owners.insert(pin, owner);
+        drop(owners);
+
+        Ok(PinClaim { pin: Some(pin), registry: self.clone() })
+    }
+
+    /// Claim several BCM pins for a driver, none being claimed if one is held by another driver.
+    ///
+    /// # Arguments
+    ///
+    /// * `pins` - BCM pin numbers.
+    /// * `owner` - Driver claiming the pins, such as "multicolour LEDs".
+    pub fn claim_all(&self, pins: &[u8], owner: &'static str) -> Result<Vec<PinClaim>, PinInUse> {
+        pins.iter().map(|&pin| self.claim(pin, owner)).collect()
+    }
+
+    /// Get the driver holding a BCM pin in this registry, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `pin` - BCM pin number.
+    pub fn owner(&self, pin: u8) -> Option<&'static str> {
+        self.lock().get(&pin).copied()
+    }
+
+    /// Locks the owners. A poisoned lock is recovered as the map is always left consistent.
+    fn lock(&self) -> MutexGuard<'_, HashMap<u8, &'static str>> {
+        self.owners.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Claim of a BCM pin by a driver, released when dropped.
+#[derive(Debug)]
+pub struct PinClaim {
+
+    /// BCM pin number, None when the check was disabled at the time of the claim.
+    pin: Option<u8>,
+
+    /// Registry the pin is recorded in.
+    registry: PinRegistry,
+}
+
+impl PinClaim {
+
+    /// Get the pin claimed, None when the check was disabled at the time of the claim.
+    pub fn pin(&self) -> Option<u8> {
+        self.pin
+    }
+}
+
+impl Drop for PinClaim {
+    fn drop(&mut self) {
+        if let Some(pin) = self.pin {
+            self.registry.lock().remove(&pin);
+        }
+    }
+}
+
+/// Claim a BCM pin for a driver in the registry of the process, failing if another driver of the process holds it.
+/// The drivers claim the pins they open on the hardware when set up, and release them with the drivers or when
+/// they are released. In simulation mode they claim their pins in the registry of their board instead, and the
+/// outputs given to the drivers are not claimed.
+///
+/// # Arguments
+///
+/// * `pin` - BCM pin number.
+/// * `owner` - Driver claiming the pin, such as "buzzer".
+pub fn claim(pin: u8, owner: &'static str) -> Result<PinClaim, PinInUse> {
+    PinRegistry::process().claim(pin, owner)
+}
+
+/// Claim several BCM pins for a driver in the registry of the process, none being claimed if one is held by
+/// another driver.
+///
+/// # Arguments
+///
+/// * `pins` - BCM pin numbers.
+/// * `owner` - Driver claiming the pins, such as "multicolour LEDs".
+pub fn claim_all(pins: &[u8], owner: &'static str) -> Result<Vec<PinClaim>, PinInUse> {
+    PinRegistry::process().claim_all(pins, owner)
+}
+
+/// Get the driver holding a BCM pin on the hardware, if any.
+///
+/// # Arguments
+///
+/// * `pin` - BCM pin number.
+pub fn owner(pin: u8) -> Option<&'static str> {
+    PinRegistry::process().owner(pin)
+}
+
+/// Check the pins claimed by the drivers or not, for the whole process. Disabling the check lets several drivers
+/// share a pin on purpose, for advanced uses such as a light driven from two places. Enabled by default.
+/// The claims made while the check is disabled are not recorded.
+///
+/// # Arguments
+///
+/// * `enabled` - false to let the drivers share the pins.
+pub fn set_check_enabled(enabled: bool) {
+    CHECK_ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Get whether the pins claimed by the drivers are checked.
+pub fn is_check_enabled() -> bool {
+    CHECK_ENABLED.load(Ordering::SeqCst)
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lock running the tests one at a time, as they use the registry and the opt-out of the process.
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Takes the lock of the tests using the registry of the process.
+    fn lock() -> MutexGuard<'static, ()> {
+        TEST_LOCK.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Tests a pin claimed twice in the registry of the process is rejected, naming its owner, until the first
+    /// claim is dropped.
+    #[test]
+    fn test_pins_conflict() {
+        let _lock = lock();
+
+        let first = claim(200, "light").unwrap();
+        assert!(first.pin() == Some(200) && owner(200) == Some("light"));
+        assert!(PinRegistry::process().owner(200) == Some("light"));
+
+        let err = claim(200, "buzzer").unwrap_err();
+        assert!(err == PinInUse { pin: 200, owner: "light" });
+        assert!(err.to_string() == "BCM 200 is already used by the light");
+
+        // Another pin is free
+        let other = claim(201, "buzzer").unwrap();
+
+        drop(first);
+        assert!(owner(200).is_none());
+        assert!(claim(200, "buzzer").is_ok());
+        drop(other);
+        assert!(owner(201).is_none());
+
+        // Claiming several pins claims none of them on a conflict
+        let held = claim(203, "light").unwrap();
+        assert!(claim_all(&[204, 203], "multicolour LEDs").unwrap_err() == PinInUse { pin: 203, owner: "light" });
+        assert!(owner(204).is_none());
+        drop(held);
+        let claims = claim_all(&[204, 203], "multicolour LEDs").unwrap();
+        assert!(claims.len() == 2 && owner(203) == Some("multicolour LEDs"));
+    }
+
+    /// Tests the check can be disabled for the whole process.
+    #[test]
+    fn test_pins_opt_out() {
+        let _lock = lock();
+
+        let first = claim(202, "light").unwrap();
+
+        set_check_enabled(false);
+        assert!(!is_check_enabled());
+        let shared = claim(202, "button").unwrap();
+        assert!(shared.pin().is_none());
+        drop(shared);
+
+        // Dropping an unrecorded claim keeps the first one
+        set_check_enabled(true);
+        assert!(owner(202) == Some("light"));
+        assert!(claim(202, "button").is_err());
+        drop(first);
+    }
+
+    /// Tests the registries created apart are independent from each other and from the one of the process.
+    #[test]
+    fn test_pins_registry() {
+        let _lock = lock();
+
+        let board = PinRegistry::new();
+        let claim = board.claim(205, "buzzer").unwrap();
+        assert!(board.clone().claim(205, "light").unwrap_err() == PinInUse { pin: 205, owner: "buzzer" });
+        assert!(PinRegistry::new().claim(205, "light").is_ok());
+        assert!(owner(205).is_none());
+        let hardware = super::claim(205, "light").unwrap();
+
+        drop(claim);
+        assert!(board.owner(205).is_none() && owner(205) == Some("light"));
+        drop(hardware);
+    }
+}
@@ -0,0 +1,16 @@
+pub use crate::Error;
+pub use crate::{APA102Error, Alphanum4Error, BMP280Error, BuzzerError, HT16K33Error, LightsError, TouchError};
+pub use crate::alphanum4::{Alignment, Alphanum4};
+pub use crate::apa102::{Color, GraphPalette, APA102};
+pub use crate::app::EventLoop;
+pub use crate::bmp280::{Measurement, BMP280};
+pub use crate::buzzer::{AlertTone, Buzzer};
+pub use crate::colors::NamedColor;
+pub use crate::ht16k33::{BlinkRate, BufferedDisplay, HT16K33};
+pub use crate::lights::{Light, Lights};
+pub use crate::rainbow::Rainbow;
+pub use crate::rainbow_hat::{RainbowHat, SharedRainbowHat};
+pub use crate::touch::{Button, ButtonId, Buttons, Gesture, TouchEvent};
+pub use crate::units::{Brightness, Pressure, Temperature};
+#[cfg(feature = "signals")]
+pub use crate::signals::install_cleanup_handler;
@@ -0,0 +1,216 @@
+use std::time::{Duration, Instant};
+use crate::apa102::{APA102, Error, NUM_PIXELS};
+use crate::clock::{Clock, SystemClock};
+
+/// Hue change per second, in degrees.
+pub const DEFAULT_SPEED: f32 = 20.0;
+
+/// Hue difference between two neighbouring pixels, in degrees.
+pub const DEFAULT_SPREAD: f32 = 10.0;
+
+/// Rainbow animation of the APA102 pixels, cycling the hue of each pixel over time.
+/// The hue is computed from the monotonic time elapsed since the first frame,
+/// so adjustments of the system wall-clock don't make the animation jump.
+#[derive(Debug)]
+pub struct Rainbow {
+
+    /// clock giving the time of each frame
+    clock: Box<dyn Clock>,
+
+    /// instant of the first frame
+    start: Option<Instant>,
+
+    /// hue change per second, in degrees
+    speed: f32,
+
+    /// hue difference between two neighbouring pixels, in degrees
+    spread: f32,
+}
+
+impl Rainbow {
+
+    /// Creates a rainbow animation using the system monotonic time.
+    pub fn new() -> Rainbow {
+        Rainbow::with_clock(SystemClock)
+    }
+
+    /// Creates a rainbow animation using the given clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Clock to use.
+    pub fn with_clock<C: Clock + 'static>(clock: C) -> Rainbow {
+        Self {
+            clock: Box::new(clock),
+            start: None,
+            speed: DEFAULT_SPEED,
+            spread: DEFAULT_SPREAD,
+        }
+    }
+
+    /// Set the hue change per second.
+    ///
+    /// # Arguments
+    ///
+    /// * `degrees_per_second` - Hue change per second, in degrees.
+    pub fn set_speed(&mut self, degrees_per_second: f32) {
+        self.speed = degrees_per_second;
+    }
+
+    /// Set the hue difference between two neighbouring pixels.
+    ///
+    /// # Arguments
+    ///
+    /// * `degrees` - Hue difference, in degrees.
+    pub fn set_spread(&mut self, degrees: f32) {
+        self.spread = degrees;
+    }
+
+    /// Get the hue of each pixel at the current time, between 0 and 360 degrees.
+    /// The time starts on the first call.
+    pub fn hues(&mut self) -> [f32; NUM_PIXELS] {
+        let now = self.clock.now();
+        let start = *self.start.get_or_insert(now);
+        self.hues_at(now - start)
+    }
+
+    /// Get the hue of each pixel after some time, between 0 and 360 degrees, for a caller keeping the time itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `elapsed` - Time elapsed since the start of the animation.
+    pub fn hues_at(&self, elapsed: Duration) -> [f32; NUM_PIXELS] {
+        let delta = elapsed.as_secs_f64() * self.speed as f64;
+
+        let mut hues = [0.0; NUM_PIXELS];
+        for (x, hue) in hues.iter_mut().enumerate() {
+            *hue = (delta + x as f64 * self.spread as f64).rem_euclid(360.0) as f32;
+        }
+        hues
+    }
+
+    /// Set the pixels to the colors of the current frame. Call `show()` on the driver to output them.
+    /// The first hue is shown on the last pixel.
+    ///
+    /// # Arguments
+    ///
+    /// * `apa102` - Driver of the pixels.
+    /// * `brightness` - Brightness of the pixels, between 0.0 and 1.0.
+    pub fn render(&mut self, apa102: &mut APA102, brightness: f32) -> Result<(), Error> {
+        let hues = self.hues();
+        Rainbow::render_hues(apa102, &hues, brightness)
+    }
+
+    /// Set the pixels to the colors after some time, as with `render`.
+    ///
+    /// # Arguments
+    ///
+    /// * `apa102` - Driver of the pixels.
+    /// * `brightness` - Brightness of the pixels, between 0.0 and 1.0.
+    /// * `elapsed` - Time elapsed since the start of the animation.
+    pub fn render_at(&self, apa102: &mut APA102, brightness: f32, elapsed: Duration) -> Result<(), Error> {
+        Rainbow::render_hues(apa102, &self.hues_at(elapsed), brightness)
+    }
+
+    /// Set the pixels to the colors of hues, the first hue being shown on the last pixel.
+    ///
+    /// # Arguments
+    ///
+    /// * `apa102` - Driver of the pixels.
+    /// * `hues` - Hue of each pixel, in degrees.
+    /// * `brightness` - Brightness of the pixels, between 0.0 and 1.0.
+    fn render_hues(apa102: &mut APA102, hues: &[f32; NUM_PIXELS], brightness: f32) -> Result<(), Error> {
+        for (x, &hue) in hues.iter().enumerate() {
+            let (r, g, b) = hsv_to_rgb(hue, 1.0, 1.0);
+            apa102.set_pixel(NUM_PIXELS - 1 - x, r, g, b, brightness)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Rainbow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Converts a color from HSV to RGB.
+///
+/// # Arguments
+///
+/// * `hue` - Hue, in degrees.
+/// * `saturation` - Saturation, between 0.0 and 1.0.
+/// * `value` - Value, between 0.0 and 1.0.
+pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> (u8, u8, u8) {
+    let hue = hue.rem_euclid(360.0) / 60.0;
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let m = value - chroma;
+
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    let to_u8 = |c: f32| ((c + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+    (to_u8(r), to_u8(g), to_u8(b))
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apa102::Error;
+    use crate::clock::ManualClock;
+
+    /// Tests the hues advance steadily with a monotonic clock, wrapping at 360 degrees.
+    #[test]
+    fn test_rainbow_hues_advance() {
+        let clock = ManualClock::new();
+        let mut rainbow = Rainbow::with_clock(clock.clone());
+
+        let mut previous = rainbow.hues();
+        assert!(previous[0] == 0.0);
+        assert!((previous[1] - DEFAULT_SPREAD).abs() < 0.001);
+
+        // 50ms per frame, advancing the hue by 1 degree, over more than a full turn
+        for _i in 0..400 {
+            clock.advance(Duration::from_millis(50));
+            let hues = rainbow.hues();
+
+            for x in 0..NUM_PIXELS {
+                let step = (hues[x] - previous[x]).rem_euclid(360.0);
+                assert!((step - 1.0).abs() < 0.001);
+            }
+            previous = hues;
+        }
+    }
+
+    /// Tests converting HSV colors to RGB.
+    #[test]
+    fn test_hsv_to_rgb() {
+        assert!(hsv_to_rgb(0.0, 1.0, 1.0) == (255, 0, 0));
+        assert!(hsv_to_rgb(120.0, 1.0, 1.0) == (0, 255, 0));
+        assert!(hsv_to_rgb(240.0, 1.0, 1.0) == (0, 0, 255));
+        assert!(hsv_to_rgb(60.0, 1.0, 1.0) == (255, 255, 0));
+        assert!(hsv_to_rgb(360.0, 1.0, 1.0) == (255, 0, 0));
+        assert!(hsv_to_rgb(0.0, 0.0, 0.5) == (128, 128, 128));
+    }
+
+    /// Tests rendering the first hue on the last pixel.
+    #[test]
+    fn test_rainbow_render() -> Result<(), Error> {
+        let mut apa102 = APA102::new()?;
+        let mut rainbow = Rainbow::with_clock(ManualClock::new());
+
+        rainbow.render(&mut apa102, 1.0)?;
+        assert!(apa102.pixels[NUM_PIXELS - 1] == [255, 0, 0, 31]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,2096 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::alphanum4::{Alignment, Alphanum4};
+use crate::apa102::{APA102, GraphPalette, GPIO_CLK, GPIO_CS, GPIO_DAT, NUM_PIXELS};
+use crate::bindings::Bindings;
+use crate::bmp280::{self, BMP280};
+use crate::buzzer::{AlertTone, Buzzer, GPIO_BUZZER};
+use crate::clock::{Clock, SystemClock};
+use crate::colors::NamedColor;
+use crate::eeprom;
+use crate::Error;
+#[cfg(feature = "hardware")]
+use crate::hal::{Backend, Rppal, SharedGpio};
+use crate::ht16k33::{self, BUFFER_SIZE, HT16K33_BLINK_1HZ, HT16K33_BLINK_2HZ, HT16K33_BLINK_HALFHZ, HT16K33_BLINK_OFF};
+use crate::error::invalid_input;
+use crate::pins::PinRegistry;
+use crate::recorder::{Entry, MemoryRecorder, Recorder};
+use crate::retry::RetryPolicy;
+use crate::lights::{Light, Lights, GPIO_LIGHT_BLUE, GPIO_LIGHT_GREEN, GPIO_LIGHT_RED};
+use crate::touch::{Button, Buttons, ButtonId, EdgeDetector, TouchEvent, GPIO_TOUCH_A, GPIO_TOUCH_B, GPIO_TOUCH_C};
+use crate::trace::SharedHook;
+use crate::units::Brightness;
+
+/// Number of times `RainbowHat::notify` flashes the multicolour LEDs and the lights.
+pub const NOTIFY_FLASHES: usize = 3;
+
+/// Duration of a frame of `RainbowHat::notify`: the flashes turn on or off and the message scrolls by a character at each frame.
+pub const NOTIFY_FRAME_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Brightness of the multicolour LEDs flashed by `RainbowHat::notify`.
+pub const NOTIFY_BRIGHTNESS: f32 = 0.5;
+
+/// Animation rendering a frame in the multicolour LEDs buffer.
+pub type Animation = Box<dyn FnMut(&mut APA102) -> Result<(), crate::apa102::Error> + Send>;
+
+/// Animation run when the buttons are not used for a while.
+struct IdleAnimation {
+
+    /// Animation rendering each frame.
+    animation: Animation,
+
+    /// Duration without press after which the animation starts.
+    timeout: Duration,
+
+    /// Instant of the last press.
+    last_activity: Instant,
+
+    /// Whether the animation is running.
+    running: bool,
+
+    /// Detects the presses of each button: A, B and C.
+    edges: [EdgeDetector; 3],
+}
+
+/// State cached by `RainbowHat::standby`, restored by `RainbowHat::wake`.
+#[derive(Debug, Clone)]
+struct Standby {
+
+    /// Outputs before the standby.
+    state: BoardState,
+
+    /// Power mode of the sensor before the standby.
+    sensor_mode: bmp280::Mode,
+}
+
+/// State of the outputs of the board that can be read back, to restore it after a crash or mirror it remotely.
+/// The buzzer is not part of it as its note cannot be read back.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BoardState {
+
+    /// Multicolour LEDs: red, green, blue and brightness from 0 to 31 of each pixel.
+    pub pixels: [[u8; 4]; NUM_PIXELS],
+
+    /// Global brightness of the multicolour LEDs: 0.0 to 1.0.
+    pub global_brightness: f32,
+
+    /// Blue, green and red LEDs.
+    pub lights: LightsState,
+
+    /// Alphanumeric display.
+    pub display: DisplayState,
+}
+
+/// State of the blue, green and red LEDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LightsState {
+
+    /// Whether the red light is on.
+    pub red: bool,
+
+    /// Whether the green light is on.
+    pub green: bool,
+
+    /// Whether the blue light is on.
+    pub blue: bool,
+}
+
+/// State of the alphanumeric display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DisplayState {
+
+    /// Text of the buffer, None when a digit is not the glyph of a character. Informative, not restored.
+    pub text: Option<String>,
+
+    /// Raw display buffer, restored as is.
+    pub buffer: [u8; BUFFER_SIZE],
+
+    /// Brightness: 0 to 15.
+    pub brightness: u8,
+
+    /// Blink frequency, as the raw blink bits of the display setup command.
+    pub blink_frequency: u8,
+
+    /// Whether the display is turned on.
+    pub on: bool,
+}
+
+/// Peripheral of the Rainbow HAT, which can be disabled with the builder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Component {
+
+    /// Multicolour LEDs.
+    Pixels,
+
+    /// Blue, green and red LEDs.
+    Lights,
+
+    /// Capacitive touch buttons.
+    Buttons,
+
+    /// Alphanumeric display.
+    Display,
+
+    /// Piezo buzzer.
+    Buzzer,
+
+    /// Temperature and pressure sensor.
+    Sensor,
+}
+
+impl fmt::Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Component::Pixels => "multicolour LEDs",
+            Component::Lights => "lights",
+            Component::Buttons => "buttons",
+            Component::Display => "display",
+            Component::Buzzer => "buzzer",
+            Component::Sensor => "sensor",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Builder of a `RainbowHat` choosing the peripherals to use, the simulation mode and the pins or addresses.
+/// The drivers of the disabled peripherals are created in simulation mode, so they never access the hardware,
+/// for example a display removed from the board.
+#[derive(Debug, Clone)]
+pub struct RainbowHatBuilder {
+
+    /// Peripherals not used.
+    disabled: Vec<Component>,
+
+    /// Whether every peripheral runs in simulation mode.
+    simulation: bool,
+
+    /// Whether the peripherals switch to simulation mode when the hardware is absent.
+    auto_simulate: bool,
+
+    /// Global brightness of the multicolour LEDs, if set.
+    apa102_brightness: Option<f32>,
+
+    /// SPI bus and clock speed writing the multicolour LEDs, None for the GPIO pins.
+    #[cfg(feature = "hardware")]
+    apa102_spi: Option<(rppal::spi::Bus, u32)>,
+
+    /// BCM pins of the red, green and blue lights.
+    light_pins: [u8; 3],
+
+    /// BCM pins of the buttons A, B and C.
+    button_pins: [u8; 3],
+
+    /// BCM pin of the buzzer.
+    buzzer_pin: u8,
+
+    /// I2C address of the display.
+    display_address: u16,
+
+    /// I2C address of the sensor.
+    sensor_address: u16,
+
+    /// I2C bus of the display and the sensor, None for the default bus.
+    i2c_bus: Option<u8>,
+
+    /// whether the Rainbow HAT must be detected from its EEPROM
+    check_hat: bool,
+
+    /// device tree directory of the HAT EEPROM
+    hat_dir: PathBuf,
+
+    /// number of attempts and initial backoff of the setup done by `build()`, if set
+    setup_retry: Option<(u32, Duration)>,
+}
+
+impl RainbowHatBuilder {
+
+    /// Creates a builder of the board with every peripheral, on the pins and addresses of the Rainbow HAT.
+    pub fn new() -> RainbowHatBuilder {
+        Self {
+            disabled: Vec::new(),
+            simulation: false,
+            auto_simulate: false,
+            apa102_brightness: None,
+            #[cfg(feature = "hardware")]
+            apa102_spi: None,
+            light_pins: [GPIO_LIGHT_RED, GPIO_LIGHT_GREEN, GPIO_LIGHT_BLUE],
+            button_pins: [GPIO_TOUCH_A, GPIO_TOUCH_B, GPIO_TOUCH_C],
+            buzzer_pin: GPIO_BUZZER,
+            display_address: ht16k33::DEFAULT_ADDRESS,
+            sensor_address: bmp280::DEFAULT_ADDRESS,
+            i2c_bus: None,
+            check_hat: false,
+            hat_dir: PathBuf::from(eeprom::HAT_DIR),
+            setup_retry: None,
+        }
+    }
+
+    /// Use a peripheral or not.
+    ///
+    /// # Arguments
+    ///
+    /// * `component` - Peripheral.
+    /// * `enabled` - true to use it, false to keep its driver in simulation mode.
+    pub fn with_component(mut self, component: Component, enabled: bool) -> Self {
+        self.disabled.retain(|&disabled| disabled != component);
+        if !enabled {
+            self.disabled.push(component);
+        }
+        self
+    }
+
+    /// Use the multicolour LEDs or not.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to use them.
+    pub fn with_pixels(self, enabled: bool) -> Self {
+        self.with_component(Component::Pixels, enabled)
+    }
+
+    /// Use the blue, green and red LEDs or not.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to use them.
+    pub fn with_lights(self, enabled: bool) -> Self {
+        self.with_component(Component::Lights, enabled)
+    }
+
+    /// Use the buttons or not.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to use them.
+    pub fn with_buttons(self, enabled: bool) -> Self {
+        self.with_component(Component::Buttons, enabled)
+    }
+
+    /// Use the alphanumeric display or not.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to use it.
+    pub fn with_display(self, enabled: bool) -> Self {
+        self.with_component(Component::Display, enabled)
+    }
+
+    /// Use the buzzer or not.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to use it.
+    pub fn with_buzzer(self, enabled: bool) -> Self {
+        self.with_component(Component::Buzzer, enabled)
+    }
+
+    /// Use the temperature and pressure sensor or not.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to use it.
+    pub fn with_sensor(self, enabled: bool) -> Self {
+        self.with_component(Component::Sensor, enabled)
+    }
+
+    /// Run every peripheral in simulation mode, never accessing the hardware.
+    ///
+    /// # Arguments
+    ///
+    /// * `simulation` - true to simulate the whole board.
+    pub fn simulation(mut self, simulation: bool) -> Self {
+        self.simulation = simulation;
+        self
+    }
+
+    /// Switch the peripherals to simulation mode when the hardware is absent, for example when running
+    /// off the Raspberry Pi, instead of returning an error on their first use.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to fall back to simulation mode.
+    pub fn auto_simulate(mut self, enabled: bool) -> Self {
+        self.auto_simulate = enabled;
+        self
+    }
+
+    /// Check the Rainbow HAT is attached by reading its EEPROM, `build()` returning `Error::HatNotDetected`
+    /// if not, or switching to simulation mode with `auto_simulate`. Not checked in simulation mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to check the HAT.
+    pub fn check_hat(mut self, enabled: bool) -> Self {
+        self.check_hat = enabled;
+        self
+    }
+
+    /// Set up every enabled peripheral in `build()`, retrying the setup of each one with an exponential backoff
+    /// while it fails with an error which plausibly resolves by itself, such as a GPIO or I2C bus busy on boot.
+    /// `build()` returns the setup errors, naming the peripherals which still failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `attempts` - Number of attempts of each setup, at least 1, checked by `build()`.
+    /// * `initial_backoff` - Wait before the first retry, doubled before each following one.
+    pub fn setup_with_retry(mut self, attempts: u32, initial_backoff: Duration) -> Self {
+        self.setup_retry = Some((attempts, initial_backoff));
+        self
+    }
+
+    /// Set the global brightness of the multicolour LEDs, scaling the brightness of every pixel.
+    ///
+    /// # Arguments
+    ///
+    /// * `brightness` - Brightness: 0.0 to 1.0, checked by `build()`.
+    pub fn apa102_brightness(mut self, brightness: f32) -> Self {
+        self.apa102_brightness = Some(brightness);
+        self
+    }
+
+    /// Write the multicolour LEDs with hardware SPI instead of the GPIO pins.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - SPI bus, the LEDs being selected by its first slave select pin.
+    /// * `clock_speed` - Clock speed in hertz, checked by `build()`.
+    #[cfg(feature = "hardware")]
+    pub fn apa102_spi(mut self, bus: rppal::spi::Bus, clock_speed: u32) -> Self {
+        self.apa102_spi = Some((bus, clock_speed));
+        self
+    }
+
+    /// Set the pins of the lights.
+    ///
+    /// # Arguments
+    ///
+    /// * `red` - BCM pin of the red light.
+    /// * `green` - BCM pin of the green light.
+    /// * `blue` - BCM pin of the blue light.
+    pub fn light_pins(mut self, red: u8, green: u8, blue: u8) -> Self {
+        self.light_pins = [red, green, blue];
+        self
+    }
+
+    /// Set the pins of the buttons.
+    ///
+    /// # Arguments
+    ///
+    /// * `a` - BCM pin of the button A.
+    /// * `b` - BCM pin of the button B.
+    /// * `c` - BCM pin of the button C.
+    pub fn button_pins(mut self, a: u8, b: u8, c: u8) -> Self {
+        self.button_pins = [a, b, c];
+        self
+    }
+
+    /// Set the pin of the buzzer.
+    ///
+    /// # Arguments
+    ///
+    /// * `pin` - BCM pin of the buzzer.
+    pub fn buzzer_pin(mut self, pin: u8) -> Self {
+        self.buzzer_pin = pin;
+        self
+    }
+
+    /// Set the I2C address of the alphanumeric display.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the display: 0x70 to 0x77, checked by `build()`.
+    pub fn display_address(mut self, address: u16) -> Self {
+        self.display_address = address;
+        self
+    }
+
+    /// Set the I2C bus of the display and the sensor.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - I2C bus number of the Raspberry Pi.
+    pub fn i2c_bus(mut self, bus: u8) -> Self {
+        self.i2c_bus = Some(bus);
+        self
+    }
+
+    /// Set the I2C address of the temperature and pressure sensor.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the sensor: 0x76 or 0x77.
+    pub fn sensor_address(mut self, address: u16) -> Self {
+        self.sensor_address = address;
+        self
+    }
+
+    /// Creates the board. The hardware of the enabled peripherals is initialized when first used.
+    pub fn build(mut self) -> Result<RainbowHat, Error> {
+        if self.check_hat && !self.simulation {
+            match eeprom::check_rainbow_hat(&self.hat_dir) {
+                Ok(_) => {}
+                Err(Error::HatNotDetected(_)) if self.auto_simulate => self.simulation = true,
+                Err(err) => return Err(err),
+            }
+        }
+
+        if let Some(brightness) = self.apa102_brightness {
+            if !(0.0..=1.0).contains(&brightness) {
+                return Err(Error::InvalidInput(invalid_input("multicolour LEDs brightness", brightness, "0.0 to 1.0")));
+            }
+        }
+
+        let setup_retry = match self.setup_retry {
+            Some((attempts, initial_backoff)) => Some(RetryPolicy::new(attempts, initial_backoff)?),
+            None => None,
+        };
+
+        #[cfg(feature = "hardware")]
+        let apa102 = match self.apa102_spi {
+            Some((bus, clock_speed)) if !self.disabled.contains(&Component::Pixels) => APA102::with_spi_bus(bus, clock_speed)?,
+            _ => APA102::new()?,
+        };
+        #[cfg(not(feature = "hardware"))]
+        let apa102 = APA102::new()?;
+
+        let [red, green, blue] = self.light_pins;
+        let [a, b, c] = self.button_pins;
+
+        let mut hat = RainbowHat {
+            apa102,
+            lights: Lights {
+                red: Light::new(red)?,
+                green: Light::new(green)?,
+                blue: Light::new(blue)?,
+            },
+            buttons: Buttons {
+                a: Button::new(a)?,
+                b: Button::new(b)?,
+                c: Button::new(c)?,
+            },
+            alphanum: Alphanum4::new_i2c(self.i2c_bus, self.display_address)?,
+            buzzer: Buzzer::with_bcm_pin(self.buzzer_pin)?,
+            bmp280: BMP280::new_i2c_bus(self.i2c_bus, self.sensor_address)?,
+            clock: Box::new(SystemClock),
+            idle: None,
+            disabled: self.disabled,
+            standby: None,
+            recorder: Box::new(MemoryRecorder::new()),
+            #[cfg(feature = "config")]
+            config_warnings: Vec::new(),
+        };
+        hat.set_recorder(MemoryRecorder::new());
+        hat.set_pin_registry(PinRegistry::new());
+        #[cfg(feature = "hardware")]
+        hat.set_backend(Rppal);
+
+        if self.simulation {
+            hat.enable_simulation();
+        }
+        hat.set_auto_simulate(self.auto_simulate);
+
+        // The drivers of the disabled peripherals never access the hardware
+        for component in hat.disabled.clone() {
+            match component {
+                Component::Pixels => hat.apa102.enable_simulation(),
+                Component::Lights => hat.lights.enable_simulation(),
+                Component::Buttons => hat.buttons.enable_simulation(),
+                Component::Display => hat.alphanum.enable_simulation(),
+                Component::Buzzer => hat.buzzer.enable_simulation(),
+                Component::Sensor => hat.bmp280.enable_simulation(),
+            }
+        }
+
+        if let Some(brightness) = self.apa102_brightness {
+            hat.apa102.set_global_brightness(brightness)?;
+        }
+
+        if let Some(policy) = setup_retry {
+            hat.setup_with_retry(policy)?;
+        }
+
+        Ok(hat)
+    }
+}
+
+impl Default for RainbowHatBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Every peripheral of the Rainbow HAT.
+/// Send but not Sync like the drivers it owns, which can be moved out to share each of them with its `into_shared`.
+/// The drivers of the peripherals disabled with the builder are in simulation mode: the fields stay usable,
+/// while the accessors such as `display()` return `Error::ComponentDisabled`.
+pub struct RainbowHat {
+
+    /// Multicolour LEDs.
+    pub apa102: APA102,
+
+    /// Blue, green and red LEDs.
+    pub lights: Lights,
+
+    /// Capacitive touch buttons.
+    pub buttons: Buttons,
+
+    /// Alphanumeric display.
+    pub alphanum: Alphanum4,
+
+    /// Piezo buzzer.
+    pub buzzer: Buzzer,
+
+    /// Temperature and pressure sensor.
+    pub bmp280: BMP280,
+
+    /// clock used to recognize the gestures and the idle time
+    clock: Box<dyn Clock>,
+
+    /// animation run when the buttons are not used, if any
+    idle: Option<IdleAnimation>,
+
+    /// peripherals disabled with the builder
+    disabled: Vec<Component>,
+
+    /// state to restore on wake, while in standby
+    standby: Option<Standby>,
+
+    /// recorder shared by the drivers, giving the timeline of the operations in simulation mode
+    recorder: Box<dyn Recorder>,
+
+    /// warnings about the configuration the board was created from
+    #[cfg(feature = "config")]
+    config_warnings: Vec<String>,
+}
+
+impl RainbowHat {
+
+    /// Creates the drivers of every peripheral. The hardware is initialized when first used.
+    pub fn new() -> Result<RainbowHat, Error> {
+        RainbowHat::builder().build()
+    }
+
+    /// Creates the drivers of every peripheral after checking the Rainbow HAT is attached by reading its EEPROM,
+    /// returning `Error::HatNotDetected` if not. Use `builder().check_hat(true)` to combine the check with
+    /// simulation or auto simulation.
+    pub fn new_checked() -> Result<RainbowHat, Error> {
+        RainbowHat::builder().check_hat(true).build()
+    }
+
+    /// Creates a builder choosing the peripherals to use, the simulation mode and the pins or addresses.
+    pub fn builder() -> RainbowHatBuilder {
+        RainbowHatBuilder::new()
+    }
+
+    /// Creates the board from a TOML configuration file of its pins and addresses.
+    /// In non strict mode, the unknown keys are ignored and reported by `config_warnings()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the configuration file.
+    #[cfg(feature = "config")]
+    pub fn from_config<P: AsRef<std::path::Path>>(path: P) -> Result<RainbowHat, Error> {
+        RainbowHat::from_loaded_config(crate::config::Config::load(path)?)
+    }
+
+    /// Creates the board from a TOML configuration of its pins and addresses.
+    /// In non strict mode, the unknown keys are ignored and reported by `config_warnings()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - Configuration in the TOML format.
+    #[cfg(feature = "config")]
+    pub fn from_config_str(text: &str) -> Result<RainbowHat, Error> {
+        RainbowHat::from_loaded_config(crate::config::Config::parse(text)?)
+    }
+
+    /// Creates the board from a parsed configuration, keeping its warnings.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Configuration parsed.
+    #[cfg(feature = "config")]
+    fn from_loaded_config(config: crate::config::Config) -> Result<RainbowHat, Error> {
+        let warnings = config.warnings().to_vec();
+        let mut hat = config.build()?;
+        hat.config_warnings = warnings;
+        Ok(hat)
+    }
+
+    /// Get the warnings about the configuration the board was created from, such as the unknown keys ignored
+    /// in non strict mode. Empty when the board was not created from a configuration.
+    #[cfg(feature = "config")]
+    pub fn config_warnings(&self) -> &[String] {
+        &self.config_warnings
+    }
+
+    /// Get whether a peripheral is used, that is not disabled with the builder.
+    ///
+    /// # Arguments
+    ///
+    /// * `component` - Peripheral.
+    pub fn is_enabled(&self, component: Component) -> bool {
+        !self.disabled.contains(&component)
+    }
+
+    /// Checks a peripheral is used.
+    ///
+    /// # Arguments
+    ///
+    /// * `component` - Peripheral.
+    fn check_enabled(&self, component: Component) -> Result<(), Error> {
+        if !self.is_enabled(component) {
+            return Err(Error::ComponentDisabled(component));
+        }
+
+        Ok(())
+    }
+
+    /// Get the multicolour LEDs, an error if they are disabled.
+    pub fn pixels(&mut self) -> Result<&mut APA102, Error> {
+        self.check_enabled(Component::Pixels)?;
+        Ok(&mut self.apa102)
+    }
+
+    /// Get the blue, green and red LEDs, an error if they are disabled.
+    pub fn lights(&mut self) -> Result<&mut Lights, Error> {
+        self.check_enabled(Component::Lights)?;
+        Ok(&mut self.lights)
+    }
+
+    /// Get the buttons, an error if they are disabled.
+    pub fn buttons(&mut self) -> Result<&mut Buttons, Error> {
+        self.check_enabled(Component::Buttons)?;
+        Ok(&mut self.buttons)
+    }
+
+    /// Get the alphanumeric display, an error if it is disabled.
+    pub fn display(&mut self) -> Result<&mut Alphanum4, Error> {
+        self.check_enabled(Component::Display)?;
+        Ok(&mut self.alphanum)
+    }
+
+    /// Get the buzzer, an error if it is disabled.
+    pub fn buzzer(&mut self) -> Result<&mut Buzzer, Error> {
+        self.check_enabled(Component::Buzzer)?;
+        Ok(&mut self.buzzer)
+    }
+
+    /// Get the temperature and pressure sensor, an error if it is disabled.
+    pub fn sensor(&mut self) -> Result<&mut BMP280, Error> {
+        self.check_enabled(Component::Sensor)?;
+        Ok(&mut self.bmp280)
+    }
+
+    /// Creates the drivers of every peripheral in simulation mode, never accessing the hardware.
+    pub fn with_simulation() -> Result<RainbowHat, Error> {
+        let mut hat = RainbowHat::new()?;
+        hat.enable_simulation();
+        Ok(hat)
+    }
+
+    /// Enables simulation mode on every peripheral.
+    pub fn enable_simulation(&mut self) {
+        self.apa102.enable_simulation();
+        self.lights.enable_simulation();
+        self.buttons.enable_simulation();
+        self.alphanum.enable_simulation();
+        self.buzzer.enable_simulation();
+        self.bmp280.enable_simulation();
+    }
+
+    /// Get whether simulation mode is enabled on every peripheral.
+    pub fn is_simulation(&self) -> bool {
+        self.apa102.is_simulation()
+            && self.lights.is_simulation()
+            && self.buttons.is_simulation()
+            && self.alphanum.is_simulation()
+            && self.buzzer.is_simulation()
+            && self.bmp280.is_simulation()
+    }
+
+    /// Switch every peripheral to simulation mode on setup when the hardware is absent, for example when running
+    /// off the Raspberry Pi, instead of returning an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to fall back to simulation mode.
+    pub fn set_auto_simulate(&mut self, enabled: bool) {
+        self.apa102.set_auto_simulate(enabled);
+        self.lights.set_auto_simulate(enabled);
+        self.buttons.set_auto_simulate(enabled);
+        self.alphanum.set_auto_simulate(enabled);
+        self.buzzer.set_auto_simulate(enabled);
+        self.bmp280.set_auto_simulate(enabled);
+    }
+
+    /// Set the backend opening the peripherals on setup. The GPIO is opened once for every driver using it,
+    /// the failure to open it being reported by each of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Backend to use, shared by the drivers.
+    #[cfg(feature = "hardware")]
+    pub fn set_backend<B: Backend + 'static>(&mut self, backend: B) {
+        let shared = SharedGpio::new(backend);
+        self.apa102.set_backend(shared.clone());
+        self.lights.set_backend(shared.clone());
+        self.buttons.set_backend(shared.clone());
+        self.alphanum.set_backend(shared.clone());
+        self.buzzer.set_backend(shared.clone());
+        self.bmp280.set_backend(shared);
+    }
+
+    /// Set the registry the pins of the GPIO drivers are claimed in when set up in simulation mode. Each board has
+    /// its own, finding two of its drivers on the same pin without conflicting with the other simulated boards.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - Registry to use, shared by the drivers.
+    pub fn set_pin_registry(&mut self, registry: PinRegistry) {
+        self.apa102.set_pin_registry(registry.clone());
+        self.lights.set_pin_registry(registry.clone());
+        self.buttons.set_pin_registry(registry.clone());
+        self.buzzer.set_pin_registry(registry);
+    }
+
+    /// Initialize every enabled peripheral now instead of on its first use, for example to report a wiring problem
+    /// on startup. Every peripheral is set up even if another one fails; the errors are returned together,
+    /// each naming the peripheral and its pins or address.
+    pub fn setup(&mut self) -> Result<(), Error> {
+        self.setup_with_retry(RetryPolicy::once())
+    }
+
+    /// Initialize every enabled peripheral now as `setup`, retrying the setup of each peripheral with the policy
+    /// while it fails with an error which plausibly resolves by itself, such as a busy GPIO or I2C bus on boot.
+    /// The errors name the peripherals which still failed after the retries. The waits use the clock of the board.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - Number of attempts and backoff.
+    pub fn setup_with_retry(&mut self, policy: RetryPolicy) -> Result<(), Error> {
+        let mut errors = Vec::new();
+        let mut check = |component: Component, location: String, result: Result<(), Error>| {
+            if let Err(err) = result {
+                errors.push(Error::ComponentSetup { component, location, source: Box::new(err) });
+            }
+        };
+        let clock = self.clock.as_ref();
+
+        if self.is_enabled(Component::Pixels) {
+            #[cfg(feature = "hardware")]
+            let location = match self.apa102.spi_bus() {
+                Some(bus) => format!("SPI bus {:?}", bus),
+                None => format!("pins {}, {} and {}", GPIO_DAT, GPIO_CLK, GPIO_CS),
+            };
+            #[cfg(not(feature = "hardware"))]
+            let location = format!("pins {}, {} and {}", GPIO_DAT, GPIO_CLK, GPIO_CS);
+            let apa102 = &mut self.apa102;
+            check(Component::Pixels, location, policy.run_with_clock(clock, |_attempt| apa102.setup()).map_err(Error::from));
+        }
+        if self.is_enabled(Component::Lights) {
+            for light in [&mut self.lights.red, &mut self.lights.green, &mut self.lights.blue] {
+                check(Component::Lights, format!("pin {}", light.bcm_pin), policy.run_with_clock(clock, |_attempt| light.setup()).map_err(Error::from));
+            }
+        }
+        if self.is_enabled(Component::Buttons) {
+            for button in [&mut self.buttons.a, &mut self.buttons.b, &mut self.buttons.c] {
+                check(Component::Buttons, format!("pin {}", button.bcm_pin()), policy.run_with_clock(clock, |_attempt| button.setup()).map_err(Error::from));
+            }
+        }
+        if self.is_enabled(Component::Display) {
+            let location = format!("I2C address 0x{:02X}", self.alphanum.ht16k33.address());
+
+            // The display remembers a failed setup, which is forgotten by the retries only
+            let display = &mut self.alphanum.ht16k33;
+            let result = policy.run_with_clock(clock, |attempt| if attempt == 0 { display.setup() } else { display.try_setup() });
+            check(Component::Display, location, result.map_err(Error::from));
+        }
+        if self.is_enabled(Component::Buzzer) {
+            let buzzer = &mut self.buzzer;
+            check(Component::Buzzer, format!("pin {}", buzzer.bcm_pin()), policy.run_with_clock(clock, |_attempt| buzzer.setup()).map_err(Error::from));
+        }
+        if self.is_enabled(Component::Sensor) {
+            let location = match self.bmp280.address() {
+                Some(address) => format!("I2C address 0x{:02X}", address),
+                None => "its bus".to_string(),
+            };
+            let bmp280 = &mut self.bmp280;
+            check(Component::Sensor, location, policy.run_with_clock(clock, |_attempt| bmp280.setup()).map_err(Error::from));
+        }
+
+        RainbowHat::combine(errors)
+    }
+
+    /// Set a hook receiving a message before each hardware operation of any peripheral,
+    /// also in simulation mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Closure receiving each message.
+    pub fn set_trace<F: FnMut(&str) + Send + 'static>(&mut self, hook: F) {
+        let shared = SharedHook::new(hook);
+        self.apa102.set_trace(shared.forward());
+        self.lights.set_trace(shared.forward());
+        self.alphanum.set_trace(shared.forward());
+        self.buzzer.set_trace(shared.forward());
+        self.bmp280.set_trace(shared.forward());
+    }
+
+    /// Set the recorder shared by the drivers in simulation mode, interleaving their operations in a single timeline.
+    /// The operations already recorded are not moved.
+    ///
+    /// # Arguments
+    ///
+    /// * `recorder` - Recorder to use, cloned for each driver.
+    pub fn set_recorder<R: Recorder + Clone + 'static>(&mut self, recorder: R) {
+        self.apa102.set_recorder(recorder.clone());
+        self.lights.set_recorder(recorder.clone());
+        self.alphanum.set_recorder(recorder.clone());
+        self.buzzer.set_recorder(recorder.clone());
+        self.bmp280.set_recorder(recorder.clone());
+        self.recorder = Box::new(recorder);
+    }
+
+    /// Get the operations of every driver in simulation mode, oldest first, such as the frames shown,
+    /// the writes to the display and the sensor, the levels of the lights and the tones of the buzzer.
+    pub fn recording(&self) -> Vec<Entry> {
+        self.recorder.entries()
+    }
+
+    /// Remove the trace hook of every peripheral.
+    pub fn clear_trace(&mut self) {
+        self.apa102.clear_trace();
+        self.lights.clear_trace();
+        self.alphanum.clear_trace();
+        self.buzzer.clear_trace();
+        self.bmp280.clear_trace();
+    }
+
+    /// Release the pins and buses of every peripheral, for example before handing the GPIO to another process,
+    /// without turning the outputs off: call `stop_all` first for that. Each peripheral is set up again on its next use.
+    pub fn release(&mut self) {
+        self.apa102.release();
+        self.lights.release();
+        self.buttons.release();
+        self.alphanum.ht16k33.release();
+        self.buzzer.release();
+        self.bmp280.release();
+    }
+
+    /// Turn everything off now: clear the multicolour LEDs, turn off the lights, silence the buzzer
+    /// and clear the display. Usable from an error path or a signal handler.
+    /// Every peripheral is turned off even if another one fails; the errors are returned together.
+    pub fn stop_all(&mut self) -> Result<(), Error> {
+        let mut errors = Vec::new();
+
+        self.apa102.clear();
+        if let Err(err) = self.apa102.show() {
+            errors.push(Error::from(err));
+        }
+
+        self.lights.all(false);
+
+        if let Err(err) = self.buzzer.stop() {
+            errors.push(Error::from(err));
+        }
+
+        self.alphanum.ht16k33.clear();
+        if let Err(err) = self.alphanum.show() {
+            errors.push(Error::from(err));
+        }
+
+        RainbowHat::combine(errors)
+    }
+
+    /// Show a percentage as a gauge, for example a CPU load or a volume: a bar graph on the multicolour LEDs
+    /// and the rounded number on the display, such as "42%". Both are shown at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `percentage` - Value of the gauge, clamped to 0.0 to 100.0.
+    /// * `palette` - Colors of the bar graph.
+    pub fn show_percentage(&mut self, percentage: f32, palette: GraphPalette) -> Result<(), Error> {
+        self.apa102.graph(percentage, palette, 1.0)?;
+        self.alphanum.print_str(&format!("{:.0}%", percentage.clamp(0.0, 100.0)), Alignment::Right)?;
+
+        self.apa102.show()?;
+        self.alphanum.show()?;
+
+        Ok(())
+    }
+
+    /// Alert the user in one call: show the message, scrolling it if longer than the display, flash the multicolour
+    /// LEDs and the lights in a color `NOTIFY_FLASHES` times, and play a tone while the first flash is lit.
+    /// The multicolour LEDs, the lights and the display are then restored as they were, even if a flash failed,
+    /// the first error being returned.
+    /// In simulation mode, the frames are not waited for.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - Message where characters should be any ASCII value 32 to 127 (printable ASCII).
+    /// * `color` - Color of the flashes, the lights showing its strongest components.
+    /// * `tone` - Tone to play.
+    pub fn notify(&mut self, message: &str, color: NamedColor, tone: AlertTone) -> Result<(), Error> {
+        let saved = self.export_state();
+
+        let flashed = self.notify_frames(message, color, tone);
+        let restored = self.apply_state(&saved);
+
+        flashed.and(restored)
+    }
+
+    /// Play the frames of `notify`, stopping at the first error.
+    fn notify_frames(&mut self, message: &str, color: NamedColor, tone: AlertTone) -> Result<(), Error> {
+        let (r, g, b) = color.rgb();
+        let (red, green, blue) = color.lights();
+
+        // A long message scrolls through once, the flashes going on meanwhile
+        let length = message.chars().count();
+        let frames = (if length > 4 { length + 4 } else { 1 }).max(NOTIFY_FLASHES * 2);
+
+        for frame in 0..frames {
+            self.alphanum.scroll_str(message, frame)?;
+
+            let lit = frame < NOTIFY_FLASHES * 2 && frame & 1 == 0;
+            if lit {
+                self.apa102.set_all(r, g, b, NOTIFY_BRIGHTNESS)?;
+            } else {
+                self.apa102.clear();
+            }
+            self.lights.rgb(red && lit, green && lit, blue && lit);
+
+            self.apa102.show()?;
+            self.alphanum.show_if_changed()?;
+
+            if frame == 0 {
+                self.buzzer.alert(tone)?;
+            }
+            if !self.is_simulation() {
+                thread::sleep(NOTIFY_FRAME_INTERVAL);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Put the board in standby, for example at night: the multicolour LEDs are cleared, the display turned off
+    /// keeping its contents, the lights turned off, the buzzer silenced and the sensor put to sleep.
+    /// The previous state is cached for `wake()`. Does nothing if already in standby.
+    /// Every peripheral is put in standby even if another one fails; the errors are returned together.
+    pub fn standby(&mut self) -> Result<(), Error> {
+        if self.standby.is_some() {
+            return Ok(());
+        }
+
+        self.standby = Some(Standby {
+            state: self.export_state(),
+            sensor_mode: self.bmp280.configuration().mode,
+        });
+
+        let mut errors = Vec::new();
+
+        self.apa102.clear();
+        if let Err(err) = self.apa102.show() {
+            errors.push(Error::from(err));
+        }
+
+        self.lights.all(false);
+
+        if let Err(err) = self.buzzer.stop() {
+            errors.push(Error::from(err));
+        }
+
+        if let Err(err) = self.alphanum.ht16k33.set_display(false) {
+            errors.push(Error::from(err));
+        }
+
+        if let Err(err) = self.bmp280.set_mode(bmp280::Mode::Sleep) {
+            errors.push(Error::from(err));
+        }
+
+        RainbowHat::combine(errors)
+    }
+
+    /// Leave the standby: restore the multicolour LEDs, the lights, the display contents and settings,
+    /// and the power mode of the sensor cached by `standby()`. Does nothing if not in standby.
+    /// On error, the board stays in standby with its cached state, so waking can be retried.
+    pub fn wake(&mut self) -> Result<(), Error> {
+        let standby = match self.standby.take() {
+            Some(standby) => standby,
+            None => return Ok(()),
+        };
+
+        let result = self.apply_state(&standby.state)
+            .and_then(|()| self.bmp280.set_mode(standby.sensor_mode).map_err(Error::from));
+        if result.is_err() {
+            self.standby = Some(standby);
+        }
+
+        result
+    }
+
+    /// Get whether the board is in standby.
+    pub fn is_standby(&self) -> bool {
+        self.standby.is_some()
+    }
+
+    /// Get the result of operations run despite the failure of others.
+    ///
+    /// # Arguments
+    ///
+    /// * `errors` - Errors of the failed operations.
+    fn combine(mut errors: Vec<Error>) -> Result<(), Error> {
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(Error::Multiple(errors)),
+        }
+    }
+
+    /// Capture the state of the multicolour LEDs, the lights and the display.
+    pub fn export_state(&self) -> BoardState {
+        let display = &self.alphanum.ht16k33;
+
+        BoardState {
+            pixels: self.apa102.pixels,
+            global_brightness: self.apa102.global_brightness(),
+            lights: LightsState {
+                red: self.lights.red.state,
+                green: self.lights.green.state,
+                blue: self.lights.blue.state,
+            },
+            display: DisplayState {
+                text: self.alphanum.read_str(),
+                buffer: display.buffer,
+                brightness: display.brightness(),
+                blink_frequency: display.blink_frequency(),
+                on: display.is_display_on(),
+            },
+        }
+    }
+
+    /// Restore a state captured with `export_state` and write it to the hardware.
+    /// The state is validated first: nothing is changed when it is invalid.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - State to restore.
+    pub fn apply_state(&mut self, state: &BoardState) -> Result<(), Error> {
+        if let Some(pixel) = state.pixels.iter().find(|pixel| pixel[3] > 31) {
+            return Err(Error::InvalidInput(format!("pixel brightness {} out of range 0 to 31", pixel[3])));
+        }
+        if !(0.0..=1.0).contains(&state.global_brightness) {
+            return Err(Error::InvalidInput(format!("global brightness {} out of range 0.0 to 1.0", state.global_brightness)));
+        }
+        if state.display.brightness > 15 {
+            return Err(Error::InvalidInput(format!("display brightness {} out of range 0 to 15", state.display.brightness)));
+        }
+        let blink_frequencies = [HT16K33_BLINK_OFF, HT16K33_BLINK_2HZ, HT16K33_BLINK_1HZ, HT16K33_BLINK_HALFHZ];
+        if !blink_frequencies.contains(&state.display.blink_frequency) {
+            return Err(Error::InvalidInput(format!("invalid blink frequency 0x{:02X}", state.display.blink_frequency)));
+        }
+
+        self.apa102.pixels = state.pixels;
+        self.apa102.set_global_brightness(state.global_brightness)?;
+        self.apa102.show()?;
+
+        self.lights.rgb(state.lights.red, state.lights.green, state.lights.blue);
+
+        let display = &mut self.alphanum.ht16k33;
+        display.buffer = state.display.buffer;
+        display.set_brightness(state.display.brightness)?;
+        display.set_blink_raw(state.display.blink_frequency)?;
+        display.set_display(state.display.on)?;
+        self.alphanum.show()?;
+
+        Ok(())
+    }
+
+    /// Set the brightness of every dimmable output at once: the global brightness of the multicolour LEDs,
+    /// shown again at once, and the brightness of the display, quantized to its 16 levels.
+    /// The lights are switched on and off without dimming and are left unchanged.
+    /// # Arguments
+    ///
+    /// * `brightness` - Brightness, for example `Brightness::percent(30)`.
+    pub fn set_brightness<B: Into<Brightness>>(&mut self, brightness: B) -> Result<(), Error> {
+        let brightness = brightness.into();
+
+        self.apa102.dim_to(brightness);
+        self.apa102.show()?;
+        self.alphanum.ht16k33.dim_to(brightness)?;
+
+        Ok(())
+    }
+
+    /// Set the clock used to recognize the gestures and the idle time.
+    /// # Arguments
+    ///
+    /// * `clock` - Clock to use.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Box::new(clock);
+    }
+
+    /// Run an animation on the multicolour LEDs when no button is pressed for a while, for example on a kiosk.
+    /// `tick()` must be called regularly to check the buttons and render the frames.
+    /// # Arguments
+    ///
+    /// * `animation` - Closure rendering a frame in the buffer, shown after it returns, its error being returned by `tick()`.
+    /// * `timeout` - Duration without press after which the animation starts.
+    pub fn set_idle_animation<F>(&mut self, animation: F, timeout: Duration)
+    where
+        F: FnMut(&mut APA102) -> Result<(), crate::apa102::Error> + Send + 'static,
+    {
+        self.idle = Some(IdleAnimation {
+            animation: Box::new(animation),
+            timeout,
+            last_activity: self.clock.now(),
+            running: false,
+            edges: [EdgeDetector::new(); 3],
+        });
+    }
+
+    /// Remove the idle animation. The multicolour LEDs are left as they are.
+    pub fn clear_idle_animation(&mut self) {
+        self.idle = None;
+    }
+
+    /// Get whether the idle animation is running.
+    pub fn is_idle(&self) -> bool {
+        self.idle.as_ref().is_some_and(|idle| idle.running)
+    }
+
+    /// Read the buttons once and update the idle animation: a press stops it and clears the multicolour LEDs,
+    /// otherwise it starts once the timeout has elapsed since the last press, rendering a frame on each call.
+    pub fn tick(&mut self) -> Result<(), Error> {
+        let now = self.clock.now();
+        let idle = match self.idle.as_mut() {
+            Some(idle) => idle,
+            None => return Ok(()),
+        };
+
+        let mut pressed = false;
+        for (i, &id) in [ButtonId::A, ButtonId::B, ButtonId::C].iter().enumerate() {
+            let state = self.buttons.get_mut(id).is_pressed();
+            if let Some(TouchEvent::Pressed) = idle.edges[i].update(state) {
+                pressed = true;
+            }
+        }
+
+        if pressed {
+            idle.last_activity = now;
+            if idle.running {
+                idle.running = false;
+                self.apa102.clear();
+                self.apa102.show()?;
+            }
+        } else if now.duration_since(idle.last_activity) >= idle.timeout {
+            idle.running = true;
+            (idle.animation)(&mut self.apa102)?;
+            self.apa102.show()?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the buttons once and run the actions bound to the recognized gestures.
+    /// Returns the number of actions run.
+    /// # Arguments
+    ///
+    /// * `bindings` - Actions bound to the gestures.
+    pub fn poll_bindings(&mut self, bindings: &mut Bindings) -> usize {
+        let now = self.clock.now();
+        let mut count = 0;
+
+        for &id in [ButtonId::A, ButtonId::B, ButtonId::C].iter() {
+            let pressed = self.buttons.get_mut(id).is_pressed();
+
+            if let Some(gesture) = bindings.detector_mut(id).update(pressed, now) {
+                if bindings.run(self, id, gesture) {
+                    count += 1;
+                }
+            }
+        }
+
+        count
+    }
+
+    /// Poll the buttons and run the actions bound to the recognized gestures until the stop condition is met.
+    /// The stop condition is checked before each reading.
+    /// # Arguments
+    ///
+    /// * `bindings` - Actions bound to the gestures.
+    /// * `interval` - Interval between two readings of the buttons.
+    /// * `should_stop` - Stop condition, receiving the board.
+    pub fn run_bindings<S>(&mut self, bindings: &mut Bindings, interval: Duration, mut should_stop: S)
+    where
+        S: FnMut(&mut RainbowHat) -> bool,
+    {
+        while !should_stop(self) {
+            self.poll_bindings(bindings);
+            thread::sleep(interval);
+        }
+    }
+
+    /// Move the board behind a mutex so it can be shared between threads, for example with a cleanup handler.
+    pub fn into_shared(self) -> SharedRainbowHat {
+        SharedRainbowHat {
+            inner: Arc::new(Mutex::new(self)),
+        }
+    }
+}
+
+/// Handle to a board shared between threads, Send and Sync. Clones refer to the same board.
+#[derive(Clone)]
+pub struct SharedRainbowHat {
+
+    /// Shared board.
+    inner: Arc<Mutex<RainbowHat>>,
+}
+
+impl SharedRainbowHat {
+
+    /// Locks the board. A poisoned lock is recovered so the outputs can still be turned off after a panic.
+    fn lock(&self) -> MutexGuard<'_, RainbowHat> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Run a closure with exclusive access to the board.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - Closure receiving the board.
+    pub fn with_board<R, F: FnOnce(&mut RainbowHat) -> R>(&self, f: F) -> R {
+        f(&mut self.lock())
+    }
+
+    /// Turn every output off, as with `RainbowHat::stop_all`: every peripheral is turned off even if another one fails.
+    /// To be called when the application ends, for example from a signal handler.
+    pub fn cleanup(&self) -> Result<(), Error> {
+        self.lock().stop_all()
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphanum4::Alignment;
+    use crate::clock::ManualClock;
+    use crate::recorder::Payload;
+
+    /// Set a state on every output that can be read back.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board to set.
+    fn set_sample_state(hat: &mut RainbowHat) -> Result<(), Error> {
+        hat.apa102.set_pixel(0, 255, 0, 0, 1.0)?;
+        hat.apa102.set_pixel(6, 0, 128, 255, 1.0)?;
+        hat.apa102.pixels[3] = [10, 20, 30, 16];
+        hat.apa102.set_global_brightness(0.5)?;
+        hat.lights.rgb(true, false, true);
+        hat.alphanum.print_str("HI", Alignment::Right)?;
+        hat.alphanum.set_decimal(3, true)?;
+        hat.alphanum.ht16k33.set_brightness(7)?;
+        hat.alphanum.ht16k33.set_blink(crate::ht16k33::BlinkRate::OneHz)?;
+
+        Ok(())
+    }
+
+    /// Tests exporting the state of a board and restoring it on another one.
+    #[test]
+    fn test_rainbow_hat_state_round_trip() -> Result<(), Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+        set_sample_state(&mut hat)?;
+
+        let state = hat.export_state();
+        assert!(state.pixels[3] == [10, 20, 30, 16]);
+        assert!(state.lights == LightsState { red: true, green: false, blue: true });
+        assert!(state.display.text.as_deref() == Some("  HI"));
+        assert!(state.display.brightness == 7 && state.display.blink_frequency == HT16K33_BLINK_1HZ && state.display.on);
+
+        let mut restored = RainbowHat::with_simulation()?;
+        restored.apply_state(&state)?;
+        assert!(restored.export_state() == state);
+
+        // The state is written to the hardware
+        assert!(restored.apa102.last_frame().unwrap()[0][3] == 255);
+        assert!(restored.alphanum.ht16k33.transcript().iter().any(|(_command, data)| data[..] == state.display.buffer[..]));
+
+        Ok(())
+    }
+
+    /// Tests an invalid state is rejected without changing the board.
+    #[test]
+    fn test_rainbow_hat_apply_invalid_state() -> Result<(), Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+        set_sample_state(&mut hat)?;
+        let state = hat.export_state();
+
+        let mut invalid_states = [state.clone(), state.clone(), state.clone(), state.clone()];
+        invalid_states[0].pixels[1][3] = 32;
+        invalid_states[1].global_brightness = 1.5;
+        invalid_states[2].display.brightness = 16;
+        invalid_states[3].display.blink_frequency = 0x03;
+
+        let mut restored = RainbowHat::with_simulation()?;
+        let blank = restored.export_state();
+        for invalid in invalid_states.iter() {
+            assert!(matches!(restored.apply_state(invalid), Err(Error::InvalidInput(_))));
+            assert!(restored.export_state() == blank);
+        }
+
+        Ok(())
+    }
+
+    /// Tests the JSON format of the state against a golden file, to catch accidental format changes.
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_rainbow_hat_state_json() -> Result<(), Error> {
+        let golden = include_str!("../tests/data/board_state.json");
+
+        let mut hat = RainbowHat::with_simulation()?;
+        set_sample_state(&mut hat)?;
+        assert!(serde_json::to_string_pretty(&hat.export_state()).unwrap() == golden.trim_end());
+
+        let state: BoardState = serde_json::from_str(golden).unwrap();
+        assert!(state == hat.export_state());
+
+        Ok(())
+    }
+
+    /// Tests turning every peripheral off at once.
+    #[test]
+    fn test_rainbow_hat_stop_all() -> Result<(), Error> {
+        let mut hat = RainbowHat::new()?;
+        hat.enable_simulation();
+
+        hat.apa102.set_all(255, 128, 0, 1.0)?;
+        hat.apa102.show()?;
+        hat.lights.rgb(true, true, true);
+        hat.alphanum.print_str("STOP", Alignment::Left)?;
+        hat.alphanum.set_decimal_all(true);
+        hat.alphanum.show()?;
+        hat.buzzer.note(440.0, 0.0)?;
+
+        hat.stop_all()?;
+
+        for pixel in hat.apa102.pixels.iter() {
+            assert!(pixel[0..3] == [0, 0, 0]);
+        }
+        for pixel in hat.apa102.last_frame().unwrap().iter() {
+            assert!(pixel[1..4] == [0, 0, 0]);
+        }
+
+        assert!(!hat.lights.red.state);
+        assert!(!hat.lights.green.state);
+        assert!(!hat.lights.blue.state);
+
+        assert!(hat.alphanum.ht16k33.buffer.iter().all(|&b| b == 0));
+        let (_command, data) = hat.alphanum.ht16k33.transcript().pop().unwrap();
+        assert!(data.iter().all(|&b| b == 0));
+
+        Ok(())
+    }
+
+    /// Tests the state of the board round-trips through a standby.
+    #[test]
+    fn test_rainbow_hat_standby_wake() -> Result<(), Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+
+        // Waking without standby changes nothing
+        hat.wake()?;
+        assert!(!hat.is_standby());
+
+        set_sample_state(&mut hat)?;
+        hat.bmp280.set_mode(bmp280::Mode::Normal)?;
+        hat.apa102.show()?;
+        hat.alphanum.show()?;
+        let before = hat.export_state();
+
+        hat.standby()?;
+        assert!(hat.is_standby());
+        assert!(hat.apa102.last_frame().unwrap().iter().all(|pixel| pixel[1..4] == [0, 0, 0]));
+        assert!(!hat.lights.red.state && !hat.lights.blue.state);
+        assert!(!hat.alphanum.ht16k33.is_display_on());
+        assert!(hat.alphanum.ht16k33.buffer == before.display.buffer);
+        assert!(hat.bmp280.configuration().mode == bmp280::Mode::Sleep);
+
+        // A second standby keeps the state cached by the first one
+        hat.standby()?;
+        hat.wake()?;
+        assert!(!hat.is_standby());
+        assert!(hat.export_state() == before);
+        assert!(hat.bmp280.configuration().mode == bmp280::Mode::Normal);
+        assert!(hat.apa102.last_frame().unwrap()[0][1..4] == [0, 0, 255]);
+
+        Ok(())
+    }
+
+    /// Tests a failed wake keeps the board in standby with its cached state, a retry restoring it.
+    #[test]
+    fn test_rainbow_hat_wake_error() -> Result<(), Error> {
+        let registry = PinRegistry::new();
+        let mut hat = RainbowHat::with_simulation()?;
+        hat.set_pin_registry(registry.clone());
+        set_sample_state(&mut hat)?;
+        hat.bmp280.set_mode(bmp280::Mode::Normal)?;
+        hat.apa102.show()?;
+        let before = hat.export_state();
+        hat.standby()?;
+
+        // The multicolour LEDs failing to set up again on a pin taken meanwhile
+        hat.release();
+        let claim = registry.claim(GPIO_DAT, "test")?;
+        assert!(matches!(hat.wake(), Err(Error::PinAlreadyInUse { pin: GPIO_DAT, owner: "test" })));
+        assert!(hat.is_standby());
+
+        drop(claim);
+        hat.wake()?;
+        assert!(!hat.is_standby());
+        assert!(hat.export_state() == before);
+        assert!(hat.bmp280.configuration().mode == bmp280::Mode::Normal);
+
+        Ok(())
+    }
+
+    /// Tests the operations of every driver are interleaved in a single timeline.
+    #[test]
+    fn test_rainbow_hat_recording() -> Result<(), Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+        let position = |hat: &RainbowHat, component: Component, operation: &str| hat.recording().iter()
+            .position(|entry| entry.component == component && entry.operation == operation);
+
+        hat.alphanum.print_str("BEEP", Alignment::Left)?;
+        hat.alphanum.show()?;
+        hat.buzzer.note(880.0, 0.1)?;
+        hat.lights.red.write(true);
+
+        // The display was updated before the buzzer beeped, then the red light turned on
+        let display = position(&hat, Component::Display, "write").unwrap();
+        let buzzer = position(&hat, Component::Buzzer, "note").unwrap();
+        let light = position(&hat, Component::Lights, "set").unwrap();
+        assert!(display < buzzer && buzzer < light);
+
+        let recording = hat.recording();
+        assert!(recording[buzzer].payload == Payload::Tone { frequency: 880.0, duration: Duration::from_millis(100) });
+        assert!(recording[light].payload == Payload::Level { pin: GPIO_LIGHT_RED, high: true });
+        assert!(recording.windows(2).all(|pair| pair[0].timestamp <= pair[1].timestamp));
+
+        // The transcripts of the drivers are views of the shared timeline
+        let writes = recording.iter().filter(|entry| entry.component == Component::Display).count();
+        assert!(hat.alphanum.ht16k33.transcript().len() == writes);
+        hat.alphanum.ht16k33.clear_transcript();
+        assert!(hat.recording().len() == recording.len() - writes);
+
+        // A shared recorder given afterwards receives the next operations
+        let recorder = MemoryRecorder::new();
+        hat.set_recorder(recorder.clone());
+        hat.apa102.show()?;
+        assert!(recorder.len() == 1 && hat.recording()[0].component == Component::Pixels);
+
+        Ok(())
+    }
+
+    /// Tests a percentage is shown on the multicolour LEDs and the display.
+    #[test]
+    fn test_rainbow_hat_show_percentage() -> Result<(), Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+
+        hat.show_percentage(49.9, GraphPalette::Thresholds)?;
+        assert!(hat.alphanum.read_str().as_deref() == Some(" 50%"));
+        assert!(hat.apa102.last_frame().unwrap()[2][1..4] == [0, 255, 0]);
+        assert!(hat.apa102.last_frame().unwrap()[3][1..4] == [0, 0, 0]);
+
+        hat.show_percentage(101.0, GraphPalette::Thresholds)?;
+        assert!(hat.alphanum.read_str().as_deref() == Some("100%"));
+        assert!(hat.apa102.last_frame().unwrap().iter().all(|pixel| pixel[1..4] == [0, 0, 255]));
+
+        Ok(())
+    }
+
+    /// Tests creating the board in simulation mode.
+    #[test]
+    fn test_rainbow_hat_with_simulation() -> Result<(), Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+
+        hat.apa102.show()?;
+        hat.alphanum.show()?;
+        hat.buzzer.note(440.0, 0.0)?;
+        assert!(hat.apa102.last_frame().is_some());
+        assert!(!hat.alphanum.ht16k33.transcript().is_empty());
+
+        Ok(())
+    }
+
+    /// Tests the idle animation starts after the timeout and stops on a press.
+    #[test]
+    fn test_rainbow_hat_idle_animation() -> Result<(), Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+        let clock = ManualClock::new();
+        hat.set_clock(clock.clone());
+
+        hat.set_idle_animation(|apa102| apa102.set_all(0, 0, 255, 1.0), Duration::from_secs(30));
+
+        hat.tick()?;
+        clock.advance(Duration::from_secs(29));
+        hat.tick()?;
+        assert!(!hat.is_idle());
+        assert!(hat.apa102.last_frame().is_none());
+
+        clock.advance(Duration::from_secs(1));
+        hat.tick()?;
+        assert!(hat.is_idle());
+        assert!(hat.apa102.last_frame().unwrap().iter().all(|pixel| *pixel == [31, 255, 0, 0]));
+
+        // A press stops the animation and restarts the timeout
+        hat.buttons.b.set_simulated_state(true);
+        hat.tick()?;
+        assert!(!hat.is_idle());
+        assert!(hat.apa102.last_frame().unwrap().iter().all(|pixel| pixel[1..4] == [0, 0, 0]));
+
+        // Held, but not pressed again
+        clock.advance(Duration::from_secs(10));
+        hat.tick()?;
+        assert!(!hat.is_idle());
+
+        hat.buttons.b.set_simulated_state(false);
+        clock.advance(Duration::from_secs(20));
+        hat.tick()?;
+        assert!(hat.is_idle());
+
+        Ok(())
+    }
+
+    /// Tests enabling simulation mode on every peripheral.
+    #[test]
+    fn test_rainbow_hat_enable_simulation() -> Result<(), Error> {
+        let mut hat = RainbowHat::new()?;
+        assert!(hat.is_simulation() != cfg!(feature = "hardware"));
+
+        hat.enable_simulation();
+        assert!(hat.is_simulation());
+        assert!(RainbowHat::with_simulation()?.is_simulation());
+
+        Ok(())
+    }
+
+    /// Tests building a board without the display.
+    #[test]
+    fn test_rainbow_hat_builder_without_display() -> Result<(), Error> {
+        let mut hat = RainbowHat::builder().with_display(false).with_sensor(true).build()?;
+
+        assert!(!hat.is_enabled(Component::Display));
+        assert!(hat.is_enabled(Component::Sensor));
+        assert!(hat.alphanum.is_simulation());
+        assert!(matches!(hat.display(), Err(Error::ComponentDisabled(Component::Display))));
+        assert!(hat.sensor().is_ok());
+        assert!(Error::ComponentDisabled(Component::Display).to_string() == "Component disabled: display");
+
+        // Enabled again
+        let mut hat = RainbowHat::builder().with_display(false).with_display(true).simulation(true).build()?;
+        assert!(hat.display().is_ok());
+
+        Ok(())
+    }
+
+    /// Tests building a board in simulation mode with custom settings.
+    #[test]
+    fn test_rainbow_hat_builder_simulation() -> Result<(), Error> {
+        let mut hat = RainbowHat::builder()
+            .simulation(true)
+            .apa102_brightness(0.3)
+            .light_pins(5, 6, 13)
+            .button_pins(17, 27, 22)
+            .build()?;
+
+        assert!(hat.is_simulation());
+        assert!(hat.apa102.global_brightness() == 0.3);
+        assert!(hat.lights.red.bcm_pin == 5 && hat.lights.green.bcm_pin == 6 && hat.lights.blue.bcm_pin == 13);
+        hat.pixels()?.set_all(255, 0, 0, 1.0)?;
+        hat.pixels()?.show()?;
+        assert!(hat.apa102.last_frame().is_some());
+
+        assert!(matches!(RainbowHat::builder().apa102_brightness(1.5).build(), Err(Error::InvalidInput(_))));
+
+        Ok(())
+    }
+
+    /// Tests the simulated boards claim their pins apart from each other, while two drivers of the same board on a pin
+    /// are rejected.
+    #[test]
+    fn test_rainbow_hat_pin_in_use() -> Result<(), Error> {
+        let mut first = RainbowHat::with_simulation()?;
+        let mut second = RainbowHat::with_simulation()?;
+        first.setup()?;
+        second.setup()?;
+        second.apa102.show()?;
+
+        // The blue light rewired to the pin of the buzzer
+        let mut hat = RainbowHat::builder().simulation(true).light_pins(5, 6, GPIO_BUZZER).build()?;
+        let err = hat.setup().unwrap_err();
+        assert!(err.to_string() == "Setup of the buzzer on pin 13 failed: BCM 13 is already used by the light");
+        assert!(matches!(hat.buzzer.note(440.0, 0.0), Err(crate::buzzer::Error::PinAlreadyInUse { pin: GPIO_BUZZER, owner: "light" })));
+
+        Ok(())
+    }
+
+    /// Tests a single trace hook receives the messages of every peripheral.
+    #[test]
+    fn test_rainbow_hat_trace() -> Result<(), Error> {
+        let messages = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut hat = RainbowHat::with_simulation()?;
+        hat.alphanum.ht16k33.setup()?;
+
+        let received = messages.clone();
+        hat.set_trace(move |message| received.lock().unwrap().push(message.to_string()));
+        hat.apa102.show()?;
+        hat.lights.red.on();
+        hat.alphanum.show()?;
+
+        assert!(*messages.lock().unwrap() == [
+            "APA102 show: 7 pixels",
+            "Light 6: on",
+            "HT16K33 write_display: 16 bytes",
+        ]);
+
+        Ok(())
+    }
+
+    /// Tests a small app on tokio: the LEDs and the display are animated concurrently
+    /// until a button press, answered by a beep.
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_rainbow_hat_async_app() -> Result<(), Error> {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use crate::hal::DigitalInput;
+        use crate::touch::Button;
+
+        /// Input released until the test presses it.
+        #[derive(Debug)]
+        struct SharedInput(Arc<AtomicBool>);
+
+        impl DigitalInput for SharedInput {
+            fn is_high(&self) -> bool {
+                self.0.load(Ordering::SeqCst)
+            }
+        }
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        let tick = Duration::from_millis(1);
+        let released = Arc::new(AtomicBool::new(true));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let mut apa102 = APA102::with_simulation()?;
+        let mut alphanum = Alphanum4::with_simulation()?;
+        let mut buzzer = Buzzer::with_simulation()?;
+        let mut bmp280 = BMP280::with_simulation()?;
+        let mut button = Button::with_pin(crate::touch::GPIO_TOUCH_A, SharedInput(released.clone()));
+
+        let (apa102, alphanum, frames) = runtime.block_on(async {
+            let stop = done.clone();
+            let leds = tokio::spawn(async move {
+                let mut frames = 0;
+                while !stop.load(Ordering::SeqCst) {
+                    apa102.clear();
+                    apa102.set_pixel(frames % 7, 255, 0, 0, 1.0)?;
+                    apa102.show_async().await?;
+                    frames += 1;
+                    tokio::time::sleep(tick).await;
+                }
+                Ok::<_, Error>((apa102, frames))
+            });
+
+            let stop = done.clone();
+            let display = tokio::spawn(async move {
+                let mut count = 0;
+                while !stop.load(Ordering::SeqCst) {
+                    alphanum.print_str(&format!("{:>4}", count % 10_000), Alignment::Left)?;
+                    alphanum.show_async().await?;
+                    count += 1;
+                    tokio::time::sleep(tick).await;
+                }
+                Ok::<_, Error>(alphanum)
+            });
+
+            let stop = done.clone();
+            let input = tokio::spawn(async move {
+                button.wait_for_press_async(tick).await;
+                buzzer.note_async(440.0, 0.01).await?;
+                let measurement = bmp280.measure_once_async().await?;
+                stop.store(true, Ordering::SeqCst);
+                Ok::<_, Error>(measurement)
+            });
+
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            released.store(false, Ordering::SeqCst);
+
+            input.await.unwrap()?;
+            let (apa102, frames) = leds.await.unwrap()?;
+            let alphanum = display.await.unwrap()?;
+
+            Ok::<_, Error>((apa102, alphanum, frames))
+        })?;
+
+        assert!(done.load(Ordering::SeqCst));
+        assert!(frames > 1);
+        assert!(apa102.last_frame().unwrap().iter().filter(|pixel| pixel[3] == 255).count() == 1);
+        assert!(alphanum.ht16k33.transcript().len() > 1);
+
+        Ok(())
+    }
+
+    /// Tests every output is turned off even when other peripherals fail, the errors being returned together.
+    #[test]
+    fn test_rainbow_hat_stop_all_failures() -> Result<(), Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+        hat.buzzer = Buzzer::with_pin(FailingTone);
+        hat.alphanum.ht16k33 = crate::ht16k33::HT16K33::with_bus(FailingBus);
+
+        hat.apa102.set_all(255, 0, 0, 1.0)?;
+        hat.lights.rgb(true, true, true);
+
+        assert!(matches!(hat.stop_all(), Err(Error::Multiple(errors)) if errors.len() == 2));
+        assert!(hat.apa102.last_frame().unwrap().iter().all(|pixel| pixel[1..4] == [0, 0, 0]));
+        assert!(!hat.lights.red.state && !hat.lights.green.state && !hat.lights.blue.state);
+
+        Ok(())
+    }
+
+    /// Tests a notification drives the display, the multicolour LEDs, the lights and the buzzer, then restores the board.
+    #[test]
+    fn test_rainbow_hat_notify() -> Result<(), Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+        set_sample_state(&mut hat)?;
+        hat.apa102.show()?;
+        let before = hat.export_state();
+        let recorder = MemoryRecorder::new();
+        hat.set_recorder(recorder.clone());
+
+        hat.notify("HELLO", NamedColor::Amber, AlertTone::Chime)?;
+        let entries = recorder.entries();
+
+        // The message scrolls through once
+        let mut expected = Alphanum4::with_simulation()?;
+        let windows = (0..9).map(|step| {
+            expected.scroll_str("HELLO", step)?;
+            Ok(expected.ht16k33.buffer.to_vec())
+        }).collect::<Result<Vec<_>, Error>>()?;
+        let written: Vec<Vec<u8>> = entries.iter().filter_map(|entry| match &entry.payload {
+            Payload::Write { register: 0, data } if entry.component == Component::Display => Some(data.clone()),
+            _ => None,
+        }).collect();
+        assert!(written[..9] == windows[..]);
+
+        // Three amber flashes on the multicolour LEDs, the red and green lights following them
+        let frames: Vec<&Vec<[u8; 4]>> = entries.iter().filter_map(|entry| match &entry.payload {
+            Payload::Frame(frame) => Some(frame),
+            _ => None,
+        }).collect();
+        let lit: Vec<bool> = frames[..9].iter().map(|frame| frame.iter().all(|pixel| pixel[1..4] == [0, 191, 255])).collect();
+        assert!(lit == [true, false, true, false, true, false, false, false, false]);
+        assert!(frames[..9].iter().zip(lit.iter()).all(|(frame, &lit)| lit || frame.iter().all(|pixel| pixel[1..4] == [0, 0, 0])));
+
+        let levels = |pin: u8| entries.iter().filter(|entry| entry.payload == Payload::Level { pin, high: true }).count();
+        assert!(levels(GPIO_LIGHT_RED) == 3 + 1 && levels(GPIO_LIGHT_GREEN) == 3 && levels(GPIO_LIGHT_BLUE) == 1);
+
+        // The chime during the first flash
+        let tones: Vec<(u32, Duration)> = entries.iter().filter_map(|entry| match entry.payload {
+            Payload::Tone { frequency, duration } => Some((frequency.round() as u32, duration)),
+            _ => None,
+        }).collect();
+        assert!(tones == [(659, Duration::from_millis(120)), (784, Duration::from_millis(120)), (1047, Duration::from_millis(120))]);
+        let first_tone = entries.iter().position(|entry| entry.component == Component::Buzzer).unwrap();
+        assert!(entries[..first_tone].iter().filter(|entry| entry.component == Component::Pixels).count() == 1);
+
+        assert!(hat.export_state() == before);
+        assert!(matches!(hat.notify("\u{e9}t\u{e9}", NamedColor::Red, AlertTone::Beep), Err(Error::InvalidInput(_))));
+        assert!(hat.export_state() == before);
+
+        Ok(())
+    }
+
+    /// Tests the state is restored when a flash fails, the error of the flash being returned.
+    #[test]
+    fn test_rainbow_hat_notify_error() -> Result<(), Error> {
+        // The blue light rewired to the pin of the buzzer, the buzzer failing to set up
+        let mut hat = RainbowHat::builder().simulation(true).light_pins(5, 6, GPIO_BUZZER).build()?;
+        set_sample_state(&mut hat)?;
+        hat.apa102.show()?;
+        let before = hat.export_state();
+
+        let err = hat.notify("HI", NamedColor::Amber, AlertTone::Beep).unwrap_err();
+        assert!(matches!(err, Error::PinAlreadyInUse { pin: GPIO_BUZZER, owner: "light" }));
+
+        // The alert fails during the first flash, the previous state being shown again
+        assert!(hat.export_state() == before);
+        assert!(hat.apa102.get_pixel(0)?.0 == 255);
+
+        Ok(())
+    }
+
+    /// Tests releasing the peripherals sets them up again on their next use.
+    #[test]
+    fn test_rainbow_hat_release() -> Result<(), Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+        hat.setup()?;
+        hat.alphanum.print_str("REL", Alignment::Left)?;
+        hat.alphanum.show()?;
+        assert!(hat.alphanum.ht16k33.is_setup());
+
+        hat.release();
+        assert!(!hat.alphanum.ht16k33.is_setup());
+
+        // The display is configured again from the cached state before the next write
+        hat.alphanum.ht16k33.clear_transcript();
+        hat.alphanum.show()?;
+        assert!(hat.alphanum.ht16k33.is_setup());
+        assert!(matches!(hat.alphanum.ht16k33.commands().iter().next(), Some(crate::ht16k33::Command::SystemSetup { oscillator: true })));
+        assert!(hat.alphanum.read_str().as_deref() == Some("REL "));
+
+        Ok(())
+    }
+
+    /// Tests dimming the multicolour LEDs and the display together.
+    #[test]
+    fn test_rainbow_hat_set_brightness() -> Result<(), Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+        hat.apa102.set_pixel(0, 255, 0, 0, 1.0)?;
+        hat.lights.red.on();
+
+        hat.set_brightness(Brightness::percent(30))?;
+        assert!((hat.apa102.global_brightness() - 0.3).abs() < 0.001);
+        assert!(hat.alphanum.ht16k33.brightness() == 5);
+        assert!(hat.lights.red.state);
+
+        // The pixels are shown again with the new brightness
+        let frame = hat.recording().into_iter().rev().find(|entry| entry.component == Component::Pixels).unwrap();
+        assert!(matches!(frame.payload, Payload::Frame(pixels) if pixels[0] == [9, 0, 0, 255]));
+
+        hat.set_brightness(Brightness::MAX)?;
+        assert!(hat.apa102.global_brightness() == 1.0 && hat.alphanum.ht16k33.brightness() == 15);
+
+        Ok(())
+    }
+
+    /// Output failing to play or stop any tone.
+    #[derive(Debug)]
+    struct FailingTone;
+
+    impl crate::hal::ToneOutput for FailingTone {
+        fn start_tone(&mut self, _frequency: f64) -> Result<(), crate::buzzer::Error> {
+            Err(crate::buzzer::Error::InvalidArgument("disconnected".to_string()))
+        }
+
+        fn stop_tone(&mut self) -> Result<(), crate::buzzer::Error> {
+            Err(crate::buzzer::Error::InvalidArgument("disconnected".to_string()))
+        }
+    }
+
+    /// Bus failing every write.
+    #[derive(Debug)]
+    struct FailingBus;
+
+    impl crate::hal::I2cBus for FailingBus {
+        fn block_write(&mut self, _command: u8, _buffer: &[u8]) -> Result<(), crate::ht16k33::Error> {
+            Err(crate::ht16k33::Error::from(std::io::Error::from_raw_os_error(121)))
+        }
+    }
+
+    /// Tests the board checks the HAT is attached, unless simulating.
+    #[test]
+    fn test_rainbow_hat_check_hat() -> Result<(), Error> {
+        let mut builder = RainbowHat::builder().check_hat(true);
+        builder.hat_dir = std::env::temp_dir().join(format!("rainbow-hat-rs-{}-no-hat", std::process::id()));
+        let absent = builder.hat_dir.clone();
+
+        assert!(matches!(builder.build(), Err(Error::HatNotDetected(msg)) if msg.starts_with("no HAT EEPROM")));
+
+        let mut builder = RainbowHat::builder().check_hat(true).simulation(true);
+        builder.hat_dir = absent.clone();
+        assert!(builder.build()?.buzzer.is_simulation());
+
+        let mut builder = RainbowHat::builder().check_hat(true).auto_simulate(true);
+        builder.hat_dir = absent;
+        let hat = builder.build()?;
+        assert!(hat.apa102.is_simulation() && hat.lights.red.is_simulation() && hat.bmp280.is_simulation());
+
+        Ok(())
+    }
+
+    /// Backend failing to open the peripherals, counting the GPIO openings.
+    #[cfg(feature = "hardware")]
+    #[derive(Debug, Default)]
+    struct CountingBackend {
+        openings: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    #[cfg(feature = "hardware")]
+    impl Backend for CountingBackend {
+        fn gpio(&self) -> Result<rppal::gpio::Gpio, rppal::gpio::Error> {
+            self.openings.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Err(rppal::gpio::Error::UnknownModel)
+        }
+
+        fn i2c(&self, _bus: Option<u8>) -> Result<rppal::i2c::I2c, rppal::i2c::Error> {
+            Err(rppal::i2c::Error::UnknownModel)
+        }
+    }
+
+    /// Tests the GPIO is opened once for the whole board, every peripheral using it reporting the failure.
+    #[test]
+    #[cfg(feature = "hardware")]
+    fn test_rainbow_hat_setup_shared_gpio() -> Result<(), Error> {
+        let mut hat = RainbowHat::builder()
+            .with_component(Component::Display, false)
+            .with_component(Component::Sensor, false)
+            .build()?;
+        let backend = CountingBackend::default();
+        let openings = backend.openings.clone();
+        hat.set_backend(backend);
+
+        let errors = match hat.setup() {
+            Err(Error::Multiple(errors)) => errors,
+            other => panic!("unexpected result: {:?}", other),
+        };
+        assert!(errors.len() == 8);
+        assert!(errors.iter().all(|err| matches!(err, Error::ComponentSetup { .. })));
+        assert!(errors[1].to_string() == "Setup of the lights on pin 6 failed: failed to configure BCM 6 for light: Unknown Raspberry Pi model");
+        assert!(errors[7].to_string() == "Setup of the buzzer on pin 13 failed: failed to configure BCM 13 for buzzer: Unknown Raspberry Pi model");
+        assert!(openings.load(std::sync::atomic::Ordering::SeqCst) == 1);
+
+        Ok(())
+    }
+
+    /// Tests only the peripherals failing to set up are reported.
+    #[test]
+    #[cfg(feature = "hardware")]
+    fn test_rainbow_hat_setup_partial_failure() -> Result<(), Error> {
+        let mut hat = RainbowHat::builder()
+            .with_component(Component::Pixels, false)
+            .with_component(Component::Buttons, false)
+            .with_component(Component::Display, false)
+            .with_component(Component::Sensor, false)
+            .build()?;
+        let backend = CountingBackend::default();
+        let openings = backend.openings.clone();
+        hat.set_backend(backend);
+        hat.lights.green = Light::with_pin(GPIO_LIGHT_GREEN, IdleOutput);
+
+        let errors = match hat.setup() {
+            Err(Error::Multiple(errors)) => errors,
+            other => panic!("unexpected result: {:?}", other),
+        };
+        let locations: Vec<String> = errors.iter().map(|err| match err {
+            Error::ComponentSetup { component, location, .. } => format!("{} {}", component, location),
+            other => panic!("unexpected error: {:?}", other),
+        }).collect();
+        assert!(locations == ["lights pin 6", "lights pin 26", "buzzer pin 13"]);
+        assert!(openings.load(std::sync::atomic::Ordering::SeqCst) == 1);
+
+        Ok(())
+    }
+
+    /// Bus busy for a number of writes, then accepting them.
+    #[derive(Debug)]
+    struct BusyBus {
+
+        /// Number of writes still failing.
+        busy: u32,
+    }
+
+    impl crate::hal::I2cBus for BusyBus {
+        fn block_write(&mut self, _command: u8, _buffer: &[u8]) -> Result<(), crate::ht16k33::Error> {
+            if self.busy == 0 {
+                return Ok(());
+            }
+
+            self.busy -= 1;
+            Err(crate::ht16k33::Error::from(std::io::Error::from_raw_os_error(16)))
+        }
+    }
+
+    /// Tests the setup of a peripheral busy on startup is retried with a backoff.
+    #[test]
+    fn test_rainbow_hat_setup_with_retry() -> Result<(), Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+        let clock = ManualClock::new();
+        hat.set_clock(clock.clone());
+        let start = clock.now();
+        let policy = RetryPolicy::new(3, Duration::from_millis(100))?;
+
+        hat.alphanum.ht16k33 = crate::ht16k33::HT16K33::with_bus(BusyBus { busy: 2 });
+        hat.setup_with_retry(policy)?;
+        assert!(hat.alphanum.ht16k33.is_setup());
+        assert!(clock.now() - start == Duration::from_millis(300));
+
+        // Still busy after the attempts: the display is named
+        hat.alphanum.ht16k33 = crate::ht16k33::HT16K33::with_bus(BusyBus { busy: 3 });
+        assert!(matches!(hat.setup_with_retry(policy), Err(Error::ComponentSetup { component: Component::Display, .. })));
+
+        // Without retry, the first busy write fails
+        hat.alphanum.ht16k33 = crate::ht16k33::HT16K33::with_bus(BusyBus { busy: 1 });
+        assert!(hat.setup().is_err());
+
+        // The builder sets the board up, checking the number of attempts
+        let hat = RainbowHat::builder().simulation(true).setup_with_retry(3, Duration::from_millis(10)).build()?;
+        assert!(hat.alphanum.ht16k33.is_setup());
+        assert!(matches!(RainbowHat::builder().simulation(true).setup_with_retry(0, Duration::from_millis(10)).build(), Err(Error::InvalidInput(_))));
+
+        Ok(())
+    }
+
+    /// Output ignoring the levels written.
+    #[cfg(feature = "hardware")]
+    #[derive(Debug)]
+    struct IdleOutput;
+
+    #[cfg(feature = "hardware")]
+    impl crate::hal::DigitalOutput for IdleOutput {
+        fn set_level(&mut self, _high: bool) {}
+    }
+
+    /// Tests the shared board is cleaned up from another thread, even after a panic poisoned its lock.
+    #[test]
+    fn test_shared_rainbow_hat_cleanup() -> Result<(), Error> {
+        let hat = RainbowHat::with_simulation()?.into_shared();
+        hat.with_board(|board| {
+            board.apa102.set_all(255, 0, 0, 1.0)?;
+            board.lights.all(true);
+            board.alphanum.print_str("RUN", Alignment::Left)?;
+            Ok::<_, Error>(())
+        })?;
+
+        let render = hat.clone();
+        let panicked = thread::spawn(move || render.with_board(|_board| panic!("render loop crashed"))).join();
+        assert!(panicked.is_err());
+
+        let cleaner = hat.clone();
+        thread::spawn(move || cleaner.cleanup()).join().unwrap()?;
+
+        hat.with_board(|board| {
+            assert!(board.apa102.is_blank());
+            assert!(!board.lights.red.state);
+            assert!(board.alphanum.is_blank());
+        });
+
+        Ok(())
+    }
+
+    /// Pins the auto traits: the board can be moved to another thread, and shared with its handle.
+    const _: () = {
+        const fn assert_send<T: Send>() {}
+        const fn assert_sync<T: Sync>() {}
+        assert_send::<RainbowHat>();
+        assert_send::<SharedRainbowHat>();
+        assert_sync::<SharedRainbowHat>();
+    };
+}
@@ -0,0 +1,274 @@
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::clock::{Clock, SystemClock};
+use crate::rainbow_hat::Component;
+
+/// Number of operations kept by a `MemoryRecorder` unless set otherwise, the oldest being dropped first.
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+/// Data of an operation recorded in simulation mode.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Payload {
+
+    /// Operation without data, such as silencing the buzzer.
+    None,
+
+    /// Write to a bus: register or command byte followed by the data written.
+    Write {
+        register: u8,
+        data: Vec<u8>,
+    },
+
+    /// Frame of the multicolour LEDs as sent on the wire: brightness, blue, green, red of each pixel.
+    Frame(Vec<[u8; 4]>),
+
+    /// Level of an output pin, using the BCM pin numbering.
+    Level {
+        pin: u8,
+        high: bool,
+    },
+
+    /// Tone of the buzzer.
+    Tone {
+        frequency: f64,
+        duration: Duration,
+    },
+}
+
+/// Operation of a driver recorded in simulation mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+
+    /// Peripheral of the driver.
+    pub component: Component,
+
+    /// Name of the operation, such as "write", "show", "set" or "note".
+    pub operation: &'static str,
+
+    /// Data of the operation.
+    pub payload: Payload,
+
+    /// Instant of the operation, read from the clock of the recorder.
+    pub timestamp: Instant,
+}
+
+/// Receiver of the operations the drivers do in simulation mode, instead of accessing the hardware.
+/// Giving the same recorder to several drivers interleaves their operations in a single timeline,
+/// so a test can check the order of operations across peripherals.
+pub trait Recorder: Debug + Send {
+
+    /// Record an operation, stamped with the current instant.
+    ///
+    /// # Arguments
+    ///
+    /// * `component` - Peripheral of the driver.
+    /// * `operation` - Name of the operation.
+    /// * `payload` - Data of the operation.
+    fn record(&self, component: Component, operation: &'static str, payload: Payload);
+
+    /// Get the operations recorded, oldest first.
+    fn entries(&self) -> Vec<Entry>;
+
+    /// Visit the operations recorded, oldest first, without copying them.
+    ///
+    /// # Arguments
+    ///
+    /// * `visit` - Function called with each operation.
+    fn visit(&self, visit: &mut dyn FnMut(&Entry)) {
+        for entry in self.entries() {
+            visit(&entry);
+        }
+    }
+
+    /// Keep only the operations for which a predicate returns true.
+    ///
+    /// # Arguments
+    ///
+    /// * `keep` - Predicate telling whether to keep an operation.
+    fn retain(&self, keep: &mut dyn FnMut(&Entry) -> bool);
+}
+
+/// Operations recorded by a `MemoryRecorder`, with the clock stamping them.
+#[derive(Debug)]
+struct Timeline {
+
+    /// operations recorded, oldest first
+    entries: VecDeque<Entry>,
+
+    /// maximum number of operations kept
+    capacity: usize,
+
+    /// clock stamping the operations
+    clock: Box<dyn Clock>,
+}
+
+/// Recorder keeping the latest operations in memory, up to its capacity, dropping the oldest ones beyond it.
+/// Clones share the same timeline, so a test can keep a clone of a recorder given to the drivers.
+#[derive(Debug, Clone)]
+pub struct MemoryRecorder {
+
+    /// timeline shared by the clones
+    timeline: Arc<Mutex<Timeline>>,
+}
+
+impl MemoryRecorder {
+
+    /// Creates a recorder stamping the operations with the system clock, keeping `DEFAULT_CAPACITY` operations.
+    pub fn new() -> MemoryRecorder {
+        MemoryRecorder::with_clock(SystemClock)
+    }
+
+    /// Creates a recorder stamping the operations with the system clock, keeping a number of operations.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Maximum number of operations kept, 0 to record nothing.
+    pub fn with_capacity(capacity: usize) -> MemoryRecorder {
+        let recorder = MemoryRecorder::new();
+        recorder.set_capacity(capacity);
+        recorder
+    }
+
+    /// Creates a recorder stamping the operations with another clock, keeping `DEFAULT_CAPACITY` operations.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Clock to use.
+    pub fn with_clock<C: Clock + 'static>(clock: C) -> MemoryRecorder {
+        Self {
+            timeline: Arc::new(Mutex::new(Timeline {
+                entries: VecDeque::new(),
+                capacity: DEFAULT_CAPACITY,
+                clock: Box::new(clock),
+            })),
+        }
+    }
+
+    /// Get the number of operations recorded.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Get whether no operation is recorded.
+    pub fn is_empty(&self) -> bool {
+        self.lock().entries.is_empty()
+    }
+
+    /// Get the maximum number of operations kept.
+    pub fn capacity(&self) -> usize {
+        self.lock().capacity
+    }
+
+    /// Set the maximum number of operations kept, for every clone, dropping the oldest ones beyond it.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Maximum number of operations kept, 0 to record nothing.
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut timeline = self.lock();
+        timeline.capacity = capacity;
+        let excess = timeline.entries.len().saturating_sub(capacity);
+        timeline.entries.drain(..excess);
+    }
+
+    /// Forget every operation recorded.
+    pub fn clear(&self) {
+        self.lock().entries.clear();
+    }
+
+    /// Locks the timeline. A poisoned lock is recovered as the entries stay consistent between calls.
+    fn lock(&self) -> std::sync::MutexGuard<'_, Timeline> {
+        self.timeline.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for MemoryRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Recorder for MemoryRecorder {
+    fn record(&self, component: Component, operation: &'static str, payload: Payload) {
+        let mut timeline = self.lock();
+        if timeline.capacity == 0 {
+            return;
+        }
+        if timeline.entries.len() == timeline.capacity {
+            timeline.entries.pop_front();
+        }
+        let timestamp = timeline.clock.now();
+        timeline.entries.push_back(Entry { component, operation, payload, timestamp });
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        self.lock().entries.iter().cloned().collect()
+    }
+
+    fn visit(&self, visit: &mut dyn FnMut(&Entry)) {
+        self.lock().entries.iter().for_each(visit);
+    }
+
+    fn retain(&self, keep: &mut dyn FnMut(&Entry) -> bool) {
+        self.lock().entries.retain(|entry| keep(entry));
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    /// Tests clones share the timeline, stamped with the clock of the recorder.
+    #[test]
+    fn test_memory_recorder() {
+        let clock = ManualClock::new();
+        let recorder = MemoryRecorder::with_clock(clock.clone());
+        let shared = recorder.clone();
+        assert!(recorder.is_empty());
+
+        shared.record(Component::Buzzer, "stop", Payload::None);
+        clock.advance(Duration::from_millis(5));
+        recorder.record(Component::Lights, "set", Payload::Level { pin: 6, high: true });
+
+        let entries = recorder.entries();
+        assert!(entries.len() == 2 && shared.len() == 2);
+        assert!(entries[0].component == Component::Buzzer && entries[0].operation == "stop");
+        assert!(entries[1].payload == Payload::Level { pin: 6, high: true });
+        assert!(entries[1].timestamp - entries[0].timestamp == Duration::from_millis(5));
+
+        recorder.retain(&mut |entry| entry.component == Component::Lights);
+        assert!(shared.len() == 1);
+        shared.clear();
+        assert!(recorder.is_empty());
+    }
+
+    /// Tests the timeline keeps the latest operations up to its capacity.
+    #[test]
+    fn test_memory_recorder_capacity() {
+        let recorder = MemoryRecorder::new();
+        assert!(recorder.capacity() == DEFAULT_CAPACITY);
+
+        for pin in 0..(DEFAULT_CAPACITY + 50) {
+            recorder.record(Component::Lights, "set", Payload::Level { pin: pin as u8, high: true });
+        }
+        assert!(recorder.len() == DEFAULT_CAPACITY);
+
+        // Shrinking drops the oldest operations, for every clone
+        let shared = recorder.clone();
+        shared.set_capacity(3);
+        recorder.record(Component::Buzzer, "stop", Payload::None);
+        let mut operations = Vec::new();
+        recorder.visit(&mut |entry| operations.push(entry.operation));
+        assert!(operations == ["set", "set", "stop"]);
+        assert!(recorder.entries()[0].payload == Payload::Level { pin: ((DEFAULT_CAPACITY + 48) % 256) as u8, high: true });
+
+        // Nothing recorded with no capacity
+        let disabled = MemoryRecorder::with_capacity(0);
+        disabled.record(Component::Buzzer, "stop", Payload::None);
+        assert!(disabled.is_empty());
+    }
+}
@@ -0,0 +1,321 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use crate::Error;
+use crate::apa102::NUM_PIXELS;
+use crate::clock::{Clock, SystemClock};
+use crate::error::invalid_input;
+use crate::ht16k33::{Command, BUFFER_SIZE};
+use crate::rainbow_hat::{Component, RainbowHat};
+use crate::recorder::{Entry, Payload};
+
+/// Longest sleep of a replay between two checks of the cancel flag.
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Operation of a macro, replayable on a board.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MacroCommand {
+
+    /// Show the multicolour LEDs: red, green, blue and brightness from 0 to 31 of each pixel, as shown.
+    Pixels(Vec<[u8; 4]>),
+
+    /// Turn a light on or off, using the BCM pin numbering.
+    Light {
+        pin: u8,
+        on: bool,
+    },
+
+    /// Write data to the display buffer starting at an address, and show it.
+    DisplayWrite {
+        offset: u8,
+        data: Vec<u8>,
+    },
+
+    /// Turn the display on or off and set its blink frequency, as the raw blink bits.
+    DisplaySetup {
+        on: bool,
+        blink: u8,
+    },
+
+    /// Set the brightness of the display, from 0 to 15.
+    DisplayBrightness(u8),
+
+    /// Play a tone on the buzzer.
+    Tone {
+        frequency: f64,
+        duration: Duration,
+    },
+
+    /// Silence the buzzer.
+    BuzzerStop,
+
+    /// Operation the replay can't perform, such as a write to the sensor, skipped with a warning.
+    Unsupported(String),
+}
+
+/// Command of a macro with the delay since the previous one.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MacroStep {
+
+    /// Delay since the previous command, or since the start of the replay for the first one.
+    pub delay: Duration,
+
+    /// Command to replay.
+    pub command: MacroCommand,
+}
+
+/// Sequence of board operations recorded while driving a simulated board, to be saved, with the `serde` feature,
+/// and replayed later on a real or simulated board with the same relative timing.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Macro {
+
+    /// Commands, in the order they are replayed.
+    pub steps: Vec<MacroStep>,
+}
+
+impl Macro {
+
+    /// Converts the operations recorded by the drivers of a board in simulation mode into a macro,
+    /// such as `hat.recording()`. The setup of the display is left to the replaying board.
+    ///
+    /// # Arguments
+    ///
+    /// * `recording` - Operations recorded, oldest first.
+    pub fn from_recording(recording: &[Entry]) -> Macro {
+        let mut steps = Vec::new();
+        let mut previous: Option<Instant> = None;
+
+        for entry in recording {
+            let command = match Self::command(entry) {
+                Some(command) => command,
+                None => continue,
+            };
+
+            let delay = previous.map_or(Duration::ZERO, |previous| entry.timestamp.saturating_duration_since(previous));
+            previous = Some(entry.timestamp);
+            steps.push(MacroStep { delay, command });
+        }
+
+        Macro { steps }
+    }
+
+    /// Converts a recorded operation, None for the ones done by the setup of the drivers.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - Operation recorded.
+    fn command(entry: &Entry) -> Option<MacroCommand> {
+        let command = match (entry.component, &entry.payload) {
+            (Component::Pixels, Payload::Frame(frame)) => {
+                MacroCommand::Pixels(frame.iter().map(|pixel| [pixel[3], pixel[2], pixel[1], pixel[0]]).collect())
+            }
+            (Component::Lights, Payload::Level { pin, high }) => MacroCommand::Light { pin: *pin, on: *high },
+            (Component::Display, Payload::Write { register, data }) => match Command::decode(*register, data) {
+                Some(Command::SystemSetup { .. }) => return None,
+                Some(Command::WriteRam { offset, data }) => MacroCommand::DisplayWrite { offset, data: data.to_vec() },
+                Some(Command::DisplaySetup { on, blink }) => MacroCommand::DisplaySetup { on, blink },
+                Some(Command::Brightness(level)) => MacroCommand::DisplayBrightness(level),
+                None => MacroCommand::Unsupported(format!("display command 0x{:02X}", register)),
+            },
+            (Component::Buzzer, Payload::Tone { frequency, duration }) => MacroCommand::Tone { frequency: *frequency, duration: *duration },
+            (Component::Buzzer, Payload::None) => MacroCommand::BuzzerStop,
+            (Component::Sensor, Payload::Write { register, .. }) => MacroCommand::Unsupported(format!("sensor write to register 0x{:02X}", register)),
+            (component, _payload) => MacroCommand::Unsupported(format!("{:?} {}", component, entry.operation)),
+        };
+
+        Some(command)
+    }
+
+    /// Get the duration of the macro at normal speed.
+    pub fn duration(&self) -> Duration {
+        self.steps.iter().map(|step| step.delay).sum()
+    }
+
+    /// Replay the macro on a board, waiting the recorded delays divided by the speed.
+    /// The commands are scheduled from the start, so the time taken by the board does not make the replay drift.
+    /// Returns a warning for each command skipped as it can't be replayed. Stops at the first error of the board.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board to drive.
+    /// * `speed` - Speed of the replay, greater than 0.0: 1.0 for the recorded timing, 2.0 twice as fast.
+    /// * `cancel` - Set to true to stop, checked while waiting.
+    pub fn replay(&self, hat: &mut RainbowHat, speed: f32, cancel: &AtomicBool) -> Result<Vec<String>, Error> {
+        self.replay_with_clock(hat, speed, cancel, &SystemClock)
+    }
+
+    /// Replay the macro on a board, as with `replay`, timed by a clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board to drive.
+    /// * `speed` - Speed of the replay, greater than 0.0.
+    /// * `cancel` - Set to true to stop, checked while waiting.
+    /// * `clock` - Clock timing the commands.
+    pub fn replay_with_clock(&self, hat: &mut RainbowHat, speed: f32, cancel: &AtomicBool, clock: &dyn Clock) -> Result<Vec<String>, Error> {
+        if !(speed.is_finite() && speed > 0.0) {
+            return Err(Error::InvalidInput(invalid_input("speed", speed, "greater than 0.0")));
+        }
+
+        let mut warnings = Vec::new();
+        let mut deadline = clock.now();
+
+        for (i, step) in self.steps.iter().enumerate() {
+            deadline += step.delay.div_f64(speed as f64);
+            loop {
+                if cancel.load(Ordering::SeqCst) {
+                    return Ok(warnings);
+                }
+
+                let now = clock.now();
+                if now >= deadline {
+                    break;
+                }
+                clock.sleep((deadline - now).min(CANCEL_CHECK_INTERVAL));
+            }
+
+            if let MacroCommand::Unsupported(operation) = &step.command {
+                warnings.push(format!("step {}: skipped {}", i, operation));
+            } else {
+                Self::run(&step.command, hat)?;
+            }
+        }
+
+        Ok(warnings)
+    }
+
+    /// Runs a command on the board.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - Command to run, not `Unsupported`.
+    /// * `hat` - Board to drive.
+    fn run(command: &MacroCommand, hat: &mut RainbowHat) -> Result<(), Error> {
+        match command {
+            MacroCommand::Pixels(pixels) => {
+                if pixels.len() != NUM_PIXELS {
+                    return Err(Error::InvalidInput(invalid_input("number of pixels", pixels.len(), &NUM_PIXELS.to_string())));
+                }
+
+                hat.apa102.pixels.copy_from_slice(pixels);
+
+                // The brightness of the pixels is the one shown, already scaled by the global brightness
+                hat.apa102.present(1.0, false)?;
+            }
+            MacroCommand::Light { pin, on } => {
+                let lights = &mut hat.lights;
+                match [&mut lights.red, &mut lights.green, &mut lights.blue].iter_mut().find(|light| light.bcm_pin == *pin) {
+                    Some(light) => light.write(*on),
+                    None => return Err(Error::InvalidInput(invalid_input("light pin", pin, "the pin of a light"))),
+                }
+            }
+            MacroCommand::DisplayWrite { offset, data } => {
+                let offset = *offset as usize;
+                if offset + data.len() > BUFFER_SIZE {
+                    return Err(Error::InvalidInput(invalid_input("display write", format!("{} bytes at {}", data.len(), offset), "within the buffer")));
+                }
+
+                hat.alphanum.ht16k33.buffer[offset..offset + data.len()].copy_from_slice(data);
+                hat.alphanum.show()?;
+            }
+            MacroCommand::DisplaySetup { on, blink } => {
+                hat.alphanum.ht16k33.set_blink_raw(*blink)?;
+                hat.alphanum.ht16k33.set_display(*on)?;
+            }
+            MacroCommand::DisplayBrightness(level) => hat.alphanum.ht16k33.set_brightness(*level)?,
+            MacroCommand::Tone { frequency, duration } => hat.buzzer.note(*frequency, duration.as_secs_f64())?,
+            MacroCommand::BuzzerStop => hat.buzzer.stop()?,
+            MacroCommand::Unsupported(_operation) => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphanum4::Alignment;
+    use crate::clock::ManualClock;
+    use crate::recorder::MemoryRecorder;
+
+    /// Drives a simulated board recording its operations, 100 ms apart.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Clock stamping the operations.
+    fn record(clock: &ManualClock) -> Result<RainbowHat, Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+        hat.set_recorder(MemoryRecorder::with_clock(clock.clone()));
+
+        hat.apa102.set_pixel(0, 255, 0, 0, 1.0)?;
+        hat.apa102.set_pixel(6, 0, 128, 255, 0.5)?;
+        hat.apa102.show()?;
+        clock.advance(Duration::from_millis(100));
+        hat.lights.rgb(true, false, true);
+        clock.advance(Duration::from_millis(100));
+        hat.alphanum.print_str("DEMO", Alignment::Left)?;
+        hat.alphanum.show()?;
+        hat.alphanum.ht16k33.set_brightness(7)?;
+        clock.advance(Duration::from_millis(100));
+        hat.buzzer.note(440.0, 0.25)?;
+        clock.advance(Duration::from_millis(100));
+        hat.bmp280.read_temperature()?;
+        hat.buzzer.stop()?;
+
+        Ok(hat)
+    }
+
+    /// Tests recording a board, saving the macro and replaying it on another board.
+    #[test]
+    fn test_macro_round_trip() -> Result<(), Error> {
+        let clock = ManualClock::new();
+        let recorded = record(&clock)?;
+        let recording = Macro::from_recording(&recorded.recording());
+        assert!(recording.duration() == Duration::from_millis(400));
+        assert!(recording.steps.iter().all(|step| step.command != MacroCommand::Unsupported("display command 0x21".to_string())));
+
+        #[cfg(feature = "serde")]
+        let recording: Macro = serde_json::from_str(&serde_json::to_string(&recording).unwrap()).unwrap();
+
+        // Twice as fast, on a clock advanced by the waits
+        let mut hat = RainbowHat::with_simulation()?;
+        let replay_clock = ManualClock::new();
+        let start = replay_clock.now();
+        let warnings = recording.replay_with_clock(&mut hat, 2.0, &AtomicBool::new(false), &replay_clock)?;
+        assert!(replay_clock.now() - start == Duration::from_millis(200));
+
+        assert!(hat.export_state() == recorded.export_state());
+        assert!(hat.apa102.last_frame() == recorded.apa102.last_frame());
+        let tones = |hat: &RainbowHat| hat.recording().into_iter().filter(|entry| entry.component == Component::Buzzer).map(|entry| entry.payload).collect::<Vec<_>>();
+        assert!(tones(&hat) == tones(&recorded));
+
+        assert!(!warnings.is_empty());
+        assert!(warnings.iter().all(|warning| warning.contains("skipped sensor write")));
+
+        Ok(())
+    }
+
+    /// Tests cancelling a replay and the invalid speeds and commands.
+    #[test]
+    fn test_macro_replay_cancel_invalid() -> Result<(), Error> {
+        let recording = Macro::from_recording(&record(&ManualClock::new())?.recording());
+        let mut hat = RainbowHat::with_simulation()?;
+        let clock = ManualClock::new();
+
+        let warnings = recording.replay_with_clock(&mut hat, 1.0, &AtomicBool::new(true), &clock)?;
+        assert!(warnings.is_empty() && hat.recording().is_empty());
+
+        for speed in [0.0, -1.0, f32::NAN, f32::INFINITY].iter() {
+            assert!(matches!(recording.replay_with_clock(&mut hat, *speed, &AtomicBool::new(false), &clock), Err(Error::InvalidInput(_))));
+        }
+
+        let invalid = Macro { steps: vec![MacroStep { delay: Duration::ZERO, command: MacroCommand::Pixels(vec![[0; 4]; 3]) }] };
+        assert!(invalid.replay_with_clock(&mut hat, 1.0, &AtomicBool::new(false), &clock).is_err());
+
+        Ok(())
+    }
+}
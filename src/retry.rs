@@ -0,0 +1,237 @@
+use std::error;
+use std::io;
+use std::time::Duration;
+use crate::Error;
+use crate::error::invalid_input;
+use crate::clock::{Clock, SystemClock};
+
+/// Longest wait between two attempts, whatever the number of retries.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Error number of a busy device or resource on Linux (EBUSY).
+const EBUSY: i32 = 16;
+
+/// Error number of a resource temporarily unavailable on Linux (EAGAIN).
+const EAGAIN: i32 = 11;
+
+/// Policy retrying an operation with an exponential backoff, for example the setup of a peripheral racing udev
+/// at boot. Only the errors which plausibly resolve by themselves are retried: a busy device, a resource
+/// temporarily unavailable or an interrupted call. Any other error is returned at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+
+    /// Number of attempts, at least 1.
+    attempts: u32,
+
+    /// Wait before the first retry, doubled before each following one.
+    initial_backoff: Duration,
+}
+
+impl RetryPolicy {
+
+    /// Creates a policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `attempts` - Number of attempts, at least 1, the first one included.
+    /// * `initial_backoff` - Wait before the first retry, doubled before each following one up to `MAX_BACKOFF`.
+    pub fn new(attempts: u32, initial_backoff: Duration) -> Result<RetryPolicy, Error> {
+        if attempts == 0 {
+            return Err(Error::InvalidInput(invalid_input("attempts", attempts, "at least 1")));
+        }
+
+        Ok(Self {
+            attempts,
+            initial_backoff,
+        })
+    }
+
+    /// Creates a policy making a single attempt.
+    pub fn once() -> RetryPolicy {
+        Self {
+            attempts: 1,
+            initial_backoff: Duration::from_secs(0),
+        }
+    }
+
+    /// Get the number of attempts.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Get the wait before a retry.
+    ///
+    /// # Arguments
+    ///
+    /// * `retry` - Number of the retry, 0 for the first one.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        2u32.checked_pow(retry)
+            .and_then(|factor| self.initial_backoff.checked_mul(factor))
+            .map_or(MAX_BACKOFF, |backoff| backoff.min(MAX_BACKOFF))
+    }
+
+    /// Run an operation until it succeeds, fails with an error which is not transient, or every attempt is made.
+    /// The error of the last attempt is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - Operation receiving the number of the attempt, 0 for the first one.
+    pub fn run<T, E, F>(&self, operation: F) -> Result<T, E>
+        where E: error::Error + 'static, F: FnMut(u32) -> Result<T, E> {
+        self.run_with_clock(&SystemClock, operation)
+    }
+
+    /// Run an operation as `run`, waiting between the attempts with a clock, for example a `ManualClock` in tests.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Clock waiting between the attempts.
+    /// * `operation` - Operation receiving the number of the attempt, 0 for the first one.
+    pub fn run_with_clock<T, E, F>(&self, clock: &dyn Clock, mut operation: F) -> Result<T, E>
+        where E: error::Error + 'static, F: FnMut(u32) -> Result<T, E> {
+        let mut attempt = 0;
+
+        loop {
+            match operation(attempt) {
+                Err(err) if attempt + 1 < self.attempts && is_transient(&err) => {
+                    clock.sleep(self.backoff(attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Get whether an error plausibly resolves by itself, from the I/O error found in its chain of sources:
+/// a busy device, a resource temporarily unavailable or an interrupted call.
+///
+/// # Arguments
+///
+/// * `err` - Error to check.
+pub fn is_transient(err: &(dyn error::Error + 'static)) -> bool {
+    let mut next = Some(err);
+
+    while let Some(err) = next {
+        if let Some(err) = io_error(err) {
+            return matches!(err.raw_os_error(), Some(EBUSY) | Some(EAGAIN))
+                || matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted);
+        }
+        next = err.source();
+    }
+
+    false
+}
+
+/// Get the I/O error carried by an error, including the I/O errors of rppal.
+///
+/// # Arguments
+///
+/// * `err` - Error to check.
+fn io_error<'a>(err: &'a (dyn error::Error + 'static)) -> Option<&'a io::Error> {
+    if let Some(err) = err.downcast_ref::<io::Error>() {
+        return Some(err);
+    }
+
+    #[cfg(feature = "hardware")]
+    {
+        if let Some(rppal::gpio::Error::Io(err)) = err.downcast_ref::<rppal::gpio::Error>() {
+            return Some(err);
+        }
+        if let Some(rppal::i2c::Error::Io(err)) = err.downcast_ref::<rppal::i2c::Error>() {
+            return Some(err);
+        }
+        if let Some(rppal::spi::Error::Io(err)) = err.downcast_ref::<rppal::spi::Error>() {
+            return Some(err);
+        }
+    }
+
+    None
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use crate::clock::ManualClock;
+
+    /// Creates an I/O error from an error number.
+    fn os_error(errno: i32) -> Error {
+        Error::Io(io::Error::from_raw_os_error(errno))
+    }
+
+    /// Tests the backoff doubles up to the maximum.
+    #[test]
+    fn test_retry_backoff() -> Result<(), Error> {
+        let policy = RetryPolicy::new(5, Duration::from_millis(100))?;
+
+        let backoffs: Vec<u128> = (0..4).map(|retry| policy.backoff(retry).as_millis()).collect();
+        assert!(backoffs == [100, 200, 400, 800]);
+        assert!(policy.backoff(10) == MAX_BACKOFF && policy.backoff(40) == MAX_BACKOFF);
+
+        assert!(matches!(RetryPolicy::new(0, Duration::from_millis(100)), Err(Error::InvalidInput(_))));
+
+        Ok(())
+    }
+
+    /// Tests an operation failing with busy errors then succeeding is retried with the backoff.
+    #[test]
+    fn test_retry_transient() -> Result<(), Error> {
+        let policy = RetryPolicy::new(4, Duration::from_millis(100))?;
+        let clock = ManualClock::new();
+        let start = clock.now();
+
+        let mut attempts = Vec::new();
+        let result = policy.run_with_clock(&clock, |attempt| {
+            attempts.push((attempt, clock.now() - start));
+            match attempt {
+                0 => Err(os_error(EBUSY)),
+                1 => Err(os_error(EAGAIN)),
+                _ => Ok("ready"),
+            }
+        });
+
+        assert!(matches!(result, Ok("ready")));
+        assert!(attempts == [(0, Duration::from_millis(0)), (1, Duration::from_millis(100)), (2, Duration::from_millis(300))]);
+
+        Ok(())
+    }
+
+    /// Tests the errors which are not transient are returned at once, and the last error once the attempts are made.
+    #[test]
+    fn test_retry_gives_up() -> Result<(), Error> {
+        let policy = RetryPolicy::new(3, Duration::from_millis(100))?;
+        let clock = ManualClock::new();
+        let start: Instant = clock.now();
+
+        // No such device: not retried
+        let mut count = 0;
+        let result: Result<(), Error> = policy.run_with_clock(&clock, |_attempt| {
+            count += 1;
+            Err(os_error(19))
+        });
+        assert!(matches!(result, Err(Error::Io(_))) && count == 1);
+        assert!(clock.now() == start);
+
+        // Always busy: the last error is returned after the attempts
+        let result: Result<(), Error> = policy.run_with_clock(&clock, |attempt| {
+            count = attempt;
+            Err(os_error(EBUSY))
+        });
+        assert!(matches!(result, Err(Error::Io(err)) if err.raw_os_error() == Some(EBUSY)));
+        assert!(count == 2 && clock.now() - start == Duration::from_millis(300));
+
+        // The I/O error is found through the sources
+        let err = Error::ComponentSetup {
+            component: crate::rainbow_hat::Component::Display,
+            location: "I2C address 0x70".to_string(),
+            source: Box::new(crate::ht16k33::Error::Io(io::Error::from_raw_os_error(EBUSY)).into()),
+        };
+        assert!(is_transient(&err));
+        assert!(!is_transient(&Error::InvalidInput("attempts".to_string())));
+        assert!(RetryPolicy::once().run_with_clock(&clock, |_attempt| Err::<(), _>(os_error(EBUSY))).is_err());
+
+        Ok(())
+    }
+}
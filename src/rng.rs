@@ -0,0 +1,90 @@
+use std::fmt::Debug;
+
+/// Seed used instead of 0, which xorshift64 cannot leave.
+pub const DEFAULT_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Source of pseudo-random numbers used by the random effects.
+/// Injecting a seeded source allows the effects to be tested and replayed exactly.
+pub trait Rng: Debug + Send {
+
+    /// Get the next random number.
+    fn next_u64(&mut self) -> u64;
+
+    /// Get the next random byte, from the high bits of the next number.
+    fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    /// Get the next random number between 0.0 included and 1.0 excluded.
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u32 << 24) as f32
+    }
+}
+
+/// Seedable xorshift64 generator: fast and small, not suited to cryptography.
+/// The same seed always gives the same sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleRng {
+
+    /// State of the generator, never 0.
+    state: u64,
+}
+
+impl SimpleRng {
+
+    /// Creates a generator.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - Seed of the sequence, 0 being replaced by `DEFAULT_SEED`.
+    pub fn new(seed: u64) -> SimpleRng {
+        Self { state: if seed == 0 { DEFAULT_SEED } else { seed } }
+    }
+}
+
+impl Default for SimpleRng {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED)
+    }
+}
+
+impl Rng for SimpleRng {
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests a fixed seed gives the known xorshift64 sequence.
+    #[test]
+    fn test_simple_rng_sequence() {
+        let mut rng = SimpleRng::new(1);
+        assert!(rng.next_u64() == 0x4082_2041);
+        assert!(rng.next_u64() == 0x1000_4106_0C01_1441);
+        assert!(rng.next_u8() == 155);
+
+        let mut rng = SimpleRng::new(1);
+        rng.next_u64();
+        assert!(rng.next_f32() == 0.062_503_874);
+
+        // 0 is replaced, as the generator would only give 0
+        assert!(SimpleRng::new(0) == SimpleRng::new(DEFAULT_SEED));
+        assert!(SimpleRng::new(0).next_u64() != 0);
+    }
+
+    /// Tests the numbers stay in range over many draws.
+    #[test]
+    fn test_simple_rng_range() {
+        let mut rng = SimpleRng::default();
+        for _ in 0..1000 {
+            assert!((0.0..1.0).contains(&rng.next_f32()));
+        }
+    }
+}
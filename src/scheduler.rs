@@ -0,0 +1,692 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+use crate::Error;
+use crate::app::Handler;
+use crate::clock::{Clock, SystemClock};
+use crate::error::invalid_input;
+use crate::rainbow_hat::{RainbowHat, SharedRainbowHat};
+
+/// Longest wait of the scheduler thread between two checks, so a clock adjustment is noticed within this delay.
+pub const MAX_WAIT: Duration = Duration::from_secs(1);
+
+/// Difference between the wall clock and the monotonic time above which the wall clock is considered adjusted.
+pub const ADJUSTMENT_TOLERANCE: Duration = Duration::from_secs(2);
+
+/// Largest offset from UTC, in seconds.
+const MAX_UTC_OFFSET: i32 = 14 * 3600;
+
+/// Number of nanoseconds in a day.
+const NANOS_PER_DAY: i128 = 86_400 * 1_000_000_000;
+
+/// Time of the day, in the time zone of the scheduler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeOfDay {
+
+    /// Hour, 0 to 23.
+    hour: u8,
+
+    /// Minute, 0 to 59.
+    minute: u8,
+
+    /// Second, 0 to 59.
+    second: u8,
+}
+
+impl TimeOfDay {
+
+    /// Creates a time of the day.
+    ///
+    /// # Arguments
+    ///
+    /// * `hour` - Hour, 0 to 23.
+    /// * `minute` - Minute, 0 to 59.
+    /// * `second` - Second, 0 to 59.
+    pub fn new(hour: u8, minute: u8, second: u8) -> Result<TimeOfDay, Error> {
+        if hour > 23 {
+            return Err(Error::InvalidInput(invalid_input("hour", hour, "0 to 23")));
+        }
+        if minute > 59 {
+            return Err(Error::InvalidInput(invalid_input("minute", minute, "0 to 59")));
+        }
+        if second > 59 {
+            return Err(Error::InvalidInput(invalid_input("second", second, "0 to 59")));
+        }
+
+        Ok(Self { hour, minute, second })
+    }
+
+    /// Get the hour, 0 to 23.
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    /// Get the minute, 0 to 59.
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    /// Get the second, 0 to 59.
+    pub fn second(&self) -> u8 {
+        self.second
+    }
+
+    /// Get the number of nanoseconds since midnight.
+    fn nanos(&self) -> i128 {
+        (self.hour as i128 * 3600 + self.minute as i128 * 60 + self.second as i128) * 1_000_000_000
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = Error;
+
+    /// Parses "HH:MM" or "HH:MM:SS", such as "07:00" or "22:30:15".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidInput(invalid_input("time of day", format!("{:?}", s), "HH:MM or HH:MM:SS"));
+
+        let fields = s.split(':').map(|field| field.parse::<u8>().map_err(|_| invalid())).collect::<Result<Vec<u8>, Error>>()?;
+        match fields[..] {
+            [hour, minute] => TimeOfDay::new(hour, minute, 0),
+            [hour, minute, second] => TimeOfDay::new(hour, minute, second),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}", self.hour, self.minute, self.second)
+    }
+}
+
+/// When a scheduled action runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+
+    /// Every day at a time of the wall clock.
+    Daily(TimeOfDay),
+
+    /// At a regular interval of monotonic time, the first time one interval after the scheduling starts.
+    Every(Duration),
+}
+
+/// Scheduled action as listed by `Scheduler::entries`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScheduleEntry {
+
+    /// Identifier, to cancel the action.
+    pub id: u64,
+
+    /// When the action runs.
+    pub trigger: Trigger,
+}
+
+/// Action with its trigger.
+struct Entry {
+
+    /// Identifier returned when scheduling.
+    id: u64,
+
+    /// When the action runs.
+    trigger: Trigger,
+
+    /// Instant of the next run of an interval, None until the first check.
+    next: Option<Instant>,
+
+    /// Local time of the last run at a time of the day, in nanoseconds since the epoch, None before the first one.
+    last_deadline: Option<i128>,
+
+    /// Action to run.
+    action: Handler,
+}
+
+/// Actions scheduled by the scheduler and its thread.
+#[derive(Default)]
+struct Entries {
+
+    /// Scheduled actions, in the order they were added.
+    entries: Vec<Entry>,
+
+    /// Identifier of the next action.
+    next_id: u64,
+}
+
+/// Runs actions on the board at times of the day, such as "lights amber at 07:00", or at regular intervals.
+/// The deadlines are recomputed from the clock at each check instead of sleeping a precomputed duration:
+/// a time of the day passed by adjusting the wall clock, for example when NTP sets the time after boot, is skipped
+/// rather than run late, and setting the clock back does not run the actions twice.
+pub struct Scheduler {
+
+    /// Scheduled actions, shared with the handle of the thread.
+    entries: Arc<Mutex<Entries>>,
+
+    /// Clock giving the wall clock time and the instants.
+    clock: Box<dyn Clock>,
+
+    /// Offset of the time zone from UTC, in seconds.
+    utc_offset: i32,
+
+    /// Instant and wall clock time of the previous check, None before the first one.
+    last_check: Option<(Instant, SystemTime)>,
+}
+
+impl Scheduler {
+
+    /// Creates a scheduler without actions, on the system clock and in UTC.
+    pub fn new() -> Scheduler {
+        Self {
+            entries: Arc::new(Mutex::new(Entries::default())),
+            clock: Box::new(SystemClock),
+            utc_offset: 0,
+            last_check: None,
+        }
+    }
+
+    /// Set the clock giving the wall clock time and the instants.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Clock to use.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Box::new(clock);
+        self.last_check = None;
+    }
+
+    /// Set the time zone of the times of the day, as an offset from UTC.
+    ///
+    /// # Arguments
+    ///
+    /// * `seconds` - Offset in seconds, such as 3600 for UTC+1, from -50400 to 50400.
+    pub fn set_utc_offset(&mut self, seconds: i32) -> Result<(), Error> {
+        if !(-MAX_UTC_OFFSET..=MAX_UTC_OFFSET).contains(&seconds) {
+            return Err(Error::InvalidInput(invalid_input("UTC offset", seconds, "-50400 to 50400 seconds")));
+        }
+
+        self.utc_offset = seconds;
+        Ok(())
+    }
+
+    /// Run an action every day at a time of the wall clock. Returns its identifier.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - Time of the day, in the time zone of the scheduler.
+    /// * `action` - Closure receiving the board.
+    pub fn at<F>(&mut self, time: TimeOfDay, action: F) -> u64
+    where
+        F: FnMut(&mut RainbowHat) -> Result<(), Error> + Send + 'static,
+    {
+        self.add(Trigger::Daily(time), Box::new(action))
+    }
+
+    /// Run an action at a regular interval, the first time one interval after the first check. Returns its identifier.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Interval between two runs, greater than 0.
+    /// * `action` - Closure receiving the board.
+    pub fn every<F>(&mut self, interval: Duration, action: F) -> Result<u64, Error>
+    where
+        F: FnMut(&mut RainbowHat) -> Result<(), Error> + Send + 'static,
+    {
+        if interval.is_zero() {
+            return Err(Error::InvalidInput(invalid_input("interval", format!("{:?}", interval), "greater than 0")));
+        }
+
+        Ok(self.add(Trigger::Every(interval), Box::new(action)))
+    }
+
+    /// Adds an action.
+    ///
+    /// # Arguments
+    ///
+    /// * `trigger` - When the action runs.
+    /// * `action` - Action to run.
+    fn add(&mut self, trigger: Trigger, action: Handler) -> u64 {
+        lock(&self.entries).add(trigger, action)
+    }
+
+    /// List the scheduled actions, in the order they were added.
+    pub fn entries(&self) -> Vec<ScheduleEntry> {
+        lock(&self.entries).list()
+    }
+
+    /// Cancel an action. Returns true if it was scheduled.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Identifier returned when scheduling.
+    pub fn cancel(&mut self, id: u64) -> bool {
+        lock(&self.entries).cancel(id)
+    }
+
+    /// Check the clock and run the actions that are due, the most overdue first, then show the multicolour LEDs
+    /// and the display if an action ran. The first check only starts the intervals.
+    /// Every due action runs even if another one fails, the errors being returned together.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board given to the actions.
+    pub fn step(&mut self, hat: &mut RainbowHat) -> Result<(), Error> {
+        let now = self.clock.now();
+        let wall = self.clock.system_time();
+        let mut entries = lock(&self.entries);
+
+        // Times of the day between the previous check and this one, unless the wall clock was adjusted since
+        let window = match self.last_check {
+            Some((last, last_wall)) if !Self::adjusted(now - last, last_wall, wall) => Some((self.local_nanos(last_wall), self.local_nanos(wall))),
+            _ => None,
+        };
+        self.last_check = Some((now, wall));
+
+        // Due actions with how late they are
+        let mut due = Vec::new();
+        for (i, entry) in entries.entries.iter_mut().enumerate() {
+            match entry.trigger {
+                Trigger::Daily(time) => {
+                    if let Some((start, end)) = window {
+                        let day = start.div_euclid(NANOS_PER_DAY) * NANOS_PER_DAY;
+                        let mut deadline = day + time.nanos();
+                        if deadline <= start {
+                            deadline += NANOS_PER_DAY;
+                        }
+                        // A time of the day passed again after setting the clock back already ran
+                        if deadline <= end && !matches!(entry.last_deadline, Some(last) if last >= deadline) {
+                            entry.last_deadline = Some(deadline);
+                            due.push((end - deadline, i));
+                        }
+                    }
+                }
+                Trigger::Every(interval) => {
+                    let next = *entry.next.get_or_insert(now + interval);
+                    if now >= next {
+                        due.push(((now - next).as_nanos() as i128, i));
+
+                        // Scheduled from the previous run so the interval does not drift, unless it is late
+                        let next = next + interval;
+                        entry.next = Some(if next > now { next } else { now + interval });
+                    }
+                }
+            }
+        }
+        due.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        let mut errors = Vec::new();
+        for (_lateness, i) in due.iter() {
+            if let Err(err) = (entries.entries[*i].action)(hat) {
+                errors.push(err);
+            }
+        }
+
+        if !due.is_empty() {
+            if let Err(err) = hat.apa102.show() {
+                errors.push(err.into());
+            }
+            if let Err(err) = hat.alphanum.show_if_changed() {
+                errors.push(err.into());
+            }
+        }
+
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(Error::Multiple(errors)),
+        }
+    }
+
+    /// Get how long to wait before the next check: until the next deadline, at most `MAX_WAIT`.
+    fn wait(&self) -> Duration {
+        let now = self.clock.now();
+        let wall = self.local_nanos(self.clock.system_time());
+
+        lock(&self.entries).entries.iter().map(|entry| match (entry.trigger, entry.next) {
+            (Trigger::Daily(time), _) => {
+                let until = (time.nanos() - wall.rem_euclid(NANOS_PER_DAY)).rem_euclid(NANOS_PER_DAY);
+                Duration::from_nanos(until as u64)
+            }
+            (Trigger::Every(_interval), Some(next)) => next.saturating_duration_since(now),
+            (Trigger::Every(interval), None) => interval,
+        }).fold(MAX_WAIT, Duration::min)
+    }
+
+    /// Move the scheduler to a thread running the actions on a shared board until stopped.
+    /// The errors of the actions are sent down a channel and the scheduling goes on.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board given to the actions.
+    pub fn start(mut self, hat: SharedRainbowHat) -> SchedulerHandle {
+        let (sender, receiver) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let entries = self.entries.clone();
+
+        let stopped = stop.clone();
+        let thread = thread::spawn(move || {
+            while !stopped.load(Ordering::SeqCst) {
+                if let Err(err) = hat.with_board(|board| self.step(board)) {
+                    // Nobody reads the errors any more, the scheduling goes on
+                    let _result = sender.send(err);
+                }
+
+                let wait = self.wait();
+                self.clock.sleep(wait.max(Duration::from_millis(1)));
+            }
+
+            self
+        });
+
+        SchedulerHandle {
+            entries,
+            receiver,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Get whether the wall clock was adjusted between two checks, as it moved differently from the monotonic time.
+    ///
+    /// # Arguments
+    ///
+    /// * `elapsed` - Monotonic time elapsed since the previous check.
+    /// * `last_wall` - Wall clock time of the previous check.
+    /// * `wall` - Wall clock time of this check.
+    fn adjusted(elapsed: Duration, last_wall: SystemTime, wall: SystemTime) -> bool {
+        match wall.duration_since(last_wall) {
+            Ok(wall_elapsed) => wall_elapsed.abs_diff(elapsed) > ADJUSTMENT_TOLERANCE,
+            // Set back
+            Err(err) => err.duration() + elapsed > ADJUSTMENT_TOLERANCE,
+        }
+    }
+
+    /// Get the nanoseconds since the epoch in the time zone of the scheduler.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - Wall clock time.
+    fn local_nanos(&self, time: SystemTime) -> i128 {
+        let since_epoch = match time.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(duration) => duration.as_nanos() as i128,
+            Err(err) => -(err.duration().as_nanos() as i128),
+        };
+
+        since_epoch + self.utc_offset as i128 * 1_000_000_000
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("entries", &self.entries())
+            .field("utc_offset", &self.utc_offset)
+            .finish()
+    }
+}
+
+impl Entries {
+
+    /// Adds an action. Returns its identifier.
+    ///
+    /// # Arguments
+    ///
+    /// * `trigger` - When the action runs.
+    /// * `action` - Action to run.
+    fn add(&mut self, trigger: Trigger, action: Handler) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.push(Entry {
+            id,
+            trigger,
+            next: None,
+            last_deadline: None,
+            action,
+        });
+        id
+    }
+
+    /// Lists the actions.
+    fn list(&self) -> Vec<ScheduleEntry> {
+        self.entries.iter().map(|entry| ScheduleEntry { id: entry.id, trigger: entry.trigger }).collect()
+    }
+
+    /// Removes an action. Returns true if it was scheduled.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Identifier of the action.
+    fn cancel(&mut self, id: u64) -> bool {
+        let count = self.entries.len();
+        self.entries.retain(|entry| entry.id != id);
+        self.entries.len() != count
+    }
+}
+
+/// Locks the actions. A poisoned lock is recovered, as a panicking action leaves the list consistent.
+///
+/// # Arguments
+///
+/// * `entries` - Shared actions.
+fn lock(entries: &Arc<Mutex<Entries>>) -> MutexGuard<'_, Entries> {
+    entries.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Handle of the thread running the scheduler, to list and cancel its actions and receive their errors.
+/// Dropping the handle stops the thread.
+pub struct SchedulerHandle {
+
+    /// Scheduled actions, shared with the thread.
+    entries: Arc<Mutex<Entries>>,
+
+    /// Errors of the failed actions.
+    receiver: Receiver<Error>,
+
+    /// Set to stop the thread.
+    stop: Arc<AtomicBool>,
+
+    /// Thread running the scheduler, giving it back when stopped.
+    thread: Option<JoinHandle<Scheduler>>,
+}
+
+impl SchedulerHandle {
+
+    /// List the scheduled actions, in the order they were added.
+    pub fn entries(&self) -> Vec<ScheduleEntry> {
+        lock(&self.entries).list()
+    }
+
+    /// Cancel an action. Returns true if it was scheduled.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Identifier returned when scheduling.
+    pub fn cancel(&self, id: u64) -> bool {
+        lock(&self.entries).cancel(id)
+    }
+
+    /// Get the receiver of the errors of the failed actions.
+    pub fn errors(&self) -> &Receiver<Error> {
+        &self.receiver
+    }
+
+    /// Stop the thread, within `MAX_WAIT`, and get the scheduler back.
+    pub fn stop(mut self) -> Scheduler {
+        self.stop.store(true, Ordering::SeqCst);
+        self.thread.take().unwrap().join().unwrap()
+    }
+}
+
+impl Drop for SchedulerHandle {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.stop.store(true, Ordering::SeqCst);
+            let _result = thread.join();
+        }
+    }
+}
+
+impl fmt::Debug for SchedulerHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SchedulerHandle")
+            .field("entries", &self.entries())
+            .finish()
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    /// Creates a scheduler on a manual clock set to a time of the first day after the epoch.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - Wall clock time of the day.
+    fn scheduler_at(time: TimeOfDay) -> (Scheduler, ManualClock) {
+        let clock = ManualClock::new();
+        clock.set_system_time(SystemTime::UNIX_EPOCH + Duration::from_nanos(time.nanos() as u64));
+
+        let mut scheduler = Scheduler::new();
+        scheduler.set_clock(clock.clone());
+        (scheduler, clock)
+    }
+
+    /// Tests parsing and formatting the times of the day.
+    #[test]
+    fn test_time_of_day() -> Result<(), Error> {
+        assert!("07:00".parse::<TimeOfDay>()? == TimeOfDay::new(7, 0, 0)?);
+        assert!("22:30:15".parse::<TimeOfDay>()?.to_string() == "22:30:15");
+
+        for invalid in ["24:00", "7", "07:60", "07:00:00:00", "07:xx", ""].iter() {
+            assert!(matches!(invalid.parse::<TimeOfDay>(), Err(Error::InvalidInput(_))), "{}", invalid);
+        }
+
+        Ok(())
+    }
+
+    /// Tests fast-forwarding across a day boundary runs the actions in order on the board.
+    #[test]
+    fn test_scheduler_across_midnight() -> Result<(), Error> {
+        let (mut scheduler, clock) = scheduler_at("06:59".parse()?);
+        let mut hat = RainbowHat::with_simulation()?;
+        let fired = Arc::new(Mutex::new(Vec::new()));
+
+        let log = fired.clone();
+        scheduler.at("07:00".parse()?, move |hat| {
+            log.lock().unwrap().push("amber");
+            hat.lights.rgb(true, true, false);
+            Ok(())
+        });
+        let log = fired.clone();
+        scheduler.at("22:00".parse()?, move |hat| {
+            log.lock().unwrap().push("display off");
+            hat.alphanum.ht16k33.set_display(false)?;
+            hat.lights.all(false);
+            Ok(())
+        });
+        let log = fired.clone();
+        let hourly = scheduler.every(Duration::from_secs(8 * 3600), move |_hat| {
+            log.lock().unwrap().push("8 hours");
+            Ok(())
+        })?;
+
+        // 30 seconds at a time until 07:00:30 the next day
+        scheduler.step(&mut hat)?;
+        for _ in 0..(24 * 120 + 3) {
+            clock.advance(Duration::from_secs(30));
+            scheduler.step(&mut hat)?;
+        }
+        assert!(*fired.lock().unwrap() == ["amber", "8 hours", "display off", "8 hours", "8 hours", "amber"]);
+        assert!(hat.lights.red.state && hat.lights.green.state && !hat.lights.blue.state);
+
+        assert!(scheduler.entries()[2] == ScheduleEntry { id: hourly, trigger: Trigger::Every(Duration::from_secs(8 * 3600)) });
+        assert!(scheduler.cancel(hourly) && !scheduler.cancel(hourly));
+        assert!(scheduler.entries().len() == 2);
+
+        Ok(())
+    }
+
+    /// Tests the adjustments of the wall clock do not run the actions late or twice.
+    #[test]
+    fn test_scheduler_clock_adjustment() -> Result<(), Error> {
+        let (mut scheduler, clock) = scheduler_at("06:00".parse()?);
+        let mut hat = RainbowHat::with_simulation()?;
+        let count = Arc::new(Mutex::new(0));
+
+        let counter = count.clone();
+        scheduler.at("07:00".parse()?, move |_hat| {
+            *counter.lock().unwrap() += 1;
+            Ok(())
+        });
+        scheduler.step(&mut hat)?;
+
+        // Set forward past 07:00: skipped
+        clock.set_system_time(SystemTime::UNIX_EPOCH + Duration::from_secs(10 * 3600));
+        scheduler.step(&mut hat)?;
+        assert!(*count.lock().unwrap() == 0);
+
+        // Set back before 07:00, then the time passes: runs once
+        clock.set_system_time(SystemTime::UNIX_EPOCH + Duration::from_secs(6 * 3600 + 59 * 60 + 59));
+        scheduler.step(&mut hat)?;
+        clock.advance(Duration::from_secs(1));
+        scheduler.step(&mut hat)?;
+        assert!(*count.lock().unwrap() == 1);
+
+        // Set back again by a minute: not run twice
+        clock.set_system_time(SystemTime::UNIX_EPOCH + Duration::from_secs(6 * 3600 + 59 * 60 + 30));
+        scheduler.step(&mut hat)?;
+        clock.advance(Duration::from_secs(40));
+        scheduler.step(&mut hat)?;
+        assert!(*count.lock().unwrap() == 1);
+
+        // In another time zone, 07:00 at UTC+2 is 05:00 UTC
+        scheduler.set_utc_offset(2 * 3600)?;
+        clock.set_system_time(SystemTime::UNIX_EPOCH + Duration::from_secs(86_400 + 4 * 3600 + 59 * 60 + 59));
+        scheduler.step(&mut hat)?;
+        clock.advance(Duration::from_secs(1));
+        scheduler.step(&mut hat)?;
+        assert!(*count.lock().unwrap() == 2);
+        assert!(scheduler.set_utc_offset(15 * 3600).is_err());
+
+        Ok(())
+    }
+
+    /// Tests running the scheduler on a thread, listing and cancelling its actions and receiving their errors.
+    #[test]
+    fn test_scheduler_thread() -> Result<(), Error> {
+        let (mut scheduler, _clock) = scheduler_at("06:59:58".parse()?);
+        let amber = scheduler.at("07:00".parse()?, |hat| {
+            hat.lights.rgb(true, true, false);
+            Ok(())
+        });
+        scheduler.at("07:00:01".parse()?, |_hat| Err(Error::InvalidInput("failed".to_string())));
+        assert!(scheduler.every(Duration::ZERO, |_hat| Ok(())).is_err());
+
+        let hat = RainbowHat::with_simulation()?.into_shared();
+        let handle = scheduler.start(hat.clone());
+
+        // The manual clock moves forward at each wait of the thread
+        let error = handle.errors().recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(error.to_string() == "Invalid input: failed");
+        assert!(hat.with_board(|board| board.lights.red.state && board.lights.green.state));
+
+        assert!(handle.entries().len() == 2);
+        assert!(handle.cancel(amber) && !handle.cancel(amber));
+        assert!(handle.entries().iter().map(|entry| entry.trigger).collect::<Vec<_>>() == [Trigger::Daily("07:00:01".parse()?)]);
+
+        let scheduler = handle.stop();
+        assert!(scheduler.entries().len() == 1);
+
+        Ok(())
+    }
+}
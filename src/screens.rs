@@ -0,0 +1,153 @@
+use std::fmt;
+use crate::alphanum4::{Alphanum4, Error};
+use crate::ht16k33::{BufferedDisplay, HT16K33};
+
+/// Screen rendering into the alphanumeric display.
+pub type Screen<D> = Box<dyn FnMut(&mut Alphanum4<D>) -> Result<(), Error> + Send>;
+
+/// Ordered list of screens shown one at a time on the alphanumeric display,
+/// for apps cycling between views (time, date, temperature...) on button presses.
+/// Send but not Sync, as the screens are only Send.
+pub struct ScreenRotator<D: BufferedDisplay = HT16K33> {
+
+    /// Screens in display order.
+    screens: Vec<Screen<D>>,
+
+    /// Index of the current screen.
+    current: usize,
+}
+
+impl<D: BufferedDisplay> ScreenRotator<D> {
+
+    /// Creates a rotator without screens.
+    pub fn new() -> ScreenRotator<D> {
+        Self {
+            screens: Vec::new(),
+            current: 0,
+        }
+    }
+
+    /// Add a screen after the existing ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `screen` - Closure rendering the screen into the display buffer.
+    pub fn add<F>(&mut self, screen: F) -> &mut Self
+    where
+        F: FnMut(&mut Alphanum4<D>) -> Result<(), Error> + Send + 'static,
+    {
+        self.screens.push(Box::new(screen));
+        self
+    }
+
+    /// Get the number of screens.
+    pub fn len(&self) -> usize {
+        self.screens.len()
+    }
+
+    /// Get whether there are no screens.
+    pub fn is_empty(&self) -> bool {
+        self.screens.is_empty()
+    }
+
+    /// Get the index of the current screen.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Move to the next screen, wrapping after the last one.
+    pub fn next(&mut self) {
+        if !self.screens.is_empty() {
+            self.current = (self.current + 1) % self.screens.len();
+        }
+    }
+
+    /// Move to the previous screen, wrapping before the first one.
+    pub fn prev(&mut self) {
+        if !self.screens.is_empty() {
+            self.current = (self.current + self.screens.len() - 1) % self.screens.len();
+        }
+    }
+
+    /// Render the current screen into the display buffer, returning the error of the screen.
+    /// Call `show()` on the display to output it.
+    ///
+    /// # Arguments
+    ///
+    /// * `alphanum` - Display to render into.
+    pub fn render(&mut self, alphanum: &mut Alphanum4<D>) -> Result<(), Error> {
+        match self.screens.get_mut(self.current) {
+            Some(screen) => screen(alphanum),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<D: BufferedDisplay> Default for ScreenRotator<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: BufferedDisplay> fmt::Debug for ScreenRotator<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScreenRotator")
+            .field("screens", &self.screens.len())
+            .field("current", &self.current)
+            .finish()
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphanum4::Alignment;
+
+    /// Renders the current screen and compares the buffer with the given text printed.
+    fn rendered(rotator: &mut ScreenRotator, text: &str) -> Result<bool, Error> {
+        let mut alphanum = Alphanum4::new()?;
+        rotator.render(&mut alphanum)?;
+
+        let mut expected = Alphanum4::new()?;
+        expected.print_str(text, Alignment::Left)?;
+
+        Ok(alphanum.ht16k33.buffer == expected.ht16k33.buffer)
+    }
+
+    /// Tests advancing and wrapping through three screens.
+    #[test]
+    fn test_screen_rotator_next_prev() -> Result<(), Error> {
+        let mut rotator = ScreenRotator::new();
+        rotator
+            .add(|alphanum| alphanum.print_str("TIME", Alignment::Left))
+            .add(|alphanum| alphanum.print_str("DATE", Alignment::Left))
+            .add(|alphanum| alphanum.print_str("TEMP", Alignment::Left));
+
+        assert!(rotator.len() == 3);
+        assert!(rendered(&mut rotator, "TIME")?);
+
+        rotator.next();
+        assert!(rendered(&mut rotator, "DATE")?);
+
+        rotator.next();
+        assert!(rendered(&mut rotator, "TEMP")?);
+
+        // Wraps to the first screen
+        rotator.next();
+        assert!(rotator.current() == 0);
+        assert!(rendered(&mut rotator, "TIME")?);
+
+        // Wraps back to the last screen
+        rotator.prev();
+        assert!(rotator.current() == 2);
+        assert!(rendered(&mut rotator, "TEMP")?);
+
+        // The error of a screen is returned
+        rotator.add(|alphanum| alphanum.print_str("\u{e9}", Alignment::Left));
+        rotator.next();
+        assert!(matches!(rotator.render(&mut Alphanum4::new()?), Err(Error::InvalidArgument(_))));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,379 @@
+use std::fmt;
+use std::net::SocketAddr;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response};
+use crate::Error;
+use crate::buzzer;
+use crate::commands;
+use crate::rainbow_hat::RainbowHat;
+
+/// Longest note played by `POST /buzzer/note`, in seconds, as the server waits for the end of the note.
+pub const MAX_NOTE_DURATION: f64 = 5.0;
+
+/// HTTP server remote controlling a board with JSON requests:
+///
+/// * `GET /lights`, `PUT /lights` with `{"r": true, "g": false, "b": true}`, each light being optional.
+/// * `PUT /pixels` with an array of colors, or `{"pixels": [...], "brightness": 0.5}`. Each color is
+///   a `[r, g, b]` array, a 0xRRGGBB number, a name or a "#RRGGBB" string. The pixels not given are turned off.
+/// * `PUT /display` with `{"text": "HI", "align": "right"}`, the alignment being left, right or center.
+/// * `POST /buzzer/note` with `{"note": "A4", "duration": 0.3}`, the note being a name or a MIDI note number.
+/// * `GET /buttons`, returning `{"a": false, "b": true, "c": false}`.
+/// * `GET /sensor`, returning `{"temperature_c": 21.5, "pressure_hpa": 1013.2}`.
+///
+/// Invalid requests are answered with status 400 and `{"error": "..."}`, unknown paths with 404,
+/// unsupported methods with 405 and errors of the drivers with 500.
+pub struct Server {
+
+    /// HTTP listener.
+    http: tiny_http::Server,
+
+    /// Board controlled.
+    board: RainbowHat,
+}
+
+impl Server {
+
+    /// Creates a server listening on an address.
+    ///
+    /// # Arguments
+    ///
+    /// * `board` - Board controlled, for example in simulation mode.
+    /// * `addr` - Address to listen on, port 0 choosing a free port.
+    pub fn bind(board: RainbowHat, addr: SocketAddr) -> Result<Server, Error> {
+        let http = tiny_http::Server::http(addr).map_err(|err| Error::Io(std::io::Error::other(err)))?;
+
+        Ok(Self {
+            http,
+            board,
+        })
+    }
+
+    /// Get the address the server listens on.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Get the board controlled.
+    pub fn board(&mut self) -> &mut RainbowHat {
+        &mut self.board
+    }
+
+    /// Wait for the next request and answer it.
+    pub fn handle_next(&mut self) -> Result<(), Error> {
+        let request = self.http.recv()?;
+        self.respond(request)
+    }
+
+    /// Answer requests until the listener fails.
+    pub fn run(mut self) -> Result<(), Error> {
+        loop {
+            self.handle_next()?;
+        }
+    }
+
+    /// Answer a request.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - Request received.
+    fn respond(&mut self, mut request: Request) -> Result<(), Error> {
+        let mut body = String::new();
+
+        let (status, json) = match request.as_reader().read_to_string(&mut body) {
+            Ok(_size) => {
+                let method = match request.method() {
+                    Method::Get => "GET",
+                    Method::Put => "PUT",
+                    Method::Post => "POST",
+                    _ => "",
+                };
+                handle(&mut self.board, method, request.url(), &body)
+            }
+            Err(_err) => (400, json!({ "error": "the body must be UTF-8" })),
+        };
+
+        let response = Response::from_string(json.to_string())
+            .with_status_code(status)
+            .with_header(Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap());
+        request.respond(response)?;
+
+        Ok(())
+    }
+}
+
+/// Serve the HTTP API of `Server` on an address, until the listener fails.
+///
+/// # Arguments
+///
+/// * `board` - Board controlled.
+/// * `addr` - Address to listen on.
+pub fn serve(board: RainbowHat, addr: SocketAddr) -> Result<(), Error> {
+    Server::bind(board, addr)?.run()
+}
+
+/// Answer a request of the HTTP API of `Server` without the network, returning the status code and the JSON body.
+///
+/// # Arguments
+///
+/// * `board` - Board controlled.
+/// * `method` - HTTP method: GET, PUT or POST.
+/// * `url` - Path, with an optional query string which is ignored.
+/// * `body` - JSON body of the request, empty for GET.
+pub fn handle(board: &mut RainbowHat, method: &str, url: &str, body: &str) -> (u16, Value) {
+    let path = url.split('?').next().unwrap_or("");
+
+    let result = match (method, path) {
+        ("GET", "/lights") => Ok(commands::lights_state(board)),
+        ("PUT", "/lights") => commands::set_lights(board, body).map_err(Rejection::from),
+        ("PUT", "/pixels") => commands::set_pixels(board, body).map_err(Rejection::from),
+        ("PUT", "/display") => commands::set_display(board, body).map_err(Rejection::from),
+        ("POST", "/buzzer/note") => post_note(board, body),
+        ("GET", "/buttons") => Ok(commands::buttons_state(board)),
+        ("GET", "/sensor") => commands::sensor_state(board).map_err(Rejection::from),
+        (_, "/lights") | (_, "/pixels") | (_, "/display") | (_, "/buzzer/note") | (_, "/buttons") | (_, "/sensor") => {
+            Err(Rejection::new(405, format!("method {} not allowed on {}", method, path)))
+        }
+        _ => Err(Rejection::new(404, format!("no endpoint {}", path))),
+    };
+
+    match result {
+        Ok(json) => (200, json),
+        Err(rejection) => (rejection.status, json!({ "error": rejection.message })),
+    }
+}
+
+/// Request rejected, with its status code.
+#[derive(Debug)]
+struct Rejection {
+
+    /// HTTP status code.
+    status: u16,
+
+    /// Reason of the rejection.
+    message: String,
+}
+
+impl Rejection {
+
+    /// Creates a rejection.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - HTTP status code.
+    /// * `message` - Reason of the rejection.
+    fn new<M: fmt::Display>(status: u16, message: M) -> Rejection {
+        Rejection {
+            status,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Converts the errors of the board and of each peripheral: invalid input is a bad request,
+/// a disabled peripheral is unavailable, others are internal errors
+impl<E: Into<Error>> From<E> for Rejection {
+    fn from(err: E) -> Rejection {
+        match err.into() {
+            err @ Error::InvalidInput(_) => Rejection::new(400, err),
+            err @ Error::ComponentDisabled(_) => Rejection::new(503, err),
+            err => Rejection::new(500, err),
+        }
+    }
+}
+
+/// Body of `POST /buzzer/note`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoteBody {
+    note: Value,
+    duration: f64,
+}
+
+/// Play a note.
+///
+/// # Arguments
+///
+/// * `board` - Board controlled.
+/// * `body` - JSON body.
+fn post_note(board: &mut RainbowHat, body: &str) -> Result<Value, Rejection> {
+    let note: NoteBody = commands::parse(body)?;
+
+    let note_number = match &note.note {
+        Value::String(name) => buzzer::parse_note_name(name)?,
+        Value::Number(number) => match number.as_u64() {
+            Some(note_number) if (1..=127).contains(&note_number) => note_number as u32,
+            _ => return Err(Rejection::new(400, format!("invalid MIDI note {}, expected 1 to 127", number))),
+        },
+        _ => return Err(Rejection::new(400, "the note must be a name or a MIDI note number")),
+    };
+
+    if !(0.0..=MAX_NOTE_DURATION).contains(&note.duration) {
+        return Err(Rejection::new(400, format!("duration {} out of range 0 to {} s", note.duration, MAX_NOTE_DURATION)));
+    }
+
+    board.buzzer.midi_note(note_number, note.duration)?;
+
+    Ok(json!({ "note": note_number, "duration": note.duration }))
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::touch::ButtonId;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+
+    /// Tests the lights endpoints.
+    #[test]
+    fn test_server_lights() -> Result<(), Error> {
+        let mut board = RainbowHat::with_simulation()?;
+
+        assert!(handle(&mut board, "PUT", "/lights", r#"{"r": true, "g": false, "b": true}"#) == (200, json!({ "r": true, "g": false, "b": true })));
+        assert!(board.lights.red.state && !board.lights.green.state && board.lights.blue.state);
+
+        // The lights not given are kept
+        assert!(handle(&mut board, "PUT", "/lights", r#"{"g": true}"#) == (200, json!({ "r": true, "g": true, "b": true })));
+        assert!(handle(&mut board, "GET", "/lights", "") == (200, json!({ "r": true, "g": true, "b": true })));
+
+        for invalid in ["", "{", r#"{"r": 1}"#, r#"{"x": true}"#].iter() {
+            assert!(handle(&mut board, "PUT", "/lights", invalid).0 == 400);
+        }
+
+        Ok(())
+    }
+
+    /// Tests the pixels endpoint.
+    #[test]
+    fn test_server_pixels() -> Result<(), Error> {
+        let mut board = RainbowHat::with_simulation()?;
+        board.apa102.set_all(255, 255, 255, 1.0)?;
+
+        let (status, json) = handle(&mut board, "PUT", "/pixels", r##"[[255, 0, 0], 65280, "blue", "#ffa500"]"##);
+        assert!(status == 200);
+        assert!(json["pixels"] == json!(["#ff0000", "#00ff00", "#0000ff", "#ffa500", "#000000", "#000000", "#000000"]));
+        assert!(board.apa102.last_frame().is_some());
+
+        let (status, _json) = handle(&mut board, "PUT", "/pixels", r#"{"pixels": ["red"], "brightness": 0.0}"#);
+        assert!(status == 200);
+        assert!(board.apa102.get_pixel(0)? == (255, 0, 0, 0));
+
+        let invalid = [
+            r#"["octarine"]"#,
+            r#"[[256, 0, 0]]"#,
+            r#"[[255, 0]]"#,
+            r#"[16777216]"#,
+            r#"["red", "red", "red", "red", "red", "red", "red", "red"]"#,
+            r#"{"pixels": ["red"], "brightness": 2.0}"#,
+            r#"{"colors": ["red"]}"#,
+            r#""red""#,
+        ];
+        for body in invalid.iter() {
+            assert!(handle(&mut board, "PUT", "/pixels", body).0 == 400, "{}", body);
+        }
+
+        // The rejected requests did not change the pixels
+        assert!(board.apa102.get_pixel(0)? == (255, 0, 0, 0));
+
+        Ok(())
+    }
+
+    /// Tests the display endpoint.
+    #[test]
+    fn test_server_display() -> Result<(), Error> {
+        let mut board = RainbowHat::with_simulation()?;
+
+        assert!(handle(&mut board, "PUT", "/display", r#"{"text": "ABCD"}"#) == (200, json!({ "text": "ABCD" })));
+
+        // The previous text is cleared
+        assert!(handle(&mut board, "PUT", "/display", r#"{"text": "HI", "align": "right"}"#).0 == 200);
+        let mut expected = crate::alphanum4::Alphanum4::with_simulation()?;
+        expected.print_str("HI", crate::alphanum4::Alignment::Right)?;
+        assert!(board.alphanum.ht16k33.buffer == expected.ht16k33.buffer);
+
+        assert!(handle(&mut board, "PUT", "/display", r#"{"text": "HELLO"}"#).0 == 400);
+        assert!(handle(&mut board, "PUT", "/display", r#"{"text": "é"}"#).0 == 400);
+        assert!(handle(&mut board, "PUT", "/display", r#"{}"#).0 == 400);
+
+        Ok(())
+    }
+
+    /// Tests the buzzer endpoint.
+    #[test]
+    fn test_server_note() -> Result<(), Error> {
+        let mut board = RainbowHat::with_simulation()?;
+
+        assert!(handle(&mut board, "POST", "/buzzer/note", r#"{"note": "A4", "duration": 0.1}"#) == (200, json!({ "note": 69, "duration": 0.1 })));
+        assert!(handle(&mut board, "POST", "/buzzer/note", r#"{"note": 72, "duration": 0}"#).0 == 200);
+
+        for invalid in [r#"{"note": "H4", "duration": 0.1}"#, r#"{"note": 0, "duration": 0.1}"#, r#"{"note": "A4", "duration": 60}"#, r#"{"note": "A4"}"#].iter() {
+            assert!(handle(&mut board, "POST", "/buzzer/note", invalid).0 == 400, "{}", invalid);
+        }
+
+        Ok(())
+    }
+
+    /// Tests the buttons and sensor endpoints, and the unknown paths and methods.
+    #[test]
+    fn test_server_inputs() -> Result<(), Error> {
+        let mut board = RainbowHat::with_simulation()?;
+
+        board.buttons.get_mut(ButtonId::B).set_simulated_state(true);
+        assert!(handle(&mut board, "GET", "/buttons", "") == (200, json!({ "a": false, "b": true, "c": false })));
+
+        let (status, json) = handle(&mut board, "GET", "/sensor?unit=c", "");
+        assert!(status == 200);
+        assert!(json["temperature_c"].is_number() && json["pressure_hpa"].is_number());
+
+        assert!(handle(&mut board, "GET", "/unknown", "").0 == 404);
+        assert!(handle(&mut board, "POST", "/buttons", "").0 == 405);
+        assert!(handle(&mut board, "GET", "/pixels", "").0 == 405);
+
+        Ok(())
+    }
+
+    /// Send a request to a server and read the status code and body of the response.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Address of the server.
+    /// * `method` - HTTP method.
+    /// * `path` - Path.
+    /// * `body` - Body of the request.
+    fn send(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}", method, path, body.len(), body).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let status = response[9..12].parse().unwrap();
+        let body = &response[response.find("\r\n\r\n").unwrap() + 4..];
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    /// Tests the endpoints through HTTP with an in-process client.
+    #[test]
+    fn test_server_http() -> Result<(), Error> {
+        let mut server = Server::bind(RainbowHat::with_simulation()?, "127.0.0.1:0".parse().unwrap())?;
+        let addr = server.local_addr().unwrap();
+
+        let requests = thread::spawn(move || {
+            for _i in 0..3 {
+                server.handle_next().unwrap();
+            }
+            server
+        });
+
+        assert!(send(addr, "PUT", "/lights", r#"{"r": true}"#) == (200, json!({ "r": true, "g": false, "b": false })));
+        assert!(send(addr, "PUT", "/display", r#"{"text": "TOOLONG"}"#).0 == 400);
+        assert!(send(addr, "DELETE", "/sensor", "").0 == 405);
+
+        let mut server = requests.join().unwrap();
+        assert!(server.board().lights.red.state);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use std::process;
+use std::thread;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+use signal_hook::low_level;
+use crate::Error;
+use crate::rainbow_hat::SharedRainbowHat;
+
+/// Turn every output of the board off when the process receives SIGINT (Ctrl-C) or SIGTERM, then end it as the
+/// signal would have: clear the multicolour LEDs, turn off the lights, silence the buzzer and clear the display.
+/// The failures of the peripherals are ignored so the others are still turned off.
+/// The signals are handled on a background thread, which waits for the lock of the board held by the application.
+///
+/// # Arguments
+///
+/// * `board` - Board to clean up.
+pub fn install_cleanup_handler(board: &SharedRainbowHat) -> Result<(), Error> {
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    let board = board.clone();
+
+    thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            let _result = board.cleanup();
+
+            // Terminate with the default action of the signal, the exit code being the fallback
+            let _result = low_level::emulate_default_handler(signal);
+            process::exit(128 + signal);
+        }
+    });
+
+    Ok(())
+}
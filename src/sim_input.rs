@@ -0,0 +1,392 @@
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::touch::{ButtonId, Buttons, SimulatedTouch};
+
+/// Time after the last keypress of a key at which its button is released.
+/// Longer than the delay before the keyboard repeats a held key, so holding a key holds the button.
+pub const RELEASE_TIMEOUT: Duration = Duration::from_millis(600);
+
+/// Interval at which the thread pressing the buttons checks whether it is asked to stop.
+const STOP_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Buttons in the order of their keys a, b and c.
+const BUTTONS: [ButtonId; 3] = [ButtonId::A, ButtonId::B, ButtonId::C];
+
+/// Press or release of a button from the keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEvent {
+
+    /// The key of the button was typed while the button was released.
+    Press(ButtonId),
+
+    /// The key of the button was not typed again before the release timeout.
+    Release(ButtonId),
+}
+
+/// Maps the bytes typed on a keyboard to the presses and releases of the buttons, without any terminal or thread.
+/// The keys a, b and c, in any case, press the buttons A, B and C. Typing the key again, or the keyboard
+/// repeating it while held, keeps the button pressed until the key is not typed for the release timeout.
+/// The other bytes, such as the end of lines, are ignored.
+#[derive(Debug, Clone)]
+pub struct KeyMapper {
+
+    /// Time after the last keypress of a key at which its button is released.
+    timeout: Duration,
+
+    /// Instant of the last keypress of each held button, in the order A, B and C.
+    held: [Option<Instant>; 3],
+}
+
+impl KeyMapper {
+
+    /// Creates a mapper with every button released.
+    /// # Arguments
+    ///
+    /// * `timeout` - Time after the last keypress of a key at which its button is released.
+    pub fn new(timeout: Duration) -> KeyMapper {
+        Self {
+            timeout,
+            held: [None; 3],
+        }
+    }
+
+    /// Get the button pressed by a key: a, b or c, in any case.
+    /// # Arguments
+    ///
+    /// * `byte` - Byte typed.
+    pub fn button(byte: u8) -> Option<ButtonId> {
+        match byte.to_ascii_lowercase() {
+            b'a' => Some(ButtonId::A),
+            b'b' => Some(ButtonId::B),
+            b'c' => Some(ButtonId::C),
+            _ => None,
+        }
+    }
+
+    /// Map a byte typed, getting the press of its button if it was released.
+    /// The buttons held longer than the timeout are released first, with `poll`.
+    /// # Arguments
+    ///
+    /// * `byte` - Byte typed.
+    /// * `now` - Instant at which the byte was typed.
+    pub fn feed(&mut self, byte: u8, now: Instant) -> Vec<KeyEvent> {
+        let mut events = self.poll(now);
+
+        if let Some(id) = KeyMapper::button(byte) {
+            let index = KeyMapper::index(id);
+            if self.held[index].is_none() {
+                events.push(KeyEvent::Press(id));
+            }
+            self.held[index] = Some(now);
+        }
+
+        events
+    }
+
+    /// Map bytes typed at the same instant, getting the presses of their buttons.
+    /// # Arguments
+    ///
+    /// * `bytes` - Bytes typed.
+    /// * `now` - Instant at which the bytes were typed.
+    pub fn feed_bytes(&mut self, bytes: &[u8], now: Instant) -> Vec<KeyEvent> {
+        let mut events = Vec::new();
+        for &byte in bytes {
+            events.extend(self.feed(byte, now));
+        }
+        events
+    }
+
+    /// Get the releases of the buttons whose key was not typed for the timeout.
+    /// # Arguments
+    ///
+    /// * `now` - Current instant.
+    pub fn poll(&mut self, now: Instant) -> Vec<KeyEvent> {
+        let mut events = Vec::new();
+        for (index, held) in self.held.iter_mut().enumerate() {
+            if let Some(last) = *held {
+                if now.saturating_duration_since(last) >= self.timeout {
+                    *held = None;
+                    events.push(KeyEvent::Release(BUTTONS[index]));
+                }
+            }
+        }
+        events
+    }
+
+    /// Release every held button, for example when the input ends.
+    pub fn release_all(&mut self) -> Vec<KeyEvent> {
+        let mut events = Vec::new();
+        for (index, held) in self.held.iter_mut().enumerate() {
+            if held.take().is_some() {
+                events.push(KeyEvent::Release(BUTTONS[index]));
+            }
+        }
+        events
+    }
+
+    /// Get the instant at which the next held button is released, None if every button is released.
+    pub fn next_release(&self) -> Option<Instant> {
+        self.held.iter().flatten().min().map(|last| *last + self.timeout)
+    }
+
+    /// Get whether a button is held.
+    /// # Arguments
+    ///
+    /// * `id` - Button.
+    pub fn is_held(&self, id: ButtonId) -> bool {
+        self.held[KeyMapper::index(id)].is_some()
+    }
+
+    /// Get the index of a button in the order A, B and C.
+    /// # Arguments
+    ///
+    /// * `id` - Button.
+    fn index(id: ButtonId) -> usize {
+        match id {
+            ButtonId::A => 0,
+            ButtonId::B => 1,
+            ButtonId::C => 2,
+        }
+    }
+}
+
+/// Binding of keys to the buttons, pressing them from a background thread.
+/// Dropping it stops the thread, releasing the held buttons, like `stop`.
+#[derive(Debug)]
+pub struct KeyBinding {
+
+    /// Flag asking the thread to stop.
+    stop: Arc<AtomicBool>,
+
+    /// Thread pressing the buttons, None once joined.
+    thread: Option<JoinHandle<()>>,
+}
+
+impl KeyBinding {
+
+    /// Stop pressing the buttons, release the held ones and wait for the thread to finish.
+    pub fn stop(mut self) {
+        self.join();
+    }
+
+    /// Get whether the thread has finished, because the input ended.
+    pub fn is_finished(&self) -> bool {
+        match &self.thread {
+            Some(thread) => thread.is_finished(),
+            None => true,
+        }
+    }
+
+    /// Ask the thread to stop and wait for it.
+    fn join(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _result = thread.join();
+        }
+    }
+}
+
+impl Drop for KeyBinding {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+/// Press the buttons with the keys a, b and c typed on the standard input, switching them to simulation mode.
+///
+/// The standard input is read in line mode: the terminal hands the keys over when Enter is typed, so typing
+/// "a" then Enter presses the button A for the release timeout. For the keys to press
+/// the buttons as soon as they are typed, and holding a key to hold its button through the repeats of the
+/// keyboard, put the terminal in raw mode first, for example with `stty -icanon -echo` (`stty sane` to restore it).
+///
+/// The binding ends when the standard input is closed, or when the returned `KeyBinding` is stopped or dropped.
+/// The held buttons are released in both cases. A read of the standard input cannot be interrupted, so the
+/// thread reading it ends with the next byte typed after the binding stopped.
+/// # Arguments
+///
+/// * `buttons` - Buttons to press.
+pub fn bind_keys(buttons: &mut Buttons) -> KeyBinding {
+    bind_reader(buttons, io::stdin(), RELEASE_TIMEOUT)
+}
+
+/// Press the buttons with the keys a, b and c read from any input, switching them to simulation mode.
+/// The binding ends at the end of the input, or when the returned `KeyBinding` is stopped or dropped.
+/// # Arguments
+///
+/// * `buttons` - Buttons to press.
+/// * `input` - Input the keys are read from, such as the standard input.
+/// * `timeout` - Time after the last keypress of a key at which its button is released.
+pub fn bind_reader<R: Read + Send + 'static>(buttons: &mut Buttons, mut input: R, timeout: Duration) -> KeyBinding {
+    buttons.enable_simulation();
+    let touches: [SimulatedTouch; 3] = [buttons.a.simulated_touch(), buttons.b.simulated_touch(), buttons.c.simulated_touch()];
+
+    // The input is read on its own thread, as a read blocks until a key is typed
+    let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+    thread::spawn(move || {
+        let mut buffer = [0u8; 64];
+        loop {
+            match input.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(count) => {
+                    if sender.send(buffer[..count].to_vec()).is_err() {
+                        break;
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+    });
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+
+    let thread = thread::spawn(move || {
+        let mut mapper = KeyMapper::new(timeout);
+        let apply = |events: Vec<KeyEvent>| {
+            for event in events {
+                match event {
+                    KeyEvent::Press(id) => touches[KeyMapper::index(id)].set_pressed(true),
+                    KeyEvent::Release(id) => touches[KeyMapper::index(id)].set_pressed(false),
+                }
+            }
+        };
+
+        while !thread_stop.load(Ordering::SeqCst) {
+            let wait = match mapper.next_release() {
+                Some(release) => release.saturating_duration_since(Instant::now()).min(STOP_INTERVAL),
+                None => STOP_INTERVAL,
+            };
+
+            match receiver.recv_timeout(wait) {
+                Ok(bytes) => apply(mapper.feed_bytes(&bytes, Instant::now())),
+                Err(RecvTimeoutError::Timeout) => apply(mapper.poll(Instant::now())),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        apply(mapper.release_all());
+    });
+
+    KeyBinding { stop, thread: Some(thread) }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::Mutex;
+    use crate::touch::TouchEvent;
+
+    /// Tests the keys press the buttons, ignoring the other bytes.
+    #[test]
+    fn test_key_mapper_press() {
+        let start = Instant::now();
+        let mut mapper = KeyMapper::new(Duration::from_millis(500));
+
+        let events = mapper.feed_bytes(b"a\nxB\r\n", start);
+        assert!(events == [KeyEvent::Press(ButtonId::A), KeyEvent::Press(ButtonId::B)]);
+        assert!(mapper.is_held(ButtonId::A) && mapper.is_held(ButtonId::B) && !mapper.is_held(ButtonId::C));
+        assert!(mapper.next_release() == Some(start + Duration::from_millis(500)));
+
+        // Released together once the timeout elapsed
+        assert!(mapper.poll(start + Duration::from_millis(499)).is_empty());
+        let events = mapper.poll(start + Duration::from_millis(500));
+        assert!(events == [KeyEvent::Release(ButtonId::A), KeyEvent::Release(ButtonId::B)]);
+        assert!(mapper.next_release().is_none());
+    }
+
+    /// Tests repeated keypresses hold the button until the timeout after the last one.
+    #[test]
+    fn test_key_mapper_hold() {
+        let start = Instant::now();
+        let ms = |millis: u64| start + Duration::from_millis(millis);
+        let mut mapper = KeyMapper::new(Duration::from_millis(500));
+
+        assert!(mapper.feed(b'c', ms(0)) == [KeyEvent::Press(ButtonId::C)]);
+        assert!(mapper.feed(b'c', ms(400)).is_empty());
+        assert!(mapper.feed(b'c', ms(800)).is_empty());
+        assert!(mapper.poll(ms(1_200)).is_empty());
+        assert!(mapper.poll(ms(1_300)) == [KeyEvent::Release(ButtonId::C)]);
+
+        // A keypress after the timeout releases then presses again
+        mapper.feed(b'a', ms(2_000));
+        let events = mapper.feed(b'a', ms(2_600));
+        assert!(events == [KeyEvent::Release(ButtonId::A), KeyEvent::Press(ButtonId::A)]);
+
+        assert!(mapper.release_all() == [KeyEvent::Release(ButtonId::A)]);
+        assert!(mapper.release_all().is_empty());
+    }
+
+    /// Tests the binding presses the buttons from a byte stream and ends with it, releasing the buttons.
+    #[test]
+    fn test_bind_reader() -> Result<(), crate::Error> {
+        let mut buttons = Buttons::new()?;
+        let binding = bind_reader(&mut buttons, Cursor::new(b"b".to_vec()), Duration::from_secs(60));
+        assert!(buttons.is_simulation());
+
+        let start = Instant::now();
+        while !binding.is_finished() {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        // The press is released at the end of the input, long before the timeout
+        assert!(!buttons.b.is_pressed() && !buttons.a.is_pressed());
+        binding.stop();
+
+        Ok(())
+    }
+
+    /// Input sending the bytes written to it, and blocking until then.
+    struct Pipe {
+        receiver: Mutex<mpsc::Receiver<u8>>,
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.receiver.lock().unwrap().recv() {
+                Ok(byte) => {
+                    buf[0] = byte;
+                    Ok(1)
+                }
+                Err(_) => Ok(0),
+            }
+        }
+    }
+
+    /// Tests a held key is released after the timeout, and dropping the binding stops it with the input still open.
+    #[test]
+    fn test_bind_reader_timeout_and_drop() -> Result<(), crate::Error> {
+        let mut buttons = Buttons::new()?;
+        let (sender, receiver) = mpsc::channel();
+        let binding = bind_reader(&mut buttons, Pipe { receiver: Mutex::new(receiver) }, Duration::from_millis(300));
+
+        sender.send(b'a').unwrap();
+        let start = Instant::now();
+        while buttons.a.poll_event() != Some(TouchEvent::Pressed) {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(5));
+        }
+        while buttons.a.poll_event() != Some(TouchEvent::Released) {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        // Held again when dropped: released by the drop
+        sender.send(b'c').unwrap();
+        while !buttons.c.is_pressed() {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(5));
+        }
+        drop(binding);
+        assert!(!buttons.c.is_pressed());
+
+        Ok(())
+    }
+}
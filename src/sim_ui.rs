@@ -0,0 +1,564 @@
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use crate::alphanum4::NUM_DIGITS;
+use crate::apa102::NUM_PIXELS;
+use crate::ht16k33::{Command, BUFFER_SIZE};
+use crate::lights::{GPIO_LIGHT_BLUE, GPIO_LIGHT_GREEN, GPIO_LIGHT_RED};
+use crate::rainbow_hat::{Component, RainbowHat};
+use crate::recorder::{Entry, MemoryRecorder, Payload, Recorder};
+use crate::touch::SimulatedTouch;
+use crate::Error;
+
+/// Duration of a button press injected from the keyboard.
+pub const PRESS_DURATION: Duration = Duration::from_millis(150);
+
+/// Interval at which the drawing thread checks whether it is asked to stop.
+const STOP_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Escape sequence moving the cursor home and clearing the terminal.
+const CLEAR: &str = "\x1b[H\x1b[2J";
+
+/// Escape sequence resetting the colors.
+const RESET: &str = "\x1b[0m";
+
+/// Segments of a 14-segment digit drawn in ASCII: bit of the segment, row, column and character.
+/// The digit is drawn on 5 rows of 5 columns.
+const SEGMENTS: [(u16, usize, usize, char); 19] = [
+    (0, 0, 1, '-'), (0, 0, 2, '-'), (0, 0, 3, '-'), // A
+    (1, 1, 4, '|'), // B
+    (2, 3, 4, '|'), // C
+    (3, 4, 1, '-'), (3, 4, 2, '-'), (3, 4, 3, '-'), // D
+    (4, 3, 0, '|'), // E
+    (5, 1, 0, '|'), // F
+    (6, 2, 1, '-'), // G1
+    (7, 2, 3, '-'), // G2
+    (8, 1, 1, '\\'), // H
+    (9, 1, 2, '|'), // J
+    (10, 1, 3, '/'), // K
+    (11, 3, 1, '/'), // L
+    (12, 3, 2, '|'), // M
+    (13, 3, 3, '\\'), // N
+    (14, 4, 4, '.'), // decimal point
+];
+
+/// Picture of the board rebuilt from the operations of the drivers in simulation mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoardView {
+
+    /// Color of each multicolour LED, scaled by its brightness.
+    pub pixels: [(u8, u8, u8); NUM_PIXELS],
+
+    /// State of the red, green and blue lights.
+    pub lights: [bool; 3],
+
+    /// Segments lit on each digit of the display, the decimal point being bit 14.
+    pub digits: [u16; NUM_DIGITS],
+
+    /// Whether the display is turned on.
+    pub display_on: bool,
+
+    /// Frequency and duration of the last tone of the buzzer, None once silenced.
+    pub tone: Option<(f64, Duration)>,
+
+    /// BCM pins of the red, green and blue lights.
+    light_pins: [u8; 3],
+
+    /// RAM of the HT16K33 driving the display.
+    ram: [u8; BUFFER_SIZE],
+}
+
+impl BoardView {
+
+    /// Creates the view of a board with everything off, with the lights on the pins of the Rainbow HAT.
+    pub fn new() -> BoardView {
+        BoardView::with_light_pins([GPIO_LIGHT_RED, GPIO_LIGHT_GREEN, GPIO_LIGHT_BLUE])
+    }
+
+    /// Creates the view of a board with everything off.
+    /// # Arguments
+    ///
+    /// * `light_pins` - BCM pins of the red, green and blue lights.
+    pub fn with_light_pins(light_pins: [u8; 3]) -> BoardView {
+        Self {
+            pixels: [(0, 0, 0); NUM_PIXELS],
+            lights: [false; 3],
+            digits: [0; NUM_DIGITS],
+            display_on: true,
+            tone: None,
+            light_pins,
+            ram: [0; BUFFER_SIZE],
+        }
+    }
+
+    /// Creates the view of a board by replaying operations, for example the `recording()` of a `RainbowHat`.
+    /// # Arguments
+    ///
+    /// * `entries` - Operations, oldest first.
+    pub fn from_entries(entries: &[Entry]) -> BoardView {
+        let mut view = BoardView::new();
+        for entry in entries {
+            view.apply(entry.component, entry.operation, &entry.payload);
+        }
+        view
+    }
+
+    /// Update the view with an operation of a driver.
+    /// Returns whether the view changed.
+    /// # Arguments
+    ///
+    /// * `component` - Peripheral of the driver.
+    /// * `operation` - Name of the operation.
+    /// * `payload` - Data of the operation.
+    pub fn apply(&mut self, component: Component, operation: &str, payload: &Payload) -> bool {
+        let before = self.clone();
+
+        match (component, payload) {
+            (Component::Pixels, Payload::Frame(frame)) => {
+                for (pixel, [brightness, b, g, r]) in self.pixels.iter_mut().zip(frame) {
+                    let scale = |value: u8| (value as u16 * (*brightness & 0x1F) as u16 / 31) as u8;
+                    *pixel = (scale(*r), scale(*g), scale(*b));
+                }
+            }
+            (Component::Lights, Payload::Level { pin, high }) => {
+                if let Some(index) = self.light_pins.iter().position(|light_pin| light_pin == pin) {
+                    self.lights[index] = *high;
+                }
+            }
+            (Component::Display, Payload::Write { register, data }) => match Command::decode(*register, data) {
+                Some(Command::WriteRam { offset, data }) => {
+                    for (byte, value) in self.ram.iter_mut().skip(offset as usize).zip(data) {
+                        *byte = *value;
+                    }
+                    for (pos, digit) in self.digits.iter_mut().enumerate() {
+                        *digit = self.ram[pos * 2] as u16 | (self.ram[pos * 2 + 1] as u16) << 8;
+                    }
+                }
+                Some(Command::DisplaySetup { on, .. }) => self.display_on = on,
+                _ => {}
+            },
+            (Component::Buzzer, Payload::Tone { frequency, duration }) => self.tone = Some((*frequency, *duration)),
+            (Component::Buzzer, Payload::None) if operation == "stop" => self.tone = None,
+            _ => {}
+        }
+
+        *self != before
+    }
+
+    /// Render the view as text for a terminal, the colors being ANSI escape sequences:
+    /// the multicolour LEDs as blocks, the lights as dots, the display in ASCII and the tone of the buzzer.
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+
+        text.push_str("Pixels   ");
+        for (r, g, b) in self.pixels.iter() {
+            let _result = write!(text, "\x1b[48;2;{};{};{}m    {} ", r, g, b, RESET);
+        }
+        text.push('\n');
+
+        text.push_str("Lights   ");
+        for (on, color) in self.lights.iter().zip([31, 32, 34]) {
+            if *on {
+                let _result = write!(text, "\x1b[{}m●{} ", color, RESET);
+            } else {
+                text.push_str("○ ");
+            }
+        }
+        text.push('\n');
+
+        text.push_str("Display\n");
+        let digits = if self.display_on { self.digits } else { [0; NUM_DIGITS] };
+        let drawings: Vec<[[char; 5]; 5]> = digits.iter().map(|digit| BoardView::draw_digit(*digit)).collect();
+        for row in 0..5 {
+            let line: Vec<String> = drawings.iter().map(|drawing| drawing[row].iter().collect()).collect();
+            let _result = writeln!(text, "{}", format!("  {}", line.join("  ")).trim_end());
+        }
+
+        match self.tone {
+            Some((frequency, duration)) => {
+                let _result = writeln!(text, "Buzzer   {:.1} Hz for {:.2} s", frequency, duration.as_secs_f64());
+            }
+            None => text.push_str("Buzzer   off\n"),
+        }
+
+        text
+    }
+
+    /// Draws a digit of the display in ASCII.
+    /// # Arguments
+    ///
+    /// * `bitmask` - Segments lit.
+    fn draw_digit(bitmask: u16) -> [[char; 5]; 5] {
+        let mut drawing = [[' '; 5]; 5];
+        for (bit, row, column, character) in SEGMENTS.iter() {
+            if bitmask & (1 << bit) != 0 {
+                drawing[*row][*column] = *character;
+            }
+        }
+        drawing
+    }
+}
+
+impl Default for BoardView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// View shared by the clones of a `ViewRecorder`.
+#[derive(Debug)]
+struct SharedView {
+
+    /// picture of the board
+    view: BoardView,
+
+    /// whether the view changed since it was last drawn
+    changed: bool,
+}
+
+/// Recorder keeping the operations in memory like a `MemoryRecorder`, while updating a view of the board.
+/// Clones share the timeline and the view.
+#[derive(Debug, Clone)]
+pub struct ViewRecorder {
+
+    /// operations recorded
+    timeline: MemoryRecorder,
+
+    /// view of the board, with the condition notified when it changes
+    view: Arc<(Mutex<SharedView>, Condvar)>,
+}
+
+impl ViewRecorder {
+
+    /// Creates a recorder updating a view of the board.
+    /// # Arguments
+    ///
+    /// * `view` - Initial view.
+    pub fn new(view: BoardView) -> ViewRecorder {
+        Self {
+            timeline: MemoryRecorder::new(),
+            view: Arc::new((Mutex::new(SharedView { view, changed: true }), Condvar::new())),
+        }
+    }
+
+    /// Get the current view of the board.
+    pub fn view(&self) -> BoardView {
+        self.lock().view.clone()
+    }
+
+    /// Wait for the view to change since it was last returned by this function.
+    /// Returns None if it did not change within the timeout.
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum duration to wait.
+    fn wait_for_change(&self, timeout: Duration) -> Option<BoardView> {
+        let (_, condvar) = &*self.view;
+        let mut shared = self.lock();
+        if !shared.changed {
+            shared = condvar.wait_timeout(shared, timeout)
+                .map(|(shared, _)| shared)
+                .unwrap_or_else(|poisoned| poisoned.into_inner().0);
+        }
+
+        if shared.changed {
+            shared.changed = false;
+            Some(shared.view.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Locks the view. A poisoned lock is recovered as the view stays consistent between calls.
+    fn lock(&self) -> MutexGuard<'_, SharedView> {
+        self.view.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Recorder for ViewRecorder {
+    fn record(&self, component: Component, operation: &'static str, payload: Payload) {
+        {
+            let mut shared = self.lock();
+            if shared.view.apply(component, operation, &payload) {
+                shared.changed = true;
+                self.view.1.notify_all();
+            }
+        }
+        self.timeline.record(component, operation, payload);
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        self.timeline.entries()
+    }
+
+    fn visit(&self, visit: &mut dyn FnMut(&Entry)) {
+        self.timeline.visit(visit);
+    }
+
+    fn retain(&self, keep: &mut dyn FnMut(&Entry) -> bool) {
+        self.timeline.retain(keep);
+    }
+}
+
+/// Keys injecting presses of the buttons of a simulated board.
+#[derive(Debug, Clone)]
+pub struct Keys {
+
+    /// Handles pressing the buttons A, B and C.
+    touches: [SimulatedTouch; 3],
+}
+
+impl Keys {
+
+    /// Creates the keys pressing the buttons of the board.
+    /// # Arguments
+    ///
+    /// * `hat` - Board in simulation mode.
+    pub fn new(hat: &RainbowHat) -> Keys {
+        Self {
+            touches: [hat.buttons.a.simulated_touch(), hat.buttons.b.simulated_touch(), hat.buttons.c.simulated_touch()],
+        }
+    }
+
+    /// Get the handle pressing the button of a key: a, b or c, in any case.
+    /// # Arguments
+    ///
+    /// * `key` - Key typed.
+    pub fn touch(&self, key: char) -> Option<&SimulatedTouch> {
+        match key.to_ascii_lowercase() {
+            'a' => Some(&self.touches[0]),
+            'b' => Some(&self.touches[1]),
+            'c' => Some(&self.touches[2]),
+            _ => None,
+        }
+    }
+
+    /// Press the button of a key for `PRESS_DURATION`, then release it.
+    /// Returns whether the key is the one of a button.
+    /// # Arguments
+    ///
+    /// * `key` - Key typed.
+    pub fn press(&self, key: char) -> bool {
+        match self.touch(key) {
+            Some(touch) => {
+                touch.set_pressed(true);
+                thread::sleep(PRESS_DURATION);
+                touch.set_pressed(false);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Live view of a simulated board in a terminal, redrawn by a background thread when the board changes.
+/// The view is rebuilt from the operations the drivers report to their shared recorder.
+#[derive(Debug)]
+pub struct LiveView {
+
+    /// recorder given to the board
+    recorder: ViewRecorder,
+
+    /// flag asking the drawing thread to stop
+    stop: Arc<AtomicBool>,
+
+    /// thread drawing the view
+    thread: JoinHandle<()>,
+}
+
+impl LiveView {
+
+    /// Draw a simulated board on the standard output, and press its buttons with the keys a, b and c
+    /// typed on the standard input, each line being read when Enter is typed.
+    /// The thread reading the keys ends with the standard input.
+    /// # Arguments
+    ///
+    /// * `hat` - Board in simulation mode.
+    pub fn attach(hat: &mut RainbowHat) -> Result<LiveView, Error> {
+        let live_view = LiveView::with_output(hat, io::stdout())?;
+
+        let keys = Keys::new(hat);
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                for key in line.chars() {
+                    keys.press(key);
+                }
+            }
+        });
+
+        Ok(live_view)
+    }
+
+    /// Draw a simulated board on an output.
+    /// # Arguments
+    ///
+    /// * `hat` - Board in simulation mode.
+    /// * `output` - Output the view is drawn to, such as a terminal.
+    pub fn with_output<W: Write + Send + 'static>(hat: &mut RainbowHat, mut output: W) -> Result<LiveView, Error> {
+        if !hat.is_simulation() {
+            return Err(Error::Unsupported("live view of a board not in simulation mode".to_string()));
+        }
+
+        let light_pins = [hat.lights.red.bcm_pin, hat.lights.green.bcm_pin, hat.lights.blue.bcm_pin];
+        let recorder = ViewRecorder::new(BoardView::with_light_pins(light_pins));
+        hat.set_recorder(recorder.clone());
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread_recorder = recorder.clone();
+
+        let thread = thread::spawn(move || {
+            let mut draw = |view: BoardView| {
+                let _result = write!(output, "{}{}", CLEAR, view.render());
+                let _result = output.flush();
+            };
+
+            while !thread_stop.load(Ordering::SeqCst) {
+                if let Some(view) = thread_recorder.wait_for_change(STOP_INTERVAL) {
+                    draw(view);
+                }
+            }
+
+            // Draw the last changes before stopping
+            if let Some(view) = thread_recorder.wait_for_change(Duration::ZERO) {
+                draw(view);
+            }
+        });
+
+        Ok(LiveView { recorder, stop, thread })
+    }
+
+    /// Get the current view of the board.
+    pub fn view(&self) -> BoardView {
+        self.recorder.view()
+    }
+
+    /// Stop drawing the view and wait for the drawing thread to finish.
+    /// The board keeps reporting its operations to the recorder of the view.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let _result = self.thread.join();
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphanum4::Alignment;
+    use crate::touch::TouchEvent;
+
+    /// Output sharing the bytes written.
+    #[derive(Debug, Clone)]
+    struct SharedOutput {
+        bytes: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.bytes.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Tests rendering the view rebuilt from the operations of a simulated board.
+    #[test]
+    fn test_board_view_render() -> Result<(), Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+        assert!(BoardView::from_entries(&hat.recording()) == BoardView::new());
+
+        hat.apa102.set_pixel(0, 255, 0, 0, 1.0)?;
+        hat.apa102.set_pixel(1, 0, 255, 0, 1.0)?;
+        hat.apa102.show()?;
+        hat.lights.green.on();
+        hat.alphanum.print_str("A1X5", Alignment::Left)?;
+        hat.alphanum.show()?;
+        hat.buzzer.note(880.0, 0.1)?;
+
+        let view = BoardView::from_entries(&hat.recording());
+        assert!(view.pixels[0] == (255, 0, 0));
+        assert!(view.pixels[1] == (0, 255, 0));
+        assert!(view.lights == [false, true, false]);
+        assert!(view.tone == Some((880.0, Duration::from_millis(100))));
+
+        let text = view.render();
+        assert!(text.contains("\x1b[48;2;255;0;0m    \x1b[0m"));
+        assert!(text.contains("○ \x1b[32m●\x1b[0m ○"));
+        assert!(text.contains("Buzzer   880.0 Hz for 0.10 s"));
+
+        let display: Vec<&str> = text.lines().skip(3).take(5).collect();
+        assert!(display == [
+            "   ---                  ---",
+            "  |   |      |   \\ /   |",
+            "   - -                  -",
+            "  |   |      |   / \\      \\",
+            "                        ---",
+        ]);
+
+        // The colors are scaled by the brightness of each pixel
+        let mut view = BoardView::new();
+        assert!(view.apply(Component::Pixels, "show", &Payload::Frame(vec![[31, 0, 0, 200], [15, 0, 200, 0]])));
+        assert!(view.pixels[..2] == [(200, 0, 0), (0, 96, 0)]);
+        assert!(!view.apply(Component::Pixels, "show", &Payload::Frame(vec![[31, 0, 0, 200], [15, 0, 200, 0]])));
+
+        // Turning the display off blanks it, silencing the buzzer clears its tone
+        hat.alphanum.ht16k33.set_display(false)?;
+        hat.buzzer.stop()?;
+        let text = BoardView::from_entries(&hat.recording()).render();
+        assert!(text.contains("Display\n\n\n\n\n\nBuzzer   off\n"));
+
+        Ok(())
+    }
+
+    /// Tests the live view draws the board when it changes, and stops.
+    #[test]
+    fn test_live_view() -> Result<(), Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+        let output = SharedOutput { bytes: Arc::new(Mutex::new(Vec::new())) };
+        let live_view = LiveView::with_output(&mut hat, output.clone())?;
+
+        hat.lights.red.on();
+        assert!(live_view.view().lights == [true, false, false]);
+        assert!(hat.recording().len() == 1);
+        live_view.stop();
+
+        let text = String::from_utf8(output.bytes.lock().unwrap().clone()).unwrap();
+        assert!(text.starts_with(CLEAR));
+        assert!(text.ends_with(&BoardView::from_entries(&hat.recording()).render()));
+
+        // A board accessing the hardware can't be viewed
+        let mut hat = RainbowHat::new()?;
+        if !hat.is_simulation() {
+            assert!(matches!(LiveView::with_output(&mut hat, output), Err(Error::Unsupported(_))));
+        }
+
+        Ok(())
+    }
+
+    /// Tests the keys press the buttons of the board.
+    #[test]
+    fn test_keys() -> Result<(), Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+        let keys = Keys::new(&hat);
+        assert!(keys.touch('x').is_none());
+        assert!(!keys.press('x'));
+
+        keys.touch('B').unwrap().set_pressed(true);
+        assert!(hat.buttons.b.poll_event() == Some(TouchEvent::Pressed));
+        assert!(!hat.buttons.a.is_pressed());
+        keys.touch('b').unwrap().set_pressed(false);
+        assert!(hat.buttons.b.poll_event() == Some(TouchEvent::Released));
+
+        Ok(())
+    }
+}
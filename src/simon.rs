@@ -0,0 +1,328 @@
+use std::thread;
+use std::time::Duration;
+use crate::Error;
+use crate::error::invalid_input;
+use crate::alphanum4::Alignment;
+use crate::rainbow_hat::RainbowHat;
+use crate::rng::{Rng, SimpleRng};
+use crate::touch::{ButtonId, TouchEvent};
+
+/// Default number of rounds to win a game.
+pub const DEFAULT_ROUNDS: usize = 10;
+
+/// Default duration of a flash of the sequence.
+pub const DEFAULT_FLASH_DURATION: Duration = Duration::from_millis(400);
+
+/// Default pause between two flashes of the sequence.
+pub const DEFAULT_GAP: Duration = Duration::from_millis(200);
+
+/// MIDI note played when the game is lost.
+pub const LOSE_NOTE: u32 = 40;
+
+/// Result of a button press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+
+    /// The press was correct, more presses are expected to complete the round.
+    Correct,
+
+    /// The round was completed, the longer sequence of the next round was played.
+    NextRound,
+
+    /// The last round was completed.
+    Won,
+
+    /// The press was wrong.
+    Lost,
+}
+
+/// Simon memory game: repeat a growing sequence of colors flashed on the lights with a tone,
+/// by pressing the button under each light. The score is shown on the display.
+/// Button A plays red, B green and C blue.
+#[derive(Debug, Clone)]
+pub struct SimonGame {
+
+    /// Sequence of the last round, the rounds use its beginning.
+    sequence: Vec<ButtonId>,
+
+    /// Number of completed rounds.
+    score: usize,
+
+    /// Number of presses already repeated in the current round.
+    position: usize,
+
+    /// Outcome ending the game, if over.
+    result: Option<Outcome>,
+
+    /// Duration of a flash of the sequence.
+    flash_duration: Duration,
+
+    /// Pause between two flashes of the sequence.
+    gap: Duration,
+}
+
+impl SimonGame {
+
+    /// Creates a game. The same seed always gives the same sequence.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - Seed of the sequence.
+    /// * `rounds` - Number of rounds to win, at least 1.
+    pub fn new(seed: u64, rounds: usize) -> Result<SimonGame, Error> {
+        if rounds == 0 {
+            return Err(Error::InvalidInput(invalid_input("rounds", rounds, "at least 1")));
+        }
+
+        let mut rng = SimpleRng::new(seed);
+        let sequence = (0..rounds).map(|_| {
+            match rng.next_u64() % 3 {
+                0 => ButtonId::A,
+                1 => ButtonId::B,
+                _ => ButtonId::C,
+            }
+        }).collect();
+
+        Ok(Self {
+            sequence,
+            score: 0,
+            position: 0,
+            result: None,
+            flash_duration: DEFAULT_FLASH_DURATION,
+            gap: DEFAULT_GAP,
+        })
+    }
+
+    /// Set the timings of the sequence.
+    ///
+    /// # Arguments
+    ///
+    /// * `flash_duration` - Duration of a flash.
+    /// * `gap` - Pause between two flashes.
+    pub fn set_timings(&mut self, flash_duration: Duration, gap: Duration) {
+        self.flash_duration = flash_duration;
+        self.gap = gap;
+    }
+
+    /// Get the sequence to repeat in the current round.
+    pub fn sequence(&self) -> &[ButtonId] {
+        &self.sequence[..(self.score + 1).min(self.sequence.len())]
+    }
+
+    /// Get the number of completed rounds.
+    pub fn score(&self) -> usize {
+        self.score
+    }
+
+    /// Get the outcome ending the game: `Won` or `Lost`, None while playing.
+    pub fn result(&self) -> Option<Outcome> {
+        self.result
+    }
+
+    /// Start the game from the first round: shows the score and plays the first sequence.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board to play on.
+    pub fn start(&mut self, hat: &mut RainbowHat) -> Result<(), Error> {
+        self.score = 0;
+        self.position = 0;
+        self.result = None;
+
+        self.show_score(hat)?;
+        self.play_sequence(hat)
+    }
+
+    /// Play the sequence of the current round.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board to play on.
+    pub fn play_sequence(&mut self, hat: &mut RainbowHat) -> Result<(), Error> {
+        for i in 0..self.sequence().len() {
+            if i > 0 {
+                thread::sleep(self.gap);
+            }
+            self.flash(hat, self.sequence[i])?;
+        }
+
+        Ok(())
+    }
+
+    /// Handle a button press: the press is flashed, then checked against the sequence.
+    /// Once the game is over, presses are ignored and the final outcome is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board to play on.
+    /// * `pressed` - Button pressed.
+    pub fn step(&mut self, hat: &mut RainbowHat, pressed: ButtonId) -> Result<Outcome, Error> {
+        if let Some(result) = self.result {
+            return Ok(result);
+        }
+
+        if pressed != self.sequence[self.position] {
+            self.result = Some(Outcome::Lost);
+            hat.buzzer.midi_note(LOSE_NOTE, self.flash_duration.as_secs_f64() * 2.0)?;
+            return Ok(Outcome::Lost);
+        }
+
+        self.flash(hat, pressed)?;
+        self.position += 1;
+
+        if self.position < self.sequence().len() {
+            return Ok(Outcome::Correct);
+        }
+
+        self.score += 1;
+        self.position = 0;
+        self.show_score(hat)?;
+
+        if self.score == self.sequence.len() {
+            self.result = Some(Outcome::Won);
+            hat.alphanum.print_str("WIN ", Alignment::Left)?;
+            hat.alphanum.show()?;
+            return Ok(Outcome::Won);
+        }
+
+        thread::sleep(self.flash_duration);
+        self.play_sequence(hat)?;
+
+        Ok(Outcome::NextRound)
+    }
+
+    /// Read the buttons once and handle a new press, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board to play on.
+    pub fn poll(&mut self, hat: &mut RainbowHat) -> Result<Option<Outcome>, Error> {
+        for &id in [ButtonId::A, ButtonId::B, ButtonId::C].iter() {
+            if let Some(TouchEvent::Pressed) = hat.buttons.get_mut(id).poll_event() {
+                return self.step(hat, id).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Light the color of a button while playing its tone.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board to play on.
+    /// * `id` - Button whose color is flashed.
+    fn flash(&self, hat: &mut RainbowHat, id: ButtonId) -> Result<(), Error> {
+        let (light, note) = match id {
+            ButtonId::A => (&mut hat.lights.red, 64),
+            ButtonId::B => (&mut hat.lights.green, 69),
+            ButtonId::C => (&mut hat.lights.blue, 72),
+        };
+
+        light.on();
+        let result = hat.buzzer.midi_note(note, self.flash_duration.as_secs_f64());
+        light.off();
+
+        Ok(result?)
+    }
+
+    /// Show the score on the display.
+    fn show_score(&self, hat: &mut RainbowHat) -> Result<(), Error> {
+        hat.alphanum.print_str(&format!("{:>4}", self.score), Alignment::Left)?;
+        hat.alphanum.show()?;
+
+        Ok(())
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphanum4::Alphanum4;
+
+    /// Creates a board in simulation mode and a game without pauses.
+    fn setup(seed: u64, rounds: usize) -> Result<(RainbowHat, SimonGame), Error> {
+        let hat = RainbowHat::with_simulation()?;
+        let mut game = SimonGame::new(seed, rounds)?;
+        game.set_timings(Duration::from_millis(0), Duration::from_millis(0));
+
+        Ok((hat, game))
+    }
+
+    /// Tests the same seed gives the same sequence.
+    #[test]
+    fn test_simon_sequence_seeded() -> Result<(), Error> {
+        let game = SimonGame::new(42, 20)?;
+
+        assert!(game.sequence().len() == 1);
+        assert!(game.sequence.len() == 20);
+        assert!(game.sequence == SimonGame::new(42, 20)?.sequence);
+        assert!(game.sequence != SimonGame::new(7, 20)?.sequence);
+        assert!(game.sequence.contains(&ButtonId::A));
+        assert!(game.sequence.contains(&ButtonId::B));
+        assert!(game.sequence.contains(&ButtonId::C));
+
+        assert!(matches!(SimonGame::new(42, 0), Err(Error::InvalidInput(_))));
+
+        Ok(())
+    }
+
+    /// Tests winning a short game by repeating every sequence, pressing the simulated buttons.
+    #[test]
+    fn test_simon_win() -> Result<(), Error> {
+        let (mut hat, mut game) = setup(1, 3)?;
+        game.start(&mut hat)?;
+
+        let mut outcomes = Vec::new();
+        while game.result().is_none() {
+            for id in game.sequence().to_vec() {
+                hat.buttons.get_mut(id).set_simulated_state(true);
+                outcomes.push(game.poll(&mut hat)?.unwrap());
+                hat.buttons.get_mut(id).set_simulated_state(false);
+                assert!(game.poll(&mut hat)?.is_none());
+            }
+        }
+
+        assert!(outcomes == [
+            Outcome::NextRound,
+            Outcome::Correct, Outcome::NextRound,
+            Outcome::Correct, Outcome::Correct, Outcome::Won,
+        ]);
+        assert!(game.score() == 3);
+
+        let mut expected = Alphanum4::new()?;
+        expected.print_str("WIN ", Alignment::Left)?;
+        assert!(hat.alphanum.ht16k33.buffer == expected.ht16k33.buffer);
+        assert!(!hat.lights.red.state && !hat.lights.green.state && !hat.lights.blue.state);
+
+        Ok(())
+    }
+
+    /// Tests losing on a wrong press, the score of the completed rounds staying on the display.
+    #[test]
+    fn test_simon_lose() -> Result<(), Error> {
+        let (mut hat, mut game) = setup(1, 5)?;
+        game.start(&mut hat)?;
+
+        let first = game.sequence()[0];
+        assert!(game.step(&mut hat, first)? == Outcome::NextRound);
+
+        let wrong = match game.sequence()[0] {
+            ButtonId::A => ButtonId::B,
+            _ => ButtonId::A,
+        };
+        assert!(game.step(&mut hat, wrong)? == Outcome::Lost);
+        assert!(game.result() == Some(Outcome::Lost));
+        assert!(game.score() == 1);
+
+        // Presses are ignored once the game is over
+        assert!(game.step(&mut hat, first)? == Outcome::Lost);
+
+        let mut expected = Alphanum4::new()?;
+        expected.print_str("   1", Alignment::Left)?;
+        assert!(hat.alphanum.ht16k33.buffer == expected.ht16k33.buffer);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,384 @@
+use std::time::{Duration, Instant};
+use crate::Error;
+use crate::bmp280::Calibration;
+use crate::rainbow_hat::{Component, RainbowHat};
+use crate::recorder::{Entry, MemoryRecorder, Recorder};
+use crate::touch::ButtonId;
+
+pub use crate::clock::{Clock, ManualClock};
+
+/// Calibration of the sensor simulated by the scenarios, compensating the raw measurements linearly:
+/// the temperature is the raw temperature divided by 16384, the pressure in Pascal 1048576 minus the raw pressure.
+const LINEAR_CALIBRATION: Calibration = Calibration {
+    dig_t1: 0,
+    dig_t2: 5120,
+    dig_t3: 0,
+    dig_p1: 6250,
+    dig_p2: 0,
+    dig_p3: 0,
+    dig_p4: 0,
+    dig_p5: 0,
+    dig_p6: 0,
+    dig_p7: 0,
+    dig_p8: 0,
+    dig_p9: 0,
+};
+
+/// Input of a scenario applied to the board.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScenarioAction {
+
+    /// Touch a button.
+    Press(ButtonId),
+
+    /// Release a button.
+    Release(ButtonId),
+
+    /// Set the measurements of the sensor.
+    Sensor {
+
+        /// Temperature, in degrees Celsius.
+        temperature_c: f32,
+
+        /// Pressure, in hectopascal.
+        pressure_hpa: f32,
+    },
+
+    /// Only let the time pass.
+    Advance,
+}
+
+/// Input of a scenario with the delay since the previous one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScenarioStep {
+
+    /// Delay since the previous input, or since the start of the scenario for the first one.
+    pub delay: Duration,
+
+    /// Input applied once the delay has elapsed.
+    pub action: ScenarioAction,
+}
+
+/// Builder of a `Scenario`, scheduling the inputs at offsets relative to the previous one.
+#[derive(Debug, Clone, Default)]
+pub struct ScenarioBuilder {
+
+    /// inputs, in the order they are applied
+    steps: Vec<ScenarioStep>,
+}
+
+impl ScenarioBuilder {
+
+    /// Creates a builder without input.
+    pub fn new() -> ScenarioBuilder {
+        Self::default()
+    }
+
+    /// Touch a button.
+    ///
+    /// # Arguments
+    ///
+    /// * `delay` - Delay since the previous input.
+    /// * `button` - Button touched.
+    pub fn press(self, delay: Duration, button: ButtonId) -> Self {
+        self.step(delay, ScenarioAction::Press(button))
+    }
+
+    /// Release a button.
+    ///
+    /// # Arguments
+    ///
+    /// * `delay` - Delay since the previous input.
+    /// * `button` - Button released.
+    pub fn release(self, delay: Duration, button: ButtonId) -> Self {
+        self.step(delay, ScenarioAction::Release(button))
+    }
+
+    /// Touch a button then release it.
+    ///
+    /// # Arguments
+    ///
+    /// * `delay` - Delay between the previous input and the touch.
+    /// * `button` - Button tapped.
+    /// * `hold` - Duration of the touch.
+    pub fn tap(self, delay: Duration, button: ButtonId, hold: Duration) -> Self {
+        self.press(delay, button).release(hold, button)
+    }
+
+    /// Set the measurements of the sensor, read until the next ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `delay` - Delay since the previous input.
+    /// * `temperature_c` - Temperature, in degrees Celsius.
+    /// * `pressure_hpa` - Pressure, in hectopascal.
+    pub fn sensor(self, delay: Duration, temperature_c: f32, pressure_hpa: f32) -> Self {
+        self.step(delay, ScenarioAction::Sensor { temperature_c, pressure_hpa })
+    }
+
+    /// Let the time pass, the application being run once more at the end.
+    ///
+    /// # Arguments
+    ///
+    /// * `delay` - Delay since the previous input.
+    pub fn advance(self, delay: Duration) -> Self {
+        self.step(delay, ScenarioAction::Advance)
+    }
+
+    /// Add an input.
+    ///
+    /// # Arguments
+    ///
+    /// * `delay` - Delay since the previous input.
+    /// * `action` - Input to apply.
+    pub fn step(mut self, delay: Duration, action: ScenarioAction) -> Self {
+        self.steps.push(ScenarioStep { delay, action });
+        self
+    }
+
+    /// Creates the scenario, with its own manual clock and recorder.
+    pub fn build(self) -> Scenario {
+        let clock = ManualClock::new();
+
+        Scenario {
+            steps: self.steps,
+            start: clock.now(),
+            recorder: MemoryRecorder::with_clock(clock.clone()),
+            clock,
+        }
+    }
+}
+
+/// Scripted inputs played on a simulated board, for testing an application built on this crate without waiting:
+/// the time only passes when the scenario advances its manual clock, and the operations of the drivers are recorded
+/// in a single timeline stamped with it.
+#[derive(Debug, Clone)]
+pub struct Scenario {
+
+    /// inputs, in the order they are applied
+    steps: Vec<ScenarioStep>,
+
+    /// clock of the board, advanced by the delays of the inputs
+    clock: ManualClock,
+
+    /// instant at which the scenario starts
+    start: Instant,
+
+    /// recorder of the operations of the board, stamped with the clock
+    recorder: MemoryRecorder,
+}
+
+impl Scenario {
+
+    /// Creates a builder of scenario.
+    pub fn builder() -> ScenarioBuilder {
+        ScenarioBuilder::new()
+    }
+
+    /// Get the inputs, in the order they are applied.
+    pub fn steps(&self) -> &[ScenarioStep] {
+        &self.steps
+    }
+
+    /// Get the clock of the scenario. Clones share its time.
+    pub fn clock(&self) -> &ManualClock {
+        &self.clock
+    }
+
+    /// Get the recorder of the operations of the board. Clones share its timeline.
+    pub fn recorder(&self) -> &MemoryRecorder {
+        &self.recorder
+    }
+
+    /// Get the time elapsed since the start of the scenario.
+    pub fn elapsed(&self) -> Duration {
+        self.clock.now() - self.start
+    }
+
+    /// Creates a board in simulation mode using the clock and the recorder of the scenario.
+    pub fn board(&self) -> Result<RainbowHat, Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+        self.attach(&mut hat);
+        Ok(hat)
+    }
+
+    /// Give a board the clock and the recorder of the scenario.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board, in simulation mode.
+    pub fn attach(&self, hat: &mut RainbowHat) {
+        hat.set_clock(self.clock.clone());
+        hat.apa102.set_clock(self.clock.clone());
+        hat.buzzer.set_clock(self.clock.clone());
+        hat.set_recorder(self.recorder.clone());
+    }
+
+    /// Apply the inputs to a board, advancing the clock by the delay of each one, and run the application after each.
+    /// Stops at the first error of the application.
+    ///
+    /// # Arguments
+    ///
+    /// * `hat` - Board, in simulation mode, usually created by `board()`.
+    /// * `tick` - Application, run once after each input.
+    pub fn run<F>(&self, hat: &mut RainbowHat, mut tick: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut RainbowHat) -> Result<(), Error>,
+    {
+        for step in &self.steps {
+            self.clock.advance(step.delay);
+
+            match step.action {
+                ScenarioAction::Press(button) => hat.buttons.get_mut(button).set_simulated_state(true),
+                ScenarioAction::Release(button) => hat.buttons.get_mut(button).set_simulated_state(false),
+                ScenarioAction::Sensor { temperature_c, pressure_hpa } => {
+                    let adc_t = (temperature_c as f64 * 16384.0).round() as i32;
+                    let adc_p = 1048576 - (pressure_hpa as f64 * 100.0).round() as i32;
+                    hat.bmp280.simulate(LINEAR_CALIBRATION, adc_t, adc_p);
+                }
+                ScenarioAction::Advance => {}
+            }
+
+            tick(hat)?;
+        }
+
+        Ok(())
+    }
+
+    /// Assert the operations recorded include the given ones in this order, as `assert_order`.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - Labels of the operations, such as "display:write".
+    pub fn assert_order(&self, expected: &[&str]) {
+        assert_order(&self.recorder, expected);
+    }
+}
+
+/// Get the label of a recorded operation: the peripheral and the operation, such as "display:write",
+/// "buzzer:note", "pixels:show" or "lights:set".
+///
+/// # Arguments
+///
+/// * `entry` - Operation recorded.
+pub fn label(entry: &Entry) -> String {
+    let component = match entry.component {
+        Component::Pixels => "pixels",
+        Component::Lights => "lights",
+        Component::Buttons => "buttons",
+        Component::Display => "display",
+        Component::Buzzer => "buzzer",
+        Component::Sensor => "sensor",
+    };
+    format!("{}:{}", component, entry.operation)
+}
+
+/// Check operations include the given ones in this order, other operations being allowed before, between and after.
+/// The error names the first operation missing and lists the operations.
+///
+/// # Arguments
+///
+/// * `entries` - Operations recorded, oldest first.
+/// * `expected` - Labels of the operations, such as "display:write".
+pub fn check_order(entries: &[Entry], expected: &[&str]) -> Result<(), String> {
+    let labels: Vec<String> = entries.iter().map(label).collect();
+    let mut remaining = labels.iter();
+
+    for (index, &wanted) in expected.iter().enumerate() {
+        if !remaining.any(|label| label == wanted) {
+            let after = match index {
+                0 => String::new(),
+                _ => format!(" after \"{}\"", expected[index - 1]),
+            };
+            return Err(format!("no \"{}\"{} in the recording: [{}]", wanted, after, labels.join(", ")));
+        }
+    }
+
+    Ok(())
+}
+
+/// Assert the operations of a recorder include the given ones in this order, other operations being allowed
+/// before, between and after: `assert_order(&recorder, &["display:write", "buzzer:note"])`.
+///
+/// # Arguments
+///
+/// * `recorder` - Recorder of the operations, such as the one of a `Scenario`.
+/// * `expected` - Labels of the operations, such as "display:write".
+pub fn assert_order(recorder: &dyn Recorder, expected: &[&str]) {
+    if let Err(message) = check_order(&recorder.entries(), expected) {
+        panic!("{}", message);
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphanum4::Alignment;
+
+    /// Tests the inputs are applied at their offsets, the application being run after each one.
+    #[test]
+    fn test_scenario_run() -> Result<(), Error> {
+        let scenario = Scenario::builder()
+            .press(Duration::from_millis(100), ButtonId::B)
+            .release(Duration::from_millis(50), ButtonId::B)
+            .sensor(Duration::from_secs(1), 31.5, 1013.25)
+            .advance(Duration::from_secs(2))
+            .build();
+        assert!(scenario.steps().len() == 4);
+        let mut hat = scenario.board()?;
+
+        let mut ticks = Vec::new();
+        scenario.run(&mut hat, |hat| {
+            let measurement = hat.bmp280.read_measurement()?;
+            ticks.push((scenario.elapsed().as_millis(), hat.buttons.b.is_pressed(), measurement.temperature_c));
+            Ok(())
+        })?;
+
+        assert!(ticks.iter().map(|tick| (tick.0, tick.1)).eq([(100, true), (150, false), (1150, false), (3150, false)]));
+        assert!(ticks[3].2 == 31.5);
+        let pressure = hat.bmp280.read_measurement()?.pressure_hpa;
+        assert!((pressure - 1013.25).abs() < 0.01);
+
+        // The errors of the application stop the scenario
+        let mut count = 0;
+        let result = scenario.run(&mut hat, |_hat| {
+            count += 1;
+            Err(Error::Unsupported("tick".to_string()))
+        });
+        assert!(matches!(result, Err(Error::Unsupported(_))) && count == 1);
+
+        Ok(())
+    }
+
+    /// Tests checking the order of the operations recorded across the peripherals.
+    #[test]
+    fn test_scenario_order() -> Result<(), Error> {
+        let scenario = Scenario::builder().tap(Duration::ZERO, ButtonId::A, Duration::from_millis(80)).build();
+        let mut hat = scenario.board()?;
+
+        scenario.run(&mut hat, |hat| {
+            if hat.buttons.a.poll_event() == Some(crate::touch::TouchEvent::Pressed) {
+                hat.alphanum.print_str("DING", Alignment::Left)?;
+                hat.alphanum.show()?;
+                hat.buzzer.note(880.0, 0.1)?;
+                hat.lights.red.on();
+            }
+            Ok(())
+        })?;
+
+        scenario.assert_order(&["display:write", "buzzer:note", "lights:set"]);
+        assert!(check_order(&hat.recording(), &[]).is_ok());
+
+        let err = check_order(&hat.recording(), &["buzzer:note", "display:write"]).unwrap_err();
+        assert!(err.starts_with("no \"display:write\" after \"buzzer:note\" in the recording: [display:write, "));
+        assert!(err.ends_with("buzzer:note, lights:set]"));
+        assert!(check_order(&hat.recording(), &["pixels:show"]).is_err());
+
+        // The operations are stamped with the clock of the scenario
+        let recording = scenario.recorder().entries();
+        assert!(recording.iter().all(|entry| entry.timestamp == recording[0].timestamp));
+
+        Ok(())
+    }
+}
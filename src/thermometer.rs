@@ -0,0 +1,230 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use crate::alphanum4::{Alignment, Alphanum4};
+use crate::apa102::{APA102, NUM_PIXELS};
+use crate::bmp280::BMP280;
+use crate::Error;
+
+/// Color of the strip at the bottom of the range.
+pub const COLD_COLOR: (u8, u8, u8) = (0, 0, 255);
+
+/// Color of the strip at the top of the range.
+pub const HOT_COLOR: (u8, u8, u8) = (255, 0, 0);
+
+/// Brightness of the strip.
+pub const STRIP_BRIGHTNESS: f32 = 0.5;
+
+/// What the thermometer shows for a temperature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rendering {
+
+    /// Four characters printed on the display.
+    pub text: String,
+
+    /// Position of the decimal point lit on the display, if any.
+    pub decimal: Option<usize>,
+
+    /// Color of each pixel of the strip, if the strip is used.
+    pub colors: Option<[(u8, u8, u8); NUM_PIXELS]>,
+}
+
+/// Shows the temperature of the BMP280 on the alphanumeric display,
+/// and optionally colors the strip from blue to red according to a range.
+#[derive(Debug)]
+pub struct Thermometer {
+
+    /// Temperature sensor.
+    pub bmp280: BMP280,
+
+    /// Display showing the temperature.
+    pub alphanum: Alphanum4,
+
+    /// Strip colored according to the temperature, if enabled.
+    pub apa102: Option<APA102>,
+
+    /// Temperatures shown as blue and red on the strip, in degrees Celsius.
+    range: (f32, f32),
+}
+
+impl Thermometer {
+
+    /// Creates a thermometer using the display only.
+    pub fn new() -> Result<Thermometer, Error> {
+        Ok(Self {
+            bmp280: BMP280::new()?,
+            alphanum: Alphanum4::new()?,
+            apa102: None,
+            range: (15.0, 30.0),
+        })
+    }
+
+    /// Color the strip according to the temperature: blue at the minimum, red at the maximum.
+    ///
+    /// # Arguments
+    ///
+    /// * `min_c` - Temperature shown in blue, in degrees Celsius.
+    /// * `max_c` - Temperature shown in red, in degrees Celsius.
+    pub fn enable_strip(&mut self, min_c: f32, max_c: f32) -> Result<(), Error> {
+        if self.apa102.is_none() {
+            self.apa102 = Some(APA102::new()?);
+        }
+        self.range = (min_c, max_c);
+
+        Ok(())
+    }
+
+    /// Stop coloring the strip.
+    pub fn disable_strip(&mut self) {
+        self.apa102 = None;
+    }
+
+    /// Read the sensor and show the temperature. Returns the temperature in degrees Celsius.
+    pub fn update(&mut self) -> Result<f32, Error> {
+        let temperature = self.bmp280.read_temperature()?;
+        let range = self.apa102.as_ref().map(|_| self.range);
+        let rendering = Thermometer::render(temperature, range);
+
+        self.alphanum.print_str(&rendering.text, Alignment::Left)?;
+        if let Some(pos) = rendering.decimal {
+            self.alphanum.set_decimal(pos, true)?;
+        }
+        self.alphanum.show()?;
+
+        if let (Some(apa102), Some(colors)) = (self.apa102.as_mut(), rendering.colors) {
+            for (x, &(r, g, b)) in colors.iter().enumerate() {
+                apa102.set_pixel(x, r, g, b, STRIP_BRIGHTNESS)?;
+            }
+            apa102.show()?;
+        }
+
+        Ok(temperature)
+    }
+
+    /// Update the thermometer at each interval until cancelled.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Interval between two updates.
+    /// * `cancel` - Set to true to stop.
+    pub fn run(&mut self, interval: Duration, cancel: &AtomicBool) -> Result<(), Error> {
+        while !cancel.load(Ordering::SeqCst) {
+            self.update()?;
+            thread::sleep(interval);
+        }
+
+        Ok(())
+    }
+
+    /// Computes what is shown for a temperature.
+    /// Temperatures between -9.9 and 99.9 are shown with one decimal, followed by C.
+    /// Temperatures up to 999 and down to -99 are shown without decimal. Others are shown as HIGH or LOW.
+    ///
+    /// # Arguments
+    ///
+    /// * `temperature_c` - Temperature in degrees Celsius.
+    /// * `range` - Temperatures shown as blue and red on the strip, None if the strip is not used.
+    pub fn render(temperature_c: f32, range: Option<(f32, f32)>) -> Rendering {
+        let (text, decimal) = if temperature_c.is_nan() {
+            ("----".to_string(), None)
+        } else if temperature_c > -9.95 && temperature_c < 99.95 {
+
+            // The decimal point is lit on the digit before the decimals
+            let digits = format!("{:.1}", temperature_c).replace('.', "");
+            let text = format!("{:>3}C", digits);
+            (text, Some(1))
+        } else if temperature_c > -99.5 && temperature_c < 999.5 {
+            (format!("{:>3.0}C", temperature_c), None)
+        } else if temperature_c > 0.0 {
+            ("HIGH".to_string(), None)
+        } else {
+            ("LOW ".to_string(), None)
+        };
+
+        let colors = range.map(|(min, max)| {
+            let ratio = if max > min { ((temperature_c - min) / (max - min)).clamp(0.0, 1.0) } else { 0.0 };
+            let ratio = if ratio.is_nan() { 0.0 } else { ratio };
+
+            let lerp = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * ratio).round() as u8;
+            [(lerp(COLD_COLOR.0, HOT_COLOR.0), lerp(COLD_COLOR.1, HOT_COLOR.1), lerp(COLD_COLOR.2, HOT_COLOR.2)); NUM_PIXELS]
+        });
+
+        Rendering { text, decimal, colors }
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bmp280::Calibration;
+
+    /// Tests the text shown for temperatures in and out of the displayable range.
+    #[test]
+    fn test_thermometer_render_text() {
+        let cases = [
+            (21.54, "215C", Some(1)),
+            (-5.2, "-52C", Some(1)),
+            (9.0, " 90C", Some(1)),
+            (0.0, " 00C", Some(1)),
+            (99.94, "999C", Some(1)),
+            (99.96, "100C", None),
+            (-12.3, "-12C", None),
+            (1500.0, "HIGH", None),
+            (-150.0, "LOW ", None),
+            (f32::NAN, "----", None),
+        ];
+
+        for &(temperature, text, decimal) in cases.iter() {
+            let rendering = Thermometer::render(temperature, None);
+            assert!(rendering.text == text, "{} rendered as {}", temperature, rendering.text);
+            assert!(rendering.decimal == decimal);
+            assert!(rendering.colors.is_none());
+        }
+    }
+
+    /// Tests the strip colors along the range, clamped outside of it.
+    #[test]
+    fn test_thermometer_render_colors() {
+        let range = Some((10.0, 30.0));
+
+        let colors = Thermometer::render(10.0, range).colors.unwrap();
+        assert!(colors.iter().all(|&color| color == COLD_COLOR));
+
+        let colors = Thermometer::render(20.0, range).colors.unwrap();
+        assert!(colors[0] == (128, 0, 128));
+
+        assert!(Thermometer::render(30.0, range).colors.unwrap()[0] == HOT_COLOR);
+        assert!(Thermometer::render(-40.0, range).colors.unwrap()[0] == COLD_COLOR);
+        assert!(Thermometer::render(120.0, range).colors.unwrap()[0] == HOT_COLOR);
+    }
+
+    /// Tests updating the display and the strip from the simulated sensor.
+    #[test]
+    fn test_thermometer_update() -> Result<(), Error> {
+        let mut thermometer = Thermometer::new()?;
+        thermometer.enable_strip(20.0, 30.0)?;
+        thermometer.alphanum.ht16k33.enable_simulation();
+        thermometer.apa102.as_mut().unwrap().enable_simulation();
+
+        // Datasheet example: 25.08°C
+        let calibration = Calibration {
+            dig_t1: 27504, dig_t2: 26435, dig_t3: -1000,
+            ..Calibration::default()
+        };
+        thermometer.bmp280.simulate(calibration, 519888, 415148);
+
+        let temperature = thermometer.update()?;
+        assert!((temperature - 25.08).abs() < 0.01);
+
+        let mut expected = Alphanum4::new()?;
+        expected.print_str("251C", Alignment::Left)?;
+        expected.set_decimal(1, true)?;
+        assert!(thermometer.alphanum.ht16k33.buffer == expected.ht16k33.buffer);
+
+        let frame = thermometer.apa102.as_ref().unwrap().last_frame().unwrap();
+        assert!(frame[0][1..4] == [125, 0, 130]);
+
+        Ok(())
+    }
+}
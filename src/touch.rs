@@ -1,5 +1,15 @@
 use std::fmt;
-use rppal::gpio::{Gpio, InputPin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::hal::DigitalInput;
+#[cfg(feature = "hardware")]
+use crate::hal::{self, Backend, Rppal};
+#[cfg(feature = "hardware")]
+use crate::pins;
+use crate::pins::{PinClaim, PinInUse, PinRegistry};
 
 /// GPIO BCM pin number for the touch button A.
 pub const GPIO_TOUCH_A: u8 = 21;
@@ -10,17 +20,264 @@ pub const GPIO_TOUCH_B: u8 = 20;
 /// GPIO BCM pin number for the touch button C.
 pub const GPIO_TOUCH_C: u8 = 16;
 
+/// Minimum duration of a touch recognized as a long press.
+pub const LONG_PRESS_DURATION: Duration = Duration::from_millis(800);
+
+/// Maximum duration between two taps recognized as a double tap.
+pub const DOUBLE_TAP_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Identifier of a touch button on the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ButtonId {
+
+    /// Button A
+    A,
+
+    /// Button B
+    B,
+
+    /// Button C
+    C,
+}
+
+/// Change of state of a touch button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchEvent {
+
+    /// The button was touched.
+    Pressed,
+
+    /// The button was released.
+    Released,
+}
+
+/// Detects the changes of state of a button between successive readings.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EdgeDetector {
+
+    /// State at the previous reading.
+    last: bool,
+}
+
+impl EdgeDetector {
+
+    /// Creates an edge detector starting from the released state.
+    pub fn new() -> EdgeDetector {
+        Self {
+            last: false,
+        }
+    }
+
+    /// Update with a new reading and get the event if the state changed.
+    /// # Arguments
+    ///
+    /// * `pressed` - Current state of the button: true for pressed.
+    pub fn update(&mut self, pressed: bool) -> Option<TouchEvent> {
+        let event = match (self.last, pressed) {
+            (false, true) => Some(TouchEvent::Pressed),
+            (true, false) => Some(TouchEvent::Released),
+            _ => None,
+        };
+
+        self.last = pressed;
+        event
+    }
+}
+
+/// Gesture made on a touch button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Gesture {
+
+    /// The button was touched and released quickly.
+    Tap,
+
+    /// The button was tapped twice in a short interval. The first tap is reported as a `Tap`.
+    DoubleTap,
+
+    /// The button was held. Reported once while the button is still held.
+    LongPress,
+}
+
+/// Recognizes gestures from successive readings of a button.
+#[derive(Debug, Clone, Copy)]
+pub struct GestureDetector {
+
+    /// Detects the changes of state.
+    edge: EdgeDetector,
+
+    /// Instant at which the current touch started.
+    pressed_at: Option<Instant>,
+
+    /// Whether the current touch was already reported as a long press.
+    long_press_reported: bool,
+
+    /// Instant of the last tap which can be followed by a double tap.
+    last_tap: Option<Instant>,
+
+    /// Minimum duration of a long press.
+    long_press: Duration,
+
+    /// Maximum duration between two taps of a double tap.
+    double_tap: Duration,
+}
+
+impl GestureDetector {
+
+    /// Creates a gesture detector with the default timings.
+    pub fn new() -> GestureDetector {
+        GestureDetector::with_timings(LONG_PRESS_DURATION, DOUBLE_TAP_INTERVAL)
+    }
+
+    /// Creates a gesture detector with the given timings.
+    /// # Arguments
+    ///
+    /// * `long_press` - Minimum duration of a touch recognized as a long press.
+    /// * `double_tap` - Maximum duration between two taps recognized as a double tap.
+    pub fn with_timings(long_press: Duration, double_tap: Duration) -> GestureDetector {
+        Self {
+            edge: EdgeDetector::new(),
+            pressed_at: None,
+            long_press_reported: false,
+            last_tap: None,
+            long_press,
+            double_tap,
+        }
+    }
+
+    /// Update with a new reading and get the gesture if one was completed.
+    /// # Arguments
+    ///
+    /// * `pressed` - Current state of the button: true for pressed.
+    /// * `now` - Instant of the reading.
+    pub fn update(&mut self, pressed: bool, now: Instant) -> Option<Gesture> {
+        match self.edge.update(pressed) {
+            Some(TouchEvent::Pressed) => {
+                self.pressed_at = Some(now);
+                self.long_press_reported = false;
+                None
+            }
+            Some(TouchEvent::Released) => {
+                let pressed_at = self.pressed_at.take()?;
+
+                if self.long_press_reported {
+                    None
+                } else if now - pressed_at >= self.long_press {
+                    self.last_tap = None;
+                    Some(Gesture::LongPress)
+                } else {
+                    match self.last_tap {
+                        Some(last_tap) if now - last_tap <= self.double_tap => {
+                            self.last_tap = None;
+                            Some(Gesture::DoubleTap)
+                        }
+                        _ => {
+                            self.last_tap = Some(now);
+                            Some(Gesture::Tap)
+                        }
+                    }
+                }
+            }
+            None => match self.pressed_at {
+                Some(pressed_at) if !self.long_press_reported && now - pressed_at >= self.long_press => {
+                    self.long_press_reported = true;
+                    self.last_tap = None;
+                    Some(Gesture::LongPress)
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+impl Default for GestureDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads the state of a button, from the pin or from the simulated state.
+/// Can be moved to another thread while the button is still used.
+#[derive(Debug, Clone)]
+struct ButtonReader {
+
+    /// Input pin shared with the button. None in simulation mode.
+    pin: Option<Arc<Mutex<Box<dyn DigitalInput>>>>,
+
+    /// Simulated state shared with the button.
+    simulated_state: Arc<AtomicBool>,
+}
+
+impl ButtonReader {
+
+    /// Get whether the button is pressed.
+    fn is_pressed(&self) -> bool {
+        match &self.pin {
+
+            // Touched if the pin is low
+            Some(pin) => !pin.lock().unwrap_or_else(|err| err.into_inner()).is_high(),
+            None => self.simulated_state.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// Handle setting the state of a button in simulation mode from another thread, for example a simulator
+/// reading the keyboard. Has no effect on the state read from the hardware.
+#[derive(Debug, Clone)]
+pub struct SimulatedTouch {
+
+    /// Simulated state shared with the button.
+    simulated_state: Arc<AtomicBool>,
+
+    /// Flag telling the button the simulated state was set, shared with the button.
+    touched: Arc<AtomicBool>,
+}
+
+impl SimulatedTouch {
+
+    /// Set the state of the button, as if it was touched or released.
+    /// # Arguments
+    ///
+    /// * `pressed` - true for pressed, false for released.
+    pub fn set_pressed(&self, pressed: bool) {
+        self.simulated_state.store(pressed, Ordering::SeqCst);
+        self.touched.store(true, Ordering::SeqCst);
+    }
+}
+
 /// Touch button on the board.
+/// Send and Sync, as its pin is shared with its readers behind a mutex.
 #[derive(Debug)]
 pub struct Button {
     bcm_pin: u8,
 
     /// Output pin to read from GPIO. Optional as not used in simulated mode.
-    pin: Option<Box<InputPin>>,
+    pin: Option<Arc<Mutex<Box<dyn DigitalInput>>>>,
 
     /// State of the button: true for pressed, false for released
     state: bool,
 
+    /// State injected in simulation mode, shared with the event threads.
+    simulated_state: Arc<AtomicBool>,
+
+    /// Whether the simulated state was set through a `SimulatedTouch` since the last reading.
+    touched: Arc<AtomicBool>,
+
+    /// Detects the changes of state for `poll_event`.
+    edge: EdgeDetector,
+
+    /// backend opening the peripherals on setup
+    #[cfg(feature = "hardware")]
+    backend: Box<dyn Backend>,
+
+    /// claim of the pin on setup, released with the driver
+    claim: Option<PinClaim>,
+
+    /// registry of the pins claimed in simulation mode, shared by the drivers of a board
+    pin_registry: PinRegistry,
+
+    /// whether to switch to simulation mode on setup when the hardware is absent
+    auto_simulate: bool,
+
     /// In simulation mode, no interaction with the hardware is done to simplify testability.
     simulation: bool, 
 
@@ -35,54 +292,230 @@ impl Button {
     ///
     /// * `bcm_pin` - GPIO pin number using the BCM pin numbering.
     pub fn new(bcm_pin: u8) -> Result<Button, Error> {
+        Ok(Button::create(bcm_pin))
+    }
 
-        Ok(Self {
+    /// Creates a button with every setting at its default, released and not set up.
+    /// # Arguments
+    ///
+    /// * `bcm_pin` - GPIO pin number using the BCM pin numbering.
+    fn create(bcm_pin: u8) -> Button {
+        Self {
             bcm_pin,
             pin: None,
             state: false,
-            simulation: false,
+            simulated_state: Arc::new(AtomicBool::new(false)),
+            touched: Arc::new(AtomicBool::new(false)),
+            edge: EdgeDetector::new(),
+            #[cfg(feature = "hardware")]
+            backend: Box::new(Rppal),
+            claim: None,
+            pin_registry: PinRegistry::new(),
+            auto_simulate: false,
+            simulation: !cfg!(feature = "hardware"),
             is_setup: false,
-        })
+        }
+    }
+
+    /// Creates a touch button read from the given input instead of the Raspberry Pi GPIO.
+    /// The button is pressed when the input is low.
+    /// # Arguments
+    ///
+    /// * `bcm_pin` - GPIO pin number using the BCM pin numbering, identifying the button.
+    /// * `pin` - Input reading the button.
+    pub fn with_pin<P: DigitalInput + 'static>(bcm_pin: u8, pin: P) -> Button {
+        let mut button = Button::create(bcm_pin);
+        button.pin = Some(Arc::new(Mutex::new(Box::new(pin))));
+        button.simulation = false;
+        button
+    }
+
+    /// Enables simulation mode: the state is set with `set_simulated_state` instead of read from the hardware.
+    /// Enable it before the first operation, which initializes the hardware.
+    pub fn enable_simulation(&mut self) {
+        self.simulation = true;
+    }
+
+    /// Get whether simulation mode is enabled.
+    pub fn is_simulation(&self) -> bool {
+        self.simulation
+    }
+
+    /// Get the GPIO pin number of the button, using the BCM pin numbering.
+    pub fn bcm_pin(&self) -> u8 {
+        self.bcm_pin
+    }
+
+    /// Switch to simulation mode on setup when the hardware is absent, for example when running off the Raspberry Pi,
+    /// instead of returning an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to fall back to simulation mode.
+    pub fn set_auto_simulate(&mut self, enabled: bool) {
+        self.auto_simulate = enabled;
+    }
+
+    /// Get whether the driver switches to simulation mode on setup when the hardware is absent.
+    pub fn is_auto_simulate(&self) -> bool {
+        self.auto_simulate
+    }
+
+    /// Set the backend opening the GPIO on setup.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Backend to use.
+    #[cfg(feature = "hardware")]
+    pub fn set_backend<B: Backend + 'static>(&mut self, backend: B) {
+        self.backend = Box::new(backend);
+    }
+
+    /// Set the registry the pin of the button is claimed in when simulated, a board sharing one between its drivers.
+    /// A button has its own registry by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - Registry to use.
+    pub fn set_pin_registry(&mut self, registry: PinRegistry) {
+        self.pin_registry = registry;
     }
 
     /// Initialize driver.
     pub fn setup(&mut self) -> Result <(), Error> {
         if !self.is_setup {
 
-            // Ignore Gpio initialization if in sumulation mode
-            if !self.simulation {
-                let gpio = Gpio::new()?;
-                let input = gpio.get(self.bcm_pin)?.into_input();
-                self.pin = Some(Box::new(input));
+            // Claim the pin unless given the input: in the registry of the process when opened on the hardware,
+            // of the board in simulation mode
+            let mut claim = None;
+
+            // Ignore Gpio initialization if in sumulation mode or given an input
+            #[cfg(feature = "hardware")]
+            if !self.simulation && self.pin.is_none() {
+                match self.backend.gpio() {
+                    Ok(gpio) => {
+                        claim = Some(pins::claim(self.bcm_pin, "button")?);
+                        let input = gpio.get(self.bcm_pin).map_err(|source| Error::Gpio { pin: self.bcm_pin, source })?.into_input();
+                        self.pin = Some(Arc::new(Mutex::new(Box::new(input))));
+                    }
+                    Err(err) if self.auto_simulate && hal::is_gpio_absent(&err) => self.simulation = true,
+                    Err(source) => return Err(Error::Gpio { pin: self.bcm_pin, source }),
+                }
+            }
+
+            if self.simulation && self.pin.is_none() {
+                claim = Some(self.pin_registry.claim(self.bcm_pin, "button")?);
             }
 
+            self.claim = claim;
             self.is_setup = true;
         }
         Ok(())
     }
 
+    /// Release the pin: its handle is dropped, so rppal returns it to its prior state now rather than when the
+    /// driver is dropped, or when a thread waiting on the button still using it ends.
+    /// The driver is set up again on its next use.
+    pub fn release(&mut self) {
+        self.pin = None;
+        self.claim = None;
+        self.is_setup = false;
+    }
+
     /// Get the state of the touch button.
     /// returns true if the touch button is pressed or false if it is not.
     pub fn is_pressed(&mut self) -> bool {
 
-        // Initialize the Gpio reading if not done yet
-        if !self.is_setup {
-            let _result = self.setup();
+        // Initialize the Gpio reading if not done yet, a button failing to set up keeping its state
+        if !self.is_setup && self.setup().is_err() {
+            return self.state;
         }
 
         // Only perform actual pin write if not in simulation mode
         if !self.simulation {
-            let pin = self.pin.as_deref_mut().unwrap();
-
-            // Touched if the pin is low
-            self.state = !pin.is_high();
+            self.state = self.reader().is_pressed();
+        } else if self.touched.swap(false, Ordering::SeqCst) {
+            self.state = self.simulated_state.load(Ordering::SeqCst);
         }
 
         self.state
     }
+
+    /// Read the button and get the event if its state changed since the previous call.
+    pub fn poll_event(&mut self) -> Option<TouchEvent> {
+        let pressed = self.is_pressed();
+        self.edge.update(pressed)
+    }
+
+    /// Wait for the button to be pressed from an async task, reading it at each interval.
+    /// A press is consumed only when the future completes, so dropping the future does not lose it.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Interval between two readings.
+    #[cfg(feature = "async")]
+    pub async fn wait_for_press_async(&mut self, interval: Duration) {
+        while self.poll_event() != Some(TouchEvent::Pressed) {
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Set the state of the button in simulation mode, as if it was touched or released.
+    /// Has no effect on the state read from the hardware.
+    /// # Arguments
+    ///
+    /// * `pressed` - true for pressed, false for released.
+    pub fn set_simulated_state(&mut self, pressed: bool) {
+        if self.simulation {
+            self.state = pressed;
+            self.simulated_state.store(pressed, Ordering::SeqCst);
+        }
+    }
+
+    /// Get a handle setting the state of the button in simulation mode from another thread.
+    pub fn simulated_touch(&self) -> SimulatedTouch {
+        SimulatedTouch {
+            simulated_state: self.simulated_state.clone(),
+            touched: self.touched.clone(),
+        }
+    }
+
+    /// Get a reader sharing the pin and simulated state of the button.
+    fn reader(&self) -> ButtonReader {
+        ButtonReader {
+            pin: self.pin.clone(),
+            simulated_state: self.simulated_state.clone(),
+        }
+    }
 }
 
-/// Set of buttons on the board.
+/// Handle to stop the thread sending button events.
+/// Dropping the handle leaves the thread running until the receiver is dropped.
+#[derive(Debug)]
+pub struct StopHandle {
+
+    /// Flag asking the thread to stop.
+    stop: Arc<AtomicBool>,
+
+    /// Thread sending the events.
+    thread: JoinHandle<()>,
+}
+
+impl StopHandle {
+
+    /// Stop the thread and wait for it to finish.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let _result = self.thread.join();
+    }
+
+    /// Get whether the thread has finished, for example because the receiver was dropped.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+}
+
+/// Set of buttons on the board, Send and Sync like each button.
 pub struct Buttons {
 
     /// Button A
@@ -106,11 +539,118 @@ impl Buttons {
         })
     }
 
+    /// Creates the set of buttons in simulation mode, never accessing the hardware.
+    /// The state of the buttons is set with `Button::set_simulated_state`.
+    pub fn with_simulation() -> Result<Buttons, Error> {
+        let mut buttons = Buttons::new()?;
+        buttons.enable_simulation();
+        Ok(buttons)
+    }
+
     /// Enables simulation mode.
     pub fn enable_simulation(&mut self) {
-        self.a.simulation = true;
-        self.b.simulation = true;
-        self.c.simulation = true;
+        self.a.enable_simulation();
+        self.b.enable_simulation();
+        self.c.enable_simulation();
+    }
+
+    /// Get whether simulation mode is enabled on every button.
+    pub fn is_simulation(&self) -> bool {
+        self.a.is_simulation() && self.b.is_simulation() && self.c.is_simulation()
+    }
+
+    /// Switch every button to simulation mode on setup when the hardware is absent, instead of returning an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true to fall back to simulation mode.
+    pub fn set_auto_simulate(&mut self, enabled: bool) {
+        self.a.set_auto_simulate(enabled);
+        self.b.set_auto_simulate(enabled);
+        self.c.set_auto_simulate(enabled);
+    }
+
+    /// Set the backend opening the GPIO of every button on setup.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Backend to use, cloned for each button, such as a `SharedGpio` opening the GPIO once.
+    #[cfg(feature = "hardware")]
+    pub fn set_backend<B: Backend + Clone + 'static>(&mut self, backend: B) {
+        self.a.set_backend(backend.clone());
+        self.b.set_backend(backend.clone());
+        self.c.set_backend(backend);
+    }
+
+    /// Set the registry the pins are claimed in when set up in simulation mode, shared by every button.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - Registry to use.
+    pub fn set_pin_registry(&mut self, registry: PinRegistry) {
+        self.a.set_pin_registry(registry.clone());
+        self.b.set_pin_registry(registry.clone());
+        self.c.set_pin_registry(registry);
+    }
+
+    /// Release the pins of every button, as with `Button::release`.
+    pub fn release(&mut self) {
+        self.a.release();
+        self.b.release();
+        self.c.release();
+    }
+
+    /// Get a button by its identifier.
+    /// # Arguments
+    ///
+    /// * `id` - Identifier of the button.
+    pub fn get_mut(&mut self, id: ButtonId) -> &mut Button {
+        match id {
+            ButtonId::A => &mut self.a,
+            ButtonId::B => &mut self.b,
+            ButtonId::C => &mut self.c,
+        }
+    }
+
+    /// Spawn a thread polling the buttons and sending their events down a channel,
+    /// so the main loop can wait on the receiver instead of polling.
+    /// The thread stops when the `StopHandle` is stopped, or when the receiver is dropped
+    /// (noticed when the next event is sent).
+    /// # Arguments
+    ///
+    /// * `interval` - Interval between two readings of the buttons.
+    pub fn event_channel(&mut self, interval: Duration) -> Result<(Receiver<(ButtonId, TouchEvent)>, StopHandle), Error> {
+        self.a.setup()?;
+        self.b.setup()?;
+        self.c.setup()?;
+
+        let mut readers = [
+            (ButtonId::A, self.a.reader(), EdgeDetector::new()),
+            (ButtonId::B, self.b.reader(), EdgeDetector::new()),
+            (ButtonId::C, self.c.reader(), EdgeDetector::new()),
+        ];
+
+        let (sender, receiver) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let thread = thread::spawn(move || {
+            while !thread_stop.load(Ordering::SeqCst) {
+                for (id, reader, edge) in readers.iter_mut() {
+                    if let Some(event) = edge.update(reader.is_pressed()) {
+
+                        // The receiver was dropped
+                        if sender.send((*id, event)).is_err() {
+                            return;
+                        }
+                    }
+                }
+
+                thread::sleep(interval);
+            }
+        });
+
+        Ok((receiver, StopHandle { stop, thread }))
     }
 }
 
@@ -118,31 +658,64 @@ impl Buttons {
 #[derive(Debug)]
 pub enum Error {
 
-    /// Gpio error.
-    Gpio(rppal::gpio::Error),
+    /// Gpio error configuring the pin of the button.
+    #[cfg(feature = "hardware")]
+    Gpio {
+
+        /// GPIO pin number using the BCM pin numbering.
+        pin: u8,
+
+        /// Error of the GPIO.
+        source: rppal::gpio::Error,
+    },
+
+    /// The pin of the button is already used by another driver of the process.
+    PinAlreadyInUse {
+
+        /// GPIO pin number using the BCM pin numbering.
+        pin: u8,
+
+        /// Driver using the pin, such as "light".
+        owner: &'static str,
+    },
 }
 
-impl std::error::Error for Error {}
+/// Converts a conflict of pins
+impl From<PinInUse> for Error {
+    fn from(err: PinInUse) -> Error {
+        Error::PinAlreadyInUse { pin: err.pin, owner: err.owner }
+    }
+}
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &*self {
-            Error::Gpio(err) => write!(f, "Gpio error: {}", &err),
+impl std::error::Error for Error {
+    #[cfg_attr(not(feature = "hardware"), allow(unused_variables))]
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            #[cfg(feature = "hardware")]
+            Error::Gpio { ref source, .. } => Some(source),
+            Error::PinAlreadyInUse { .. } => None,
         }
     }
 }
 
-/// Converts Gpio error
-impl From<rppal::gpio::Error> for Error {
-    fn from(err: rppal::gpio::Error) -> Error {
-        Error::Gpio(err)
+impl std::fmt::Display for Error {
+    #[cfg_attr(not(feature = "hardware"), allow(unused_variables))]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "hardware")]
+            Error::Gpio { pin, ref source } => write!(f, "failed to configure BCM {} for button: {}", pin, &source),
+            Error::PinAlreadyInUse { pin, owner } => write!(f, "BCM {} is already used by the {}", pin, owner),
+        }
     }
 }
 
 /// Unit tests
 #[cfg(test)]
+#[allow(clippy::bool_comparison)]
 mod tests {
     use super::*;
+    use crate::clock::Clock;
+    use crate::testing::Scenario;
 
     /// Tests the setup of the button.
     #[test]
@@ -186,6 +759,23 @@ mod tests {
         Ok(())
     }
 
+    /// Tests pressing a simulated button from another thread.
+    #[test]
+    fn test_button_simulated_touch() -> Result<(), Error> {
+        let mut button = Button::new(GPIO_TOUCH_A)?;
+        button.enable_simulation();
+        let touch = button.simulated_touch();
+
+        std::thread::spawn(move || touch.set_pressed(true)).join().unwrap();
+        assert!(button.poll_event() == Some(TouchEvent::Pressed));
+        assert!(button.is_pressed());
+
+        button.simulated_touch().set_pressed(false);
+        assert!(button.poll_event() == Some(TouchEvent::Released));
+
+        Ok(())
+    }
+
     /// Tests the setup of the button.
     #[test]
     fn test_buttons_new() -> Result<(), Error> {
@@ -205,9 +795,9 @@ mod tests {
         let mut buttons = Buttons::new()?;
 
         // Simulation off by default
-        assert!(!buttons.a.simulation);
-        assert!(!buttons.b.simulation);
-        assert!(!buttons.c.simulation);
+        assert!(buttons.a.simulation != cfg!(feature = "hardware"));
+        assert!(buttons.b.simulation != cfg!(feature = "hardware"));
+        assert!(buttons.c.simulation != cfg!(feature = "hardware"));
 
         // Turn on simulation
         buttons.enable_simulation();
@@ -218,4 +808,219 @@ mod tests {
 
         Ok(())
     }
+
+    /// Tests detecting the changes of state.
+    #[test]
+    fn test_edge_detector() {
+        let mut edge = EdgeDetector::new();
+
+        assert!(edge.update(false).is_none());
+        assert!(edge.update(true) == Some(TouchEvent::Pressed));
+        assert!(edge.update(true).is_none());
+        assert!(edge.update(false) == Some(TouchEvent::Released));
+        assert!(edge.update(false).is_none());
+    }
+
+    /// Tests polling the events of a button.
+    #[test]
+    fn test_button_poll_event() -> Result<(), crate::Error> {
+        let step = Duration::from_millis(10);
+        let scenario = Scenario::builder()
+            .advance(step)
+            .press(step, ButtonId::A)
+            .advance(step)
+            .release(step, ButtonId::A)
+            .build();
+        let mut hat = scenario.board()?;
+
+        let mut events = Vec::new();
+        scenario.run(&mut hat, |hat| {
+            events.push(hat.buttons.a.poll_event());
+            Ok(())
+        })?;
+        assert!(events == [None, Some(TouchEvent::Pressed), None, Some(TouchEvent::Released)]);
+
+        Ok(())
+    }
+
+    /// Tests recognizing taps, double taps and long presses.
+    #[test]
+    fn test_gesture_detector() -> Result<(), crate::Error> {
+        let ms = Duration::from_millis;
+        let scenario = Scenario::builder()
+            // Tap
+            .tap(ms(0), ButtonId::A, ms(100))
+            // Second tap within the interval
+            .tap(ms(100), ButtonId::A, ms(100))
+            // Tap after the interval
+            .tap(ms(700), ButtonId::A, ms(100))
+            // Long press, reported once while held
+            .press(ms(900), ButtonId::A)
+            .advance(ms(500))
+            .advance(ms(300))
+            .advance(ms(200))
+            .release(ms(100), ButtonId::A)
+            // Long press only noticed on release
+            .tap(ms(900), ButtonId::A, ms(1000))
+            .build();
+        let mut hat = scenario.board()?;
+
+        let mut detector = GestureDetector::new();
+        let mut gestures = Vec::new();
+        scenario.run(&mut hat, |hat| {
+            if let Some(gesture) = detector.update(hat.buttons.a.is_pressed(), scenario.clock().now()) {
+                gestures.push((scenario.elapsed().as_millis(), gesture));
+            }
+            Ok(())
+        })?;
+
+        assert!(gestures == [
+            (100, Gesture::Tap),
+            (300, Gesture::DoubleTap),
+            (1100, Gesture::Tap),
+            (2800, Gesture::LongPress),
+            (5000, Gesture::LongPress),
+        ]);
+
+        Ok(())
+    }
+
+    /// Tests receiving the events on a channel.
+    #[test]
+    fn test_buttons_event_channel() -> Result<(), Error> {
+        let mut buttons = Buttons::new()?;
+        buttons.enable_simulation();
+
+        let (receiver, stop) = buttons.event_channel(Duration::from_millis(1))?;
+        let timeout = Duration::from_secs(5);
+
+        buttons.a.set_simulated_state(true);
+        assert!(receiver.recv_timeout(timeout).unwrap() == (ButtonId::A, TouchEvent::Pressed));
+
+        buttons.a.set_simulated_state(false);
+        assert!(receiver.recv_timeout(timeout).unwrap() == (ButtonId::A, TouchEvent::Released));
+
+        buttons.get_mut(ButtonId::C).set_simulated_state(true);
+        assert!(receiver.recv_timeout(timeout).unwrap() == (ButtonId::C, TouchEvent::Pressed));
+
+        stop.stop();
+
+        Ok(())
+    }
+
+    /// Tests the thread stops when the receiver is dropped.
+    #[test]
+    fn test_buttons_event_channel_receiver_dropped() -> Result<(), Error> {
+        let mut buttons = Buttons::new()?;
+        buttons.enable_simulation();
+
+        let (receiver, stop) = buttons.event_channel(Duration::from_millis(1))?;
+        drop(receiver);
+
+        // The next event can't be sent
+        buttons.b.set_simulated_state(true);
+
+        for _i in 0..5000 {
+            if stop.is_finished() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(stop.is_finished());
+
+        Ok(())
+    }
+
+    /// Tests creating the buttons in simulation mode.
+    #[test]
+    fn test_buttons_with_simulation() -> Result<(), Error> {
+        let buttons = Buttons::with_simulation()?;
+
+        assert!(buttons.a.simulation);
+        assert!(buttons.b.simulation);
+        assert!(buttons.c.simulation);
+
+        Ok(())
+    }
+
+    /// Tests getting whether simulation mode is enabled on a button and on the set of buttons.
+    #[test]
+    fn test_buttons_is_simulation() -> Result<(), Error> {
+        let mut button = Button::new(GPIO_TOUCH_A)?;
+        assert!(button.is_simulation() != cfg!(feature = "hardware"));
+        button.enable_simulation();
+        assert!(button.is_simulation());
+        button.set_simulated_state(true);
+        assert!(button.is_pressed());
+
+        let mut buttons = Buttons::new()?;
+        assert!(buttons.is_simulation() != cfg!(feature = "hardware"));
+        buttons.enable_simulation();
+        assert!(buttons.is_simulation());
+
+        Ok(())
+    }
+
+    /// Input whose level is set by the test.
+    #[derive(Debug)]
+    struct SharedInput {
+        high: Arc<AtomicBool>,
+    }
+
+    impl DigitalInput for SharedInput {
+        fn is_high(&self) -> bool {
+            self.high.load(Ordering::SeqCst)
+        }
+    }
+
+    /// Tests a button read from a custom input, pressed when the input is low.
+    #[test]
+    fn test_button_with_pin() -> Result<(), Error> {
+        let high = Arc::new(AtomicBool::new(true));
+        let mut button = Button::with_pin(GPIO_TOUCH_A, SharedInput { high: high.clone() });
+        assert!(!button.is_simulation());
+
+        assert!(!button.is_pressed());
+        high.store(false, Ordering::SeqCst);
+        assert!(button.is_pressed());
+        assert!(button.poll_event() == Some(TouchEvent::Pressed));
+
+        Ok(())
+    }
+
+    /// Tests a pin used by two buttons of the same registry is rejected in simulation mode, the second button not
+    /// being read, until the first button is dropped.
+    #[test]
+    fn test_button_pin_in_use() -> Result<(), Error> {
+        let registry = PinRegistry::new();
+        let mut first = Button::new(GPIO_TOUCH_A)?;
+        first.enable_simulation();
+        first.set_pin_registry(registry.clone());
+        first.setup()?;
+
+        let mut second = Button::new(GPIO_TOUCH_A)?;
+        second.enable_simulation();
+        second.set_pin_registry(registry);
+        assert!(matches!(second.setup(), Err(Error::PinAlreadyInUse { pin: GPIO_TOUCH_A, owner: "button" })));
+        second.simulated_touch().set_pressed(true);
+        assert!(!second.is_pressed() && !second.is_setup);
+
+        drop(first);
+        assert!(second.is_pressed());
+
+        Ok(())
+    }
+
+    /// Pins the auto traits: the buttons can be read from any thread.
+    const _: () = {
+        const fn assert_send<T: Send>() {}
+        const fn assert_sync<T: Sync>() {}
+        assert_send::<Button>();
+        assert_send::<Buttons>();
+        assert_send::<StopHandle>();
+        assert_send::<Error>();
+        assert_sync::<Button>();
+        assert_sync::<Buttons>();
+        assert_sync::<Error>();
+    };
 }
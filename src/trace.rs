@@ -0,0 +1,73 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Hook receiving a message before each hardware operation.
+pub type TraceHook = Box<dyn FnMut(&str) + Send>;
+
+/// Optional trace hook of a driver, called before each hardware operation, also in simulation mode.
+/// Send but not Sync, as the hook is only Send: this is what keeps the drivers from being Sync.
+#[derive(Default)]
+pub struct Tracer {
+
+    /// hook receiving the messages, if set
+    hook: Option<TraceHook>,
+}
+
+impl Tracer {
+
+    /// Creates a tracer without hook.
+    pub fn new() -> Tracer {
+        Self { hook: None }
+    }
+
+    /// Set the hook receiving the messages.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Closure receiving each message.
+    pub fn set<F: FnMut(&str) + Send + 'static>(&mut self, hook: F) {
+        self.hook = Some(Box::new(hook));
+    }
+
+    /// Remove the hook.
+    pub fn clear(&mut self) {
+        self.hook = None;
+    }
+
+    /// Send a message to the hook. The message is only built if a hook is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - Builds the message.
+    pub fn trace<M: FnOnce() -> String>(&mut self, message: M) {
+        if let Some(hook) = self.hook.as_mut() {
+            hook(&message());
+        }
+    }
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("hook", &self.hook.is_some())
+            .finish()
+    }
+}
+
+/// Hook shared by several drivers, so a single closure receives the messages of all of them.
+#[derive(Clone)]
+pub(crate) struct SharedHook(Arc<Mutex<TraceHook>>);
+
+impl SharedHook {
+
+    /// Shares a hook.
+    pub(crate) fn new<F: FnMut(&str) + Send + 'static>(hook: F) -> SharedHook {
+        SharedHook(Arc::new(Mutex::new(Box::new(hook))))
+    }
+
+    /// Get a hook forwarding the messages to the shared one.
+    pub(crate) fn forward(&self) -> impl FnMut(&str) + Send + 'static {
+        let shared = self.0.clone();
+        move |message| (shared.lock().unwrap_or_else(|err| err.into_inner()))(message)
+    }
+}
@@ -0,0 +1,252 @@
+use std::convert::TryFrom;
+use std::fmt;
+use crate::Error;
+use crate::error::invalid_input;
+
+/// Hectopascals in one inch of mercury.
+pub const HPA_PER_INHG: f32 = 33.863_89;
+
+/// Kelvin at zero degrees Celsius.
+pub const KELVIN_OFFSET: f32 = 273.15;
+
+/// Temperature, stored in degrees Celsius.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+pub struct Temperature(f32);
+
+impl Temperature {
+
+    /// Creates a temperature from degrees Celsius.
+    pub fn from_celsius(celsius: f32) -> Temperature {
+        Temperature(celsius)
+    }
+
+    /// Creates a temperature from degrees Fahrenheit.
+    pub fn from_fahrenheit(fahrenheit: f32) -> Temperature {
+        Temperature((fahrenheit - 32.0) * 5.0 / 9.0)
+    }
+
+    /// Creates a temperature from Kelvin.
+    pub fn from_kelvin(kelvin: f32) -> Temperature {
+        Temperature(kelvin - KELVIN_OFFSET)
+    }
+
+    /// Get the temperature in degrees Celsius.
+    pub fn celsius(self) -> f32 {
+        self.0
+    }
+
+    /// Get the temperature in degrees Fahrenheit.
+    pub fn fahrenheit(self) -> f32 {
+        self.0 * 9.0 / 5.0 + 32.0
+    }
+
+    /// Get the temperature in Kelvin.
+    pub fn kelvin(self) -> f32 {
+        self.0 + KELVIN_OFFSET
+    }
+}
+
+/// Converts degrees Celsius
+impl From<f32> for Temperature {
+    fn from(celsius: f32) -> Temperature {
+        Temperature::from_celsius(celsius)
+    }
+}
+
+impl fmt::Display for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1}°C", self.0)
+    }
+}
+
+/// Pressure, stored in hectopascals.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+pub struct Pressure(f32);
+
+impl Pressure {
+
+    /// Creates a pressure from hectopascals.
+    pub fn from_hpa(hpa: f32) -> Pressure {
+        Pressure(hpa)
+    }
+
+    /// Creates a pressure from pascals.
+    pub fn from_pa(pa: f32) -> Pressure {
+        Pressure(pa / 100.0)
+    }
+
+    /// Creates a pressure from inches of mercury.
+    pub fn from_inhg(inhg: f32) -> Pressure {
+        Pressure(inhg * HPA_PER_INHG)
+    }
+
+    /// Get the pressure in hectopascals.
+    pub fn hpa(self) -> f32 {
+        self.0
+    }
+
+    /// Get the pressure in pascals.
+    pub fn pa(self) -> f32 {
+        self.0 * 100.0
+    }
+
+    /// Get the pressure in inches of mercury.
+    pub fn inhg(self) -> f32 {
+        self.0 / HPA_PER_INHG
+    }
+}
+
+/// Converts hectopascals
+impl From<f32> for Pressure {
+    fn from(hpa: f32) -> Pressure {
+        Pressure::from_hpa(hpa)
+    }
+}
+
+impl fmt::Display for Pressure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1} hPa", self.0)
+    }
+}
+
+/// Brightness of the outputs of the board, stored from 0.0 for the darkest level to 1.0 for the brightest,
+/// whatever the scale of the hardware: 5 bits for the multicolour LEDs and 4 bits for the display.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Brightness(f32);
+
+impl Brightness {
+
+    /// Darkest level.
+    pub const MIN: Brightness = Brightness(0.0);
+
+    /// Brightest level.
+    pub const MAX: Brightness = Brightness(1.0);
+
+    /// Creates a brightness from a percentage, clamped to 100.
+    ///
+    /// # Arguments
+    ///
+    /// * `percent` - Percentage of the brightest level.
+    pub fn percent(percent: u8) -> Brightness {
+        Brightness(percent.min(100) as f32 / 100.0)
+    }
+
+    /// Creates a brightness from a level of the 5 bits scale of the multicolour LEDs, clamped to 31.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - Level from 0 to 31.
+    pub fn from_5bit(level: u8) -> Brightness {
+        Brightness(level.min(31) as f32 / 31.0)
+    }
+
+    /// Creates a brightness from a level of the 4 bits scale of the display, clamped to 15.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - Level from 0 to 15.
+    pub fn from_4bit(level: u8) -> Brightness {
+        Brightness(level.min(15) as f32 / 15.0)
+    }
+
+    /// Get the brightness from 0.0 to 1.0.
+    pub fn value(self) -> f32 {
+        self.0
+    }
+
+    /// Get the nearest level of the 5 bits scale of the multicolour LEDs, from 0 to 31.
+    pub fn to_5bit(self) -> u8 {
+        (self.0 * 31.0).round() as u8
+    }
+
+    /// Get the nearest level of the 4 bits scale of the display, from 0 to 15.
+    pub fn to_4bit(self) -> u8 {
+        (self.0 * 15.0).round() as u8
+    }
+}
+
+/// Converts a brightness from 0.0 to 1.0
+impl TryFrom<f32> for Brightness {
+    type Error = Error;
+
+    fn try_from(value: f32) -> Result<Brightness, Error> {
+        if !(0.0..=1.0).contains(&value) {
+            return Err(Error::InvalidInput(invalid_input("brightness", value, "0.0 to 1.0")));
+        }
+
+        Ok(Brightness(value))
+    }
+}
+
+impl From<Brightness> for f32 {
+    fn from(brightness: Brightness) -> f32 {
+        brightness.0
+    }
+}
+
+impl fmt::Display for Brightness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.0}%", self.0 * 100.0)
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests converting temperatures between units.
+    #[test]
+    fn test_temperature_units() {
+        assert!(Temperature::from_celsius(0.0).fahrenheit() == 32.0);
+        assert!(Temperature::from_celsius(100.0).fahrenheit() == 212.0);
+        assert!(Temperature::from_celsius(-40.0).fahrenheit() == -40.0);
+        assert!(Temperature::from_celsius(0.0).kelvin() == 273.15);
+        assert!(Temperature::from_fahrenheit(212.0).celsius() == 100.0);
+        assert!(Temperature::from_kelvin(273.15).celsius() == 0.0);
+        assert!(Temperature::from(21.5).celsius() == 21.5);
+
+        assert!(Temperature::from_celsius(25.08).to_string() == "25.1°C");
+    }
+
+    /// Tests converting pressures between units.
+    #[test]
+    fn test_pressure_units() {
+        assert!((Pressure::from_hpa(1013.25).inhg() - 29.92).abs() < 0.005);
+        assert!((Pressure::from_inhg(29.92).hpa() - 1013.21).abs() < 0.01);
+        assert!(Pressure::from_hpa(1013.25).pa() == 101325.0);
+        assert!(Pressure::from_pa(101325.0).hpa() == 1013.25);
+        assert!(Pressure::from(1006.53).hpa() == 1006.53);
+
+        assert!(Pressure::from_hpa(1006.53).to_string() == "1006.5 hPa");
+    }
+
+    /// Tests the construction and the validation of brightness values.
+    #[test]
+    fn test_brightness() {
+        assert!(Brightness::percent(30).value() == 0.3);
+        assert!(Brightness::percent(150) == Brightness::MAX);
+        assert!(Brightness::try_from(0.25).unwrap().value() == 0.25);
+        assert!(f32::from(Brightness::percent(50)) == 0.5);
+
+        assert!(matches!(Brightness::try_from(1.5), Err(Error::InvalidInput(_))));
+        assert!(matches!(Brightness::try_from(-0.1), Err(Error::InvalidInput(_))));
+        assert!(matches!(Brightness::try_from(f32::NAN), Err(Error::InvalidInput(_))));
+
+        assert!(Brightness::percent(30).to_string() == "30%");
+    }
+
+    /// Tests the quantization to the 5 bits scale of the multicolour LEDs and the 4 bits scale of the display.
+    #[test]
+    fn test_brightness_quantization() {
+        let levels: Vec<(u8, u8)> = [0, 1, 10, 25, 30, 50, 75, 99, 100].iter()
+            .map(|&percent| (Brightness::percent(percent).to_5bit(), Brightness::percent(percent).to_4bit()))
+            .collect();
+        assert!(levels == [(0, 0), (0, 0), (3, 2), (8, 4), (9, 5), (16, 8), (23, 11), (31, 15), (31, 15)]);
+
+        // Every hardware level survives a round trip
+        assert!((0..=31).all(|level| Brightness::from_5bit(level).to_5bit() == level));
+        assert!((0..=15).all(|level| Brightness::from_4bit(level).to_4bit() == level));
+        assert!(Brightness::from_5bit(40) == Brightness::MAX && Brightness::from_4bit(16) == Brightness::MAX);
+    }
+}
@@ -0,0 +1,86 @@
+use std::process::{Command, Output};
+
+/// Run the command line tool in simulation mode.
+///
+/// # Arguments
+///
+/// * `args` - Command and its arguments.
+fn simulate(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rainbow-hat"))
+        .arg("--simulate")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Get the standard output of a successful command.
+///
+/// # Arguments
+///
+/// * `args` - Command and its arguments.
+fn stdout(args: &[&str]) -> String {
+    let output = simulate(args);
+    assert!(output.status.success(), "{:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Tests every command runs without the hardware.
+#[test]
+fn test_cli_commands() {
+    assert!(stdout(&["lights", "rgb", "on", "off", "on"]) == "lights: red on, green off, blue on\n");
+    assert!(stdout(&["lights", "all", "off"]) == "lights: all off\n");
+    assert!(stdout(&["pixel", "3", "255", "0", "0", "--brightness", "0.5"]) == "pixel 3: 255 0 0 at brightness 0.5\n");
+    assert!(stdout(&["pixel", "0", "0", "0", "255"]) == "pixel 0: 0 0 255 at brightness 1\n");
+    assert!(stdout(&["display", "print", "HI"]) == "display: HI\n");
+    assert!(stdout(&["display", "scroll", "HELLO WORLD"]) == "display: scrolled HELLO WORLD in 15 steps\n");
+    assert!(stdout(&["buzzer", "note", "A4", "0.3"]) == "buzzer: A4 (MIDI 69) for 0.3 s\n");
+    assert!(stdout(&["buzzer", "rtttl", "scale:d=4,o=5,b=120:c,d,e,f,g,a,b,c6"]) == "buzzer: played 8 notes\n");
+    assert!(stdout(&["sensor", "read"]).starts_with("temperature: "));
+    assert!(stdout(&["clear"]) == "cleared\n");
+
+    // The flag can follow the command
+    let output = Command::new(env!("CARGO_BIN_EXE_rainbow-hat")).args(["clear", "--simulate"]).output().unwrap();
+    assert!(output.status.success());
+}
+
+/// Tests invalid command lines exit with the usage and code 2.
+#[test]
+fn test_cli_usage_errors() {
+    let invalid: [&[&str]; 7] = [
+        &[],
+        &["bogus"],
+        &["lights", "rgb", "on", "off"],
+        &["lights", "all", "maybe"],
+        &["pixel", "3", "red", "0", "0"],
+        &["display", "print", "HELLO"],
+        &["buzzer", "note", "A4", "-1"],
+    ];
+
+    for args in invalid.iter() {
+        let output = simulate(args);
+        assert!(output.status.code() == Some(2), "{:?} exited with {:?}", args, output.status);
+        assert!(output.stdout.is_empty());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Usage: rainbow-hat"));
+    }
+
+    let help = Command::new(env!("CARGO_BIN_EXE_rainbow-hat")).arg("--help").output().unwrap();
+    assert!(help.status.success());
+    assert!(String::from_utf8_lossy(&help.stdout).starts_with("Usage: rainbow-hat"));
+}
+
+/// Tests values rejected by the drivers exit with code 1.
+#[test]
+fn test_cli_driver_errors() {
+    let invalid: [&[&str]; 3] = [
+        &["pixel", "9", "255", "0", "0"],
+        &["buzzer", "note", "H4", "0.1"],
+        &["buzzer", "rtttl", "no notes"],
+    ];
+
+    for args in invalid.iter() {
+        let output = simulate(args);
+        assert!(output.status.code() == Some(1), "{:?} exited with {:?}", args, output.status);
+        assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: "));
+    }
+}
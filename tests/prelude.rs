@@ -0,0 +1,51 @@
+use rainbow_hat_rs::prelude::*;
+
+/// Uses every item of the prelude needed by the examples, so removing one from the prelude fails to compile.
+///
+/// # Arguments
+///
+/// * `hat` - Board, in simulation mode.
+fn examples_items(hat: &mut RainbowHat) -> Result<(), Error> {
+    let mut event_loop = EventLoop::new(RainbowHat::with_simulation()?);
+    event_loop.on_press(ButtonId::A, |hat| {
+        hat.lights.rgb(true, false, false);
+        Ok(())
+    });
+
+    hat.alphanum.print_str("HI", Alignment::Left)?;
+    hat.alphanum.show()?;
+
+    let mut alphanum = Alphanum4::new()?;
+    alphanum.print_str("HI", Alignment::Right)?;
+
+    let mut buzzer = Buzzer::new()?;
+    buzzer.enable_simulation();
+    buzzer.midi_note(69, 0.0)?;
+
+    let mut rainbow = Rainbow::new();
+    rainbow.render(&mut hat.apa102, 0.5)?;
+
+    let shared: SharedRainbowHat = RainbowHat::with_simulation()?.into_shared();
+    shared.with_board(|board| board.apa102.show())?;
+
+    Ok(())
+}
+
+/// Tests the prelude covers the examples, and that the errors of the drivers convert into the unified error.
+#[test]
+fn test_prelude() -> Result<(), Error> {
+    let mut hat = RainbowHat::with_simulation()?;
+    examples_items(&mut hat)?;
+
+    let errors: Vec<Error> = vec![
+        APA102Error::InvalidArgument("pixel".to_string()).into(),
+        Alphanum4Error::InvalidArgument("glyph".to_string()).into(),
+        BuzzerError::InvalidArgument("note".to_string()).into(),
+        HT16K33Error::InvalidArgument("brightness".to_string()).into(),
+    ];
+    assert!(errors.iter().all(|err| matches!(err, Error::InvalidInput(_))));
+
+    let _types: (Option<LightsError>, Option<TouchError>, Option<BMP280Error>) = (None, None, None);
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use std::thread;
+use rainbow_hat_rs::prelude::*;
+use rainbow_hat_rs::testing::Scenario;
+
+/// Tests the simulated boards and drivers of a process set up on the same pins without conflicting, as the tests
+/// of a crate using the boards run in parallel.
+#[test]
+fn test_simulated_boards_pins() -> Result<(), Error> {
+    let scenario = Scenario::builder().build();
+    let mut boards = [RainbowHat::with_simulation()?, RainbowHat::with_simulation()?, scenario.board()?];
+    for hat in boards.iter_mut() {
+        hat.setup()?;
+        hat.apa102.show()?;
+        hat.buzzer.note(440.0, 0.0)?;
+    }
+
+    let mut buzzer = Buzzer::new()?;
+    buzzer.enable_simulation();
+    buzzer.note(440.0, 0.0)?;
+
+    let threads: Vec<_> = (0..4).map(|_| thread::spawn(|| -> Result<(), Error> {
+        let mut hat = RainbowHat::with_simulation()?;
+        hat.setup()?;
+        hat.apa102.show()?;
+        Ok(())
+    })).collect();
+    for thread in threads {
+        thread.join().unwrap()?;
+    }
+
+    Ok(())
+}